@@ -0,0 +1,120 @@
+//! Allocation benchmark.
+//!
+//! Counts the heap allocations, and the bytes they request, that each pipeline phase
+//! makes on the story files in `benches/stories`: parsing, macro expansion, a second
+//! expansion of the same program (served by the expansion cache), and evaluation.
+//! Counts are deterministic, so they can be compared across changes to the AST
+//! representation without the noise of timings.
+//!
+//! Run with `cargo bench --bench allocations`.
+
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    fs,
+    path::PathBuf,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use sutra::{
+    atoms::{EngineOutputBuffer, SharedOutput},
+    cli::ExecutionPipeline,
+    errors::SourceContext,
+    parser,
+};
+
+/// The system allocator, counting what is asked of it.
+struct Counting;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+static BYTES: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        BYTES.fetch_add(layout.size(), Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        BYTES.fetch_add(new_size, Ordering::Relaxed);
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: Counting = Counting;
+
+/// Allocations made while running `f`, and how many bytes they requested.
+fn measure<T>(f: impl FnOnce() -> T) -> (T, usize, usize) {
+    let (allocations, bytes) = (
+        ALLOCATIONS.load(Ordering::Relaxed),
+        BYTES.load(Ordering::Relaxed),
+    );
+    let result = f();
+    (
+        result,
+        ALLOCATIONS.load(Ordering::Relaxed) - allocations,
+        BYTES.load(Ordering::Relaxed) - bytes,
+    )
+}
+
+fn main() {
+    let stories = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("benches/stories");
+    let mut paths: Vec<_> = fs::read_dir(&stories)
+        .expect("benches/stories exists")
+        .filter_map(|entry| Some(entry.ok()?.path()))
+        .filter(|path| path.extension().is_some_and(|ext| ext == "sutra"))
+        .collect();
+    paths.sort();
+
+    println!(
+        "{:<16} {:<10} {:>12} {:>12}",
+        "story", "phase", "allocations", "bytes"
+    );
+    for path in paths {
+        let name = path.file_name().unwrap().to_string_lossy().into_owned();
+        let source = fs::read_to_string(&path).expect("story is readable");
+        let pipeline = ExecutionPipeline::builder()
+            .with_seed(1)
+            .with_output(SharedOutput::new(EngineOutputBuffer::new()))
+            .build()
+            .expect("pipeline builds");
+        let context = SourceContext::from_file(&name, &source);
+
+        let (nodes, parse, parse_bytes) =
+            measure(|| parser::parse(&source, context.clone()).expect("story parses"));
+        let program = parser::wrap_in_do(nodes);
+        let expand = || {
+            pipeline
+                .macro_env
+                .expand(program.clone())
+                .expect("story expands")
+        };
+        let (expanded, first, first_bytes) = measure(expand);
+        let (_, cached, cached_bytes) = measure(expand);
+        let (_, evaluate, evaluate_bytes) = measure(|| {
+            pipeline
+                .execute_expanded_ast(
+                    &expanded,
+                    pipeline.world.clone(),
+                    pipeline.output.clone(),
+                    context.clone(),
+                )
+                .expect("story runs")
+        });
+
+        for (phase, allocations, bytes) in [
+            ("parse", parse, parse_bytes),
+            ("expand", first, first_bytes),
+            ("re-expand", cached, cached_bytes),
+            ("evaluate", evaluate, evaluate_bytes),
+        ] {
+            println!("{name:<16} {phase:<10} {allocations:>12} {bytes:>12}");
+        }
+    }
+}
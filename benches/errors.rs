@@ -0,0 +1,100 @@
+//! Error-heavy benchmark.
+//!
+//! Times the paths that make many errors against a large source: building runtime
+//! errors the way atoms do, reading their codes, validating a script in which every
+//! call is undefined, and displaying a thousand of the errors. Errors keep
+//! structured data and share their source text, so building one should cost about
+//! the same whatever the size of the file it points into; only displaying it formats
+//! text.
+//!
+//! Run with `cargo bench --bench errors`.
+
+use std::{hint::black_box, time::Instant};
+
+use sutra::{
+    build_canonical_macro_env, build_canonical_world,
+    errors::{to_source_span, ErrorReporting, SourceContext},
+    parser,
+    prelude::WorldHandle,
+    validate_ast_semantics, EngineOutputBuffer, EvaluationContext, SharedOutput,
+};
+
+/// How many errors each runtime case builds.
+const ERRORS: usize = 100_000;
+
+/// Lines of the benchmark source, each an undefined call.
+const CALLS: usize = 660;
+
+/// Roughly 16KB of source: calls of a function no one defines.
+fn source() -> String {
+    (0..CALLS)
+        .map(|i| format!("(undefined-call {i} \"x\")\n"))
+        .collect()
+}
+
+/// Runs `f`, printing how long it took.
+fn time<T>(case: &str, count: usize, f: impl FnOnce() -> T) -> T {
+    let start = Instant::now();
+    let result = black_box(f());
+    let elapsed = start.elapsed();
+    println!(
+        "{case:<24} {count:>8} {:>10.2}ms {:>8.0}ns each",
+        elapsed.as_secs_f64() * 1e3,
+        elapsed.as_nanos() as f64 / count as f64
+    );
+    result
+}
+
+fn main() {
+    let source = source();
+    let context = SourceContext::from_file("errors.sutra", &source);
+    let world = build_canonical_world();
+    let evaluation = EvaluationContext::new(
+        world.clone(),
+        SharedOutput::new(EngineOutputBuffer::new()),
+        context.clone(),
+    );
+    let span = |i: usize| {
+        let start = (i * 23) % (source.len() - 16);
+        to_source_span(sutra::Span {
+            start,
+            end: start + 14,
+        })
+    };
+
+    println!("{} bytes of source", source.len());
+    println!(
+        "{:<24} {:>8} {:>12} {:>13}",
+        "case", "count", "total", "per item"
+    );
+    time("type mismatch", ERRORS, || {
+        for i in 0..ERRORS {
+            black_box(evaluation.type_mismatch("Number", "String", span(i)));
+        }
+    });
+    time("arity mismatch", ERRORS, || {
+        for i in 0..ERRORS {
+            black_box(evaluation.arity_mismatch("2", 3, span(i)));
+        }
+    });
+    let errors: Vec<_> = (0..ERRORS)
+        .map(|i| evaluation.type_mismatch("Number", "String", span(i)))
+        .collect();
+    time("error codes", ERRORS, || {
+        errors.iter().map(|error| error.code().len()).sum::<usize>()
+    });
+
+    let program = parser::wrap_in_do(parser::parse(&source, context.clone()).expect("parses"));
+    let macros = build_canonical_macro_env().expect("the prelude loads");
+    let found = time("validate", CALLS, || {
+        validate_ast_semantics(&program, &macros, &world.read(), &context)
+    });
+    assert_eq!(found.len(), CALLS, "every call is undefined");
+
+    time("display", 1_000, || {
+        errors[..1_000]
+            .iter()
+            .map(|error| error.to_string().len())
+            .sum::<usize>()
+    });
+}
@@ -0,0 +1,146 @@
+//! Turns fuzz artifacts into regression tests that `sutra test` runs.
+//!
+//! ```text
+//! cargo run --example regress -- <target> <artifact>...
+//! ```
+//!
+//! Once the crash or timeout an artifact found is fixed, this replays it, records what
+//! the engine now does with it (a value, an error kind or a parse error) and writes a
+//! test expecting exactly that to `tests/fuzz_regressions/<target>-<artifact>.sutra`.
+//! An artifact that still panics or times out is refused, as is one that only the
+//! sandbox's resource limits stop, since `sutra test` runs without them, and a
+//! written test that fails when the test runner runs it.
+
+use std::{
+    env, fs, panic,
+    path::{Path, PathBuf},
+    process::ExitCode,
+};
+
+use arbitrary::{Arbitrary, Unstructured};
+use sutra::{
+    build_canonical_world, discovery::TestDiscoverer, errors::ErrorKind, syntax::escape_string,
+    test_runner::TestRunner,
+};
+use sutra_fuzz::Script;
+
+/// What a test checks once it has run the artifact.
+enum Outcome {
+    /// The text does not parse.
+    ParseError,
+    /// The script ran to an error of this kind.
+    Error(&'static str),
+    /// The text parses, or the script ran to a value.
+    Finished,
+}
+
+fn main() -> ExitCode {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let Some((target, artifacts)) = args.split_first() else {
+        eprintln!("usage: regress <fuzz_parse|fuzz_eval_expanded|fuzz_ast> <artifact>...");
+        return ExitCode::FAILURE;
+    };
+    let mut failed = false;
+    for artifact in artifacts {
+        match regress(target, Path::new(artifact)) {
+            Ok(written) => println!("wrote {}", written.display()),
+            Err(message) => {
+                eprintln!("{artifact}: {message}");
+                failed = true;
+            }
+        }
+    }
+    if failed {
+        ExitCode::FAILURE
+    } else {
+        ExitCode::SUCCESS
+    }
+}
+
+fn regress(target: &str, artifact: &Path) -> Result<PathBuf, String> {
+    let data = fs::read(artifact).map_err(|e| e.to_string())?;
+    let source = match target {
+        "fuzz_parse" | "fuzz_eval_expanded" => String::from_utf8(data)
+            .map_err(|_| "not UTF-8, so the target never ran it".to_string())?,
+        "fuzz_ast" => Script::arbitrary_take_rest(Unstructured::new(&data))
+            .map_err(|e| e.to_string())?
+            .to_source(),
+        _ => return Err(format!("unknown target {target}")),
+    };
+    let parse_only = target == "fuzz_parse";
+    let outcome = panic::catch_unwind(|| replay(&source, parse_only))
+        .map_err(|_| "still panics".to_string())??;
+
+    let name = artifact
+        .file_name()
+        .map_or_else(|| "artifact".into(), |name| name.to_string_lossy());
+    let repository = Path::new(env!("CARGO_MANIFEST_DIR")).parent().unwrap();
+    let path = repository
+        .join("tests/fuzz_regressions")
+        .join(format!("{target}-{name}.sutra"));
+    let test = test_form(
+        &format!("fuzz: {target} {name}"),
+        &source,
+        parse_only,
+        &outcome,
+    );
+    let file = format!(
+        ";; Replays the {target} artifact {name}; written by fuzz/examples/regress.rs.\n\n{test}\n"
+    );
+    fs::write(&path, file).map_err(|e| e.to_string())?;
+
+    if let Err(message) = check(&path) {
+        let _ = fs::remove_file(&path);
+        return Err(message);
+    }
+    Ok(path)
+}
+
+fn replay(source: &str, parse_only: bool) -> Result<Outcome, String> {
+    if sutra_fuzz::parse(source).is_err() {
+        return Ok(Outcome::ParseError);
+    }
+    if parse_only {
+        return Ok(Outcome::Finished);
+    }
+    match sutra_fuzz::execute(source) {
+        Ok(_) => Ok(Outcome::Finished),
+        Err(e) if matches!(e.kind, ErrorKind::Timeout { .. }) => Err("still times out".to_string()),
+        Err(e) if matches!(e.kind, ErrorKind::ResourceLimit { .. }) => Err(format!(
+            "only the sandbox limits stop it ({e}); test it from Rust"
+        )),
+        Err(e) => Ok(Outcome::Error(e.code())),
+    }
+}
+
+/// Writes the test. Text that does not parse is a string body, which the test runner
+/// only parses; text from `fuzz_parse` that does is read with `read` rather than run.
+fn test_form(name: &str, source: &str, parse_only: bool, outcome: &Outcome) -> String {
+    let name = escape_string(name);
+    match outcome {
+        Outcome::ParseError => format!(
+            "(test \"{name}\"\n      (expect (error Parse))\n      \"{}\")",
+            escape_string(source)
+        ),
+        Outcome::Finished if parse_only => format!(
+            "(test \"{name}\"\n      (expect (value nil))\n      (read \"{}\")\n      nil)",
+            escape_string(&format!("(do {source}\n)"))
+        ),
+        Outcome::Finished => {
+            format!("(test \"{name}\"\n      (expect (value nil))\n{source}\nnil)")
+        }
+        Outcome::Error(kind) => {
+            format!("(test \"{name}\"\n      (expect (error {kind}))\n{source}\n)")
+        }
+    }
+}
+
+fn check(path: &Path) -> Result<(), String> {
+    let tests = TestDiscoverer::extract_tests_from_file(path, None)
+        .map_err(|e| format!("the written test does not load: {e}"))?;
+    for test in &tests {
+        TestRunner::run_single_test(test, build_canonical_world(), None)
+            .map_err(|e| format!("the written test fails: {e}"))?;
+    }
+    Ok(())
+}
@@ -0,0 +1,11 @@
+//! Evaluates scripts generated from the `Expr` grammar. Most of them parse, so this
+//! target spends its time in the expander and the atoms rather than the parser.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use sutra_fuzz::Script;
+
+fuzz_target!(|script: Script| {
+    let _ = sutra_fuzz::execute(&script.to_source());
+});
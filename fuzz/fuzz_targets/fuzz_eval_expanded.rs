@@ -0,0 +1,11 @@
+//! Parses, expands and evaluates arbitrary text, as `sutra run` would.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(source) = std::str::from_utf8(data) {
+        let _ = sutra_fuzz::execute(source);
+    }
+});
@@ -0,0 +1,11 @@
+//! Parses arbitrary text. The parser must return forms or a diagnostic for any input.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(source) = std::str::from_utf8(data) {
+        let _ = sutra_fuzz::parse(source);
+    }
+});
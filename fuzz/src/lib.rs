@@ -0,0 +1,147 @@
+//! Shared harness for the fuzz targets and the `regress` tool.
+//!
+//! Every target runs its input close to the way `sutra test` runs a test body, so
+//! that an artifact turned into a regression test (see `examples/regress.rs`) behaves
+//! the same under the test runner as it did under the fuzzer. The differences are
+//! that `Net` is never granted, output is discarded, the time limit is [`TIME_LIMIT`]
+//! and [`SANDBOX_LIMITS`] cap what a script may allocate: without them, a script
+//! such as `(range 1e200)` is an out-of-memory report rather than a finding.
+
+use std::{
+    sync::{Arc, OnceLock},
+    time::Duration,
+};
+
+use arbitrary::{Arbitrary, Unstructured};
+use sutra::{
+    atoms::NullSink,
+    build_canonical_macro_env, build_canonical_world,
+    cli::{ExecutionPipeline, SANDBOX_LIMITS},
+    prelude::*,
+    Capability,
+};
+
+/// How long one input may run. Scripts that loop end in a timeout error well before
+/// libFuzzer's own `-timeout`, so an input it reports as a timeout is one the engine
+/// failed to stop.
+pub const TIME_LIMIT: Duration = Duration::from_secs(1);
+
+/// The name every input is run under, as it appears in diagnostics.
+pub const SOURCE_NAME: &str = "fuzz";
+
+/// Builds the pipeline an input runs in.
+pub fn pipeline() -> ExecutionPipeline {
+    let capabilities: Vec<Capability> = Capability::ALL
+        .into_iter()
+        .filter(|capability| *capability != Capability::Net)
+        .collect();
+    ExecutionPipeline::builder()
+        .with_capabilities(&capabilities)
+        .with_resource_limits(SANDBOX_LIMITS)
+        .with_time_limit(TIME_LIMIT)
+        .with_output(SharedOutput::new(NullSink))
+        .build()
+        .expect("the default pipeline builds")
+}
+
+/// Parses `source` without running it.
+pub fn parse(source: &str) -> Result<Vec<AstNode>, SutraError> {
+    sutra::parser::parse(source, SourceContext::from_file(SOURCE_NAME, source))
+}
+
+/// Parses, expands and evaluates `source`.
+pub fn execute(source: &str) -> Result<Value, SutraError> {
+    pipeline().execute_for_value(source, SOURCE_NAME)
+}
+
+/// A script generated from the `Expr` grammar rather than from bytes, so that most
+/// inputs get past the parser and exercise the expander and evaluator.
+#[derive(Arbitrary, Debug)]
+pub struct Script(pub Vec<Form>);
+
+impl Script {
+    /// Renders the script as source text, one top-level form per line.
+    pub fn to_source(&self) -> String {
+        self.0
+            .iter()
+            .map(|form| form.to_node().value.pretty())
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
+
+/// One expression. Parameter lists are written as plain lists, as in source text.
+#[derive(Arbitrary, Debug)]
+pub enum Form {
+    List(Vec<Form>),
+    Symbol(Name),
+    Keyword(Name),
+    Path(Name, Vec<Name>),
+    String(String),
+    Integer(i8),
+    Number(f64),
+    Bool(bool),
+    If(Box<Form>, Box<Form>, Box<Form>),
+    Quote(Box<Form>),
+    Spread(Box<Form>),
+}
+
+impl Form {
+    /// Builds the AST node this form stands for.
+    pub fn to_node(&self) -> AstNode {
+        let span = Span::default();
+        let expr = match self {
+            Form::List(items) => Expr::List(items.iter().map(Form::to_node).collect(), span),
+            Form::Symbol(name) => Expr::Symbol(name.0.clone(), span),
+            Form::Keyword(name) => Expr::Keyword(name.0.clone(), span),
+            Form::Path(first, rest) => {
+                let segments = std::iter::once(first).chain(rest);
+                Expr::Path(Path(segments.map(|name| name.0.clone()).collect()), span)
+            }
+            Form::String(text) => Expr::String(text.clone(), span, false),
+            Form::Integer(n) => Expr::Number(f64::from(*n), span),
+            Form::Number(n) => Expr::Number(*n, span),
+            Form::Bool(b) => Expr::Bool(*b, span),
+            Form::If(condition, then_branch, else_branch) => Expr::If {
+                condition: Box::new(condition.to_node()),
+                then_branch: Box::new(then_branch.to_node()),
+                else_branch: Box::new(else_branch.to_node()),
+                span,
+            },
+            Form::Quote(inner) => Expr::Quote(Box::new(inner.to_node()), span),
+            Form::Spread(inner) => Expr::Spread(Box::new(inner.to_node())),
+        };
+        Spanned {
+            value: Arc::new(expr),
+            span,
+        }
+    }
+}
+
+/// A symbol the engine knows: an atom, a macro or one of a few variable names.
+#[derive(Debug, Clone)]
+pub struct Name(pub String);
+
+impl<'a> Arbitrary<'a> for Name {
+    fn arbitrary(u: &mut Unstructured<'a>) -> arbitrary::Result<Self> {
+        u.choose(names()).cloned().map(Name)
+    }
+}
+
+fn names() -> &'static [String] {
+    static NAMES: OnceLock<Vec<String>> = OnceLock::new();
+    NAMES.get_or_init(|| {
+        let mut names: Vec<String> = build_canonical_world()
+            .read()
+            .atom_specs
+            .keys()
+            .cloned()
+            .collect();
+        if let Ok(macros) = build_canonical_macro_env() {
+            names.extend(macros.macro_names());
+        }
+        names.extend(["x", "y", "f", "xs"].map(String::from));
+        names.sort();
+        names
+    })
+}
@@ -0,0 +1,242 @@
+//! On-Disk AST Cache
+//!
+//! Repeated `sutra test`, `typecheck` and `analyze` runs parse, and mostly expand, the
+//! same files again. An [`AstCache`] keeps parsed ASTs and macro expansions in a
+//! directory, [`DEFAULT_CACHE_DIR`] unless told otherwise, serialized with bincode.
+//!
+//! Entries are keyed by a SHA-256 hash of what produced them: a file's source for a
+//! parse, and the parsed program with the macros in scope for an expansion. Editing a
+//! script or a prelude macro therefore misses the cache instead of reading a stale
+//! entry. Keys also cover the engine build, so a rebuilt `sutra` never reads what an
+//! older one wrote.
+//!
+//! `run` keeps the definitions scripts open with in the same directory (see
+//! [`crate::cli::preload`]).
+//!
+//! The cache only saves time. An unreadable entry is a miss, a failed write is
+//! ignored, and errors are never cached. `--no-cache` bypasses it and `sutra clean`
+//! deletes it.
+
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+    time::UNIX_EPOCH,
+};
+
+use once_cell::sync::Lazy;
+use serde::{de::DeserializeOwned, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::{macros::MacroSystem, parser, prelude::*};
+
+/// Where the cache lives, relative to the working directory.
+pub const DEFAULT_CACHE_DIR: &str = ".sutra-cache";
+
+/// The engine build: its version, and the size and modification time of the running
+/// executable, which change whenever it is rebuilt.
+static ENGINE_BUILD: Lazy<String> = Lazy::new(|| {
+    let executable = std::env::current_exe().and_then(fs::metadata).ok();
+    let stamp = executable.map(|metadata| {
+        let modified = metadata
+            .modified()
+            .ok()
+            .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+            .map(|since| since.as_nanos());
+        (metadata.len(), modified)
+    });
+    format!("{} {:?}", env!("CARGO_PKG_VERSION"), stamp)
+});
+
+/// A directory of parsed and expanded ASTs.
+#[derive(Debug, Clone)]
+pub struct AstCache {
+    dir: PathBuf,
+}
+
+impl Default for AstCache {
+    fn default() -> Self {
+        Self::new(DEFAULT_CACHE_DIR)
+    }
+}
+
+impl AstCache {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Parses `source`, reusing the AST of an earlier parse of the same source.
+    pub fn parse(&self, source: &str, context: SourceContext) -> Result<Vec<AstNode>, SutraError> {
+        let key = key(&[b"parse", source.as_bytes()]);
+        if let Some(nodes) = self.read(&key) {
+            tracing::trace!("parsed {} from the cache", context.name());
+            return Ok(nodes);
+        }
+        let nodes = parser::parse(source, context)?;
+        self.write(&key, &nodes);
+        Ok(nodes)
+    }
+
+    /// Expands `program` with `macros`, reusing an earlier expansion of the same
+    /// program, spans included, under the same macro definitions.
+    pub fn expand(&self, program: AstNode, macros: &MacroSystem) -> Result<AstNode, SutraError> {
+        let Ok(encoded) = bincode::serialize(&program) else {
+            return macros.expand(program);
+        };
+        let key = key(&[b"expand", &encoded, macros.fingerprint().as_bytes()]);
+        if let Some(expanded) = self.read(&key) {
+            tracing::trace!("expanded a program from the cache");
+            return Ok(expanded);
+        }
+        let expanded = macros.expand(program)?;
+        self.write(&key, &expanded);
+        Ok(expanded)
+    }
+
+    /// Deletes the cache directory. Returns whether there was one.
+    pub fn clear(&self) -> io::Result<bool> {
+        match fs::remove_dir_all(&self.dir) {
+            Ok(()) => Ok(true),
+            Err(error) if error.kind() == io::ErrorKind::NotFound => Ok(false),
+            Err(error) => Err(error),
+        }
+    }
+
+    fn entry(&self, key: &str) -> PathBuf {
+        self.dir.join(format!("{key}.bin"))
+    }
+
+    pub(crate) fn read<T: DeserializeOwned>(&self, key: &str) -> Option<T> {
+        let bytes = fs::read(self.entry(key)).ok()?;
+        bincode::deserialize(&bytes).ok()
+    }
+
+    /// Writes an entry through a temporary file, so a concurrent run never reads half
+    /// of one.
+    pub(crate) fn write<T: Serialize>(&self, key: &str, value: &T) {
+        let Ok(bytes) = bincode::serialize(value) else {
+            return;
+        };
+        let path = self.entry(key);
+        let temporary = path.with_extension(format!("{}.tmp", std::process::id()));
+        let written = fs::create_dir_all(&self.dir)
+            .and_then(|()| fs::write(&temporary, bytes))
+            .and_then(|()| fs::rename(&temporary, &path));
+        if let Err(error) = written {
+            tracing::debug!("could not cache {}: {error}", path.display());
+            let _ = fs::remove_file(&temporary);
+        }
+    }
+}
+
+/// Parses `source` through `cache` when there is one.
+pub fn parse_with(
+    cache: Option<&AstCache>,
+    source: &str,
+    context: SourceContext,
+) -> Result<Vec<AstNode>, SutraError> {
+    match cache {
+        Some(cache) => cache.parse(source, context),
+        None => parser::parse(source, context),
+    }
+}
+
+/// Expands `program` with `macros` through `cache` when there is one.
+pub fn expand_with(
+    cache: Option<&AstCache>,
+    program: AstNode,
+    macros: &MacroSystem,
+) -> Result<AstNode, SutraError> {
+    match cache {
+        Some(cache) => cache.expand(program, macros),
+        None => macros.expand(program),
+    }
+}
+
+/// A SHA-256 hash, in hex, of the engine build and `parts`. Parts are
+/// length-prefixed so no two lists of them share an encoding.
+pub(crate) fn key(parts: &[&[u8]]) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(ENGINE_BUILD.as_bytes());
+    for part in parts {
+        hasher.update((part.len() as u64).to_le_bytes());
+        hasher.update(part);
+    }
+    hasher
+        .finalize()
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cache(name: &str) -> AstCache {
+        let cache = AstCache::new(std::env::temp_dir().join(name));
+        cache.clear().unwrap();
+        cache
+    }
+
+    fn entries(cache: &AstCache) -> usize {
+        fs::read_dir(cache.dir()).map_or(0, |entries| entries.count())
+    }
+
+    #[test]
+    fn reuses_parses_and_expansions_of_the_same_source() {
+        let cache = cache("sutra_ast_cache_reuse");
+        let source = "(m 2)";
+        let parse = || {
+            cache
+                .parse(source, SourceContext::from_file("t", source))
+                .unwrap()
+        };
+        let nodes = parse();
+        assert_eq!(parse(), nodes);
+        assert_eq!(entries(&cache), 1);
+
+        let expand = |prelude: &str| {
+            let mut macros = MacroSystem::new();
+            macros.load_from_source("prelude", prelude).unwrap();
+            let expanded = cache.expand(nodes[0].clone(), &macros).unwrap();
+            expanded.value.pretty()
+        };
+        assert_eq!(expand("(define (m x) (+ x 1))"), "(+ 2 1)");
+        assert_eq!(expand("(define (m x) (+ x 1))"), "(+ 2 1)");
+        assert_eq!(entries(&cache), 2);
+        assert_eq!(expand("(define (m x) (- x 1))"), "(- 2 1)");
+        assert_eq!(entries(&cache), 3);
+
+        assert!(cache.clear().unwrap());
+        assert!(!cache.clear().unwrap());
+    }
+
+    #[test]
+    fn errors_and_unreadable_entries_are_not_reused() {
+        let cache = cache("sutra_ast_cache_errors");
+        let source = "(+ 1";
+        assert!(cache
+            .parse(source, SourceContext::from_file("t", source))
+            .is_err());
+        assert_eq!(entries(&cache), 0);
+
+        let source = "(+ 1 2)";
+        let nodes = cache
+            .parse(source, SourceContext::from_file("t", source))
+            .unwrap();
+        for entry in fs::read_dir(cache.dir()).unwrap() {
+            fs::write(entry.unwrap().path(), b"not bincode").unwrap();
+        }
+        assert_eq!(
+            cache
+                .parse(source, SourceContext::from_file("t", source))
+                .unwrap(),
+            nodes
+        );
+        cache.clear().unwrap();
+    }
+}
@@ -10,12 +10,24 @@
 //
 // - **`math`**: Arithmetic operations (`+`, `-`, `*`, `/`, `mod`, `abs`, `min`, `max`)
 // - **`logic`**: Logic and comparison operations (`eq?`, `gt?`, `lt?`, `not`, etc.)
-// - **`collections`**: List and collection operations (`list`, `len`, `null?`, `car`, `cdr`, etc.)
-// - **`world`**: World state management (`set!`, `get`, `del!`, `exists?`, etc.)
+// - **`collections`**: List and collection operations (`list`, `len`, `car`, `cdr`, `sort`, `range`, etc.)
+// - **`world`**: World state management (`set!`, `get`, `del!`, `exists?`, `undo!`, etc.)
 // - **`execution`**: Control flow and execution (`do`, `error`, `apply`, `for-each`)
-// - **`external`**: External I/O operations (`print`, `println`, `output`, `rand`)
+// - **`external`**: External I/O operations (`print`, `println`, `output`, `with-output-to-string`, `rand`, `args`, `read-line`, `choice`)
 // - **`string`**: String manipulation (`str`, `str+`)
-// - **`special_forms`**: Language special forms (`lambda`, `let`, `if`, `cond`, `and`, `or`, `define`)
+// - **`convert`**: Type conversions and predicates (`num->str`, `format-number`, `str->num`, `list?`, etc.)
+// - **`json`**: JSON interop (`json/parse`, `json/stringify`)
+// - **`table`**: CSV/TSV tables (`table/load`, `table/get`, `table/filter`, `table/index-by`)
+// - **`coroutines`**: Coroutines (`coroutine`, `resume`, `yield`, `coroutine/done?`)
+// - **`net`**: HTTP requests (`http/get`), only with the `net` feature
+// - **`text`**: Text generation (`text/pick`, `plural`, `quantity`, `tr`), used by the `text` macro
+// - **`special_forms`**: Language special forms (`lambda`, `let`, `if`, `cond`, `and`, `or`, `define`, `:`)
+// - **`records`**: Record types (`defrecord`, `record/make`, `record/is?`, `record/get`)
+// - **`prototypes`**: Entity prototypes (`defproto`, `spawn!`, `proto-of`)
+// - **`tags`**: Tag queries (`tag!`, `untag!`, `with-tag`)
+// - **`roll_table`**: Weighted random tables (`core/deftable`, `roll-table`), used by the `deftable` macro
+// - **`state_machine`**: State machines (`core/defstate`, `advance!`), used by the `defstate` macro
+// - **`property`**: Property-based testing (`for-all`, `gen/int`, `gen/list-of`, etc.)
 //
 // ## Architecture
 //
@@ -26,10 +38,13 @@
 // ```
 //
 // ### Registration System
-// Atoms are registered into the global environment using the `register_atom!` macro.
+// Atoms are registered into the global environment using the `register_atom!` macro,
+// or `World::register_atom` from embedding code.
 // All atoms use the same unified `NativeFn` signature regardless of whether they
 // control their own argument evaluation (like special forms) or expect pre-evaluated
-// arguments (like arithmetic operations).
+// arguments (like arithmetic operations). The evaluator has no list of special forms:
+// it resolves every call head through the environment, so a host-registered special
+// form is dispatched exactly like `if` or `lambda`.
 //
 // ### Aliases
 // Many comparison and mathematical operations support multiple aliases for convenience:
@@ -47,27 +62,42 @@
 // - **PRNG**: A seedable random number generator for deterministic randomness
 // - **Macros**: A macro expansion system for code transformation
 
-use std::{cell::RefCell, collections::HashMap, fmt, fs, rc::Rc};
+use std::{
+    cell::RefCell,
+    collections::{BTreeMap, BTreeSet},
+    fmt, fs,
+    ops::{RangeFrom, RangeInclusive},
+    rc::Rc,
+    sync::{Arc, PoisonError, RwLock},
+};
 
 use rand::{RngCore, SeedableRng};
 use rand_xoshiro::Xoshiro256StarStar;
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 
 // Core types via prelude
 use crate::prelude::*;
 
 // Domain modules with aliases
+use crate::localization::Localization;
 use crate::macros::MacroSystem;
+use crate::replay::{Event, SharedSession};
+use crate::runtime::History;
+use crate::world_diff::{ConflictError, WorldDiff};
+use prototypes::Prototype;
+use roll_table::RollTable;
+use state_machine::StateMachine;
 
 // Using a concrete, seedable PRNG for determinism.
-type SmallRng = Xoshiro256StarStar;
+pub(crate) type SmallRng = Xoshiro256StarStar;
 
 // ============================================================================
 // WORLD STATE - Simplified state management
 // ============================================================================
 
 /// A canonical, type-safe representation of a path into the world state.
-#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
 pub struct Path(pub Vec<String>);
 
 impl fmt::Display for Path {
@@ -76,16 +106,109 @@ impl fmt::Display for Path {
     }
 }
 
+/// What a write does when a parent of its path is missing or not a map.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum PathPolicy {
+    /// Creates the missing maps, replacing any non-map value in the way.
+    #[default]
+    Vivify,
+    /// Like `Vivify`, but validation warns about `set!` calls that rely on it.
+    Warn,
+    /// Fails the write.
+    Strict,
+}
+
+impl PathPolicy {
+    pub const ALL: [PathPolicy; 3] = [Self::Vivify, Self::Warn, Self::Strict];
+}
+
+impl fmt::Display for PathPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Vivify => write!(f, "vivify"),
+            Self::Warn => write!(f, "warn"),
+            Self::Strict => write!(f, "strict"),
+        }
+    }
+}
+
+impl std::str::FromStr for PathPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|policy| policy.to_string() == s)
+            .ok_or_else(|| {
+                format!(
+                    "unknown path policy '{}' (expected vivify, warn or strict)",
+                    s
+                )
+            })
+    }
+}
+
+/// How important a `log/...` message is. Messages below the world's log level are
+/// dropped.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub enum LogLevel {
+    Debug,
+    #[default]
+    Info,
+    Warn,
+}
+
+impl LogLevel {
+    pub const ALL: [LogLevel; 3] = [Self::Debug, Self::Info, Self::Warn];
+}
+
+impl fmt::Display for LogLevel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Debug => write!(f, "debug"),
+            Self::Info => write!(f, "info"),
+            Self::Warn => write!(f, "warn"),
+        }
+    }
+}
+
+impl std::str::FromStr for LogLevel {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|level| level.to_string() == s)
+            .ok_or_else(|| format!("unknown log level '{}' (expected debug, info or warn)", s))
+    }
+}
+
+/// The size of a world subtree, from [`World::stats`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct WorldStats {
+    /// Entries in all of its maps, as [`WorldState::size`] counts them.
+    pub nodes: usize,
+    /// Maps among those entries.
+    pub maps: usize,
+    /// Segments in the longest path below the root.
+    pub depth: usize,
+    /// A rough estimate of the memory its values take.
+    pub bytes: usize,
+}
+
 /// Simplified world state with root map structure
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct WorldState {
     data: Value,
+    /// The paths carrying each tag, kept beside the tree so finding them needs no scan.
+    tags: BTreeMap<String, BTreeSet<Path>>,
 }
 
 impl WorldState {
     pub fn new() -> Self {
         Self {
-            data: Value::Map(HashMap::new()),
+            data: Value::Map(BTreeMap::new()),
+            tags: BTreeMap::new(),
         }
     }
 
@@ -108,11 +231,132 @@ impl WorldState {
         set_recursive_mut(&mut self.data, &path.0, val);
     }
 
+    /// Sets `path` like [`set`](Self::set), except that under [`PathPolicy::Strict`]
+    /// it fails with the first parent of `path` that is not a map.
+    pub fn set_with_policy(
+        &mut self,
+        path: &Path,
+        val: Value,
+        policy: PathPolicy,
+    ) -> Result<(), Path> {
+        if policy == PathPolicy::Strict {
+            if let Some(parent) = self.missing_parent(path) {
+                return Err(parent);
+            }
+        }
+        self.set(path, val);
+        Ok(())
+    }
+
+    /// The shortest parent of `path` that does not hold a map, if any.
+    fn missing_parent(&self, path: &Path) -> Option<Path> {
+        (1..path.0.len())
+            .map(|len| Path(path.0[..len].to_vec()))
+            .find(|parent| !matches!(self.get(parent), Some(Value::Map(_))))
+    }
+
+    /// Deletes the value at `path`, and the tags of `path` and the paths below it.
     pub fn del(&mut self, path: &Path) {
         if path.0.is_empty() {
             return;
         }
         del_recursive_mut(&mut self.data, &path.0);
+        for paths in self.tags.values_mut() {
+            paths.retain(|tagged| !tagged.0.starts_with(&path.0));
+        }
+        self.tags.retain(|_, paths| !paths.is_empty());
+    }
+
+    /// Tags `path` with `tag`. Returns `false` if it already had the tag.
+    pub fn tag(&mut self, path: &Path, tag: &str) -> bool {
+        self.tags
+            .entry(tag.to_string())
+            .or_default()
+            .insert(path.clone())
+    }
+
+    /// Removes `tag` from `path`. Returns `false` if it did not have the tag.
+    pub fn untag(&mut self, path: &Path, tag: &str) -> bool {
+        let Some(paths) = self.tags.get_mut(tag) else {
+            return false;
+        };
+        let removed = paths.remove(path);
+        if paths.is_empty() {
+            self.tags.remove(tag);
+        }
+        removed
+    }
+
+    /// The paths tagged with `tag`, in path order.
+    pub fn tagged(&self, tag: &str) -> impl Iterator<Item = &Path> {
+        self.tags.get(tag).into_iter().flatten()
+    }
+
+    /// Every tag in use, with the paths carrying it.
+    pub fn tags(&self) -> &BTreeMap<String, BTreeSet<Path>> {
+        &self.tags
+    }
+
+    /// Replaces every tag with `tags`.
+    pub fn replace_tags(&mut self, tags: BTreeMap<String, BTreeSet<Path>>) {
+        self.tags = tags;
+    }
+
+    /// Approximate size of the state: the number of entries in all of its maps,
+    /// not counting registered atoms.
+    pub fn size(&self) -> usize {
+        fn entries(value: &Value) -> usize {
+            match value {
+                Value::Map(map) => map
+                    .values()
+                    .filter(|value| !matches!(value, Value::NativeFn(_)))
+                    .map(|value| 1 + entries(value))
+                    .sum(),
+                _ => 0,
+            }
+        }
+        entries(&self.data)
+    }
+
+    /// The size of the subtree at `path`, or of the whole state without its registered
+    /// atoms when `path` is empty. A missing subtree has no size.
+    pub fn stats(&self, path: &Path) -> WorldStats {
+        let root = if path.0.is_empty() {
+            Value::Map(self.entries())
+        } else {
+            match self.get(path) {
+                Some(value) => value.clone(),
+                None => return WorldStats::default(),
+            }
+        };
+        let mut stats = WorldStats {
+            bytes: estimated_bytes(&root),
+            ..WorldStats::default()
+        };
+        if let Value::Map(map) = &root {
+            count_entries(map, 1, &mut stats);
+        }
+        stats
+    }
+
+    /// The top-level entries of the state, leaving out registered atoms.
+    pub fn entries(&self) -> BTreeMap<String, Value> {
+        match &self.data {
+            Value::Map(map) => map
+                .iter()
+                .filter(|(_, value)| !matches!(value, Value::NativeFn(_)))
+                .map(|(key, value)| (key.clone(), value.clone()))
+                .collect(),
+            _ => BTreeMap::new(),
+        }
+    }
+
+    /// Replaces every top-level entry but the registered atoms with `entries`.
+    pub fn replace_entries(&mut self, entries: BTreeMap<String, Value>) {
+        if let Value::Map(map) = &mut self.data {
+            map.retain(|_, value| matches!(value, Value::NativeFn(_)));
+            map.extend(entries);
+        }
     }
 }
 
@@ -122,12 +366,42 @@ impl Default for WorldState {
     }
 }
 
-/// Top-level world container with state, PRNG, and macro environment
-#[derive(Debug)]
+/// Top-level world container with state, PRNG, macro environment, and definitions
+#[derive(Debug, Clone)]
 pub struct World {
     pub state: WorldState,
     pub prng: SmallRng,
     pub macros: MacroSystem,
+    /// Values bound by top-level `define`, visible to every later evaluation against this world.
+    pub defs: BTreeMap<String, Value>,
+    /// Message catalogs used by `tr`.
+    pub localization: Localization,
+    /// Checkpoints for `undo!` and `redo!`.
+    pub history: History,
+    /// Arity, argument types and documentation of each registered atom, keyed by name.
+    pub atom_specs: BTreeMap<String, AtomSpec>,
+    /// How `set!` treats missing parents of the path it writes.
+    pub path_policy: PathPolicy,
+    /// Arguments passed to the script on the command line, returned by `args`.
+    pub script_args: Vec<String>,
+    /// Text `print` and `println` write between their arguments. Empty by default.
+    pub print_separator: String,
+    /// Whether `assert` checks its condition. Shipping builds may turn this off.
+    pub assertions: bool,
+    /// The least important `log/...` messages that are written.
+    pub log_level: LogLevel,
+    /// The replay log that PRNG draws and player input are recorded to or replayed from.
+    pub session: Option<SharedSession>,
+    /// Prototypes declared by `defproto`, keyed by name.
+    pub prototypes: BTreeMap<String, Prototype>,
+    /// Weighted tables declared by `deftable`, keyed by name.
+    pub roll_tables: BTreeMap<String, RollTable>,
+    /// State machines declared by `defstate`, keyed by name.
+    pub state_machines: BTreeMap<String, StateMachine>,
+    /// Set while a coroutine is run again to restore a snapshot, during which nothing
+    /// it does may reach outside it: resuming another coroutine or reading input would
+    /// happen twice, and its PRNG draws are not noted in the session.
+    pub replaying: bool,
 }
 
 impl World {
@@ -136,6 +410,20 @@ impl World {
             state: WorldState::new(),
             prng: SmallRng::from_entropy(),
             macros: MacroSystem::new(),
+            defs: BTreeMap::new(),
+            localization: Localization::default(),
+            history: History::default(),
+            atom_specs: BTreeMap::new(),
+            path_policy: PathPolicy::default(),
+            script_args: Vec::new(),
+            print_separator: String::new(),
+            assertions: true,
+            log_level: LogLevel::default(),
+            session: None,
+            prototypes: BTreeMap::new(),
+            roll_tables: BTreeMap::new(),
+            state_machines: BTreeMap::new(),
+            replaying: false,
         }
     }
 
@@ -144,6 +432,43 @@ impl World {
             state: WorldState::new(),
             prng: SmallRng::from_seed(seed),
             macros: MacroSystem::new(),
+            defs: BTreeMap::new(),
+            localization: Localization::default(),
+            history: History::default(),
+            atom_specs: BTreeMap::new(),
+            path_policy: PathPolicy::default(),
+            script_args: Vec::new(),
+            print_separator: String::new(),
+            assertions: true,
+            log_level: LogLevel::default(),
+            session: None,
+            prototypes: BTreeMap::new(),
+            roll_tables: BTreeMap::new(),
+            state_machines: BTreeMap::new(),
+            replaying: false,
+        }
+    }
+
+    /// Creates a world whose PRNG is seeded from a single integer, for reproducible runs.
+    pub fn seed_from_u64(seed: u64) -> Self {
+        Self {
+            state: WorldState::new(),
+            prng: SmallRng::seed_from_u64(seed),
+            macros: MacroSystem::new(),
+            defs: BTreeMap::new(),
+            localization: Localization::default(),
+            history: History::default(),
+            atom_specs: BTreeMap::new(),
+            path_policy: PathPolicy::default(),
+            script_args: Vec::new(),
+            print_separator: String::new(),
+            assertions: true,
+            log_level: LogLevel::default(),
+            session: None,
+            prototypes: BTreeMap::new(),
+            roll_tables: BTreeMap::new(),
+            state_machines: BTreeMap::new(),
+            replaying: false,
         }
     }
 
@@ -155,15 +480,264 @@ impl World {
         self.state.set(path, val);
     }
 
+    /// Sets `path` under `policy`; see [`WorldState::set_with_policy`].
+    pub fn set_with_policy(
+        &mut self,
+        path: &Path,
+        val: Value,
+        policy: PathPolicy,
+    ) -> Result<(), Path> {
+        self.state.set_with_policy(path, val, policy)
+    }
+
     pub fn del(&mut self, path: &Path) {
         self.state.del(path);
     }
 
+    /// Binds `func` to `spec.name` in the global environment and records its spec.
+    /// Special forms are registered the same way, with [`AtomKind::SpecialForm`].
+    pub fn register_atom(&mut self, spec: AtomSpec, func: NativeFn) {
+        self.set(&Path(vec![spec.name.clone()]), Value::NativeFn(func));
+        self.atom_specs.insert(spec.name.clone(), spec);
+    }
+
+    /// The names of prototype `name` and of the prototypes it inherits from, nearest
+    /// first; empty if there is no such prototype.
+    pub fn prototype_chain(&self, name: &str) -> Vec<String> {
+        let mut chain = Vec::new();
+        let mut current = self.prototypes.get_key_value(name);
+        while let Some((name, prototype)) = current {
+            chain.push(name.clone());
+            current = prototype
+                .parent
+                .as_ref()
+                .and_then(|parent| self.prototypes.get_key_value(parent));
+        }
+        chain
+    }
+
+    /// The fields an instance of prototype `name` starts with, nearer prototypes
+    /// overriding farther ones, or `None` if there is no such prototype.
+    pub fn prototype_fields(&self, name: &str) -> Option<BTreeMap<String, Value>> {
+        let chain = self.prototype_chain(name);
+        if chain.is_empty() {
+            return None;
+        }
+        let mut fields = BTreeMap::new();
+        for ancestor in chain.iter().rev() {
+            fields.extend(self.prototypes[ancestor].fields.clone());
+        }
+        Some(fields)
+    }
+
+    /// Unbinds every atom that needs a capability outside `granted`. Their specs stay,
+    /// so validation can still name the missing capability.
+    pub fn revoke_atoms(&mut self, granted: &[Capability]) {
+        let denied: Vec<String> = self
+            .atom_specs
+            .values()
+            .filter(|spec| spec.missing_capability(granted).is_some())
+            .map(|spec| spec.name.clone())
+            .collect();
+        for name in denied {
+            self.del(&Path(vec![name]));
+        }
+    }
+
+    /// Draws from the PRNG, noting the draw in the session unless a coroutine is being
+    /// replayed for a snapshot.
     pub fn next_u32(&mut self) -> u32 {
-        self.prng.next_u32()
+        let value = self.prng.next_u32();
+        if let Some(session) = &self.session {
+            if !self.replaying {
+                session.lock().observe(Event::Draw(value));
+            }
+        }
+        value
+    }
+
+    /// Records the current state as an undo point.
+    pub fn checkpoint(&mut self) {
+        self.history.checkpoint(&self.state);
+    }
+
+    /// Restores the most recent checkpoint. Returns `false` if there is none.
+    pub fn undo(&mut self) -> bool {
+        match self.history.undo(&self.state) {
+            Some(previous) => {
+                self.state = previous;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Reverses the most recent undo. Returns `false` if there is nothing to redo.
+    pub fn redo(&mut self) -> bool {
+        match self.history.redo(&self.state) {
+            Some(next) => {
+                self.state = next;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// A stable SHA-256 content hash, in hex, of the value at `path`, or of the whole
+    /// state without its registered atoms when `path` is empty. A missing value hashes
+    /// like nil. Maps hash by content, not by the order their entries were set, so two
+    /// worlds that reached the same state hash alike.
+    pub fn hash(&self, path: &Path) -> String {
+        let mut hasher = Sha256::new();
+        if path.0.is_empty() {
+            hash_value(&Value::Map(self.state.entries()), &mut hasher);
+        } else {
+            hash_value(self.get(path).unwrap_or(&Value::Nil), &mut hasher);
+        }
+        hasher
+            .finalize()
+            .iter()
+            .map(|byte| format!("{byte:02x}"))
+            .collect()
+    }
+
+    /// The size of the value at `path`, or of the whole state without its registered
+    /// atoms when `path` is empty; see [`WorldState::stats`].
+    pub fn stats(&self, path: &Path) -> WorldStats {
+        self.state.stats(path)
+    }
+
+    /// Lists the state changes that turn this world into `other`.
+    pub fn diff(&self, other: &World) -> WorldDiff {
+        WorldDiff::between(&self.state, &other.state)
+    }
+
+    /// Returns a copy of this world with `diff` applied to its state, or the
+    /// conflicting paths if the state has changed since the diff was taken.
+    pub fn apply_diff(&self, diff: &WorldDiff) -> Result<World, ConflictError> {
+        let mut patched = self.clone();
+        patched.state = diff.apply_to(&self.state)?;
+        Ok(patched)
     }
 }
 
+/// Feeds `value` to `hasher` as a tag byte and its content. Strings and sequences are
+/// length-prefixed so no two values share an encoding; functions and coroutines have
+/// no comparable content and hash by kind alone.
+fn hash_value(value: &Value, hasher: &mut Sha256) {
+    fn hash_str(s: &str, hasher: &mut Sha256) {
+        hasher.update((s.len() as u64).to_le_bytes());
+        hasher.update(s.as_bytes());
+    }
+    match value {
+        Value::Nil => hasher.update(b"n"),
+        Value::Bool(b) => hasher.update(if *b { b"t" } else { b"f" }),
+        Value::Number(n) => {
+            hasher.update(b"#");
+            // Adding 0.0 turns -0.0 into 0.0, which `equal?` treats as the same number.
+            hasher.update((n + 0.0).to_bits().to_le_bytes());
+        }
+        Value::String(s) => {
+            hasher.update(b"s");
+            hash_str(s, hasher);
+        }
+        Value::Symbol(s) => {
+            hasher.update(b"y");
+            hash_str(s, hasher);
+        }
+        Value::Keyword(s) => {
+            hasher.update(b"k");
+            hash_str(s, hasher);
+        }
+        Value::Path(path) => {
+            hasher.update(b"p");
+            hasher.update((path.0.len() as u64).to_le_bytes());
+            for segment in &path.0 {
+                hash_str(segment, hasher);
+            }
+        }
+        Value::Quote(inner) => {
+            hasher.update(b"q");
+            hash_value(inner, hasher);
+        }
+        Value::Cons(_) => {
+            let items: Vec<Value> = value.clone().try_into_iter().collect();
+            hasher.update(b"l");
+            hasher.update((items.len() as u64).to_le_bytes());
+            for item in &items {
+                hash_value(item, hasher);
+            }
+        }
+        Value::Map(map) => {
+            hasher.update(b"m");
+            hasher.update((map.len() as u64).to_le_bytes());
+            for (key, value) in map {
+                hash_str(key, hasher);
+                hash_value(value, hasher);
+            }
+        }
+        Value::Record(record) => {
+            hasher.update(b"r");
+            hash_str(&record.type_name, hasher);
+            hasher.update((record.fields.len() as u64).to_le_bytes());
+            for (name, value) in &record.fields {
+                hash_str(name, hasher);
+                hash_value(value, hasher);
+            }
+        }
+        Value::Lambda(_) | Value::NativeFn(_) => hasher.update(b"\\"),
+        Value::Coroutine(_) => hasher.update(b"c"),
+        Value::StringBuilder(builder) => {
+            hasher.update(b"b");
+            hash_str(&builder.text(), hasher);
+        }
+    }
+}
+
+/// Adds the entries of `map`, which lies `depth` segments below the root, and of the
+/// maps in it to `stats`.
+fn count_entries(map: &BTreeMap<String, Value>, depth: usize, stats: &mut WorldStats) {
+    for value in map.values() {
+        stats.nodes += 1;
+        stats.depth = stats.depth.max(depth);
+        if let Value::Map(map) = value {
+            stats.maps += 1;
+            count_entries(map, depth + 1, stats);
+        }
+    }
+}
+
+/// A rough estimate of the bytes `value` takes: a `Value` for it and each value it
+/// holds, plus the text of its strings, keys and names. Functions count as a `Value`.
+fn estimated_bytes(value: &Value) -> usize {
+    let contents = match value {
+        Value::String(s) | Value::Symbol(s) | Value::Keyword(s) => s.len(),
+        Value::Path(path) => path.0.iter().map(String::len).sum(),
+        Value::Quote(inner) => estimated_bytes(inner),
+        Value::Cons(_) => value.clone().try_into_iter().map(|item| estimated_bytes(&item)).sum(),
+        Value::Map(map) => map
+            .iter()
+            .map(|(key, value)| key.len() + estimated_bytes(value))
+            .sum(),
+        Value::Record(record) => {
+            record.type_name.len()
+                + record
+                    .fields
+                    .iter()
+                    .map(|(name, value)| name.len() + estimated_bytes(value))
+                    .sum::<usize>()
+        }
+        Value::StringBuilder(builder) => builder.len(),
+        Value::Nil
+        | Value::Number(_)
+        | Value::Bool(_)
+        | Value::Lambda(_)
+        | Value::NativeFn(_)
+        | Value::Coroutine(_) => 0,
+    };
+    std::mem::size_of::<Value>() + contents
+}
+
 /// Recursive helper for mutable set operations
 fn set_recursive_mut(current: &mut Value, path_segments: &[String], val: Value) {
     let Some(key) = path_segments.first() else {
@@ -174,11 +748,11 @@ fn set_recursive_mut(current: &mut Value, path_segments: &[String], val: Value)
 
     // Ensure the current value is a map, upgrading if necessary.
     if !matches!(current, Value::Map(_)) {
-        *current = Value::Map(HashMap::new());
+        *current = Value::Map(BTreeMap::new());
     }
 
     let Value::Map(map) = current else {
-        unreachable!(); // Should have been upgraded above
+        return; // Upgraded above
     };
 
     if remaining_segments.is_empty() {
@@ -186,7 +760,7 @@ fn set_recursive_mut(current: &mut Value, path_segments: &[String], val: Value)
     } else {
         let child = map
             .entry(key.clone())
-            .or_insert_with(|| Value::Map(HashMap::new()));
+            .or_insert_with(|| Value::Map(BTreeMap::new()));
         set_recursive_mut(child, remaining_segments, val);
     }
 }
@@ -214,11 +788,76 @@ fn del_recursive_mut(current: &mut Value, path_segments: &[String]) {
     }
 }
 
+// ============================================================================
+// SHARED WORLD HANDLES
+// ============================================================================
+
+/// A shared handle to a world, as held by the pipeline, every evaluation context and
+/// the coroutines they make. [`CanonicalWorld`] is `Rc<RefCell<World>>`, or with the
+/// `sync` feature `Arc<RwLock<World>>`, which is `Send + Sync` so hosts can run the
+/// engine on worker threads. The engine reads and writes worlds only through this
+/// trait, so it builds with either.
+pub trait WorldHandle: Clone {
+    type Read<'a>: std::ops::Deref<Target = World>
+    where
+        Self: 'a;
+    type Write<'a>: std::ops::DerefMut<Target = World>
+    where
+        Self: 'a;
+
+    /// A handle to a new shared world.
+    fn from_world(world: World) -> Self;
+
+    /// Borrows the world to read it, waiting for any writer on another thread.
+    /// Panics if this thread is writing it.
+    fn read(&self) -> Self::Read<'_>;
+
+    /// Borrows the world to change it, waiting for other threads to finish with it.
+    /// Panics if this thread is already reading or writing it.
+    fn write(&self) -> Self::Write<'_>;
+}
+
+impl WorldHandle for Rc<RefCell<World>> {
+    type Read<'a> = std::cell::Ref<'a, World>;
+    type Write<'a> = std::cell::RefMut<'a, World>;
+
+    fn from_world(world: World) -> Self {
+        Rc::new(RefCell::new(world))
+    }
+
+    fn read(&self) -> Self::Read<'_> {
+        self.borrow()
+    }
+
+    fn write(&self) -> Self::Write<'_> {
+        self.borrow_mut()
+    }
+}
+
+/// A lock poisoned by a panic while writing is still used: a failed evaluation
+/// already leaves the world as it was when it failed.
+impl WorldHandle for Arc<RwLock<World>> {
+    type Read<'a> = std::sync::RwLockReadGuard<'a, World>;
+    type Write<'a> = std::sync::RwLockWriteGuard<'a, World>;
+
+    fn from_world(world: World) -> Self {
+        Arc::new(RwLock::new(world))
+    }
+
+    fn read(&self) -> Self::Read<'_> {
+        RwLock::read(self).unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn write(&self) -> Self::Write<'_> {
+        RwLock::write(self).unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
 /// Builds and returns a canonical, fully-populated world for evaluation.
 pub fn build_canonical_world() -> CanonicalWorld {
     let mut world = World::new();
     register_all_atoms(&mut world);
-    Rc::new(RefCell::new(world))
+    CanonicalWorld::from_world(world)
 }
 
 /// Builds and returns the canonical macro environment
@@ -227,8 +866,9 @@ pub fn build_canonical_macro_env() -> Result<MacroSystem, SutraError> {
     let mut env = MacroSystem::new();
 
     // Step 2: Load user macros from file
-    let file_content = fs::read_to_string("src/macros/std_macros.sutra").unwrap_or_default();
-    env.load_from_source(&file_content)?;
+    let path = "src/macros/std_macros.sutra";
+    let file_content = fs::read_to_string(path).unwrap_or_default();
+    env.load_from_source(path, &file_content)?;
 
     Ok(env)
 }
@@ -263,9 +903,82 @@ pub trait StateContext {
     fn exists(&self, path: &Path) -> bool;
 }
 
+/// A question put to the host by `choice`: an optional prompt, and the labels of the
+/// branches to pick from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Choice {
+    pub prompt: Option<String>,
+    pub options: Vec<String>,
+}
+
+impl Choice {
+    /// The choice as the value a coroutine yields when it asks one: a map of its
+    /// `prompt`, nil if it has none, and the list of its `options`.
+    pub fn to_value(&self) -> Value {
+        let prompt = self.prompt.clone().map_or(Value::Nil, Value::String);
+        let options = self.options.iter().cloned().map(Value::String).collect();
+        Value::Map(BTreeMap::from([
+            ("prompt".to_string(), prompt),
+            ("options".to_string(), Value::from_list(options)),
+        ]))
+    }
+
+    /// The choice `value` holds, if it is one made by [`Choice::to_value`], so hosts
+    /// can tell a coroutine's choices from its other yields.
+    pub fn from_value(value: &Value) -> Option<Self> {
+        let map = value.as_map().filter(|map| map.len() == 2)?;
+        let prompt = match map.get("prompt")? {
+            Value::Nil => None,
+            prompt => Some(prompt.as_str()?.to_string()),
+        };
+        let options = map.get("options")?.clone().try_into_iter();
+        let options = options
+            .map(|option| option.as_str().map(String::from))
+            .collect::<Option<Vec<_>>>()?;
+        Some(Self { prompt, options })
+    }
+}
+
+impl fmt::Display for Choice {
+    /// The prompt, then one numbered line per option, counting from 1.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(prompt) = &self.prompt {
+            writeln!(f, "{prompt}")?;
+        }
+        for (number, option) in self.options.iter().enumerate() {
+            writeln!(f, "{}. {option}", number + 1)?;
+        }
+        Ok(())
+    }
+}
+
 /// Output sink for `print` and similar I/O operations, enabling testable and injectable output.
 pub trait OutputSink {
+    /// Writes `text` exactly as given. Sinks add nothing, not even a newline: atoms
+    /// such as `println` include any they want.
     fn emit(&mut self, text: &str, span: Option<&Span>);
+
+    /// Whether text written here may contain ANSI color codes. Defaults to false.
+    fn supports_color(&self) -> bool {
+        false
+    }
+
+    /// Asks the host to pick one of `choice.options`, returning its index. Evaluation
+    /// is suspended until this returns, then resumes in the chosen branch. Defaults to
+    /// `None`, meaning the sink cannot ask, which makes `choice` fail.
+    fn choose(&mut self, _choice: &Choice, _span: Option<&Span>) -> Option<usize> {
+        None
+    }
+
+    /// Writes a `log/...` message that passed the world's log level. Defaults to
+    /// emitting it as a line prefixed by its level, e.g. `[warn] hp is low`.
+    fn log(&mut self, level: LogLevel, message: &str, span: Option<&Span>) {
+        self.emit(&format!("[{level}] {message}\n"), span);
+    }
+
+    /// Reports that `done` of the `total` steps of a long-running `task`, such as a
+    /// `generate!` call, are finished. Defaults to doing nothing.
+    fn progress(&mut self, _task: &str, _done: usize, _total: usize, _span: Option<&Span>) {}
 }
 
 /// A null output sink for testing or running without output.
@@ -287,6 +1000,18 @@ impl SharedOutput {
     pub fn emit(&self, text: &str, span: Option<&Span>) {
         self.0.borrow_mut().emit(text, span);
     }
+    /// Ask the sink to pick one of a choice's options.
+    pub fn choose(&self, choice: &Choice, span: Option<&Span>) -> Option<usize> {
+        self.0.borrow_mut().choose(choice, span)
+    }
+    /// Write a log message via the sink.
+    pub fn log(&self, level: LogLevel, message: &str, span: Option<&Span>) {
+        self.0.borrow_mut().log(level, message, span);
+    }
+    /// Report a task's progress via the sink.
+    pub fn progress(&self, task: &str, done: usize, total: usize, span: Option<&Span>) {
+        self.0.borrow_mut().progress(task, done, total, span);
+    }
     /// Borrow the sink mutably (for advanced use).
     pub fn borrow_mut(&self) -> std::cell::RefMut<'_, dyn OutputSink> {
         self.0.borrow_mut()
@@ -299,11 +1024,24 @@ impl SharedOutput {
 
 // Domain-specific atom modules
 pub mod collections;
+pub mod convert;
+pub mod coroutines;
 pub mod execution;
 pub mod external;
+pub mod json;
 pub mod logic;
 pub mod math;
+#[cfg(feature = "net")]
+pub mod net;
+pub mod prototypes;
+pub mod records;
+pub mod roll_table;
 pub mod special_forms;
+pub mod state_machine;
+pub mod string_builder;
+pub mod table;
+pub mod tags;
+pub mod text;
 pub mod world;
 
 // Re-export output types for external use
@@ -313,19 +1051,257 @@ pub use external::{EngineOutputBuffer, EngineStdoutSink};
 #[cfg(any(test, feature = "test-atom", debug_assertions))]
 pub mod test;
 
+// Property-based testing atoms - shipped alongside the test atoms
+#[cfg(any(test, feature = "test-atom", debug_assertions))]
+pub mod property;
+
 // ============================================================================
 // ATOM REGISTRATION SYSTEM
 // ============================================================================
 
+/// What an atom does besides computing a value from its arguments.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AtomKind {
+    /// Depends only on its arguments.
+    Pure,
+    /// Reads or changes the world, output, definitions or random state.
+    Stateful,
+    /// Decides which of its arguments are evaluated, and when (`if`, `lambda`).
+    SpecialForm,
+}
+
+/// Something outside its arguments that an atom reaches. A run that does not grant a
+/// capability rejects scripts calling atoms that need it (see `ExecutionPipelineBuilder`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Capability {
+    /// Writes to the host's output or reads files.
+    Io,
+    /// Reads or changes the world state.
+    State,
+    /// Draws from the world's PRNG.
+    Random,
+    /// Reads the clock.
+    Time,
+    /// Makes network requests. Never granted to sandboxed scripts.
+    Net,
+    /// Runs code built while the script runs, which no check before it can see.
+    Eval,
+}
+
+impl Capability {
+    pub const ALL: [Capability; 6] = [
+        Self::Io,
+        Self::State,
+        Self::Random,
+        Self::Time,
+        Self::Net,
+        Self::Eval,
+    ];
+}
+
+impl fmt::Display for Capability {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io => write!(f, "io"),
+            Self::State => write!(f, "state"),
+            Self::Random => write!(f, "random"),
+            Self::Time => write!(f, "time"),
+            Self::Net => write!(f, "net"),
+            Self::Eval => write!(f, "eval"),
+        }
+    }
+}
+
+impl std::str::FromStr for Capability {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|capability| capability.to_string() == s)
+            .ok_or_else(|| {
+                format!(
+                    "unknown capability '{}' (expected io, state, random, time, net or eval)",
+                    s
+                )
+            })
+    }
+}
+
+/// How many arguments an atom takes: `2`, `2..` or `2..=3`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Arity {
+    pub min: usize,
+    /// `None` if any number of further arguments is accepted.
+    pub max: Option<usize>,
+}
+
+impl From<usize> for Arity {
+    fn from(count: usize) -> Self {
+        Self {
+            min: count,
+            max: Some(count),
+        }
+    }
+}
+
+impl From<RangeFrom<usize>> for Arity {
+    fn from(range: RangeFrom<usize>) -> Self {
+        Self {
+            min: range.start,
+            max: None,
+        }
+    }
+}
+
+impl From<RangeInclusive<usize>> for Arity {
+    fn from(range: RangeInclusive<usize>) -> Self {
+        Self {
+            min: *range.start(),
+            max: Some(*range.end()),
+        }
+    }
+}
+
+/// What tooling knows about an atom without calling it: its arity, argument types and
+/// a one-line description. Calls are checked against it before the atom runs.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct AtomSpec {
+    pub name: String,
+    pub kind: AtomKind,
+    pub min_args: usize,
+    /// `None` if any number of further arguments is accepted.
+    pub max_args: Option<usize>,
+    /// The type of each argument; the last one also applies to any further arguments.
+    pub arg_types: Vec<&'static str>,
+    pub doc: &'static str,
+    /// What the atom needs beyond its arguments; empty for most atoms.
+    pub capabilities: Vec<Capability>,
+}
+
+impl AtomSpec {
+    pub fn new(
+        name: &str,
+        kind: AtomKind,
+        arity: impl Into<Arity>,
+        arg_types: &[&'static str],
+        doc: &'static str,
+    ) -> Self {
+        let arity = arity.into();
+        Self {
+            name: name.to_string(),
+            kind,
+            min_args: arity.min,
+            max_args: arity.max,
+            arg_types: arg_types.to_vec(),
+            doc,
+            capabilities: Vec::new(),
+        }
+    }
+
+    /// Declares the capabilities the atom needs.
+    pub fn requires(mut self, capabilities: &[Capability]) -> Self {
+        self.capabilities = capabilities.to_vec();
+        self
+    }
+
+    /// The first capability the atom needs that is not in `granted`.
+    pub fn missing_capability(&self, granted: &[Capability]) -> Option<Capability> {
+        self.capabilities
+            .iter()
+            .copied()
+            .find(|capability| !granted.contains(capability))
+    }
+
+    /// Returns true if the atom can be called with `count` arguments.
+    pub fn accepts(&self, count: usize) -> bool {
+        count >= self.min_args && self.max_args.is_none_or(|max| count <= max)
+    }
+
+    /// The accepted argument count, worded for arity errors: `2`, `2 or 3`, `at least 1`.
+    pub fn expected(&self) -> String {
+        match self.max_args {
+            None => format!("at least {}", self.min_args),
+            Some(max) if max == self.min_args => max.to_string(),
+            Some(max) if max == self.min_args + 1 => format!("{} or {max}", self.min_args),
+            Some(max) => format!("{} to {max}", self.min_args),
+        }
+    }
+
+    /// A call template such as `(plural <Number> <String> [<String>])`.
+    pub fn usage(&self) -> String {
+        let mut usage = format!("({}", self.name);
+        for index in 0..self.min_args {
+            usage.push_str(&format!(" <{}>", self.arg_type(index)));
+        }
+        match self.max_args {
+            None => usage.push_str(&format!(" [<{}> ...]", self.arg_type(self.min_args))),
+            Some(max) => {
+                for index in self.min_args..max {
+                    usage.push_str(&format!(" [<{}>]", self.arg_type(index)));
+                }
+            }
+        }
+        usage.push(')');
+        usage
+    }
+
+    fn arg_type(&self, index: usize) -> &'static str {
+        self.arg_types
+            .get(index)
+            .or(self.arg_types.last())
+            .copied()
+            .unwrap_or("Any")
+    }
+}
+
 /// Helper macro to register atoms in the global environment.
 /// All atoms use the same `NativeFn` signature regardless of evaluation strategy.
+/// The long form also records an [`AtomSpec`]: kind, arity, argument types, doc and,
+/// after `needs`, the capabilities the atom requires.
 #[macro_export]
 macro_rules! register_atom {
     ($world:expr, $name:expr, $func:expr) => {
-        $world.set(&Path(vec![$name.to_string()]), Value::NativeFn($func));
+        $world.set(
+            &$crate::atoms::Path(vec![$name.to_string()]),
+            $crate::runtime::Value::NativeFn($func),
+        );
+    };
+    (
+        $world:expr, $name:expr, $func:expr, $kind:ident, $arity:expr, [$($ty:expr),* $(,)?],
+        $doc:expr $(, needs [$($capability:ident),*])?
+    ) => {
+        $world.register_atom(
+            $crate::atoms::AtomSpec::new(
+                $name,
+                $crate::atoms::AtomKind::$kind,
+                $arity,
+                &[$($ty),*],
+                $doc,
+            )
+            $(.requires(&[$($crate::atoms::Capability::$capability),*]))?,
+            $func,
+        );
     };
 }
 
+/// Registers `alias` as another name for the already registered atom `target`.
+fn register_alias(world: &mut World, alias: &str, target: &str) {
+    let path = Path(vec![target.to_string()]);
+    if let Some(atom) = world.get(&path).cloned() {
+        world.set(&Path(vec![alias.to_string()]), atom);
+    }
+    if let Some(spec) = world.atom_specs.get(target).cloned() {
+        let spec = AtomSpec {
+            name: alias.to_string(),
+            ..spec
+        };
+        world.atom_specs.insert(alias.to_string(), spec);
+    }
+}
+
 /// Registers all standard atoms from all modules with the given world.
 /// This is the main entry point for populating the global environment.
 pub fn register_all_atoms(world: &mut World) {
@@ -337,95 +1313,1489 @@ pub fn register_all_atoms(world: &mut World) {
     register_execution_atoms(world);
     register_external_atoms(world);
     register_string_atoms(world);
+    register_convert_atoms(world);
+    register_json_atoms(world);
+    register_table_atoms(world);
+    register_coroutine_atoms(world);
+    #[cfg(feature = "net")]
+    register_net_atoms(world);
+    register_text_atoms(world);
     register_special_forms(world);
+    register_record_atoms(world);
+    register_prototype_atoms(world);
+    register_tag_atoms(world);
+    register_roll_table_atoms(world);
+    register_state_machine_atoms(world);
 
     // Register test atoms only in debug or test builds
     #[cfg(any(test, feature = "test-atom", debug_assertions))]
-    test::register_test_atoms(world);
+    {
+        test::register_test_atoms(world);
+        register_property_atoms(world);
+    }
 }
 
 // Private registration functions for each module
 fn register_math_atoms(world: &mut World) {
-    register_atom!(world, "+", math::ATOM_ADD);
-    register_atom!(world, "-", math::ATOM_SUB);
-    register_atom!(world, "*", math::ATOM_MUL);
-    register_atom!(world, "/", math::ATOM_DIV);
-    register_atom!(world, "mod", math::ATOM_MOD);
-    register_atom!(world, "abs", math::ATOM_ABS);
-    register_atom!(world, "min", math::ATOM_MIN);
-    register_atom!(world, "max", math::ATOM_MAX);
+    register_atom!(
+        world,
+        "+",
+        math::ATOM_ADD,
+        Pure,
+        2..,
+        ["Number"],
+        "Adds numbers."
+    );
+    register_atom!(
+        world,
+        "-",
+        math::ATOM_SUB,
+        Pure,
+        1..,
+        ["Number"],
+        "Subtracts numbers; negates a single number."
+    );
+    register_atom!(
+        world,
+        "*",
+        math::ATOM_MUL,
+        Pure,
+        2..,
+        ["Number"],
+        "Multiplies numbers."
+    );
+    register_atom!(
+        world,
+        "/",
+        math::ATOM_DIV,
+        Pure,
+        1..,
+        ["Number"],
+        "Divides numbers; inverts a single number."
+    );
+    register_atom!(
+        world,
+        "mod",
+        math::ATOM_MOD,
+        Pure,
+        2,
+        ["Number"],
+        "Modulo operation."
+    );
+    register_atom!(
+        world,
+        "abs",
+        math::ATOM_ABS,
+        Pure,
+        1,
+        ["Number"],
+        "Absolute value."
+    );
+    register_atom!(
+        world,
+        "min",
+        math::ATOM_MIN,
+        Pure,
+        1..,
+        ["Number"],
+        "Smallest number."
+    );
+    register_atom!(
+        world,
+        "max",
+        math::ATOM_MAX,
+        Pure,
+        1..,
+        ["Number"],
+        "Largest number."
+    );
 }
 
 fn register_logic_atoms(world: &mut World) {
-    register_atom!(world, "eq?", logic::ATOM_EQ);
-    register_atom!(world, "=", logic::ATOM_EQ); // alias for eq?
-    register_atom!(world, "is?", logic::ATOM_EQ); // alias for eq?
-    register_atom!(world, "gt?", logic::ATOM_GT);
-    register_atom!(world, ">", logic::ATOM_GT); // alias for gt?
-    register_atom!(world, "over?", logic::ATOM_GT); // alias for gt?
-    register_atom!(world, "lt?", logic::ATOM_LT);
-    register_atom!(world, "<", logic::ATOM_LT); // alias for lt?
-    register_atom!(world, "under?", logic::ATOM_LT); // alias for lt?
-    register_atom!(world, "gte?", logic::ATOM_GTE);
-    register_atom!(world, ">=", logic::ATOM_GTE); // alias for gte?
-    register_atom!(world, "at-least?", logic::ATOM_GTE); // alias for gte?
-    register_atom!(world, "lte?", logic::ATOM_LTE);
-    register_atom!(world, "<=", logic::ATOM_LTE); // alias for lte?
-    register_atom!(world, "at-most?", logic::ATOM_LTE); // alias for lte?
-    register_atom!(world, "not", logic::ATOM_NOT);
+    register_atom!(
+        world,
+        "eq?",
+        logic::ATOM_EQ,
+        Pure,
+        2..,
+        ["Any"],
+        "True if all are identical: primitives by value, lists and records by identity."
+    );
+    register_alias(world, "=", "eq?");
+    register_alias(world, "is?", "eq?");
+    register_atom!(
+        world,
+        "equal?",
+        logic::ATOM_EQUAL,
+        Pure,
+        2..,
+        ["Any"],
+        "True if all are structurally equal."
+    );
+    register_atom!(
+        world,
+        "approx=",
+        logic::ATOM_APPROX_EQ,
+        Pure,
+        2..=3,
+        ["Number", "Number", "Number"],
+        "True if two numbers differ by at most epsilon (default 1e-9)."
+    );
+    register_atom!(
+        world,
+        "gt?",
+        logic::ATOM_GT,
+        Pure,
+        2..,
+        ["Number"],
+        "True if each number is greater than the next."
+    );
+    register_alias(world, ">", "gt?");
+    register_alias(world, "over?", "gt?");
+    register_atom!(
+        world,
+        "lt?",
+        logic::ATOM_LT,
+        Pure,
+        2..,
+        ["Number"],
+        "True if each number is less than the next."
+    );
+    register_alias(world, "<", "lt?");
+    register_alias(world, "under?", "lt?");
+    register_atom!(
+        world,
+        "gte?",
+        logic::ATOM_GTE,
+        Pure,
+        2..,
+        ["Number"],
+        "True if each number is greater than or equal to the next."
+    );
+    register_alias(world, ">=", "gte?");
+    register_alias(world, "at-least?", "gte?");
+    register_atom!(
+        world,
+        "lte?",
+        logic::ATOM_LTE,
+        Pure,
+        2..,
+        ["Number"],
+        "True if each number is less than or equal to the next."
+    );
+    register_alias(world, "<=", "lte?");
+    register_alias(world, "at-most?", "lte?");
+    register_atom!(
+        world,
+        "not",
+        logic::ATOM_NOT,
+        Pure,
+        1,
+        ["Any"],
+        "Logical negation."
+    );
 }
 
 fn register_world_atoms(world: &mut World) {
-    register_atom!(world, "set!", world::ATOM_SET);
-    register_atom!(world, "get", world::ATOM_GET);
-    register_atom!(world, "del!", world::ATOM_DEL);
-    register_atom!(world, "exists?", world::ATOM_EXISTS);
-    register_atom!(world, "inc!", world::ATOM_INC);
-    register_atom!(world, "dec!", world::ATOM_DEC);
-    register_atom!(world, "add!", world::ATOM_ADD);
-    register_atom!(world, "sub!", world::ATOM_SUB);
-    register_atom!(world, "path", world::ATOM_PATH);
+    register_atom!(
+        world,
+        "set!",
+        world::ATOM_SET,
+        Stateful,
+        2,
+        ["Path", "Any"],
+        "Sets the value at a world path.",
+        needs[State]
+    );
+    register_atom!(
+        world,
+        "ensure-path!",
+        world::ATOM_ENSURE_PATH,
+        Stateful,
+        2,
+        ["Path", "Any"],
+        "Sets a world path to a default unless it holds a value; returns the value there.",
+        needs[State]
+    );
+    register_atom!(
+        world,
+        "get",
+        world::ATOM_GET,
+        Stateful,
+        1,
+        ["Path"],
+        "Reads the value at a world path.",
+        needs[State]
+    );
+    register_atom!(
+        world,
+        "del!",
+        world::ATOM_DEL,
+        Stateful,
+        1,
+        ["Path"],
+        "Deletes the value at a world path.",
+        needs[State]
+    );
+    register_atom!(
+        world,
+        "exists?",
+        world::ATOM_EXISTS,
+        Stateful,
+        1,
+        ["Path"],
+        "True if a world path holds a value.",
+        needs[State]
+    );
+    register_atom!(
+        world,
+        "world/hash",
+        world::ATOM_WORLD_HASH,
+        Stateful,
+        0..=1,
+        ["Path"],
+        "Stable content hash of the value at a world path, or of the whole world state.",
+        needs[State]
+    );
+    register_atom!(
+        world,
+        "world/stats",
+        world::ATOM_WORLD_STATS,
+        Stateful,
+        0..=1,
+        ["Path"],
+        "Node count, depth and estimated bytes of a world path, or of the whole world state.",
+        needs[State]
+    );
+    register_atom!(
+        world,
+        "world/prune!",
+        world::ATOM_WORLD_PRUNE,
+        Stateful,
+        2..,
+        ["Path", "Lambda"],
+        "Deletes the entries of a world map a predicate accepts; :dry-run true only lists them.",
+        needs[State]
+    );
+    register_atom!(
+        world,
+        "generate!",
+        world::ATOM_GENERATE,
+        Stateful,
+        3,
+        ["Path", "Number", "Lambda"],
+        "Sets one entry under a world path per index below a count, to a function's result for it.",
+        needs[State]
+    );
+    register_atom!(
+        world,
+        "inc!",
+        world::ATOM_INC,
+        Stateful,
+        1,
+        ["Path"],
+        "Adds 1 to the number at a world path.",
+        needs[State]
+    );
+    register_atom!(
+        world,
+        "dec!",
+        world::ATOM_DEC,
+        Stateful,
+        1,
+        ["Path"],
+        "Subtracts 1 from the number at a world path.",
+        needs[State]
+    );
+    register_atom!(
+        world,
+        "add!",
+        world::ATOM_ADD,
+        Stateful,
+        2,
+        ["Path", "Number"],
+        "Adds to the number at a world path.",
+        needs[State]
+    );
+    register_atom!(
+        world,
+        "sub!",
+        world::ATOM_SUB,
+        Stateful,
+        2,
+        ["Path", "Number"],
+        "Subtracts from the number at a world path.",
+        needs[State]
+    );
+    register_atom!(
+        world,
+        "update!",
+        world::ATOM_UPDATE,
+        Stateful,
+        2,
+        ["Path", "Lambda"],
+        "Replaces the value at a world path with a function's result on it; returns the new value.",
+        needs[State]
+    );
+    register_atom!(
+        world,
+        "push-front!",
+        world::ATOM_PUSH_FRONT,
+        Stateful,
+        2,
+        ["Path", "Any"],
+        "Adds a value to the front of the list at a world path; returns the list.",
+        needs[State]
+    );
+    register_atom!(
+        world,
+        "pop-front!",
+        world::ATOM_POP_FRONT,
+        Stateful,
+        1,
+        ["Path"],
+        "Removes and returns the first value of the list at a world path.",
+        needs[State]
+    );
+    register_atom!(
+        world,
+        "peek-front",
+        world::ATOM_PEEK_FRONT,
+        Stateful,
+        1,
+        ["Path"],
+        "The first value of the list at a world path, without removing it.",
+        needs[State]
+    );
+    register_atom!(
+        world,
+        "peek",
+        world::ATOM_PEEK,
+        Stateful,
+        1,
+        ["Path"],
+        "The last value of the list at a world path, without removing it.",
+        needs[State]
+    );
+    register_atom!(
+        world,
+        "path",
+        world::ATOM_PATH,
+        Pure,
+        1..,
+        ["Any"],
+        "Builds a path from strings, symbols, keywords, whole numbers and paths."
+    );
+    register_atom!(
+        world,
+        "path/join",
+        world::ATOM_PATH_JOIN,
+        Pure,
+        1..,
+        ["Any"],
+        "Joins segments and paths into one path, e.g. (path/join 'npcs id 'hp)."
+    );
+    register_atom!(
+        world,
+        "path/child",
+        world::ATOM_PATH_CHILD,
+        Pure,
+        2..,
+        ["Path", "Any"],
+        "Extends a path with further segments."
+    );
+    register_atom!(
+        world,
+        "path/parent",
+        world::ATOM_PATH_PARENT,
+        Pure,
+        1,
+        ["Path"],
+        "The path without its last segment, or nil for a single-segment path."
+    );
+    register_atom!(
+        world,
+        "path/last",
+        world::ATOM_PATH_LAST,
+        Pure,
+        1,
+        ["Path"],
+        "The last segment of a path, as a string."
+    );
+    register_atom!(
+        world,
+        "path/segments",
+        world::ATOM_PATH_SEGMENTS,
+        Pure,
+        1,
+        ["Path"],
+        "The segments of a path, as a list of strings."
+    );
+    register_atom!(
+        world,
+        "checkpoint!",
+        world::ATOM_CHECKPOINT,
+        Stateful,
+        0,
+        [],
+        "Records the world state as an undo point.",
+        needs[State]
+    );
+    register_atom!(
+        world,
+        "undo!",
+        world::ATOM_UNDO,
+        Stateful,
+        0,
+        [],
+        "Restores the most recent checkpoint.",
+        needs[State]
+    );
+    register_atom!(
+        world,
+        "redo!",
+        world::ATOM_REDO,
+        Stateful,
+        0,
+        [],
+        "Reverses the most recent undo!.",
+        needs[State]
+    );
 }
 
 fn register_collection_atoms(world: &mut World) {
-    register_atom!(world, "list", collections::ATOM_LIST);
-    register_atom!(world, "len", collections::ATOM_LEN);
-    register_atom!(world, "null?", collections::ATOM_NULL);
-    register_atom!(world, "has?", collections::ATOM_HAS);
-    register_atom!(world, "car", collections::ATOM_CAR);
-    register_atom!(world, "cdr", collections::ATOM_CDR);
-    register_atom!(world, "cons", collections::ATOM_CONS);
-    register_atom!(world, "append", collections::ATOM_APPEND);
-    register_atom!(world, "map", collections::ATOM_MAP);
-    register_atom!(world, "core/str+", collections::ATOM_CORE_STR_PLUS);
-    register_atom!(world, "core/map", collections::ATOM_CORE_MAP);
+    register_atom!(
+        world,
+        "list",
+        collections::ATOM_LIST,
+        Pure,
+        0..,
+        ["Any"],
+        "Builds a list of its arguments."
+    );
+    register_atom!(
+        world,
+        "len",
+        collections::ATOM_LEN,
+        Pure,
+        1,
+        ["Any"],
+        "Length of a list or string."
+    );
+    register_atom!(
+        world,
+        "null?",
+        collections::ATOM_NULL,
+        Pure,
+        1,
+        ["Any"],
+        "True if a list is empty."
+    );
+    register_atom!(
+        world,
+        "has?",
+        collections::ATOM_HAS,
+        Pure,
+        2,
+        ["Any", "Any"],
+        "True if a collection contains a value or key."
+    );
+    register_atom!(
+        world,
+        "car",
+        collections::ATOM_CAR,
+        Pure,
+        1,
+        ["List"],
+        "First element of a list."
+    );
+    register_atom!(
+        world,
+        "cdr",
+        collections::ATOM_CDR,
+        Pure,
+        1,
+        ["List"],
+        "All elements of a list but the first."
+    );
+    register_atom!(
+        world,
+        "cons",
+        collections::ATOM_CONS,
+        Pure,
+        2,
+        ["Any", "List"],
+        "Prepends an element to a list."
+    );
+    register_atom!(
+        world,
+        "append",
+        collections::ATOM_APPEND,
+        Pure,
+        0..,
+        ["List"],
+        "Concatenates lists."
+    );
+    register_atom!(
+        world,
+        "reverse",
+        collections::ATOM_REVERSE,
+        Pure,
+        1,
+        ["List"],
+        "Returns a list with the elements in reverse order."
+    );
+    register_atom!(
+        world,
+        "nth",
+        collections::ATOM_NTH,
+        Pure,
+        2,
+        ["List", "Number"],
+        "Returns the element at a zero-based index, or nil past the end."
+    );
+    register_atom!(
+        world,
+        "sort",
+        collections::ATOM_SORT,
+        Pure,
+        1..=2,
+        ["List", "Lambda"],
+        "Sorts a list, optionally by a less-than function."
+    );
+    register_atom!(
+        world,
+        "sum",
+        collections::ATOM_SUM,
+        Pure,
+        1..=2,
+        ["List", "Lambda"],
+        "Adds up the numbers in a list or map, optionally by a key function."
+    );
+    register_atom!(
+        world,
+        "avg",
+        collections::ATOM_AVG,
+        Pure,
+        1..=2,
+        ["List", "Lambda"],
+        "The mean of the numbers in a list or map, or nil if it is empty."
+    );
+    register_atom!(
+        world,
+        "min-of",
+        collections::ATOM_MIN_OF,
+        Pure,
+        1..=2,
+        ["List", "Lambda"],
+        "The item with the smallest number, optionally by a key function."
+    );
+    register_atom!(
+        world,
+        "max-of",
+        collections::ATOM_MAX_OF,
+        Pure,
+        1..=2,
+        ["List", "Lambda"],
+        "The item with the largest number, optionally by a key function."
+    );
+    register_atom!(
+        world,
+        "count-if",
+        collections::ATOM_COUNT_IF,
+        Pure,
+        1..=2,
+        ["List", "Lambda"],
+        "How many items are truthy, or satisfy a predicate."
+    );
+    register_atom!(
+        world,
+        "range",
+        collections::ATOM_RANGE,
+        Pure,
+        1..=3,
+        ["Number", "Number", "Number"],
+        "Builds a list of numbers from start up to end, by step."
+    );
+    register_atom!(
+        world,
+        "unique",
+        collections::ATOM_UNIQUE,
+        Pure,
+        1,
+        ["List"],
+        "Removes duplicate elements, keeping the first of each."
+    );
+    register_atom!(
+        world,
+        "union",
+        collections::ATOM_UNION,
+        Pure,
+        0..,
+        ["List"],
+        "Elements found in any of the lists, without duplicates."
+    );
+    register_atom!(
+        world,
+        "intersect",
+        collections::ATOM_INTERSECT,
+        Pure,
+        1..,
+        ["List"],
+        "Elements of the first list found in every other list."
+    );
+    register_atom!(
+        world,
+        "difference",
+        collections::ATOM_DIFFERENCE,
+        Pure,
+        1..,
+        ["List"],
+        "Elements of the first list found in none of the others."
+    );
+    register_atom!(
+        world,
+        "map",
+        collections::ATOM_MAP,
+        Pure,
+        2,
+        ["Lambda", "List"],
+        "Applies a function to each element of a list."
+    );
+    register_atom!(
+        world,
+        "core/str+",
+        collections::ATOM_CORE_STR_PLUS,
+        Pure,
+        0..,
+        ["String"],
+        "Concatenates strings."
+    );
+    register_atom!(
+        world,
+        "core/map",
+        collections::ATOM_CORE_MAP,
+        Pure,
+        0..,
+        ["Any"],
+        "Builds a map from alternating keys and values."
+    );
 }
 
 fn register_execution_atoms(world: &mut World) {
-    register_atom!(world, "do", execution::ATOM_DO);
-    register_atom!(world, "error", execution::ATOM_ERROR);
-    register_atom!(world, "apply", execution::ATOM_APPLY);
-    register_atom!(world, "for-each", execution::ATOM_FOR_EACH);
+    register_atom!(
+        world,
+        "do",
+        execution::ATOM_DO,
+        Pure,
+        0..,
+        ["Any"],
+        "Evaluates expressions in order, returning the last."
+    );
+    register_atom!(
+        world,
+        "error",
+        execution::ATOM_ERROR,
+        Pure,
+        1,
+        ["String"],
+        "Raises an error with a message."
+    );
+    register_atom!(
+        world,
+        "assert",
+        execution::ATOM_ASSERT,
+        SpecialForm,
+        1..=2,
+        ["Any", "String"],
+        "Fails with the condition's source and an optional message unless the condition holds."
+    );
+    register_atom!(
+        world,
+        "apply",
+        execution::ATOM_APPLY,
+        Pure,
+        2..,
+        ["Lambda", "Any"],
+        "Calls a function, spreading its final list argument."
+    );
+    register_atom!(
+        world,
+        "eval",
+        execution::ATOM_EVAL,
+        Stateful,
+        1,
+        ["Any"],
+        "Expands and evaluates quoted code in the current scope.",
+        needs[Eval]
+    );
+    register_atom!(
+        world,
+        "for-each",
+        execution::ATOM_FOR_EACH,
+        Pure,
+        3..,
+        ["Symbol", "List", "Any"],
+        "Evaluates a body once per element of a list."
+    );
 }
 
 fn register_external_atoms(world: &mut World) {
-    register_atom!(world, "print", external::ATOM_PRINT);
-    register_atom!(world, "println", external::ATOM_PRINTLN);
-    register_atom!(world, "output", external::ATOM_OUTPUT);
-    register_atom!(world, "rand", external::ATOM_RAND);
+    register_atom!(
+        world,
+        "print",
+        external::ATOM_PRINT,
+        Stateful,
+        1..,
+        ["Any"],
+        "Writes its arguments to the output.",
+        needs[Io]
+    );
+    register_atom!(
+        world,
+        "println",
+        external::ATOM_PRINTLN,
+        Stateful,
+        0..,
+        ["Any"],
+        "Writes its arguments and a newline to the output.",
+        needs[Io]
+    );
+    register_atom!(
+        world,
+        "output",
+        external::ATOM_OUTPUT,
+        Stateful,
+        1,
+        ["Any"],
+        "Writes a value to the output.",
+        needs[Io]
+    );
+    register_atom!(
+        world,
+        "inspect",
+        external::ATOM_INSPECT,
+        Stateful,
+        1..,
+        ["Any"],
+        "Writes an indented, type-annotated rendering of a value and returns it.",
+        needs[Io]
+    );
+    register_atom!(
+        world,
+        "log/debug",
+        external::ATOM_LOG_DEBUG,
+        Stateful,
+        0..,
+        ["Any"],
+        "Logs its arguments at the debug level.",
+        needs[Io]
+    );
+    register_atom!(
+        world,
+        "log/info",
+        external::ATOM_LOG_INFO,
+        Stateful,
+        0..,
+        ["Any"],
+        "Logs its arguments at the info level.",
+        needs[Io]
+    );
+    register_atom!(
+        world,
+        "log/warn",
+        external::ATOM_LOG_WARN,
+        Stateful,
+        0..,
+        ["Any"],
+        "Logs its arguments at the warn level.",
+        needs[Io]
+    );
+    register_atom!(
+        world,
+        "set-log-level!",
+        external::ATOM_SET_LOG_LEVEL,
+        Stateful,
+        1,
+        ["Symbol"],
+        "Sets the least important log level written: debug, info or warn."
+    );
+    register_atom!(
+        world,
+        "with-output-to-string",
+        external::ATOM_WITH_OUTPUT_TO_STRING,
+        SpecialForm,
+        0..,
+        ["Any"],
+        "Evaluates a body and returns what it wrote to the output as a string."
+    );
+    register_atom!(
+        world,
+        "rand",
+        external::ATOM_RAND,
+        Stateful,
+        0,
+        [],
+        "Random number in [0, 1) from the world's PRNG.",
+        needs[Random]
+    );
+    register_atom!(
+        world,
+        "args",
+        external::ATOM_ARGS,
+        Stateful,
+        0,
+        [],
+        "The arguments passed to the script after `--`, as a list of strings."
+    );
+    register_atom!(
+        world,
+        "read-line",
+        external::ATOM_READ_LINE,
+        Stateful,
+        0,
+        [],
+        "Reads a line from standard input, or returns nil at the end of input.",
+        needs[Io]
+    );
+    register_atom!(
+        world,
+        "choice",
+        external::ATOM_CHOICE,
+        SpecialForm,
+        1..=2,
+        ["String", "List"],
+        "Asks the host to pick a branch by its label, then evaluates that branch.",
+        needs[Io]
+    );
 }
 
 fn register_string_atoms(world: &mut World) {
-    register_atom!(world, "str", collections::ATOM_STR);
-    register_atom!(world, "str+", collections::ATOM_STR_PLUS);
+    register_atom!(
+        world,
+        "str",
+        collections::ATOM_STR,
+        Pure,
+        1,
+        ["Any"],
+        "Converts a value to a string."
+    );
+    register_atom!(
+        world,
+        "str+",
+        collections::ATOM_STR_PLUS,
+        Pure,
+        0..,
+        ["Any"],
+        "Concatenates values into a string."
+    );
+    register_atom!(
+        world,
+        "str-builder",
+        string_builder::ATOM_STR_BUILDER,
+        Stateful,
+        0,
+        [],
+        "Creates an empty string builder, which grows in place."
+    );
+    register_atom!(
+        world,
+        "sb-append!",
+        string_builder::ATOM_SB_APPEND,
+        Stateful,
+        1..,
+        ["StringBuilder", "Any"],
+        "Appends values to a string builder as str+ joins them, returning the builder."
+    );
+    register_atom!(
+        world,
+        "sb-build",
+        string_builder::ATOM_SB_BUILD,
+        Stateful,
+        1,
+        ["StringBuilder"],
+        "Returns the text a string builder holds."
+    );
+}
+
+fn register_convert_atoms(world: &mut World) {
+    register_atom!(
+        world,
+        "num->str",
+        convert::ATOM_NUM_TO_STR,
+        Pure,
+        1,
+        ["Number"],
+        "Formats a number as a string."
+    );
+    register_atom!(
+        world,
+        "format-number",
+        convert::ATOM_FORMAT_NUMBER,
+        Pure,
+        1..,
+        ["Number"],
+        "Formats a number for presentation; takes :decimals, :thousands, :width and :fill."
+    );
+    register_atom!(
+        world,
+        "str->num",
+        convert::ATOM_STR_TO_NUM,
+        Pure,
+        1,
+        ["String"],
+        "Parses a string as a number, or returns nil if it is not one."
+    );
+    register_atom!(
+        world,
+        "sym->str",
+        convert::ATOM_SYM_TO_STR,
+        Pure,
+        1,
+        ["Symbol"],
+        "Returns the name of a symbol or keyword."
+    );
+    register_atom!(
+        world,
+        "list->str",
+        convert::ATOM_LIST_TO_STR,
+        Pure,
+        1..=2,
+        ["List", "String"],
+        "Joins the elements of a list into a string, with an optional separator."
+    );
+    register_atom!(
+        world,
+        "str->list",
+        convert::ATOM_STR_TO_LIST,
+        Pure,
+        1..=2,
+        ["String", "String"],
+        "Splits a string on a separator, or into characters."
+    );
+    register_atom!(
+        world,
+        "read",
+        convert::ATOM_READ,
+        Pure,
+        1,
+        ["String"],
+        "Parses a string into quoted data, without expanding macros."
+    );
+    register_atom!(
+        world,
+        "bool?",
+        convert::ATOM_IS_BOOL,
+        Pure,
+        1,
+        ["Any"],
+        "True for booleans."
+    );
+    register_atom!(
+        world,
+        "number?",
+        convert::ATOM_IS_NUMBER,
+        Pure,
+        1,
+        ["Any"],
+        "True for numbers."
+    );
+    register_atom!(
+        world,
+        "string?",
+        convert::ATOM_IS_STRING,
+        Pure,
+        1,
+        ["Any"],
+        "True for strings."
+    );
+    register_atom!(
+        world,
+        "list?",
+        convert::ATOM_IS_LIST,
+        Pure,
+        1,
+        ["Any"],
+        "True for lists, including nil."
+    );
+    register_atom!(
+        world,
+        "path?",
+        convert::ATOM_IS_PATH,
+        Pure,
+        1,
+        ["Any"],
+        "True for path values."
+    );
+}
+
+fn register_json_atoms(world: &mut World) {
+    register_atom!(
+        world,
+        "json/parse",
+        json::ATOM_JSON_PARSE,
+        Pure,
+        1,
+        ["String"],
+        "Parses JSON text into maps, lists and primitives."
+    );
+    register_atom!(
+        world,
+        "json/stringify",
+        json::ATOM_JSON_STRINGIFY,
+        Pure,
+        1..,
+        ["Any"],
+        "Writes a value as JSON text; :pretty true indents it."
+    );
+}
+
+fn register_table_atoms(world: &mut World) {
+    register_atom!(
+        world,
+        "table/load",
+        table::ATOM_TABLE_LOAD,
+        Stateful,
+        1,
+        ["String"],
+        "Loads a CSV or TSV file as a list of maps keyed by its header row.",
+        needs[Io]
+    );
+    register_atom!(
+        world,
+        "table/get",
+        table::ATOM_TABLE_GET,
+        Pure,
+        2,
+        ["Map", "String"],
+        "Returns a row's value in a column, or nil."
+    );
+    register_atom!(
+        world,
+        "table/filter",
+        table::ATOM_TABLE_FILTER,
+        Pure,
+        2,
+        ["List", "Lambda"],
+        "Keeps the rows for which a predicate is truthy."
+    );
+    register_atom!(
+        world,
+        "table/index-by",
+        table::ATOM_TABLE_INDEX_BY,
+        Pure,
+        2,
+        ["List", "String"],
+        "Maps each row's value in a column to the row."
+    );
+}
+
+fn register_coroutine_atoms(world: &mut World) {
+    register_atom!(
+        world,
+        "coroutine",
+        coroutines::ATOM_COROUTINE,
+        Pure,
+        1..,
+        ["Lambda", "Any"],
+        "Wraps a call of a function in a coroutine for resume to run."
+    );
+    register_atom!(
+        world,
+        "resume",
+        coroutines::ATOM_RESUME,
+        Stateful,
+        1..=2,
+        ["Coroutine", "Any"],
+        "Runs a coroutine until it yields or returns, giving that value."
+    );
+    register_atom!(
+        world,
+        "yield",
+        coroutines::ATOM_YIELD,
+        Stateful,
+        0..=1,
+        ["Any"],
+        "Suspends the running coroutine, handing a value to resume."
+    );
+    register_atom!(
+        world,
+        "coroutine/done?",
+        coroutines::ATOM_COROUTINE_DONE,
+        Pure,
+        1,
+        ["Coroutine"],
+        "True once a coroutine's function has returned or failed."
+    );
+}
+
+#[cfg(feature = "net")]
+fn register_net_atoms(world: &mut World) {
+    register_atom!(
+        world,
+        "http/get",
+        net::ATOM_HTTP_GET,
+        Stateful,
+        1..,
+        ["String"],
+        "Fetches a URL as a map of status, headers and body.",
+        needs[Net]
+    );
+}
+
+fn register_text_atoms(world: &mut World) {
+    register_atom!(
+        world,
+        "text/pick",
+        text::ATOM_TEXT_PICK,
+        Stateful,
+        1..,
+        ["Any"],
+        "Evaluates one of its arguments, chosen at random.",
+        needs[Random]
+    );
+    register_atom!(
+        world,
+        "plural",
+        text::ATOM_PLURAL,
+        Pure,
+        2..=3,
+        ["Number", "String"],
+        "Singular or plural form of a word for a count."
+    );
+    register_atom!(
+        world,
+        "quantity",
+        text::ATOM_QUANTITY,
+        Pure,
+        2..=3,
+        ["Number", "String"],
+        "A count followed by the matching form of a word."
+    );
+    register_atom!(
+        world,
+        "tr",
+        text::ATOM_TR,
+        Stateful,
+        1..,
+        ["String", "Any"],
+        "Looks up a translated message and fills its placeholders."
+    );
+}
+
+#[cfg(any(test, feature = "test-atom", debug_assertions))]
+fn register_property_atoms(world: &mut World) {
+    register_atom!(
+        world,
+        "gen/int",
+        property::ATOM_GEN_INT,
+        Stateful,
+        0..=2,
+        ["Number"],
+        "Generates integers in an inclusive range.",
+        needs[Random]
+    );
+    register_atom!(
+        world,
+        "gen/number",
+        property::ATOM_GEN_NUMBER,
+        Stateful,
+        0..=2,
+        ["Number"],
+        "Generates numbers in an inclusive range.",
+        needs[Random]
+    );
+    register_atom!(
+        world,
+        "gen/bool",
+        property::ATOM_GEN_BOOL,
+        Stateful,
+        0,
+        [],
+        "Generates booleans.",
+        needs[Random]
+    );
+    register_atom!(
+        world,
+        "gen/string",
+        property::ATOM_GEN_STRING,
+        Stateful,
+        0..=1,
+        ["Number"],
+        "Generates alphanumeric strings.",
+        needs[Random]
+    );
+    register_atom!(
+        world,
+        "gen/list-of",
+        property::ATOM_GEN_LIST_OF,
+        Stateful,
+        1..=2,
+        ["Generator", "Number"],
+        "Generates lists whose elements come from another generator.",
+        needs[Random]
+    );
+    register_atom!(
+        world,
+        "gen/one-of",
+        property::ATOM_GEN_ONE_OF,
+        Stateful,
+        1..,
+        ["Any"],
+        "Picks one of the given values.",
+        needs[Random]
+    );
+    register_atom!(
+        world,
+        "gen/path",
+        property::ATOM_GEN_PATH,
+        Stateful,
+        0..=1,
+        ["Number"],
+        "Generates world paths.",
+        needs[Random]
+    );
+    register_atom!(
+        world,
+        "for-all",
+        property::ATOM_FOR_ALL,
+        Stateful,
+        2..,
+        ["Bindings", "Any"],
+        "Checks a property against many generated inputs.",
+        needs[Random]
+    );
+}
+
+fn register_record_atoms(world: &mut World) {
+    register_atom!(
+        world,
+        "defrecord",
+        records::ATOM_DEFRECORD,
+        Stateful,
+        2,
+        ["Symbol", "List"],
+        "Declares a record type with its constructor, predicate and accessors."
+    );
+    register_atom!(
+        world,
+        "record/make",
+        records::ATOM_RECORD_MAKE,
+        Pure,
+        1..,
+        ["String", "Any"],
+        "Builds a record from alternating field names and values."
+    );
+    register_atom!(
+        world,
+        "record/is?",
+        records::ATOM_RECORD_IS,
+        Pure,
+        2,
+        ["String", "Any"],
+        "True if a value is a record of the given type."
+    );
+    register_atom!(
+        world,
+        "record/get",
+        records::ATOM_RECORD_GET,
+        Pure,
+        3,
+        ["String", "String", "Record"],
+        "Reads a field from a record of the given type."
+    );
+}
+
+fn register_prototype_atoms(world: &mut World) {
+    register_atom!(
+        world,
+        "defproto",
+        prototypes::ATOM_DEFPROTO,
+        Stateful,
+        1..,
+        ["Symbol", "List"],
+        "Declares a prototype with default fields, optionally inheriting from another."
+    );
+    register_atom!(
+        world,
+        "spawn!",
+        prototypes::ATOM_SPAWN,
+        Stateful,
+        2..,
+        ["Path", "Symbol", "List"],
+        "Writes an instance of a prototype to a world path, overriding the given fields.",
+        needs[State]
+    );
+    register_atom!(
+        world,
+        "proto-of",
+        prototypes::ATOM_PROTO_OF,
+        Stateful,
+        1,
+        ["Path"],
+        "The prototype of the instance at a world path, or the parent of a prototype.",
+        needs[State]
+    );
+}
+
+fn register_tag_atoms(world: &mut World) {
+    register_atom!(
+        world,
+        "tag!",
+        tags::ATOM_TAG,
+        Stateful,
+        2..,
+        ["Path", "Symbol"],
+        "Tags a world path.",
+        needs[State]
+    );
+    register_atom!(
+        world,
+        "untag!",
+        tags::ATOM_UNTAG,
+        Stateful,
+        2..,
+        ["Path", "Symbol"],
+        "Removes tags from a world path.",
+        needs[State]
+    );
+    register_atom!(
+        world,
+        "with-tag",
+        tags::ATOM_WITH_TAG,
+        Stateful,
+        1..,
+        ["Symbol"],
+        "The world paths carrying every given tag, in path order.",
+        needs[State]
+    );
+}
+
+fn register_roll_table_atoms(world: &mut World) {
+    register_atom!(
+        world,
+        "core/deftable",
+        roll_table::ATOM_CORE_DEFTABLE,
+        Stateful,
+        2..,
+        ["String", "List"],
+        "Stores a weighted table; written by the deftable macro."
+    );
+    register_atom!(
+        world,
+        "roll-table",
+        roll_table::ATOM_ROLL_TABLE,
+        Stateful,
+        1,
+        ["Symbol"],
+        "Picks an entry of a deftable table at random by weight, rolling nested tables.",
+        needs[Random]
+    );
+}
+
+fn register_state_machine_atoms(world: &mut World) {
+    register_atom!(
+        world,
+        "core/defstate",
+        state_machine::ATOM_CORE_DEFSTATE,
+        Stateful,
+        3..,
+        ["String", "Path", "Map"],
+        "Stores a state machine; written by the defstate macro.",
+        needs[State]
+    );
+    register_atom!(
+        world,
+        "advance!",
+        state_machine::ATOM_ADVANCE,
+        Stateful,
+        2,
+        ["Symbol", "Symbol"],
+        "Sends an event to a defstate machine; returns the new state, or nil if no transition fires.",
+        needs[State]
+    );
 }
 
 fn register_special_forms(world: &mut World) {
-    register_atom!(world, "lambda", special_forms::ATOM_LAMBDA);
-    register_atom!(world, "let", special_forms::ATOM_LET);
-    register_atom!(world, "if", special_forms::ATOM_IF);
-    register_atom!(world, "cond", special_forms::ATOM_COND);
-    register_atom!(world, "and", special_forms::ATOM_AND);
-    register_atom!(world, "or", special_forms::ATOM_OR);
-    register_atom!(world, "define", special_forms::ATOM_DEFINE);
+    register_atom!(
+        world,
+        "lambda",
+        special_forms::ATOM_LAMBDA,
+        SpecialForm,
+        2..,
+        ["Params", "Any"],
+        "Creates a function."
+    );
+    register_atom!(
+        world,
+        "let",
+        special_forms::ATOM_LET,
+        SpecialForm,
+        2..,
+        ["Bindings", "Any"],
+        "Evaluates a body with local bindings."
+    );
+    register_atom!(
+        world,
+        "if",
+        special_forms::ATOM_IF,
+        SpecialForm,
+        3,
+        ["Any"],
+        "Evaluates one of two branches."
+    );
+    register_atom!(
+        world,
+        "cond",
+        special_forms::ATOM_COND,
+        SpecialForm,
+        0..,
+        ["Clause"],
+        "Evaluates the first clause whose test holds."
+    );
+    register_atom!(
+        world,
+        "and",
+        special_forms::ATOM_AND,
+        SpecialForm,
+        0..,
+        ["Any"],
+        "True unless an argument is false; stops at the first false one."
+    );
+    register_atom!(
+        world,
+        "or",
+        special_forms::ATOM_OR,
+        SpecialForm,
+        0..,
+        ["Any"],
+        "False unless an argument is true; stops at the first true one."
+    );
+    register_atom!(
+        world,
+        "define",
+        special_forms::ATOM_DEFINE,
+        SpecialForm,
+        2..,
+        ["Symbol", "Any"],
+        "Binds a top-level name to a value or function."
+    );
+    register_atom!(
+        world,
+        "quote",
+        special_forms::ATOM_QUOTE,
+        SpecialForm,
+        1,
+        ["Any"],
+        "Returns its argument unevaluated."
+    );
+    register_atom!(
+        world,
+        ":",
+        special_forms::ATOM_TYPE_ANNOTATION,
+        SpecialForm,
+        2,
+        ["Type", "Symbol"],
+        "Declares a name's type for `sutra typecheck`."
+    );
 }
@@ -2,11 +2,19 @@
 //!
 //! This module provides atoms for working with lists, strings, and maps.
 //! Includes both pure operations and stateful world operations.
+//!
+//! The set operations (`unique`, `union`, `intersect`, `difference`) compare elements
+//! with value equality and keep the order in which elements are first seen.
+//!
+//! The aggregates (`sum`, `avg`, `min-of`, `max-of`, `count-if`) take a list or a map,
+//! such as a world subtree read with `get`, and an optional function applied to each
+//! item first.
 
 use std::sync::Arc;
 
 use crate::{
-    errors::{to_source_span, ErrorReporting, SutraError},
+    atoms::special_forms::call_lambda,
+    errors::{to_source_span, ErrorKind, ErrorReporting, SutraError},
     runtime::{evaluate_ast_node, EvaluationContext, NativeFn, SpannedValue, Value},
     syntax::{AstNode, Span},
 };
@@ -117,17 +125,123 @@ fn call_function_with_value(
     }
 }
 
+/// Calls a function (Lambda or NativeFn) with several arguments.
+pub(crate) fn call_function_with_values(
+    func: &Value,
+    args: &[Value],
+    context: &mut EvaluationContext,
+    call_span: &Span,
+    arg_span: Span,
+) -> Result<SpannedValue, SutraError> {
+    match func {
+        Value::Lambda(lambda) => call_lambda(lambda, args, context, call_span),
+        Value::NativeFn(native_fn) => {
+            let mut nodes = Vec::with_capacity(args.len());
+            for arg in args {
+                let expr = crate::syntax::expr_from_value_with_span(arg.clone(), arg_span)
+                    .map_err(|_| {
+                        context.type_mismatch(
+                            "convertible to expression",
+                            arg.type_name(),
+                            to_source_span(arg_span),
+                        )
+                    })?;
+                nodes.push(AstNode {
+                    value: Arc::new(expr),
+                    span: arg_span,
+                });
+            }
+            native_fn(&nodes, context, call_span)
+        }
+        _ => Err(context.type_mismatch(
+            "Lambda or NativeFn",
+            func.type_name(),
+            to_source_span(*call_span),
+        )),
+    }
+}
+
+/// Evaluates an argument that must be a list or nil.
+fn eval_list_arg(
+    arg: &AstNode,
+    context: &mut EvaluationContext,
+) -> Result<SpannedValue, SutraError> {
+    let list = eval_arg(arg, context)?;
+    match list.value {
+        Value::Cons(_) | Value::Nil => Ok(list),
+        _ => type_error("List or Nil", &list, context),
+    }
+}
+
+/// Evaluates an argument that must be a number.
+fn eval_number_arg(arg: &AstNode, context: &mut EvaluationContext) -> Result<f64, SutraError> {
+    let val = eval_arg(arg, context)?;
+    match val.value {
+        Value::Number(n) => Ok(n),
+        _ => type_error("Number", &val, context),
+    }
+}
+
+/// Stable merge sort with a comparator that may fail.
+fn merge_sort<F>(mut items: Vec<Value>, less: &mut F) -> Result<Vec<Value>, SutraError>
+where
+    F: FnMut(&Value, &Value) -> Result<bool, SutraError>,
+{
+    if items.len() < 2 {
+        return Ok(items);
+    }
+    let right = items.split_off(items.len() / 2);
+    let left = merge_sort(items, less)?;
+    let right = merge_sort(right, less)?;
+
+    let mut merged = Vec::with_capacity(left.len() + right.len());
+    let mut left = left.into_iter().peekable();
+    let mut right = right.into_iter().peekable();
+    while let (Some(a), Some(b)) = (left.peek(), right.peek()) {
+        // Taking from the right only when strictly less keeps equal elements in order.
+        let next = if less(b, a)? {
+            right.next()
+        } else {
+            left.next()
+        };
+        merged.extend(next);
+    }
+    merged.extend(left);
+    merged.extend(right);
+    Ok(merged)
+}
+
+/// The default `sort` order: numbers ascending, strings lexicographically.
+fn natural_less(
+    a: &Value,
+    b: &Value,
+    context: &mut EvaluationContext,
+    span: Span,
+) -> Result<bool, SutraError> {
+    match (a, b) {
+        (Value::Number(a), Value::Number(b)) => Ok(a < b),
+        (Value::String(a), Value::String(b)) => Ok(a < b),
+        (Value::Number(_) | Value::String(_), _) => {
+            Err(context.type_mismatch(a.type_name(), b.type_name(), to_source_span(span)))
+        }
+        _ => Err(context.type_mismatch("Number or String", a.type_name(), to_source_span(span))),
+    }
+}
+
 // ============================================================================
 // LIST OPERATIONS
 // ============================================================================
 
 /// Constructs a list from arguments: (list <a> <b> ...)
 pub const ATOM_LIST: NativeFn = |args, context, call_span| {
-    let mut result = Value::Nil;
-    for item in args.iter().rev() {
-        let value = eval_arg(item, context)?.value;
-        result = Value::Cons(crate::runtime::ConsRepr::cons(value, result));
-    }
+    // Evaluate left to right, so side effects such as `read-line` happen in order.
+    let values = args
+        .iter()
+        .map(|item| Ok(eval_arg(item, context)?.value))
+        .collect::<Result<Vec<_>, SutraError>>()?;
+    let result = values.into_iter().rev().fold(Value::Nil, |list, value| {
+        Value::Cons(crate::runtime::ConsRepr::cons(value, list))
+    });
     ok_span(result, *call_span)
 };
 
@@ -262,6 +376,31 @@ pub const ATOM_APPEND: NativeFn = |args, context, call_span| {
     Ok(build_list(items, *call_span))
 };
 
+/// Returns a list with the elements in reverse order: (reverse <list-or-nil>)
+pub const ATOM_REVERSE: NativeFn = |args, context, call_span| {
+    require_arity(args, 1, context, *call_span)?;
+    let list = eval_list_arg(&args[0], context)?;
+
+    let mut items: Vec<Value> = list.value.try_into_iter().collect();
+    items.reverse();
+    Ok(build_list(items, *call_span))
+};
+
+/// Returns the element at a zero-based index, or nil past the end: (nth <list> <index>)
+pub const ATOM_NTH: NativeFn = |args, context, call_span| {
+    require_arity(args, 2, context, *call_span)?;
+    let list = eval_list_arg(&args[0], context)?;
+    let index = eval_arg(&args[1], context)?;
+
+    let position = match index.value {
+        Value::Number(n) if n >= 0.0 && n.fract() == 0.0 => n as usize,
+        _ => return type_error("non-negative whole Number", &index, context),
+    };
+
+    let value = list.value.try_into_iter().nth(position).unwrap_or_default();
+    ok_span(value, *call_span)
+};
+
 /// Applies a function to each element of a list: (map <function> <list>)
 pub const ATOM_MAP: NativeFn = |args, context, call_span| {
     require_arity(args, 2, context, *call_span)?;
@@ -288,6 +427,297 @@ pub const ATOM_MAP: NativeFn = |args, context, call_span| {
     Ok(build_list(results, *call_span))
 };
 
+/// Sorts a list, stably: (sort <list>) or (sort <list> <less-than>)
+/// Without a comparator, numbers sort ascending and strings lexicographically.
+/// A comparator is called with two elements and returns true if the first goes first.
+pub const ATOM_SORT: NativeFn = |args, context, call_span| {
+    if !(1..=2).contains(&args.len()) {
+        return Err(context.arity_mismatch("1 or 2", args.len(), to_source_span(*call_span)));
+    }
+    let list = eval_list_arg(&args[0], context)?;
+    let compare = match args.get(1) {
+        Some(arg) => {
+            let func = eval_arg(arg, context)?;
+            match func.value {
+                Value::Lambda(_) | Value::NativeFn(_) => Some(func),
+                _ => return type_error("Lambda or NativeFn", &func, context),
+            }
+        }
+        None => None,
+    };
+
+    let items: Vec<Value> = list.value.try_into_iter().collect();
+    let sorted = match compare {
+        Some(func) => merge_sort(items, &mut |a, b| {
+            let pair = [a.clone(), b.clone()];
+            let result =
+                call_function_with_values(&func.value, &pair, context, call_span, list.span)?;
+            Ok(result.value.is_truthy())
+        })?,
+        None => merge_sort(items, &mut |a, b| natural_less(a, b, context, list.span))?,
+    };
+
+    Ok(build_list(sorted, *call_span))
+};
+
+/// Builds a list of numbers from `start` up to, but not including, `end`:
+/// (range <end>), (range <start> <end>) or (range <start> <end> <step>)
+/// A negative step counts down.
+pub const ATOM_RANGE: NativeFn = |args, context, call_span| {
+    if !(1..=3).contains(&args.len()) {
+        return Err(context.arity_mismatch("1 to 3", args.len(), to_source_span(*call_span)));
+    }
+    let mut bounds = Vec::with_capacity(args.len());
+    for arg in args {
+        bounds.push(eval_number_arg(arg, context)?);
+    }
+    let (start, end, step) = match bounds[..] {
+        [end] => (0.0, end, 1.0),
+        [start, end] => (start, end, 1.0),
+        [start, end, step] => (start, end, step),
+        _ => return Err(context.arity_mismatch("1 to 3", args.len(), to_source_span(*call_span))),
+    };
+    if step == 0.0 {
+        return Err(context.invalid_operation("range", "zero step", to_source_span(args[2].span)));
+    }
+    // Refuse a range past the collection quota before building any of it
+    let count = ((end - start) / step).ceil();
+    if let Some(limit) = context
+        .limits
+        .max_collection_len
+        .filter(|limit| count > *limit as f64)
+    {
+        return Err(context.report(
+            ErrorKind::ResourceLimit {
+                resource: "range length".to_string(),
+                limit,
+                actual: count as usize,
+            },
+            to_source_span(*call_span),
+        ));
+    }
+
+    let mut items = Vec::new();
+    let mut current = start;
+    while (step > 0.0 && current < end) || (step < 0.0 && current > end) {
+        items.push(Value::Number(current));
+        current = start + step * items.len() as f64;
+    }
+
+    Ok(build_list(items, *call_span))
+};
+
+// ============================================================================
+// SET OPERATIONS
+// ============================================================================
+
+/// Evaluates every argument as a list, returning each list's elements.
+fn eval_list_args(
+    args: &[AstNode],
+    context: &mut EvaluationContext,
+) -> Result<Vec<Vec<Value>>, SutraError> {
+    args.iter()
+        .map(|arg| Ok(eval_list_arg(arg, context)?.value.try_into_iter().collect()))
+        .collect()
+}
+
+/// Appends each of `items` not already in `result`.
+fn push_unique(result: &mut Vec<Value>, items: impl IntoIterator<Item = Value>) {
+    for item in items {
+        if !result.contains(&item) {
+            result.push(item);
+        }
+    }
+}
+
+/// Removes duplicate elements, keeping the first of each: (unique <list>)
+pub const ATOM_UNIQUE: NativeFn = |args, context, call_span| {
+    require_arity(args, 1, context, *call_span)?;
+    let list = eval_list_arg(&args[0], context)?;
+
+    let mut result = Vec::new();
+    push_unique(&mut result, list.value.try_into_iter());
+    Ok(build_list(result, *call_span))
+};
+
+/// Elements found in any of the lists, in first-seen order: (union <list1> <list2> ...)
+pub const ATOM_UNION: NativeFn = |args, context, call_span| {
+    let lists = eval_list_args(args, context)?;
+
+    let mut result = Vec::new();
+    for list in lists {
+        push_unique(&mut result, list);
+    }
+    Ok(build_list(result, *call_span))
+};
+
+/// Elements of the first list found in every other list: (intersect <list1> <list2> ...)
+pub const ATOM_INTERSECT: NativeFn = |args, context, call_span| {
+    if args.is_empty() {
+        return Err(context.arity_mismatch("at least 1", 0, to_source_span(*call_span)));
+    }
+    let mut lists = eval_list_args(args, context)?.into_iter();
+    let first = lists.next().unwrap_or_default();
+    let others: Vec<Vec<Value>> = lists.collect();
+
+    let mut result = Vec::new();
+    push_unique(
+        &mut result,
+        first
+            .into_iter()
+            .filter(|item| others.iter().all(|list| list.contains(item))),
+    );
+    Ok(build_list(result, *call_span))
+};
+
+/// Elements of the first list found in none of the others: (difference <list1> <list2> ...)
+pub const ATOM_DIFFERENCE: NativeFn = |args, context, call_span| {
+    if args.is_empty() {
+        return Err(context.arity_mismatch("at least 1", 0, to_source_span(*call_span)));
+    }
+    let mut lists = eval_list_args(args, context)?.into_iter();
+    let first = lists.next().unwrap_or_default();
+    let others: Vec<Vec<Value>> = lists.collect();
+
+    let mut result = Vec::new();
+    push_unique(
+        &mut result,
+        first
+            .into_iter()
+            .filter(|item| !others.iter().any(|list| list.contains(item))),
+    );
+    Ok(build_list(result, *call_span))
+};
+
+// ============================================================================
+// AGGREGATES
+// ============================================================================
+
+/// Evaluates the arguments of an aggregate: a list, or a map such as a world subtree
+/// whose values are taken in key order, and an optional function. Returns the items,
+/// the function and the span of the collection argument.
+fn eval_aggregate_args(
+    args: &[AstNode],
+    context: &mut EvaluationContext,
+    call_span: Span,
+) -> Result<(Vec<Value>, Option<Value>, Span), SutraError> {
+    if !(1..=2).contains(&args.len()) {
+        return Err(context.arity_mismatch("1 or 2", args.len(), to_source_span(call_span)));
+    }
+    let collection = eval_arg(&args[0], context)?;
+    let span = collection.span;
+    let items = match collection.value {
+        Value::Cons(_) | Value::Nil => collection.value.try_into_iter().collect(),
+        Value::Map(map) => map.into_values().collect(),
+        _ => return type_error("List or Map", &collection, context),
+    };
+    let function = match args.get(1) {
+        Some(arg) => {
+            let func = eval_arg(arg, context)?;
+            match func.value {
+                Value::Lambda(_) | Value::NativeFn(_) => Some(func.value),
+                _ => return type_error("Lambda or NativeFn", &func, context),
+            }
+        }
+        None => None,
+    };
+    Ok((items, function, span))
+}
+
+/// The number each item stands for: the item itself, or what `key` returns for it.
+/// An item that gives something else fails, naming its index.
+fn aggregate_numbers(
+    items: &[Value],
+    key: Option<&Value>,
+    context: &mut EvaluationContext,
+    call_span: &Span,
+    span: Span,
+) -> Result<Vec<f64>, SutraError> {
+    let mut numbers = Vec::with_capacity(items.len());
+    for (index, item) in items.iter().enumerate() {
+        let value = match key {
+            Some(key) => {
+                let item = std::slice::from_ref(item);
+                call_function_with_values(key, item, context, call_span, span)?.value
+            }
+            None => item.clone(),
+        };
+        let Value::Number(n) = value else {
+            let actual = format!("{} at index {index}", value.type_name());
+            return Err(context.type_mismatch("Number", &actual, to_source_span(span)));
+        };
+        numbers.push(n);
+    }
+    Ok(numbers)
+}
+
+/// The item whose number `better` prefers over every other, the first on ties, or nil
+/// for an empty collection.
+fn aggregate_extreme(
+    args: &[AstNode],
+    context: &mut EvaluationContext,
+    call_span: &Span,
+    better: fn(f64, f64) -> bool,
+) -> Result<SpannedValue, SutraError> {
+    let (items, key, span) = eval_aggregate_args(args, context, *call_span)?;
+    let numbers = aggregate_numbers(&items, key.as_ref(), context, call_span, span)?;
+    let best = (1..numbers.len()).fold(0, |best, i| {
+        if better(numbers[i], numbers[best]) {
+            i
+        } else {
+            best
+        }
+    });
+    ok_span(items.get(best).cloned().unwrap_or_default(), *call_span)
+}
+
+/// Adds up a list of numbers, or the numbers a key function gives for its items:
+/// (sum <list>) or (sum <list> <key>). A map's values are summed.
+pub const ATOM_SUM: NativeFn = |args, context, call_span| {
+    let (items, key, span) = eval_aggregate_args(args, context, *call_span)?;
+    let numbers = aggregate_numbers(&items, key.as_ref(), context, call_span, span)?;
+    ok_span(Value::Number(numbers.iter().sum()), *call_span)
+};
+
+/// The mean of a list of numbers, or nil for an empty list: (avg <list>) or
+/// (avg <list> <key>)
+pub const ATOM_AVG: NativeFn = |args, context, call_span| {
+    let (items, key, span) = eval_aggregate_args(args, context, *call_span)?;
+    let numbers = aggregate_numbers(&items, key.as_ref(), context, call_span, span)?;
+    let value = match numbers.len() {
+        0 => Value::Nil,
+        len => Value::Number(numbers.iter().sum::<f64>() / len as f64),
+    };
+    ok_span(value, *call_span)
+};
+
+/// The smallest item, by its own value or by a key function, or nil for an empty
+/// list: (min-of <list>) or (min-of <list> <key>)
+pub const ATOM_MIN_OF: NativeFn =
+    |args, context, call_span| aggregate_extreme(args, context, call_span, |a, b| a < b);
+
+/// The largest item, by its own value or by a key function, or nil for an empty
+/// list: (max-of <list>) or (max-of <list> <key>)
+pub const ATOM_MAX_OF: NativeFn =
+    |args, context, call_span| aggregate_extreme(args, context, call_span, |a, b| a > b);
+
+/// How many items are truthy, or satisfy a predicate: (count-if <list>) or
+/// (count-if <list> <predicate>)
+pub const ATOM_COUNT_IF: NativeFn = |args, context, call_span| {
+    let (items, predicate, span) = eval_aggregate_args(args, context, *call_span)?;
+    let mut count = 0;
+    for item in items {
+        let value = match &predicate {
+            Some(predicate) => {
+                call_function_with_values(predicate, &[item], context, call_span, span)?.value
+            }
+            None => item,
+        };
+        count += usize::from(value.is_truthy());
+    }
+    ok_span(Value::Number(count as f64), *call_span)
+};
+
 // ============================================================================
 // MAP OPERATIONS
 // ============================================================================
@@ -296,7 +726,7 @@ pub const ATOM_MAP: NativeFn = |args, context, call_span| {
 pub const ATOM_CORE_MAP: NativeFn = |args, context, call_span| {
     require_even_arity(args, context, *call_span)?;
 
-    let mut map = std::collections::HashMap::new();
+    let mut map = std::collections::BTreeMap::new();
     for chunk in args.chunks(2) {
         let key_val = eval_arg(&chunk[0], context)?;
         let key = match key_val.value {
@@ -0,0 +1,425 @@
+//! Type conversion and type predicate atoms for the Sutra language.
+//!
+//! ## Atoms Provided
+//!
+//! - **Conversions**: `num->str`, `format-number`, `str->num`, `sym->str`, `list->str`,
+//!   `str->list`, `read`
+//! - **Predicates**: `bool?`, `number?`, `string?`, `list?`, `path?`
+//!
+//! ## Design Notes
+//!
+//! A conversion given a value of the wrong type fails with a type error. `str->num`
+//! is meant for user input, so a string that is not a number returns `nil` instead.
+//! The predicates accept any value and never fail; `list?` is true for `nil`, the
+//! empty list.
+//!
+//! `num->str` formats a number as it displays, rounded to 15 significant digits so
+//! `(+ 0.1 0.2)` is `"0.3"`. `format-number` is for presenting one, with a fixed
+//! number of decimals, a thousands separator and padding to a width.
+//!
+//! `read` parses text as `quote` would its argument, without expanding macros, so
+//! `(read "(1 2 3)")` equals `'(1 2 3)`. Text that is not exactly one form fails with
+//! an `invalid_data` error giving the line and column within the text.
+
+use crate::{
+    errors::{
+        line_and_column, to_source_span, ErrorKind, ErrorReporting, SourceContext, SutraError,
+    },
+    parser,
+    runtime::{
+        ast_to_value, evaluate_ast_node, format_number, ConsRepr, EvaluationContext, NativeFn,
+        SpannedValue, Value,
+    },
+    syntax::{trailing_keyword_args, AstNode, KeywordArg, Span},
+};
+
+// ============================================================================
+// HELPER FUNCTIONS
+// ============================================================================
+
+/// Evaluates the arguments of an atom taking `min..=max` of them.
+fn eval_args(
+    args: &[AstNode],
+    min: usize,
+    max: usize,
+    context: &mut EvaluationContext,
+    span: Span,
+) -> Result<Vec<SpannedValue>, SutraError> {
+    if !(min..=max).contains(&args.len()) {
+        let expected = if min == max {
+            min.to_string()
+        } else {
+            format!("{min} or {max}")
+        };
+        return Err(context.arity_mismatch(&expected, args.len(), to_source_span(span)));
+    }
+    args.iter()
+        .map(|arg| evaluate_ast_node(arg, context))
+        .collect()
+}
+
+fn type_error<T>(
+    expected: &str,
+    val: &SpannedValue,
+    context: &mut EvaluationContext,
+) -> Result<T, SutraError> {
+    Err(context.type_mismatch(expected, val.value.type_name(), to_source_span(val.span)))
+}
+
+fn expect_string<'a>(
+    val: &'a SpannedValue,
+    context: &mut EvaluationContext,
+) -> Result<&'a str, SutraError> {
+    match &val.value {
+        Value::String(s) => Ok(s),
+        _ => type_error("String", val, context),
+    }
+}
+
+fn ok_span(value: Value, span: Span) -> Result<SpannedValue, SutraError> {
+    Ok(SpannedValue { value, span })
+}
+
+fn build_list(items: impl DoubleEndedIterator<Item = Value>) -> Value {
+    items.rev().fold(Value::Nil, |list, item| {
+        Value::Cons(ConsRepr::cons(item, list))
+    })
+}
+
+/// Parses a finite decimal number, ignoring surrounding whitespace.
+fn parse_number(s: &str) -> Option<f64> {
+    s.trim().parse::<f64>().ok().filter(|n| n.is_finite())
+}
+
+/// Most decimals `format-number` gives.
+const MAX_DECIMALS: usize = 100;
+
+/// Widest `format-number` pads to.
+const MAX_WIDTH: usize = 1000;
+
+/// How `format-number` presents a number.
+struct NumberFormat {
+    decimals: Option<usize>,
+    thousands: String,
+    width: usize,
+    fill: char,
+}
+
+impl Default for NumberFormat {
+    fn default() -> Self {
+        Self {
+            decimals: None,
+            thousands: String::new(),
+            width: 0,
+            fill: ' ',
+        }
+    }
+}
+
+impl NumberFormat {
+    fn apply(&self, n: f64) -> String {
+        let text = match self.decimals {
+            Some(decimals) if n.is_finite() => format!("{n:.decimals$}"),
+            _ => format_number(n),
+        };
+        let (sign, digits) = match text.strip_prefix('-') {
+            // A negative number that rounds to zero has no sign.
+            Some(digits) if digits.bytes().any(|b| matches!(b, b'1'..=b'9')) => ("-", digits),
+            Some(digits) => ("", digits),
+            None => ("", text.as_str()),
+        };
+        let digits = if self.thousands.is_empty() || !n.is_finite() || digits.contains('e') {
+            digits.to_string()
+        } else {
+            group_thousands(digits, &self.thousands)
+        };
+        let padding: String = std::iter::repeat_n(
+            self.fill,
+            self.width
+                .saturating_sub(sign.len() + digits.chars().count()),
+        )
+        .collect();
+        if self.fill == '0' {
+            format!("{sign}{padding}{digits}")
+        } else {
+            format!("{padding}{sign}{digits}")
+        }
+    }
+}
+
+/// Separates the whole part of unsigned decimal `digits` into groups of three.
+fn group_thousands(digits: &str, separator: &str) -> String {
+    let (whole, fraction) = digits.split_at(digits.find('.').unwrap_or(digits.len()));
+    let mut grouped = String::new();
+    for (i, digit) in whole.chars().enumerate() {
+        if i > 0 && (whole.len() - i) % 3 == 0 {
+            grouped.push_str(separator);
+        }
+        grouped.push(digit);
+    }
+    grouped + fraction
+}
+
+/// The whole number from 0 to `max` given for a `format-number` option.
+fn count_option(
+    option: &KeywordArg,
+    value: &SpannedValue,
+    max: usize,
+    context: &mut EvaluationContext,
+) -> Result<usize, SutraError> {
+    let Value::Number(n) = value.value else {
+        return type_error("Number", value, context);
+    };
+    if n.fract() != 0.0 || !(0.0..=max as f64).contains(&n) {
+        return Err(context.invalid_operation(
+            "format-number",
+            &format!("':{}' must be a whole number from 0 to {max}", option.name),
+            to_source_span(value.span),
+        ));
+    }
+    Ok(n as usize)
+}
+
+/// The error for text given to `read` that is not one form, at `offset` in it.
+fn unreadable(
+    message: String,
+    text: &str,
+    offset: usize,
+    context: &EvaluationContext,
+    span: Span,
+) -> SutraError {
+    let (line, column) = line_and_column(text, offset);
+    context.report(
+        ErrorKind::InvalidData {
+            message,
+            line,
+            column,
+        },
+        to_source_span(span),
+    )
+}
+
+/// Applies `test` to the single argument of a predicate atom.
+fn predicate(
+    args: &[AstNode],
+    context: &mut EvaluationContext,
+    span: &Span,
+    test: fn(&Value) -> bool,
+) -> Result<SpannedValue, SutraError> {
+    let values = eval_args(args, 1, 1, context, *span)?;
+    ok_span(Value::Bool(test(&values[0].value)), *span)
+}
+
+// ============================================================================
+// CONVERSIONS
+// ============================================================================
+
+/// Formats a number as a string: (num->str <number>)
+/// Whole numbers have no decimal point: `(num->str 3)` is `"3"`.
+pub const ATOM_NUM_TO_STR: NativeFn = |args, context, call_span| {
+    let values = eval_args(args, 1, 1, context, *call_span)?;
+    match &values[0].value {
+        Value::Number(n) => ok_span(Value::String(format_number(*n)), *call_span),
+        _ => type_error("Number", &values[0], context),
+    }
+};
+
+/// Formats a number for presentation:
+/// (format-number <number> [:decimals n] [:thousands separator] [:width n] [:fill char])
+///
+/// `:decimals` rounds to a fixed number of decimals, `:thousands` separates groups of
+/// three digits, and `:width` pads on the left with `:fill`, a space by default. Zero
+/// fill goes after the sign.
+///
+/// Example:
+///   (format-number 1234.5 :decimals 2 :thousands ",") ; => "1,234.50"
+pub const ATOM_FORMAT_NUMBER: NativeFn = |args, context, call_span| {
+    let (positional, options) = trailing_keyword_args(args, context)?;
+    if positional.len() != 1 {
+        return Err(context.arity_mismatch("1", positional.len(), to_source_span(*call_span)));
+    }
+    let number = evaluate_ast_node(&positional[0], context)?;
+    let Value::Number(n) = number.value else {
+        return type_error("Number", &number, context);
+    };
+
+    let mut format = NumberFormat::default();
+    for option in options {
+        let value = evaluate_ast_node(option.value, context)?;
+        match option.name {
+            "decimals" => {
+                format.decimals = Some(count_option(&option, &value, MAX_DECIMALS, context)?)
+            }
+            "width" => format.width = count_option(&option, &value, MAX_WIDTH, context)?,
+            "thousands" => format.thousands = expect_string(&value, context)?.to_string(),
+            "fill" => {
+                let mut chars = expect_string(&value, context)?.chars();
+                match (chars.next(), chars.next()) {
+                    (Some(fill), None) => format.fill = fill,
+                    _ => {
+                        return Err(context.invalid_operation(
+                            "format-number",
+                            "':fill' must be a single character",
+                            to_source_span(value.span),
+                        ))
+                    }
+                }
+            }
+            name => {
+                return Err(context.invalid_operation(
+                    "format-number",
+                    &format!("unknown option ':{name}'"),
+                    to_source_span(option.keyword.span),
+                ))
+            }
+        }
+    }
+    ok_span(Value::String(format.apply(n)), *call_span)
+};
+
+/// Parses a string as a number, or returns nil if it is not one: (str->num <string>)
+pub const ATOM_STR_TO_NUM: NativeFn = |args, context, call_span| {
+    let values = eval_args(args, 1, 1, context, *call_span)?;
+    let s = expect_string(&values[0], context)?;
+    let value = parse_number(s).map_or(Value::Nil, Value::Number);
+    ok_span(value, *call_span)
+};
+
+/// Returns the name of a symbol or keyword: (sym->str <symbol>)
+/// `(sym->str 'north)` is `"north"` and `(sym->str :north)` is `"north"`.
+pub const ATOM_SYM_TO_STR: NativeFn = |args, context, call_span| {
+    let values = eval_args(args, 1, 1, context, *call_span)?;
+    let name = match &values[0].value {
+        Value::Symbol(name) | Value::Keyword(name) => name.clone(),
+        Value::Quote(quoted) => match &**quoted {
+            Value::Symbol(name) => name.clone(),
+            _ => return type_error("Symbol or Keyword", &values[0], context),
+        },
+        _ => return type_error("Symbol or Keyword", &values[0], context),
+    };
+    ok_span(Value::String(name), *call_span)
+};
+
+/// Joins the elements of a list into a string: (list->str <list> [separator])
+/// Strings are joined as they are; other elements as `str` would format them.
+pub const ATOM_LIST_TO_STR: NativeFn = |args, context, call_span| {
+    let values = eval_args(args, 1, 2, context, *call_span)?;
+    let separator = match values.get(1) {
+        Some(separator) => expect_string(separator, context)?.to_string(),
+        None => String::new(),
+    };
+    let list = &values[0];
+    if !matches!(list.value, Value::Cons(_) | Value::Nil) {
+        return type_error("List or Nil", list, context);
+    }
+
+    let joined = list
+        .value
+        .clone()
+        .try_into_iter()
+        .map(|item| item.to_string())
+        .collect::<Vec<_>>()
+        .join(&separator);
+    ok_span(Value::String(joined), *call_span)
+};
+
+/// Splits a string into a list of strings: (str->list <string> [separator])
+/// Without a separator, the string is split into its characters.
+pub const ATOM_STR_TO_LIST: NativeFn = |args, context, call_span| {
+    let values = eval_args(args, 1, 2, context, *call_span)?;
+    let s = expect_string(&values[0], context)?;
+    let parts: Vec<String> = match values.get(1) {
+        None => s.chars().map(String::from).collect(),
+        Some(separator) => {
+            let separator_str = expect_string(separator, context)?;
+            if separator_str.is_empty() {
+                return Err(context.invalid_operation(
+                    "str->list",
+                    "empty separator",
+                    to_source_span(separator.span),
+                ));
+            }
+            if s.is_empty() {
+                Vec::new()
+            } else {
+                s.split(separator_str).map(String::from).collect()
+            }
+        }
+    };
+    let list = build_list(parts.into_iter().map(Value::String));
+    ok_span(list, *call_span)
+};
+
+/// Parses a string into quoted data: (read <string>)
+/// `(read "(1 2 3)")` is `'(1 2 3)`, and `(eval (read "(+ 1 2)"))` is 3.
+pub const ATOM_READ: NativeFn = |args, context, call_span| {
+    let values = eval_args(args, 1, 1, context, *call_span)?;
+    let text = expect_string(&values[0], context)?;
+    let span = values[0].span;
+    let mut forms =
+        parser::parse(text, SourceContext::from_file("read", text)).map_err(|error| {
+            let offset = error.span().map_or(0, |span| span.offset());
+            let message = error.to_string();
+            let message = message.strip_prefix("Parse error: ").unwrap_or(&message);
+            unreadable(message.to_string(), text, offset, context, span)
+        })?;
+    match forms.len() {
+        1 => {
+            let data = ast_to_value(&forms.remove(0));
+            ok_span(Value::Quote(Box::new(data)), *call_span)
+        }
+        0 => Err(unreadable(
+            "no form".to_string(),
+            text,
+            text.len(),
+            context,
+            span,
+        )),
+        n => Err(unreadable(
+            format!("expected one form, found {n}"),
+            text,
+            forms[1].span.start,
+            context,
+            span,
+        )),
+    }
+};
+
+// ============================================================================
+// PREDICATES
+// ============================================================================
+
+/// True for `true` and `false`: (bool? <value>)
+pub const ATOM_IS_BOOL: NativeFn = |args, context, call_span| {
+    predicate(args, context, call_span, |value| {
+        matches!(value, Value::Bool(_))
+    })
+};
+
+/// True for numbers: (number? <value>)
+pub const ATOM_IS_NUMBER: NativeFn = |args, context, call_span| {
+    predicate(args, context, call_span, |value| {
+        matches!(value, Value::Number(_))
+    })
+};
+
+/// True for strings: (string? <value>)
+pub const ATOM_IS_STRING: NativeFn = |args, context, call_span| {
+    predicate(args, context, call_span, |value| {
+        matches!(value, Value::String(_))
+    })
+};
+
+/// True for lists, including the empty list `nil`: (list? <value>)
+pub const ATOM_IS_LIST: NativeFn = |args, context, call_span| {
+    predicate(args, context, call_span, |value| {
+        matches!(value, Value::Cons(_) | Value::Nil)
+    })
+};
+
+/// True for path values: (path? <value>)
+pub const ATOM_IS_PATH: NativeFn = |args, context, call_span| {
+    predicate(args, context, call_span, |value| {
+        matches!(value, Value::Path(_))
+    })
+};
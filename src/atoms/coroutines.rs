@@ -0,0 +1,621 @@
+//! Coroutine atoms for the Sutra language.
+//!
+//! ## Atoms Provided
+//!
+//! - `coroutine`: wraps a function call in a coroutine, without running it yet
+//! - `resume`: runs a coroutine until it yields or returns
+//! - `yield`: suspends the running coroutine, handing a value to `resume`
+//! - `coroutine/done?`: whether a coroutine's function has returned
+//!
+//! ## Design Notes
+//!
+//! Each coroutine evaluates on a stack of its own, so a suspended coroutine keeps its
+//! whole evaluation, and `yield` works anywhere in the code the coroutine calls, not
+//! only directly in its function. The value passed to `resume` becomes the value of
+//! the `yield` that suspended the coroutine; the first `resume`'s value is dropped, as
+//! no `yield` is waiting for it. Once the function returns, `resume` gives what it
+//! returned and the coroutine is done. An error in the function ends the coroutine
+//! and fails the `resume` that ran it.
+//!
+//! Hosts can drive coroutines a script returns with [`Coroutine::resume`].
+//!
+//! A coroutine's stack may hold what only its own thread can use, so a coroutine is
+//! only resumed on the thread that made it. Resuming it on another fails, and one
+//! dropped on another thread leaks its stack rather than unwind it there.
+//!
+//! A stack cannot be saved, so each coroutine also keeps a [`CoroutineRecord`] of its
+//! call, the world it started in, and what it was resumed with and yielded. An
+//! [engine snapshot](crate::snapshot) saves the record, and restoring it runs the
+//! function again with output muted, checking that it yields the same values.
+
+use std::{
+    cell::RefCell,
+    collections::BTreeMap,
+    fmt,
+    mem::ManuallyDrop,
+    rc::Rc,
+    sync::{Arc, Mutex, MutexGuard, PoisonError, TryLockError},
+    thread::{self, ThreadId},
+};
+
+use corosensei::{stack::DefaultStack, CoroutineResult, Yielder};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    atoms::{
+        collections::call_function_with_values, Choice, LogLevel, OutputSink, SharedOutput,
+        SmallRng, World, WorldHandle,
+    },
+    errors::{to_source_span, ErrorKind, ErrorReporting, SutraError},
+    runtime::{evaluate_ast_node, EvaluationContext, NativeFn, SpannedValue, Value},
+    syntax::Span,
+};
+
+/// Stack size of each coroutine: a main thread's, so evaluation can nest as deeply.
+const STACK_SIZE: usize = 8 << 20;
+
+type Body = corosensei::Coroutine<Value, Value, Result<Value, SutraError>, DefaultStack>;
+
+/// What a coroutine did when resumed.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Resumed {
+    /// It suspended at a `yield` of this value.
+    Yielded(Value),
+    /// Its function returned this value, so it is done.
+    Returned(Value),
+}
+
+/// Where a coroutine is in its life.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CoroutineStatus {
+    /// Not yet started, or waiting at a `yield`.
+    Suspended,
+    /// Being resumed; only code it calls can observe this.
+    Running,
+    /// Its function has returned or failed.
+    Done,
+}
+
+/// A function call that can suspend itself with `yield` and be resumed later.
+pub struct Coroutine {
+    /// `None` once the function has returned or failed.
+    body: Mutex<Option<Stack>>,
+    record: Mutex<CoroutineRecord>,
+}
+
+/// A coroutine's body, with the thread it was made on, the only one it runs or is
+/// dropped on.
+struct Stack {
+    thread: ThreadId,
+    body: ManuallyDrop<Body>,
+}
+
+// SAFETY: the body is only resumed and dropped on the thread that made it, so whatever
+// its stack holds never reaches another thread.
+unsafe impl Send for Stack {}
+
+impl Stack {
+    fn new(body: Body) -> Self {
+        Self {
+            thread: thread::current().id(),
+            body: ManuallyDrop::new(body),
+        }
+    }
+
+    /// The body, unless this is not the thread it was made on.
+    fn body(&mut self) -> Option<&mut Body> {
+        (self.thread == thread::current().id()).then_some(&mut self.body)
+    }
+}
+
+impl Drop for Stack {
+    fn drop(&mut self) {
+        if self.thread == thread::current().id() {
+            // SAFETY: the body is not used again.
+            unsafe { ManuallyDrop::drop(&mut self.body) }
+        }
+    }
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// What a snapshot keeps of a coroutine: enough to run it again to where it is.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) enum CoroutineRecord {
+    /// Its function has returned or failed.
+    Done,
+    /// It called `function` with `args` in the world `origin`, and has been resumed
+    /// once for each of `resumes` since.
+    Started {
+        function: Value,
+        args: Vec<Value>,
+        origin: Origin,
+        resumes: Vec<Resume>,
+    },
+}
+
+/// The parts of a world a coroutine can read: its state, definitions and PRNG.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct Origin {
+    state: BTreeMap<String, Value>,
+    defs: BTreeMap<String, Value>,
+    prng: SmallRng,
+}
+
+impl Origin {
+    fn capture(world: &World) -> Self {
+        Self {
+            state: world.state.entries(),
+            defs: world.defs.clone(),
+            prng: world.prng.clone(),
+        }
+    }
+
+    /// Exchanges these parts with the world's; doing it twice changes nothing.
+    fn swap_with(&mut self, world: &mut World) {
+        let state = world.state.entries();
+        world
+            .state
+            .replace_entries(std::mem::replace(&mut self.state, state));
+        std::mem::swap(&mut self.defs, &mut world.defs);
+        std::mem::swap(&mut self.prng, &mut world.prng);
+    }
+}
+
+/// A resume that ended in a `yield`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct Resume {
+    input: Value,
+    yielded: Value,
+}
+
+/// Passes output on to `target` except while `muted`, so replays stay silent.
+struct ReplayOutput {
+    target: SharedOutput,
+    muted: bool,
+}
+
+impl OutputSink for ReplayOutput {
+    fn emit(&mut self, text: &str, span: Option<&Span>) {
+        if !self.muted {
+            self.target.emit(text, span);
+        }
+    }
+
+    fn supports_color(&self) -> bool {
+        self.target.0.borrow().supports_color()
+    }
+
+    fn choose(&mut self, choice: &Choice, span: Option<&Span>) -> Option<usize> {
+        if self.muted {
+            return None;
+        }
+        self.target.choose(choice, span)
+    }
+
+    fn log(&mut self, level: LogLevel, message: &str, span: Option<&Span>) {
+        if !self.muted {
+            self.target.log(level, message, span);
+        }
+    }
+}
+
+impl fmt::Debug for Coroutine {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Coroutine({:?})", self.status())
+    }
+}
+
+/// Lets `yield` reach the coroutine an evaluation is running in.
+#[derive(Clone, Copy)]
+pub struct YieldHandle(*const Yielder<Value, Value>);
+
+impl YieldHandle {
+    pub(crate) fn suspend(self, value: Value) -> Value {
+        // SAFETY: handles are only stored in contexts derived from the one a coroutine's
+        // body creates, on the coroutine's own stack. Those contexts cannot outlive the
+        // body, and the body's yielder lives as long as the body, so it is alive here.
+        unsafe { (*self.0).suspend(value) }
+    }
+}
+
+impl Coroutine {
+    /// A coroutine that will call `function` with `args` when first resumed, evaluating
+    /// in a copy of `context`.
+    pub fn new(
+        function: Value,
+        args: Vec<Value>,
+        context: &EvaluationContext,
+        span: Span,
+    ) -> std::io::Result<Self> {
+        let origin = Origin::capture(&context.world.read());
+        let body = start(function.clone(), args.clone(), context, span)?;
+        Ok(Self {
+            body: Mutex::new(Some(Stack::new(body))),
+            record: Mutex::new(CoroutineRecord::Started {
+                function,
+                args,
+                origin,
+                resumes: Vec::new(),
+            }),
+        })
+    }
+
+    /// A coroutine that is done until [`replay`](Self::replay) brings it back.
+    pub(crate) fn pending() -> Self {
+        Self {
+            body: Mutex::new(None),
+            record: Mutex::new(CoroutineRecord::Done),
+        }
+    }
+
+    pub(crate) fn record(&self) -> MutexGuard<'_, CoroutineRecord> {
+        lock(&self.record)
+    }
+
+    pub fn status(&self) -> CoroutineStatus {
+        let body = match self.body.try_lock() {
+            Ok(body) => body,
+            Err(TryLockError::Poisoned(poisoned)) => poisoned.into_inner(),
+            Err(TryLockError::WouldBlock) => return CoroutineStatus::Running,
+        };
+        match *body {
+            Some(_) => CoroutineStatus::Suspended,
+            None => CoroutineStatus::Done,
+        }
+    }
+
+    /// Runs the coroutine until it yields or returns, making `input` the value of the
+    /// `yield` it is waiting at. Fails if the coroutine is running or done, if it was
+    /// made on another thread, or if its function fails.
+    pub fn resume(&self, input: Value) -> Result<Resumed, SutraError> {
+        let resumed = self.step(input.clone());
+        if self.status() == CoroutineStatus::Done {
+            *lock(&self.record) = CoroutineRecord::Done;
+        } else if let (Ok(Resumed::Yielded(value)), CoroutineRecord::Started { resumes, .. }) =
+            (&resumed, &mut *lock(&self.record))
+        {
+            resumes.push(Resume {
+                input,
+                yielded: value.clone(),
+            });
+        }
+        resumed
+    }
+
+    /// Makes a [`pending`](Self::pending) coroutine the one `record` describes, by
+    /// calling its function again in the world it started in and resuming it with the
+    /// recorded values, with its output muted. `context`'s world is left as it was.
+    /// Fails if the function fails or does not yield what was recorded.
+    pub(crate) fn replay(
+        &self,
+        record: CoroutineRecord,
+        context: &EvaluationContext,
+    ) -> Result<(), String> {
+        let CoroutineRecord::Started {
+            function,
+            args,
+            origin,
+            resumes,
+        } = &record
+        else {
+            return Ok(());
+        };
+        let output = Rc::new(RefCell::new(ReplayOutput {
+            target: context.output.clone(),
+            muted: true,
+        }));
+        let mut context = context.with_new_frame();
+        context.output = SharedOutput(output.clone());
+        let body = start(function.clone(), args.clone(), &context, Span::default())
+            .map_err(|e| e.to_string())?;
+        *lock(&self.body) = Some(Stack::new(body));
+
+        let mut world = origin.clone();
+        world.swap_with(&mut context.world.write());
+        context.world.write().replaying = true;
+        let replayed = resumes.iter().enumerate().try_for_each(|(n, resume)| {
+            let n = n + 1;
+            match self.step(resume.input.clone()) {
+                Ok(Resumed::Yielded(value)) if value == resume.yielded => Ok(()),
+                Ok(Resumed::Yielded(value)) => Err(format!(
+                    "resume {n} yielded {value} where {} was recorded",
+                    resume.yielded
+                )),
+                Ok(Resumed::Returned(value)) => Err(format!(
+                    "resume {n} returned {value} where a yield of {} was recorded",
+                    resume.yielded
+                )),
+                Err(error) => Err(format!("resume {n} failed: {error}")),
+            }
+        });
+        context.world.write().replaying = false;
+        world.swap_with(&mut context.world.write());
+        output.borrow_mut().muted = false;
+
+        if replayed.is_err() {
+            *lock(&self.body) = None;
+        }
+        replayed?;
+        *lock(&self.record) = record;
+        Ok(())
+    }
+
+    fn step(&self, input: Value) -> Result<Resumed, SutraError> {
+        let status = self.status();
+        let mut slot = match self.body.try_lock() {
+            Ok(slot) if slot.is_some() => slot,
+            _ => return Err(unresumable(status_description(status))),
+        };
+        let Some(body) = slot.as_mut().and_then(Stack::body) else {
+            return Err(unresumable("a coroutine made on another thread"));
+        };
+        match body.resume(input) {
+            CoroutineResult::Yield(value) => Ok(Resumed::Yielded(value)),
+            CoroutineResult::Return(result) => {
+                *slot = None;
+                result.map(Resumed::Returned)
+            }
+        }
+    }
+}
+
+/// The stack a coroutine calling `function` with `args` runs on.
+fn start(
+    function: Value,
+    args: Vec<Value>,
+    context: &EvaluationContext,
+    span: Span,
+) -> std::io::Result<Body> {
+    let mut context = context.with_new_frame();
+    let stack = DefaultStack::new(STACK_SIZE)?;
+    Ok(Body::with_stack(
+        stack,
+        move |yielder: &Yielder<Value, Value>, _| {
+            context.yielder = Some(YieldHandle(yielder));
+            call_function_with_values(&function, &args, &mut context, &span, span)
+                .map(|result| result.value)
+        },
+    ))
+}
+
+/// The error for resuming `coroutine`, described as by [`status_description`], for
+/// hosts, which have no span.
+fn unresumable(coroutine: &str) -> SutraError {
+    SutraError::without_source(
+        ErrorKind::InvalidOperation {
+            operation: "resume".to_string(),
+            operand_type: coroutine.to_string(),
+        },
+        "coroutine",
+        "runtime",
+    )
+}
+
+fn status_description(status: CoroutineStatus) -> &'static str {
+    match status {
+        CoroutineStatus::Running => "a coroutine that is already running",
+        _ => "a coroutine that is done",
+    }
+}
+
+fn expect_coroutine(
+    val: &SpannedValue,
+    context: &mut EvaluationContext,
+) -> Result<Arc<Coroutine>, SutraError> {
+    match &val.value {
+        Value::Coroutine(coroutine) => Ok(Arc::clone(coroutine)),
+        other => {
+            Err(context.type_mismatch("Coroutine", other.type_name(), to_source_span(val.span)))
+        }
+    }
+}
+
+// ============================================================================
+// ATOMS
+// ============================================================================
+
+/// Wraps a call of a function in a coroutine, which `resume` then runs:
+/// (coroutine <function> <arg>...)
+///
+/// Example:
+///   (define counter
+///     (coroutine (lambda (n) (do (yield n) (yield (+ n 1)) "done")) 1))
+///   (resume counter) ; => 1
+///   (resume counter) ; => 2
+///   (resume counter) ; => "done"
+pub const ATOM_COROUTINE: NativeFn = |args, context, call_span| {
+    let Some((function, rest)) = args.split_first() else {
+        return Err(context.arity_mismatch("at least 1", 0, to_source_span(*call_span)));
+    };
+    let function = evaluate_ast_node(function, context)?;
+    if !matches!(function.value, Value::Lambda(_) | Value::NativeFn(_)) {
+        return Err(context.type_mismatch(
+            "Lambda or NativeFn",
+            function.value.type_name(),
+            to_source_span(function.span),
+        ));
+    }
+    let mut values = Vec::with_capacity(rest.len());
+    for arg in rest {
+        values.push(evaluate_ast_node(arg, context)?.value);
+    }
+
+    let coroutine = Coroutine::new(function.value, values, context, *call_span)
+        .map_err(|e| context.internal_error(&e.to_string(), to_source_span(*call_span)))?;
+    Ok(SpannedValue {
+        value: Value::Coroutine(Arc::new(coroutine)),
+        span: *call_span,
+    })
+};
+
+/// Runs a coroutine until it yields or returns, giving the value it yielded or
+/// returned: (resume <coroutine> [value])
+/// The value, nil by default, becomes the result of the `yield` being resumed.
+pub const ATOM_RESUME: NativeFn = |args, context, call_span| {
+    if !(1..=2).contains(&args.len()) {
+        return Err(context.arity_mismatch("1 or 2", args.len(), to_source_span(*call_span)));
+    }
+    let target = evaluate_ast_node(&args[0], context)?;
+    let coroutine = expect_coroutine(&target, context)?;
+    let input = match args.get(1) {
+        Some(arg) => evaluate_ast_node(arg, context)?.value,
+        None => Value::Nil,
+    };
+    let status = coroutine.status();
+    if status != CoroutineStatus::Suspended {
+        return Err(context.invalid_operation(
+            "resume",
+            status_description(status),
+            to_source_span(target.span),
+        ));
+    }
+    if context.world.read().replaying {
+        return Err(context.invalid_operation(
+            "resume",
+            "another coroutine while a snapshot is restored",
+            to_source_span(*call_span),
+        ));
+    }
+
+    let (Resumed::Yielded(value) | Resumed::Returned(value)) = coroutine.resume(input)?;
+    Ok(SpannedValue {
+        value,
+        span: *call_span,
+    })
+};
+
+/// Suspends the running coroutine, handing a value (nil by default) to the `resume`
+/// that ran it: (yield [value])
+/// Evaluates to the value passed to the next `resume`.
+pub const ATOM_YIELD: NativeFn = |args, context, call_span| {
+    if args.len() > 1 {
+        return Err(context.arity_mismatch("0 or 1", args.len(), to_source_span(*call_span)));
+    }
+    let value = match args.first() {
+        Some(arg) => evaluate_ast_node(arg, context)?.value,
+        None => Value::Nil,
+    };
+    let Some(yielder) = context.yielder else {
+        return Err(context.invalid_operation(
+            "yield",
+            "code outside a coroutine",
+            to_source_span(*call_span),
+        ));
+    };
+
+    Ok(SpannedValue {
+        value: yielder.suspend(value),
+        span: *call_span,
+    })
+};
+
+/// True once a coroutine's function has returned or failed: (coroutine/done? <coroutine>)
+pub const ATOM_COROUTINE_DONE: NativeFn = |args, context, call_span| {
+    if args.len() != 1 {
+        return Err(context.arity_mismatch("1", args.len(), to_source_span(*call_span)));
+    }
+    let target = evaluate_ast_node(&args[0], context)?;
+    let coroutine = expect_coroutine(&target, context)?;
+    Ok(SpannedValue {
+        value: Value::Bool(coroutine.status() == CoroutineStatus::Done),
+        span: *call_span,
+    })
+};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cli::ExecutionPipeline;
+
+    fn coroutine_from(source: &str) -> Arc<Coroutine> {
+        let pipeline = ExecutionPipeline::default();
+        match pipeline.execute_for_value(source, "coroutine").unwrap() {
+            Value::Coroutine(coroutine) => coroutine,
+            other => panic!("expected a coroutine, got {other}"),
+        }
+    }
+
+    #[test]
+    fn hosts_resume_coroutines_scripts_return() {
+        let coroutine = coroutine_from("(coroutine (lambda () (do (define x (yield 1)) (+ x 1))))");
+        assert_eq!(coroutine.status(), CoroutineStatus::Suspended);
+        assert_eq!(
+            coroutine.resume(Value::Nil).unwrap(),
+            Resumed::Yielded(Value::Number(1.0))
+        );
+        assert_eq!(
+            coroutine.resume(Value::Number(41.0)).unwrap(),
+            Resumed::Returned(Value::Number(42.0))
+        );
+        assert_eq!(coroutine.status(), CoroutineStatus::Done);
+        assert!(coroutine.resume(Value::Nil).is_err());
+    }
+
+    #[test]
+    fn hosts_answer_choices_a_coroutine_asks() {
+        let coroutine = coroutine_from(
+            "(coroutine (lambda () (choice \"The road forks.\" ((\"North\" 1) (\"Rest\" 2)))))",
+        );
+        let Resumed::Yielded(asked) = coroutine.resume(Value::Nil).unwrap() else {
+            panic!("expected the coroutine to wait at the choice");
+        };
+        assert_eq!(
+            Choice::from_value(&asked),
+            Some(Choice {
+                prompt: Some("The road forks.".to_string()),
+                options: vec!["North".to_string(), "Rest".to_string()],
+            })
+        );
+        assert_eq!(coroutine.status(), CoroutineStatus::Suspended);
+        assert_eq!(
+            coroutine.resume(Value::Number(1.0)).unwrap(),
+            Resumed::Returned(Value::Number(2.0))
+        );
+    }
+
+    #[test]
+    fn errors_end_the_coroutine() {
+        let coroutine = coroutine_from("(coroutine (lambda () (do (yield 1) (car 1))))");
+        coroutine.resume(Value::Nil).unwrap();
+        assert!(coroutine.resume(Value::Nil).is_err());
+        assert_eq!(coroutine.status(), CoroutineStatus::Done);
+    }
+
+    #[test]
+    fn suspended_coroutines_can_be_dropped() {
+        let coroutine = coroutine_from("(coroutine (lambda () (do (yield 1) (yield 2))))");
+        coroutine.resume(Value::Nil).unwrap();
+        drop(coroutine);
+    }
+
+    #[test]
+    fn coroutines_only_resume_on_their_own_thread() {
+        let coroutine = coroutine_from("(coroutine (lambda () (do (yield 1) (yield 2))))");
+        let elsewhere = Arc::clone(&coroutine);
+        let error = std::thread::spawn(move || elsewhere.resume(Value::Nil).unwrap_err())
+            .join()
+            .unwrap();
+        assert!(error.to_string().contains("another thread"), "{error}");
+        assert_eq!(
+            coroutine.resume(Value::Nil).unwrap(),
+            Resumed::Yielded(Value::Number(1.0))
+        );
+        std::thread::spawn(move || drop(coroutine)).join().unwrap();
+    }
+
+    #[test]
+    fn restoring_one_world_leaves_others_alone() {
+        let source = "(resume (coroutine (lambda () 1)))";
+        let restoring = ExecutionPipeline::default();
+        restoring.world.write().replaying = true;
+        let other = ExecutionPipeline::default();
+        assert_eq!(
+            other.execute_for_value(source, "other").unwrap(),
+            Value::Number(1.0)
+        );
+        assert!(restoring.execute_for_value(source, "restoring").is_err());
+    }
+}
@@ -5,7 +5,7 @@
 //!
 //! ## Atoms Provided
 //!
-//! - **Control Flow**: `do`, `error`
+//! - **Control Flow**: `do`, `error`, `assert`, `eval`
 //! - **Higher-Order Functions**: `apply`, `for-each`
 //!
 //! ## Design Notes
@@ -14,9 +14,9 @@
 //! They properly thread world state through sequential operations.
 
 use crate::{
-    atoms::special_forms::call_lambda,
+    atoms::{special_forms::call_lambda, WorldHandle},
     errors::{to_source_span, ErrorKind, ErrorReporting},
-    runtime::{evaluate_ast_node, NativeFn, SpannedValue, Value},
+    runtime::{evaluate_ast_node, value_to_ast, NativeFn, SpannedValue, Value},
     syntax::Expr,
 };
 
@@ -105,6 +105,102 @@ pub const ATOM_ERROR: NativeFn = |args, context, call_span| {
     ))
 };
 
+/// Fails unless a condition holds.
+///
+/// Usage: (assert <condition> [<message>])
+///   - <condition>: Any value; the assertion holds if it is truthy
+///   - <message>: String, evaluated only when the assertion fails
+///
+///   Returns: Nil. Fails with an `assertion_failed` error quoting the condition's
+///   source. With assertions turned off, returns nil without evaluating anything.
+///
+/// Example:
+///   (assert (gte? (get player.hp) 0) "hp went negative")
+pub const ATOM_ASSERT: NativeFn = |args, context, call_span| {
+    if !(1..=2).contains(&args.len()) {
+        return Err(context.arity_mismatch("1 or 2", args.len(), to_source_span(*call_span)));
+    }
+    let nil = SpannedValue {
+        value: Value::Nil,
+        span: *call_span,
+    };
+    if !context.world.read().assertions {
+        return Ok(nil);
+    }
+    if evaluate_ast_node(&args[0], context)?.value.is_truthy() {
+        return Ok(nil);
+    }
+
+    let message = match args.get(1) {
+        Some(arg) => match evaluate_ast_node(arg, context)? {
+            SpannedValue {
+                value: Value::String(message),
+                ..
+            } => Some(message),
+            other => {
+                return Err(context.type_mismatch(
+                    "String",
+                    other.value.type_name(),
+                    to_source_span(other.span),
+                ))
+            }
+        },
+        None => None,
+    };
+    let condition = &args[0];
+    let expression = context
+        .source
+        .content()
+        .get(condition.span.start..condition.span.end)
+        .map_or_else(|| condition.value.pretty(), str::to_string);
+    Err(context.report(
+        ErrorKind::AssertionFailed {
+            expression,
+            message,
+        },
+        to_source_span(condition.span),
+    ))
+};
+
+/// The code `value` stands for: the contents of a quoted value and, for a list built
+/// at runtime such as `(list '+ 1 2)`, the list of what its elements stand for.
+fn unquote_code(value: Value) -> Value {
+    match value {
+        Value::Quote(inner) => *inner,
+        Value::Cons(_) => Value::from_list(value.try_into_iter().map(unquote_code).collect()),
+        value => value,
+    }
+}
+
+/// Evaluates quoted code in the current scope, after expanding its macros.
+///
+/// Usage: (eval <code>)
+///   - <code>: Quoted code, a list built at runtime from quoted symbols and
+///     values, or a literal
+///
+///   Returns: Value of the code. Its nodes carry the span of the `eval` call, so
+///   errors in it point there.
+///
+/// Example:
+///   (eval '(+ 1 2)) ; => 3
+///   (let ((x 2)) (eval '(* x 10))) ; => 20
+///   (eval (list '+ 1 2)) ; => 3
+///
+/// # Safety
+/// Does whatever the code does. Needs the `eval` capability.
+pub const ATOM_EVAL: NativeFn = |args, context, call_span| {
+    if args.len() != 1 {
+        return Err(context.arity_mismatch("1", args.len(), to_source_span(*call_span)));
+    }
+
+    let code = unquote_code(evaluate_ast_node(&args[0], context)?.value);
+    let ast = value_to_ast(&code, *call_span).map_err(|value| {
+        context.type_mismatch("code", value.type_name(), to_source_span(args[0].span))
+    })?;
+    let expanded = context.world.read().macros.expand(ast)?;
+    evaluate_ast_node(&expanded, context)
+};
+
 // ============================================================================
 // HIGHER-ORDER OPERATIONS
 // ============================================================================
@@ -123,22 +219,22 @@ pub const ATOM_ERROR: NativeFn = |args, context, call_span| {
 ///   (apply core/str+ (list "a" "b" "c")) ; => "abc"
 pub const ATOM_APPLY: NativeFn = |args, context, call_span| {
     // 1. Validate arity
-    if args.len() < 2 {
+    let [callable, normal_args @ .., list] = args else {
         return Err(context.arity_mismatch("at least 2", args.len(), to_source_span(*call_span)));
-    }
+    };
 
     // 2. Evaluate the callable
-    let callable_sv = evaluate_ast_node(&args[0], context)?;
+    let callable_sv = evaluate_ast_node(callable, context)?;
 
     // 3. Eagerly evaluate all normal arguments
     let mut final_args = Vec::new();
-    for arg_node in &args[1..args.len() - 1] {
+    for arg_node in normal_args {
         let spanned_value = evaluate_ast_node(arg_node, context)?;
         final_args.push(spanned_value.value);
     }
 
     // 4. Evaluate the final argument, which must be a list
-    let list_sv = evaluate_ast_node(args.last().unwrap(), context)?;
+    let list_sv = evaluate_ast_node(list, context)?;
     let list_span = list_sv.span;
 
     // 5. Validate and iterate over the list argument
@@ -5,18 +5,26 @@
 //!
 //! ## Atoms Provided
 //!
-//! - **I/O Operations**: `print`, `println`, `output`
+//! - **I/O Operations**: `print`, `println`, `output`, `inspect`, `read-line`
+//! - **Logging**: `log/debug`, `log/info`, `log/warn`, `set-log-level!`
+//! - **Output Capture**: `with-output-to-string`
+//! - **Player Choices**: `choice`
 //! - **Randomness**: `rand`
+//! - **Script Arguments**: `args`
 //!
 //! ## Design Notes
 //!
 //! These atoms have side effects and may produce non-deterministic results.
 //! The PRNG used for `rand` is seedable for testing purposes.
 
+use std::{collections::VecDeque, io::Write};
+
 use crate::{
-    errors::{to_source_span, ErrorReporting},
+    atoms::{Choice, LogLevel},
+    errors::{line_and_column, to_source_span, ErrorReporting},
     prelude::*,
-    runtime::{evaluate_ast_node, SpannedValue},
+    runtime::{evaluate_ast_node, Record, SpannedResult, SpannedValue},
+    syntax::{escape_string, trailing_keyword_args},
 };
 
 // ============================================================================
@@ -24,14 +32,24 @@ use crate::{
 // ============================================================================
 
 /// OutputBuffer: collects output into a String for testing or programmatic capture.
+/// Choices are answered from a queue of indices, and written to the buffer as asked.
 pub struct EngineOutputBuffer {
     pub buffer: String,
+    pub answers: VecDeque<usize>,
 }
 
 impl EngineOutputBuffer {
     pub fn new() -> Self {
         Self {
             buffer: String::new(),
+            answers: VecDeque::new(),
+        }
+    }
+    /// A buffer that answers successive choices with `answers`, in order.
+    pub fn with_answers(answers: impl IntoIterator<Item = usize>) -> Self {
+        Self {
+            buffer: String::new(),
+            answers: answers.into_iter().collect(),
         }
     }
     pub fn as_str(&self) -> &str {
@@ -49,6 +67,11 @@ impl crate::atoms::OutputSink for EngineOutputBuffer {
     fn emit(&mut self, text: &str, _span: Option<&Span>) {
         self.buffer.push_str(text);
     }
+
+    fn choose(&mut self, choice: &Choice, _span: Option<&Span>) -> Option<usize> {
+        self.buffer.push_str(&choice.to_string());
+        self.answers.pop_front()
+    }
 }
 
 /// StdoutSink: writes output to stdout for CLI and default runner use.
@@ -56,7 +79,69 @@ pub struct EngineStdoutSink;
 
 impl crate::atoms::OutputSink for EngineStdoutSink {
     fn emit(&mut self, text: &str, _span: Option<&Span>) {
-        println!("{text}");
+        print!("{text}");
+        std::io::stdout().flush().ok();
+    }
+
+    fn supports_color(&self) -> bool {
+        atty::is(atty::Stream::Stdout)
+    }
+
+    /// Writes the message after its level, colored by level on a terminal.
+    fn log(&mut self, level: LogLevel, message: &str, span: Option<&Span>) {
+        let settings = InspectSettings {
+            color: self.supports_color(),
+            ..InspectSettings::default()
+        };
+        let color = match level {
+            LogLevel::Debug => DIM,
+            LogLevel::Info => CYAN,
+            LogLevel::Warn => YELLOW,
+        };
+        let label = settings.paint(color, &format!("[{level}]"));
+        self.emit(&format!("{label} {message}\n"), span);
+    }
+
+    /// Lists the options and reads lines from stdin until one is an option's number.
+    /// Gives up at the end of input.
+    fn choose(&mut self, choice: &Choice, _span: Option<&Span>) -> Option<usize> {
+        print!("{choice}");
+        loop {
+            print!("> ");
+            std::io::stdout().flush().ok();
+            let mut line = String::new();
+            if std::io::stdin().read_line(&mut line).ok()? == 0 {
+                println!();
+                return None;
+            }
+            match line.trim().parse::<usize>() {
+                Ok(number) if (1..=choice.options.len()).contains(&number) => {
+                    return Some(number - 1)
+                }
+                _ => println!("Enter a number from 1 to {}.", choice.options.len()),
+            }
+        }
+    }
+}
+
+/// Collects what a `with-output-to-string` body writes, passing its choices on to
+/// the sink it stands in for.
+struct CapturedOutput {
+    text: String,
+    outer: SharedOutput,
+}
+
+impl crate::atoms::OutputSink for CapturedOutput {
+    fn emit(&mut self, text: &str, _span: Option<&Span>) {
+        self.text.push_str(text);
+    }
+
+    fn choose(&mut self, choice: &Choice, span: Option<&Span>) -> Option<usize> {
+        self.outer.choose(choice, span)
+    }
+
+    fn log(&mut self, level: LogLevel, message: &str, span: Option<&Span>) {
+        self.outer.log(level, message, span);
     }
 }
 
@@ -64,7 +149,7 @@ impl crate::atoms::OutputSink for EngineStdoutSink {
 // I/O OPERATIONS
 // ============================================================================
 
-/// Prints concatenated arguments to the output sink.
+/// Prints its arguments to the output sink, separated by the world's print separator.
 ///
 /// Usage: (print <value>...)
 ///   - <value>: Any number of values to print
@@ -73,18 +158,13 @@ impl crate::atoms::OutputSink for EngineStdoutSink {
 ///
 /// Examples:
 ///   (print "hello")     ; outputs "hello"
-///   (print "hello" 123 true)  ; outputs "hello123true"
+///   (print "hello" 123 true)  ; outputs "hello123true", or "hello 123 true" with separator " "
 pub const ATOM_PRINT: NativeFn = |args, context, call_span| {
     if args.is_empty() {
         return Err(context.arity_mismatch("at least 1", 0, to_source_span(*call_span)));
     }
 
-    let mut output = String::new();
-    for arg in args {
-        let spanned_val = evaluate_ast_node(arg, context)?;
-        output.push_str(&spanned_val.value.to_string());
-    }
-
+    let output = join_printed(args, context)?;
     context.output.borrow_mut().emit(&output, Some(call_span));
     Ok(SpannedValue {
         value: Value::Nil,
@@ -92,7 +172,7 @@ pub const ATOM_PRINT: NativeFn = |args, context, call_span| {
     })
 };
 
-/// Prints concatenated arguments to the output sink with a trailing newline.
+/// Prints its arguments as `print` does, then a newline.
 ///
 /// Usage: (println <value>...)
 ///   - <value>: Any number of values to print (zero or more)
@@ -104,11 +184,7 @@ pub const ATOM_PRINT: NativeFn = |args, context, call_span| {
 ///   (println "hello" 123 true)  ; outputs "hello123true\n"
 ///   (println)  ; prints just a newline
 pub const ATOM_PRINTLN: NativeFn = |args, context, call_span| {
-    let mut output = String::new();
-    for arg in args {
-        let spanned_val = evaluate_ast_node(arg, context)?;
-        output.push_str(&spanned_val.value.to_string());
-    }
+    let mut output = join_printed(args, context)?;
     output.push('\n');
 
     context.output.borrow_mut().emit(&output, Some(call_span));
@@ -118,6 +194,15 @@ pub const ATOM_PRINTLN: NativeFn = |args, context, call_span| {
     })
 };
 
+/// The values of `args`, displayed and joined by the world's print separator.
+fn join_printed(args: &[AstNode], context: &mut EvaluationContext) -> Result<String, SutraError> {
+    let mut printed = Vec::with_capacity(args.len());
+    for arg in args {
+        printed.push(evaluate_ast_node(arg, context)?.value.to_string());
+    }
+    Ok(printed.join(&context.world.read().print_separator))
+}
+
 /// Emits output to the output sink (alias for print).
 ///
 /// Usage: (output <value>)
@@ -142,10 +227,414 @@ pub const ATOM_OUTPUT: NativeFn = |args, context, call_span| {
     })
 };
 
+/// Writes an indented, type-annotated rendering of a value, headed by the source of
+/// the expression and where it is, then returns the value unchanged.
+///
+/// Usage: (inspect <value> [:depth <n>] [:items <n>])
+///   - <value>: Any value
+///   - :depth: Nesting levels to show before eliding (default 6)
+///   - :items: Elements to show per list or map before eliding (default 50)
+///
+///   Returns: <value>. Colors the rendering when the output sink supports it.
+///
+/// Example:
+///   (inspect (list 1 "a")) ; outputs
+///   ; (list 1 "a") at story.sutra:1:10
+///   ; List(2) [
+///   ;   1 : Number
+///   ;   "a" : String
+///   ; ]
+pub const ATOM_INSPECT: NativeFn = |args, context, call_span| {
+    let (positional, options) = trailing_keyword_args(args, context)?;
+    if positional.len() != 1 {
+        return Err(context.arity_mismatch("1", positional.len(), to_source_span(*call_span)));
+    }
+
+    let mut settings = InspectSettings {
+        color: context.output.borrow_mut().supports_color(),
+        ..InspectSettings::default()
+    };
+    for option in options {
+        let limit = match evaluate_ast_node(option.value, context)?.value {
+            Value::Number(n) if n >= 0.0 => n as usize,
+            other => {
+                return Err(context.type_mismatch(
+                    "non-negative Number",
+                    other.type_name(),
+                    to_source_span(option.value.span),
+                ))
+            }
+        };
+        match option.name {
+            "depth" => settings.max_depth = limit,
+            "items" => settings.max_items = limit,
+            _ => {
+                return Err(context.invalid_operation(
+                    "inspect",
+                    &format!("unknown option ':{}'", option.name),
+                    to_source_span(option.keyword.span),
+                ))
+            }
+        }
+    }
+
+    let target = &positional[0];
+    let value = evaluate_ast_node(target, context)?.value;
+    let source = context.source.content();
+    let (line, column) = line_and_column(source, target.span.start);
+    let expression = source
+        .get(target.span.start..target.span.end)
+        .unwrap_or("?");
+    let output = format!(
+        "{} at {}:{}:{}\n{}\n",
+        settings.paint(BOLD, expression),
+        context.source.name(),
+        line,
+        column,
+        inspect_value(&value, &settings)
+    );
+
+    context.output.borrow_mut().emit(&output, Some(call_span));
+    Ok(SpannedValue {
+        value,
+        span: *call_span,
+    })
+};
+
+// ============================================================================
+// LOGGING
+// ============================================================================
+
+/// Logs its arguments at the debug level.
+///
+/// Usage: (log/debug <value>...)
+///   - <value>: Any number of values, joined as by `print`
+///
+///   Returns: Nil. Below the world's log level the message is dropped and its
+///   arguments are not evaluated.
+///
+/// Example:
+///   (log/debug "entering room " (get room))  ; outputs "[debug] entering room hall\n"
+pub const ATOM_LOG_DEBUG: NativeFn =
+    |args, context, call_span| log_at(LogLevel::Debug, args, context, call_span);
+
+/// Logs its arguments at the info level. See `log/debug`.
+///
+/// Usage: (log/info <value>...)
+pub const ATOM_LOG_INFO: NativeFn =
+    |args, context, call_span| log_at(LogLevel::Info, args, context, call_span);
+
+/// Logs its arguments at the warn level. See `log/debug`.
+///
+/// Usage: (log/warn <value>...)
+pub const ATOM_LOG_WARN: NativeFn =
+    |args, context, call_span| log_at(LogLevel::Warn, args, context, call_span);
+
+fn log_at(
+    level: LogLevel,
+    args: &[AstNode],
+    context: &mut EvaluationContext,
+    call_span: &Span,
+) -> SpannedResult {
+    if level >= context.world.read().log_level {
+        let message = join_printed(args, context)?;
+        context.output.log(level, &message, Some(call_span));
+    }
+    Ok(SpannedValue {
+        value: Value::Nil,
+        span: *call_span,
+    })
+}
+
+/// Sets the least important level of messages that `log/...` writes.
+///
+/// Usage: (set-log-level! <level>)
+///   - <level>: 'debug, 'info or 'warn, as a quoted symbol, keyword or string
+///
+///   Returns: Nil
+///
+/// Example:
+///   (set-log-level! 'warn)  ; later log/debug and log/info calls write nothing
+pub const ATOM_SET_LOG_LEVEL: NativeFn = |args, context, call_span| {
+    if args.len() != 1 {
+        return Err(context.arity_mismatch("1", args.len(), to_source_span(*call_span)));
+    }
+    let level = match evaluate_ast_node(&args[0], context)?.value {
+        Value::Symbol(name) | Value::Keyword(name) | Value::String(name) => name,
+        Value::Quote(quoted) if matches!(*quoted, Value::Symbol(_)) => quoted.to_string(),
+        other => {
+            return Err(context.type_mismatch(
+                "log level (Symbol, Keyword or String)",
+                other.type_name(),
+                to_source_span(args[0].span),
+            ))
+        }
+    };
+    let level = level.parse().map_err(|problem: String| {
+        context.invalid_operation("set-log-level!", &problem, to_source_span(args[0].span))
+    })?;
+    context.world.write().log_level = level;
+    Ok(SpannedValue {
+        value: Value::Nil,
+        span: *call_span,
+    })
+};
+
+/// Evaluates a body with its output captured instead of written.
+///
+/// Usage: (with-output-to-string <expr>...)
+///   - <expr>: Expressions evaluated in order, as by `do`
+///
+///   Returns: String of everything the body wrote; the body's value is dropped.
+///   Choices the body asks still go to the player. If the body fails, the error
+///   is returned and what it wrote is discarded.
+///
+/// Example:
+///   (with-output-to-string (print "gold: ") (println 10))  ; => "gold: 10\n"
+pub const ATOM_WITH_OUTPUT_TO_STRING: NativeFn = |args, context, call_span| {
+    let captured = Rc::new(RefCell::new(CapturedOutput {
+        text: String::new(),
+        outer: context.output.clone(),
+    }));
+    let outer = std::mem::replace(&mut context.output, SharedOutput(captured.clone()));
+    let evaluated = args
+        .iter()
+        .try_for_each(|arg| evaluate_ast_node(arg, context).map(drop));
+    context.output = outer;
+    evaluated?;
+
+    let text = std::mem::take(&mut captured.borrow_mut().text);
+    Ok(SpannedValue {
+        value: Value::String(text),
+        span: *call_span,
+    })
+};
+
+// ============================================================================
+// INSPECTION - Multi-line value rendering for `inspect`
+// ============================================================================
+
+const BOLD: &str = "1";
+const DIM: &str = "2";
+const GREEN: &str = "32";
+const YELLOW: &str = "33";
+const CYAN: &str = "36";
+
+/// How much of a value `inspect` renders, and whether to color it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InspectSettings {
+    /// Nesting levels shown before a collection is elided as `[...]`.
+    pub max_depth: usize,
+    /// Elements shown per list, map or record before the rest are counted instead.
+    pub max_items: usize,
+    /// Whether to add ANSI color codes.
+    pub color: bool,
+}
+
+impl Default for InspectSettings {
+    fn default() -> Self {
+        Self {
+            max_depth: 6,
+            max_items: 50,
+            color: false,
+        }
+    }
+}
+
+impl InspectSettings {
+    fn paint(&self, code: &str, text: &str) -> String {
+        if self.color {
+            format!("\x1b[{code}m{text}\x1b[0m")
+        } else {
+            text.to_string()
+        }
+    }
+}
+
+/// Renders `value` over multiple lines: one element per line, indented by nesting,
+/// with scalars annotated by type and collections by type and size.
+pub fn inspect_value(value: &Value, settings: &InspectSettings) -> String {
+    let mut out = String::new();
+    render(value, settings, 0, &mut out);
+    out
+}
+
+fn render(value: &Value, settings: &InspectSettings, depth: usize, out: &mut String) {
+    match value {
+        Value::Cons(_) => {
+            let items: Vec<Value> = value.clone().try_into_iter().collect();
+            let header = format!("List({})", items.len());
+            let entries = items.iter().map(|item| (None, item)).collect();
+            render_collection(&header, ("[", "]"), entries, settings, depth, out);
+        }
+        Value::Map(map) => {
+            let header = format!("Map({})", map.len());
+            let entries = map
+                .iter()
+                .map(|(key, item)| (Some(format!("\"{}\"", escape_string(key))), item))
+                .collect();
+            render_collection(&header, ("{", "}"), entries, settings, depth, out);
+        }
+        Value::Record(record) => {
+            let Record { type_name, fields } = record.as_ref();
+            let entries = fields
+                .iter()
+                .map(|(name, item)| (Some(name.clone()), item))
+                .collect();
+            render_collection(type_name, ("{", "}"), entries, settings, depth, out);
+        }
+        scalar => {
+            let (code, text) = match scalar {
+                Value::String(s) => (GREEN, format!("\"{}\"", escape_string(s))),
+                Value::Number(_) | Value::Bool(_) | Value::Nil => (YELLOW, scalar.to_string()),
+                _ => (CYAN, scalar.to_string()),
+            };
+            out.push_str(&settings.paint(code, &text));
+            out.push_str(&settings.paint(DIM, &format!(" : {}", scalar.type_name())));
+        }
+    }
+}
+
+fn render_collection(
+    header: &str,
+    (open, close): (&str, &str),
+    entries: Vec<(Option<String>, &Value)>,
+    settings: &InspectSettings,
+    depth: usize,
+    out: &mut String,
+) {
+    let len = entries.len();
+    out.push_str(&settings.paint(DIM, header));
+    if len == 0 {
+        out.push_str(&format!(" {open}{close}"));
+        return;
+    }
+    if depth >= settings.max_depth {
+        out.push_str(&format!(" {open}...{close}"));
+        return;
+    }
+
+    let indent = "  ".repeat(depth + 1);
+    out.push_str(&format!(" {open}\n"));
+    for (key, item) in entries.into_iter().take(settings.max_items) {
+        out.push_str(&indent);
+        if let Some(key) = key {
+            out.push_str(&settings.paint(CYAN, &key));
+            out.push_str(": ");
+        }
+        render(item, settings, depth + 1, out);
+        out.push('\n');
+    }
+    if len > settings.max_items {
+        let more = format!("... {} more", len - settings.max_items);
+        out.push_str(&format!("{indent}{}\n", settings.paint(DIM, &more)));
+    }
+    out.push_str(&"  ".repeat(depth));
+    out.push_str(close);
+}
+
 // ============================================================================
 // RANDOMNESS OPERATIONS
 // ============================================================================
 
+/// Asks the host to pick one of several labeled branches, then evaluates the branch
+/// picked.
+///
+/// Inside a coroutine, `choice` suspends it as `yield` would, handing over the
+/// question as a map of its `prompt` and `options` (see [`Choice::from_value`]). The
+/// host answers whenever it likes by resuming the coroutine with the index of the
+/// option picked, counting from 0, and the branch runs then. Anywhere else evaluation
+/// waits for the answer: the CLI prompts on stdin, and embedders answer through their
+/// output sink's [`OutputSink::choose`].
+///
+/// Usage: (choice [<prompt>] ((<label> <body>...) ...))
+///   - <prompt>: Optional text shown before the options
+///   - <label>: Text of an option; evaluated, so it may be interpolated
+///   - <body>: Expressions evaluated, in order, if the option is picked
+///
+///   Returns: The value of the picked branch's last expression, or nil if it is empty.
+///
+/// Example:
+///   (choice "The road forks."
+///           (("Go north" (set! room "forest"))
+///            ("Rest" (set! hp (+ (get hp) 1)))))
+///
+/// [`Choice::from_value`]: crate::atoms::Choice::from_value
+/// [`OutputSink::choose`]: crate::atoms::OutputSink::choose
+pub const ATOM_CHOICE: NativeFn = |args, context, call_span| {
+    let (prompt, branches) = match args {
+        [branches] => (None, branches),
+        [prompt, branches] => {
+            let prompt = evaluate_ast_node(prompt, context)?.value.to_string();
+            (Some(prompt), branches)
+        }
+        _ => return Err(context.arity_mismatch("1 or 2", args.len(), to_source_span(*call_span))),
+    };
+    let branches = choice_branches(branches, context)?;
+
+    let mut options = Vec::with_capacity(branches.len());
+    for (label, _) in &branches {
+        options.push(evaluate_ast_node(label, context)?.value.to_string());
+    }
+    let choice = Choice { prompt, options };
+    let picked = match context.yielder {
+        Some(yielder) => {
+            let answer = yielder.suspend(choice.to_value());
+            answer
+                .as_number()
+                .filter(|index| *index >= 0.0 && index.fract() == 0.0)
+                .map(|index| index as usize)
+        }
+        None => crate::replay::choose(&context.world, || {
+            context.output.choose(&choice, Some(call_span))
+        }),
+    };
+    let Some((_, body)) = picked.and_then(|index| branches.get(index)) else {
+        let problem = match picked {
+            Some(index) => format!("option {} of {}", index + 1, branches.len()),
+            None => "a question left unanswered".to_string(),
+        };
+        return Err(context.invalid_operation("choice", &problem, to_source_span(*call_span)));
+    };
+
+    let mut result = SpannedValue {
+        value: Value::Nil,
+        span: *call_span,
+    };
+    for expr in *body {
+        result = evaluate_ast_node(expr, context)?;
+    }
+    Ok(result)
+};
+
+/// Splits the branch list of a `choice` into each branch's label and body.
+fn choice_branches<'a>(
+    branches: &'a AstNode,
+    context: &mut EvaluationContext,
+) -> Result<Vec<(&'a AstNode, &'a [AstNode])>, SutraError> {
+    let malformed = |context: &mut EvaluationContext, node: &AstNode| {
+        Err(context.invalid_operation(
+            "choice",
+            "branches that are not a list of (label body...) clauses",
+            to_source_span(node.span),
+        ))
+    };
+    let Expr::List(items, _) = &*branches.value else {
+        return malformed(context, branches);
+    };
+    if items.is_empty() {
+        return malformed(context, branches);
+    }
+    let mut split = Vec::with_capacity(items.len());
+    for item in items {
+        match &*item.value {
+            Expr::List(parts, _) if !parts.is_empty() => split.push((&parts[0], &parts[1..])),
+            _ => return malformed(context, item),
+        }
+    }
+    Ok(split)
+}
+
 /// Generates a pseudo-random number between 0.0 (inclusive) and 1.0 (exclusive).
 ///
 /// Usage: (rand)
@@ -159,10 +648,211 @@ pub const ATOM_RAND: NativeFn = |args, context, call_span| {
     if !args.is_empty() {
         return Err(context.arity_mismatch("0", args.len(), to_source_span(*call_span)));
     }
-    let rand_val = context.world.borrow_mut().next_u32();
+    let rand_val = context.world.write().next_u32();
     let result = (rand_val as f64) / (u32::MAX as f64);
     Ok(SpannedValue {
         value: Value::Number(result),
         span: *call_span,
     })
 };
+
+/// Returns the arguments passed to the script, e.g. by `sutra run story.sutra -- a b`.
+///
+/// Usage: (args)
+///
+///   Returns: List of strings, or nil when there are none.
+///
+/// Example:
+///   (args) ; => ("a" "b")
+pub const ATOM_ARGS: NativeFn = |args, context, call_span| {
+    if !args.is_empty() {
+        return Err(context.arity_mismatch("0", args.len(), to_source_span(*call_span)));
+    }
+    let script_args = context
+        .world
+        .read()
+        .script_args
+        .iter()
+        .cloned()
+        .map(Value::String)
+        .collect();
+    Ok(SpannedValue {
+        value: Value::from_list(script_args),
+        span: *call_span,
+    })
+};
+
+/// Reads one line from standard input.
+///
+/// Usage: (read-line)
+///
+///   Returns: String without its line ending, or nil at the end of input.
+///
+/// Example:
+///   (println "Hello, " (read-line))
+pub const ATOM_READ_LINE: NativeFn = |args, context, call_span| {
+    if !args.is_empty() {
+        return Err(context.arity_mismatch("0", args.len(), to_source_span(*call_span)));
+    }
+    if context.world.read().replaying {
+        return Err(context.invalid_operation(
+            "read-line",
+            "input while a snapshot is restored",
+            to_source_span(*call_span),
+        ));
+    }
+    let line = crate::replay::read_line(&context.world, || {
+        let mut line = String::new();
+        if std::io::stdin().read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+        let trimmed = line.trim_end_matches(['\n', '\r']).len();
+        line.truncate(trimmed);
+        Ok(Some(line))
+    })
+    .map_err(|e| context.internal_error(&e.to_string(), to_source_span(*call_span)))?;
+    let value = line.map_or(Value::Nil, Value::String);
+    Ok(SpannedValue {
+        value,
+        span: *call_span,
+    })
+};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn list(items: &[Value]) -> Value {
+        Value::from_list(items.to_vec())
+    }
+
+    /// Runs `source` with choices answered from `answers`, returning its value and output.
+    fn run_with_answers(source: &str, answers: &[usize]) -> (Result<Value, SutraError>, String) {
+        let buffer = Rc::new(RefCell::new(EngineOutputBuffer::with_answers(
+            answers.iter().copied(),
+        )));
+        let pipeline = crate::cli::ExecutionPipeline::builder()
+            .with_output(SharedOutput(buffer.clone()))
+            .build()
+            .unwrap();
+        let result = pipeline.execute_for_value(source, "choice");
+        let output = buffer.borrow().buffer.clone();
+        (result, output)
+    }
+
+    #[test]
+    fn test_choice_evaluates_only_the_picked_branch() {
+        let (result, output) = run_with_answers(
+            "(choice \"Where?\" ((\"North\" (set! went 1) \"north\") (\"South\" (set! went 2) \"south\"))) (list (get went) (choice ((\"A\") (\"B\"))))",
+            &[1, 0],
+        );
+        assert_eq!(result.unwrap(), list(&[Value::Number(2.0), Value::Nil]));
+        assert_eq!(output, "Where?\n1. North\n2. South\n1. A\n2. B\n");
+    }
+
+    #[test]
+    fn test_choice_fails_without_a_valid_answer() {
+        let branches = "(choice ((\"A\" 1) (\"B\" 2)))";
+        assert!(run_with_answers(branches, &[]).0.is_err());
+        assert!(run_with_answers(branches, &[2]).0.is_err());
+        assert!(run_with_answers("(choice (\"A\" 1))", &[0]).0.is_err());
+    }
+
+    #[test]
+    fn test_captured_output_leaves_choices_to_the_player() {
+        let (result, output) = run_with_answers(
+            "(with-output-to-string (print \"before \") (choice \"Go?\" ((\"Yes\" (print \"went\")) (\"No\" (print \"stayed\")))))",
+            &[1],
+        );
+        assert_eq!(result.unwrap(), Value::String("before stayed".to_string()));
+        assert_eq!(output, "Go?\n1. Yes\n2. No\n");
+    }
+
+    #[test]
+    fn test_output_is_restored_when_a_captured_body_fails() {
+        let buffer = Rc::new(RefCell::new(EngineOutputBuffer::new()));
+        let body = "(print \"lost\") (car 1)";
+        let source = SourceContext::from_file("capture", body);
+        let mut context = EvaluationContext::new(
+            crate::atoms::build_canonical_world(),
+            SharedOutput(buffer.clone()),
+            source.clone(),
+        );
+        let args = crate::parser::parse(body, source).unwrap();
+        assert!(ATOM_WITH_OUTPUT_TO_STRING(&args, &mut context, &Span::default()).is_err());
+        context.output.emit("after", None);
+        assert_eq!(buffer.borrow().buffer, "after");
+    }
+
+    #[test]
+    fn test_sinks_hear_the_progress_of_generate() {
+        #[derive(Default)]
+        struct Progress(Vec<(String, usize, usize)>);
+        impl crate::atoms::OutputSink for Progress {
+            fn emit(&mut self, _text: &str, _span: Option<&Span>) {}
+            fn progress(&mut self, task: &str, done: usize, total: usize, _span: Option<&Span>) {
+                self.0.push((task.to_string(), done, total));
+            }
+        }
+        let sink = Rc::new(RefCell::new(Progress::default()));
+        let pipeline = crate::cli::ExecutionPipeline::builder()
+            .with_output(SharedOutput(sink.clone()))
+            .build()
+            .unwrap();
+        pipeline
+            .execute_for_value("(generate! rooms 2 (lambda (i) i))", "progress")
+            .unwrap();
+        assert_eq!(
+            sink.borrow().0,
+            [
+                ("generate! rooms".to_string(), 1, 2),
+                ("generate! rooms".to_string(), 2, 2)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_inspect_indents_nested_values_with_types() {
+        let mut map = std::collections::BTreeMap::new();
+        map.insert("hp".to_string(), Value::Number(10.0));
+        map.insert(
+            "bag".to_string(),
+            list(&[Value::String("key".to_string()), Value::Bool(true)]),
+        );
+        assert_eq!(
+            inspect_value(&Value::Map(map), &InspectSettings::default()),
+            "Map(2) {\n  \"bag\": List(2) [\n    \"key\" : String\n    true : Bool\n  ]\n  \"hp\": 10 : Number\n}"
+        );
+    }
+
+    #[test]
+    fn test_inspect_elides_past_depth_and_item_limits() {
+        let settings = InspectSettings {
+            max_depth: 1,
+            max_items: 2,
+            color: false,
+        };
+        let numbers = list(&[Value::Number(1.0), Value::Number(2.0), Value::Number(3.0)]);
+        assert_eq!(
+            inspect_value(&numbers, &settings),
+            "List(3) [\n  1 : Number\n  2 : Number\n  ... 1 more\n]"
+        );
+        let nested = list(&[numbers]);
+        assert_eq!(
+            inspect_value(&nested, &settings),
+            "List(1) [\n  List(3) [...]\n]"
+        );
+    }
+
+    #[test]
+    fn test_inspect_colors_only_when_asked() {
+        let settings = InspectSettings {
+            color: true,
+            ..InspectSettings::default()
+        };
+        assert_eq!(
+            inspect_value(&Value::Number(1.0), &settings),
+            "\x1b[33m1\x1b[0m\x1b[2m : Number\x1b[0m"
+        );
+    }
+}
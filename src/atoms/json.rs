@@ -0,0 +1,190 @@
+//! JSON interop atoms for the Sutra language.
+//!
+//! ## Atoms Provided
+//!
+//! - `json/parse`: JSON text to a value
+//! - `json/stringify`: a value to JSON text
+//!
+//! ## Design Notes
+//!
+//! JSON objects map to maps, arrays to lists, and `null` to `nil`. In the other
+//! direction, symbols and keywords become their names, paths their dotted form,
+//! string builders their text, and records objects of their fields. Functions have no JSON form and fail with a type
+//! error, as do numbers JSON cannot represent (NaN and the infinities).
+
+use std::collections::BTreeMap;
+
+use serde_json::{Map, Number};
+
+use crate::{
+    errors::{to_source_span, ErrorKind, ErrorReporting, SutraError},
+    runtime::{evaluate_ast_node, ConsRepr, EvaluationContext, NativeFn, SpannedValue, Value},
+    syntax::{trailing_keyword_args, Span},
+};
+
+/// Largest magnitude below which every whole number is an exact `f64`, so it can be
+/// written as a JSON integer.
+const MAX_EXACT_INTEGER: f64 = 9_007_199_254_740_992.0;
+
+// ============================================================================
+// CONVERSIONS
+// ============================================================================
+
+/// Converts parsed JSON to a value.
+pub fn value_from_json(json: serde_json::Value) -> Value {
+    match json {
+        serde_json::Value::Null => Value::Nil,
+        serde_json::Value::Bool(b) => Value::Bool(b),
+        serde_json::Value::Number(n) => Value::Number(n.as_f64().unwrap_or(f64::NAN)),
+        serde_json::Value::String(s) => Value::String(s),
+        serde_json::Value::Array(items) => {
+            items.into_iter().rev().fold(Value::Nil, |list, item| {
+                Value::Cons(ConsRepr::cons(value_from_json(item), list))
+            })
+        }
+        serde_json::Value::Object(fields) => Value::Map(
+            fields
+                .into_iter()
+                .map(|(key, value)| (key, value_from_json(value)))
+                .collect::<BTreeMap<_, _>>(),
+        ),
+    }
+}
+
+/// Converts a value to JSON, or returns the type name of the first part of it that
+/// has no JSON form.
+pub fn value_to_json(value: &Value) -> Result<serde_json::Value, &'static str> {
+    let json = match value {
+        Value::Nil => serde_json::Value::Null,
+        Value::Bool(b) => serde_json::Value::Bool(*b),
+        Value::Number(n) => serde_json::Value::Number(json_number(*n).ok_or("non-finite Number")?),
+        Value::String(s) | Value::Symbol(s) | Value::Keyword(s) => {
+            serde_json::Value::String(s.clone())
+        }
+        Value::Path(path) => serde_json::Value::String(path.to_string()),
+        Value::StringBuilder(builder) => serde_json::Value::String(builder.text()),
+        Value::Quote(inner) => value_to_json(inner)?,
+        Value::Cons(_) => serde_json::Value::Array(
+            value
+                .clone()
+                .try_into_iter()
+                .map(|item| value_to_json(&item))
+                .collect::<Result<_, _>>()?,
+        ),
+        Value::Map(map) => serde_json::Value::Object(json_object(map.iter())?),
+        Value::Record(record) => serde_json::Value::Object(json_object(
+            record.fields.iter().map(|(name, value)| (name, value)),
+        )?),
+        Value::Lambda(_) | Value::NativeFn(_) | Value::Coroutine(_) => {
+            return Err(value.type_name())
+        }
+    };
+    Ok(json)
+}
+
+fn json_object<'a>(
+    fields: impl Iterator<Item = (&'a String, &'a Value)>,
+) -> Result<Map<String, serde_json::Value>, &'static str> {
+    fields
+        .map(|(key, value)| Ok((key.clone(), value_to_json(value)?)))
+        .collect()
+}
+
+/// Whole numbers are written without a fractional part.
+fn json_number(n: f64) -> Option<Number> {
+    if n.fract() == 0.0 && n.abs() < MAX_EXACT_INTEGER {
+        Some(Number::from(n as i64))
+    } else {
+        Number::from_f64(n)
+    }
+}
+
+/// The error for JSON text that does not parse, located by line and column.
+fn parse_error(error: serde_json::Error, context: &EvaluationContext, span: Span) -> SutraError {
+    let location = format!(" at line {} column {}", error.line(), error.column());
+    let message = error.to_string();
+    context.report(
+        ErrorKind::InvalidJson {
+            message: message
+                .strip_suffix(&location)
+                .unwrap_or(&message)
+                .to_string(),
+            line: error.line(),
+            column: error.column(),
+        },
+        to_source_span(span),
+    )
+}
+
+// ============================================================================
+// ATOMS
+// ============================================================================
+
+/// Parses JSON text: (json/parse <string>)
+///
+/// Braces in a string literal are interpolation, so JSON written inline doubles them.
+///
+/// Example:
+///   (json/parse "{{\"hp\": 10, \"tags\": [\"npc\"]}}") ; => {hp: 10, tags: (npc)}
+pub const ATOM_JSON_PARSE: NativeFn = |args, context, call_span| {
+    if args.len() != 1 {
+        return Err(context.arity_mismatch("1", args.len(), to_source_span(*call_span)));
+    }
+    let text = evaluate_ast_node(&args[0], context)?;
+    let Value::String(source) = &text.value else {
+        return Err(context.type_mismatch(
+            "String",
+            text.value.type_name(),
+            to_source_span(text.span),
+        ));
+    };
+
+    let json = serde_json::from_str(source).map_err(|e| parse_error(e, context, args[0].span))?;
+    Ok(SpannedValue {
+        value: value_from_json(json),
+        span: *call_span,
+    })
+};
+
+/// Writes a value as JSON text: (json/stringify <value> [:pretty true])
+///
+/// Example:
+///   (json/stringify (list 1 "a" nil)) ; => "[1,\"a\",null]"
+pub const ATOM_JSON_STRINGIFY: NativeFn = |args, context, call_span| {
+    let (positional, options) = trailing_keyword_args(args, context)?;
+    if positional.len() != 1 {
+        return Err(context.arity_mismatch("1", positional.len(), to_source_span(*call_span)));
+    }
+
+    let mut pretty = false;
+    for option in options {
+        if option.name != "pretty" {
+            return Err(context.invalid_operation(
+                "json/stringify",
+                &format!("unknown option ':{}'", option.name),
+                to_source_span(option.keyword.span),
+            ));
+        }
+        pretty = evaluate_ast_node(option.value, context)?.value.is_truthy();
+    }
+
+    let value = evaluate_ast_node(&positional[0], context)?;
+    let json = value_to_json(&value.value).map_err(|unsupported| {
+        context.type_mismatch(
+            "JSON-compatible value",
+            unsupported,
+            to_source_span(value.span),
+        )
+    })?;
+    let text = if pretty {
+        serde_json::to_string_pretty(&json)
+    } else {
+        serde_json::to_string(&json)
+    }
+    .map_err(|e| context.internal_error(&e.to_string(), to_source_span(*call_span)))?;
+
+    Ok(SpannedValue {
+        value: Value::String(text),
+        span: *call_span,
+    })
+};
@@ -5,7 +5,7 @@
 //!
 //! ## Atoms Provided
 //!
-//! - **Equality**: `eq?` (aliases: `=`, `is?`)
+//! - **Equality**: `eq?` (aliases: `=`, `is?`), `equal?`, `approx=`
 //! - **Comparison**: `gt?` (`>`), `lt?` (`<`), `gte?` (`>=`), `lte?` (`<=`)
 //! - **Aliases**: `over?`, `under?`, `at-least?`, `at-most?`
 //! - **Logic**: `not`
@@ -15,13 +15,18 @@
 //! Comparison operations work primarily with numeric values and return boolean results.
 //! Multiple aliases are provided for convenience and readability.
 
+use std::sync::Arc;
+
 use crate::{
     errors::{to_source_span, ErrorReporting, SutraError},
     prelude::*,
-    runtime::{evaluate_ast_node, NativeFn, SpannedResult, SpannedValue, Value},
+    runtime::{evaluate_ast_node, ConsRepr, NativeFn, SpannedResult, SpannedValue, Value},
     syntax::AstNode,
 };
 
+/// Tolerance `approx=` uses when none is given.
+pub const DEFAULT_EPSILON: f64 = 1e-9;
+
 // ============================================================================
 // CORE HELPERS
 // ============================================================================
@@ -83,24 +88,66 @@ where
     })
 }
 
+/// Identity comparison behind `eq?`: primitives by value, shared values by pointer.
+fn identical(a: &Value, b: &Value) -> bool {
+    match (a, b) {
+        (Value::Cons(ConsRepr::Chain(a)), Value::Cons(ConsRepr::Chain(b))) => Arc::ptr_eq(a, b),
+        (Value::Lambda(a), Value::Lambda(b)) => Arc::ptr_eq(a, b),
+        (Value::Record(a), Value::Record(b)) => Arc::ptr_eq(a, b),
+        (Value::Cons(_) | Value::Map(_) | Value::Quote(_), _) => false,
+        _ => a == b,
+    }
+}
+
 // ============================================================================
 // EXPORTED ATOMS
 // ============================================================================
 
-/// Returns true if two values are equal.
+/// Returns true if the values are identical.
 ///
 /// Usage: (eq? <a> <b> ...)
 ///   - <a>, <b>, ...: Values to compare (at least 2 required)
 ///
 ///   Returns: Bool
 ///
+/// Nil, numbers, strings, booleans, symbols, keywords, paths and atoms compare by value.
+/// Lists, records and lambdas are eq? only if they are the same shared value, as when
+/// one variable is bound to another. Maps, one-element lists and quoted values are
+/// copied rather than shared, so they are never eq?. Use `equal?` to compare contents.
+///
 /// Example:
 ///   (eq? 1 1) ; => true
-///   (eq? 1 2) ; => false
-///   (eq? 1 1 1) ; => true
+///   (eq? "a" "a" "a") ; => true
+///   (eq? (list 1 2) (list 1 2)) ; => false
 pub const ATOM_EQ: NativeFn = |args, context, call_span| {
     let values = evaluate_args_with_min_arity(args, context, 2, "eq?", *call_span)?;
 
+    let result = values
+        .windows(2)
+        .all(|pair| identical(&pair[0].value, &pair[1].value));
+
+    Ok(SpannedValue {
+        value: Value::Bool(result),
+        span: *call_span,
+    })
+};
+
+/// Returns true if the values are structurally equal.
+///
+/// Usage: (equal? <a> <b> ...)
+///   - <a>, <b>, ...: Values to compare (at least 2 required)
+///
+///   Returns: Bool
+///
+/// Lists, maps, records and quoted values are equal if their contents are, recursively.
+/// Values of different types are never equal, so `(equal? 1 "1")` is false.
+///
+/// Example:
+///   (equal? (list 1 (list 2)) (list 1 (list 2))) ; => true
+///   (equal? 1 2) ; => false
+pub const ATOM_EQUAL: NativeFn = |args, context, call_span| {
+    let values = evaluate_args_with_min_arity(args, context, 2, "equal?", *call_span)?;
+
     let result = values.windows(2).all(|pair| pair[0].value == pair[1].value);
 
     Ok(SpannedValue {
@@ -109,6 +156,38 @@ pub const ATOM_EQ: NativeFn = |args, context, call_span| {
     })
 };
 
+/// Returns true if two numbers differ by at most epsilon.
+///
+/// Usage: (approx= <a> <b> [epsilon])
+///   - <a>, <b>: Numbers
+///   - [epsilon]: Non-negative tolerance; defaults to 1e-9
+///
+///   Returns: Bool
+///
+/// Example:
+///   (approx= (+ 0.1 0.2) 0.3) ; => true
+///   (approx= 1 1.05 0.1) ; => true
+pub const ATOM_APPROX_EQ: NativeFn = |args, context, call_span| {
+    if !(2..=3).contains(&args.len()) {
+        return Err(context.arity_mismatch("2 or 3", args.len(), to_source_span(*call_span)));
+    }
+    let values = evaluate_args_with_min_arity(args, context, 2, "approx=", *call_span)?;
+    let numbers = extract_numbers(&values, context)?;
+    let epsilon = numbers.get(2).copied().unwrap_or(DEFAULT_EPSILON);
+    if epsilon < 0.0 || epsilon.is_nan() {
+        return Err(context.invalid_operation(
+            "approx=",
+            "negative epsilon",
+            to_source_span(values[2].span),
+        ));
+    }
+
+    Ok(SpannedValue {
+        value: Value::Bool((numbers[0] - numbers[1]).abs() <= epsilon),
+        span: *call_span,
+    })
+};
+
 /// Returns true if a > b.
 ///
 /// Usage: (gt? <a> <b> ...)
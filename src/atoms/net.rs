@@ -0,0 +1,156 @@
+//! Network atoms, compiled in only with the `net` feature.
+//!
+//! ## Atoms Provided
+//!
+//! - `http/get`: fetches a URL
+//!
+//! ## Design Notes
+//!
+//! `http/get` needs the `net` capability, which sandboxed runs never grant. Every
+//! request is bounded: it fails once it takes longer than its timeout (and never
+//! outlives the evaluation's own time limit), or once the response body grows past
+//! its size limit. An HTTP error status is a response like any other; only failing
+//! to get a response is an error.
+
+use std::{collections::BTreeMap, io::Read, time::Duration};
+
+use crate::{
+    errors::{to_source_span, ErrorKind, ErrorReporting, SutraError},
+    runtime::{evaluate_ast_node, EvaluationContext, NativeFn, SpannedValue, Value},
+    syntax::{trailing_keyword_args, Span},
+};
+
+/// How long a request may take unless `:timeout-ms` says otherwise.
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Largest response body accepted unless `:max-bytes` says otherwise.
+pub const DEFAULT_MAX_BYTES: usize = 1 << 20;
+
+// ============================================================================
+// HELPER FUNCTIONS
+// ============================================================================
+
+fn request_error(url: &str, reason: String, context: &EvaluationContext, span: Span) -> SutraError {
+    context.report(
+        ErrorKind::RequestFailed {
+            url: url.to_string(),
+            reason,
+        },
+        to_source_span(span),
+    )
+}
+
+/// The response as a map of `status`, `headers` (lowercased names) and `body`.
+fn response_value(response: ureq::Response, max_bytes: usize) -> Result<Value, String> {
+    let status = Value::Number(f64::from(response.status()));
+    let headers = response
+        .headers_names()
+        .into_iter()
+        .filter_map(|name| {
+            let value = response.header(&name)?.to_string();
+            Some((name.to_ascii_lowercase(), Value::String(value)))
+        })
+        .collect::<BTreeMap<_, _>>();
+
+    let mut body = Vec::new();
+    response
+        .into_reader()
+        .take((max_bytes as u64).saturating_add(1))
+        .read_to_end(&mut body)
+        .map_err(|e| e.to_string())?;
+    if body.len() > max_bytes {
+        return Err(format!(
+            "the response body is larger than {max_bytes} bytes"
+        ));
+    }
+
+    Ok(Value::Map(BTreeMap::from([
+        ("status".to_string(), status),
+        ("headers".to_string(), Value::Map(headers)),
+        (
+            "body".to_string(),
+            Value::String(String::from_utf8_lossy(&body).into_owned()),
+        ),
+    ])))
+}
+
+// ============================================================================
+// ATOMS
+// ============================================================================
+
+/// Fetches a URL: (http/get <url> [:timeout-ms n] [:max-bytes n])
+///
+/// Returns a map with the numeric `status`, the response `headers` keyed by
+/// lowercase name, and the `body` as a string.
+///
+/// Example:
+///   (http/get "https://example.com/events.json" :timeout-ms 2000)
+pub const ATOM_HTTP_GET: NativeFn = |args, context, call_span| {
+    let (positional, options) = trailing_keyword_args(args, context)?;
+    if positional.len() != 1 {
+        return Err(context.arity_mismatch("1", positional.len(), to_source_span(*call_span)));
+    }
+
+    let mut timeout = DEFAULT_TIMEOUT;
+    let mut max_bytes = DEFAULT_MAX_BYTES;
+    for option in options {
+        let limit = match evaluate_ast_node(option.value, context)?.value {
+            Value::Number(n) if n >= 0.0 && n.is_finite() => n as u64,
+            other => {
+                return Err(context.type_mismatch(
+                    "finite non-negative Number",
+                    other.type_name(),
+                    to_source_span(option.value.span),
+                ))
+            }
+        };
+        match option.name {
+            "timeout-ms" => timeout = Duration::from_millis(limit),
+            "max-bytes" => max_bytes = usize::try_from(limit).unwrap_or(usize::MAX),
+            _ => {
+                return Err(context.invalid_operation(
+                    "http/get",
+                    &format!("unknown option ':{}'", option.name),
+                    to_source_span(option.keyword.span),
+                ))
+            }
+        }
+    }
+    if let Some(deadline) = &context.deadline {
+        let remaining = deadline
+            .at
+            .saturating_duration_since(std::time::Instant::now());
+        timeout = timeout.min(remaining);
+    }
+
+    let url = evaluate_ast_node(&positional[0], context)?;
+    let Value::String(url_text) = &url.value else {
+        return Err(context.type_mismatch(
+            "String",
+            url.value.type_name(),
+            to_source_span(url.span),
+        ));
+    };
+
+    let response = match ureq::get(url_text).timeout(timeout).call() {
+        Ok(response) | Err(ureq::Error::Status(_, response)) => response,
+        Err(ureq::Error::Transport(transport)) => {
+            // The transport error's own text starts with the URL.
+            let text = transport.to_string();
+            let reason = text.strip_prefix(&format!("{url_text}: ")).unwrap_or(&text);
+            return Err(request_error(
+                url_text,
+                reason.to_string(),
+                context,
+                url.span,
+            ));
+        }
+    };
+    let value = response_value(response, max_bytes)
+        .map_err(|reason| request_error(url_text, reason, context, url.span))?;
+
+    Ok(SpannedValue {
+        value,
+        span: *call_span,
+    })
+};
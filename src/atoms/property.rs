@@ -0,0 +1,746 @@
+//! Property-based testing atoms for the Sutra language.
+//!
+//! This module provides value generators and the `for-all` form, which checks a
+//! property against many seeded random inputs and shrinks any counterexample it finds.
+//!
+//! ## Atoms Provided
+//!
+//! - **Generators**: `gen/int`, `gen/number`, `gen/bool`, `gen/string`, `gen/list-of`,
+//!   `gen/one-of`, `gen/path`
+//! - **Properties**: `for-all`
+//!
+//! ## Design Notes
+//!
+//! Generators are plain data: each `gen/...` atom returns a Map descriptor such as
+//! `{gen: int, min: 0, max: 10}`. `for-all` interprets the descriptor, so generators
+//! compose like any other value. Every run draws its seed from the world PRNG unless
+//! `:seed` is given, and a failure reports the seed so the run can be reproduced.
+
+use std::collections::BTreeMap;
+
+use rand::{Rng, SeedableRng};
+use rand_xoshiro::Xoshiro256StarStar;
+
+use crate::{
+    errors::{to_source_span, ErrorReporting, SutraError},
+    prelude::*,
+    runtime::{evaluate_ast_node, NativeFn, SpannedResult, SpannedValue},
+    syntax::leading_keyword_args,
+};
+
+/// Number of cases `for-all` checks when `:runs` is not given.
+const DEFAULT_RUNS: usize = 100;
+
+/// Seeds are kept within f64's exact integer range so a printed seed can be typed back in.
+const SEED_MASK: u64 = (1 << 53) - 1;
+
+/// Upper bound on successful shrink steps, to keep pathological properties bounded.
+const MAX_SHRINK_STEPS: usize = 1000;
+
+/// Default bounds used by generators called without explicit limits.
+const DEFAULT_INT_RANGE: (i64, i64) = (-100, 100);
+const DEFAULT_MAX_LEN: usize = 10;
+
+const STRING_ALPHABET: &[u8] = b"abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ0123456789 ";
+const PATH_ALPHABET: &[u8] = b"abcdefghijklmnopqrstuvwxyz";
+
+// ============================================================================
+// GENERATOR DESCRIPTORS
+// ============================================================================
+
+/// Interpreted form of a generator descriptor map.
+#[derive(Debug, Clone)]
+enum Generator {
+    Int {
+        min: i64,
+        max: i64,
+    },
+    Number {
+        min: f64,
+        max: f64,
+    },
+    Bool,
+    String {
+        max_len: usize,
+    },
+    ListOf {
+        elem: Box<Generator>,
+        max_len: usize,
+    },
+    OneOf(Vec<Value>),
+    Path {
+        max_depth: usize,
+    },
+}
+
+/// Why a value could not be read as a generator.
+enum DescriptorError {
+    /// It is not a generator descriptor at all.
+    NotAGenerator,
+    /// It is one, but with settings no value can be drawn from.
+    Invalid(String),
+}
+
+impl Generator {
+    /// Parses a descriptor produced by one of the `gen/...` atoms, or built by hand
+    /// as a map. Settings are checked here, since a hand-built map never went through
+    /// the atom's checks.
+    fn from_value(value: &Value) -> Result<Self, DescriptorError> {
+        use DescriptorError::{Invalid, NotAGenerator};
+
+        let map = value.as_map().ok_or(NotAGenerator)?;
+        let number = |key: &str| map.get(key).and_then(Value::as_number).ok_or(NotAGenerator);
+        let length = |key: &str| number(key).map(|n| n.max(0.0) as usize);
+        let range = |min: f64, max: f64| {
+            if min <= max {
+                return Ok(());
+            }
+            Err(Invalid(format!("min {} is greater than max {}", min, max)))
+        };
+
+        let kind = map
+            .get("gen")
+            .and_then(Value::as_str)
+            .ok_or(NotAGenerator)?;
+        match kind {
+            "int" => {
+                let (min, max) = (number("min")?.ceil(), number("max")?.floor());
+                range(min, max)?;
+                Ok(Generator::Int {
+                    min: min as i64,
+                    max: max as i64,
+                })
+            }
+            "number" => {
+                let (min, max) = (number("min")?, number("max")?);
+                if !min.is_finite() || !max.is_finite() {
+                    return Err(Invalid("bounds must be finite".to_string()));
+                }
+                range(min, max)?;
+                Ok(Generator::Number { min, max })
+            }
+            "bool" => Ok(Generator::Bool),
+            "string" => Ok(Generator::String {
+                max_len: length("max-len")?,
+            }),
+            "list-of" => Ok(Generator::ListOf {
+                elem: Box::new(Generator::from_value(
+                    map.get("elem").ok_or(NotAGenerator)?,
+                )?),
+                max_len: length("max-len")?,
+            }),
+            "one-of" => {
+                let choices: Vec<Value> = map
+                    .get("choices")
+                    .ok_or(NotAGenerator)?
+                    .clone()
+                    .try_into_iter()
+                    .collect();
+                if choices.is_empty() {
+                    return Err(Invalid("one-of needs at least one choice".to_string()));
+                }
+                Ok(Generator::OneOf(choices))
+            }
+            "path" => {
+                let max_depth = length("max-depth")?;
+                if max_depth < 1 {
+                    return Err(Invalid("max-depth must be at least 1".to_string()));
+                }
+                Ok(Generator::Path { max_depth })
+            }
+            _ => Err(NotAGenerator),
+        }
+    }
+
+    /// Draws a random value.
+    fn generate(&self, rng: &mut Xoshiro256StarStar) -> Value {
+        match self {
+            Generator::Int { min, max } => Value::Number(rng.gen_range(*min..=*max) as f64),
+            Generator::Number { min, max } => Value::Number(rng.gen_range(*min..=*max)),
+            Generator::Bool => Value::Bool(rng.gen_bool(0.5)),
+            Generator::String { max_len } => {
+                let len = rng.gen_range(0..=*max_len);
+                Value::String(random_text(rng, STRING_ALPHABET, len))
+            }
+            Generator::ListOf { elem, max_len } => {
+                let len = rng.gen_range(0..=*max_len);
+                Value::from_list((0..len).map(|_| elem.generate(rng)).collect())
+            }
+            Generator::OneOf(choices) => choices[rng.gen_range(0..choices.len())].clone(),
+            Generator::Path { max_depth } => {
+                let depth = rng.gen_range(1..=*max_depth);
+                let segments = (0..depth)
+                    .map(|_| {
+                        let len = rng.gen_range(1..=6);
+                        random_text(rng, PATH_ALPHABET, len)
+                    })
+                    .collect();
+                Value::Path(Path(segments))
+            }
+        }
+    }
+
+    /// Returns simpler candidate values for a failing input, simplest first.
+    fn shrink(&self, value: &Value) -> Vec<Value> {
+        match (self, value) {
+            (Generator::Int { min, max }, Value::Number(n)) => {
+                let origin = 0.clamp(*min, *max) as f64;
+                shrink_number(*n, origin, true)
+            }
+            (Generator::Number { min, max }, Value::Number(n)) => {
+                shrink_number(*n, 0.0_f64.clamp(*min, *max), false)
+            }
+            (Generator::Bool, Value::Bool(true)) => vec![Value::Bool(false)],
+            (Generator::String { .. }, Value::String(s)) => {
+                let chars: Vec<char> = s.chars().collect();
+                shrink_sequence(&chars, |_| Vec::new())
+                    .into_iter()
+                    .map(|c| Value::String(c.into_iter().collect()))
+                    .collect()
+            }
+            (Generator::ListOf { elem, .. }, Value::Cons(_) | Value::Nil) => {
+                let items: Vec<Value> = value.clone().try_into_iter().collect();
+                shrink_sequence(&items, |item| elem.shrink(item))
+                    .into_iter()
+                    .map(Value::from_list)
+                    .collect()
+            }
+            (Generator::OneOf(choices), _) => choices
+                .iter()
+                .take_while(|choice| *choice != value)
+                .cloned()
+                .collect(),
+            (Generator::Path { .. }, Value::Path(path)) if path.0.len() > 1 => {
+                vec![Value::Path(Path(path.0[..path.0.len() - 1].to_vec()))]
+            }
+            _ => Vec::new(),
+        }
+    }
+}
+
+fn random_text(rng: &mut Xoshiro256StarStar, alphabet: &[u8], len: usize) -> String {
+    (0..len)
+        .map(|_| alphabet[rng.gen_range(0..alphabet.len())] as char)
+        .collect()
+}
+
+/// Candidates that move a number toward `origin`: the origin itself, the midpoint, one step.
+fn shrink_number(n: f64, origin: f64, integral: bool) -> Vec<Value> {
+    if n == origin {
+        return Vec::new();
+    }
+    let mut midpoint = origin + (n - origin) / 2.0;
+    if integral {
+        midpoint = midpoint.trunc();
+    }
+    let step = n - (n - origin).signum();
+
+    let mut candidates = vec![origin, midpoint];
+    if integral {
+        candidates.push(step);
+    }
+    candidates.dedup();
+    candidates
+        .into_iter()
+        .filter(|c| *c != n)
+        .map(Value::Number)
+        .collect()
+}
+
+/// Candidates for a sequence: drop halves, drop single elements, then shrink elements.
+fn shrink_sequence<T: Clone>(items: &[T], shrink_item: impl Fn(&T) -> Vec<T>) -> Vec<Vec<T>> {
+    let mut candidates = Vec::new();
+    if items.is_empty() {
+        return candidates;
+    }
+    candidates.push(Vec::new());
+    if items.len() > 1 {
+        let half = items.len() / 2;
+        candidates.push(items[half..].to_vec());
+        candidates.push(items[..half].to_vec());
+    }
+    for i in 0..items.len() {
+        let mut without = items.to_vec();
+        without.remove(i);
+        candidates.push(without);
+    }
+    for (i, item) in items.iter().enumerate() {
+        for simpler in shrink_item(item) {
+            let mut replaced = items.to_vec();
+            replaced[i] = simpler;
+            candidates.push(replaced);
+        }
+    }
+    candidates
+}
+
+// ============================================================================
+// HELPERS
+// ============================================================================
+
+fn descriptor(kind: &str, fields: Vec<(&str, Value)>) -> Value {
+    let mut map = BTreeMap::new();
+    map.insert("gen".to_string(), Value::String(kind.to_string()));
+    for (key, value) in fields {
+        map.insert(key.to_string(), value);
+    }
+    Value::Map(map)
+}
+
+/// Evaluates optional numeric arguments, falling back to defaults for missing ones.
+fn eval_numbers(
+    args: &[AstNode],
+    defaults: &[f64],
+    context: &mut EvaluationContext,
+    call_span: &Span,
+) -> Result<Vec<f64>, SutraError> {
+    if args.len() > defaults.len() {
+        return Err(context.arity_mismatch(
+            &format!("at most {}", defaults.len()),
+            args.len(),
+            to_source_span(*call_span),
+        ));
+    }
+    let mut numbers = defaults.to_vec();
+    for (slot, arg) in numbers.iter_mut().zip(args) {
+        let value = evaluate_ast_node(arg, context)?;
+        *slot = value.value.as_number().ok_or_else(|| {
+            context.type_mismatch(
+                "Number",
+                value.value.type_name(),
+                to_source_span(value.span),
+            )
+        })?;
+    }
+    Ok(numbers)
+}
+
+fn eval_generator(
+    arg: &AstNode,
+    context: &mut EvaluationContext,
+) -> Result<(Value, Generator), SutraError> {
+    let value = evaluate_ast_node(arg, context)?;
+    match Generator::from_value(&value.value) {
+        Ok(generator) => Ok((value.value, generator)),
+        Err(DescriptorError::NotAGenerator) => Err(context.type_mismatch(
+            "Generator",
+            value.value.type_name(),
+            to_source_span(value.span),
+        )),
+        Err(DescriptorError::Invalid(reason)) => {
+            Err(context.invalid_operation("generator", &reason, to_source_span(value.span)))
+        }
+    }
+}
+
+fn check_range(
+    min: f64,
+    max: f64,
+    context: &EvaluationContext,
+    call_span: &Span,
+) -> Result<(), SutraError> {
+    if min > max {
+        return Err(context.invalid_operation(
+            "generator range",
+            &format!("min {} is greater than max {}", min, max),
+            to_source_span(*call_span),
+        ));
+    }
+    Ok(())
+}
+
+fn ok(value: Value, span: &Span) -> SpannedResult {
+    Ok(SpannedValue { value, span: *span })
+}
+
+// ============================================================================
+// GENERATOR ATOMS
+// ============================================================================
+
+/// Generates integers in an inclusive range.
+///
+/// Usage: (gen/int [min] [max])
+///   - <min>, <max>: Numbers (default -100 and 100)
+///
+///   Returns: Generator. Shrinks toward 0, or the bound closest to it.
+///
+/// Example:
+///   (gen/int 0 10)
+pub const ATOM_GEN_INT: NativeFn = |args, context, call_span| {
+    let (min, max) = (DEFAULT_INT_RANGE.0 as f64, DEFAULT_INT_RANGE.1 as f64);
+    let bounds = eval_numbers(args, &[min, max], context, call_span)?;
+    check_range(bounds[0].ceil(), bounds[1].floor(), context, call_span)?;
+    let fields = vec![
+        ("min", Value::Number(bounds[0].ceil())),
+        ("max", Value::Number(bounds[1].floor())),
+    ];
+    ok(descriptor("int", fields), call_span)
+};
+
+/// Generates floating-point numbers in an inclusive range.
+///
+/// Usage: (gen/number [min] [max])
+///   - <min>, <max>: Numbers (default -100 and 100)
+///
+///   Returns: Generator
+pub const ATOM_GEN_NUMBER: NativeFn = |args, context, call_span| {
+    let (min, max) = (DEFAULT_INT_RANGE.0 as f64, DEFAULT_INT_RANGE.1 as f64);
+    let bounds = eval_numbers(args, &[min, max], context, call_span)?;
+    check_range(bounds[0], bounds[1], context, call_span)?;
+    let fields = vec![
+        ("min", Value::Number(bounds[0])),
+        ("max", Value::Number(bounds[1])),
+    ];
+    ok(descriptor("number", fields), call_span)
+};
+
+/// Generates booleans.
+///
+/// Usage: (gen/bool)
+///
+///   Returns: Generator. Shrinks toward false.
+pub const ATOM_GEN_BOOL: NativeFn = |args, context, call_span| {
+    eval_numbers(args, &[], context, call_span)?;
+    ok(descriptor("bool", Vec::new()), call_span)
+};
+
+/// Generates alphanumeric strings.
+///
+/// Usage: (gen/string [max-len])
+///   - <max-len>: Number (default 10)
+///
+///   Returns: Generator. Shrinks toward the empty string.
+pub const ATOM_GEN_STRING: NativeFn = |args, context, call_span| {
+    let limits = eval_numbers(args, &[DEFAULT_MAX_LEN as f64], context, call_span)?;
+    check_range(0.0, limits[0], context, call_span)?;
+    let fields = vec![("max-len", Value::Number(limits[0].floor()))];
+    ok(descriptor("string", fields), call_span)
+};
+
+/// Generates lists whose elements come from another generator.
+///
+/// Usage: (gen/list-of <generator> [max-len])
+///   - <generator>: Generator for the elements
+///   - <max-len>: Number (default 10)
+///
+///   Returns: Generator. Shrinks by dropping and simplifying elements.
+///
+/// Example:
+///   (gen/list-of (gen/int 0 5) 3)
+pub const ATOM_GEN_LIST_OF: NativeFn = |args, context, call_span| {
+    if args.is_empty() {
+        return Err(context.arity_mismatch("1 or 2", 0, to_source_span(*call_span)));
+    }
+    let (elem, _) = eval_generator(&args[0], context)?;
+    let limits = eval_numbers(&args[1..], &[DEFAULT_MAX_LEN as f64], context, call_span)?;
+    check_range(0.0, limits[0], context, call_span)?;
+    let fields = vec![
+        ("elem", elem),
+        ("max-len", Value::Number(limits[0].floor())),
+    ];
+    ok(descriptor("list-of", fields), call_span)
+};
+
+/// Picks one of the given values.
+///
+/// Usage: (gen/one-of <value> ...)
+///   - <value>: At least one candidate value
+///
+///   Returns: Generator. Shrinks toward earlier choices.
+pub const ATOM_GEN_ONE_OF: NativeFn = |args, context, call_span| {
+    if args.is_empty() {
+        return Err(context.arity_mismatch("at least 1", 0, to_source_span(*call_span)));
+    }
+    let mut choices = Vec::with_capacity(args.len());
+    for arg in args {
+        choices.push(evaluate_ast_node(arg, context)?.value);
+    }
+    let fields = vec![("choices", Value::from_list(choices))];
+    ok(descriptor("one-of", fields), call_span)
+};
+
+/// Generates world paths with lowercase segments.
+///
+/// Usage: (gen/path [max-depth])
+///   - <max-depth>: Number of segments at most (default 3)
+///
+///   Returns: Generator. Shrinks by dropping trailing segments.
+pub const ATOM_GEN_PATH: NativeFn = |args, context, call_span| {
+    let limits = eval_numbers(args, &[3.0], context, call_span)?;
+    check_range(1.0, limits[0], context, call_span)?;
+    let fields = vec![("max-depth", Value::Number(limits[0].floor()))];
+    ok(descriptor("path", fields), call_span)
+};
+
+// ============================================================================
+// FOR-ALL
+// ============================================================================
+
+/// Parsed `for-all` configuration.
+struct PropertySpec<'a> {
+    names: Vec<String>,
+    generators: Vec<Generator>,
+    runs: usize,
+    seed: Option<u64>,
+    body: &'a [AstNode],
+}
+
+fn parse_for_all<'a>(
+    args: &'a [AstNode],
+    context: &mut EvaluationContext,
+    call_span: &Span,
+) -> Result<PropertySpec<'a>, SutraError> {
+    let mut runs = DEFAULT_RUNS;
+    let mut seed = None;
+
+    let (options, rest) = leading_keyword_args(args, context)?;
+    for option in options {
+        let value = eval_numbers(
+            std::slice::from_ref(option.value),
+            &[0.0],
+            context,
+            call_span,
+        )?[0];
+        match option.name {
+            "runs" => runs = value.max(1.0) as usize,
+            "seed" => seed = Some(value as u64),
+            _ => {
+                return Err(context.invalid_operation(
+                    "for-all",
+                    &format!("unknown option ':{}'", option.name),
+                    to_source_span(option.keyword.span),
+                ))
+            }
+        }
+    }
+    let args = rest;
+
+    if args.len() < 2 {
+        return Err(context.arity_mismatch(
+            "bindings and at least 1 body expression",
+            args.len(),
+            to_source_span(*call_span),
+        ));
+    }
+
+    let Expr::List(bindings, _) = &*args[0].value else {
+        return Err(context.invalid_operation(
+            "for-all",
+            "bindings must be a list of (name generator) pairs",
+            to_source_span(args[0].span),
+        ));
+    };
+
+    let mut names = Vec::with_capacity(bindings.len());
+    let mut generators = Vec::with_capacity(bindings.len());
+    for binding in bindings {
+        let (name, generator_node) = match &*binding.value {
+            Expr::List(pair, _) if pair.len() == 2 => match &*pair[0].value {
+                Expr::Symbol(name, _) => (name.clone(), &pair[1]),
+                _ => {
+                    return Err(context.type_mismatch(
+                        "Symbol",
+                        pair[0].value.type_name(),
+                        to_source_span(pair[0].span),
+                    ))
+                }
+            },
+            _ => {
+                return Err(context.invalid_operation(
+                    "for-all",
+                    "binding must be a (name generator) pair",
+                    to_source_span(binding.span),
+                ))
+            }
+        };
+        names.push(name);
+        generators.push(eval_generator(generator_node, context)?.1);
+    }
+
+    Ok(PropertySpec {
+        names,
+        generators,
+        runs,
+        seed,
+        body: &args[1..],
+    })
+}
+
+/// Outcome of checking a property against one input tuple.
+enum Verdict {
+    Holds,
+    Falsified(String),
+}
+
+/// Checks the property against `inputs`. A failed assertion or property falsifies it;
+/// any other error, such as a timeout, is returned as it is.
+fn check_property(
+    spec: &PropertySpec,
+    inputs: &[Value],
+    context: &mut EvaluationContext,
+) -> Result<Verdict, SutraError> {
+    let mut frame = context.with_new_frame();
+    for (name, value) in spec.names.iter().zip(inputs) {
+        frame.set_var(name, value.clone());
+    }
+
+    let mut result = Value::Nil;
+    for expr in spec.body {
+        match evaluate_ast_node(expr, &mut frame) {
+            Ok(value) => result = value.value,
+            Err(error) if falsifies(&error) => return Ok(Verdict::Falsified(error.to_string())),
+            Err(error) => return Err(error),
+        }
+    }
+
+    Ok(if result.is_truthy() {
+        Verdict::Holds
+    } else {
+        Verdict::Falsified(format!("property returned {}", result))
+    })
+}
+
+fn falsifies(error: &SutraError) -> bool {
+    matches!(
+        error.kind,
+        ErrorKind::AssertionFailed { .. }
+            | ErrorKind::AssertionFailure { .. }
+            | ErrorKind::PropertyFalsified { .. }
+    )
+}
+
+/// Greedily replaces inputs with simpler candidates while the property still fails.
+fn shrink_inputs(
+    spec: &PropertySpec,
+    mut inputs: Vec<Value>,
+    mut reason: String,
+    context: &mut EvaluationContext,
+) -> Result<(Vec<Value>, String, usize), SutraError> {
+    let mut steps = 0;
+    while steps < MAX_SHRINK_STEPS {
+        let Some((simpler, why)) = find_simpler_failure(spec, &inputs, context)? else {
+            break;
+        };
+        inputs = simpler;
+        reason = why;
+        steps += 1;
+    }
+    Ok((inputs, reason, steps))
+}
+
+/// Returns the first single-input simplification that still falsifies the property.
+fn find_simpler_failure(
+    spec: &PropertySpec,
+    inputs: &[Value],
+    context: &mut EvaluationContext,
+) -> Result<Option<(Vec<Value>, String)>, SutraError> {
+    for (i, generator) in spec.generators.iter().enumerate() {
+        for candidate in generator.shrink(&inputs[i]) {
+            let mut attempt = inputs.to_vec();
+            attempt[i] = candidate;
+            if let Verdict::Falsified(why) = check_property(spec, &attempt, context)? {
+                return Ok(Some((attempt, why)));
+            }
+        }
+    }
+    Ok(None)
+}
+
+fn describe_inputs(names: &[String], inputs: &[Value]) -> String {
+    names
+        .iter()
+        .zip(inputs)
+        .map(|(name, value)| match value {
+            Value::String(s) => format!("{} = {:?}", name, s),
+            _ => format!("{} = {}", name, value),
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Checks that a property holds for many randomly generated inputs.
+///
+/// Usage: (for-all [:runs <n>] [:seed <n>] ((<name> <generator>) ...) <body>...)
+///   - <n> (runs): Number of cases to check (default 100)
+///   - <n> (seed): Seed for reproducing a previous run (default drawn from the world PRNG)
+///   - <name>: Symbol bound to each generated value in <body>
+///   - <generator>: Generator from one of the `gen/...` atoms
+///   - <body>: Property; the last expression must be truthy, and any `assert` in it
+///     must hold
+///
+///   Returns: true if every case holds. Otherwise fails with a `property_falsified`
+///   error giving the shrunk counterexample and the seed needed to reproduce it. Any
+///   other error in <body>, such as a timeout, is passed on as it is.
+///
+/// Example:
+///   (for-all ((x (gen/int)) (y (gen/int))) (eq? (+ x y) (+ y x))) ; => true
+pub const ATOM_FOR_ALL: NativeFn = |args, context, call_span| {
+    let spec = parse_for_all(args, context, call_span)?;
+    let seed = match spec.seed {
+        Some(seed) => seed,
+        None => {
+            let mut world = context.world.write();
+            ((u64::from(world.next_u32()) << 32) | u64::from(world.next_u32())) & SEED_MASK
+        }
+    };
+    let mut rng = Xoshiro256StarStar::seed_from_u64(seed);
+
+    for case in 1..=spec.runs {
+        let inputs: Vec<Value> = spec
+            .generators
+            .iter()
+            .map(|g| g.generate(&mut rng))
+            .collect();
+        let Verdict::Falsified(reason) = check_property(&spec, &inputs, context)? else {
+            continue;
+        };
+
+        let (shrunk, reason, shrinks) = shrink_inputs(&spec, inputs, reason, context)?;
+        let mut error = context.report(
+            ErrorKind::PropertyFalsified {
+                case,
+                counterexample: describe_inputs(&spec.names, &shrunk),
+                shrinks,
+                seed,
+                reason,
+            },
+            to_source_span(*call_span),
+        );
+        error.diagnostic_info.help =
+            Some(format!("Reproduce this failure with (for-all :seed {} ...)", seed).into());
+        return Err(error);
+    }
+
+    ok(Value::Bool(true), call_span)
+};
+
+#[cfg(test)]
+mod tests {
+    use crate::cli::ExecutionPipeline;
+
+    /// The message `for-all` fails with for `source`.
+    fn falsified(source: &str) -> String {
+        let pipeline = ExecutionPipeline::default();
+        match pipeline.execute_for_value(source, "property") {
+            Ok(value) => panic!("expected the property to fail, got {value}"),
+            Err(error) => error.to_string(),
+        }
+    }
+
+    #[test]
+    fn same_seed_gives_the_same_counterexample() {
+        let source = "(for-all :seed 7 ((x (gen/int 0 100))) (lt? x 50))";
+        let first = falsified(source);
+        assert!(first.contains("seed 7"), "{first}");
+        assert_eq!(falsified(source), first);
+    }
+
+    #[test]
+    fn failures_shrink_to_the_simplest_counterexample() {
+        let message = falsified("(for-all :seed 7 ((x (gen/int 0 100))) (lt? x 50))");
+        assert!(message.contains("with x = 50 ("), "{message}");
+        let message =
+            falsified("(for-all :seed 7 ((xs (gen/list-of (gen/int 0 10) 8))) (lt? (len xs) 3))");
+        assert!(message.contains("with xs = (0 0 0) ("), "{message}");
+    }
+}
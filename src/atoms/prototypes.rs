@@ -0,0 +1,238 @@
+//! Entity prototypes for the Sutra language.
+//!
+//! ## Atoms Provided
+//!
+//! - `defproto`: declares a prototype, optionally inheriting from another
+//! - `spawn!`: writes an instance of a prototype to a world path
+//! - `proto-of`: the prototype of an instance, or the parent of a prototype
+//!
+//! ## Design Notes
+//!
+//! Prototypes hold the defaults many entities share:
+//!
+//! ```text
+//! (defproto goblin (hp 10) (mood "grumpy"))
+//! (defproto goblin-chief goblin (hp 20))
+//! (spawn! npcs.g1 goblin (hp 12))
+//! (get npcs.g1.mood) ; => "grumpy"
+//! ```
+//!
+//! A prototype's fields are evaluated once, when it is declared. `spawn!` writes a map
+//! of every field along the prototype chain, nearer prototypes and then the `spawn!`
+//! overrides taking precedence, plus a `proto` field naming the prototype. Instances
+//! are ordinary world maps, so reads, diffs and snapshots need nothing special;
+//! redeclaring a prototype changes later spawns, not existing instances.
+
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    atoms::{world::resolve_path, WorldHandle},
+    errors::{to_source_span, ErrorKind, ErrorReporting, SutraError},
+    runtime::{evaluate_ast_node, EvaluationContext, NativeFn, SpannedValue, Value},
+    syntax::{AstNode, Expr},
+};
+
+/// The field of an instance that names its prototype.
+pub const PROTO_FIELD: &str = "proto";
+
+/// Defaults shared by the instances `spawn!` creates.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Prototype {
+    /// The prototype this one inherits fields from.
+    pub parent: Option<String>,
+    pub fields: BTreeMap<String, Value>,
+}
+
+// ============================================================================
+// HELPER FUNCTIONS
+// ============================================================================
+
+fn symbol_name(node: &AstNode) -> Option<&str> {
+    match &*node.value {
+        Expr::Symbol(name, _) => Some(name),
+        _ => None,
+    }
+}
+
+/// Evaluates `(field value)` clauses into field names and values.
+fn field_clauses(
+    clauses: &[AstNode],
+    operation: &str,
+    context: &mut EvaluationContext,
+) -> Result<BTreeMap<String, Value>, SutraError> {
+    let mut fields = BTreeMap::new();
+    for clause in clauses {
+        let (field, value) = match &*clause.value {
+            Expr::List(items, _) if items.len() == 2 => match symbol_name(&items[0]) {
+                Some(field) => (field, &items[1]),
+                None => {
+                    return Err(context.type_mismatch(
+                        "field name symbol",
+                        items[0].value.type_name(),
+                        to_source_span(items[0].span),
+                    ))
+                }
+            },
+            _ => {
+                return Err(context.invalid_operation(
+                    operation,
+                    "a field that is not a (name value) clause",
+                    to_source_span(clause.span),
+                ))
+            }
+        };
+        if field == PROTO_FIELD {
+            return Err(context.invalid_operation(
+                operation,
+                "a field named 'proto', which holds the prototype's name",
+                to_source_span(clause.span),
+            ));
+        }
+        let value = evaluate_ast_node(value, context)?.value;
+        fields.insert(field.to_string(), value);
+    }
+    Ok(fields)
+}
+
+fn unknown_prototype(
+    operation: &str,
+    node: &AstNode,
+    name: &str,
+    context: &mut EvaluationContext,
+) -> SutraError {
+    context.invalid_operation(
+        operation,
+        &format!("unknown prototype '{name}'"),
+        to_source_span(node.span),
+    )
+}
+
+// ============================================================================
+// ATOMS
+// ============================================================================
+
+/// Declares a prototype: (defproto <name> [<parent>] (<field> <value>)...)
+/// Returns the prototype's name.
+///
+/// Example:
+///   (defproto goblin (hp 10) (mood "grumpy"))
+///   (defproto goblin-chief goblin (hp 20))
+pub const ATOM_DEFPROTO: NativeFn = |args, context, call_span| {
+    let Some(name_node) = args.first() else {
+        return Err(context.arity_mismatch("at least 1", 0, to_source_span(*call_span)));
+    };
+    let Some(name) = symbol_name(name_node) else {
+        return Err(context.type_mismatch(
+            "prototype name symbol",
+            name_node.value.type_name(),
+            to_source_span(name_node.span),
+        ));
+    };
+
+    let (parent, clauses) = match args.get(1).and_then(symbol_name) {
+        Some(parent) => (Some(parent), &args[2..]),
+        None => (None, &args[1..]),
+    };
+    if let Some(parent) = parent {
+        let chain = context.world.read().prototype_chain(parent);
+        if chain.is_empty() {
+            return Err(unknown_prototype("defproto", &args[1], parent, context));
+        }
+        if chain.iter().any(|ancestor| ancestor == name) {
+            return Err(context.invalid_operation(
+                "defproto",
+                &format!("a prototype '{name}' that inherits from itself"),
+                to_source_span(args[1].span),
+            ));
+        }
+    }
+    let fields = field_clauses(clauses, "defproto", context)?;
+
+    context.world.write().prototypes.insert(
+        name.to_string(),
+        Prototype {
+            parent: parent.map(str::to_string),
+            fields,
+        },
+    );
+    Ok(SpannedValue {
+        value: Value::Symbol(name.to_string()),
+        span: *call_span,
+    })
+};
+
+/// Writes an instance of a prototype to a world path, with any fields overridden:
+/// (spawn! <path> <prototype> (<field> <value>)...)
+/// Returns the instance.
+///
+/// Example:
+///   (spawn! npcs.g1 goblin (hp 12)) ; => {hp: 12, mood: "grumpy", proto: goblin}
+pub const ATOM_SPAWN: NativeFn = |args, context, call_span| {
+    if args.len() < 2 {
+        return Err(context.arity_mismatch("at least 2", args.len(), to_source_span(*call_span)));
+    }
+    let path = resolve_path(&args[0], context)?;
+    let Some(name) = symbol_name(&args[1]) else {
+        return Err(context.type_mismatch(
+            "prototype name symbol",
+            args[1].value.type_name(),
+            to_source_span(args[1].span),
+        ));
+    };
+    let Some(mut fields) = context.world.read().prototype_fields(name) else {
+        return Err(unknown_prototype("spawn!", &args[1], name, context));
+    };
+    fields.extend(field_clauses(&args[2..], "spawn!", context)?);
+    fields.insert(PROTO_FIELD.to_string(), Value::Symbol(name.to_string()));
+    let instance = Value::Map(fields);
+
+    let result = {
+        let mut world = context.world.write();
+        let policy = world.path_policy;
+        world.set_with_policy(&path, instance.clone(), policy)
+    };
+    if let Err(parent) = result {
+        return Err(context.report(
+            ErrorKind::MissingParent {
+                path: path.to_string(),
+                parent: parent.to_string(),
+            },
+            to_source_span(args[0].span),
+        ));
+    }
+    Ok(SpannedValue {
+        value: instance,
+        span: *call_span,
+    })
+};
+
+/// The prototype an instance was spawned from, or the parent of a prototype; nil if
+/// there is none: (proto-of <path>)
+///
+/// Example:
+///   (proto-of npcs.g1)     ; => goblin
+///   (proto-of goblin-chief) ; => goblin
+pub const ATOM_PROTO_OF: NativeFn = |args, context, call_span| {
+    if args.len() != 1 {
+        return Err(context.arity_mismatch("1", args.len(), to_source_span(*call_span)));
+    }
+    let path = resolve_path(&args[0], context)?;
+    let world = context.world.read();
+    let value = match world.get(&path) {
+        Some(Value::Map(fields)) if fields.contains_key(PROTO_FIELD) => fields[PROTO_FIELD].clone(),
+        _ => match path.0.as_slice() {
+            [name] => world
+                .prototypes
+                .get(name)
+                .and_then(|prototype| prototype.parent.clone())
+                .map_or(Value::Nil, Value::Symbol),
+            _ => Value::Nil,
+        },
+    };
+    Ok(SpannedValue {
+        value,
+        span: *call_span,
+    })
+};
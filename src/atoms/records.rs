@@ -0,0 +1,275 @@
+//! Record definitions for the Sutra language.
+//!
+//! `(defrecord npc (name hp mood))` declares a record type and defines, at the
+//! current scope:
+//! - `make-npc`: constructor taking one argument per field, in order
+//! - `npc?`: predicate that is true only for `npc` records
+//! - `npc-name`, `npc-hp`, `npc-mood`: field accessors
+//!
+//! The generated functions are ordinary lambdas whose bodies call the
+//! `record/make`, `record/is?` and `record/get` atoms defined here.
+
+use std::{collections::HashMap, sync::Arc};
+
+use smallvec::smallvec;
+
+use crate::{
+    errors::{to_source_span, ErrorKind, ErrorReporting, SutraError},
+    runtime::{
+        evaluate_ast_node, EvaluationContext, Lambda, NativeFn, Record, SpannedValue, Value,
+    },
+    syntax::{AstNode, Expr, ParamList, Span},
+};
+
+// ============================================================================
+// HELPER FUNCTIONS
+// ============================================================================
+
+fn node(expr: Expr, span: Span) -> AstNode {
+    AstNode {
+        value: Arc::new(expr),
+        span,
+    }
+}
+
+fn symbol(name: &str, span: Span) -> AstNode {
+    node(Expr::Symbol(name.to_string(), span), span)
+}
+
+fn string(text: &str, span: Span) -> AstNode {
+    node(Expr::String(text.to_string(), span, true), span)
+}
+
+fn call(head: &str, args: Vec<AstNode>, span: Span) -> AstNode {
+    let mut items = smallvec![symbol(head, span)];
+    items.extend(args);
+    node(Expr::List(items, span), span)
+}
+
+/// Builds a lambda with the given parameters and body and no captured environment.
+fn generated_lambda(params: &[&str], body: AstNode, span: Span) -> Value {
+    Value::Lambda(Arc::new(Lambda {
+        params: ParamList {
+            required: params.iter().map(|p| p.to_string()).collect(),
+            rest: None,
+            span,
+        },
+        body: Box::new(body),
+        captured_env: HashMap::new(),
+    }))
+}
+
+/// Extracts the symbol name from an unevaluated node, or reports what was found instead.
+fn expect_symbol(
+    node: &AstNode,
+    what: &str,
+    context: &mut EvaluationContext,
+) -> Result<String, SutraError> {
+    match &*node.value {
+        Expr::Symbol(name, _) => Ok(name.clone()),
+        _ => Err(context.type_mismatch(what, "non-symbol", to_source_span(node.span))),
+    }
+}
+
+/// Evaluates an argument that must be a string, such as a record or field name.
+fn eval_string(arg: &AstNode, context: &mut EvaluationContext) -> Result<String, SutraError> {
+    let value = evaluate_ast_node(arg, context)?;
+    match value.value {
+        Value::String(s) => Ok(s),
+        other => {
+            Err(context.type_mismatch("String", other.type_name(), to_source_span(value.span)))
+        }
+    }
+}
+
+fn ok(value: Value, span: Span) -> Result<SpannedValue, SutraError> {
+    Ok(SpannedValue { value, span })
+}
+
+// ============================================================================
+// RECORD DEFINITION
+// ============================================================================
+
+/// Declares a record type and defines its constructor, predicate and accessors.
+///
+/// Usage: (defrecord <name> (<field> ...))
+///   - name: Symbol naming the record type
+///   - fields: List of field-name symbols, in constructor argument order
+///
+/// Returns: Symbol naming the record type
+///
+/// Example:
+///   (defrecord npc (name hp))
+///   (npc-hp (make-npc "Ada" 10)) ; => 10
+pub const ATOM_DEFRECORD: NativeFn = |args, context, call_span| {
+    if args.len() != 2 {
+        return Err(context.arity_mismatch("2", args.len(), to_source_span(*call_span)));
+    }
+
+    let type_name = expect_symbol(&args[0], "record name symbol", context)?;
+    let Expr::List(field_nodes, _) = &*args[1].value else {
+        return Err(context.type_mismatch(
+            "list of field names",
+            "non-list",
+            to_source_span(args[1].span),
+        ));
+    };
+    let mut fields = Vec::with_capacity(field_nodes.len());
+    for field_node in field_nodes {
+        let field = expect_symbol(field_node, "field name symbol", context)?;
+        if fields.contains(&field) {
+            return Err(context.report(
+                ErrorKind::DuplicateDefinition {
+                    symbol: field,
+                    original_location: to_source_span(field_node.span),
+                },
+                to_source_span(field_node.span),
+            ));
+        }
+        fields.push(field);
+    }
+
+    let span = *call_span;
+    let field_params: Vec<&str> = fields.iter().map(String::as_str).collect();
+
+    // (record/make "npc" "name" name "hp" hp ...)
+    let mut make_args = vec![string(&type_name, span)];
+    for field in &fields {
+        make_args.push(string(field, span));
+        make_args.push(symbol(field, span));
+    }
+    let constructor = generated_lambda(&field_params, call("record/make", make_args, span), span);
+    context.define_var(&format!("make-{type_name}"), constructor);
+
+    // (record/is? "npc" value)
+    let predicate_body = call(
+        "record/is?",
+        vec![string(&type_name, span), symbol("value", span)],
+        span,
+    );
+    let predicate = generated_lambda(&["value"], predicate_body, span);
+    context.define_var(&format!("{type_name}?"), predicate);
+
+    // (record/get "npc" "name" value)
+    for field in &fields {
+        let accessor_body = call(
+            "record/get",
+            vec![
+                string(&type_name, span),
+                string(field, span),
+                symbol("value", span),
+            ],
+            span,
+        );
+        let accessor = generated_lambda(&["value"], accessor_body, span);
+        context.define_var(&format!("{type_name}-{field}"), accessor);
+    }
+
+    ok(Value::Symbol(type_name), span)
+};
+
+// ============================================================================
+// RECORD PRIMITIVES
+// ============================================================================
+
+/// Constructs a record from alternating field names and values.
+///
+/// Usage: (record/make <type> <field> <value> ...)
+///   - type: String naming the record type
+///   - field: String field name
+///   - value: Field value
+///
+/// Returns: Record
+///
+/// Example:
+///   (record/make "npc" "name" "Ada") ; => npc{name: Ada}
+pub const ATOM_RECORD_MAKE: NativeFn = |args, context, call_span| {
+    if args.is_empty() || args.len() % 2 == 0 {
+        return Err(context.arity_mismatch(
+            "type name followed by field/value pairs",
+            args.len(),
+            to_source_span(*call_span),
+        ));
+    }
+
+    let type_name = eval_string(&args[0], context)?;
+    let mut fields = Vec::with_capacity(args.len() / 2);
+    for pair in args[1..].chunks(2) {
+        let field = eval_string(&pair[0], context)?;
+        let value = evaluate_ast_node(&pair[1], context)?;
+        fields.push((field, value.value));
+    }
+
+    ok(
+        Value::Record(Arc::new(Record { type_name, fields })),
+        *call_span,
+    )
+};
+
+/// Tests whether a value is a record of the given type.
+///
+/// Usage: (record/is? <type> <value>)
+///   - type: String naming the record type
+///   - value: Any value
+///
+/// Returns: Bool
+///
+/// Example:
+///   (record/is? "npc" (make-npc "Ada" 10)) ; => true
+pub const ATOM_RECORD_IS: NativeFn = |args, context, call_span| {
+    if args.len() != 2 {
+        return Err(context.arity_mismatch("2", args.len(), to_source_span(*call_span)));
+    }
+
+    let type_name = eval_string(&args[0], context)?;
+    let value = evaluate_ast_node(&args[1], context)?;
+    let matches = matches!(&value.value, Value::Record(record) if record.type_name == type_name);
+    ok(Value::Bool(matches), *call_span)
+};
+
+/// Reads a field from a record of the given type.
+///
+/// Usage: (record/get <type> <field> <record>)
+///   - type: String naming the expected record type
+///   - field: String field name
+///   - record: Record to read from
+///
+/// Returns: The field's value. Errors if the record is of another type.
+///
+/// Example:
+///   (record/get "npc" "hp" (make-npc "Ada" 10)) ; => 10
+pub const ATOM_RECORD_GET: NativeFn = |args, context, call_span| {
+    if args.len() != 3 {
+        return Err(context.arity_mismatch("3", args.len(), to_source_span(*call_span)));
+    }
+
+    let type_name = eval_string(&args[0], context)?;
+    let field = eval_string(&args[1], context)?;
+    let value = evaluate_ast_node(&args[2], context)?;
+
+    let record = match &value.value {
+        Value::Record(record) if record.type_name == type_name => record,
+        Value::Record(record) => {
+            return Err(context.type_mismatch(
+                &type_name,
+                &record.type_name,
+                to_source_span(value.span),
+            ))
+        }
+        other => {
+            return Err(context.type_mismatch(
+                &type_name,
+                other.type_name(),
+                to_source_span(value.span),
+            ))
+        }
+    };
+
+    match record.get(&field) {
+        Some(field_value) => ok(field_value.clone(), *call_span),
+        None => Err(context.missing_element(
+            &format!("field '{field}' on {type_name}"),
+            to_source_span(*call_span),
+        )),
+    }
+};
@@ -0,0 +1,262 @@
+//! Weighted random tables for the Sutra language.
+//!
+//! ## Atoms Provided
+//!
+//! - `core/deftable`: stores a table, as written by the `deftable` macro
+//! - `roll-table`: picks an entry of a table at random by weight
+//!
+//! ## Design Notes
+//!
+//! Tables are declared with the `deftable` macro, one `(weight value)` clause per
+//! entry; an entry whose value is `(table <name>)` rolls that table instead:
+//!
+//! ```text
+//! (deftable potions (3 "healing draught") (1 "elixir"))
+//! (deftable loot (6 "gold") (3 "sword") (1 (table potions)))
+//! (roll-table loot) ; => "gold" six times in ten
+//! ```
+//!
+//! Entry values are evaluated once, when the table is declared, and each entry's
+//! weight is stored with its share of the table's total. Rolls draw from the world's
+//! seeded PRNG, so they repeat under `--seed` and are recorded by `--record`. A nested
+//! table is looked up when rolled, so tables can refer to ones declared later, but a
+//! roll that comes back to a table it is already rolling fails.
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    atoms::WorldHandle,
+    errors::{to_source_span, ErrorReporting, SutraError},
+    runtime::{evaluate_ast_node, EvaluationContext, NativeFn, SpannedValue, Value},
+    syntax::{Expr, Span},
+};
+
+/// The keyword marking an entry that rolls another table, in `core/deftable` calls.
+pub const NESTED_MARKER: &str = "table";
+
+/// A table of weighted entries, stored by `deftable`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RollTable {
+    pub entries: Vec<RollEntry>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RollEntry {
+    pub weight: f64,
+    /// The weight's share of the table's total, between 0 and 1.
+    pub chance: f64,
+    pub outcome: Outcome,
+}
+
+/// What rolling an entry gives.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum Outcome {
+    Value(Value),
+    /// A roll of the named table.
+    Table(String),
+}
+
+impl RollTable {
+    /// Builds a table from weighted outcomes, normalizing the weights. Fails if a
+    /// weight is negative or not finite, or if they add up to zero.
+    pub fn new(weighted: Vec<(f64, Outcome)>) -> Result<Self, String> {
+        if let Some((weight, _)) = weighted
+            .iter()
+            .find(|(weight, _)| !weight.is_finite() || *weight < 0.0)
+        {
+            return Err(format!("a weight of {weight}"));
+        }
+        let total: f64 = weighted.iter().map(|(weight, _)| weight).sum();
+        if total <= 0.0 {
+            return Err("no entry with a positive weight".to_string());
+        }
+        let entries = weighted
+            .into_iter()
+            .map(|(weight, outcome)| RollEntry {
+                weight,
+                chance: weight / total,
+                outcome,
+            })
+            .collect();
+        Ok(Self { entries })
+    }
+
+    /// The entry that `draw`, a number in [0, 1), falls on.
+    pub fn pick(&self, draw: f64) -> &RollEntry {
+        let mut cumulative = 0.0;
+        for entry in &self.entries {
+            cumulative += entry.chance;
+            if entry.chance > 0.0 && draw < cumulative {
+                return entry;
+            }
+        }
+        // Rounding can leave the shares summing to just under 1.
+        self.entries
+            .iter()
+            .rev()
+            .find(|entry| entry.chance > 0.0)
+            .unwrap_or(&self.entries[0])
+    }
+}
+
+// ============================================================================
+// HELPER FUNCTIONS
+// ============================================================================
+
+/// Reads one evaluated `(weight value)` or `(weight :table name)` entry.
+fn weighted_outcome(
+    entry: Value,
+    span: Span,
+    context: &mut EvaluationContext,
+) -> Result<(f64, Outcome), SutraError> {
+    let items: Vec<Value> = match entry {
+        Value::Cons(_) => entry.try_into_iter().collect(),
+        other => {
+            return Err(context.type_mismatch("List", other.type_name(), to_source_span(span)))
+        }
+    };
+    match items.as_slice() {
+        [Value::Number(weight), value] => Ok((*weight, Outcome::Value(value.clone()))),
+        [Value::Number(weight), Value::Keyword(marker), Value::String(name)]
+            if marker == NESTED_MARKER =>
+        {
+            Ok((*weight, Outcome::Table(name.clone())))
+        }
+        _ => Err(context.invalid_operation(
+            "core/deftable",
+            "an entry that is not (weight value) or (weight :table name)",
+            to_source_span(span),
+        )),
+    }
+}
+
+/// Rolls table `name`, following nested tables. `rolling` holds the tables this roll
+/// is already inside.
+fn roll(
+    name: &str,
+    rolling: &mut Vec<String>,
+    context: &mut EvaluationContext,
+    span: Span,
+) -> Result<Value, SutraError> {
+    if rolling.iter().any(|outer| outer == name) {
+        return Err(context.invalid_operation(
+            "roll-table",
+            &format!("table '{name}', which leads back to itself"),
+            to_source_span(span),
+        ));
+    }
+    let Some(table) = context.world.read().roll_tables.get(name).cloned() else {
+        return Err(context.invalid_operation(
+            "roll-table",
+            &format!("unknown table '{name}'"),
+            to_source_span(span),
+        ));
+    };
+    let draw = f64::from(context.world.write().next_u32()) / (f64::from(u32::MAX) + 1.0);
+    match &table.pick(draw).outcome {
+        Outcome::Value(value) => Ok(value.clone()),
+        Outcome::Table(nested) => {
+            rolling.push(name.to_string());
+            roll(nested, rolling, context, span)
+        }
+    }
+}
+
+// ============================================================================
+// ATOMS
+// ============================================================================
+
+/// Stores a weighted table: (core/deftable <name> (<weight> <value>)...)
+/// An entry `(<weight> :table <name>)` rolls another table. Returns the table's name.
+/// Written by the `deftable` macro rather than by hand.
+pub const ATOM_CORE_DEFTABLE: NativeFn = |args, context, call_span| {
+    if args.len() < 2 {
+        return Err(context.arity_mismatch("at least 2", args.len(), to_source_span(*call_span)));
+    }
+    let name = evaluate_ast_node(&args[0], context)?;
+    let Value::String(name) = name.value else {
+        return Err(context.type_mismatch(
+            "String",
+            name.value.type_name(),
+            to_source_span(name.span),
+        ));
+    };
+    let mut weighted = Vec::new();
+    for node in &args[1..] {
+        let entry = evaluate_ast_node(node, context)?.value;
+        weighted.push(weighted_outcome(entry, node.span, context)?);
+    }
+    let table = RollTable::new(weighted).map_err(|reason| {
+        context.invalid_operation("deftable", &reason, to_source_span(*call_span))
+    })?;
+    context
+        .world
+        .write()
+        .roll_tables
+        .insert(name.clone(), table);
+    Ok(SpannedValue {
+        value: Value::Symbol(name),
+        span: *call_span,
+    })
+};
+
+/// Rolls a weighted table declared by `deftable`: (roll-table <name>)
+///
+/// Example:
+///   (roll-table loot) ; => "gold"
+pub const ATOM_ROLL_TABLE: NativeFn = |args, context, call_span| {
+    if args.len() != 1 {
+        return Err(context.arity_mismatch("1", args.len(), to_source_span(*call_span)));
+    }
+    let Expr::Symbol(name, _) = &*args[0].value else {
+        return Err(context.type_mismatch(
+            "table name symbol",
+            args[0].value.type_name(),
+            to_source_span(args[0].span),
+        ));
+    };
+    let value = roll(name, &mut Vec::new(), context, args[0].span)?;
+    Ok(SpannedValue {
+        value,
+        span: *call_span,
+    })
+};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn table(weights: &[f64]) -> RollTable {
+        RollTable::new(
+            weights
+                .iter()
+                .map(|weight| (*weight, Outcome::Value(Value::Number(*weight))))
+                .collect(),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn normalizes_weights() {
+        let chances: Vec<f64> = table(&[3.0, 1.0])
+            .entries
+            .iter()
+            .map(|e| e.chance)
+            .collect();
+        assert_eq!(chances, vec![0.75, 0.25]);
+    }
+
+    #[test]
+    fn picks_by_cumulative_share_and_skips_zero_weights() {
+        let table = table(&[1.0, 0.0, 3.0]);
+        assert_eq!(table.pick(0.0).weight, 1.0);
+        assert_eq!(table.pick(0.25).weight, 3.0);
+        assert_eq!(table.pick(0.999_999).weight, 3.0);
+    }
+
+    #[test]
+    fn rejects_weights_that_cannot_be_rolled() {
+        assert!(RollTable::new(vec![(0.0, Outcome::Value(Value::Nil))]).is_err());
+        assert!(RollTable::new(vec![(-1.0, Outcome::Value(Value::Nil))]).is_err());
+    }
+}
@@ -3,15 +3,15 @@
 //! This module provides core language constructs with simplified, unified logic.
 
 use std::collections::HashMap;
-use std::rc::Rc;
+use std::sync::Arc;
 
 use crate::{
     errors::{to_source_span, ErrorKind, ErrorReporting},
     runtime::{
-        evaluate_ast_node, EvaluationContext, Lambda, NativeFn, SpannedResult,
+        ast_to_value, evaluate_ast_node, EvaluationContext, Lambda, NativeFn, SpannedResult,
         SpannedValue, Value,
     },
-    syntax::{AstNode, Expr, ParamList, Span},
+    syntax::{AstList, AstNode, Expr, ParamList, Span},
 };
 
 /// Creates a synthetic (do ...) expression from multiple body expressions
@@ -25,9 +25,9 @@ fn wrap_in_do(expressions: &[AstNode], span: Span) -> AstNode {
         span,
     };
 
-    let mut items = Vec::with_capacity(expressions.len() + 1);
+    let mut items = AstList::with_capacity(expressions.len() + 1);
     items.push(do_symbol);
-    items.extend_from_slice(expressions);
+    items.extend(expressions.iter().cloned());
 
     AstNode {
         value: std::sync::Arc::new(Expr::List(items, span)),
@@ -103,7 +103,7 @@ fn create_lambda(
     let body = Box::new(wrap_in_do(body_exprs, span));
     let captured_env = capture_environment(&body, &params, context);
 
-    Value::Lambda(Rc::new(Lambda {
+    Value::Lambda(Arc::new(Lambda {
         params,
         body,
         captured_env,
@@ -133,7 +133,7 @@ pub const ATOM_DEFINE: NativeFn = |args, context, call_span| {
                 ));
             }
             let value = evaluate_ast_node(&args[1], context)?;
-            context.set_var(name, value.value.clone());
+            context.define_var(name, value.value.clone());
             Ok(value)
         }
 
@@ -183,7 +183,7 @@ pub const ATOM_DEFINE: NativeFn = |args, context, call_span| {
             };
 
             let lambda = create_lambda(params, &args[1..], context, *call_span);
-            context.set_var(&name, lambda.clone());
+            context.define_var(&name, lambda.clone());
 
             Ok(SpannedValue {
                 value: lambda,
@@ -211,7 +211,7 @@ pub const ATOM_DEFINE: NativeFn = |args, context, call_span| {
             };
 
             let lambda = create_lambda(params, &args[1..], context, *call_span);
-            context.set_var(&name, lambda.clone());
+            context.define_var(&name, lambda.clone());
 
             Ok(SpannedValue {
                 value: lambda,
@@ -318,6 +318,39 @@ pub const ATOM_AND: NativeFn = |args, context, call_span| {
     Ok(result)
 };
 
+/// Type annotation, read by `sutra typecheck` and inert at runtime.
+///
+/// Usage: (: <type> <name>)
+///
+/// Returns: nil. Neither argument is evaluated.
+///
+/// Example:
+///   (: (-> Number Number Bool) can-afford?)
+pub const ATOM_TYPE_ANNOTATION: NativeFn = |_args, _context, call_span| {
+    Ok(SpannedValue {
+        value: Value::Nil,
+        span: *call_span,
+    })
+};
+
+/// Returns its argument as data, without evaluating it; the long form of `'`.
+///
+/// Usage: (quote <form>)
+///
+/// Returns: the quoted form, as `'<form>` does.
+///
+/// Example:
+///   (eval (quote (+ 1 2))) ; => 3
+pub const ATOM_QUOTE: NativeFn = |args, context, call_span| {
+    let [form] = args else {
+        return Err(context.arity_mismatch("1", args.len(), to_source_span(*call_span)));
+    };
+    Ok(SpannedValue {
+        value: Value::Quote(Box::new(ast_to_value(form))),
+        span: *call_span,
+    })
+};
+
 pub const ATOM_OR: NativeFn = |args, context, call_span| {
     let mut result = SpannedValue {
         value: Value::Bool(false),
@@ -417,6 +450,7 @@ pub fn call_lambda(
     call_span: &Span,
 ) -> SpannedResult {
     let mut new_context = context.with_new_frame();
+    new_context.depth += 1;
 
     // Restore captured environment
     for (name, value) in &lambda.captured_env {
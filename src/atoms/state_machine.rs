@@ -0,0 +1,286 @@
+//! State machines for the Sutra language.
+//!
+//! ## Atoms Provided
+//!
+//! - `core/defstate`: stores a machine, as written by the `defstate` macro
+//! - `advance!`: sends an event to a machine, firing the first transition it allows
+//!
+//! ## Design Notes
+//!
+//! Machines are declared with the `defstate` macro, which keeps each machine's current
+//! state at a world path:
+//!
+//! ```text
+//! (defstate quest quests.main
+//!   (idle (start active :when (gte? (get player.level) 2)))
+//!   (active :on-enter (println "The quest begins.") (finish done))
+//!   (done))
+//! (advance! quest start) ; => active, once the player reaches level 2
+//! (get quests.main)      ; => active
+//! ```
+//!
+//! Because the current state is ordinary world state, saves, diffs, undo and
+//! `world/hash` cover it. Declaring a machine puts it in its first state unless its
+//! path already holds one, so redeclaring a machine after loading a save keeps the
+//! saved state; no `:on-enter` hook runs then. When `advance!` fires a transition it
+//! runs the current state's `:on-exit`, writes the target state, then runs the
+//! target's `:on-enter`, so hooks see the state they run in.
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    atoms::{collections::call_function_with_values, WorldHandle},
+    errors::{to_source_span, ErrorKind, ErrorReporting, SutraError},
+    prelude::Path,
+    runtime::{evaluate_ast_node, EvaluationContext, NativeFn, SpannedValue, Value},
+    syntax::{AstNode, Expr, Span},
+};
+
+/// A machine stored by `defstate`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StateMachine {
+    /// Where the current state's name is kept.
+    pub path: Path,
+    /// The machine's states; the first is the initial one.
+    pub states: Vec<MachineState>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MachineState {
+    pub name: String,
+    pub on_enter: Option<Value>,
+    pub on_exit: Option<Value>,
+    pub transitions: Vec<Transition>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Transition {
+    pub event: String,
+    pub target: String,
+    /// A function of no arguments; the transition fires only if it returns a truthy value.
+    pub guard: Option<Value>,
+}
+
+impl StateMachine {
+    pub fn state(&self, name: &str) -> Option<&MachineState> {
+        self.states.iter().find(|state| state.name == name)
+    }
+}
+
+// ============================================================================
+// HELPER FUNCTIONS
+// ============================================================================
+
+/// Reads a state map written by the `defstate` macro.
+fn machine_state(
+    value: Value,
+    span: Span,
+    context: &mut EvaluationContext,
+) -> Result<MachineState, SutraError> {
+    let malformed = |context: &mut EvaluationContext| {
+        context.invalid_operation(
+            "core/defstate",
+            "a state that is not a map of name, hooks and transitions",
+            to_source_span(span),
+        )
+    };
+    let Value::Map(mut fields) = value else {
+        return Err(malformed(context));
+    };
+    let Some(Value::String(name)) = fields.remove("name") else {
+        return Err(malformed(context));
+    };
+    let mut transitions = Vec::new();
+    for transition in fields
+        .remove("transitions")
+        .unwrap_or_default()
+        .try_into_iter()
+    {
+        let Value::Map(mut transition) = transition else {
+            return Err(malformed(context));
+        };
+        let (Some(Value::String(event)), Some(Value::String(target))) =
+            (transition.remove("event"), transition.remove("target"))
+        else {
+            return Err(malformed(context));
+        };
+        transitions.push(Transition {
+            event,
+            target,
+            guard: transition.remove("when"),
+        });
+    }
+    Ok(MachineState {
+        name,
+        on_enter: fields.remove("on-enter"),
+        on_exit: fields.remove("on-exit"),
+        transitions,
+    })
+}
+
+/// The name of an event: a bare symbol as written, or else the symbol, keyword or
+/// string its expression evaluates to.
+fn event_name(node: &AstNode, context: &mut EvaluationContext) -> Result<String, SutraError> {
+    if let Expr::Symbol(name, _) = &*node.value {
+        return Ok(name.clone());
+    }
+    let value = evaluate_ast_node(node, context)?;
+    match value.value {
+        Value::Symbol(name) | Value::Keyword(name) | Value::String(name) => Ok(name),
+        Value::Quote(quoted) if matches!(*quoted, Value::Symbol(_)) => Ok(quoted.to_string()),
+        other => Err(context.type_mismatch(
+            "event (Symbol, Keyword or String)",
+            other.type_name(),
+            to_source_span(value.span),
+        )),
+    }
+}
+
+/// Writes `state` to the machine's path.
+fn enter(
+    machine: &StateMachine,
+    state: &str,
+    context: &mut EvaluationContext,
+    span: Span,
+) -> Result<(), SutraError> {
+    let result = {
+        let mut world = context.world.write();
+        let policy = world.path_policy;
+        world.set_with_policy(&machine.path, Value::Symbol(state.to_string()), policy)
+    };
+    result.map_err(|parent| {
+        context.report(
+            ErrorKind::MissingParent {
+                path: machine.path.to_string(),
+                parent: parent.to_string(),
+            },
+            to_source_span(span),
+        )
+    })
+}
+
+/// Calls a guard or hook.
+fn call(
+    function: &Value,
+    context: &mut EvaluationContext,
+    span: &Span,
+) -> Result<Value, SutraError> {
+    Ok(call_function_with_values(function, &[], context, span, *span)?.value)
+}
+
+// ============================================================================
+// ATOMS
+// ============================================================================
+
+/// Stores a state machine: (core/defstate <name> <path> <state-map>...)
+/// Each state map has a `name`, optional `on-enter` and `on-exit` functions, and a
+/// list of `transitions` maps with an `event`, a `target` and an optional `when`
+/// guard. Returns the machine's name. Written by the `defstate` macro rather than by
+/// hand.
+pub const ATOM_CORE_DEFSTATE: NativeFn = |args, context, call_span| {
+    if args.len() < 3 {
+        return Err(context.arity_mismatch("at least 3", args.len(), to_source_span(*call_span)));
+    }
+    let name = evaluate_ast_node(&args[0], context)?;
+    let Value::String(name) = name.value else {
+        return Err(context.type_mismatch(
+            "String",
+            name.value.type_name(),
+            to_source_span(name.span),
+        ));
+    };
+    let path = evaluate_ast_node(&args[1], context)?;
+    let Value::Path(path) = path.value else {
+        return Err(context.type_mismatch(
+            "Path",
+            path.value.type_name(),
+            to_source_span(path.span),
+        ));
+    };
+    let mut states = Vec::new();
+    for node in &args[2..] {
+        let state = evaluate_ast_node(node, context)?.value;
+        states.push(machine_state(state, node.span, context)?);
+    }
+    let machine = StateMachine { path, states };
+
+    if context.world.read().get(&machine.path).is_none() {
+        enter(&machine, &machine.states[0].name, context, *call_span)?;
+    }
+    context
+        .world
+        .write()
+        .state_machines
+        .insert(name.clone(), machine);
+    Ok(SpannedValue {
+        value: Value::Symbol(name),
+        span: *call_span,
+    })
+};
+
+/// Sends an event to a state machine: (advance! <machine> <event>)
+/// Fires the first transition from the current state on that event whose guard, if
+/// any, passes, and returns the new state; returns nil if none fires.
+///
+/// Example:
+///   (advance! quest start) ; => active
+pub const ATOM_ADVANCE: NativeFn = |args, context, call_span| {
+    if args.len() != 2 {
+        return Err(context.arity_mismatch("2", args.len(), to_source_span(*call_span)));
+    }
+    let Expr::Symbol(name, _) = &*args[0].value else {
+        return Err(context.type_mismatch(
+            "machine name symbol",
+            args[0].value.type_name(),
+            to_source_span(args[0].span),
+        ));
+    };
+    let event = event_name(&args[1], context)?;
+    let Some(machine) = context.world.read().state_machines.get(name).cloned() else {
+        return Err(context.invalid_operation(
+            "advance!",
+            &format!("unknown state machine '{name}'"),
+            to_source_span(args[0].span),
+        ));
+    };
+    let current = match context.world.read().get(&machine.path) {
+        Some(Value::Symbol(state) | Value::String(state)) => machine.state(state).cloned(),
+        _ => None,
+    };
+    let Some(current) = current else {
+        return Err(context.invalid_operation(
+            "advance!",
+            &format!(
+                "state machine '{name}', whose path {} holds none of its states",
+                machine.path
+            ),
+            to_source_span(args[0].span),
+        ));
+    };
+
+    for transition in current.transitions.iter().filter(|t| t.event == event) {
+        if let Some(guard) = &transition.guard {
+            if !call(guard, context, call_span)?.is_truthy() {
+                continue;
+            }
+        }
+        if let Some(on_exit) = &current.on_exit {
+            call(on_exit, context, call_span)?;
+        }
+        enter(&machine, &transition.target, context, *call_span)?;
+        if let Some(on_enter) = machine
+            .state(&transition.target)
+            .and_then(|target| target.on_enter.as_ref())
+        {
+            call(on_enter, context, call_span)?;
+        }
+        return Ok(SpannedValue {
+            value: Value::Symbol(transition.target.clone()),
+            span: *call_span,
+        });
+    }
+    Ok(SpannedValue {
+        value: Value::Nil,
+        span: *call_span,
+    })
+};
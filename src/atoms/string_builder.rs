@@ -0,0 +1,148 @@
+//! String builder atoms for the Sutra language.
+//!
+//! ## Atoms Provided
+//!
+//! - `str-builder`: a new, empty builder
+//! - `sb-append!`: appends values to a builder
+//! - `sb-build`: the text a builder holds
+//!
+//! ## Design Notes
+//!
+//! Each `str+` copies the string it extends, so growing a transcript a line at a time
+//! with it takes quadratic time. A builder grows in place instead. Builders are shared
+//! by reference: every copy of one sees what is appended through any other, and one
+//! is only equal to itself. A snapshot saves the text a builder holds.
+
+use std::{
+    fmt,
+    sync::{Arc, Mutex, MutexGuard, PoisonError},
+};
+
+use serde::{Deserialize, Deserializer, Serializer};
+
+use crate::{
+    errors::{to_source_span, ErrorReporting},
+    runtime::{evaluate_ast_node, NativeFn, SpannedValue, Value},
+};
+
+/// Text that grows in place, the value of `(str-builder)`.
+#[derive(Default)]
+pub struct StringBuilder {
+    text: Mutex<String>,
+}
+
+impl StringBuilder {
+    pub fn new(text: String) -> Self {
+        Self {
+            text: Mutex::new(text),
+        }
+    }
+
+    pub fn push_str(&self, s: &str) {
+        self.lock().push_str(s);
+    }
+
+    /// A copy of the text built so far.
+    pub fn text(&self) -> String {
+        self.lock().clone()
+    }
+
+    /// Length of the text in bytes.
+    pub fn len(&self) -> usize {
+        self.lock().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lock().is_empty()
+    }
+
+    fn lock(&self) -> MutexGuard<'_, String> {
+        self.text.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl fmt::Debug for StringBuilder {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("StringBuilder").field(&*self.lock()).finish()
+    }
+}
+
+/// Saves a builder as its text.
+pub(crate) fn save_builder<S: Serializer>(
+    builder: &Arc<StringBuilder>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&builder.lock())
+}
+
+pub(crate) fn load_builder<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Arc<StringBuilder>, D::Error> {
+    Ok(Arc::new(StringBuilder::new(String::deserialize(
+        deserializer,
+    )?)))
+}
+
+// ============================================================================
+// ATOMS
+// ============================================================================
+
+/// Creates an empty string builder: (str-builder)
+pub const ATOM_STR_BUILDER: NativeFn = |args, context, call_span| {
+    if !args.is_empty() {
+        return Err(context.arity_mismatch("0", args.len(), to_source_span(*call_span)));
+    }
+    Ok(SpannedValue {
+        value: Value::StringBuilder(Arc::default()),
+        span: *call_span,
+    })
+};
+
+/// Appends values to a builder as `str+` would join them, and returns the builder:
+/// (sb-append! <builder> <value>...)
+///
+/// Example:
+///   (sb-build (sb-append! (str-builder) "hp: " 10)) ; => "hp: 10"
+pub const ATOM_SB_APPEND: NativeFn = |args, context, call_span| {
+    if args.is_empty() {
+        return Err(context.arity_mismatch("at least 1", 0, to_source_span(*call_span)));
+    }
+    let target = evaluate_ast_node(&args[0], context)?;
+    let Value::StringBuilder(builder) = &target.value else {
+        return Err(context.type_mismatch(
+            "StringBuilder",
+            target.value.type_name(),
+            to_source_span(target.span),
+        ));
+    };
+    for arg in &args[1..] {
+        match evaluate_ast_node(arg, context)?.value {
+            Value::String(s) => builder.push_str(&s),
+            value => builder.push_str(&value.to_string()),
+        }
+    }
+    Ok(SpannedValue {
+        value: target.value.clone(),
+        span: *call_span,
+    })
+};
+
+/// Returns the text a builder holds: (sb-build <builder>)
+/// The builder can still be appended to afterwards.
+pub const ATOM_SB_BUILD: NativeFn = |args, context, call_span| {
+    if args.len() != 1 {
+        return Err(context.arity_mismatch("1", args.len(), to_source_span(*call_span)));
+    }
+    let target = evaluate_ast_node(&args[0], context)?;
+    let Value::StringBuilder(builder) = &target.value else {
+        return Err(context.type_mismatch(
+            "StringBuilder",
+            target.value.type_name(),
+            to_source_span(target.span),
+        ));
+    };
+    Ok(SpannedValue {
+        value: Value::String(builder.text()),
+        span: *call_span,
+    })
+};
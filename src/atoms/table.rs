@@ -0,0 +1,257 @@
+//! Table atoms for data-driven content.
+//!
+//! ## Atoms Provided
+//!
+//! - `table/load`: reads a CSV or TSV file into a list of maps
+//! - `table/get`: reads one cell of a row
+//! - `table/filter`: keeps the rows a predicate accepts
+//! - `table/index-by`: maps each row's value in one column to the row
+//!
+//! ## Design Notes
+//!
+//! The first line of a table file names its columns, and every later line becomes a
+//! map from those names to the line's cells. A cell that reads as a number becomes a
+//! number; every other cell stays a string. Files ending in `.tsv` are split on tabs,
+//! all others on commas. A relative path is resolved against the directory of the
+//! running script, or the current directory when there is no script file.
+
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    atoms::collections::call_function_with_values,
+    errors::{to_source_span, ErrorKind, ErrorReporting, SutraError},
+    runtime::{evaluate_ast_node, ConsRepr, EvaluationContext, NativeFn, SpannedValue, Value},
+    syntax::{AstNode, Span},
+};
+
+// ============================================================================
+// HELPER FUNCTIONS
+// ============================================================================
+
+fn build_list(items: Vec<Value>) -> Value {
+    items.into_iter().rev().fold(Value::Nil, |list, item| {
+        Value::Cons(ConsRepr::cons(item, list))
+    })
+}
+
+/// `path` relative to the running script's directory, if the script is a file.
+fn resolve_table_path(path: &str, context: &EvaluationContext) -> PathBuf {
+    let path = Path::new(path);
+    let script = Path::new(context.source.name());
+    match script.parent() {
+        Some(dir) if path.is_relative() && script.is_file() => dir.join(path),
+        _ => path.to_path_buf(),
+    }
+}
+
+fn cell_value(cell: &str) -> Value {
+    match cell.trim().parse::<f64>() {
+        Ok(n) if n.is_finite() && !cell.trim().is_empty() => Value::Number(n),
+        _ => Value::String(cell.to_string()),
+    }
+}
+
+/// Reads the table at `path` into one map per row.
+pub fn load_table(path: &Path) -> Result<Vec<Value>, String> {
+    let delimiter = match path.extension().and_then(|ext| ext.to_str()) {
+        Some("tsv") => b'\t',
+        _ => b',',
+    };
+    let mut reader = csv::ReaderBuilder::new()
+        .delimiter(delimiter)
+        .from_path(path)
+        .map_err(|e| e.to_string())?;
+    let headers = reader.headers().map_err(|e| e.to_string())?.clone();
+
+    let mut rows = Vec::new();
+    for record in reader.records() {
+        let record = record.map_err(|e| e.to_string())?;
+        let row: BTreeMap<String, Value> = headers
+            .iter()
+            .zip(record.iter())
+            .map(|(header, cell)| (header.to_string(), cell_value(cell)))
+            .collect();
+        rows.push(Value::Map(row));
+    }
+    Ok(rows)
+}
+
+fn expect_list(
+    val: SpannedValue,
+    context: &mut EvaluationContext,
+) -> Result<Vec<Value>, SutraError> {
+    match val.value {
+        Value::Cons(_) | Value::Nil => Ok(val.value.try_into_iter().collect()),
+        other => {
+            Err(context.type_mismatch("List or Nil", other.type_name(), to_source_span(val.span)))
+        }
+    }
+}
+
+fn require_arity(
+    args: &[AstNode],
+    expected: usize,
+    context: &mut EvaluationContext,
+    span: Span,
+) -> Result<(), SutraError> {
+    if args.len() != expected {
+        return Err(context.arity_mismatch(
+            &expected.to_string(),
+            args.len(),
+            to_source_span(span),
+        ));
+    }
+    Ok(())
+}
+
+// ============================================================================
+// ATOMS
+// ============================================================================
+
+/// Loads a CSV or TSV file as a list of maps keyed by its header row:
+/// (table/load <path>)
+///
+/// Example:
+///   (table/load "items.csv") ; => ({id: sword, price: 10} {id: shield, price: 15})
+pub const ATOM_TABLE_LOAD: NativeFn = |args, context, call_span| {
+    require_arity(args, 1, context, *call_span)?;
+    let path = evaluate_ast_node(&args[0], context)?;
+    let Value::String(name) = &path.value else {
+        return Err(context.type_mismatch(
+            "String",
+            path.value.type_name(),
+            to_source_span(path.span),
+        ));
+    };
+
+    let rows = load_table(&resolve_table_path(name, context)).map_err(|reason| {
+        context.report(
+            ErrorKind::InvalidTable {
+                path: name.clone(),
+                reason,
+            },
+            to_source_span(path.span),
+        )
+    })?;
+    Ok(SpannedValue {
+        value: build_list(rows),
+        span: *call_span,
+    })
+};
+
+/// Returns a row's value in a column, or nil if the row has no such column:
+/// (table/get <row> <column>)
+pub const ATOM_TABLE_GET: NativeFn = |args, context, call_span| {
+    require_arity(args, 2, context, *call_span)?;
+    let row = evaluate_ast_node(&args[0], context)?;
+    let Value::Map(fields) = &row.value else {
+        return Err(context.type_mismatch("Map", row.value.type_name(), to_source_span(row.span)));
+    };
+    let column = evaluate_ast_node(&args[1], context)?;
+    let Value::String(column) = &column.value else {
+        return Err(context.type_mismatch(
+            "String",
+            column.value.type_name(),
+            to_source_span(column.span),
+        ));
+    };
+
+    Ok(SpannedValue {
+        value: fields.get(column).cloned().unwrap_or_default(),
+        span: *call_span,
+    })
+};
+
+/// Keeps the rows for which a predicate returns a truthy value:
+/// (table/filter <table> <predicate>)
+///
+/// Example:
+///   (table/filter items (lambda (row) (lt? (table/get row "price") 12)))
+pub const ATOM_TABLE_FILTER: NativeFn = |args, context, call_span| {
+    require_arity(args, 2, context, *call_span)?;
+    let table = evaluate_ast_node(&args[0], context)?;
+    let table_span = table.span;
+    let rows = expect_list(table, context)?;
+    let predicate = evaluate_ast_node(&args[1], context)?;
+    if !matches!(predicate.value, Value::Lambda(_) | Value::NativeFn(_)) {
+        return Err(context.type_mismatch(
+            "Lambda or NativeFn",
+            predicate.value.type_name(),
+            to_source_span(predicate.span),
+        ));
+    }
+
+    let mut kept = Vec::new();
+    for row in rows {
+        let keep = call_function_with_values(
+            &predicate.value,
+            std::slice::from_ref(&row),
+            context,
+            call_span,
+            table_span,
+        )?;
+        if keep.value.is_truthy() {
+            kept.push(row);
+        }
+    }
+    Ok(SpannedValue {
+        value: build_list(kept),
+        span: *call_span,
+    })
+};
+
+/// Builds a map from each row's value in a column to the row:
+/// (table/index-by <table> <column>)
+/// Fails if a row lacks the column or two rows share a value in it.
+///
+/// Example:
+///   (table/index-by items "id") ; => {shield: {...}, sword: {...}}
+pub const ATOM_TABLE_INDEX_BY: NativeFn = |args, context, call_span| {
+    require_arity(args, 2, context, *call_span)?;
+    let table = evaluate_ast_node(&args[0], context)?;
+    let table_span = table.span;
+    let rows = expect_list(table, context)?;
+    let column = evaluate_ast_node(&args[1], context)?;
+    let Value::String(column) = column.value else {
+        return Err(context.type_mismatch(
+            "String",
+            column.value.type_name(),
+            to_source_span(column.span),
+        ));
+    };
+
+    let mut index = BTreeMap::new();
+    for row in rows {
+        let key = match &row {
+            Value::Map(fields) => fields.get(&column).map(Value::to_string),
+            other => {
+                return Err(context.type_mismatch(
+                    "Map",
+                    other.type_name(),
+                    to_source_span(table_span),
+                ))
+            }
+        };
+        let Some(key) = key else {
+            return Err(context.invalid_operation(
+                "table/index-by",
+                &format!("a row without column '{column}'"),
+                to_source_span(args[1].span),
+            ));
+        };
+        if index.insert(key.clone(), row).is_some() {
+            return Err(context.invalid_operation(
+                "table/index-by",
+                &format!("rows sharing the {column} '{key}'"),
+                to_source_span(args[1].span),
+            ));
+        }
+    }
+    Ok(SpannedValue {
+        value: Value::Map(index),
+        span: *call_span,
+    })
+};
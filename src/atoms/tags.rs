@@ -0,0 +1,134 @@
+//! Tag queries for the Sutra language.
+//!
+//! ## Atoms Provided
+//!
+//! - `tag!`: tags a world path
+//! - `untag!`: removes tags from a world path
+//! - `with-tag`: the paths carrying every given tag
+//!
+//! ## Design Notes
+//!
+//! Tags mark world paths as members of a group, such as everything hostile in a scene:
+//!
+//! ```text
+//! (tag! npcs.g1 'hostile 'goblin)
+//! (tag! npcs.g2 'hostile)
+//! (with-tag 'hostile)          ; => (npcs.g1 npcs.g2)
+//! (with-tag 'hostile 'goblin)  ; => (npcs.g1)
+//! ```
+//!
+//! The world state keeps an index from each tag to its paths beside the path tree, so
+//! `with-tag` reads the index rather than walking the state. A path can be tagged
+//! before it holds a value; `del!` drops the tags of the paths it deletes, and `undo!`
+//! and `redo!` restore tags with the rest of the state.
+
+use std::collections::BTreeSet;
+
+use crate::{
+    atoms::{world::resolve_path, WorldHandle},
+    errors::{to_source_span, ErrorReporting, SutraError},
+    prelude::Path,
+    runtime::{evaluate_ast_node, EvaluationContext, NativeFn, SpannedValue, Value},
+    syntax::AstNode,
+};
+
+// ============================================================================
+// HELPER FUNCTIONS
+// ============================================================================
+
+/// Evaluates `node` to a tag name: a symbol, quoted symbol, keyword or string.
+fn tag_name(node: &AstNode, context: &mut EvaluationContext) -> Result<String, SutraError> {
+    let value = evaluate_ast_node(node, context)?;
+    match value.value {
+        Value::Symbol(name) | Value::Keyword(name) | Value::String(name) => Ok(name),
+        Value::Quote(quoted) if matches!(*quoted, Value::Symbol(_)) => Ok(quoted.to_string()),
+        other => Err(context.type_mismatch(
+            "tag (Symbol, Keyword or String)",
+            other.type_name(),
+            to_source_span(value.span),
+        )),
+    }
+}
+
+/// Resolves the path and tag names of a `tag!` or `untag!` call.
+fn path_and_tags(
+    args: &[AstNode],
+    context: &mut EvaluationContext,
+    call_span: &crate::Span,
+) -> Result<(Path, Vec<String>), SutraError> {
+    if args.len() < 2 {
+        return Err(context.arity_mismatch("at least 2", args.len(), to_source_span(*call_span)));
+    }
+    let path = resolve_path(&args[0], context)?;
+    let tags = args[1..]
+        .iter()
+        .map(|node| tag_name(node, context))
+        .collect::<Result<_, _>>()?;
+    Ok((path, tags))
+}
+
+// ============================================================================
+// ATOMS
+// ============================================================================
+
+/// Tags a world path: (tag! <path> <tag>...)
+///
+/// Example:
+///   (tag! npcs.g1 'hostile 'goblin)
+pub const ATOM_TAG: NativeFn = |args, context, call_span| {
+    let (path, tags) = path_and_tags(args, context, call_span)?;
+    let mut world = context.world.write();
+    for tag in &tags {
+        world.state.tag(&path, tag);
+    }
+    Ok(SpannedValue {
+        value: Value::Nil,
+        span: *call_span,
+    })
+};
+
+/// Removes tags from a world path: (untag! <path> <tag>...)
+///
+/// Example:
+///   (untag! npcs.g1 'hostile)
+pub const ATOM_UNTAG: NativeFn = |args, context, call_span| {
+    let (path, tags) = path_and_tags(args, context, call_span)?;
+    let mut world = context.world.write();
+    for tag in &tags {
+        world.state.untag(&path, tag);
+    }
+    Ok(SpannedValue {
+        value: Value::Nil,
+        span: *call_span,
+    })
+};
+
+/// The paths carrying every given tag, in path order: (with-tag <tag>...)
+///
+/// Example:
+///   (with-tag 'hostile)          ; => (npcs.g1 npcs.g2)
+///   (with-tag 'hostile 'goblin)  ; => (npcs.g1)
+pub const ATOM_WITH_TAG: NativeFn = |args, context, call_span| {
+    if args.is_empty() {
+        return Err(context.arity_mismatch("at least 1", 0, to_source_span(*call_span)));
+    }
+    let tags = args
+        .iter()
+        .map(|node| tag_name(node, context))
+        .collect::<Result<Vec<_>, _>>()?;
+    let world = context.world.read();
+    let others: Vec<BTreeSet<&Path>> = tags[1..]
+        .iter()
+        .map(|tag| world.state.tagged(tag).collect())
+        .collect();
+    let paths = world
+        .state
+        .tagged(&tags[0])
+        .filter(|path| others.iter().all(|other| other.contains(path)))
+        .map(|path| Value::Path(path.clone()))
+        .collect();
+    Ok(SpannedValue {
+        value: Value::from_list(paths),
+        span: *call_span,
+    })
+};
@@ -5,28 +5,35 @@
 //!
 //! ## Atoms Provided
 //!
-//! - **Test Assertions**: `assert`, `assert-eq`
+//! - **Test Assertions**: `assert-eq`, `assert-world-diff`
 //! - **Test Utilities**: `test/echo`
 
 use crate::prelude::*;
 use crate::{
+    atoms::{world::resolve_path, WorldState},
     errors::{to_source_span, ErrorReporting},
     register_atom,
     runtime::{evaluate_ast_node, EvaluationContext, SpannedResult, SpannedValue},
     syntax::{AstNode, Expr, Span},
+    world_diff::{WorldChange, WorldDiff},
 };
 
-/// Simple assertion - fails if argument is not truthy
-fn assert_atom(args: &[AstNode], ctx: &mut EvaluationContext, call_span: &Span) -> SpannedResult {
-    if args.len() != 1 {
-        return Err(ctx.arity_mismatch("1", args.len(), to_source_span(*call_span)));
+/// Equality assertion - fails if arguments are not equal
+fn assert_eq_atom(
+    args: &[AstNode],
+    ctx: &mut EvaluationContext,
+    call_span: &Span,
+) -> SpannedResult {
+    if args.len() != 2 {
+        return Err(ctx.arity_mismatch("2", args.len(), to_source_span(*call_span)));
     }
-    let value_sv = evaluate_ast_node(&args[0], ctx)?;
-    if !value_sv.value.is_truthy() {
+    let expected_sv = evaluate_ast_node(&args[0], ctx)?;
+    let actual_sv = evaluate_ast_node(&args[1], ctx)?;
+    if expected_sv.value != actual_sv.value {
         return Err(ctx.invalid_operation(
-            "assertion",
-            &format!("expected truthy value, got {}", value_sv.value),
-            to_source_span(value_sv.span),
+            "assertion equality",
+            &format!("expected {}, got {}", expected_sv.value, actual_sv.value),
+            to_source_span(*call_span),
         ));
     }
     Ok(SpannedValue {
@@ -35,21 +42,38 @@ fn assert_atom(args: &[AstNode], ctx: &mut EvaluationContext, call_span: &Span)
     })
 }
 
-/// Equality assertion - fails if arguments are not equal
-fn assert_eq_atom(
+/// World diff assertion - evaluates the body, then fails unless the world state
+/// changed in exactly the listed ways:
+/// `(assert-world-diff <body> (added path value) (removed path [value]) (changed path old new) ...)`
+fn assert_world_diff_atom(
     args: &[AstNode],
     ctx: &mut EvaluationContext,
     call_span: &Span,
 ) -> SpannedResult {
-    if args.len() != 2 {
-        return Err(ctx.arity_mismatch("2", args.len(), to_source_span(*call_span)));
+    let Some((body, clauses)) = args.split_first() else {
+        return Err(ctx.arity_mismatch("at least 1", 0, to_source_span(*call_span)));
+    };
+
+    let before = ctx.world.read().state.clone();
+    evaluate_ast_node(body, ctx)?;
+    let actual = WorldDiff::between(&before, &ctx.world.read().state);
+
+    let mut expected = WorldDiff::default();
+    for clause in clauses {
+        expected
+            .changes
+            .push(expected_change(clause, &before, ctx)?);
     }
-    let expected_sv = evaluate_ast_node(&args[0], ctx)?;
-    let actual_sv = evaluate_ast_node(&args[1], ctx)?;
-    if expected_sv.value != actual_sv.value {
+    expected.changes.sort_by(|a, b| a.path().0.cmp(&b.path().0));
+
+    if expected != actual {
         return Err(ctx.invalid_operation(
-            "assertion equality",
-            &format!("expected {}, got {}", expected_sv.value, actual_sv.value),
+            "assertion world diff",
+            &format!(
+                "expected changes:\n{}got:\n{}",
+                describe_diff(&expected),
+                describe_diff(&actual)
+            ),
             to_source_span(*call_span),
         ));
     }
@@ -59,6 +83,66 @@ fn assert_eq_atom(
     })
 }
 
+/// Evaluates one `(added ...)`, `(removed ...)` or `(changed ...)` clause. A `removed`
+/// clause without a value matches whatever value the path held before the body ran.
+fn expected_change(
+    clause: &AstNode,
+    before: &WorldState,
+    ctx: &mut EvaluationContext,
+) -> Result<WorldChange, SutraError> {
+    let items = match &*clause.value {
+        Expr::List(items, _) => items.as_slice(),
+        _ => &[],
+    };
+    let (kind, path, values) = match items {
+        [head, path, values @ ..] => match &*head.value {
+            Expr::Symbol(kind, _) => (kind.as_str(), resolve_path(path, ctx)?, values),
+            _ => ("", Path(Vec::new()), values),
+        },
+        _ => ("", Path(Vec::new()), &[][..]),
+    };
+
+    let values = values
+        .iter()
+        .map(|value| evaluate_ast_node(value, ctx).map(|v| v.value))
+        .collect::<Result<Vec<_>, _>>()?;
+    match (kind, values.as_slice()) {
+        ("added", [value]) => Ok(WorldChange::Added {
+            path,
+            value: value.clone(),
+        }),
+        ("removed", []) => Ok(WorldChange::Removed {
+            value: before.get(&path).cloned().unwrap_or(Value::Nil),
+            path,
+        }),
+        ("removed", [value]) => Ok(WorldChange::Removed {
+            path,
+            value: value.clone(),
+        }),
+        ("changed", [old, new]) => Ok(WorldChange::Changed {
+            path,
+            old: old.clone(),
+            new: new.clone(),
+        }),
+        _ => Err(ctx.invalid_operation(
+            "assert-world-diff",
+            "expected (added path value), (removed path [value]) or (changed path old new)",
+            to_source_span(clause.span),
+        )),
+    }
+}
+
+fn describe_diff(diff: &WorldDiff) -> String {
+    if diff.is_empty() {
+        "  (no changes)\n".to_string()
+    } else {
+        diff.changes
+            .iter()
+            .map(|change| format!("  {change}\n"))
+            .collect()
+    }
+}
+
 /// Debug output utility for tests
 fn test_echo_atom(
     args: &[AstNode],
@@ -67,7 +151,7 @@ fn test_echo_atom(
 ) -> SpannedResult {
     let output_str = if let Some(first) = args.first() {
         match &*first.value {
-            Expr::String(s, _) => s.clone(),
+            Expr::String(s, ..) => s.clone(),
             _ => format!("{}", first.value),
         }
     } else {
@@ -82,7 +166,33 @@ fn test_echo_atom(
 }
 
 pub fn register_test_atoms(world: &mut World) {
-    register_atom!(world, "assert", assert_atom);
-    register_atom!(world, "assert-eq", assert_eq_atom);
-    register_atom!(world, "test/echo", test_echo_atom);
+    register_atom!(
+        world,
+        "assert-eq",
+        assert_eq_atom,
+        Pure,
+        2,
+        ["Any"],
+        "Fails the test unless its arguments are equal."
+    );
+    register_atom!(
+        world,
+        "assert-world-diff",
+        assert_world_diff_atom,
+        Stateful,
+        1..,
+        ["Any", "Clause"],
+        "Fails the test unless its body changes the world exactly as listed.",
+        needs[State]
+    );
+    register_atom!(
+        world,
+        "test/echo",
+        test_echo_atom,
+        Stateful,
+        0..,
+        ["Any"],
+        "Writes its first argument, unevaluated, to the output.",
+        needs[Io]
+    );
 }
@@ -0,0 +1,193 @@
+//! Text generation atoms for the Sutra language.
+//!
+//! `text/pick` backs the `[a|b|c]` alternation in `(text ...)` templates; `plural`
+//! and `quantity` are the pluralization helpers used alongside them. `tr` looks up
+//! translated messages in the world's catalogs (see [`crate::localization`]).
+
+use std::collections::HashMap;
+
+use crate::{
+    atoms::WorldHandle,
+    errors::{to_source_span, ErrorReporting, SutraError},
+    localization::format_message,
+    runtime::{evaluate_ast_node, EvaluationContext, NativeFn, SpannedValue, Value},
+    syntax::{trailing_keyword_args, AstNode, Span},
+};
+
+// ============================================================================
+// HELPER FUNCTIONS
+// ============================================================================
+
+fn eval_number(arg: &AstNode, context: &mut EvaluationContext) -> Result<f64, SutraError> {
+    let value = evaluate_ast_node(arg, context)?;
+    match value.value {
+        Value::Number(n) => Ok(n),
+        other => {
+            Err(context.type_mismatch("Number", other.type_name(), to_source_span(value.span)))
+        }
+    }
+}
+
+fn eval_string(arg: &AstNode, context: &mut EvaluationContext) -> Result<String, SutraError> {
+    let value = evaluate_ast_node(arg, context)?;
+    match value.value {
+        Value::String(s) => Ok(s),
+        other => {
+            Err(context.type_mismatch("String", other.type_name(), to_source_span(value.span)))
+        }
+    }
+}
+
+/// Evaluates `(<count> <singular> [<plural>])` and returns the count and the word to use.
+fn counted_noun(
+    args: &[AstNode],
+    context: &mut EvaluationContext,
+    call_span: Span,
+) -> Result<(f64, String), SutraError> {
+    if !(2..=3).contains(&args.len()) {
+        return Err(context.arity_mismatch("2 or 3", args.len(), to_source_span(call_span)));
+    }
+
+    let count = eval_number(&args[0], context)?;
+    let singular = eval_string(&args[1], context)?;
+    if count == 1.0 {
+        return Ok((count, singular));
+    }
+    let plural = match args.get(2) {
+        Some(arg) => eval_string(arg, context)?,
+        None => english_plural(&singular),
+    };
+    Ok((count, plural))
+}
+
+/// Regular English plural: `box` -> `boxes`, `city` -> `cities`, `coin` -> `coins`.
+fn english_plural(word: &str) -> String {
+    const SIBILANT_ENDINGS: [&str; 5] = ["s", "x", "z", "ch", "sh"];
+    if SIBILANT_ENDINGS.iter().any(|ending| word.ends_with(ending)) {
+        return format!("{word}es");
+    }
+    if let Some(stem) = word.strip_suffix('y') {
+        if !stem.is_empty() && !stem.ends_with(['a', 'e', 'i', 'o', 'u']) {
+            return format!("{stem}ies");
+        }
+    }
+    format!("{word}s")
+}
+
+// ============================================================================
+// TEXT ATOMS
+// ============================================================================
+
+/// Evaluates one of its arguments, chosen at random.
+///
+/// Usage: (text/pick <alternative> ...)
+///   - alternative: Expression; only the chosen one is evaluated
+///
+/// Returns: The chosen alternative's value. Uses the world's seeded PRNG.
+///
+/// Example:
+///   (text/pick "a" "b" "c") ; => "b" (example)
+pub const ATOM_TEXT_PICK: NativeFn = |args, context, call_span| {
+    if args.is_empty() {
+        return Err(context.arity_mismatch("at least 1", 0, to_source_span(*call_span)));
+    }
+
+    let index = context.world.write().next_u32() as usize % args.len();
+    let chosen = evaluate_ast_node(&args[index], context)?;
+    Ok(SpannedValue {
+        value: chosen.value,
+        span: *call_span,
+    })
+};
+
+/// Chooses the singular or plural form of a word for a count.
+///
+/// Usage: (plural <count> <singular> [<plural>])
+///   - count: Number
+///   - singular: String used when count is 1
+///   - plural: Optional String; derived with regular English rules if omitted
+///
+/// Returns: String
+///
+/// Example:
+///   (plural 3 "coin")            ; => "coins"
+///   (plural 1 "coin")            ; => "coin"
+///   (plural 2 "mouse" "mice")    ; => "mice"
+pub const ATOM_PLURAL: NativeFn = |args, context, call_span| {
+    let (_, word) = counted_noun(args, context, *call_span)?;
+    Ok(SpannedValue {
+        value: Value::String(word),
+        span: *call_span,
+    })
+};
+
+/// Formats a count followed by the matching form of a word.
+///
+/// Usage: (quantity <count> <singular> [<plural>])
+///   - count: Number
+///   - singular: String used when count is 1
+///   - plural: Optional String; derived with regular English rules if omitted
+///
+/// Returns: String
+///
+/// Example:
+///   (quantity 3 "coin") ; => "3 coins"
+///   (quantity 1 "coin") ; => "1 coin"
+pub const ATOM_QUANTITY: NativeFn = |args, context, call_span| {
+    let (count, word) = counted_noun(args, context, *call_span)?;
+    Ok(SpannedValue {
+        value: Value::String(format!("{} {word}", Value::Number(count))),
+        span: *call_span,
+    })
+};
+
+/// Looks up a translated message and fills its placeholders.
+///
+/// Usage: (tr <key> [<arg> ...] [:<name> <value> ...])
+///   - key: String message key, e.g. "shop.greeting"
+///   - arg: Positional values, filling `{0}`, `{1}`, ...
+///   - name/value: Named values, filling `{name}`
+///
+/// Returns: String from the active locale's catalog, else the default locale's,
+///   else the key itself. Errors if the message uses a placeholder with no value.
+///
+/// Example:
+///   (tr "shop.greeting" :name "Ada") ; => "Welcome, Ada!"
+pub const ATOM_TR: NativeFn = |args, context, call_span| {
+    let Some((key_arg, rest)) = args.split_first() else {
+        return Err(context.arity_mismatch("at least 1", 0, to_source_span(*call_span)));
+    };
+    let key = eval_string(key_arg, context)?;
+    let (positional, keywords) = trailing_keyword_args(rest, context)?;
+
+    let mut values = HashMap::new();
+    for (index, arg) in positional.iter().enumerate() {
+        let value = evaluate_ast_node(arg, context)?;
+        values.insert(index.to_string(), value.value.to_string());
+    }
+    for keyword in keywords {
+        let value = evaluate_ast_node(keyword.value, context)?;
+        values.insert(keyword.name.to_string(), value.value.to_string());
+    }
+
+    let template = context
+        .world
+        .read()
+        .localization
+        .lookup(&key)
+        .map(str::to_string);
+    let message = match template {
+        Some(template) => format_message(&template, &values).map_err(|name| {
+            context.missing_element(
+                &format!("value for '{{{name}}}' in message '{key}'"),
+                to_source_span(*call_span),
+            )
+        })?,
+        None => key,
+    };
+
+    Ok(SpannedValue {
+        value: Value::String(message),
+        span: *call_span,
+    })
+};
@@ -5,35 +5,55 @@
 //!
 //! ## Atoms Provided
 //!
-//! - **State Operations**: `set!`, `get`, `del!`, `path`
-//! - **State Queries**: `exists?`
-//! - **Arithmetic Updates**: `inc!`, `dec!`, `add!`, `sub!`
+//! - **State Operations**: `set!`, `ensure-path!`, `get`, `del!`, `world/prune!`
+//! - **Path Construction**: `path`, `path/join`, `path/child`, `path/parent`, `path/last`,
+//!   `path/segments`
+//! - **State Queries**: `exists?`, `world/hash`, `world/stats`
+//! - **Generation**: `generate!`
+//! - **Undo History**: `checkpoint!`, `undo!`, `redo!`
+//! - **Updates**: `inc!`, `dec!`, `add!`, `sub!`, `update!`
+//! - **Queues and Stacks**: `push-front!`, `pop-front!`, `peek-front`, `peek`
 //!
 //! ## Design Notes
 //!
-//! All operations use `Path` objects for addressing state locations.
+//! All operations use `Path` objects for addressing state locations. A path argument is
+//! a path literal or symbol, a symbol bound to a path value, or any call that returns a
+//! path, so `(get (path/join 'npcs id 'hp))` reads a location chosen at runtime.
 //! Missing values return `nil` rather than errors for graceful handling.
+//!
+//! `set!` creates missing parent maps unless the world's `PathPolicy` is `Strict`, in
+//! which case `ensure-path!` initializes a path explicitly.
+
+use std::collections::BTreeMap;
 
 use crate::{
-    errors::{to_source_span, ErrorReporting},
+    atoms::{collections::call_function_with_values, WorldHandle},
+    errors::{to_source_span, ErrorKind, ErrorReporting},
     prelude::Path,
-    runtime::{evaluate_ast_node, NativeFn, SpannedValue, Value},
-    syntax::{AstNode, Expr},
+    runtime::{evaluate_ast_node, ConsRepr, NativeFn, SpannedValue, Value},
+    syntax::{trailing_keyword_args, AstNode, Expr},
 };
 
 // ============================================================================
 // CORE UTILITIES
 // ============================================================================
 
-/// Convert AST node to Path, handling only the patterns actually used
-fn resolve_path(
+/// Convert AST node to Path. Symbols name a path directly unless bound to a path value;
+/// a call must return a path.
+pub(crate) fn resolve_path(
     node: &AstNode,
     context: &mut crate::runtime::EvaluationContext,
 ) -> Result<Path, crate::errors::SutraError> {
     match &*node.value {
         Expr::Symbol(s, _) if s.contains('.') => Ok(Path(s.split('.').map(String::from).collect())),
-        Expr::Symbol(s, _) => Ok(Path(vec![s.clone()])),
+        Expr::Symbol(s, _) => Ok(bound_path(s, context).unwrap_or_else(|| Path(vec![s.clone()]))),
         Expr::Path(p, _) => Ok(p.clone()),
+        Expr::List(..) => match evaluate_ast_node(node, context)?.value {
+            Value::Path(path) => Ok(path),
+            other => {
+                Err(context.type_mismatch("Path", other.type_name(), to_source_span(node.span)))
+            }
+        },
         _ => Err(context.type_mismatch(
             "Path expression",
             node.value.type_name(),
@@ -42,6 +62,58 @@ fn resolve_path(
     }
 }
 
+/// The path value bound to `name` by `let`, a parameter or a top-level `define`.
+fn bound_path(name: &str, context: &crate::runtime::EvaluationContext) -> Option<Path> {
+    let local = context.get_var(name).cloned();
+    let value = local.or_else(|| context.world.read().defs.get(name).cloned());
+    match value {
+        Some(Value::Path(path)) => Some(path),
+        _ => None,
+    }
+}
+
+/// Evaluates `node` and requires a path value.
+fn eval_path(
+    node: &AstNode,
+    context: &mut crate::runtime::EvaluationContext,
+) -> Result<Path, crate::errors::SutraError> {
+    let value = evaluate_ast_node(node, context)?;
+    match value.value {
+        Value::Path(path) => Ok(path),
+        other => Err(context.type_mismatch("Path", other.type_name(), to_source_span(value.span))),
+    }
+}
+
+/// Appends the segments named by each of `nodes` to `path`. A path contributes all its
+/// segments; a string, symbol, keyword or whole number contributes one.
+fn push_segments(
+    path: &mut Path,
+    nodes: &[AstNode],
+    context: &mut crate::runtime::EvaluationContext,
+) -> Result<(), crate::errors::SutraError> {
+    for node in nodes {
+        let value = evaluate_ast_node(node, context)?;
+        let segment = match value.value {
+            Value::Path(other) => {
+                path.0.extend(other.0);
+                continue;
+            }
+            Value::String(s) | Value::Symbol(s) | Value::Keyword(s) => s,
+            Value::Quote(quoted) if matches!(*quoted, Value::Symbol(_)) => quoted.to_string(),
+            Value::Number(n) if n.fract() == 0.0 => n.to_string(),
+            other => {
+                return Err(context.type_mismatch(
+                    "path segment (String, Symbol, Keyword, whole Number or Path)",
+                    other.type_name(),
+                    to_source_span(value.span),
+                ))
+            }
+        };
+        path.0.push(segment);
+    }
+    Ok(())
+}
+
 /// Unified arithmetic operations on world state
 #[derive(Clone, Copy)]
 enum ArithmeticOp {
@@ -57,12 +129,7 @@ fn apply_arithmetic(
     context: &mut crate::runtime::EvaluationContext,
     call_span: crate::Span,
 ) -> Result<SpannedValue, crate::errors::SutraError> {
-    let current = context
-        .world
-        .borrow()
-        .get(path)
-        .cloned()
-        .unwrap_or_default();
+    let current = context.world.read().get(path).cloned().unwrap_or_default();
 
     let new_value = match (current, op) {
         (Value::Number(n), ArithmeticOp::Add(x)) => Value::Number(n + x),
@@ -78,13 +145,51 @@ fn apply_arithmetic(
         }
     };
 
-    context.world.borrow_mut().set(path, new_value.clone());
+    context.world.write().set(path, new_value.clone());
     Ok(SpannedValue {
         value: new_value,
         span: call_span,
     })
 }
 
+/// Sets `path` under the world's path policy, reporting a missing parent at
+/// `path_node`.
+fn write_path(
+    path: &Path,
+    value: Value,
+    path_node: &AstNode,
+    context: &mut crate::runtime::EvaluationContext,
+) -> Result<(), crate::errors::SutraError> {
+    let result = {
+        let mut world = context.world.write();
+        let policy = world.path_policy;
+        world.set_with_policy(path, value, policy)
+    };
+    result.map_err(|parent| {
+        context.report(
+            ErrorKind::MissingParent {
+                path: path.to_string(),
+                parent: parent.to_string(),
+            },
+            to_source_span(path_node.span),
+        )
+    })
+}
+
+/// The list at `path`, or nil if the path holds nothing.
+fn list_at(
+    path: &Path,
+    path_node: &AstNode,
+    context: &mut crate::runtime::EvaluationContext,
+) -> Result<Value, crate::errors::SutraError> {
+    match context.world.read().get(path).cloned().unwrap_or_default() {
+        list @ (Value::Nil | Value::Cons(_)) => Ok(list),
+        other => {
+            Err(context.type_mismatch("List", other.type_name(), to_source_span(path_node.span)))
+        }
+    }
+}
+
 // ============================================================================
 // WORLD STATE ATOMS
 // ============================================================================
@@ -98,7 +203,7 @@ pub const ATOM_SET: NativeFn = |args, context, call_span| {
 
     let path = resolve_path(&args[0], context)?;
     let value = evaluate_ast_node(&args[1], context)?.value;
-    context.world.borrow_mut().set(&path, value);
+    write_path(&path, value, &args[0], context)?;
 
     Ok(SpannedValue {
         value: Value::Nil,
@@ -106,6 +211,31 @@ pub const ATOM_SET: NativeFn = |args, context, call_span| {
     })
 };
 
+/// Sets a path to a default value unless it already holds one, creating any missing
+/// parents whatever the world's path policy. Returns the value now at the path.
+/// `(ensure-path! <path> <default>)`
+pub const ATOM_ENSURE_PATH: NativeFn = |args, context, call_span| {
+    if args.len() != 2 {
+        return Err(context.arity_mismatch("2", args.len(), to_source_span(*call_span)));
+    }
+
+    let path = resolve_path(&args[0], context)?;
+    let existing = context.world.read().get(&path).cloned();
+    let value = match existing {
+        Some(value) => value,
+        None => {
+            let default = evaluate_ast_node(&args[1], context)?.value;
+            context.world.write().set(&path, default.clone());
+            default
+        }
+    };
+
+    Ok(SpannedValue {
+        value,
+        span: *call_span,
+    })
+};
+
 /// Gets a value at a path in the world state.
 /// `(get <path>)`
 pub const ATOM_GET: NativeFn = |args, context, call_span| {
@@ -114,12 +244,7 @@ pub const ATOM_GET: NativeFn = |args, context, call_span| {
     }
 
     let path = resolve_path(&args[0], context)?;
-    let value = context
-        .world
-        .borrow()
-        .get(&path)
-        .cloned()
-        .unwrap_or_default();
+    let value = context.world.read().get(&path).cloned().unwrap_or_default();
 
     Ok(SpannedValue {
         value,
@@ -135,7 +260,7 @@ pub const ATOM_DEL: NativeFn = |args, context, call_span| {
     }
 
     let path = resolve_path(&args[0], context)?;
-    context.world.borrow_mut().del(&path);
+    context.world.write().del(&path);
 
     Ok(SpannedValue {
         value: Value::Nil,
@@ -151,7 +276,7 @@ pub const ATOM_EXISTS: NativeFn = |args, context, call_span| {
     }
 
     let path = resolve_path(&args[0], context)?;
-    let exists = context.world.borrow().get(&path).is_some();
+    let exists = context.world.read().get(&path).is_some();
 
     Ok(SpannedValue {
         value: Value::Bool(exists),
@@ -159,6 +284,229 @@ pub const ATOM_EXISTS: NativeFn = |args, context, call_span| {
     })
 };
 
+/// Returns a stable content hash of the value at a path, or of the whole world state
+/// when no path is given, as a hex string. A missing value hashes like nil, and equal
+/// subtrees hash alike however they were built.
+/// `(world/hash [<path>])`
+///
+/// Example:
+///   (eq? (world/hash player) saved-hash) ; => true if `player` is unchanged
+pub const ATOM_WORLD_HASH: NativeFn = |args, context, call_span| {
+    if args.len() > 1 {
+        return Err(context.arity_mismatch("0 or 1", args.len(), to_source_span(*call_span)));
+    }
+
+    let path = match args.first() {
+        Some(arg) => resolve_path(arg, context)?,
+        None => Path(Vec::new()),
+    };
+    let hash = context.world.read().hash(&path);
+
+    Ok(SpannedValue {
+        value: Value::String(hash),
+        span: *call_span,
+    })
+};
+
+/// Returns the size of the value at a path, or of the whole world state when no path
+/// is given, as a map: `nodes`, the entries in all of its maps; `maps`, how many of
+/// those are maps; `depth`, the segments in the longest path below it; and `bytes`, a
+/// rough estimate of the memory it takes. A missing value has no size.
+/// `(world/stats [<path>])`
+///
+/// Example:
+///   (world/stats npcs) ; => {bytes: 912, depth: 2, maps: 3, nodes: 9}
+pub const ATOM_WORLD_STATS: NativeFn = |args, context, call_span| {
+    if args.len() > 1 {
+        return Err(context.arity_mismatch("0 or 1", args.len(), to_source_span(*call_span)));
+    }
+
+    let path = match args.first() {
+        Some(arg) => resolve_path(arg, context)?,
+        None => Path(Vec::new()),
+    };
+    let stats = context.world.read().stats(&path);
+    let fields = [
+        ("nodes", stats.nodes),
+        ("maps", stats.maps),
+        ("depth", stats.depth),
+        ("bytes", stats.bytes),
+    ];
+
+    Ok(SpannedValue {
+        value: Value::Map(
+            fields
+                .into_iter()
+                .map(|(name, count)| (name.to_string(), Value::Number(count as f64)))
+                .collect::<BTreeMap<_, _>>(),
+        ),
+        span: *call_span,
+    })
+};
+
+/// Deletes the entries of the map at a path that a predicate accepts, and returns
+/// their paths in key order. The predicate gets each entry's path, and runs on every
+/// entry before any is deleted, so an error leaves the state as it was. With
+/// `:dry-run true` nothing is deleted, and the paths are those that would be.
+/// `(world/prune! <path> <predicate> [:dry-run <bool>])`
+///
+/// Example:
+///   (world/prune! timers (lambda (timer) (lt? (get timer) (get clock.turn))))
+pub const ATOM_WORLD_PRUNE: NativeFn = |args, context, call_span| {
+    let (positional, options) = trailing_keyword_args(args, context)?;
+    if positional.len() != 2 {
+        return Err(context.arity_mismatch("2", positional.len(), to_source_span(*call_span)));
+    }
+
+    let mut dry_run = false;
+    for option in options {
+        if option.name != "dry-run" {
+            return Err(context.invalid_operation(
+                "world/prune!",
+                &format!("unknown option ':{}'", option.name),
+                to_source_span(option.keyword.span),
+            ));
+        }
+        dry_run = evaluate_ast_node(option.value, context)?.value.is_truthy();
+    }
+
+    let path = resolve_path(&positional[0], context)?;
+    let predicate = evaluate_ast_node(&positional[1], context)?;
+    if !matches!(predicate.value, Value::Lambda(_) | Value::NativeFn(_)) {
+        return Err(context.type_mismatch(
+            "Lambda or NativeFn",
+            predicate.value.type_name(),
+            to_source_span(predicate.span),
+        ));
+    }
+    let keys: Vec<String> = match context.world.read().get(&path) {
+        Some(Value::Map(map)) => map.keys().cloned().collect(),
+        None => Vec::new(),
+        Some(other) => {
+            return Err(context.type_mismatch(
+                "Map",
+                other.type_name(),
+                to_source_span(positional[0].span),
+            ))
+        }
+    };
+
+    let mut pruned = Vec::new();
+    for key in keys {
+        let mut entry = path.clone();
+        entry.0.push(key);
+        let prune = call_function_with_values(
+            &predicate.value,
+            &[Value::Path(entry.clone())],
+            context,
+            call_span,
+            positional[0].span,
+        )?;
+        if prune.value.is_truthy() {
+            pruned.push(entry);
+        }
+    }
+    if !dry_run {
+        let mut world = context.world.write();
+        for entry in &pruned {
+            world.del(entry);
+        }
+    }
+
+    Ok(SpannedValue {
+        value: pruned.into_iter().rev().fold(Value::Nil, |list, entry| {
+            Value::Cons(ConsRepr::cons(Value::Path(entry), list))
+        }),
+        span: *call_span,
+    })
+};
+
+/// Calls a function with each index from 0 below a count and sets the entry under a
+/// path keyed by that index to its result, returning the entry paths in order. Each
+/// entry is set before the next call, so a template can read the ones before it; an
+/// error leaves those already set. The count may not exceed the `max_collection_len`
+/// quota, and the sink hears of each entry through [`OutputSink::progress`].
+/// `(generate! <path> <count> <template-fn>)`
+///
+/// Example:
+///   (generate! rooms 50 (lambda (i) (str+ "Room " i)))  ; rooms.0 ... rooms.49
+///
+/// [`OutputSink::progress`]: crate::atoms::OutputSink::progress
+pub const ATOM_GENERATE: NativeFn = |args, context, call_span| {
+    if args.len() != 3 {
+        return Err(context.arity_mismatch("3", args.len(), to_source_span(*call_span)));
+    }
+
+    let path = resolve_path(&args[0], context)?;
+    let count = evaluate_ast_node(&args[1], context)?;
+    let count = match count.value {
+        Value::Number(n) if n >= 0.0 && n.fract() == 0.0 => n as usize,
+        Value::Number(n) => {
+            return Err(context.invalid_operation(
+                "generate!",
+                &format!("count must be a whole number of at least 0, not {n}"),
+                to_source_span(count.span),
+            ))
+        }
+        other => {
+            return Err(context.type_mismatch(
+                "Number",
+                other.type_name(),
+                to_source_span(count.span),
+            ))
+        }
+    };
+    if let Some(limit) = context
+        .limits
+        .max_collection_len
+        .filter(|limit| count > *limit)
+    {
+        return Err(context.report(
+            ErrorKind::ResourceLimit {
+                resource: "generated entries".to_string(),
+                limit,
+                actual: count,
+            },
+            to_source_span(args[1].span),
+        ));
+    }
+    let template = evaluate_ast_node(&args[2], context)?;
+    if !matches!(template.value, Value::Lambda(_) | Value::NativeFn(_)) {
+        return Err(context.type_mismatch(
+            "Lambda or NativeFn",
+            template.value.type_name(),
+            to_source_span(template.span),
+        ));
+    }
+
+    let task = format!("generate! {path}");
+    let mut entries = Vec::with_capacity(count);
+    for index in 0..count {
+        let value = call_function_with_values(
+            &template.value,
+            &[Value::Number(index as f64)],
+            context,
+            call_span,
+            args[2].span,
+        )?
+        .value;
+        let mut entry = path.clone();
+        entry.0.push(index.to_string());
+        context.world.write().set(&entry, value);
+        context
+            .output
+            .progress(&task, index + 1, count, Some(call_span));
+        entries.push(entry);
+    }
+
+    Ok(SpannedValue {
+        value: entries.into_iter().rev().fold(Value::Nil, |list, entry| {
+            Value::Cons(ConsRepr::cons(Value::Path(entry), list))
+        }),
+        span: *call_span,
+    })
+};
+
 /// Increments a numeric value at a path.
 /// `(inc! <path>)`
 pub const ATOM_INC: NativeFn = |args, context, call_span| {
@@ -221,30 +569,282 @@ pub const ATOM_SUB: NativeFn = |args, context, call_span| {
     }
 };
 
-/// Creates a path from multiple strings.
-/// `(path <string> <string> ...)`
+/// Replaces the value at a path with a function's result on it, and returns the new
+/// value. A missing value is passed as nil. The result must have the type of the value
+/// it replaces, except that nil may stand for an empty list or an absent value.
+/// `(update! <path> <function>)`
+pub const ATOM_UPDATE: NativeFn = |args, context, call_span| {
+    if args.len() != 2 {
+        return Err(context.arity_mismatch("2", args.len(), to_source_span(*call_span)));
+    }
+
+    let path = resolve_path(&args[0], context)?;
+    let function = evaluate_ast_node(&args[1], context)?;
+    if !matches!(function.value, Value::Lambda(_) | Value::NativeFn(_)) {
+        return Err(context.type_mismatch(
+            "Lambda or NativeFn",
+            function.value.type_name(),
+            to_source_span(args[1].span),
+        ));
+    }
+
+    let current = context.world.read().get(&path).cloned().unwrap_or_default();
+    let expected = current.type_name();
+    let updated = call_function_with_values(
+        &function.value,
+        &[current],
+        context,
+        call_span,
+        args[1].span,
+    )?
+    .value;
+    if expected != updated.type_name() && expected != "Nil" && !updated.is_nil() {
+        return Err(context
+            .type_mismatch(expected, updated.type_name(), to_source_span(args[1].span))
+            .with_help(format!(
+                "update! keeps the type of the value at {path}; use set! to replace it"
+            )));
+    }
+
+    write_path(&path, updated.clone(), &args[0], context)?;
+
+    Ok(SpannedValue {
+        value: updated,
+        span: *call_span,
+    })
+};
+
+// ============================================================================
+// QUEUES AND STACKS
+// ============================================================================
+
+/// Adds a value to the front of the list at a path, creating the list if the path
+/// holds nothing. Returns the list. Taking from the front is constant time, so a
+/// world list serves as a queue without being reversed.
+/// `(push-front! <path> <value>)`
+pub const ATOM_PUSH_FRONT: NativeFn = |args, context, call_span| {
+    if args.len() != 2 {
+        return Err(context.arity_mismatch("2", args.len(), to_source_span(*call_span)));
+    }
+
+    let path = resolve_path(&args[0], context)?;
+    let list = list_at(&path, &args[0], context)?;
+    let value = evaluate_ast_node(&args[1], context)?.value;
+    let list = Value::Cons(ConsRepr::cons(value, list));
+    write_path(&path, list.clone(), &args[0], context)?;
+
+    Ok(SpannedValue {
+        value: list,
+        span: *call_span,
+    })
+};
+
+/// Removes the first value of the list at a path and returns it, or nil if the list
+/// is empty.
+/// `(pop-front! <path>)`
+pub const ATOM_POP_FRONT: NativeFn = |args, context, call_span| {
+    if args.len() != 1 {
+        return Err(context.arity_mismatch("1", args.len(), to_source_span(*call_span)));
+    }
+
+    let path = resolve_path(&args[0], context)?;
+    let Value::Cons(cell) = list_at(&path, &args[0], context)? else {
+        return Ok(SpannedValue {
+            value: Value::Nil,
+            span: *call_span,
+        });
+    };
+    write_path(&path, cell.cdr(), &args[0], context)?;
+
+    Ok(SpannedValue {
+        value: cell.car().clone(),
+        span: *call_span,
+    })
+};
+
+/// The first value of the list at a path, or nil if it is empty. The list is left
+/// as it is.
+/// `(peek-front <path>)`
+pub const ATOM_PEEK_FRONT: NativeFn = |args, context, call_span| {
+    if args.len() != 1 {
+        return Err(context.arity_mismatch("1", args.len(), to_source_span(*call_span)));
+    }
+
+    let path = resolve_path(&args[0], context)?;
+    let value = match list_at(&path, &args[0], context)? {
+        Value::Cons(cell) => cell.car().clone(),
+        _ => Value::Nil,
+    };
+    Ok(SpannedValue {
+        value,
+        span: *call_span,
+    })
+};
+
+/// The last value of the list at a path, or nil if it is empty. The list is left as
+/// it is.
+/// `(peek <path>)`
+pub const ATOM_PEEK: NativeFn = |args, context, call_span| {
+    if args.len() != 1 {
+        return Err(context.arity_mismatch("1", args.len(), to_source_span(*call_span)));
+    }
+
+    let path = resolve_path(&args[0], context)?;
+    let value = list_at(&path, &args[0], context)?
+        .try_into_iter()
+        .last()
+        .unwrap_or_default();
+    Ok(SpannedValue {
+        value,
+        span: *call_span,
+    })
+};
+
+/// Creates a path from its segments.
+/// `(path <segment> <segment> ...)`
 pub const ATOM_PATH: NativeFn = |args, context, call_span| {
     if args.is_empty() {
         return Err(context.arity_mismatch("1+", args.len(), to_source_span(*call_span)));
     }
 
-    let mut parts = Vec::new();
-    for arg in args {
-        let value = evaluate_ast_node(arg, context)?;
-        match value.value {
-            Value::String(s) => parts.push(s),
-            _ => {
-                return Err(context.type_mismatch(
-                    "String",
-                    value.value.type_name(),
-                    to_source_span(value.span),
-                ))
-            }
-        }
+    let mut path = Path(Vec::new());
+    push_segments(&mut path, args, context)?;
+    Ok(SpannedValue {
+        value: Value::Path(path),
+        span: *call_span,
+    })
+};
+
+// ============================================================================
+// PATH CONSTRUCTION
+// ============================================================================
+
+/// Joins segments and paths into one path.
+/// `(path/join 'npcs npc-id 'hp)`
+pub const ATOM_PATH_JOIN: NativeFn = |args, context, call_span| {
+    let mut path = Path(Vec::new());
+    push_segments(&mut path, args, context)?;
+    if path.0.is_empty() {
+        return Err(context.invalid_operation(
+            "path/join",
+            "the joined path has no segments",
+            to_source_span(*call_span),
+        ));
     }
+    Ok(SpannedValue {
+        value: Value::Path(path),
+        span: *call_span,
+    })
+};
+
+/// Extends a path with further segments.
+/// `(path/child <path> <segment> ...)`
+pub const ATOM_PATH_CHILD: NativeFn = |args, context, call_span| {
+    if args.len() < 2 {
+        return Err(context.arity_mismatch("at least 2", args.len(), to_source_span(*call_span)));
+    }
+
+    let mut path = eval_path(&args[0], context)?;
+    push_segments(&mut path, &args[1..], context)?;
+    Ok(SpannedValue {
+        value: Value::Path(path),
+        span: *call_span,
+    })
+};
+
+/// The path without its last segment, or nil for a single-segment path.
+/// `(path/parent <path>)`
+pub const ATOM_PATH_PARENT: NativeFn = |args, context, call_span| {
+    if args.len() != 1 {
+        return Err(context.arity_mismatch("1", args.len(), to_source_span(*call_span)));
+    }
+
+    let mut path = eval_path(&args[0], context)?;
+    path.0.pop();
+    let value = if path.0.is_empty() {
+        Value::Nil
+    } else {
+        Value::Path(path)
+    };
+    Ok(SpannedValue {
+        value,
+        span: *call_span,
+    })
+};
+
+/// The last segment of a path, as a string.
+/// `(path/last <path>)`
+pub const ATOM_PATH_LAST: NativeFn = |args, context, call_span| {
+    if args.len() != 1 {
+        return Err(context.arity_mismatch("1", args.len(), to_source_span(*call_span)));
+    }
+
+    let path = eval_path(&args[0], context)?;
+    Ok(SpannedValue {
+        value: path.0.last().cloned().map_or(Value::Nil, Value::String),
+        span: *call_span,
+    })
+};
+
+/// The segments of a path, as a list of strings.
+/// `(path/segments <path>)`
+pub const ATOM_PATH_SEGMENTS: NativeFn = |args, context, call_span| {
+    if args.len() != 1 {
+        return Err(context.arity_mismatch("1", args.len(), to_source_span(*call_span)));
+    }
+
+    let path = eval_path(&args[0], context)?;
+    Ok(SpannedValue {
+        value: Value::from_list(path.0.into_iter().map(Value::String).collect()),
+        span: *call_span,
+    })
+};
+
+// ============================================================================
+// UNDO HISTORY
+// ============================================================================
+
+/// Records the current world state as an undo point.
+/// `(checkpoint!)`
+pub const ATOM_CHECKPOINT: NativeFn = |args, context, call_span| {
+    if !args.is_empty() {
+        return Err(context.arity_mismatch("0", args.len(), to_source_span(*call_span)));
+    }
+
+    context.world.write().checkpoint();
+
+    Ok(SpannedValue {
+        value: Value::Nil,
+        span: *call_span,
+    })
+};
+
+/// Restores the world state of the most recent checkpoint.
+/// `(undo!)` returns false if there is no checkpoint to return to.
+pub const ATOM_UNDO: NativeFn = |args, context, call_span| {
+    if !args.is_empty() {
+        return Err(context.arity_mismatch("0", args.len(), to_source_span(*call_span)));
+    }
+
+    let undone = context.world.write().undo();
+
+    Ok(SpannedValue {
+        value: Value::Bool(undone),
+        span: *call_span,
+    })
+};
+
+/// Reverses the most recent `undo!`.
+/// `(redo!)` returns false if there is nothing to redo.
+pub const ATOM_REDO: NativeFn = |args, context, call_span| {
+    if !args.is_empty() {
+        return Err(context.arity_mismatch("0", args.len(), to_source_span(*call_span)));
+    }
+
+    let redone = context.world.write().redo();
 
     Ok(SpannedValue {
-        value: Value::Path(Path(parts)),
+        value: Value::Bool(redone),
         span: *call_span,
     })
 };
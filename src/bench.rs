@@ -0,0 +1,170 @@
+//! Sutra Benchmark Runner
+//!
+//! Times `(bench "name" body...)` forms discovered from `.sutra` files and compares
+//! the results against a saved JSON baseline.
+//!
+//! Each iteration expands and evaluates the benchmark body against a fresh world, so
+//! macro expansion cost is included in the measurement. World construction happens
+//! outside the timed region.
+
+use std::{collections::BTreeMap, fs, path::Path, time::Instant};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    atoms::{NullSink, SharedOutput},
+    cli::ExecutionPipeline,
+    discovery::BenchDefinition,
+    errors::{ErrorKind, SutraError},
+};
+
+/// How many times each benchmark is run.
+#[derive(Debug, Clone, Copy)]
+pub struct BenchConfig {
+    /// Untimed iterations run first to warm caches.
+    pub warmup: usize,
+    /// Timed iterations used to compute statistics.
+    pub iterations: usize,
+}
+
+impl Default for BenchConfig {
+    fn default() -> Self {
+        Self {
+            warmup: 5,
+            iterations: 30,
+        }
+    }
+}
+
+/// Summary statistics for one benchmark, in microseconds.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BenchStats {
+    pub iterations: usize,
+    pub mean_us: f64,
+    pub median_us: f64,
+    pub p95_us: f64,
+}
+
+impl BenchStats {
+    /// Computes statistics from raw samples. `samples` must be non-empty.
+    pub fn from_samples(samples: &mut [f64]) -> Self {
+        samples.sort_by(f64::total_cmp);
+        let count = samples.len();
+        let mean_us = samples.iter().sum::<f64>() / count as f64;
+        let median_us = if count.is_multiple_of(2) {
+            (samples[count / 2 - 1] + samples[count / 2]) / 2.0
+        } else {
+            samples[count / 2]
+        };
+        let p95_index = ((count as f64 * 0.95).ceil() as usize).clamp(1, count) - 1;
+        Self {
+            iterations: count,
+            mean_us,
+            median_us,
+            p95_us: samples[p95_index],
+        }
+    }
+}
+
+/// Results for a whole benchmark run, keyed by `file::name`. Serialized as the baseline format.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct BenchReport {
+    pub benches: BTreeMap<String, BenchStats>,
+}
+
+impl BenchReport {
+    /// Loads a report previously written with [`BenchReport::save`].
+    pub fn load(path: &Path) -> Result<Self, SutraError> {
+        let content = fs::read_to_string(path)
+            .map_err(|e| io_error(format!("{} ({})", path.display(), e)))?;
+        serde_json::from_str(&content).map_err(|e| {
+            io_error(format!(
+                "{} is not a valid baseline ({})",
+                path.display(),
+                e
+            ))
+        })
+    }
+
+    /// Writes the report as pretty-printed JSON.
+    pub fn save(&self, path: &Path) -> Result<(), SutraError> {
+        let json = serde_json::to_string_pretty(self)
+            .map_err(|e| io_error(format!("{} ({})", path.display(), e)))?;
+        fs::write(path, json).map_err(|e| io_error(format!("{} ({})", path.display(), e)))
+    }
+}
+
+/// Comparison of one benchmark's median against its baseline.
+#[derive(Debug, Clone)]
+pub struct BenchComparison {
+    pub name: String,
+    pub baseline_us: f64,
+    pub current_us: f64,
+    /// Relative change in percent; positive means slower.
+    pub change_pct: f64,
+    pub regressed: bool,
+}
+
+/// Compares medians of every benchmark present in both reports.
+///
+/// A benchmark regresses when its median is more than `threshold_pct` percent slower
+/// than the baseline. Benchmarks missing from the baseline are skipped.
+pub fn compare(
+    baseline: &BenchReport,
+    current: &BenchReport,
+    threshold_pct: f64,
+) -> Vec<BenchComparison> {
+    current
+        .benches
+        .iter()
+        .filter_map(|(name, stats)| {
+            let base = baseline.benches.get(name)?;
+            let change_pct = if base.median_us > 0.0 {
+                (stats.median_us - base.median_us) / base.median_us * 100.0
+            } else {
+                0.0
+            };
+            Some(BenchComparison {
+                name: name.clone(),
+                baseline_us: base.median_us,
+                current_us: stats.median_us,
+                change_pct,
+                regressed: change_pct > threshold_pct,
+            })
+        })
+        .collect()
+}
+
+/// Runs benchmark bodies through the execution pipeline.
+pub struct BenchRunner;
+
+impl BenchRunner {
+    /// Runs a single benchmark with warmup and returns its statistics.
+    ///
+    /// Evaluation errors abort the benchmark; a benchmark that fails is not timed.
+    pub fn run(bench: &BenchDefinition, config: &BenchConfig) -> Result<BenchStats, SutraError> {
+        for _ in 0..config.warmup {
+            Self::run_once(bench)?;
+        }
+
+        let mut samples = Vec::with_capacity(config.iterations.max(1));
+        for _ in 0..config.iterations.max(1) {
+            samples.push(Self::run_once(bench)?);
+        }
+        Ok(BenchStats::from_samples(&mut samples))
+    }
+
+    /// Executes the body once against a fresh world, returning elapsed microseconds.
+    fn run_once(bench: &BenchDefinition) -> Result<f64, SutraError> {
+        let pipeline = ExecutionPipeline::default();
+        let output = SharedOutput::new(NullSink);
+
+        let start = Instant::now();
+        pipeline.execute_nodes(&bench.body, output, bench.source_file.clone())?;
+        Ok(start.elapsed().as_secs_f64() * 1_000_000.0)
+    }
+}
+
+fn io_error(path: String) -> SutraError {
+    SutraError::without_source(ErrorKind::InvalidPath { path: path.clone() }, path, "bench")
+}
@@ -3,30 +3,59 @@
 //! the core library functions.
 
 use std::{
-    io::Read,
+    fmt, fs,
+    io::{self, Read},
     path::{Path, PathBuf},
     process,
+    sync::{Mutex, PoisonError},
+    time::Duration,
 };
 
-use clap::{Parser, Subcommand};
+use clap::{Args, CommandFactory, Parser, Subcommand};
+use clap_complete::Shell;
+use rand::{seq::SliceRandom, SeedableRng};
 
 use crate::prelude::*;
 use crate::{
-    atoms::{EngineStdoutSink, SharedOutput},
+    ast_cache::{self, AstCache},
+    atoms::{
+        register_all_atoms, AtomKind, Capability, EngineOutputBuffer, EngineStdoutSink, LogLevel,
+        PathPolicy, SharedOutput, SmallRng, World,
+    },
+    bench::{self, BenchConfig, BenchReport, BenchRunner},
     build_canonical_macro_env, build_canonical_world,
-    discovery::TestDiscoverer,
-    errors::{
-        self, print_error, ErrorKind, ErrorReporting, SourceContext, SutraError, ValidationContext,
+    discovery::{ASTDefinition, HookKind, TestDiscoverer, TestSuite},
+    editor_grammar::{self, Keywords},
+    errors::{closest_name, line_and_column, print_error, ErrorKind, SourceContext, SutraError},
+    evaluate, grammar_validation,
+    localization::{self, Catalog},
+    logging,
+    macros::{
+        defstate::machine_definitions,
+        deftable::{table_definitions, EntryOutcome, TableDefinition},
+        ExpansionLimits, ExpansionStep, MacroDefinition, MacroSystem, MacroTemplate,
     },
-    evaluate,
-    macros::MacroSystem,
-    parser,
-    test::TestSummary,
-    test_runner::TestRunner,
+    optimize, parser,
+    project::{Project, MANIFEST_FILE},
+    replay::{self, log_error, Header, LoggedFile, Session, SharedSession, REPLAY_VERSION},
+    runtime::{evaluate_ast_node, Deadline, EvaluationContext, ResourceLimits, DEFAULT_MAX_DEPTH},
+    semantic_validation as semantic, telemetry,
+    test::{Attempt, Quarantine, Shard, TestRecord, TestReport, TestResult, TestSummary},
+    test_runner::{TestRunner, TestSetup},
+    typecheck,
+    world_diff::WorldDiff,
 };
 use std::collections::HashMap;
 use termcolor::{Color, ColorChoice, ColorSpec, StandardStream, WriteColor};
 
+pub mod args;
+pub mod examples;
+pub mod grep;
+pub mod preload;
+pub mod rename;
+
+use args::{Settings, Source};
+
 // ============================================================================
 // TEST REPORTING STRUCTURES
 // ============================================================================
@@ -34,25 +63,19 @@ use termcolor::{Color, ColorChoice, ColorSpec, StandardStream, WriteColor};
 #[derive(Debug)]
 struct FileTestSummary {
     file_path: PathBuf,
-    results: Vec<bool>,
+    summary: TestSummary,
 }
 
 impl FileTestSummary {
     fn new(file_path: PathBuf) -> Self {
         Self {
             file_path,
-            results: Vec::new(),
+            summary: TestSummary::default(),
         }
     }
 
-    fn add_result(&mut self, passed: bool) {
-        self.results.push(passed);
-    }
-
-    fn summary(&self) -> TestSummary {
-        let passed = self.results.iter().filter(|&&r| r).count();
-        let failed = self.results.len() - passed;
-        TestSummary { passed, failed }
+    fn add_result(&mut self, result: &TestResult) {
+        self.summary.record(result);
     }
 }
 
@@ -65,11 +88,24 @@ impl FileTestSummary {
 #[command(
     name = "sutra",
     version,
-    about = "A compositional, emergent, and narrative-rich game engine."
+    about = "A compositional, emergent, and narrative-rich game engine.",
+    arg_required_else_help = true
 )]
 pub struct SutraArgs {
     #[command(subcommand)]
-    pub command: ArgsCommand,
+    pub command: Option<ArgsCommand>,
+    /// List every command with its arguments, for launchers and editors.
+    #[arg(long, hide = true)]
+    pub list_commands: bool,
+    /// Print the `--list-commands` listing as JSON.
+    #[arg(long, hide = true, requires = "list_commands")]
+    pub json: bool,
+    /// Log only errors.
+    #[arg(short, long, global = true, conflicts_with = "verbose")]
+    pub quiet: bool,
+    /// Log what the engine is doing to stderr. Repeat (-vv, -vvv) for more detail.
+    #[arg(short, long, global = true, action = clap::ArgAction::Count)]
+    pub verbose: u8,
 }
 
 /// An enumeration of all available CLI subcommands.
@@ -77,14 +113,45 @@ pub struct SutraArgs {
 pub enum ArgsCommand {
     /// Full pipeline: parse, expand, validate, eval, and output.
     Run {
-        /// The path to the Sutra script file to run.
+        /// The Sutra script files to run, in order, against one shared world, or a
+        /// project directory with a `sutra.toml`.
         #[arg(required = true)]
-        file: PathBuf,
+        files: Vec<PathBuf>,
+        /// After running, print the world state changes the scripts made.
+        #[arg(long)]
+        dump_world_diff: bool,
+        /// After running, print how many macro expansions were reused from the cache.
+        #[arg(long)]
+        stats: bool,
+        /// Run again whenever a script changes. Needs the `watch` feature.
+        #[arg(long)]
+        watch: bool,
+        /// Log the scripts, random draws and player input to this file, for `sutra replay`.
+        #[arg(long, value_name = "LOG", conflicts_with = "watch")]
+        record: Option<PathBuf>,
+        /// Call this function once the scripts have loaded, instead of `main`.
+        #[arg(long, value_name = "FUNCTION")]
+        entry: Option<String>,
+        /// Evaluate every definition again instead of restoring cached ones.
+        #[arg(long)]
+        no_cache: bool,
+        #[command(flatten)]
+        pipeline: PipelineArgs,
+    },
+    /// Rerun a session recorded with `run --record`, reporting where it first differs.
+    Replay {
+        /// The replay log to run.
+        #[arg(required = true)]
+        log: PathBuf,
+        #[command(flatten)]
+        pipeline: PipelineArgs,
     },
     /// Evaluate Sutra code directly from command line or stdin.
     Eval {
         /// Sutra code to evaluate. If not provided, reads from stdin.
         code: Option<String>,
+        #[command(flatten)]
+        pipeline: PipelineArgs,
     },
     /// Start an interactive REPL (Read-Eval-Print Loop).
     Repl,
@@ -93,6 +160,15 @@ pub enum ArgsCommand {
         /// The path to the Sutra script file to expand.
         #[arg(required = true)]
         file: PathBuf,
+        /// Print each expansion step as a diff against the step before.
+        #[arg(long, conflicts_with = "diff")]
+        step: bool,
+        /// Print a diff from the script to its expansion.
+        #[arg(long)]
+        diff: bool,
+        /// Expand only calls of this macro, leaving what they expand to as is.
+        #[arg(long, value_name = "NAME")]
+        only: Option<String>,
     },
     /// Show a stepwise macro expansion trace with diffs.
     Macrotrace {
@@ -102,28 +178,477 @@ pub enum ArgsCommand {
     },
     /// Validate the grammar.pest file for correctness.
     ValidateGrammar,
+    /// Print the grammar for editors and syntax highlighters.
+    Grammar {
+        /// How to print the grammar (ebnf or json).
+        #[arg(long, default_value_t = GrammarFormat::Ebnf, conflicts_with = "emit")]
+        format: GrammarFormat,
+        /// Write an editor grammar instead (textmate or treesitter).
+        #[arg(long, value_name = "EDITOR")]
+        emit: Option<EditorGrammar>,
+        /// Directory to write the editor grammar to.
+        #[arg(long, default_value = ".", requires = "emit")]
+        out: PathBuf,
+        /// Macro file whose macros the JSON names too. May be repeated.
+        #[arg(long = "prelude")]
+        preludes: Vec<PathBuf>,
+    },
+    /// Report problems found by static analysis of scripts.
+    Analyze {
+        /// The Sutra script files or project directories to analyze.
+        #[arg(required = true)]
+        files: Vec<PathBuf>,
+        /// List `tr` keys used in the scripts but missing from a catalog.
+        #[arg(long)]
+        missing_translations: bool,
+        /// Report each `deftable` table's total weight and odds, and its unreachable entries.
+        #[arg(long)]
+        audit_tables: bool,
+        /// Print each `defstate` state machine as a Graphviz DOT graph.
+        #[arg(long)]
+        dot_machines: bool,
+        /// Message catalog (`<locale>.toml` or `<locale>.json`) to check against. May be repeated.
+        /// Defaults to a project's catalogs.
+        #[arg(long = "catalog")]
+        catalogs: Vec<PathBuf>,
+        /// Parse every script again instead of reading cached ASTs.
+        #[arg(long)]
+        no_cache: bool,
+    },
+    /// Statically check a script against its `(: type name)` annotations.
+    Typecheck {
+        /// The path to the Sutra script file, or project directory, to check.
+        #[arg(required = true)]
+        file: PathBuf,
+        /// Parse and expand every script again instead of reading cached ASTs.
+        #[arg(long)]
+        no_cache: bool,
+    },
+    /// Find where a symbol or world path is used, searching scripts by their syntax.
+    Grep {
+        /// The symbol or dotted world path to find; a path also finds the paths below it.
+        pattern: String,
+        /// The script files, directories or project directories to search.
+        #[arg(default_value = ".")]
+        paths: Vec<PathBuf>,
+        /// Search the scripts after macro expansion instead of as written.
+        #[arg(long)]
+        expanded: bool,
+        /// Parse every script again instead of reading cached ASTs.
+        #[arg(long)]
+        no_cache: bool,
+    },
+    /// Rename a definition and every use of it, rewriting scripts in place.
+    Rename {
+        /// The name to rename; a module's definition is named `module/name`.
+        old: String,
+        /// The new name.
+        new: String,
+        /// The script files, directories or project directories to rename in. A project
+        /// covers its scripts, preludes and tests.
+        #[arg(default_value = ".")]
+        paths: Vec<PathBuf>,
+        /// Print a diff of each change instead of writing it.
+        #[arg(long)]
+        dry_run: bool,
+    },
     /// Pretty-print and normalize a script.
     Format {
-        /// The path to the Sutra script file to format.
+        /// The path to the Sutra script file, or project directory, to format.
         #[arg(required = true)]
         file: PathBuf,
     },
     /// Discover and run all test scripts in a directory.
     Test {
-        /// The path to the directory containing test scripts.
+        /// The directory containing test scripts, or a project directory whose
+        /// manifest names one.
         #[arg(default_value = "tests")]
         path: PathBuf,
+        /// Also write each test's outcome, and the summary, to this file as JSON.
+        #[arg(long)]
+        json: Option<PathBuf>,
+        /// Run the tests again whenever a file changes. Needs the `watch` feature.
+        #[arg(long)]
+        watch: bool,
+        /// Parse and expand every test again instead of reading cached ASTs.
+        #[arg(long)]
+        no_cache: bool,
+        /// Run only shard K of N (e.g. 2/5), so N CI jobs together run every test once.
+        #[arg(long)]
+        shard: Option<Shard>,
+        /// Run a failing test up to N more times, each with a fresh seed. Tests that
+        /// pass on a retry are reported as flaky.
+        #[arg(long, value_name = "N", default_value_t = 0)]
+        retries: usize,
+        /// A file naming known-flaky tests, one per line, whose failures are only warnings.
+        #[arg(long, value_name = "FILE")]
+        quarantine: Option<PathBuf>,
+        /// Run files and tests in a random order, printing the seed, to surface tests
+        /// that depend on running after others.
+        #[arg(long)]
+        shuffle: bool,
+        /// The seed for `--shuffle`, to repeat an order it printed.
+        #[arg(long, requires = "shuffle")]
+        seed: Option<u64>,
+    },
+    /// Delete the cache of parsed and expanded scripts kept by `test`, `typecheck` and
+    /// `analyze`, and the definitions kept by `run`.
+    Clean,
+    /// Discover and time all benchmark forms in a directory.
+    Bench {
+        /// The path to the directory containing benchmark scripts.
+        #[arg(default_value = "benches")]
+        path: PathBuf,
+        /// Compare against a previously saved results file and fail on regressions.
+        #[arg(long)]
+        baseline: Option<PathBuf>,
+        /// Write results as JSON, suitable for use as a later baseline.
+        #[arg(long)]
+        save: Option<PathBuf>,
+        /// Allowed slowdown of a benchmark's median, in percent.
+        #[arg(long, default_value_t = 10.0)]
+        threshold: f64,
+        /// Number of timed iterations per benchmark.
+        #[arg(long, default_value_t = 30)]
+        iterations: usize,
+        /// Number of untimed warmup iterations per benchmark.
+        #[arg(long, default_value_t = 5)]
+        warmup: usize,
+    },
+    /// List all available macros. With `--verbose`, show the usage and `;;;` doc
+    /// comment of each.
+    ListMacros {
+        /// Print name, kind, namespace, arity and doc of each macro as JSON.
+        #[arg(long)]
+        json: bool,
+        /// Macro file to load first. May be repeated.
+        #[arg(long = "prelude")]
+        preludes: Vec<PathBuf>,
     },
-    /// List all available macros with their documentation.
-    ListMacros,
     /// List all available atoms with their documentation.
-    ListAtoms,
+    ListAtoms {
+        /// Print name, kind, namespace, arity, argument types and doc of each atom as JSON.
+        #[arg(long)]
+        json: bool,
+    },
+    /// Show the usage and description of an atom or macro, or of every atom and
+    /// documented macro.
+    Doc {
+        /// The atom or macro to describe, e.g. `plural`.
+        name: Option<String>,
+        /// Macro file to load first. May be repeated.
+        #[arg(long = "prelude")]
+        preludes: Vec<PathBuf>,
+    },
+    /// Inspect the settings layered from defaults, the user config, the project and flags.
+    Config {
+        #[command(subcommand)]
+        command: ConfigCommand,
+    },
+    /// List the example scripts built into `sutra`, or show or run one.
+    Examples {
+        #[command(subcommand)]
+        command: Option<ExamplesCommand>,
+    },
+    /// Print a completion script for a shell (bash, zsh, fish, elvish or powershell).
+    Completions {
+        /// The shell to complete for.
+        shell: Shell,
+    },
     /// Show the Abstract Syntax Tree (AST) for a script.
     Ast {
         /// The path to the Sutra script file to parse.
         #[arg(required = true)]
         file: PathBuf,
+        /// How to print the AST (sexpr or json; default: sexpr).
+        #[arg(long)]
+        format: Option<AstFormat>,
+        /// Leave out source spans.
+        #[arg(long)]
+        no_spans: bool,
+        /// Show the AST after macro expansion.
+        #[arg(long)]
+        expanded: bool,
+    },
+}
+
+/// The subcommands of `sutra config`.
+#[derive(Debug, Subcommand)]
+pub enum ConfigCommand {
+    /// Print the effective value of each setting and the layer it came from.
+    Show {
+        /// Project directory whose manifest is a layer, if it has one.
+        #[arg(long, default_value = ".")]
+        project: PathBuf,
+        #[command(flatten)]
+        pipeline: PipelineArgs,
+    },
+}
+
+/// The subcommands of `sutra examples`.
+#[derive(Debug, Subcommand)]
+pub enum ExamplesCommand {
+    /// List the examples and what each shows. The default.
+    List,
+    /// Print an example's script, after its prelude if it has one.
+    Show {
+        /// The example, e.g. `state`.
+        name: String,
     },
+    /// Run an example; the testing example runs its tests.
+    Run {
+        /// The example, e.g. `state`.
+        name: String,
+        #[command(flatten)]
+        pipeline: Box<PipelineArgs>,
+    },
+}
+
+/// How `sutra ast` prints an AST.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AstFormat {
+    /// Source text, one top-level form per line, which parses back to the same AST.
+    /// Spans are shown as a `; line:column` comment before each form.
+    Sexpr,
+    /// The serialized AST nodes, as a JSON array.
+    Json,
+}
+
+impl fmt::Display for AstFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Sexpr => write!(f, "sexpr"),
+            Self::Json => write!(f, "json"),
+        }
+    }
+}
+
+impl std::str::FromStr for AstFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "sexpr" => Ok(Self::Sexpr),
+            "json" => Ok(Self::Json),
+            _ => Err(format!(
+                "unknown AST format '{}' (expected sexpr or json)",
+                s
+            )),
+        }
+    }
+}
+
+/// How `sutra grammar` prints the grammar.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GrammarFormat {
+    /// One W3C EBNF production per rule.
+    Ebnf,
+    /// The rules, tokens and literals, with the names of every atom and macro.
+    Json,
+}
+
+impl fmt::Display for GrammarFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Ebnf => write!(f, "ebnf"),
+            Self::Json => write!(f, "json"),
+        }
+    }
+}
+
+impl std::str::FromStr for GrammarFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "ebnf" => Ok(Self::Ebnf),
+            "json" => Ok(Self::Json),
+            _ => Err(format!(
+                "unknown grammar format '{}' (expected ebnf or json)",
+                s
+            )),
+        }
+    }
+}
+
+/// The editor grammar `sutra grammar --emit` writes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EditorGrammar {
+    /// `sutra.tmLanguage.json`, for VS Code, Sublime Text and other TextMate editors.
+    TextMate,
+    /// A Tree-sitter `grammar.js` skeleton and `queries/highlights.scm`.
+    TreeSitter,
+}
+
+impl fmt::Display for EditorGrammar {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::TextMate => write!(f, "textmate"),
+            Self::TreeSitter => write!(f, "treesitter"),
+        }
+    }
+}
+
+impl std::str::FromStr for EditorGrammar {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "textmate" => Ok(Self::TextMate),
+            "treesitter" => Ok(Self::TreeSitter),
+            _ => Err(format!(
+                "unknown editor grammar '{}' (expected textmate or treesitter)",
+                s
+            )),
+        }
+    }
+}
+
+/// A class of diagnostics that `--deny` turns into errors.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Lint {
+    /// Every warning: definitions that hide an atom, macro or earlier definition,
+    /// and unused parameters.
+    Warnings,
+}
+
+impl fmt::Display for Lint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Warnings => write!(f, "warnings"),
+        }
+    }
+}
+
+impl std::str::FromStr for Lint {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "warnings" => Ok(Self::Warnings),
+            _ => Err(format!("unknown lint '{}' (expected warnings)", s)),
+        }
+    }
+}
+
+/// Execution options shared by commands that run scripts.
+#[derive(Debug, Args)]
+pub struct PipelineArgs {
+    /// Maximum evaluation recursion depth.
+    #[arg(long)]
+    pub max_depth: Option<usize>,
+    /// Maximum depth of macro expansions nested within one another.
+    #[arg(long)]
+    pub max_macro_depth: Option<usize>,
+    /// Maximum AST nodes the macro expansions of one program may produce.
+    #[arg(long)]
+    pub max_expansion_nodes: Option<usize>,
+    /// Seed for the world's random number generator.
+    #[arg(long)]
+    pub seed: Option<u64>,
+    /// Macro file to load before the script. May be repeated.
+    #[arg(long = "prelude")]
+    pub preludes: Vec<PathBuf>,
+    /// Message catalog (`<locale>.toml` or `<locale>.json`) for `tr`. May be repeated.
+    #[arg(long = "catalog")]
+    pub catalogs: Vec<PathBuf>,
+    /// Locale `tr` looks messages up in before falling back to the default.
+    #[arg(long)]
+    pub locale: Option<String>,
+    /// Grant only the `state` and `random` capabilities, for untrusted scripts.
+    #[arg(long)]
+    pub sandbox: bool,
+    /// Capability to grant on top of `--sandbox` (io, state, random, time or eval; never net). May be repeated.
+    #[arg(long = "allow", requires = "sandbox", value_parser = parse_sandbox_capability)]
+    pub allowed: Vec<Capability>,
+    /// Most elements a list or map may hold.
+    #[arg(long)]
+    pub max_collection_len: Option<usize>,
+    /// Most bytes a string may hold.
+    #[arg(long)]
+    pub max_string_bytes: Option<usize>,
+    /// Most entries the world state may hold.
+    #[arg(long)]
+    pub max_world_size: Option<usize>,
+    /// What `set!` does when a parent path is missing (vivify, warn or strict).
+    #[arg(long)]
+    pub paths: Option<PathPolicy>,
+    /// Text `print` and `println` write between their arguments (default: none).
+    #[arg(long)]
+    pub print_separator: Option<String>,
+    /// Evaluate scripts as expanded, without folding constants first.
+    #[arg(long)]
+    pub no_optimize: bool,
+    /// Skip `assert` calls without evaluating them, as for a shipping build.
+    #[arg(long)]
+    pub no_assert: bool,
+    /// Diagnostics to treat as errors: `warnings` fails a prelude or script that has
+    /// any before it runs.
+    #[arg(long = "deny", value_name = "LINT")]
+    pub denied: Vec<Lint>,
+    /// The least important `log/...` messages written (debug, info or warn).
+    #[arg(long)]
+    pub log_level: Option<LogLevel>,
+    /// Arguments after `--`, passed to the script and returned by `(args)`.
+    #[arg(last = true)]
+    pub script_args: Vec<String>,
+}
+
+/// Parses an `--allow` capability. `net` is refused: sandboxed scripts never reach the network.
+fn parse_sandbox_capability(s: &str) -> Result<Capability, String> {
+    match s.parse()? {
+        Capability::Net => Err("the net capability cannot be granted to sandboxed scripts".into()),
+        capability => Ok(capability),
+    }
+}
+
+impl PipelineArgs {
+    /// Maps each flag onto the corresponding builder call.
+    pub fn builder(&self) -> ExecutionPipelineBuilder {
+        self.configure(ExecutionPipeline::builder())
+    }
+
+    /// Applies the flags on top of `builder`, e.g. one set up from a project manifest.
+    pub fn configure(&self, mut builder: ExecutionPipelineBuilder) -> ExecutionPipelineBuilder {
+        if self.sandbox {
+            let mut capabilities = SANDBOX_CAPABILITIES.to_vec();
+            capabilities.extend(&self.allowed);
+            builder = builder
+                .with_capabilities(&capabilities)
+                .with_resource_limits(SANDBOX_LIMITS);
+        }
+        builder = self.settings().configure(builder);
+        if self.no_optimize {
+            builder = builder.with_optimization(false);
+        }
+        if self.no_assert {
+            builder = builder.with_assertions(false);
+        }
+        if self.denied.contains(&Lint::Warnings) {
+            builder = builder.with_deny_warnings(true);
+        }
+        builder.with_script_args(self.script_args.iter().cloned())
+    }
+
+    /// The settings the flags give, as a layer over the user config and project.
+    pub fn settings(&self) -> Settings {
+        Settings {
+            preludes: self.preludes.clone(),
+            catalogs: self.catalogs.clone(),
+            locale: self.locale.clone(),
+            seed: self.seed,
+            max_depth: self.max_depth,
+            max_macro_depth: self.max_macro_depth,
+            max_expansion_nodes: self.max_expansion_nodes,
+            max_collection_len: self.max_collection_len,
+            max_string_bytes: self.max_string_bytes,
+            max_world_size: self.max_world_size,
+            paths: self.paths,
+            print_separator: self.print_separator.clone(),
+            log_level: self.log_level,
+            ..Settings::default()
+        }
+    }
 }
 
 // ============================================================================
@@ -141,114 +666,566 @@ impl SutraEngine {
         Self {
             world: build_canonical_world(),
             macro_env: build_canonical_macro_env().unwrap_or_else(|e| {
-                eprintln!("Warning: Failed to load standard macros: {}", e);
+                tracing::warn!("failed to load standard macros: {e}");
                 MacroSystem::new()
             }),
         }
     }
 
-    fn execute(&mut self, source: &str, filename: &str) -> Result<(), SutraError> {
-        let source_context = SourceContext::from_file(filename, source);
+    fn expand_macros(&mut self, name: &str, source: &str) -> Result<String, SutraError> {
+        let source_context = SourceContext::from_file(name, source);
         let ast_nodes = parser::parse(source, source_context.clone())?;
         let program = parser::wrap_in_do(ast_nodes);
-        let expanded = self.macro_env.expand(program)?;
-        let output = SharedOutput::new(EngineStdoutSink);
-        let result = evaluate(
-            &expanded,
-            self.world.clone(),
-            output.clone(),
-            source_context,
-        )?;
+        let expanded = self
+            .macro_env
+            .expand(program)
+            .map_err(|e| e.with_source(&source_context))?;
+        Ok(expanded.value.pretty())
+    }
 
-        if !result.is_nil() {
-            output.emit(&result.to_string(), None);
-        }
-        Ok(())
+    /// The program in `source`, and each step of its expansion (see
+    /// [`MacroSystem::expansion_steps`]).
+    fn expansion_steps(
+        &self,
+        name: &str,
+        source: &str,
+        only: Option<&str>,
+    ) -> Result<(AstNode, Vec<ExpansionStep>), SutraError> {
+        let source_context = SourceContext::from_file(name, source);
+        let program = parser::wrap_in_do(parser::parse(source, source_context.clone())?);
+        let steps = self
+            .macro_env
+            .expansion_steps(program.clone(), only)
+            .map_err(|e| e.with_source(&source_context))?;
+        Ok((program, steps))
     }
 
-    fn expand_macros(&mut self, source: &str) -> Result<String, SutraError> {
-        let source_context = SourceContext::from_file("source", source);
-        let ast_nodes = parser::parse(source, source_context)?;
-        let program = parser::wrap_in_do(ast_nodes);
-        let expanded = self.macro_env.expand(program)?;
-        Ok(expanded.value.pretty())
+    fn format(&self, name: &str, source: &str) -> Result<String, SutraError> {
+        let source_context = SourceContext::from_file(name, source);
+        parser::format(source, source_context)
     }
 
-    fn trace_macros(&mut self, source: &str) -> Result<(), SutraError> {
-        // TODO: Implement actual tracing with step-by-step expansion
-        let expanded = self.expand_macros(source)?;
-        println!("{expanded}");
-        Ok(())
+    fn parse(&self, name: &str, source: &str) -> Result<Vec<AstNode>, SutraError> {
+        let source_context = SourceContext::from_file(name, source);
+        parser::parse(source, source_context)
     }
 
-    fn format(&self, source: &str) -> Result<String, SutraError> {
-        let source_context = SourceContext::from_file("source", source);
-        let ast_nodes = parser::parse(source, source_context)?;
-        let program = parser::wrap_in_do(ast_nodes);
-        Ok(program.value.pretty())
+    /// The program in `source` after macro expansion, as a single `do` form when it
+    /// has several.
+    fn expand(&self, name: &str, source: &str) -> Result<AstNode, SutraError> {
+        let source_context = SourceContext::from_file(name, source);
+        let program = parser::wrap_in_do(parser::parse(source, source_context.clone())?);
+        self.macro_env
+            .expand(program)
+            .map_err(|e| e.with_source(&source_context))
     }
 
-    fn parse(&self, source: &str) -> Result<Vec<AstNode>, SutraError> {
-        let source_context = SourceContext::from_file("source", source);
-        parser::parse(source, source_context)
+    /// Loads the macros defined in each of `paths`, in order.
+    fn load_preludes(&mut self, paths: &[PathBuf]) -> Result<(), SutraError> {
+        for path in paths {
+            self.macro_env
+                .load_from_source(&path.display().to_string(), &read_file(path)?)?;
+        }
+        Ok(())
     }
 
     fn list_macros(&self) -> Vec<String> {
         self.macro_env.macro_names()
     }
 
+    /// Usage and doc comment of the named macro. Native macros have only a name.
+    fn macro_doc(&self, name: &str) -> Option<String> {
+        match self.macro_env.get_macro(name)? {
+            MacroDefinition::Template(template) => {
+                let usage = template.usage(name);
+                Some(match &template.doc {
+                    Some(doc) => format!("{usage}\n    {}", doc.replace('\n', "\n    ")),
+                    None => usage,
+                })
+            }
+            _ => Some(name.to_string()),
+        }
+    }
+
+    /// Usage and doc comment of every macro, sorted by name.
+    fn macro_docs(&self) -> Vec<String> {
+        let mut names = self.list_macros();
+        names.sort();
+        names
+            .iter()
+            .filter_map(|name| self.macro_doc(name))
+            .collect()
+    }
+
+    /// Names of the macros that have a doc comment, sorted.
+    fn documented_macros(&self) -> Vec<String> {
+        let mut names: Vec<String> = self
+            .list_macros()
+            .into_iter()
+            .filter(|name| {
+                matches!(
+                    self.macro_env.get_macro(name),
+                    Some(MacroDefinition::Template(MacroTemplate {
+                        doc: Some(_),
+                        ..
+                    }))
+                )
+            })
+            .collect();
+        names.sort();
+        names
+    }
+
     fn list_atoms(&self) -> Vec<String> {
-        let world = self.world.borrow();
+        let world = self.world.read();
         if let Some(Value::Map(map)) = world.state.get(&crate::atoms::Path(vec![])) {
             map.keys().cloned().collect()
         } else {
             vec![]
         }
     }
+
+    /// One entry per macro, sorted by name. Template macros report their arity.
+    fn macros_json(&self) -> serde_json::Value {
+        let mut names = self.list_macros();
+        names.sort();
+        let entries = names
+            .iter()
+            .map(|name| {
+                let mut entry = serde_json::json!({
+                    "name": name,
+                    "namespace": namespace(name),
+                });
+                match self.macro_env.get_macro(name) {
+                    Some(MacroDefinition::Template(template)) => {
+                        entry["kind"] = "template".into();
+                        entry["min_args"] = template.params.required.len().into();
+                        entry["max_args"] = match template.params.rest {
+                            Some(_) => serde_json::Value::Null,
+                            None => template.params.required.len().into(),
+                        };
+                        entry["doc"] = template.doc.clone().into();
+                    }
+                    _ => entry["kind"] = "native".into(),
+                }
+                entry
+            })
+            .collect();
+        serde_json::Value::Array(entries)
+    }
+
+    /// Usage and description of the named atom or macro, or of every atom with a
+    /// spec followed by every macro with a doc comment.
+    fn atom_docs(&self, name: Option<&str>) -> Result<Vec<String>, SutraError> {
+        let world = self.world.read();
+        let Some(name) = name else {
+            let atoms = world.atom_specs.values();
+            let atoms = atoms.map(|spec| format!("{}\n    {}", spec.usage(), spec.doc));
+            let macros = self.documented_macros();
+            let macros = macros.iter().filter_map(|name| self.macro_doc(name));
+            return Ok(atoms.chain(macros).collect());
+        };
+        if let Some(spec) = world.atom_specs.get(name) {
+            return Ok(vec![format!("{}\n    {}", spec.usage(), spec.doc)]);
+        }
+        let doc = self.macro_doc(name).ok_or_else(|| {
+            SutraError::without_source(
+                ErrorKind::UndefinedSymbol {
+                    symbol: name.to_string(),
+                },
+                "doc",
+                "cli",
+            )
+        })?;
+        Ok(vec![doc])
+    }
+
+    /// One entry per atom, sorted by name.
+    fn atoms_json(&self) -> serde_json::Value {
+        let world = self.world.read();
+        let mut names = self.list_atoms();
+        names.sort();
+        let entries = names
+            .iter()
+            .map(|name| {
+                let mut entry = serde_json::json!({
+                    "name": name,
+                    "kind": null,
+                    "namespace": namespace(name),
+                });
+                if let Some(spec) = world.atom_specs.get(name) {
+                    entry["kind"] = serde_json::json!(spec.kind);
+                    entry["min_args"] = spec.min_args.into();
+                    entry["max_args"] = serde_json::json!(spec.max_args);
+                    entry["arg_types"] = serde_json::json!(spec.arg_types);
+                    entry["doc"] = spec.doc.into();
+                    entry["capabilities"] = serde_json::json!(spec.capabilities);
+                }
+                entry
+            })
+            .collect();
+        serde_json::Value::Array(entries)
+    }
+
+    /// The names of the atoms, special forms and macros, each sorted.
+    fn keywords(&self) -> Keywords {
+        let world = self.world.read();
+        let mut names = self.list_atoms();
+        names.sort();
+        let (special_forms, atoms) = names.into_iter().partition(|name| {
+            world
+                .atom_specs
+                .get(name)
+                .is_some_and(|spec| spec.kind == AtomKind::SpecialForm)
+        });
+        let mut macros = self.list_macros();
+        macros.sort();
+        Keywords {
+            atoms,
+            special_forms,
+            macros,
+        }
+    }
+}
+
+/// The prefix of a namespaced name such as `text/pick`, if it has one.
+fn namespace(name: &str) -> Option<&str> {
+    name.split_once('/')
+        .filter(|(prefix, rest)| !prefix.is_empty() && !rest.is_empty())
+        .map(|(prefix, _)| prefix)
+}
+
+/// Prints `nodes`, parsed from `source`, in `format`.
+fn print_ast(
+    nodes: &[AstNode],
+    source: &str,
+    format: AstFormat,
+    spans: bool,
+) -> Result<(), SutraError> {
+    match format {
+        AstFormat::Json => {
+            let json = serde_json::to_value(nodes).map_err(|e| {
+                SutraError::without_source(
+                    ErrorKind::GeneralValidation {
+                        message: e.to_string(),
+                    },
+                    "stdout",
+                    "cli",
+                )
+            })?;
+            print_json(&if spans { json } else { without_spans(json) })
+        }
+        AstFormat::Sexpr => {
+            for node in nodes {
+                if spans {
+                    let (line, column) = line_and_column(source, node.span.start);
+                    println!("; {line}:{column}");
+                }
+                println!("{}", node.value.pretty());
+            }
+            Ok(())
+        }
+    }
+}
+
+/// `json`, a serialized AST, without its spans: `span` fields are dropped, nodes
+/// become their expressions, and a variant that held a span beside one value
+/// becomes that value, so `{"Number": [1.0, {"start": 0, "end": 1}]}` is
+/// `{"Number": 1.0}`.
+fn without_spans(json: serde_json::Value) -> serde_json::Value {
+    use serde_json::Value as Json;
+    let is_span = |json: &Json| {
+        json.as_object().is_some_and(|fields| {
+            fields.len() == 2 && fields.contains_key("start") && fields.contains_key("end")
+        })
+    };
+    match json {
+        Json::Object(mut fields) => {
+            fields.remove("span");
+            if fields.len() == 1 {
+                if let Some(value) = fields.remove("value") {
+                    return without_spans(value);
+                }
+            }
+            Json::Object(
+                fields
+                    .into_iter()
+                    .map(|(key, value)| (key, without_spans(value)))
+                    .collect(),
+            )
+        }
+        Json::Array(items) => {
+            let len = items.len();
+            let mut kept: Vec<Json> = items
+                .into_iter()
+                .filter(|item| !is_span(item))
+                .map(without_spans)
+                .collect();
+            if kept.len() == 1 && len > 1 {
+                kept.remove(0)
+            } else {
+                Json::Array(kept)
+            }
+        }
+        other => other,
+    }
+}
+
+/// The user config's settings, or none without one.
+fn user_settings() -> Result<Settings, SutraError> {
+    Ok(args::user_config()?
+        .map(|(_, settings)| settings)
+        .unwrap_or_default())
+}
+
+/// The built-in example called `name`, or an error suggesting the closest one.
+fn find_example(name: &str) -> Result<&'static examples::Example, SutraError> {
+    examples::find(name).ok_or_else(|| {
+        let names = examples::EXAMPLES.iter().map(|example| example.name);
+        let help = match closest_name(name, names) {
+            Some(close) => format!("did you mean `{close}`? `sutra examples` lists them"),
+            None => "`sutra examples` lists them".to_string(),
+        };
+        SutraError::without_source(
+            ErrorKind::UndefinedSymbol {
+                symbol: name.to_string(),
+            },
+            "examples",
+            "cli",
+        )
+        .with_help(help)
+    })
+}
+
+/// Writes `example` to a temporary directory and runs it from there, with its
+/// prelude added to `pipeline`'s, or runs its tests.
+fn run_example(
+    example: &examples::Example,
+    mut pipeline: Box<PipelineArgs>,
+) -> Result<(), SutraError> {
+    let dir = std::env::temp_dir().join("sutra-examples");
+    let (script, prelude) = example.write_to(&dir)?;
+    if example.tests {
+        return run_tests(script, None, &TestOptions::default());
+    }
+    pipeline.preludes.extend(prelude);
+    run_files(vec![script], false, false, None, None, None, &pipeline)
+}
+
+/// Prints each setting's effective value and the layers it came from, after the
+/// files read for them.
+fn show_config(project: &Path, flags: &PipelineArgs) -> Result<(), SutraError> {
+    let mut layers = vec![(Source::Default, Settings::defaults())];
+    match args::user_config()? {
+        Some((path, settings)) => {
+            println!("# user config: {}", path.display());
+            layers.push((Source::User, settings));
+        }
+        None => match args::user_config_path() {
+            Some(path) => println!("# user config: {} (not found)", path.display()),
+            None => println!("# user config: none"),
+        },
+    }
+    if let Some(project) = Project::at(project)? {
+        println!(
+            "# project: {}",
+            project.resolve(Path::new(MANIFEST_FILE)).display()
+        );
+        layers.push((Source::Project, Settings::from_project(&project)));
+    }
+    layers.push((Source::Flag, flags.settings()));
+
+    for (name, value, sources) in args::merge(&layers) {
+        let Some(value) = value else {
+            println!("{name} = (not set)");
+            continue;
+        };
+        let sources: Vec<_> = sources.iter().map(ToString::to_string).collect();
+        println!("{name} = {value}  # {}", sources.join(", "));
+    }
+    Ok(())
+}
+
+/// Prints every command, with its arguments as JSON, or one per line with what it does.
+fn list_commands(json: bool) -> Result<(), SutraError> {
+    let mut cli = SutraArgs::command();
+    cli.build();
+    if json {
+        return print_json(&commands_json(&cli));
+    }
+    for command in cli
+        .get_subcommands()
+        .filter(|command| !command.is_hide_set())
+    {
+        let about = command.get_about().map(|about| about.to_string());
+        println!("{}\t{}", command.get_name(), about.unwrap_or_default());
+    }
+    Ok(())
+}
+
+/// The visible subcommands of `command` and their arguments, as a JSON array.
+fn commands_json(command: &clap::Command) -> serde_json::Value {
+    let commands = command
+        .get_subcommands()
+        .filter(|command| !command.is_hide_set())
+        .map(|command| {
+            let args: Vec<_> = command
+                .get_arguments()
+                .filter(|arg| !arg.is_hide_set())
+                .map(arg_json)
+                .collect();
+            serde_json::json!({
+                "name": command.get_name(),
+                "about": command.get_about().map(|about| about.to_string()),
+                "args": args,
+                "subcommands": commands_json(command),
+            })
+        })
+        .collect();
+    serde_json::Value::Array(commands)
+}
+
+fn arg_json(arg: &clap::Arg) -> serde_json::Value {
+    let values = |values: Vec<String>| serde_json::Value::from(values);
+    serde_json::json!({
+        "name": arg.get_id().as_str(),
+        "long": arg.get_long(),
+        "short": arg.get_short().map(String::from),
+        "help": arg.get_help().map(|help| help.to_string()),
+        "positional": arg.is_positional(),
+        "required": arg.is_required_set(),
+        "takes_value": arg.get_action().takes_values(),
+        "repeatable": matches!(arg.get_action(), clap::ArgAction::Append | clap::ArgAction::Count)
+            || arg.get_num_args().is_some_and(|range| range.max_values() > 1),
+        "values": values(
+            arg.get_possible_values()
+                .iter()
+                .map(|value| value.get_name().to_string())
+                .collect()
+        ),
+        "default": values(if arg.get_action().takes_values() {
+            arg.get_default_values()
+                .iter()
+                .map(|value| value.to_string_lossy().into_owned())
+                .collect()
+        } else {
+            Vec::new()
+        }),
+    })
+}
+
+fn print_json(value: &serde_json::Value) -> Result<(), SutraError> {
+    let json = serde_json::to_string_pretty(value).map_err(|e| {
+        SutraError::without_source(
+            ErrorKind::GeneralValidation {
+                message: e.to_string(),
+            },
+            "stdout",
+            "cli",
+        )
+    })?;
+    println!("{json}");
+    Ok(())
+}
+
+/// Run a script through the execution pipeline, printing its final value if non-nil
+fn run_source(
+    pipeline: &ExecutionPipeline,
+    source: &str,
+    filename: &str,
+) -> Result<(), SutraError> {
+    let result = pipeline.execute_for_value(source, filename)?;
+    print_value(pipeline, &result);
+    Ok(())
+}
+
+/// Writes a script's final value to the pipeline's output, unless it is nil.
+fn print_value(pipeline: &ExecutionPipeline, value: &Value) {
+    if !value.is_nil() {
+        pipeline.output.emit(&format!("{value}\n"), None);
+    }
+}
+
+/// Prints the warnings `pipeline` has collected, then how many there were.
+fn print_warnings(pipeline: &ExecutionPipeline) {
+    let warnings = pipeline.take_warnings();
+    if warnings.is_empty() {
+        return;
+    }
+    let count = warnings.len();
+    for warning in warnings {
+        print_error(warning);
+    }
+    eprintln!("{count} warning(s)");
+}
+
+/// Print a world diff, one change per line
+fn print_world_diff(diff: &WorldDiff) {
+    if diff.is_empty() {
+        println!("World unchanged.");
+    } else {
+        print!("{diff}");
+    }
 }
 
 /// Read a file with proper error handling
 fn read_file(path: &Path) -> Result<String, SutraError> {
     let filename = path.to_str().ok_or_else(|| {
-        let context = ValidationContext {
-            source: SourceContext::fallback("read_file"),
-            phase: "file-system".to_string(),
-        };
-        context.report(
-            ErrorKind::InvalidPath {
-                path: path.to_string_lossy().to_string(),
-            },
-            errors::unspanned(),
+        let path = path.to_string_lossy().to_string();
+        SutraError::without_source(
+            ErrorKind::InvalidPath { path: path.clone() },
+            path,
+            "file-system",
         )
     })?;
 
     std::fs::read_to_string(filename).map_err(|error| {
-        let context = ValidationContext {
-            source: SourceContext::fallback("read_file"),
-            phase: "file-system".to_string(),
-        };
-        context.report(
+        SutraError::without_source(
             ErrorKind::InvalidPath {
                 path: format!("{} ({})", filename, error),
             },
-            errors::unspanned(),
+            filename,
+            "file-system",
         )
     })
 }
 
+/// The cache of parsed and expanded scripts, unless `--no-cache` was given.
+fn script_cache(no_cache: bool) -> Option<AstCache> {
+    (!no_cache).then(AstCache::default)
+}
+
+/// Delete the script cache, reporting whether there was one.
+fn clean_cache() -> Result<(), SutraError> {
+    let cache = AstCache::default();
+    let dir = cache.dir().display().to_string();
+    let removed = cache.clear().map_err(|error| {
+        SutraError::without_source(
+            ErrorKind::InvalidPath {
+                path: format!("{} ({})", dir, error),
+            },
+            &dir,
+            "file-system",
+        )
+    })?;
+    if removed {
+        println!("Removed {dir}");
+    } else {
+        println!("No cache to remove");
+    }
+    Ok(())
+}
+
 /// Read from stdin with proper error handling
 fn read_stdin() -> Result<String, SutraError> {
     let mut buffer = String::new();
     std::io::stdin().read_to_string(&mut buffer).map_err(|e| {
-        let context = ValidationContext {
-            source: SourceContext::fallback("read_stdin"),
-            phase: "input".to_string(),
-        };
-        context.report(
+        SutraError::without_source(
             ErrorKind::InvalidPath {
                 path: format!("stdin ({})", e),
             },
-            errors::unspanned(),
+            "<stdin>",
+            "input",
         )
     })?;
     Ok(buffer)
@@ -256,20 +1233,16 @@ fn read_stdin() -> Result<String, SutraError> {
 
 /// Validate grammar file
 fn validate_grammar() -> Result<(), SutraError> {
-    use crate::grammar_validation;
-    let validation_errors = grammar_validation::validate_grammar("src/grammar/grammar.pest")
-        .map_err(|e| {
-            let context = ValidationContext {
-                source: SourceContext::from_file("sutra-cli", ""),
-                phase: "grammar-validation".to_string(),
-            };
-            context.report(
-                ErrorKind::InvalidPath {
-                    path: format!("Failed to validate grammar: {}", e),
-                },
-                errors::unspanned(),
-            )
-        })?;
+    const GRAMMAR_PATH: &str = "src/grammar/grammar.pest";
+    let validation_errors = grammar_validation::validate_grammar(GRAMMAR_PATH).map_err(|e| {
+        SutraError::without_source(
+            ErrorKind::InvalidPath {
+                path: format!("Failed to validate grammar: {}", e),
+            },
+            GRAMMAR_PATH,
+            "grammar-validation",
+        )
+    })?;
 
     if validation_errors.is_empty() {
         println!("Grammar validation passed");
@@ -283,115 +1256,1040 @@ fn validate_grammar() -> Result<(), SutraError> {
     Ok(())
 }
 
-/// Enhanced test runner with detailed reporting
-pub fn run_tests(path: PathBuf) -> Result<(), SutraError> {
-    let test_files = TestDiscoverer::discover_test_files(path).map_err(|e| {
-        let context = ValidationContext {
-            source: SourceContext::fallback("run_tests"),
-            phase: "test-discovery".to_string(),
-        };
-        context.report(
-            ErrorKind::InvalidPath {
-                path: format!("Test discovery failed: {}", e),
+/// The rules of the built-in grammar.
+fn grammar_rules() -> Result<Vec<grammar_validation::Rule>, SutraError> {
+    grammar_validation::parse_grammar(grammar_validation::GRAMMAR).map_err(|e| {
+        SutraError::without_source(
+            ErrorKind::GeneralValidation {
+                message: e.to_string(),
             },
-            errors::unspanned(),
+            "grammar.pest",
+            "grammar-export",
         )
-    })?;
-
-    let mut file_summaries = Vec::new();
-    let mut overall_summary = TestSummary::default();
-    let mut error_categories: HashMap<String, usize> = HashMap::new();
+    })
+}
 
-    for file_path in test_files {
-        let mut file_summary = FileTestSummary::new(file_path.clone());
+/// Prints the built-in grammar in `format`. The JSON also names every atom, special
+/// form and macro, so that highlighters can tell them from other symbols.
+fn print_grammar(engine: &SutraEngine, format: GrammarFormat) -> Result<(), SutraError> {
+    let rules = grammar_rules()?;
+    if format == GrammarFormat::Ebnf {
+        print!("{}", grammar_validation::to_ebnf(&rules));
+        return Ok(());
+    }
+    let keywords = engine.keywords();
+    let mut json = grammar_validation::to_json(&rules);
+    json["atoms"] = keywords.atoms.into();
+    json["special_forms"] = keywords.special_forms.into();
+    json["macros"] = keywords.macros.into();
+    print_json(&json)
+}
 
-        let test_forms = TestDiscoverer::extract_tests_from_file(&file_path).map_err(|e| {
-            let context = ValidationContext {
-                source: SourceContext::fallback("run_tests"),
-                phase: "test-extraction".to_string(),
-            };
-            context.report(
+/// Writes the files of `editor`'s grammar to `out`, generated from the built-in
+/// grammar and the engine's atoms and macros.
+fn emit_grammar(engine: &SutraEngine, editor: EditorGrammar, out: &Path) -> Result<(), SutraError> {
+    let rules = grammar_rules()?;
+    let keywords = engine.keywords();
+    let files = match editor {
+        EditorGrammar::TextMate => {
+            let grammar = editor_grammar::textmate(&rules, &keywords);
+            let json = serde_json::to_string_pretty(&grammar).unwrap_or_default();
+            vec![("sutra.tmLanguage.json", json + "\n")]
+        }
+        EditorGrammar::TreeSitter => vec![
+            ("grammar.js", editor_grammar::tree_sitter(&rules)),
+            (
+                "queries/highlights.scm",
+                editor_grammar::highlights(&rules, &keywords),
+            ),
+        ],
+    };
+    for (name, contents) in files {
+        let path = out.join(name);
+        let written = path
+            .parent()
+            .map_or(Ok(()), fs::create_dir_all)
+            .and_then(|()| fs::write(&path, contents));
+        written.map_err(|e| {
+            SutraError::without_source(
                 ErrorKind::InvalidPath {
-                    path: format!(
-                        "Failed to extract tests from {}: {}",
-                        file_path.display(),
-                        e
-                    ),
+                    path: format!("{} ({e})", path.display()),
                 },
-                errors::unspanned(),
+                path.display().to_string(),
+                "grammar-export",
             )
         })?;
+        println!("Wrote {}", path.display());
+    }
+    Ok(())
+}
 
-        for test_form in test_forms {
-            match TestRunner::run_single_test(&test_form) {
-                Ok(()) => {
-                    // Print passed test immediately
-                    use std::io::Write;
-                    let mut stdout = StandardStream::stdout(ColorChoice::Auto);
-                    stdout
-                        .set_color(ColorSpec::new().set_fg(Some(Color::Green)))
-                        .ok();
-                    write!(&mut stdout, "✓").ok();
-                    stdout.reset().ok();
-                    println!(" {}", test_form.name);
-
-                    file_summary.add_result(true);
-                }
-                Err(e) => {
-                    // Print failed test with rich miette diagnostic immediately
-                    use std::io::Write;
-                    let mut stdout = StandardStream::stdout(ColorChoice::Auto);
-                    stdout
-                        .set_color(ColorSpec::new().set_fg(Some(Color::Red)))
-                        .ok();
-                    write!(&mut stdout, "✗").ok();
-                    stdout.reset().ok();
-                    println!(" {}", test_form.name);
-
-                    // Print rich miette diagnostic
-                    print_error(e);
-
-                    // Categorize error for summary (recreate since we consumed e)
-                    let error_category = "Runtime: Test Failed"; // Simplified for now since we consumed e
-                    *error_categories
-                        .entry(error_category.to_string())
-                        .or_insert(0) += 1;
-
-                    file_summary.add_result(false);
-                }
+/// Replaces a project directory in `paths` with the project's scripts, in load order.
+/// Returns the project too, if there was one.
+fn project_scripts(paths: Vec<PathBuf>) -> Result<(Vec<PathBuf>, Option<Project>), SutraError> {
+    let mut scripts = Vec::new();
+    let mut found = None;
+    for path in paths {
+        match Project::at(&path)? {
+            Some(project) => {
+                scripts.extend(project.source_files()?);
+                found.get_or_insert(project);
             }
+            None => scripts.push(path),
         }
+    }
+    Ok((scripts, found))
+}
 
-        // Print file summary with colors
-        print_file_summary(&file_summary);
-
-        let file_test_summary = file_summary.summary();
-        overall_summary.passed += file_test_summary.passed;
-        overall_summary.failed += file_test_summary.failed;
-
-        file_summaries.push(file_summary);
+/// Runs `files`, or the project they name, against one fresh world
+fn run_files(
+    files: Vec<PathBuf>,
+    dump_world_diff: bool,
+    stats: bool,
+    record: Option<PathBuf>,
+    entry: Option<String>,
+    cache: Option<&AstCache>,
+    pipeline: &PipelineArgs,
+) -> Result<(), SutraError> {
+    let (files, project) = project_scripts(files)?;
+    let mut builder = args::layered_builder(project.as_ref(), pipeline)?;
+    let mut scripts = Vec::with_capacity(files.len());
+    for file in files {
+        let source = read_file(&file)?;
+        scripts.push(LoggedFile {
+            name: file.display().to_string(),
+            source,
+        });
     }
 
-    // Print overall summary
-    print_overall_summary(&overall_summary, &error_categories);
+    // A recorded run needs a seed to write down, so it picks one if none was given.
+    let seed = record
+        .as_ref()
+        .map(|_| pipeline.seed.unwrap_or_else(rand::random));
+    if let Some(seed) = seed {
+        builder = builder.with_seed(seed);
+    }
+    let pipeline = builder.build()?;
+    if let (Some(path), Some(seed)) = (&record, seed) {
+        let header = Header {
+            version: REPLAY_VERSION,
+            seed,
+            args: pipeline.world.read().script_args.clone(),
+            files: scripts.clone(),
+            entry: entry.clone(),
+        };
+        let log = fs::File::create(path)
+            .and_then(|file| Session::record(Box::new(io::LineWriter::new(file)), &header))
+            .map_err(|e| log_error(format!("{}: {e}", path.display())))?;
+        pipeline.world.write().session = Some(SharedSession::new(log));
+    }
+
+    let before = pipeline.world.read().state.clone();
+    let ran = run_logged_files(&pipeline, &scripts, entry.as_deref(), cache);
+    let finished = finish_session(&pipeline);
+    print_warnings(&pipeline);
+    ran?;
+    finished?;
+    if dump_world_diff {
+        print_world_diff(&WorldDiff::between(&before, &pipeline.world.read().state));
+    }
+    if stats {
+        let stats = pipeline.macro_env.expansion_stats();
+        eprintln!(
+            "Macro expansions: {} ({} from cache, {:.1}% hit rate)",
+            stats.expansions,
+            stats.hits,
+            stats.hit_rate() * 100.0
+        );
+    }
+    Ok(())
+}
+
+/// The function `run` calls once the scripts have loaded, if they define it.
+const DEFAULT_ENTRY: &str = "main";
+
+/// Runs each script in order, noting the world's checksum after each in the session.
+/// If the scripts define an entry point (see [`entry_point`]), it is called after
+/// them, and the scripts' own values are not printed. With a `cache`, the definitions
+/// the scripts open with are restored from it (see [`preload`]).
+fn run_logged_files(
+    pipeline: &ExecutionPipeline,
+    scripts: &[LoggedFile],
+    entry: Option<&str>,
+    cache: Option<&AstCache>,
+) -> Result<(), SutraError> {
+    let entry = entry_point(scripts, entry)?;
+    let mut loader = preload::Loader::new(pipeline, cache);
+    for script in scripts {
+        tracing::info!("running {}", script.name);
+        let value = loader.execute(&script.source, &script.name)?;
+        if entry.is_none() {
+            print_value(pipeline, &value);
+        }
+        replay::checkpoint(&pipeline.world, &format!("after {}", script.name));
+    }
+    if let Some(entry) = entry {
+        tracing::info!("calling {entry}");
+        run_source(pipeline, &format!("({entry})"), &format!("<{entry}>"))?;
+        replay::checkpoint(&pipeline.world, &format!("after {entry}"));
+    }
+    Ok(())
+}
+
+/// The function called once `scripts` have loaded: `requested`, which they must
+/// define, or else `main` if they define it. When there is one, the scripts may only
+/// define things at top level, so that nothing runs before it.
+fn entry_point<'a>(
+    scripts: &[LoggedFile],
+    requested: Option<&'a str>,
+) -> Result<Option<&'a str>, SutraError> {
+    let entry = requested.unwrap_or(DEFAULT_ENTRY);
+    let mut parsed = Vec::with_capacity(scripts.len());
+    let mut found = false;
+    for script in scripts {
+        let source_context = SourceContext::from_file(&script.name, &script.source);
+        match parser::parse(&script.source, source_context.clone()) {
+            Ok(nodes) => {
+                found |= semantic::defines_entry_point(&nodes, entry)
+                    .map_err(|e| e.with_source(&source_context))?;
+                parsed.push((nodes, source_context));
+            }
+            Err(error) if requested.is_some() => return Err(error),
+            // Reported when the script runs, after the scripts before it
+            Err(_) => {}
+        }
+    }
+    if !found {
+        return match requested {
+            Some(entry) => Err(SutraError::without_source(
+                ErrorKind::GeneralValidation {
+                    message: format!("entry point '{entry}' is not defined by the scripts"),
+                },
+                "--entry",
+                "cli",
+            )
+            .with_help(format!("define it with (define ({entry}) ...)"))),
+            None => Ok(None),
+        };
+    }
+    for (nodes, source_context) in &parsed {
+        semantic::check_entry_script(nodes, entry).map_err(|e| e.with_source(source_context))?;
+    }
+    Ok(Some(entry))
+}
+
+/// Ends the pipeline's session, if it has one, returning the number of events replayed.
+fn finish_session(pipeline: &ExecutionPipeline) -> Result<usize, SutraError> {
+    let session = pipeline.world.write().session.take();
+    match session {
+        Some(session) => session.lock().finish(),
+        None => Ok(0),
+    }
+}
+
+/// Reruns the session logged at `path`, failing at the first point where it differs.
+fn run_replay(path: &Path, pipeline: &PipelineArgs) -> Result<(), SutraError> {
+    let log =
+        fs::read_to_string(path).map_err(|e| log_error(format!("{}: {e}", path.display())))?;
+    let (header, session) = Session::replay(&log)
+        .map_err(|reason| log_error(format!("{}: {reason}", path.display())))?;
+    let pipeline = args::layered_builder(None, pipeline)?
+        .with_seed(header.seed)
+        .with_script_args(header.args)
+        .build()?;
+    pipeline.world.write().session = Some(SharedSession::new(session));
+
+    // A script that failed when recorded fails again; what matters is whether it
+    // got there the same way.
+    if let Err(error) = run_logged_files(&pipeline, &header.files, header.entry.as_deref(), None) {
+        print_error(error);
+    }
+    let events = finish_session(&pipeline)?;
+    println!("Replay matched all {events} recorded events.");
+    Ok(())
+}
+
+/// Calls `run` now and whenever a file under `paths` changes, printing its errors
+/// instead of exiting
+#[cfg(feature = "watch")]
+fn watch_paths(
+    paths: &[PathBuf],
+    mut run: impl FnMut() -> Result<(), SutraError>,
+) -> Result<(), SutraError> {
+    crate::watch::watch(paths, || {
+        if let Err(error) = run() {
+            print_error(error);
+        }
+    })
+}
+
+#[cfg(not(feature = "watch"))]
+fn watch_paths(
+    paths: &[PathBuf],
+    _run: impl FnMut() -> Result<(), SutraError>,
+) -> Result<(), SutraError> {
+    let watched = paths
+        .iter()
+        .map(|path| path.display().to_string())
+        .collect::<Vec<_>>()
+        .join(", ");
+    Err(SutraError::without_source(
+        ErrorKind::GeneralValidation {
+            message: "--watch needs sutra built with the `watch` feature".to_string(),
+        },
+        watched,
+        "watch",
+    ))
+}
+
+/// Typecheck each script, using a project's preludes if `file` is a project directory
+fn typecheck_files(file: PathBuf, cache: Option<&AstCache>) -> Result<(), SutraError> {
+    let (files, project) = project_scripts(vec![file])?;
+    let macros = args::base_builder(project.as_ref())?.build()?.macro_env;
+
+    let mut count = 0;
+    for file in files {
+        let source = read_file(&file)?;
+        count += typecheck_source(&source, &file.display().to_string(), &macros, cache)?;
+    }
+    if count == 0 {
+        println!("No type errors found");
+        return Ok(());
+    }
+    eprintln!("{count} type error(s) found");
+    process::exit(1);
+}
+
+/// Print each use of `pattern` in `paths` as `file:line:col: form`, exiting with
+/// status 1 when there are none, as grep does.
+fn grep_scripts(
+    pattern: &str,
+    paths: Vec<PathBuf>,
+    expanded: bool,
+    cache: Option<&AstCache>,
+) -> Result<(), SutraError> {
+    let (paths, project) = project_scripts(paths)?;
+    let macros = if expanded {
+        Some(args::base_builder(project.as_ref())?.build()?.macro_env)
+    } else {
+        None
+    };
+    let pattern = grep::Pattern::new(pattern);
+    let mut files = Vec::new();
+    for path in paths {
+        if path.is_dir() {
+            files.extend(TestDiscoverer::discover_test_files(&path)?);
+        } else {
+            files.push(path);
+        }
+    }
+    let mut found = false;
+    for file in files {
+        // A script that fails to parse or expand is reported and the search goes on
+        match grep_file(&pattern, &file, macros.as_ref(), cache) {
+            Ok(hits) => found |= hits,
+            Err(error) => print_error(error),
+        }
+    }
+    if !found {
+        process::exit(1);
+    }
+    Ok(())
+}
+
+/// Print the uses of `pattern` in `file`, expanded with `macros` when given.
+/// Returns whether there were any.
+fn grep_file(
+    pattern: &grep::Pattern,
+    file: &Path,
+    macros: Option<&MacroSystem>,
+    cache: Option<&AstCache>,
+) -> Result<bool, SutraError> {
+    let source = read_file(file)?;
+    let name = file.display().to_string();
+    let source_context = SourceContext::from_file(name.as_str(), &source);
+    let nodes = ast_cache::parse_with(cache, &source, source_context.clone())?;
+    let hits = grep::search(pattern, &nodes, macros).map_err(|e| e.with_source(&source_context))?;
+    for hit in &hits {
+        let (line, column) = line_and_column(&source, hit.span.start);
+        println!("{name}:{line}:{column}: {}", hit.form.value.pretty());
+    }
+    Ok(!hits.is_empty())
+}
 
-    if overall_summary.has_failures() {
+/// Rename `old` to `new` in `paths`, or print the diffs with `dry_run`. Every file
+/// is checked before any is written, so a conflict leaves them all as they were.
+fn rename_definition(
+    old: &str,
+    new: &str,
+    paths: Vec<PathBuf>,
+    dry_run: bool,
+) -> Result<(), SutraError> {
+    let rename = rename::Rename::new(old, new)?;
+    let mut changes = Vec::new();
+    let mut uses = 0;
+    for file in rename_files(paths)? {
+        let source = read_file(&file)?;
+        let source_context = SourceContext::from_file(file.display().to_string(), &source);
+        let nodes = parser::parse(&source, source_context.clone())?;
+        let edits = rename.edits(nodes, &source_context)?;
+        if !edits.is_empty() {
+            uses += edits.len();
+            let renamed = rename::apply(&source, &edits);
+            changes.push((file, source, renamed));
+        }
+    }
+    if changes.is_empty() {
+        eprintln!("No uses of '{old}' found");
+        process::exit(1);
+    }
+    for (file, source, renamed) in &changes {
+        if dry_run {
+            println!("--- {}\n+++ {}", file.display(), file.display());
+            print_diff(
+                source.trim_end_matches('\n'),
+                renamed.trim_end_matches('\n'),
+            );
+        } else {
+            std::fs::write(file, renamed).map_err(|e| {
+                SutraError::without_source(
+                    ErrorKind::InvalidPath {
+                        path: format!("{} ({e})", file.display()),
+                    },
+                    file.display().to_string(),
+                    "file-system",
+                )
+            })?;
+        }
+    }
+    let verb = if dry_run { "Would rename" } else { "Renamed" };
+    println!(
+        "{verb} '{old}' to '{new}': {uses} use(s) in {} file(s)",
+        changes.len()
+    );
+    Ok(())
+}
+
+/// The scripts a rename covers: a project's scripts, preludes and tests, the `.sutra`
+/// files under a directory, or a file.
+fn rename_files(paths: Vec<PathBuf>) -> Result<Vec<PathBuf>, SutraError> {
+    let mut files = Vec::new();
+    for path in paths {
+        let found = match Project::at(&path)? {
+            Some(project) => {
+                let mut found = project.source_files()?;
+                found.extend(project.manifest.preludes.iter().map(|p| project.resolve(p)));
+                if project.test_dir().is_dir() {
+                    found.extend(TestDiscoverer::discover_test_files(project.test_dir())?);
+                }
+                found
+            }
+            None if path.is_dir() => TestDiscoverer::discover_test_files(&path)?,
+            None => vec![path],
+        };
+        for file in found {
+            if !files.contains(&file) {
+                files.push(file);
+            }
+        }
+    }
+    Ok(files)
+}
+
+/// Expand a script and report every static type mismatch found in it, returning the count
+fn typecheck_source(
+    source: &str,
+    filename: &str,
+    macros: &MacroSystem,
+    cache: Option<&AstCache>,
+) -> Result<usize, SutraError> {
+    let source_context = SourceContext::from_file(filename, source);
+    let nodes = ast_cache::parse_with(cache, source, source_context.clone())?;
+    let expanded = ast_cache::expand_with(cache, parser::wrap_in_do(nodes), macros)
+        .map_err(|e| e.with_source(&source_context))?;
+
+    let type_errors = typecheck::typecheck_ast(&expanded, &source_context);
+    let count = type_errors.len();
+    for error in type_errors {
+        print_error(error);
+    }
+    Ok(count)
+}
+
+/// Report every literal `tr` key used in `files` that some catalog lacks. Returns
+/// whether none were missing.
+fn analyze_missing_translations(
+    files: &[PathBuf],
+    catalogs: &[PathBuf],
+    cache: Option<&AstCache>,
+) -> Result<bool, SutraError> {
+    let catalogs = catalogs
+        .iter()
+        .map(|path| Catalog::load(path))
+        .collect::<Result<Vec<_>, _>>()?;
+    if catalogs.is_empty() {
+        eprintln!("No catalogs given; pass --catalog <file>");
         process::exit(1);
     }
 
+    let mut missing = 0;
+    for file in files {
+        let source = read_file(file)?;
+        let nodes = ast_cache::parse_with(
+            cache,
+            &source,
+            SourceContext::from_file(file.display().to_string(), &source),
+        )?;
+        for (key, span) in localization::translation_keys(&parser::wrap_in_do(nodes)) {
+            let (line, column) = line_and_column(&source, span.start);
+            for catalog in catalogs.iter().filter(|c| !c.messages.contains_key(&key)) {
+                println!(
+                    "{}: missing '{}' ({}:{}:{})",
+                    catalog.locale,
+                    key,
+                    file.display(),
+                    line,
+                    column
+                );
+                missing += 1;
+            }
+        }
+    }
+
+    if missing == 0 {
+        println!("No missing translations");
+        return Ok(true);
+    }
+    eprintln!("{missing} missing translation(s)");
+    Ok(false)
+}
+
+/// Report the odds of every `deftable` table in `files`, and the entries that can never
+/// be rolled: those of weight 0 and those naming a table no file declares. Returns
+/// whether every entry can be rolled and every table form is well formed.
+fn audit_tables(files: &[PathBuf], cache: Option<&AstCache>) -> Result<bool, SutraError> {
+    let mut scripts = Vec::new();
+    let mut malformed = 0;
+    for file in files {
+        let source = read_file(file)?;
+        let source_context = SourceContext::from_file(file.display().to_string(), &source);
+        let nodes = ast_cache::parse_with(cache, &source, source_context.clone())?;
+        let mut tables: Vec<TableDefinition> = Vec::new();
+        for definition in table_definitions(&parser::wrap_in_do(nodes)) {
+            match definition {
+                Ok(definition) => tables.push(definition),
+                Err(error) => {
+                    print_error(error.with_source(&source_context));
+                    malformed += 1;
+                }
+            }
+        }
+        scripts.push((file, source, tables));
+    }
+    let declared: Vec<&str> = scripts
+        .iter()
+        .flat_map(|(_, _, tables)| tables)
+        .map(|table| table.name.as_str())
+        .collect();
+
+    let mut unreachable = 0;
+    for (file, source, table) in scripts
+        .iter()
+        .flat_map(|(file, source, tables)| tables.iter().map(move |table| (file, source, table)))
+    {
+        let (line, column) = line_and_column(source, table.span.start);
+        println!(
+            "{} ({}:{}:{}): total weight {}",
+            table.name,
+            file.display(),
+            line,
+            column,
+            table.total_weight()
+        );
+        for entry in &table.entries {
+            let chance = 100.0 * entry.weight / table.total_weight();
+            let (outcome, problem) = match &entry.outcome {
+                EntryOutcome::Value(value) => (value.value.pretty(), None),
+                EntryOutcome::Table(name) if !declared.contains(&name.as_str()) => (
+                    format!("table {name}"),
+                    Some(format!("no table '{name}' is declared")),
+                ),
+                EntryOutcome::Table(name) => (format!("table {name}"), None),
+            };
+            let problem = problem.or((entry.weight == 0.0).then(|| "weight 0".to_string()));
+            match problem {
+                Some(problem) => {
+                    println!("  {chance:5.1}%  {outcome}  (unreachable: {problem})");
+                    unreachable += 1;
+                }
+                None => println!("  {chance:5.1}%  {outcome}"),
+            }
+        }
+    }
+
+    if declared.is_empty() && malformed == 0 {
+        println!("No tables declared");
+    }
+    if unreachable > 0 {
+        eprintln!(
+            "{unreachable} unreachable table entr{}",
+            if unreachable == 1 { "y" } else { "ies" }
+        );
+    }
+    Ok(unreachable == 0 && malformed == 0)
+}
+
+/// Print every `defstate` machine in `files` as a DOT graph. Returns whether every
+/// machine form is well formed.
+fn dot_machines(files: &[PathBuf], cache: Option<&AstCache>) -> Result<bool, SutraError> {
+    let mut clean = true;
+    for file in files {
+        let source = read_file(file)?;
+        let source_context = SourceContext::from_file(file.display().to_string(), &source);
+        let nodes = ast_cache::parse_with(cache, &source, source_context.clone())?;
+        for definition in machine_definitions(&parser::wrap_in_do(nodes)) {
+            match definition {
+                Ok(machine) => print!("{}", machine.to_dot()),
+                Err(error) => {
+                    print_error(error.with_source(&source_context));
+                    clean = false;
+                }
+            }
+        }
+    }
+    Ok(clean)
+}
+
+/// How `sutra test` runs a suite.
+#[derive(Debug, Clone, Default)]
+pub struct TestOptions {
+    /// Where to write each test's outcome, and the summary, as JSON.
+    pub json: Option<PathBuf>,
+    /// The part of the suite to run, or all of it.
+    pub shard: Option<Shard>,
+    /// How many more times to run a test that does not pass.
+    pub retries: usize,
+    /// A file naming tests whose failures are only warnings.
+    pub quarantine: Option<PathBuf>,
+    /// The seed to shuffle the order of files and tests with. Otherwise files run in
+    /// path order and the tests of each by name.
+    pub shuffle: Option<u64>,
+}
+
+/// Enhanced test runner with detailed reporting. Parses and expansions are cached in
+/// `cache`, if given.
+pub fn run_tests(
+    path: PathBuf,
+    cache: Option<&AstCache>,
+    options: &TestOptions,
+) -> Result<(), SutraError> {
+    if run_test_files(path, cache, options)? {
+        process::exit(1);
+    }
     Ok(())
 }
 
+/// Runs `tests`, those of `suite` that belong to this run, between the file's
+/// `before-all` and `after-all` hooks, and passes each outcome to `report`. Hooks that
+/// fail are reported under their own names; if `before-all` fails, no test runs.
+fn run_suite(
+    suite: &TestSuite,
+    tests: &[&ASTDefinition],
+    options: &TestOptions,
+    quarantine: &Quarantine,
+    cache: Option<&AstCache>,
+    report: &mut dyn FnMut(&str, TestResult, Vec<Attempt>),
+) {
+    // A file with no tests to run needs no setup either
+    if tests.is_empty() {
+        return;
+    }
+    let setup = match TestSetup::for_suite(suite, cache) {
+        Ok(setup) => setup,
+        Err(e) => {
+            let hook = HookKind::BeforeAll;
+            report(hook.name(), TestResult::HookFailed(hook, e), Vec::new());
+            for test_form in tests {
+                let reason = format!("{hook} hook failed");
+                report(&test_form.name, TestResult::Skipped(reason), Vec::new());
+            }
+            return;
+        }
+    };
+    for test_form in tests {
+        let (mut result, attempts) =
+            TestRunner::run_with_retries(test_form, &setup, cache, options.retries);
+        if quarantine.contains(&test_form.name) {
+            result = result.quarantine();
+        }
+        report(&test_form.name, result, attempts);
+    }
+    if let Err(e) = setup.finish(cache) {
+        let hook = HookKind::AfterAll;
+        report(hook.name(), TestResult::HookFailed(hook, e), Vec::new());
+    }
+}
+
+/// Runs and reports every test under `path`, or those of the options' shard. Returns
+/// true if any test did not pass.
+fn run_test_files(
+    path: PathBuf,
+    cache: Option<&AstCache>,
+    options: &TestOptions,
+) -> Result<bool, SutraError> {
+    let shard = options.shard;
+    // Read the quarantine list on every run, so a watched run sees it edited.
+    let quarantine = match &options.quarantine {
+        Some(file) => Quarantine::load(file)?,
+        None => Quarantine::default(),
+    };
+    let mut test_files = TestDiscoverer::discover_test_files(&path)?;
+    // One generator shuffles the files and then the tests of each, so a seed
+    // repeats the whole order.
+    let mut shuffler = options.shuffle.map(SmallRng::seed_from_u64);
+    if let (Some(rng), Some(seed)) = (&mut shuffler, options.shuffle) {
+        println!("Shuffling tests with seed {seed}.\n");
+        test_files.shuffle(rng);
+    }
+    // Shards hash file paths relative to the suite, so every checkout agrees.
+    let root = match path.parent() {
+        Some(parent) if path.is_file() => parent,
+        _ => &path,
+    };
+
+    let mut file_summaries = Vec::new();
+    let mut overall_summary = TestSummary::default();
+    let mut records = Vec::new();
+    let mut error_categories: HashMap<String, usize> = HashMap::new();
+
+    for file_path in test_files {
+        tracing::info!("running tests in {}", file_path.display());
+        let mut file_summary = FileTestSummary::new(file_path.clone());
+
+        // Parse errors already point into the file being extracted.
+        let suite = TestDiscoverer::extract_suite_from_file(&file_path, cache)?;
+        let relative = file_path.strip_prefix(root).unwrap_or(&file_path);
+        let (mut tests, others): (Vec<_>, Vec<_>) = suite.tests.iter().partition(|test_form| {
+            shard.is_none_or(|shard| shard.includes(relative, &test_form.name))
+        });
+        file_summary.summary.other_shards += others.len();
+        match &mut shuffler {
+            Some(rng) => tests.shuffle(rng),
+            None => tests.sort_by(|a, b| a.name.cmp(&b.name)),
+        }
+
+        let mut report = |name: &str, result: TestResult, attempts: Vec<Attempt>| {
+            file_summary.add_result(&result);
+            records.push(TestRecord::new(&file_path, name, &result, attempts.clone()));
+            print_test_result(name, result, &attempts, &mut error_categories);
+        };
+        run_suite(&suite, &tests, options, &quarantine, cache, &mut report);
+
+        // A file whose tests all belong to other shards has nothing to report.
+        if file_summary.summary.total_tests() > 0 || shard.is_none() {
+            print_file_summary(&file_summary);
+        }
+        overall_summary.merge(&file_summary.summary);
+
+        file_summaries.push(file_summary);
+    }
+
+    // Print overall summary
+    print_overall_summary(&overall_summary, &error_categories);
+    if let Some(shard) = shard {
+        println!(
+            "Shard {shard} ran {} tests; {} belong to other shards.",
+            overall_summary.total_tests(),
+            overall_summary.other_shards
+        );
+    }
+    if let Some(seed) = options.shuffle {
+        println!("Tests ran in shuffled order; repeat it with --shuffle --seed {seed}.");
+    }
+
+    let has_failures = overall_summary.has_failures();
+    if let Some(json_path) = &options.json {
+        let report = TestReport {
+            summary: overall_summary,
+            shuffle_seed: options.shuffle,
+            tests: records,
+        };
+        report.save(json_path)?;
+    }
+
+    Ok(has_failures)
+}
+
+/// Benchmark runner with optional baseline comparison. Returns true if any benchmark
+/// regressed beyond `threshold` percent of its baseline.
+pub fn run_benches(
+    path: PathBuf,
+    baseline: Option<PathBuf>,
+    save: Option<PathBuf>,
+    threshold: f64,
+    config: BenchConfig,
+) -> Result<bool, SutraError> {
+    let bench_files = TestDiscoverer::discover_test_files(&path)?;
+
+    let mut report = BenchReport::default();
+    for file_path in bench_files {
+        let file_key = file_path
+            .strip_prefix(&path)
+            .unwrap_or(&file_path)
+            .display()
+            .to_string();
+        for bench_form in TestDiscoverer::extract_benches_from_file(&file_path)? {
+            let stats = BenchRunner::run(&bench_form, &config)?;
+            println!(
+                "{:<40} mean {:>10.1}µs  median {:>10.1}µs  p95 {:>10.1}µs",
+                bench_form.name, stats.mean_us, stats.median_us, stats.p95_us
+            );
+            report
+                .benches
+                .insert(format!("{}::{}", file_key, bench_form.name), stats);
+        }
+    }
+
+    if let Some(save_path) = save {
+        report.save(&save_path)?;
+    }
+
+    let Some(baseline_path) = baseline else {
+        return Ok(false);
+    };
+    let comparisons = bench::compare(&BenchReport::load(&baseline_path)?, &report, threshold);
+    print_bench_comparisons(&comparisons, threshold);
+
+    Ok(comparisons.iter().any(|c| c.regressed))
+}
+
+/// Print each benchmark's median change against the baseline
+fn print_bench_comparisons(comparisons: &[bench::BenchComparison], threshold: f64) {
+    use std::io::Write;
+    let mut stdout = StandardStream::stdout(ColorChoice::Auto);
+
+    println!("\n{}", "=".repeat(50));
+    for comparison in comparisons {
+        let color = if comparison.regressed {
+            Color::Red
+        } else if comparison.change_pct < 0.0 {
+            Color::Green
+        } else {
+            Color::Yellow
+        };
+        stdout.set_color(ColorSpec::new().set_fg(Some(color))).ok();
+        write!(&mut stdout, "{:>+7.1}%", comparison.change_pct).ok();
+        stdout.reset().ok();
+        println!(
+            " {} ({:.1}µs -> {:.1}µs)",
+            comparison.name, comparison.baseline_us, comparison.current_us
+        );
+    }
+
+    let regressions = comparisons.iter().filter(|c| c.regressed).count();
+    println!(
+        "Regressions beyond {:.1}%: {}/{}",
+        threshold,
+        regressions,
+        comparisons.len()
+    );
+}
+
+// ============================================================================
+// MACRO EXPANSION DIFFS
+// ============================================================================
+
+/// Columns a form may take up on one line in a diff before its arguments are split
+/// over lines.
+const LAYOUT_WIDTH: usize = 80;
+
+/// Unchanged lines shown around each change in a diff.
+const DIFF_CONTEXT: usize = 3;
+
+/// Prints each expansion step of `program`, parsed from `source`, as a diff against
+/// the program before it.
+fn print_expansion_steps(program: &AstNode, steps: &[ExpansionStep], source: &str) {
+    let mut previous = layout(program, 0);
+    for (number, step) in steps.iter().enumerate() {
+        let (line, column) = line_and_column(source, step.span.start);
+        println!(";; step {}: {} at {line}:{column}", number + 1, step.name);
+        let current = layout(&step.program, 0);
+        print_diff(&previous, &current);
+        previous = current;
+    }
+}
+
+/// Lays out `node`, starting at column `indent`, for diffing: a form that fits in
+/// [`LAYOUT_WIDTH`] columns stays on one line, and a longer list puts each argument
+/// on its own line, so a diff shows the forms that changed rather than the program.
+fn layout(node: &AstNode, indent: usize) -> String {
+    let flat = node.value.pretty();
+    let Expr::List(items, _) = &*node.value else {
+        return flat;
+    };
+    if indent + flat.len() <= LAYOUT_WIDTH || items.len() < 2 {
+        return flat;
+    }
+    let mut text = format!("({}", layout(&items[0], indent + 1));
+    for item in &items[1..] {
+        text.push('\n');
+        text.push_str(&" ".repeat(indent + 2));
+        text.push_str(&layout(item, indent + 2));
+    }
+    text.push(')');
+    text
+}
+
+/// The lines of a unified diff from `old` to `new`, each marked ` `, `-` or `+`.
+/// Unchanged lines more than [`DIFF_CONTEXT`] lines from a change are left out, each
+/// run of them standing as one `@@` line.
+fn diff_lines(old: &str, new: &str) -> Vec<(char, String)> {
+    let mut lines = Vec::new();
+    for diff in difference::Changeset::new(old, new, "\n").diffs {
+        let (mark, text) = match diff {
+            difference::Difference::Same(text) => (' ', text),
+            difference::Difference::Rem(text) => ('-', text),
+            difference::Difference::Add(text) => ('+', text),
+        };
+        lines.extend(text.split('\n').map(|line| (mark, line.to_string())));
+    }
+
+    let near_change = |i: usize| {
+        let end = (i + DIFF_CONTEXT + 1).min(lines.len());
+        lines[i.saturating_sub(DIFF_CONTEXT)..end]
+            .iter()
+            .any(|(mark, _)| *mark != ' ')
+    };
+    let mut shown: Vec<(char, String)> = Vec::new();
+    for (i, line) in lines.iter().enumerate() {
+        if line.0 != ' ' || near_change(i) {
+            shown.push(line.clone());
+        } else if shown.last().is_none_or(|(mark, _)| *mark != '@') {
+            shown.push(('@', "@@".to_string()));
+        }
+    }
+    shown
+}
+
+/// Prints a diff from `old` to `new`, removed lines in red and added ones in green
+/// on a terminal.
+fn print_diff(old: &str, new: &str) {
+    use std::io::Write;
+    let choice = if atty::is(atty::Stream::Stdout) {
+        ColorChoice::Auto
+    } else {
+        ColorChoice::Never
+    };
+    let mut stdout = StandardStream::stdout(choice);
+    for (mark, line) in diff_lines(old, new) {
+        let color = match mark {
+            '-' => Color::Red,
+            '+' => Color::Green,
+            '@' => Color::Cyan,
+            _ => {
+                writeln!(&mut stdout, " {line}").ok();
+                continue;
+            }
+        };
+        stdout.set_color(ColorSpec::new().set_fg(Some(color))).ok();
+        if mark == '@' {
+            write!(&mut stdout, "{line}").ok();
+        } else {
+            write!(&mut stdout, "{mark}{line}").ok();
+        }
+        stdout.reset().ok();
+        writeln!(&mut stdout).ok();
+    }
+}
+
 // ============================================================================
 // TEST REPORTING FUNCTIONS
 // ============================================================================
 
+/// Print a single test's outcome immediately, with a diagnostic for failures and errors
+fn print_test_result(
+    name: &str,
+    result: TestResult,
+    attempts: &[Attempt],
+    error_categories: &mut HashMap<String, usize>,
+) {
+    use std::io::Write;
+    let mut stdout = StandardStream::stdout(ColorChoice::Auto);
+
+    let (mark, color) = match &result {
+        TestResult::Passed => ("✓", Color::Green),
+        TestResult::Failed(_) => ("✗", Color::Red),
+        TestResult::Errored(_) => ("!", Color::Red),
+        TestResult::TimedOut(_) => ("⏱", Color::Red),
+        TestResult::Skipped(_) => ("-", Color::Yellow),
+        TestResult::Flaky(_) => ("~", Color::Yellow),
+        TestResult::Quarantined(_) => ("⚠", Color::Yellow),
+        TestResult::HookFailed(..) => ("!", Color::Red),
+    };
+    stdout.set_color(ColorSpec::new().set_fg(Some(color))).ok();
+    write!(&mut stdout, "{}", mark).ok();
+    stdout.reset().ok();
+
+    match result {
+        TestResult::Passed => println!(" {}", name),
+        TestResult::Skipped(reason) => println!(" {} (skipped: {})", name, reason),
+        TestResult::Failed(e) => {
+            println!(" {}{}", name, outcome_note(None, attempts));
+            *error_categories
+                .entry("Failed: expectation not met".to_string())
+                .or_insert(0) += 1;
+            print_error(e);
+        }
+        TestResult::Errored(e) => {
+            println!(" {}{}", name, outcome_note(Some("errored"), attempts));
+            *error_categories
+                .entry(format!("Errored: {:?}", e.kind.category()))
+                .or_insert(0) += 1;
+            print_error(e);
+        }
+        TestResult::TimedOut(e) => {
+            println!(" {}{}", name, outcome_note(Some("timed out"), attempts));
+            *error_categories.entry("Timed out".to_string()).or_insert(0) += 1;
+            print_error(e);
+        }
+        TestResult::Flaky(_) => println!(" {}{}", name, outcome_note(Some("flaky"), attempts)),
+        TestResult::HookFailed(hook, e) => {
+            let note = format!("{hook} hook failed");
+            println!(" {}{}", name, outcome_note(Some(&note), attempts));
+            *error_categories
+                .entry(format!("Hook failed: {hook}"))
+                .or_insert(0) += 1;
+            print_error(e);
+        }
+        // A quarantined failure is shown, but does not count against the run
+        TestResult::Quarantined(e) => {
+            println!(" {}{}", name, outcome_note(Some("quarantined"), attempts));
+            print_error(e);
+        }
+    }
+}
+
+/// What follows a test's name: its outcome, if the mark alone does not say, and the
+/// seeds of its runs if it was retried.
+fn outcome_note(outcome: Option<&str>, attempts: &[Attempt]) -> String {
+    let retries = (!attempts.is_empty()).then(|| {
+        let seeds: Vec<String> = attempts.iter().map(|a| a.seed.to_string()).collect();
+        format!("{} attempts, seeds {}", attempts.len(), seeds.join(", "))
+    });
+    match (outcome, retries) {
+        (None, None) => String::new(),
+        (Some(outcome), None) => format!(" ({outcome})"),
+        (None, Some(retries)) => format!(" ({retries})"),
+        (Some(outcome), Some(retries)) => format!(" ({outcome}; {retries})"),
+    }
+}
+
 /// Print file-by-file test summary with colors
 fn print_file_summary(file_summary: &FileTestSummary) {
     use std::io::Write;
     let mut stdout = StandardStream::stdout(ColorChoice::Auto);
 
-    let summary = file_summary.summary();
+    let summary = &file_summary.summary;
     let success_rate = summary.success_rate();
 
     // Choose color based on success rate
@@ -407,20 +2305,49 @@ fn print_file_summary(file_summary: &FileTestSummary) {
     stdout.set_color(ColorSpec::new().set_fg(Some(color))).ok();
     write!(
         &mut stdout,
-        "{}: {}/{} passed ({:.1}%)\n\n",
+        "{}: {}/{} passed ({:.1}%){}\n\n",
         file_summary
             .file_path
             .file_name()
             .and_then(|n| n.to_str())
             .unwrap_or("unknown"),
-        summary.passed,
-        summary.total_tests(),
-        success_rate
+        summary.passing(),
+        summary.total_tests() - summary.skipped,
+        success_rate,
+        unusual_outcomes(summary)
     )
     .ok();
     stdout.reset().ok();
 }
 
+/// Mentions errored, skipped, flaky and quarantined tests and failed hooks, which the
+/// pass rate alone does not show
+fn unusual_outcomes(summary: &TestSummary) -> String {
+    let mut parts = Vec::new();
+    if summary.errored > 0 {
+        parts.push(format!("{} errored", summary.errored));
+    }
+    if summary.timed_out > 0 {
+        parts.push(format!("{} timed out", summary.timed_out));
+    }
+    if summary.skipped > 0 {
+        parts.push(format!("{} skipped", summary.skipped));
+    }
+    if summary.flaky > 0 {
+        parts.push(format!("{} flaky", summary.flaky));
+    }
+    if summary.quarantined > 0 {
+        parts.push(format!("{} quarantined", summary.quarantined));
+    }
+    if summary.hook_failed > 0 {
+        parts.push(format!("{} hook failed", summary.hook_failed));
+    }
+    if parts.is_empty() {
+        return String::new();
+    }
+    format!(", {}", parts.join(", "))
+}
+
 /// Print overall test summary with error categorization
 fn print_overall_summary(summary: &TestSummary, error_categories: &HashMap<String, usize>) {
     use std::io::Write;
@@ -437,33 +2364,28 @@ fn print_overall_summary(summary: &TestSummary, error_categories: &HashMap<Strin
 
     println!("\n{}", "=".repeat(50));
 
-    // Print aligned pass/fail counts with subdued miette-style colors
-    let max_digits = std::cmp::max(
-        summary.passed.to_string().len(),
-        summary.failed.to_string().len(),
-    );
-
-    // Use subdued green (similar to miette's success color)
-    stdout
-        .set_color(ColorSpec::new().set_fg(Some(Color::Ansi256(65)))) // Muted green, closer to the parsing.sutra line
-        .ok();
-    println!(
-        "Tests passed: {:>width$}",
-        summary.passed,
-        width = max_digits
-    );
-    stdout.reset().ok();
-
-    // Use subdued red (similar to miette's error color)
-    stdout
-        .set_color(ColorSpec::new().set_fg(Some(Color::Ansi256(124)))) // Dark red
-        .ok();
-    println!(
-        "Tests failed: {:>width$}",
-        summary.failed,
-        width = max_digits
-    );
-    stdout.reset().ok();
+    // Print aligned outcome counts with subdued miette-style colors:
+    // muted green, dark red for failures, errors and timeouts, and amber for skips
+    let rows = [
+        ("passed:", summary.passed, Color::Ansi256(65)),
+        ("failed:", summary.failed, Color::Ansi256(124)),
+        ("errored:", summary.errored, Color::Ansi256(124)),
+        ("timed out:", summary.timed_out, Color::Ansi256(124)),
+        ("skipped:", summary.skipped, Color::Ansi256(136)),
+    ];
+    let max_digits = rows
+        .iter()
+        .map(|(_, count, _)| count.to_string().len())
+        .max()
+        .unwrap_or(1);
+
+    for (label, count, row_color) in rows {
+        stdout
+            .set_color(ColorSpec::new().set_fg(Some(row_color)))
+            .ok();
+        println!("Tests {:<10} {:>width$}", label, count, width = max_digits);
+        stdout.reset().ok();
+    }
 
     println!("{}", "=".repeat(50));
 
@@ -472,10 +2394,11 @@ fn print_overall_summary(summary: &TestSummary, error_categories: &HashMap<Strin
         .ok();
     write!(
         &mut stdout,
-        "Overall Test Summary: {}/{} passed ({:.1}%)",
-        summary.passed,
-        summary.total_tests(),
-        success_rate
+        "Overall Test Summary: {}/{} passed ({:.1}%){}",
+        summary.passing(),
+        summary.total_tests() - summary.skipped,
+        success_rate,
+        unusual_outcomes(summary)
     )
     .ok();
     stdout.reset().ok();
@@ -489,30 +2412,89 @@ fn print_overall_summary(summary: &TestSummary, error_categories: &HashMap<Strin
     }
 }
 
-/// The main entry point for the CLI.
+/// The main entry point for the CLI. Prints any error and exits with status 1.
 pub fn run() {
-    if let Err(e) = run_inner() {
-        print_error(e.into());
+    let args = SutraArgs::parse();
+    logging::init(logging::level(args.quiet, args.verbose));
+    let result = match args.command {
+        Some(command) => run_inner(command, args.verbose > 0),
+        None if args.list_commands => list_commands(args.json),
+        None => SutraArgs::command()
+            .error(
+                clap::error::ErrorKind::MissingSubcommand,
+                "a command is required; see --help",
+            )
+            .exit(),
+    };
+    if let Err(e) = result {
+        print_error(e);
         process::exit(1);
     }
 }
 
-fn run_inner() -> Result<(), SutraError> {
-    let args = SutraArgs::parse();
+/// Runs `command`. `verbose` is set by the global `--verbose` flag, which
+/// `list-macros` also takes as a request for each macro's usage and doc comment.
+fn run_inner(command: ArgsCommand, verbose: bool) -> Result<(), SutraError> {
     let mut engine = SutraEngine::new();
 
-    match args.command {
-        ArgsCommand::Run { file } => {
-            let source = read_file(&file)?;
-            engine.execute(&source, &file.display().to_string())
+    match command {
+        ArgsCommand::Run {
+            files,
+            dump_world_diff,
+            stats,
+            watch: false,
+            record,
+            entry,
+            no_cache,
+            pipeline,
+        } => run_files(
+            files,
+            dump_world_diff,
+            stats,
+            record,
+            entry,
+            script_cache(no_cache).as_ref(),
+            &pipeline,
+        ),
+
+        ArgsCommand::Run {
+            files,
+            dump_world_diff,
+            stats,
+            watch: true,
+            entry,
+            no_cache,
+            pipeline,
+            ..
+        } => {
+            let mut watched = files.clone();
+            watched.extend(pipeline.preludes.iter().cloned());
+            watched.extend(pipeline.catalogs.iter().cloned());
+            let cache = script_cache(no_cache);
+            watch_paths(&watched, || {
+                run_files(
+                    files.clone(),
+                    dump_world_diff,
+                    stats,
+                    None,
+                    entry.clone(),
+                    cache.as_ref(),
+                    &pipeline,
+                )
+            })
         }
 
-        ArgsCommand::Eval { code } => {
+        ArgsCommand::Replay { log, pipeline } => run_replay(&log, &pipeline),
+
+        ArgsCommand::Eval { code, pipeline } => {
             let source = match code {
                 Some(code_str) => code_str,
                 None => read_stdin()?,
             };
-            engine.execute(&source, "<eval>")
+            let pipeline = args::layered_builder(None, &pipeline)?.build()?;
+            let ran = run_source(&pipeline, &source, "<eval>");
+            print_warnings(&pipeline);
+            ran
         }
 
         ArgsCommand::Repl => {
@@ -520,49 +2502,265 @@ fn run_inner() -> Result<(), SutraError> {
             Ok(())
         }
 
-        ArgsCommand::Macroexpand { file } => {
+        ArgsCommand::Macroexpand {
+            file,
+            step: false,
+            diff: false,
+            only: None,
+        } => {
             let source = read_file(&file)?;
-            let expanded = engine.expand_macros(&source)?;
+            let expanded = engine.expand_macros(&file.display().to_string(), &source)?;
             println!("{expanded}");
             Ok(())
         }
 
+        ArgsCommand::Macroexpand {
+            file,
+            step,
+            diff,
+            only,
+        } => {
+            let source = read_file(&file)?;
+            let (program, steps) =
+                engine.expansion_steps(&file.display().to_string(), &source, only.as_deref())?;
+            let expanded = steps.last().map_or(&program, |step| &step.program);
+            if step {
+                print_expansion_steps(&program, &steps, &source);
+            } else if diff {
+                print_diff(&layout(&program, 0), &layout(expanded, 0));
+            } else {
+                println!("{}", expanded.value.pretty());
+            }
+            Ok(())
+        }
+
         ArgsCommand::Macrotrace { file } => {
             let source = read_file(&file)?;
-            engine.trace_macros(&source)
+            let (program, steps) =
+                engine.expansion_steps(&file.display().to_string(), &source, None)?;
+            print_expansion_steps(&program, &steps, &source);
+            Ok(())
         }
 
         ArgsCommand::Format { file } => {
-            let source = read_file(&file)?;
-            let formatted = engine.format(&source)?;
-            println!("{formatted}");
+            let (files, project) = project_scripts(vec![file])?;
+            for file in files {
+                let source = read_file(&file)?;
+                let formatted = engine.format(&file.display().to_string(), &source)?;
+                if project.is_some() {
+                    println!("; {}", file.display());
+                }
+                println!("{formatted}");
+            }
             Ok(())
         }
 
-        ArgsCommand::Ast { file } => {
+        ArgsCommand::Ast {
+            file,
+            format,
+            no_spans,
+            expanded,
+        } => {
             let source = read_file(&file)?;
-            let ast = engine.parse(&source)?;
-            println!("{ast:#?}");
-            Ok(())
+            let nodes = if expanded {
+                vec![engine.expand(&file.display().to_string(), &source)?]
+            } else {
+                engine.parse(&file.display().to_string(), &source)?
+            };
+            let format = match format {
+                Some(format) => format,
+                None => user_settings()?.ast_format.unwrap_or(AstFormat::Sexpr),
+            };
+            print_ast(&nodes, &source, format, !no_spans)
         }
 
-        ArgsCommand::ListMacros => {
+        ArgsCommand::ListMacros { json, preludes } => {
+            engine.load_preludes(&preludes)?;
+            if json {
+                return print_json(&engine.macros_json());
+            }
+            if verbose {
+                println!("{}", engine.macro_docs().join("\n\n"));
+                return Ok(());
+            }
             for macro_name in engine.list_macros() {
                 println!("  {macro_name}");
             }
             Ok(())
         }
 
-        ArgsCommand::ListAtoms => {
+        ArgsCommand::ListAtoms { json: true } => print_json(&engine.atoms_json()),
+
+        ArgsCommand::ListAtoms { json: false } => {
             for atom_name in engine.list_atoms() {
                 println!("  {atom_name}");
             }
             Ok(())
         }
 
+        ArgsCommand::Doc { name, preludes } => {
+            engine.load_preludes(&preludes)?;
+            println!("{}", engine.atom_docs(name.as_deref())?.join("\n\n"));
+            Ok(())
+        }
+
+        ArgsCommand::Config {
+            command: ConfigCommand::Show { project, pipeline },
+        } => show_config(&project, &pipeline),
+
+        ArgsCommand::Examples { command: None }
+        | ArgsCommand::Examples {
+            command: Some(ExamplesCommand::List),
+        } => {
+            for example in examples::EXAMPLES {
+                println!("  {:<10} {}", example.name, example.summary);
+            }
+            Ok(())
+        }
+
+        ArgsCommand::Examples {
+            command: Some(ExamplesCommand::Show { name }),
+        } => {
+            let example = find_example(&name)?;
+            if let Some(prelude) = example.prelude {
+                println!("; {}.prelude.sutra\n{prelude}", example.name);
+                println!("; {}.sutra", example.name);
+            }
+            print!("{}", example.source);
+            Ok(())
+        }
+
+        ArgsCommand::Examples {
+            command: Some(ExamplesCommand::Run { name, pipeline }),
+        } => run_example(find_example(&name)?, pipeline),
+
+        ArgsCommand::Completions { shell } => {
+            clap_complete::generate(shell, &mut SutraArgs::command(), "sutra", &mut io::stdout());
+            Ok(())
+        }
+
         ArgsCommand::ValidateGrammar => validate_grammar(),
 
-        ArgsCommand::Test { path } => run_tests(path),
+        ArgsCommand::Grammar {
+            format,
+            emit,
+            out,
+            preludes,
+        } => {
+            engine.load_preludes(&preludes)?;
+            match emit {
+                Some(editor) => emit_grammar(&engine, editor, &out),
+                None => print_grammar(&engine, format),
+            }
+        }
+
+        ArgsCommand::Analyze {
+            files,
+            missing_translations,
+            audit_tables: audit,
+            dot_machines: dot,
+            catalogs,
+            no_cache,
+        } => {
+            if !missing_translations && !audit && !dot {
+                eprintln!(
+                    "Nothing to analyze; pass --missing-translations, --audit-tables or --dot-machines"
+                );
+                process::exit(1);
+            }
+            let (files, project) = project_scripts(files)?;
+            let cache = script_cache(no_cache);
+            let mut clean = true;
+            if missing_translations {
+                let catalogs = match &project {
+                    Some(project) if catalogs.is_empty() => project
+                        .manifest
+                        .catalogs
+                        .iter()
+                        .map(|catalog| project.resolve(catalog))
+                        .collect(),
+                    _ => catalogs,
+                };
+                clean &= analyze_missing_translations(&files, &catalogs, cache.as_ref())?;
+            }
+            if audit {
+                clean &= audit_tables(&files, cache.as_ref())?;
+            }
+            if dot {
+                clean &= dot_machines(&files, cache.as_ref())?;
+            }
+            if !clean {
+                process::exit(1);
+            }
+            Ok(())
+        }
+
+        ArgsCommand::Typecheck { file, no_cache } => {
+            typecheck_files(file, script_cache(no_cache).as_ref())
+        }
+
+        ArgsCommand::Rename {
+            old,
+            new,
+            paths,
+            dry_run,
+        } => rename_definition(&old, &new, paths, dry_run),
+
+        ArgsCommand::Grep {
+            pattern,
+            paths,
+            expanded,
+            no_cache,
+        } => grep_scripts(&pattern, paths, expanded, script_cache(no_cache).as_ref()),
+
+        ArgsCommand::Test {
+            path,
+            json,
+            watch,
+            no_cache,
+            shard,
+            retries,
+            quarantine,
+            shuffle,
+            seed,
+        } => {
+            let watched = path.clone();
+            let path = match Project::at(&path)? {
+                Some(project) => project.test_dir(),
+                None => path,
+            };
+            let cache = script_cache(no_cache);
+            let options = TestOptions {
+                json,
+                shard,
+                retries,
+                quarantine,
+                shuffle: shuffle.then(|| seed.unwrap_or_else(rand::random)),
+            };
+            if !watch {
+                return run_tests(path, cache.as_ref(), &options);
+            }
+            watch_paths(&[watched], || {
+                run_test_files(path.clone(), cache.as_ref(), &options).map(|_| ())
+            })
+        }
+
+        ArgsCommand::Clean => clean_cache(),
+
+        ArgsCommand::Bench {
+            path,
+            baseline,
+            save,
+            threshold,
+            iterations,
+            warmup,
+        } => {
+            let config = BenchConfig { warmup, iterations };
+            if run_benches(path, baseline, save, threshold, config)? {
+                process::exit(1);
+            }
+            Ok(())
+        }
     }
 }
 
@@ -570,6 +2768,16 @@ fn run_inner() -> Result<(), SutraError> {
 // EXECUTION PIPELINE - Legacy code kept for compatibility
 // ============================================================================
 
+/// Capabilities granted by [`ExecutionPipeline::sandboxed`]: no output and no clock.
+pub const SANDBOX_CAPABILITIES: [Capability; 2] = [Capability::State, Capability::Random];
+
+/// Quotas set by [`ExecutionPipeline::sandboxed`].
+pub const SANDBOX_LIMITS: ResourceLimits = ResourceLimits {
+    max_collection_len: Some(100_000),
+    max_string_bytes: Some(1 << 20),
+    max_world_size: Some(100_000),
+};
+
 /// Unified execution pipeline that enforces strict layering: Parse → Expand → Validate → Evaluate
 /// This is the single source of truth for all Sutra execution paths, including tests and production.
 /// All code execution, including test harnesses, must use this pipeline. Bypassing is forbidden.
@@ -582,6 +2790,22 @@ pub struct ExecutionPipeline {
     pub max_depth: usize,
     /// Whether to validate expanded AST before evaluation
     pub validate: bool,
+    /// Output sink used by the value-returning entry points
+    pub output: SharedOutput,
+    /// Wall-clock limit for each evaluation; `None` lets evaluation run indefinitely
+    pub time_limit: Option<Duration>,
+    /// Capabilities scripts may use; `None` grants all of them
+    pub capabilities: Option<Vec<Capability>>,
+    /// Allocation quotas for each evaluation
+    pub limits: ResourceLimits,
+    /// Whether to fold constants before evaluation (see [`optimize`])
+    pub optimize: bool,
+    /// Where expansions are cached between runs; `None` expands every time
+    pub ast_cache: Option<AstCache>,
+    /// Whether warnings fail the preludes or script they are found in
+    pub deny_warnings: bool,
+    /// Warnings found in the preludes and scripts so far (see [`Self::take_warnings`])
+    pub warnings: Mutex<Vec<SutraError>>,
 }
 
 impl Default for ExecutionPipeline {
@@ -593,33 +2817,232 @@ impl Default for ExecutionPipeline {
                 // Create a minimal macro environment with only core macros
                 MacroSystem::new()
             }),
-            max_depth: 100,
+            max_depth: DEFAULT_MAX_DEPTH,
             validate: false, // Keep validation disabled for now
+            output: SharedOutput::new(EngineStdoutSink),
+            time_limit: None,
+            capabilities: None,
+            limits: ResourceLimits::default(),
+            optimize: true,
+            ast_cache: None,
+            deny_warnings: false,
+            warnings: Mutex::default(),
         }
     }
 }
 
 impl ExecutionPipeline {
-    /// Core execution method that processes AST nodes through the full pipeline.
-    /// Kept for compatibility with test runner.
+    /// Starts configuring a pipeline; see [`ExecutionPipelineBuilder`].
+    pub fn builder() -> ExecutionPipelineBuilder {
+        ExecutionPipelineBuilder::default()
+    }
+
+    /// Starts configuring a pipeline for untrusted scripts, granting only
+    /// [`SANDBOX_CAPABILITIES`] and enforcing [`SANDBOX_LIMITS`].
+    pub fn sandboxed() -> ExecutionPipelineBuilder {
+        Self::builder()
+            .with_capabilities(&SANDBOX_CAPABILITIES)
+            .with_resource_limits(SANDBOX_LIMITS)
+    }
+
+    /// Parses, expands and evaluates `source`, returning the resulting value.
+    /// Output from the program goes to the pipeline's output sink.
+    pub fn execute_for_value(&self, source: &str, filename: &str) -> Result<Value, SutraError> {
+        let (program, source_context) = self.compile(source, filename)?;
+        self.evaluate_prepared(&program, source_context)
+    }
+
+    /// Parses, checks, expands and prepares `source` (see [`Self::prepare`]), ready
+    /// for [`Self::evaluate_prepared`].
+    fn compile(
+        &self,
+        source: &str,
+        filename: &str,
+    ) -> Result<(AstNode, SourceContext), SutraError> {
+        let source_context = SourceContext::from_file(filename, source);
+        let nodes = parser::parse(source, source_context.clone())?;
+        tracing::debug!("parsed {} top-level forms from {filename}", nodes.len());
+        let found = semantic::check_definitions(&nodes, &self.world.read(), &self.macro_env);
+        self.warn(found, &source_context)?;
+        tracing::debug!("expanding macros in {}", source_context.name());
+        let expanded = self.expand(parser::wrap_in_do(nodes), &source_context)?;
+        let program = self.prepare(&expanded, &self.world, &source_context)?;
+        Ok((program, source_context))
+    }
+
+    /// Evaluates a program prepared by [`Self::compile`] against the pipeline's world.
+    fn evaluate_prepared(
+        &self,
+        program: &AstNode,
+        source_context: SourceContext,
+    ) -> Result<Value, SutraError> {
+        self.run_settings().evaluate(
+            program,
+            self.world.clone(),
+            self.output.clone(),
+            source_context,
+        )
+    }
+
+    /// Removes and returns the warnings found so far: definitions that hide an
+    /// atom, macro or earlier definition, and unused parameters, in the preludes and
+    /// in each script run with [`Self::execute_for_value`].
+    pub fn take_warnings(&self) -> Vec<SutraError> {
+        let mut warnings = self.warnings.lock().unwrap_or_else(PoisonError::into_inner);
+        std::mem::take(&mut *warnings)
+    }
+
+    /// Keeps `found`, the warnings of `source_context`, or with `deny_warnings`
+    /// fails with the first of them.
+    fn warn(
+        &self,
+        found: Vec<SutraError>,
+        source_context: &SourceContext,
+    ) -> Result<(), SutraError> {
+        let mut found = found.into_iter().map(|w| w.with_source(source_context));
+        if self.deny_warnings {
+            return found
+                .next()
+                .map_or(Ok(()), |warning| Err(semantic::deny(warning)));
+        }
+        let mut warnings = self.warnings.lock().unwrap_or_else(PoisonError::into_inner);
+        warnings.extend(found);
+        Ok(())
+    }
+
+    /// Expands and evaluates a single parsed (unexpanded) AST node, returning its value.
+    pub fn execute_ast_for_value(
+        &self,
+        node: &AstNode,
+        source_context: SourceContext,
+    ) -> Result<Value, SutraError> {
+        tracing::debug!("expanding macros in {}", source_context.name());
+        let expanded = self.expand(node.clone(), &source_context)?;
+        self.evaluate_expanded(&expanded, self.output.clone(), source_context)
+    }
+
+    /// Core execution method that processes AST nodes through the full pipeline,
+    /// writing to `output` instead of the pipeline's own sink.
     pub fn execute_nodes(
         &self,
         nodes: &[AstNode],
         output: SharedOutput,
         source_context: SourceContext,
     ) -> Result<Value, SutraError> {
-        use crate::parser;
-
-        let env = self.macro_env.clone();
-
         // Wrap user code in a (do ...) block if needed
         let program = parser::wrap_in_do(nodes.to_vec());
 
         // Expand macros using the pipeline's environment.
-        let expanded = env.expand(program)?;
+        tracing::debug!("expanding macros in {}", source_context.name());
+        let expanded = self.expand(program, &source_context)?;
+
+        // Evaluate the final AST, using the pipeline's world and the given output sink.
+        self.evaluate_expanded(&expanded, output, source_context)
+    }
+
+    /// Expands `program` with the pipeline's macros, through its cache if it has one.
+    fn expand(
+        &self,
+        program: AstNode,
+        source_context: &SourceContext,
+    ) -> Result<AstNode, SutraError> {
+        let _stage = telemetry::stage("expand", source_context);
+        ast_cache::expand_with(self.ast_cache.as_ref(), program, &self.macro_env)
+            .map_err(|e| e.with_source(source_context))
+    }
+
+    /// Evaluates an expanded AST against the pipeline's world, honoring `max_depth`,
+    /// `time_limit`, `capabilities` and `limits` (see [`Self::prepare`]).
+    fn evaluate_expanded(
+        &self,
+        expanded: &AstNode,
+        output: SharedOutput,
+        source_context: SourceContext,
+    ) -> Result<Value, SutraError> {
+        let program = self.prepare(expanded, &self.world, &source_context)?;
+        self.run_settings()
+            .evaluate(&program, self.world.clone(), output, source_context)
+    }
+
+    /// Checks an expanded AST before it runs against `world`: it fails if it uses a
+    /// capability the pipeline does not grant, and under [`PathPolicy::Warn`] it
+    /// prints a warning for each `set!` that would create a missing parent path. With
+    /// `optimize`, constants are folded after those checks.
+    fn prepare(
+        &self,
+        expanded: &AstNode,
+        world: &CanonicalWorld,
+        source_context: &SourceContext,
+    ) -> Result<AstNode, SutraError> {
+        let _stage = telemetry::stage("validate", source_context);
+        if let Some(granted) = &self.capabilities {
+            let denied = semantic::check_capabilities(expanded, &world.read(), granted);
+            if let Some(error) = denied.into_iter().next() {
+                return Err(error.with_source(source_context));
+            }
+        }
+        if world.read().path_policy == PathPolicy::Warn {
+            for warning in semantic::check_path_writes(expanded, &world.read()) {
+                print_error(warning.with_source(source_context));
+            }
+        }
+        if self.optimize {
+            return Ok(optimize::fold_constants(
+                expanded.clone(),
+                world,
+                self.limits,
+            ));
+        }
+        Ok(expanded.clone())
+    }
 
-        // Evaluate the final AST, using the pipeline's world and output sink.
-        evaluate(&expanded, self.world.clone(), output, source_context)
+    fn run_settings(&self) -> RunSettings {
+        RunSettings {
+            max_depth: self.max_depth,
+            time_limit: self.time_limit,
+            limits: self.limits,
+        }
+    }
+
+    /// Runs each of `scripts`, given as `(name, source)`, against its own copy of
+    /// `base`, and returns their results in the same order. `base` is left as it was;
+    /// the copies share its structure, so each costs little beyond what its script
+    /// writes. Scripts are parsed, expanded and checked with the pipeline's macros and
+    /// settings first. With the `sync` feature they then run in parallel, one thread
+    /// per available core; otherwise one after another. Each script's output is
+    /// captured in its result rather than sent to the pipeline's sink.
+    pub fn execute_batch(&self, scripts: &[(&str, &str)], base: &World) -> Vec<BatchResult> {
+        let world = CanonicalWorld::from_world(base.clone());
+        let jobs: Vec<BatchJob> = scripts
+            .iter()
+            .map(|(name, source)| {
+                let source_context = SourceContext::from_file(*name, *source);
+                let program = parser::parse(source, source_context.clone())
+                    .and_then(|nodes| self.expand(parser::wrap_in_do(nodes), &source_context))
+                    .and_then(|expanded| self.prepare(&expanded, &world, &source_context));
+                BatchJob {
+                    name: name.to_string(),
+                    program: program.map(|program| (program, source_context)),
+                }
+            })
+            .collect();
+        let settings = self.run_settings();
+        run_jobs(jobs, |job| settings.run_job(job, base))
+    }
+
+    /// Records the world's current state as an undo point, like `(checkpoint!)`.
+    pub fn checkpoint(&self) {
+        self.world.write().checkpoint();
+    }
+
+    /// Restores the most recent checkpoint, like `(undo!)`. Returns `false` if there is none.
+    pub fn undo(&self) -> bool {
+        self.world.write().undo()
+    }
+
+    /// Reverses the most recent undo, like `(redo!)`. Returns `false` if there is nothing to redo.
+    pub fn redo(&self) -> bool {
+        self.world.write().redo()
     }
 
     /// Executes already-expanded AST nodes, bypassing macro processing.
@@ -634,3 +3057,457 @@ impl ExecutionPipeline {
         evaluate(expanded_ast, world, output, source)
     }
 }
+
+/// What one script of [`ExecutionPipeline::execute_batch`] did.
+#[derive(Debug)]
+pub struct BatchResult {
+    /// The script's name, as given.
+    pub name: String,
+    /// The script's value, or the error that stopped it.
+    pub value: Result<Value, SutraError>,
+    /// What the script printed.
+    pub output: String,
+    /// The changes the script made to the base world, up to where it stopped.
+    pub diff: WorldDiff,
+}
+
+/// A batch script ready to run: its checked program, or why it could not be.
+struct BatchJob {
+    name: String,
+    program: Result<(AstNode, SourceContext), SutraError>,
+}
+
+/// The pipeline settings an evaluation needs, which can be sent to other threads.
+#[derive(Debug, Clone, Copy)]
+struct RunSettings {
+    max_depth: usize,
+    time_limit: Option<Duration>,
+    limits: ResourceLimits,
+}
+
+impl RunSettings {
+    fn evaluate(
+        &self,
+        program: &AstNode,
+        world: CanonicalWorld,
+        output: SharedOutput,
+        source_context: SourceContext,
+    ) -> Result<Value, SutraError> {
+        tracing::debug!("evaluating {}", source_context.name());
+        let _stage = telemetry::stage("eval", &source_context);
+        let mut context =
+            EvaluationContext::with_settings(world, output, source_context, self.max_depth);
+        context.deadline = self.time_limit.map(Deadline::after);
+        context.limits = self.limits;
+        Ok(evaluate_ast_node(program, &mut context)?.value)
+    }
+
+    /// Runs `job` against a copy of `base`.
+    fn run_job(&self, job: BatchJob, base: &World) -> BatchResult {
+        let (program, source_context) = match job.program {
+            Ok(program) => program,
+            Err(error) => {
+                return BatchResult {
+                    name: job.name,
+                    value: Err(error),
+                    output: String::new(),
+                    diff: WorldDiff::default(),
+                }
+            }
+        };
+        let world = CanonicalWorld::from_world(base.clone());
+        let buffer = Rc::new(RefCell::new(EngineOutputBuffer::new()));
+        let value = self.evaluate(
+            &program,
+            world.clone(),
+            SharedOutput(buffer.clone()),
+            source_context,
+        );
+        let output = buffer.borrow().as_str().to_string();
+        let diff = base.diff(&world.read());
+        BatchResult {
+            name: job.name,
+            value,
+            output,
+            diff,
+        }
+    }
+}
+
+/// Runs `jobs` in order.
+#[cfg(not(feature = "sync"))]
+fn run_jobs(jobs: Vec<BatchJob>, run: impl Fn(BatchJob) -> BatchResult) -> Vec<BatchResult> {
+    jobs.into_iter().map(run).collect()
+}
+
+/// Runs `jobs` split evenly over a thread per available core, returning their
+/// results in order.
+#[cfg(feature = "sync")]
+fn run_jobs(jobs: Vec<BatchJob>, run: impl Fn(BatchJob) -> BatchResult + Sync) -> Vec<BatchResult> {
+    let threads = std::thread::available_parallelism().map_or(1, |n| n.get());
+    let per_thread = jobs.len().div_ceil(threads).max(1);
+    let mut jobs = jobs.into_iter();
+    let mut chunks = Vec::new();
+    loop {
+        let chunk: Vec<BatchJob> = jobs.by_ref().take(per_thread).collect();
+        if chunk.is_empty() {
+            break;
+        }
+        chunks.push(chunk);
+    }
+    let run = &run;
+    std::thread::scope(|scope| {
+        let workers: Vec<_> = chunks
+            .into_iter()
+            .map(|chunk| scope.spawn(move || chunk.into_iter().map(run).collect::<Vec<_>>()))
+            .collect();
+        workers
+            .into_iter()
+            .flat_map(|worker| {
+                worker
+                    .join()
+                    .unwrap_or_else(|panic| std::panic::resume_unwind(panic))
+            })
+            .collect()
+    })
+}
+
+/// Host callback that registers additional atoms into a freshly built world.
+pub type AtomRegistry = Box<dyn FnOnce(&mut World)>;
+
+/// Builder for [`ExecutionPipeline`], so the CLI and embedders configure runs the same way.
+///
+/// ```ignore
+/// let pipeline = ExecutionPipeline::builder()
+///     .with_seed(42)
+///     .with_prelude_file("macros/story.sutra")
+///     .with_output(SharedOutput::new(EngineOutputBuffer::new()))
+///     .build()?;
+/// ```
+#[derive(Default)]
+pub struct ExecutionPipelineBuilder {
+    max_depth: Option<usize>,
+    expansion_limits: ExpansionLimits,
+    seed: Option<u64>,
+    prelude_files: Vec<PathBuf>,
+    catalog_files: Vec<PathBuf>,
+    locale: Option<String>,
+    history_depth: Option<usize>,
+    time_limit: Option<Duration>,
+    atom_registries: Vec<AtomRegistry>,
+    output: Option<SharedOutput>,
+    capabilities: Option<Vec<Capability>>,
+    limits: ResourceLimits,
+    path_policy: PathPolicy,
+    print_separator: String,
+    log_level: LogLevel,
+    script_args: Vec<String>,
+    no_optimization: bool,
+    no_assertions: bool,
+    ast_cache: Option<AstCache>,
+    deny_warnings: bool,
+}
+
+impl ExecutionPipelineBuilder {
+    /// Sets the maximum evaluation recursion depth.
+    pub fn with_max_depth(mut self, max_depth: usize) -> Self {
+        self.max_depth = Some(max_depth);
+        self
+    }
+
+    /// Sets how deeply macro expansions may nest, 100 unless set.
+    pub fn with_max_macro_depth(mut self, depth: usize) -> Self {
+        self.expansion_limits.max_depth = depth;
+        self
+    }
+
+    /// Sets how many AST nodes the macro expansions of one program may produce, a
+    /// million unless set.
+    pub fn with_max_expansion_nodes(mut self, nodes: usize) -> Self {
+        self.expansion_limits.max_nodes = nodes;
+        self
+    }
+
+    /// Seeds the world's PRNG so random atoms are reproducible.
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.seed = Some(seed);
+        self
+    }
+
+    /// Loads macro definitions from a file after the standard macros.
+    pub fn with_prelude_file(mut self, path: impl Into<PathBuf>) -> Self {
+        self.prelude_files.push(path.into());
+        self
+    }
+
+    /// Loads a message catalog for `tr`; the file stem names its locale.
+    pub fn with_catalog_file(mut self, path: impl Into<PathBuf>) -> Self {
+        self.catalog_files.push(path.into());
+        self
+    }
+
+    /// Sets the locale `tr` prefers. Defaults to [`localization::DEFAULT_LOCALE`].
+    pub fn with_locale(mut self, locale: impl Into<String>) -> Self {
+        self.locale = Some(locale.into());
+        self
+    }
+
+    /// Sets how many `checkpoint!` snapshots are kept for undo.
+    /// Defaults to [`crate::runtime::DEFAULT_HISTORY_DEPTH`].
+    pub fn with_history_depth(mut self, depth: usize) -> Self {
+        self.history_depth = Some(depth);
+        self
+    }
+
+    /// Fails any evaluation that runs longer than `limit` with a timeout error.
+    pub fn with_time_limit(mut self, limit: Duration) -> Self {
+        self.time_limit = Some(limit);
+        self
+    }
+
+    /// Runs `register` against the world after the standard atoms, e.g. to add host atoms.
+    pub fn with_atom_registry(mut self, register: impl FnOnce(&mut World) + 'static) -> Self {
+        self.atom_registries.push(Box::new(register));
+        self
+    }
+
+    /// Grants only `capabilities`. Scripts calling an atom that needs any other one fail
+    /// before they run, and the atom is unbound so it cannot be reached indirectly.
+    pub fn with_capabilities(mut self, capabilities: &[Capability]) -> Self {
+        self.capabilities = Some(capabilities.to_vec());
+        self
+    }
+
+    /// Replaces all allocation quotas at once.
+    pub fn with_resource_limits(mut self, limits: ResourceLimits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// Fails any call that returns a list or map with more than `len` elements.
+    pub fn with_max_collection_len(mut self, len: usize) -> Self {
+        self.limits.max_collection_len = Some(len);
+        self
+    }
+
+    /// Fails any call that returns a string longer than `bytes`.
+    pub fn with_max_string_bytes(mut self, bytes: usize) -> Self {
+        self.limits.max_string_bytes = Some(bytes);
+        self
+    }
+
+    /// Fails any stateful atom call that leaves the world with more than `size` entries.
+    pub fn with_max_world_size(mut self, size: usize) -> Self {
+        self.limits.max_world_size = Some(size);
+        self
+    }
+
+    /// Sets what `set!` does when a parent of its path is missing.
+    /// Defaults to [`PathPolicy::Vivify`].
+    pub fn with_path_policy(mut self, policy: PathPolicy) -> Self {
+        self.path_policy = policy;
+        self
+    }
+
+    /// Sets the text `print` and `println` write between their arguments. Defaults to
+    /// none, so `(print "a" 1)` writes `a1`.
+    pub fn with_print_separator(mut self, separator: impl Into<String>) -> Self {
+        self.print_separator = separator.into();
+        self
+    }
+
+    /// Sets whether constants are folded before evaluation. Defaults to `true`.
+    pub fn with_optimization(mut self, enabled: bool) -> Self {
+        self.no_optimization = !enabled;
+        self
+    }
+
+    /// Sets the least important `log/...` messages written. Defaults to `info`; a
+    /// script can change it with `set-log-level!`.
+    pub fn with_log_level(mut self, level: LogLevel) -> Self {
+        self.log_level = level;
+        self
+    }
+
+    /// Sets whether warnings are errors, failing the prelude or script they are found
+    /// in before it runs. Defaults to `false`, keeping them for
+    /// [`ExecutionPipeline::take_warnings`].
+    pub fn with_deny_warnings(mut self, deny: bool) -> Self {
+        self.deny_warnings = deny;
+        self
+    }
+
+    /// Sets whether `assert` checks its condition. Defaults to `true`; a shipping build
+    /// can turn assertions off, making each `assert` return nil without evaluating
+    /// its arguments.
+    pub fn with_assertions(mut self, enabled: bool) -> Self {
+        self.no_assertions = !enabled;
+        self
+    }
+
+    /// Caches macro expansions in `cache`, so a later pipeline expanding the same
+    /// program with the same macros reads the result instead.
+    pub fn with_ast_cache(mut self, cache: AstCache) -> Self {
+        self.ast_cache = Some(cache);
+        self
+    }
+
+    /// Sets the arguments returned by `(args)`.
+    pub fn with_script_args(mut self, args: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        self.script_args = args.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Sets the sink that program output is written to. Defaults to stdout.
+    pub fn with_output(mut self, output: SharedOutput) -> Self {
+        self.output = Some(output);
+        self
+    }
+
+    /// Builds the pipeline, loading prelude files in the order they were added.
+    pub fn build(self) -> Result<ExecutionPipeline, SutraError> {
+        let mut world = match self.seed {
+            Some(seed) => World::seed_from_u64(seed),
+            None => World::new(),
+        };
+        register_all_atoms(&mut world);
+        for register in self.atom_registries {
+            register(&mut world);
+        }
+        for path in &self.catalog_files {
+            tracing::debug!("loading catalog {}", path.display());
+            world.localization.add_catalog(Catalog::load(path)?);
+        }
+        if let Some(locale) = self.locale {
+            world.localization.locale = locale;
+        }
+        if let Some(depth) = self.history_depth {
+            world.history.set_max_depth(depth);
+        }
+        world.path_policy = self.path_policy;
+        world.print_separator = self.print_separator;
+        world.assertions = !self.no_assertions;
+        world.log_level = self.log_level;
+        world.script_args = self.script_args;
+        if let Some(capabilities) = &self.capabilities {
+            world.revoke_atoms(capabilities);
+        }
+
+        let mut macro_env = build_canonical_macro_env()?;
+        macro_env.set_limits(self.expansion_limits);
+        let mut warnings = Vec::new();
+        for path in &self.prelude_files {
+            tracing::debug!("loading prelude {}", path.display());
+            let name = path.display().to_string();
+            let source = read_file(path)?;
+            let source_context = SourceContext::from_file(&name, &source);
+            let nodes = parser::parse(&source, source_context.clone())?;
+            let found = semantic::check_definitions(&nodes, &world, &macro_env);
+            warnings.push((found, source_context));
+            macro_env.load_from_source(&name, &source)?;
+        }
+        world.macros = macro_env.clone();
+
+        let pipeline = ExecutionPipeline {
+            world: CanonicalWorld::from_world(world),
+            macro_env,
+            max_depth: self.max_depth.unwrap_or(DEFAULT_MAX_DEPTH),
+            validate: false,
+            output: self
+                .output
+                .unwrap_or_else(|| SharedOutput::new(EngineStdoutSink)),
+            time_limit: self.time_limit,
+            capabilities: self.capabilities,
+            limits: self.limits,
+            optimize: !self.no_optimization,
+            ast_cache: self.ast_cache,
+            deny_warnings: self.deny_warnings,
+            warnings: Mutex::default(),
+        };
+        for (found, source_context) in warnings {
+            pipeline.warn(found, &source_context)?;
+        }
+        Ok(pipeline)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn batches_run_each_script_against_its_own_copy_of_the_base() {
+        let pipeline = ExecutionPipeline::builder()
+            .with_output(SharedOutput::new(EngineOutputBuffer::new()))
+            .build()
+            .unwrap();
+        pipeline
+            .execute_for_value("(set! player.hp 10)", "setup")
+            .unwrap();
+        let base = pipeline.world.read().clone();
+        let results = pipeline.execute_batch(
+            &[
+                (
+                    "heal",
+                    "(add! player.hp 5) (print (get player.hp)) (get player.hp)",
+                ),
+                ("loot", "(set! player.gold 3) (get player.hp)"),
+                ("fail", "(set! player.hp 0) (car 1)"),
+                ("broken", "(get player.hp"),
+            ],
+            &base,
+        );
+
+        let names: Vec<&str> = results.iter().map(|r| r.name.as_str()).collect();
+        assert_eq!(names, ["heal", "loot", "fail", "broken"]);
+        let changes = |i: usize| -> Vec<String> {
+            results[i]
+                .diff
+                .changes
+                .iter()
+                .map(|c| c.to_string())
+                .collect()
+        };
+        assert_eq!(results[0].value.as_ref().unwrap(), &Value::Number(15.0));
+        assert_eq!(results[0].output, "15");
+        assert_eq!(changes(0), ["~ player.hp: 10 -> 15"]);
+        assert_eq!(results[1].value.as_ref().unwrap(), &Value::Number(10.0));
+        assert_eq!(changes(1), ["+ player.gold: 3"]);
+        assert!(results[2].value.is_err());
+        assert_eq!(changes(2), ["~ player.hp: 10 -> 0"]);
+        assert!(results[3].value.is_err() && results[3].diff.changes.is_empty());
+        assert_eq!(
+            base.get(&Path(vec!["player".into(), "hp".into()])),
+            Some(&Value::Number(10.0))
+        );
+    }
+
+    #[test]
+    fn benches_report_regressions_instead_of_exiting() {
+        let dir = std::env::temp_dir().join("sutra_bench_regressions");
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("sum.sutra"), "(bench \"sum\" (+ 1 2))\n").unwrap();
+        let config = BenchConfig {
+            warmup: 0,
+            iterations: 3,
+        };
+        let regressed = |median_us: f64| {
+            let stats = bench::BenchStats {
+                iterations: 3,
+                mean_us: median_us,
+                median_us,
+                p95_us: median_us,
+            };
+            let baseline = BenchReport {
+                benches: [("sum.sutra::sum".to_string(), stats)].into(),
+            };
+            let baseline_path = dir.join("baseline.json");
+            baseline.save(&baseline_path).unwrap();
+            run_benches(dir.clone(), Some(baseline_path), None, 10.0, config).unwrap()
+        };
+
+        assert!(regressed(1e-9));
+        assert!(!regressed(1e9));
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,356 @@
+//! Layered CLI Settings
+//!
+//! Settings retyped as flags on every run can live in a user config file,
+//! `~/.config/sutra/config.toml` (`$XDG_CONFIG_HOME/sutra/config.toml` when that is
+//! set, or `$SUTRA_CONFIG`), with the same keys as the flags:
+//!
+//! ```toml
+//! seed = 42
+//! max_depth = 500
+//! paths = "strict"
+//! ast_format = "json"
+//! preludes = ["macros/house-style.sutra"]
+//! ```
+//!
+//! Settings come in layers, each overriding the one before: built-in defaults, the
+//! user config, the project's `sutra.toml`, then flags. Preludes and catalogs add up
+//! instead, loading in that order. Paths in a file are relative to it.
+//! `sutra config show` prints the merged settings and the layer each came from.
+
+use std::{
+    env, fmt, fs,
+    path::{Path, PathBuf},
+    str::FromStr,
+    time::Duration,
+};
+
+use serde::{Deserialize, Deserializer};
+
+use super::{AstFormat, ExecutionPipeline, ExecutionPipelineBuilder, PipelineArgs};
+use crate::{
+    atoms::{LogLevel, PathPolicy},
+    errors::{ErrorKind, SutraError},
+    macros,
+    project::Project,
+    runtime,
+};
+
+/// Names the user config file, overriding where it is looked for.
+pub const CONFIG_ENV: &str = "SUTRA_CONFIG";
+
+/// Settings one layer gives. Every field is optional.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Settings {
+    pub preludes: Vec<PathBuf>,
+    pub catalogs: Vec<PathBuf>,
+    pub locale: Option<String>,
+    pub seed: Option<u64>,
+    pub max_depth: Option<usize>,
+    pub max_macro_depth: Option<usize>,
+    pub max_expansion_nodes: Option<usize>,
+    pub time_limit_ms: Option<u64>,
+    pub max_collection_len: Option<usize>,
+    pub max_string_bytes: Option<usize>,
+    pub max_world_size: Option<usize>,
+    #[serde(deserialize_with = "named")]
+    pub paths: Option<PathPolicy>,
+    pub print_separator: Option<String>,
+    #[serde(deserialize_with = "named")]
+    pub log_level: Option<LogLevel>,
+    /// How `sutra ast` prints an AST.
+    #[serde(deserialize_with = "named")]
+    pub ast_format: Option<AstFormat>,
+}
+
+/// Reads a setting written as its name, e.g. `paths = "strict"`.
+fn named<'de, D, T>(deserializer: D) -> Result<Option<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: FromStr<Err = String>,
+{
+    let name = String::deserialize(deserializer)?;
+    name.parse().map(Some).map_err(serde::de::Error::custom)
+}
+
+impl Settings {
+    /// The settings used when no layer gives one. Those left unset have no default.
+    pub fn defaults() -> Self {
+        Self {
+            max_depth: Some(runtime::DEFAULT_MAX_DEPTH),
+            max_macro_depth: Some(macros::DEFAULT_MAX_DEPTH),
+            max_expansion_nodes: Some(macros::DEFAULT_MAX_NODES),
+            paths: Some(PathPolicy::default()),
+            print_separator: Some(String::new()),
+            log_level: Some(LogLevel::default()),
+            ast_format: Some(AstFormat::Sexpr),
+            ..Self::default()
+        }
+    }
+
+    pub fn from_toml(content: &str, name: &str) -> Result<Self, SutraError> {
+        toml::from_str(content)
+            .map_err(|e| config_error(name, format!("{name} is not a valid config file ({e})")))
+    }
+
+    /// The run settings of a project manifest, with its paths resolved.
+    pub fn from_project(project: &Project) -> Self {
+        let manifest = &project.manifest;
+        let resolve = |paths: &[PathBuf]| paths.iter().map(|path| project.resolve(path)).collect();
+        Self {
+            preludes: resolve(&manifest.preludes),
+            catalogs: resolve(&manifest.catalogs),
+            locale: manifest.locale.clone(),
+            seed: manifest.seed,
+            max_depth: manifest.max_depth,
+            max_macro_depth: manifest.max_macro_depth,
+            max_expansion_nodes: manifest.max_expansion_nodes,
+            time_limit_ms: manifest.time_limit_ms,
+            ..Self::default()
+        }
+    }
+
+    /// Resolves the prelude and catalog paths against `dir`.
+    fn relative_to(mut self, dir: &Path) -> Self {
+        for path in self.preludes.iter_mut().chain(&mut self.catalogs) {
+            *path = dir.join(&*path);
+        }
+        self
+    }
+
+    /// Applies the settings given on top of `builder`.
+    pub fn configure(&self, mut builder: ExecutionPipelineBuilder) -> ExecutionPipelineBuilder {
+        for prelude in &self.preludes {
+            builder = builder.with_prelude_file(prelude);
+        }
+        for catalog in &self.catalogs {
+            builder = builder.with_catalog_file(catalog);
+        }
+        if let Some(locale) = &self.locale {
+            builder = builder.with_locale(locale);
+        }
+        if let Some(seed) = self.seed {
+            builder = builder.with_seed(seed);
+        }
+        if let Some(max_depth) = self.max_depth {
+            builder = builder.with_max_depth(max_depth);
+        }
+        if let Some(depth) = self.max_macro_depth {
+            builder = builder.with_max_macro_depth(depth);
+        }
+        if let Some(nodes) = self.max_expansion_nodes {
+            builder = builder.with_max_expansion_nodes(nodes);
+        }
+        if let Some(ms) = self.time_limit_ms {
+            builder = builder.with_time_limit(Duration::from_millis(ms));
+        }
+        if let Some(len) = self.max_collection_len {
+            builder = builder.with_max_collection_len(len);
+        }
+        if let Some(bytes) = self.max_string_bytes {
+            builder = builder.with_max_string_bytes(bytes);
+        }
+        if let Some(size) = self.max_world_size {
+            builder = builder.with_max_world_size(size);
+        }
+        if let Some(policy) = self.paths {
+            builder = builder.with_path_policy(policy);
+        }
+        if let Some(separator) = &self.print_separator {
+            builder = builder.with_print_separator(separator);
+        }
+        if let Some(level) = self.log_level {
+            builder = builder.with_log_level(level);
+        }
+        builder
+    }
+
+    /// Every setting by name, in file order, with its value if given. Preludes and
+    /// catalogs are given when there are any.
+    fn entries(&self) -> Vec<(&'static str, Option<String>)> {
+        fn shown<T: fmt::Display>(value: &Option<T>) -> Option<String> {
+            value.as_ref().map(ToString::to_string)
+        }
+        let paths = |paths: &[PathBuf]| {
+            let shown: Vec<_> = paths
+                .iter()
+                .map(|path| path.display().to_string())
+                .collect();
+            (!shown.is_empty()).then(|| shown.join(", "))
+        };
+        vec![
+            ("preludes", paths(&self.preludes)),
+            ("catalogs", paths(&self.catalogs)),
+            ("locale", shown(&self.locale)),
+            ("seed", shown(&self.seed)),
+            ("max_depth", shown(&self.max_depth)),
+            ("max_macro_depth", shown(&self.max_macro_depth)),
+            ("max_expansion_nodes", shown(&self.max_expansion_nodes)),
+            ("time_limit_ms", shown(&self.time_limit_ms)),
+            ("max_collection_len", shown(&self.max_collection_len)),
+            ("max_string_bytes", shown(&self.max_string_bytes)),
+            ("max_world_size", shown(&self.max_world_size)),
+            ("paths", shown(&self.paths)),
+            (
+                "print_separator",
+                self.print_separator.as_ref().map(|s| format!("{s:?}")),
+            ),
+            ("log_level", shown(&self.log_level)),
+            ("ast_format", shown(&self.ast_format)),
+        ]
+    }
+}
+
+/// Where the user config file is looked for: `$SUTRA_CONFIG`, or `sutra/config.toml`
+/// under `$XDG_CONFIG_HOME`, or under `~/.config` when that is not set.
+pub fn user_config_path() -> Option<PathBuf> {
+    if let Some(path) = env::var_os(CONFIG_ENV) {
+        return Some(PathBuf::from(path));
+    }
+    let base = env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .filter(|dir| dir.is_absolute())
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+    Some(base.join("sutra").join("config.toml"))
+}
+
+/// The user config file and its settings, if there is one. A file named by
+/// `$SUTRA_CONFIG` must exist.
+pub fn user_config() -> Result<Option<(PathBuf, Settings)>, SutraError> {
+    let Some(path) = user_config_path() else {
+        return Ok(None);
+    };
+    if !path.is_file() && env::var_os(CONFIG_ENV).is_none() {
+        return Ok(None);
+    }
+    let name = path.display().to_string();
+    let content =
+        fs::read_to_string(&path).map_err(|e| config_error(&name, format!("{name} ({e})")))?;
+    let settings = Settings::from_toml(&content, &name)?;
+    let dir = path.parent().unwrap_or(Path::new("")).to_path_buf();
+    Ok(Some((path, settings.relative_to(&dir))))
+}
+
+/// A pipeline builder with the user config applied, then `project`'s manifest.
+pub fn base_builder(project: Option<&Project>) -> Result<ExecutionPipelineBuilder, SutraError> {
+    let mut builder = ExecutionPipeline::builder();
+    if let Some((_, settings)) = user_config()? {
+        builder = settings.configure(builder);
+    }
+    Ok(match project {
+        Some(project) => project.configure(builder),
+        None => builder,
+    })
+}
+
+/// A pipeline builder with every layer applied: the user config, then `project`'s
+/// manifest, then `flags`.
+pub fn layered_builder(
+    project: Option<&Project>,
+    flags: &PipelineArgs,
+) -> Result<ExecutionPipelineBuilder, SutraError> {
+    Ok(flags.configure(base_builder(project)?))
+}
+
+/// The layer a setting came from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Source {
+    Default,
+    User,
+    Project,
+    Flag,
+}
+
+impl fmt::Display for Source {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Default => write!(f, "default"),
+            Self::User => write!(f, "user config"),
+            Self::Project => write!(f, "project"),
+            Self::Flag => write!(f, "flag"),
+        }
+    }
+}
+
+/// The effective value of each setting and the layers it came from, merging `layers`
+/// in order. A setting no layer gives has no value and no source.
+pub fn merge(layers: &[(Source, Settings)]) -> Vec<(&'static str, Option<String>, Vec<Source>)> {
+    let mut merged: Vec<(&'static str, Option<String>, Vec<Source>)> = Settings::default()
+        .entries()
+        .into_iter()
+        .map(|(name, _)| (name, None, Vec::new()))
+        .collect();
+    for (source, settings) in layers {
+        for ((name, value, sources), (_, given)) in merged.iter_mut().zip(settings.entries()) {
+            let Some(given) = given else {
+                continue;
+            };
+            if matches!(*name, "preludes" | "catalogs") {
+                *value = Some(match value.take() {
+                    Some(earlier) => format!("{earlier}, {given}"),
+                    None => given,
+                });
+                sources.push(*source);
+            } else {
+                *value = Some(given);
+                *sources = vec![*source];
+            }
+        }
+    }
+    merged
+}
+
+fn config_error(resource: &str, message: String) -> SutraError {
+    SutraError::without_source(ErrorKind::GeneralValidation { message }, resource, "config")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn later_layers_override_and_paths_add_up() {
+        let user = Settings::from_toml(
+            "seed = 1\npaths = \"strict\"\npreludes = [\"a.sutra\"]\n",
+            "config.toml",
+        )
+        .unwrap();
+        let flags = Settings {
+            seed: Some(3),
+            preludes: vec![PathBuf::from("b.sutra")],
+            ..Settings::default()
+        };
+        let merged = merge(&[
+            (Source::Default, Settings::defaults()),
+            (Source::User, user),
+            (Source::Flag, flags),
+        ]);
+        let setting = |name: &str| {
+            let (_, value, sources) = merged.iter().find(|(n, ..)| *n == name).unwrap();
+            (value.clone(), sources.clone())
+        };
+        assert_eq!(setting("seed"), (Some("3".into()), vec![Source::Flag]));
+        assert_eq!(
+            setting("paths"),
+            (Some("strict".into()), vec![Source::User])
+        );
+        assert_eq!(
+            setting("max_depth"),
+            (Some("1000".into()), vec![Source::Default])
+        );
+        assert_eq!(
+            setting("preludes"),
+            (
+                Some("a.sutra, b.sutra".into()),
+                vec![Source::User, Source::Flag]
+            )
+        );
+        assert_eq!(setting("locale"), (None, Vec::new()));
+    }
+
+    #[test]
+    fn unknown_keys_and_names_are_errors() {
+        assert!(Settings::from_toml("max_steps = 10\n", "config.toml").is_err());
+        assert!(Settings::from_toml("paths = \"loose\"\n", "config.toml").is_err());
+    }
+}
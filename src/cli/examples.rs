@@ -0,0 +1,93 @@
+//! Built-in Examples
+//!
+//! `sutra examples` lists a few short scripts compiled into the binary, each showing
+//! one part of the language, so there is something to run before writing any. The
+//! sources live in the repository's `examples/` directory. Running one writes its
+//! files to a temporary directory and runs them there as `sutra run` would, or
+//! `sutra test` for the testing example, so diagnostics name files that can be
+//! opened.
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use crate::errors::{ErrorKind, SutraError};
+
+/// A script built into `sutra`.
+#[derive(Debug, Clone, Copy)]
+pub struct Example {
+    /// The name `sutra examples run` takes.
+    pub name: &'static str,
+    /// What the example shows, in one line.
+    pub summary: &'static str,
+    /// The script.
+    pub source: &'static str,
+    /// The macros the script uses, loaded before it, if it defines its own.
+    pub prelude: Option<&'static str>,
+    /// Whether the script is a file of `(test ...)` forms, run with the test runner.
+    pub tests: bool,
+}
+
+/// Every built-in example, in the order they are listed.
+pub const EXAMPLES: &[Example] = &[
+    Example {
+        name: "state",
+        summary: "Read and change world state at paths such as player.gold",
+        source: include_str!("../../examples/state.sutra"),
+        prelude: None,
+        tests: false,
+    },
+    Example {
+        name: "macros",
+        summary: "Define template macros in a prelude and call them from a script",
+        source: include_str!("../../examples/macros.sutra"),
+        prelude: Some(include_str!("../../examples/macros.prelude.sutra")),
+        tests: false,
+    },
+    Example {
+        name: "testing",
+        summary: "Write tests with expectations and fixtures, run by `sutra test`",
+        source: include_str!("../../examples/testing.sutra"),
+        prelude: None,
+        tests: true,
+    },
+    Example {
+        name: "dialogue",
+        summary: "Ask the player a question with `choice` and branch on the answer",
+        source: include_str!("../../examples/dialogue.sutra"),
+        prelude: None,
+        tests: false,
+    },
+];
+
+/// The example called `name`.
+pub fn find(name: &str) -> Option<&'static Example> {
+    EXAMPLES.iter().find(|example| example.name == name)
+}
+
+impl Example {
+    /// Writes the script to `dir` as `<name>.sutra`, and the prelude beside it as
+    /// `<name>.prelude.sutra`, creating `dir` if needed. Returns their paths.
+    pub fn write_to(&self, dir: &Path) -> Result<(PathBuf, Option<PathBuf>), SutraError> {
+        fs::create_dir_all(dir).map_err(|e| write_error(dir, e))?;
+        let script = dir.join(format!("{}.sutra", self.name));
+        fs::write(&script, self.source).map_err(|e| write_error(&script, e))?;
+        let Some(prelude_source) = self.prelude else {
+            return Ok((script, None));
+        };
+        let prelude = dir.join(format!("{}.prelude.sutra", self.name));
+        fs::write(&prelude, prelude_source).map_err(|e| write_error(&prelude, e))?;
+        Ok((script, Some(prelude)))
+    }
+}
+
+fn write_error(path: &Path, e: std::io::Error) -> SutraError {
+    SutraError::without_source(
+        ErrorKind::InvalidPath {
+            path: format!("{} ({e})", path.display()),
+        },
+        path.display().to_string(),
+        "file-system",
+    )
+}
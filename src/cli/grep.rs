@@ -0,0 +1,201 @@
+//! Syntax-Aware Search
+//!
+//! `sutra grep` finds where a symbol or world path is used by walking each script's
+//! AST rather than its text, so it skips comments and strings and finds a path
+//! however it was written: `player.gold`, or `(path "player" "gold")`. A dotted
+//! pattern also matches the paths below it, so `player` finds `player.gold`. Each hit
+//! is reported with the innermost form around it.
+//!
+//! Searching before macro expansion finds what was written; searching after it
+//! (`--expanded`) finds what macro calls expand to instead, reported at the call
+//! since the code a macro writes has no place in the script.
+
+use crate::{
+    prelude::*,
+    syntax::{walk_node, Visitor},
+};
+
+/// A symbol or world path to search for, as its dot-separated components.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Pattern(Vec<String>);
+
+impl Pattern {
+    pub fn new(pattern: &str) -> Self {
+        Self(pattern.split('.').map(String::from).collect())
+    }
+
+    fn matches(&self, components: &[String]) -> bool {
+        components.starts_with(&self.0)
+    }
+}
+
+/// A use of the pattern, with the form it appears in.
+#[derive(Debug)]
+pub struct Hit {
+    pub span: Span,
+    pub form: AstNode,
+}
+
+/// Every use of `pattern` in `roots`, in source order. With `macros`, macro calls and
+/// interpolated strings are searched as they expand, and a use found in an expansion
+/// is reported at the call, with the expanded form around it.
+pub fn search(
+    pattern: &Pattern,
+    roots: &[AstNode],
+    macros: Option<&MacroSystem>,
+) -> Result<Vec<Hit>, SutraError> {
+    let mut search = Search {
+        pattern,
+        macros,
+        enclosing: Vec::new(),
+        hits: Vec::new(),
+        error: None,
+    };
+    for root in roots {
+        search.visit_node(root);
+    }
+    match search.error {
+        Some(error) => Err(error),
+        None => Ok(search.hits),
+    }
+}
+
+struct Search<'p, 'ast> {
+    pattern: &'p Pattern,
+    macros: Option<&'p MacroSystem>,
+    /// The lists around the node being visited, innermost last.
+    enclosing: Vec<&'ast AstNode>,
+    hits: Vec<Hit>,
+    /// The first expansion that failed, which ends the search.
+    error: Option<SutraError>,
+}
+
+impl Search<'_, '_> {
+    /// Searches the expansion of `node` if it is a macro call or an interpolated
+    /// string, returning whether it was one.
+    fn search_expansion(&mut self, node: &AstNode) -> bool {
+        let Some(macros) = self.macros.filter(|macros| expands(macros, node)) else {
+            return false;
+        };
+        let found = macros
+            .expand(node.clone())
+            .and_then(|expanded| search(self.pattern, &[expanded], None));
+        match found {
+            Ok(hits) => self.hits.extend(hits.into_iter().map(|hit| Hit {
+                span: node.span,
+                form: hit.form,
+            })),
+            Err(error) => self.error = Some(error),
+        }
+        true
+    }
+}
+
+impl<'ast> Visitor<'ast> for Search<'_, 'ast> {
+    fn visit_node(&mut self, node: &'ast AstNode) {
+        if self.error.is_some() || self.search_expansion(node) {
+            return;
+        }
+        if path_components(node).is_some_and(|components| self.pattern.matches(&components)) {
+            self.hits.push(Hit {
+                span: node.span,
+                form: self.enclosing.last().copied().unwrap_or(node).clone(),
+            });
+            return;
+        }
+        let is_list = matches!(&*node.value, Expr::List(..));
+        if is_list {
+            self.enclosing.push(node);
+        }
+        walk_node(self, node);
+        if is_list {
+            self.enclosing.pop();
+        }
+    }
+}
+
+/// Whether `node` is something `macros` would expand: a call of one of them or a
+/// string with interpolated code.
+fn expands(macros: &MacroSystem, node: &AstNode) -> bool {
+    match &*node.value {
+        Expr::String(text, _, false) => text.contains(['{', '}']),
+        Expr::List(items, _) => items.first().is_some_and(
+            |head| matches!(&*head.value, Expr::Symbol(name, _) if macros.has_macro(name)),
+        ),
+        _ => false,
+    }
+}
+
+/// The components `node` names: a symbol's name, a path's components, or the
+/// arguments of a `(path ...)` call when all are strings.
+fn path_components(node: &AstNode) -> Option<Vec<String>> {
+    match &*node.value {
+        Expr::Symbol(name, _) => Some(vec![name.clone()]),
+        Expr::Path(path, _) => Some(path.0.clone()),
+        Expr::List(items, _) => {
+            let (head, args) = items.split_first()?;
+            if !matches!(&*head.value, Expr::Symbol(name, _) if name == "path") || args.is_empty() {
+                return None;
+            }
+            args.iter()
+                .map(|arg| match &*arg.value {
+                    Expr::String(s, ..) => Some(s.clone()),
+                    _ => None,
+                })
+                .collect()
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{errors::SourceContext, parser};
+
+    fn hits(pattern: &str, source: &str) -> Vec<String> {
+        let nodes = parser::parse(source, SourceContext::from_file("test", source)).unwrap();
+        search(&Pattern::new(pattern), &nodes, None)
+            .unwrap()
+            .iter()
+            .map(|hit| hit.form.value.pretty())
+            .collect()
+    }
+
+    #[test]
+    fn finds_paths_however_written() {
+        let source = "(set! player.gold 5)\n; player.gold\n(print \"player.gold\")\n(get (path \"player\" \"gold\"))\n(get player.hp)";
+        assert_eq!(
+            hits("player.gold", source),
+            ["(set! player.gold 5)", "(get (path \"player\" \"gold\"))"]
+        );
+        assert_eq!(hits("player", source).len(), 3);
+        assert!(hits("player.gold.coins", source).is_empty());
+    }
+
+    #[test]
+    fn reports_symbols_with_the_innermost_form() {
+        assert_eq!(
+            hits("heal", "(do (if ok (heal 2) 0))\nheal"),
+            ["(heal 2)", "heal"]
+        );
+        assert!(hits("hea", "(heal 2)").is_empty());
+    }
+
+    #[test]
+    fn reports_uses_in_expansions_at_the_call() {
+        let mut macros = MacroSystem::new();
+        macros
+            .load_from_source("test", "(define (pay! n) (add! player.gold n))")
+            .unwrap();
+        let source = "(do (pay! 5))";
+        let nodes = parser::parse(source, SourceContext::from_file("test", source)).unwrap();
+        let hits = search(&Pattern::new("player.gold"), &nodes, Some(&macros)).unwrap();
+        assert_eq!(hits.len(), 1);
+        assert_eq!(hits[0].span, Span { start: 4, end: 12 });
+        assert_eq!(hits[0].form.value.pretty(), "(add! player.gold 5)");
+        assert!(search(&Pattern::new("pay!"), &nodes, Some(&macros))
+            .unwrap()
+            .is_empty());
+    }
+}
@@ -0,0 +1,189 @@
+//! Load-Time Snapshots
+//!
+//! Most scripts open with definitions: functions, constants, records, prototypes and
+//! weighted tables. `run` evaluates these once and keeps what they defined in the
+//! [`AstCache`], so a later run of the same scripts restores them instead.
+//!
+//! Only a script's load-time prefix is kept: its top-level forms, from the first, that
+//! [`semantic::load_time_prefix`] finds do the same thing on every run. The prefix
+//! ends at the first form that reads the world state, writes output, draws a random
+//! number or runs a function, and the rest of the script is evaluated as usual. A
+//! script after it is evaluated as usual too, since what it defines may depend on
+//! what ran before it; a project whose included files only define things is restored
+//! up to its entry point.
+//!
+//! Each entry is keyed by the script after expansion and constant folding, the
+//! scripts before it, the pipeline's limits and the engine build. Scripts are still
+//! parsed, checked and expanded on every run, so warnings and errors are the same
+//! whether or not an entry is found, and a prefix whose definitions have no saved
+//! form, such as a coroutine, is simply evaluated every time.
+
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+use super::ExecutionPipeline;
+use crate::{
+    ast_cache::{self, AstCache},
+    atoms::{prototypes::Prototype, roll_table::RollTable},
+    parser,
+    prelude::*,
+    semantic_validation as semantic,
+};
+
+/// What a script's load-time prefix added to the world.
+#[derive(Debug, Serialize, Deserialize)]
+struct Loaded {
+    /// How many of the script's top-level forms the prefix is.
+    forms: usize,
+    /// The value of the last of them.
+    value: Value,
+    defs: BTreeMap<String, Value>,
+    prototypes: BTreeMap<String, Prototype>,
+    roll_tables: BTreeMap<String, RollTable>,
+}
+
+impl Loaded {
+    /// The definitions, prototypes and weighted tables of `world`.
+    fn capture(world: &World) -> Self {
+        Self {
+            forms: 0,
+            value: Value::Nil,
+            defs: world.defs.clone(),
+            prototypes: world.prototypes.clone(),
+            roll_tables: world.roll_tables.clone(),
+        }
+    }
+
+    /// Keeps only what is new or changed since `before`.
+    fn since(mut self, before: &Self) -> Self {
+        self.defs
+            .retain(|name, value| before.defs.get(name) != Some(value));
+        self.prototypes
+            .retain(|name, prototype| before.prototypes.get(name) != Some(prototype));
+        self.roll_tables
+            .retain(|name, table| before.roll_tables.get(name) != Some(table));
+        self
+    }
+
+    fn restore(&self, world: &mut World) {
+        world.defs.extend(self.defs.clone());
+        world.prototypes.extend(self.prototypes.clone());
+        world.roll_tables.extend(self.roll_tables.clone());
+    }
+}
+
+/// Runs scripts in order against one pipeline, restoring the load-time prefix of each
+/// from `cache` where it can; see the [module docs](self).
+pub struct Loader<'p> {
+    pipeline: &'p ExecutionPipeline,
+    cache: Option<&'p AstCache>,
+    /// The key of the scripts run so far, while each has been all prefix.
+    chain: Option<String>,
+}
+
+impl<'p> Loader<'p> {
+    pub fn new(pipeline: &'p ExecutionPipeline, cache: Option<&'p AstCache>) -> Self {
+        let settings = format!("{} {:?}", pipeline.max_depth, pipeline.limits);
+        Self {
+            pipeline,
+            cache,
+            chain: cache.map(|_| ast_cache::key(&[b"load", settings.as_bytes()])),
+        }
+    }
+
+    /// Runs `source` as [`ExecutionPipeline::execute_for_value`] does, returning its
+    /// value.
+    pub fn execute(&mut self, source: &str, filename: &str) -> Result<Value, SutraError> {
+        let (program, source_context) = self.pipeline.compile(source, filename)?;
+        let (Some(cache), Some(chain)) = (self.cache, self.chain.take()) else {
+            return self.pipeline.evaluate_prepared(&program, source_context);
+        };
+        let Ok(encoded) = bincode::serialize(&program) else {
+            return self.pipeline.evaluate_prepared(&program, source_context);
+        };
+        let key = ast_cache::key(&[chain.as_bytes(), &encoded]);
+        let forms = top_level_forms(&program);
+
+        let (prefix, value) = match cache.read::<Loaded>(&key) {
+            Some(loaded) => {
+                tracing::debug!(
+                    "restored the first {} forms of {filename} from the cache",
+                    loaded.forms
+                );
+                loaded.restore(&mut self.pipeline.world.write());
+                (loaded.forms, loaded.value)
+            }
+            None => {
+                let prefix = semantic::load_time_prefix(forms, &self.pipeline.world.read());
+                if prefix == 0 {
+                    return self.pipeline.evaluate_prepared(&program, source_context);
+                }
+                let before = Loaded::capture(&self.pipeline.world.read());
+                let prefix_program = parser::wrap_in_do(forms[..prefix].to_vec());
+                let value = self
+                    .pipeline
+                    .evaluate_prepared(&prefix_program, source_context.clone())?;
+                let loaded = Loaded {
+                    forms: prefix,
+                    value: value.clone(),
+                    ..Loaded::capture(&self.pipeline.world.read()).since(&before)
+                };
+                cache.write(&key, &loaded);
+                (prefix, value)
+            }
+        };
+        if prefix == forms.len() {
+            self.chain = Some(key);
+            return Ok(value);
+        }
+        let rest = parser::wrap_in_do(forms[prefix..].to_vec());
+        self.pipeline.evaluate_prepared(&rest, source_context)
+    }
+}
+
+/// The top-level forms of a prepared script, which wraps more than one in a `do`.
+fn top_level_forms(program: &AstNode) -> &[AstNode] {
+    match &*program.value {
+        Expr::List(items, _)
+            if items
+                .first()
+                .is_some_and(|head| matches!(&*head.value, Expr::Symbol(s, _) if s == "do")) =>
+        {
+            &items[1..]
+        }
+        _ => std::slice::from_ref(program),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn definitions_round_trip_through_the_cache() {
+        let cache = AstCache::new(std::env::temp_dir().join("sutra_preload_round_trip"));
+        cache.clear().unwrap();
+        let source = "(define (f x) (+ x 1)) \
+                      (define big (range 0 20000)) \
+                      (define mixed (list 's :k \"x\" (cons 1 2) (list true)))";
+        let run = || {
+            let pipeline = ExecutionPipeline::default();
+            let mut loader = Loader::new(&pipeline, Some(&cache));
+            loader.execute(source, "defs.sutra").unwrap();
+            let defs = pipeline.world.read().defs.clone();
+            (defs, loader.execute("(f (len big))", "main.sutra").unwrap())
+        };
+        let (evaluated, value) = run();
+        assert_eq!(value, Value::Number(20001.0));
+
+        let entry = std::fs::read_dir(cache.dir()).unwrap().next().unwrap();
+        let loaded: Loaded = bincode::deserialize(&std::fs::read(entry.unwrap().path()).unwrap())
+            .expect("the entry reads back");
+        assert_eq!(loaded.forms, 3);
+        for name in ["f", "big", "mixed"] {
+            assert_eq!(loaded.defs.get(name), evaluated.get(name), "{name}");
+        }
+        assert_eq!(run(), (evaluated, value));
+    }
+}
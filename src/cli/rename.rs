@@ -0,0 +1,462 @@
+//! Renaming Definitions
+//!
+//! `sutra rename old new` renames a definition and every use of it. Uses are found in
+//! the AST, so comments, strings, quoted data and world paths are left alone, and
+//! each is rewritten in place at its span, so the rest of the source keeps its
+//! layout. A name is only a use where it means the definition:
+//!
+//! - A parameter or `let` binding of the same name hides it.
+//! - Inside a module that defines the name itself, the plain name means the module's
+//!   definition. A module's definition is renamed as `shop/buy!`, which renames
+//!   `shop/buy!` outside the module and `buy!` inside it.
+//!
+//! A rename that would change what some name means fails instead: when the new name
+//! is already defined alongside the old one, or a renamed use falls where a local
+//! binding has the new name. Uses inside a string's `{...}` interpolations are renamed
+//! too, unless escapes in the string hide where they are in the source; then the
+//! rename fails, pointing at the string.
+
+use crate::{
+    errors::{
+        to_source_span, ErrorKind, ErrorReporting, SourceContext, SutraError, ValidationContext,
+    },
+    macros::{
+        module::{gather_headers, ModuleDefinition, MODULE},
+        split_interpolation, Segment,
+    },
+    parser,
+    prelude::*,
+    syntax::{walk_node, Visitor},
+};
+
+/// A definition to rename, by its plain names and the module defining it, if any.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Rename {
+    module: Option<String>,
+    old: String,
+    new: String,
+}
+
+/// Replacement text for a span of the source.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Edit {
+    pub span: Span,
+    pub text: String,
+}
+
+impl Rename {
+    /// Renames `old` to `new`. A module's definition is named as `module/name`, and
+    /// its new name may be given with or without the module.
+    pub fn new(old: &str, new: &str) -> Result<Self, SutraError> {
+        for name in [old, new] {
+            if !is_symbol_text(name) {
+                return Err(invalid(format!("rename: '{name}' is not a symbol")));
+            }
+        }
+        let (module, old_name) = split_qualified(old);
+        let new_name = match (split_qualified(new), &module) {
+            ((None, name), _) => name,
+            ((Some(new_module), name), Some(module)) if new_module == *module => name,
+            _ => {
+                return Err(invalid(format!(
+                    "rename: '{new}' is in another module than '{old}'; \
+                     a rename keeps a definition where it is"
+                )))
+            }
+        };
+        if old_name == new_name {
+            return Err(invalid(format!("rename: '{old}' already has that name")));
+        }
+        Ok(Self {
+            module,
+            old: old_name,
+            new: new_name,
+        })
+    }
+
+    fn qualified(&self, name: &str) -> Option<String> {
+        self.module
+            .as_ref()
+            .map(|module| format!("{module}/{name}"))
+    }
+
+    /// The edits renaming the definition and its uses in `nodes`, parsed from
+    /// `source`, in source order.
+    pub fn edits(
+        &self,
+        nodes: Vec<AstNode>,
+        source: &SourceContext,
+    ) -> Result<Vec<Edit>, SutraError> {
+        let mut renamer = Renamer {
+            rename: self,
+            source: source.content(),
+            plain: self.module.is_none(),
+            locals: Vec::new(),
+            edits: Vec::new(),
+            conflict: None,
+        };
+        renamer.visit_node(&gather_headers(parser::wrap_in_do(nodes)));
+        if let Some((span, message)) = renamer.conflict {
+            let context = ValidationContext::new(source.clone(), "rename");
+            return Err(context.report(
+                ErrorKind::GeneralValidation { message },
+                to_source_span(span),
+            ));
+        }
+        renamer.edits.sort_by_key(|edit| edit.span.start);
+        Ok(renamer.edits)
+    }
+}
+
+/// `source` with each of `edits`, which must not overlap, applied.
+pub fn apply(source: &str, edits: &[Edit]) -> String {
+    let mut text = String::with_capacity(source.len());
+    let mut end = 0;
+    for edit in edits {
+        text.push_str(&source[end..edit.span.start]);
+        text.push_str(&edit.text);
+        end = edit.span.end;
+    }
+    text.push_str(&source[end..]);
+    text
+}
+
+struct Renamer<'a> {
+    rename: &'a Rename,
+    source: &'a str,
+    /// Whether the plain old name means the definition here, outside local bindings.
+    plain: bool,
+    /// The names bound by the parameters and `let`s around the node being visited.
+    locals: Vec<String>,
+    edits: Vec<Edit>,
+    /// The first place the rename would change what a name means, and why.
+    conflict: Option<(Span, String)>,
+}
+
+impl Renamer<'_> {
+    /// Renames the symbol at `span` if it is a use of the definition.
+    fn visit_symbol(&mut self, name: &str, span: Span) {
+        match self.rename.qualified(&self.rename.old) {
+            Some(qualified) if name == qualified => self.edits.push(Edit {
+                span,
+                text: self.rename.qualified(&self.rename.new).unwrap_or_default(),
+            }),
+            _ => self.plain_use(name, span),
+        }
+    }
+
+    /// Renames the plain name at `span` if it is a use of the definition.
+    fn plain_use(&mut self, name: &str, span: Span) {
+        let rename = self.rename;
+        if !self.plain || name != rename.old || self.locals.contains(&rename.old) {
+            return;
+        }
+        if self.locals.contains(&rename.new) {
+            self.conflict(
+                span,
+                format!(
+                    "renaming '{}' here would make it mean the local '{}'",
+                    rename.old, rename.new
+                ),
+            );
+        }
+        self.edits.push(Edit {
+            span,
+            text: rename.new.clone(),
+        });
+    }
+
+    /// Notes a definition of `name` at `span`, which must not already have the new name.
+    fn definition(&mut self, name: &str, span: Span) {
+        if self.plain && self.locals.is_empty() && name == self.rename.new {
+            self.conflict(
+                span,
+                format!("'{}' is already defined here", self.rename.new),
+            );
+        }
+        self.plain_use(name, span);
+    }
+
+    fn conflict(&mut self, span: Span, message: String) {
+        self.conflict.get_or_insert((span, message));
+    }
+
+    /// Visits `forms` with `bound` as local bindings.
+    fn visit_scoped(&mut self, bound: Vec<String>, forms: &[AstNode]) {
+        let depth = self.locals.len();
+        self.locals.extend(bound);
+        for form in forms {
+            self.visit_node(form);
+        }
+        self.locals.truncate(depth);
+    }
+
+    /// Visits a `(define|lambda params body...)` form.
+    fn visit_function(&mut self, items: &[AstNode], named: bool) {
+        let mut bound = match &*items[1].value {
+            Expr::ParamList(params) => {
+                let mut bound = params.required.clone();
+                bound.extend(params.rest.clone());
+                bound
+            }
+            Expr::List(signature, _) => signature.iter().filter_map(symbol_name).collect(),
+            _ => unreachable!("only called for parameter lists"),
+        };
+        if named && !bound.is_empty() {
+            let name = bound.remove(0);
+            if let Some(span) = self.name_span(&items[1], &name) {
+                self.definition(&name, span);
+            }
+        }
+        self.visit_scoped(bound, &items[2..]);
+    }
+
+    /// The span of the first name in a signature, which is `name`.
+    fn name_span(&self, signature: &AstNode, name: &str) -> Option<Span> {
+        if let Expr::List(items, _) = &*signature.value {
+            return items.first().map(|first| first.span);
+        }
+        // A parameter list keeps its names without their spans
+        let span = signature.span;
+        let text = self.source.get(span.start..span.end)?;
+        let offset = text.find(|c: char| c != '(' && !c.is_whitespace())?;
+        let start = span.start + offset;
+        let span = Span {
+            start,
+            end: start + name.len(),
+        };
+        (self.source.get(span.start..span.end) == Some(name)).then_some(span)
+    }
+
+    /// Visits a `(let ((name value)...) body...)` form.
+    fn visit_let(&mut self, items: &[AstNode]) {
+        let Expr::List(bindings, _) = &*items[1].value else {
+            unreachable!("only called for binding lists");
+        };
+        let mut bound = Vec::new();
+        for binding in bindings {
+            let Expr::List(pair, _) = &*binding.value else {
+                self.visit_node(binding);
+                continue;
+            };
+            bound.extend(pair.first().and_then(symbol_name));
+            for value in pair.iter().skip(1) {
+                self.visit_node(value);
+            }
+        }
+        self.visit_scoped(bound, &items[2..]);
+    }
+
+    /// Visits the code interpolated into the string `text` at `span`, renaming uses
+    /// there where the code can be found in the source.
+    fn visit_interpolations(&mut self, text: &str, span: Span) {
+        let Ok(segments) = split_interpolation(text, span) else {
+            return;
+        };
+        // Without escapes the string's content is its source text, after the quote
+        let source = self.source;
+        let in_source = source.get(span.start + 1..span.end.saturating_sub(1)) == Some(text);
+        for segment in segments {
+            let Segment::Code(code, start, _) = segment else {
+                continue;
+            };
+            let Ok(nodes) = parser::parse(&code, SourceContext::from_file("interpolation", &code))
+            else {
+                continue;
+            };
+            let offset = span.start + 1 + start;
+            let (edits, outer) = (self.edits.len(), self.conflict.take());
+            self.source = match in_source {
+                true => &source[offset..offset + code.len()],
+                false => "",
+            };
+            for node in &nodes {
+                self.visit_node(node);
+            }
+            self.source = source;
+            let found = self.edits.len() > edits || self.conflict.is_some();
+            let inner = std::mem::replace(&mut self.conflict, outer);
+            if in_source {
+                for edit in &mut self.edits[edits..] {
+                    edit.span = shifted(edit.span, offset);
+                }
+                if let Some((at, message)) = inner {
+                    self.conflict(shifted(at, offset), message);
+                }
+            } else if found {
+                self.edits.truncate(edits);
+                self.conflict(
+                    span,
+                    format!(
+                        "'{}' is used in an interpolation this rename cannot rewrite; \
+                         rename it there by hand",
+                        self.rename.old
+                    ),
+                );
+            }
+        }
+    }
+
+    /// Visits a module, where the plain name means the module's own definition if it
+    /// has one.
+    fn visit_module(&mut self, module: &ModuleDefinition, items: &[AstNode]) {
+        let defines = module.defined_names().contains(&self.rename.old);
+        let plain = match &self.rename.module {
+            Some(name) => *name == module.name && defines,
+            None => !defines,
+        };
+        let outer = std::mem::replace(&mut self.plain, plain);
+        for item in &items[2..] {
+            self.visit_node(item);
+        }
+        self.plain = outer;
+    }
+}
+
+impl<'ast> Visitor<'ast> for Renamer<'_> {
+    fn visit_node(&mut self, node: &'ast AstNode) {
+        let Expr::List(items, _) = &*node.value else {
+            match &*node.value {
+                Expr::Symbol(name, span) => self.visit_symbol(name, *span),
+                Expr::String(text, span, false) => self.visit_interpolations(text, *span),
+                _ => {}
+            }
+            return walk_node(self, node);
+        };
+        let [head, second, ..] = items.as_slice() else {
+            return walk_node(self, node);
+        };
+        let is_signature = matches!(&*second.value, Expr::ParamList(_) | Expr::List(..));
+        match symbol_name(head).as_deref() {
+            Some("define") if is_signature => self.visit_function(items, true),
+            Some("define") => {
+                if let Expr::Symbol(name, span) = &*second.value {
+                    self.definition(name, *span);
+                }
+                for item in &items[2..] {
+                    self.visit_node(item);
+                }
+            }
+            Some("lambda") if is_signature => self.visit_function(items, false),
+            Some("let") if matches!(&*second.value, Expr::List(..)) => self.visit_let(items),
+            Some(MODULE) => match ModuleDefinition::parse(node) {
+                Ok(module) => self.visit_module(&module, items),
+                Err(_) => walk_node(self, node),
+            },
+            _ => walk_node(self, node),
+        }
+    }
+}
+
+/// Whether `text` parses as a single symbol.
+fn is_symbol_text(text: &str) -> bool {
+    let Ok(nodes) = parser::parse(text, SourceContext::from_file("name", text)) else {
+        return false;
+    };
+    matches!(nodes.as_slice(), [node] if matches!(&*node.value, Expr::Symbol(name, _) if name == text))
+}
+
+/// The module and plain name of a `module/name` symbol, or just the name.
+fn split_qualified(name: &str) -> (Option<String>, String) {
+    match name.rsplit_once('/') {
+        Some((module, name)) if !module.is_empty() && !name.is_empty() => {
+            (Some(module.to_string()), name.to_string())
+        }
+        _ => (None, name.to_string()),
+    }
+}
+
+fn shifted(span: Span, offset: usize) -> Span {
+    Span {
+        start: span.start + offset,
+        end: span.end + offset,
+    }
+}
+
+fn symbol_name(node: &AstNode) -> Option<String> {
+    match &*node.value {
+        Expr::Symbol(name, _) => Some(name.clone()),
+        _ => None,
+    }
+}
+
+fn invalid(message: String) -> SutraError {
+    SutraError::without_source(ErrorKind::GeneralValidation { message }, "rename", "rename")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rename(old: &str, new: &str, source: &str) -> Result<String, SutraError> {
+        let context = SourceContext::from_file("test", source);
+        let nodes = parser::parse(source, context.clone()).unwrap();
+        let edits = Rename::new(old, new)?.edits(nodes, &context)?;
+        Ok(apply(source, &edits))
+    }
+
+    #[test]
+    fn renames_uses_but_not_shadowed_names_or_data() {
+        let source = "; heal\n(define (heal  n) n)\n(heal 1)\n(let ((heal 2)) heal)\n(lambda (heal) heal)\n(print \"heal\" 'heal player.heal)";
+        assert_eq!(
+            rename("heal", "mend", source).unwrap(),
+            "; heal\n(define (mend  n) n)\n(mend 1)\n(let ((heal 2)) heal)\n(lambda (heal) heal)\n(print \"heal\" 'heal player.heal)"
+        );
+    }
+
+    #[test]
+    fn renames_uses_inside_interpolations() {
+        let source = "(define (greet n) n)\n(println \"{(greet 2)} and {{greet}} {(let ((greet 1)) greet)}\")";
+        assert_eq!(
+            rename("greet", "welcome", source).unwrap(),
+            "(define (welcome n) n)\n(println \"{(welcome 2)} and {{greet}} {(let ((greet 1)) greet)}\")"
+        );
+        let captured = rename(
+            "greet",
+            "n",
+            "(define (greet x) x)\n(define (f n) \"{(greet n)}\")",
+        )
+        .unwrap_err();
+        assert!(captured
+            .to_string()
+            .contains("would make it mean the local 'n'"));
+    }
+
+    #[test]
+    fn respects_module_qualification() {
+        let source = "(define (buy! x) x)\n(module shop (export buy!)\n  (define (buy! x) (charge x))\n  (buy! 1))\n(module shop)\n(buy! 2)\n(shop/buy! 3)";
+        assert_eq!(
+            rename("shop/buy!", "purchase!", source).unwrap(),
+            "(define (buy! x) x)\n(module shop (export purchase!)\n  (define (purchase! x) (charge x))\n  (purchase! 1))\n(module shop)\n(buy! 2)\n(shop/purchase! 3)"
+        );
+        assert_eq!(
+            rename(
+                "buy!",
+                "get!",
+                "(module shop (define (go) (buy! 1)))\n(module cart (define (buy! x) x) (buy! 1))"
+            )
+            .unwrap(),
+            "(module shop (define (go) (get! 1)))\n(module cart (define (buy! x) x) (buy! 1))"
+        );
+    }
+
+    #[test]
+    fn refuses_renames_that_change_meanings() {
+        let taken = rename("heal", "hurt", "(define (heal n) n)\n(define (hurt n) n)").unwrap_err();
+        assert!(taken.to_string().contains("'hurt' is already defined"));
+        let captured =
+            rename("heal", "n", "(define (heal x) x)\n(define (f n) (heal n))").unwrap_err();
+        assert!(captured
+            .to_string()
+            .contains("would make it mean the local 'n'"));
+        assert!(Rename::new("shop/buy!", "cart/buy!").is_err());
+        let escaped = rename(
+            "greet",
+            "welcome",
+            "(define (greet n) n)\n\"\\t{(greet 2)}\"",
+        )
+        .unwrap_err();
+        assert!(escaped.to_string().contains("cannot rewrite"), "{escaped}");
+        assert!(Rename::new("heal", "(x)").is_err());
+    }
+}
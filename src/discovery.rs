@@ -1,17 +1,19 @@
 use std::{
-    fs,
+    collections::HashMap,
+    fmt, fs,
     path::{Path, PathBuf},
+    time::Duration,
 };
 
 use walkdir::WalkDir;
 
 use crate::{
+    ast_cache::{self, AstCache},
+    atoms::Path as WorldPath,
     errors::{
-        to_source_span, unspanned, ErrorKind, ErrorReporting, SourceContext, SutraError,
-        ValidationContext,
+        to_source_span, ErrorKind, ErrorReporting, SourceContext, SutraError, ValidationContext,
     },
-    parser,
-    syntax::{AstNode, Expr, Span},
+    syntax::{leading_keyword_args, AstNode, Expr, Span},
 };
 
 // =====================
@@ -24,8 +26,20 @@ pub type SourceFile = SourceContext;
 /// Result of test form extraction - either a valid test or None
 pub type TestFormResult = Result<Option<ASTDefinition>, SutraError>;
 
-/// Result of parsing test form structure components
-pub type TestFormComponents = (Option<AstNode>, Vec<AstNode>);
+/// Result of parsing test form structure components: the `(expect ...)` and
+/// `(expect-world ...)` forms, if present, and the body
+pub type TestFormComponents = (Option<AstNode>, Option<AstNode>, Vec<AstNode>);
+
+/// Fixtures declared in a single file, keyed by name
+pub type FixtureTable = HashMap<String, FixtureDefinition>;
+
+/// The `:option value` pairs that may follow a test's name.
+#[derive(Debug, Default)]
+struct TestOptions {
+    fixture: Option<FixtureDefinition>,
+    skip: Option<String>,
+    timeout: Option<Duration>,
+}
 
 /// AST representation of a test definition extracted from a `.sutra` file.
 /// Stores the test in AST form to avoid redundant parsing and preserve original
@@ -34,6 +48,118 @@ pub type TestFormComponents = (Option<AstNode>, Vec<AstNode>);
 pub struct ASTDefinition {
     pub name: String,
     pub expect_form: Option<AstNode>,
+    /// Values the world must hold once the body has run, from `(expect-world ...)`.
+    pub expect_world: Vec<WorldExpectation>,
+    pub body: Vec<AstNode>,
+    pub span: Span,
+    pub source_file: SourceFile,
+    /// Fixture that prepares the world before the body runs, if any.
+    pub fixture: Option<FixtureDefinition>,
+    /// Why the test is skipped, if it was marked `:skip "reason"`.
+    pub skip: Option<String>,
+    /// Time limit set with `:timeout <ms>`, replacing the runner's default.
+    pub timeout: Option<Duration>,
+}
+
+/// One `(path value)` clause of an `(expect-world ...)` form. The value is an
+/// expression, evaluated in the world the test body leaves behind.
+#[derive(Debug, Clone)]
+pub struct WorldExpectation {
+    pub path: WorldPath,
+    pub value: AstNode,
+    pub span: Span,
+}
+
+/// AST representation of a `(fixture "name" body...)` form.
+///
+/// A fixture's body is evaluated against a fresh world before each test that
+/// names it via `:fixture "name"`, so every test starts from the same state.
+#[derive(Debug, Clone)]
+pub struct FixtureDefinition {
+    pub name: String,
+    pub body: Vec<AstNode>,
+    pub span: Span,
+}
+
+/// When a setup or teardown hook runs, relative to the tests of its file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HookKind {
+    /// Once, before the file's first test.
+    BeforeAll,
+    /// Before each test, ahead of its fixture.
+    BeforeEach,
+    /// After each test, whether or not it passed.
+    AfterEach,
+    /// Once, after the file's last test.
+    AfterAll,
+}
+
+impl HookKind {
+    pub const ALL: [Self; 4] = [
+        Self::BeforeAll,
+        Self::BeforeEach,
+        Self::AfterEach,
+        Self::AfterAll,
+    ];
+
+    /// The head of the hook's form, such as `before-each`.
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::BeforeAll => "before-all",
+            Self::BeforeEach => "before-each",
+            Self::AfterEach => "after-each",
+            Self::AfterAll => "after-all",
+        }
+    }
+}
+
+impl fmt::Display for HookKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// AST representation of a `(before-each body...)` form or one of its siblings.
+#[derive(Debug, Clone)]
+pub struct HookDefinition {
+    pub kind: HookKind,
+    pub body: Vec<AstNode>,
+    pub span: Span,
+}
+
+/// The setup and teardown hooks of a test file, each declared at most once.
+///
+/// Like fixtures, hooks are scoped to the file that declares them and may appear
+/// anywhere in it.
+#[derive(Debug, Clone, Default)]
+pub struct TestHooks {
+    hooks: Vec<HookDefinition>,
+}
+
+impl TestHooks {
+    pub fn get(&self, kind: HookKind) -> Option<&HookDefinition> {
+        self.hooks.iter().find(|hook| hook.kind == kind)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.hooks.is_empty()
+    }
+}
+
+/// The tests of one file and the hooks that run around them.
+#[derive(Debug, Clone)]
+pub struct TestSuite {
+    pub tests: Vec<ASTDefinition>,
+    pub hooks: TestHooks,
+    pub source_file: SourceFile,
+}
+
+/// AST representation of a `(bench "name" body...)` form.
+///
+/// Benchmarks are discovered like tests but timed rather than checked.
+#[derive(Debug, Clone)]
+pub struct BenchDefinition {
+    pub name: String,
     pub body: Vec<AstNode>,
     pub span: Span,
     pub source_file: SourceFile,
@@ -61,15 +187,16 @@ impl TestDiscoverer {
         let mut files = Vec::new();
         for entry in WalkDir::new(root) {
             let entry = entry.map_err(|e| {
-                let context = ValidationContext::new(
-                    SourceContext::fallback("TestDiscoverer::discover_test_files"),
-                    "discovery".to_string(),
-                );
-                context.report(
+                let path = e
+                    .path()
+                    .map(|p| p.display().to_string())
+                    .unwrap_or_default();
+                SutraError::without_source(
                     ErrorKind::InvalidPath {
                         path: format!("Failed to walk directory: {}", e),
                     },
-                    unspanned(),
+                    path,
+                    "discovery",
                 )
             })?;
 
@@ -91,36 +218,101 @@ impl TestDiscoverer {
     /// Parses a single `.sutra` file and extracts all `(test ...)` forms as AST nodes.
     ///
     /// This function does not perform macro expansion. It extracts the test definitions
-    /// in AST form and preserves source context for diagnostics.
+    /// in AST form and preserves source context for diagnostics. With a `cache`, a file
+    /// parsed before is read from it.
     pub fn extract_tests_from_file<P: AsRef<Path>>(
         file_path: P,
+        cache: Option<&AstCache>,
     ) -> Result<Vec<ASTDefinition>, SutraError> {
-        let path_str = file_path.as_ref().display().to_string();
-        let source = fs::read_to_string(file_path.as_ref()).map_err(|e| {
-            let context =
-                ValidationContext::new(SourceContext::fallback(&path_str), "discovery".to_string());
-            context.report(
+        Ok(Self::extract_suite_from_file(file_path, cache)?.tests)
+    }
+
+    /// Parses a single `.sutra` file and extracts its tests together with the
+    /// `(before-all ...)`, `(before-each ...)`, `(after-each ...)` and `(after-all ...)`
+    /// hooks that run around them.
+    pub fn extract_suite_from_file<P: AsRef<Path>>(
+        file_path: P,
+        cache: Option<&AstCache>,
+    ) -> Result<TestSuite, SutraError> {
+        let (ast, source_file) = Self::parse_file(file_path.as_ref(), cache)?;
+        let hooks = Self::extract_hooks_from_ast(&ast, &source_file)?;
+        Ok(TestSuite {
+            tests: Self::extract_tests_from_ast(ast, source_file.clone())?,
+            hooks,
+            source_file,
+        })
+    }
+
+    /// Reads and parses a `.sutra` file, keeping its source for diagnostics.
+    fn parse_file(
+        file_path: &Path,
+        cache: Option<&AstCache>,
+    ) -> Result<(Vec<AstNode>, SourceFile), SutraError> {
+        let path_str = file_path.display().to_string();
+        let source = fs::read_to_string(file_path).map_err(|e| {
+            SutraError::without_source(
                 ErrorKind::InvalidPath {
                     path: format!("Failed to read file '{}': {}", path_str, e),
                 },
-                unspanned(),
+                &path_str,
+                "discovery",
             )
         })?;
 
         let source_file = SourceContext::from_file(path_str, &source);
-        let ast = parser::parse(&source, source_file.clone())?;
+        let ast = ast_cache::parse_with(cache, &source, source_file.clone())?;
+        Ok((ast, source_file))
+    }
 
-        Self::extract_tests_from_ast(ast, source_file)
+    /// Parses a single `.sutra` file and extracts all `(bench ...)` forms as AST nodes.
+    pub fn extract_benches_from_file<P: AsRef<Path>>(
+        file_path: P,
+    ) -> Result<Vec<BenchDefinition>, SutraError> {
+        let (ast, source_file) = Self::parse_file(file_path.as_ref(), None)?;
+        Self::extract_benches_from_ast(&ast, &source_file)
+    }
+
+    /// Directly extracts benchmarks from a pre-parsed AST
+    pub fn extract_benches_from_ast(
+        ast: &[AstNode],
+        source_file: &SourceFile,
+    ) -> Result<Vec<BenchDefinition>, SutraError> {
+        let mut benches = Vec::new();
+        for node in ast {
+            let Some((items, span)) = Self::form_with_head(node, "bench") else {
+                continue;
+            };
+            let Some(name_node) = items.get(1) else {
+                let context = ValidationContext::new(source_file.clone(), "discovery");
+                return Err(context.report(
+                    ErrorKind::MalformedConstruct {
+                        construct: "bench form".to_string(),
+                    },
+                    to_source_span(span),
+                ));
+            };
+            benches.push(BenchDefinition {
+                name: Self::extract_and_validate_name(name_node, "bench name", source_file)?,
+                body: items[2..].to_vec(),
+                span,
+                source_file: source_file.clone(),
+            });
+        }
+        Ok(benches)
     }
 
     /// Directly extracts tests from a pre-parsed AST
+    ///
+    /// Fixtures are scoped to the file that declares them and may appear anywhere
+    /// in the file, before or after the tests that use them.
     pub fn extract_tests_from_ast(
         ast: Vec<AstNode>,
         source_file: SourceFile,
     ) -> Result<Vec<ASTDefinition>, SutraError> {
+        let fixtures = Self::extract_fixtures_from_ast(&ast, &source_file)?;
         let mut tests = Vec::new();
         for node in ast {
-            let test_form = Self::validate_and_extract_test_form(node, &source_file)?;
+            let test_form = Self::validate_and_extract_test_form(node, &source_file, &fixtures)?;
             if let Some(test_form) = test_form {
                 tests.push(test_form);
             }
@@ -128,6 +320,63 @@ impl TestDiscoverer {
         Ok(tests)
     }
 
+    /// Collects all `(fixture "name" body...)` forms from a pre-parsed AST.
+    pub fn extract_fixtures_from_ast(
+        ast: &[AstNode],
+        source_file: &SourceFile,
+    ) -> Result<FixtureTable, SutraError> {
+        let mut fixtures = FixtureTable::new();
+        for node in ast {
+            let Some(fixture) = Self::validate_and_extract_fixture_form(node, source_file)? else {
+                continue;
+            };
+            if let Some(original) = fixtures.get(&fixture.name) {
+                let context = ValidationContext::new(source_file.clone(), "discovery");
+                return Err(context.report(
+                    ErrorKind::DuplicateDefinition {
+                        symbol: fixture.name.clone(),
+                        original_location: to_source_span(original.span),
+                    },
+                    to_source_span(fixture.span),
+                ));
+            }
+            fixtures.insert(fixture.name.clone(), fixture);
+        }
+        Ok(fixtures)
+    }
+
+    /// Collects the setup and teardown hook forms from a pre-parsed AST. A file may
+    /// declare each kind of hook once.
+    pub fn extract_hooks_from_ast(
+        ast: &[AstNode],
+        source_file: &SourceFile,
+    ) -> Result<TestHooks, SutraError> {
+        let mut hooks = TestHooks::default();
+        for node in ast {
+            let Some((kind, items, span)) = HookKind::ALL.into_iter().find_map(|kind| {
+                Self::form_with_head(node, kind.name()).map(|(items, span)| (kind, items, span))
+            }) else {
+                continue;
+            };
+            if let Some(original) = hooks.get(kind) {
+                let context = ValidationContext::new(source_file.clone(), "discovery");
+                return Err(context.report(
+                    ErrorKind::DuplicateDefinition {
+                        symbol: kind.name().to_string(),
+                        original_location: to_source_span(original.span),
+                    },
+                    to_source_span(span),
+                ));
+            }
+            hooks.hooks.push(HookDefinition {
+                kind,
+                body: items[1..].to_vec(),
+                span,
+            });
+        }
+        Ok(hooks)
+    }
+
     // =====================
     // Internal - Test Form Validation
     // =====================
@@ -138,39 +387,75 @@ impl TestDiscoverer {
     /// 1. Be a list expression
     /// 2. Have a symbol "test" as the first element
     /// 3. Have at least a name as the second element
-    fn validate_and_extract_test_form(node: AstNode, source_file: &SourceFile) -> TestFormResult {
-        let Expr::List(items, span) = &*node.value else {
+    fn validate_and_extract_test_form(
+        node: AstNode,
+        source_file: &SourceFile,
+        fixtures: &FixtureTable,
+    ) -> TestFormResult {
+        let Some((items, span)) = Self::form_with_head(&node, "test") else {
             return Ok(None);
         };
 
-        let Some(head) = items.first() else {
+        let test_form =
+            Self::parse_test_form_structure(items, span, source_file.clone(), fixtures)?;
+        Ok(Some(test_form))
+    }
+
+    /// Validates that a node represents a fixture form and extracts it if valid.
+    ///
+    /// A valid fixture form is `(fixture "name" body...)` with a string literal name.
+    fn validate_and_extract_fixture_form(
+        node: &AstNode,
+        source_file: &SourceFile,
+    ) -> Result<Option<FixtureDefinition>, SutraError> {
+        let Some((items, span)) = Self::form_with_head(node, "fixture") else {
             return Ok(None);
         };
 
-        let Expr::Symbol(s, _) = &*head.value else {
-            return Ok(None);
+        let Some(name_node) = items.get(1) else {
+            let context = ValidationContext::new(source_file.clone(), "discovery");
+            return Err(context.report(
+                ErrorKind::MalformedConstruct {
+                    construct: "fixture form".to_string(),
+                },
+                to_source_span(span),
+            ));
         };
 
-        if s != "test" {
-            return Ok(None);
-        }
+        let name = Self::extract_and_validate_name(name_node, "fixture name", source_file)?;
+        Ok(Some(FixtureDefinition {
+            name,
+            body: items[2..].to_vec(),
+            span,
+        }))
+    }
 
-        let test_form = Self::parse_test_form_structure(items, *span, source_file.clone())?;
-        Ok(Some(test_form))
+    /// Returns the items of a list form whose head is the given symbol.
+    fn form_with_head<'a>(node: &'a AstNode, head_name: &str) -> Option<(&'a [AstNode], Span)> {
+        let Expr::List(items, span) = &*node.value else {
+            return None;
+        };
+        let Expr::Symbol(s, _) = &*items.first()?.value else {
+            return None;
+        };
+        (s == head_name).then_some((items.as_slice(), *span))
     }
 
     // =====================
     // Internal - Test Form Parsing
     // =====================
 
-    /// Parses the structure of a test form: `(test "name" (expect ...) body...)`
+    /// Parses the structure of a test form:
+    /// `(test "name" [:fixture "f"] [:skip "reason"] [:timeout ms] [(expect ...)]
+    /// [(expect-world (path value)...)] body...)`
     fn parse_test_form_structure(
         items: &[AstNode],
         span: Span,
         source_file: SourceFile,
+        fixtures: &FixtureTable,
     ) -> Result<ASTDefinition, SutraError> {
         if items.len() < 2 {
-            let context = ValidationContext::new(source_file.clone(), "discovery".to_string());
+            let context = ValidationContext::new(source_file.clone(), "discovery");
             return Err(context.report(
                 ErrorKind::MalformedConstruct {
                     construct: "test form".to_string(),
@@ -179,30 +464,41 @@ impl TestDiscoverer {
             ));
         }
 
-        let name = Self::extract_and_validate_test_name(&items[1], &source_file)?;
-        let (expect_form, body) = Self::extract_expect_form_and_body(items)?;
+        let name = Self::extract_and_validate_name(&items[1], "test name", &source_file)?;
+        let (options, rest) = Self::extract_test_options(&items[2..], &source_file, fixtures)?;
+        let (expect_form, expect_world, body) =
+            Self::extract_expect_form_and_body(rest, &source_file)?;
+        let expect_world = match expect_world {
+            Some(form) => Self::extract_world_expectations(&form, &source_file)?,
+            None => Vec::new(),
+        };
 
         Ok(ASTDefinition {
             name,
             expect_form,
+            expect_world,
             body,
             span,
             source_file,
+            fixture: options.fixture,
+            skip: options.skip,
+            timeout: options.timeout,
         })
     }
 
-    /// Extracts and validates the test name from the second element.
+    /// Extracts and validates a test or fixture name.
     ///
     /// The name must be a string literal.
-    fn extract_and_validate_test_name(
+    fn extract_and_validate_name(
         name_node: &AstNode,
+        literal_type: &str,
         source_file: &SourceFile,
     ) -> Result<String, SutraError> {
-        let Expr::String(s, _) = &*name_node.value else {
-            let context = ValidationContext::new(source_file.clone(), "discovery".to_string());
+        let Expr::String(s, ..) = &*name_node.value else {
+            let context = ValidationContext::new(source_file.clone(), "discovery");
             return Err(context.report(
                 ErrorKind::InvalidLiteral {
-                    literal_type: "test name".to_string(),
+                    literal_type: literal_type.to_string(),
                     value: name_node.value.to_string(),
                 },
                 to_source_span(name_node.span),
@@ -211,48 +507,161 @@ impl TestDiscoverer {
         Ok(s.clone())
     }
 
-    /// Extracts the optional expect form and remaining body elements.
+    /// Consumes leading `:option value` pairs that follow the test name.
     ///
-    /// Test forms can have two structures:
-    /// - `(test "name" body...)` - no expect form
-    /// - `(test "name" (expect ...) body...)`
-    fn extract_expect_form_and_body(items: &[AstNode]) -> Result<TestFormComponents, SutraError> {
-        let Some(second_item) = items.get(2) else {
-            let body = items.get(2..).unwrap_or_default().to_vec();
-            return Ok((None, body));
-        };
+    /// Returns the resolved options and the remaining items.
+    fn extract_test_options<'a>(
+        items: &'a [AstNode],
+        source_file: &SourceFile,
+        fixtures: &FixtureTable,
+    ) -> Result<(TestOptions, &'a [AstNode]), SutraError> {
+        let context = ValidationContext::new(source_file.clone(), "discovery");
+        let (options, rest) = leading_keyword_args(items, &context)?;
+        let mut resolved = TestOptions::default();
+
+        for option in options {
+            match option.name {
+                "fixture" => {
+                    resolved.fixture =
+                        Some(Self::resolve_fixture(option.value, source_file, fixtures)?);
+                }
+                "skip" => {
+                    resolved.skip = Some(Self::extract_and_validate_name(
+                        option.value,
+                        "skip reason",
+                        source_file,
+                    )?);
+                }
+                "timeout" => {
+                    resolved.timeout = Some(Self::extract_timeout(option.value, source_file)?);
+                }
+                _ => {
+                    return Err(context.report(
+                        ErrorKind::MalformedConstruct {
+                            construct: format!("unknown test option ':{}'", option.name),
+                        },
+                        to_source_span(option.keyword.span),
+                    ));
+                }
+            }
+        }
+
+        Ok((resolved, rest))
+    }
 
-        let expect_form = Self::try_extract_expect_form(second_item)?;
-        if let Some(expect_form) = expect_form {
-            let body = items.get(3..).unwrap_or_default().to_vec();
-            return Ok((Some(expect_form), body));
+    /// Reads a `:timeout` value, a positive number of milliseconds.
+    fn extract_timeout(
+        value_node: &AstNode,
+        source_file: &SourceFile,
+    ) -> Result<Duration, SutraError> {
+        match &*value_node.value {
+            Expr::Number(ms, _) if *ms > 0.0 && ms.is_finite() => {
+                Ok(Duration::from_millis(*ms as u64))
+            }
+            other => {
+                let context = ValidationContext::new(source_file.clone(), "discovery");
+                Err(context.report(
+                    ErrorKind::InvalidLiteral {
+                        literal_type: "timeout in milliseconds".to_string(),
+                        value: other.to_string(),
+                    },
+                    to_source_span(value_node.span),
+                ))
+            }
         }
+    }
 
-        let body = items.get(2..).unwrap_or_default().to_vec();
-        Ok((None, body))
+    /// Looks up the fixture named by a `:fixture` option value.
+    fn resolve_fixture(
+        value_node: &AstNode,
+        source_file: &SourceFile,
+        fixtures: &FixtureTable,
+    ) -> Result<FixtureDefinition, SutraError> {
+        let fixture_name =
+            Self::extract_and_validate_name(value_node, "fixture name", source_file)?;
+        fixtures.get(&fixture_name).cloned().ok_or_else(|| {
+            let context = ValidationContext::new(source_file.clone(), "discovery");
+            context.undefined_symbol(&fixture_name, to_source_span(value_node.span))
+        })
     }
 
-    /// Attempts to extract an expect form from a node.
+    /// Extracts the optional expect forms and remaining body elements.
     ///
-    /// An expect form must be a list with "expect" as the first symbol.
-    fn try_extract_expect_form(node: &AstNode) -> Result<Option<AstNode>, SutraError> {
-        let Expr::List(expect_items, _) = &*node.value else {
-            return Ok(None);
-        };
+    /// `items` starts after the test name and any options. The body may be preceded by
+    /// an `(expect ...)` form, an `(expect-world ...)` form, or both, in either order:
+    /// - `(test "name" body...)` - no expect form
+    /// - `(test "name" (expect ...) body...)`
+    /// - `(test "name" (expect ...) (expect-world ...) body...)`
+    fn extract_expect_form_and_body(
+        items: &[AstNode],
+        source_file: &SourceFile,
+    ) -> Result<TestFormComponents, SutraError> {
+        let mut expect_form = None;
+        let mut expect_world = None;
+        let mut rest = items;
+        while let Some((first, tail)) = rest.split_first() {
+            let (slot, head) = if Self::form_with_head(first, "expect").is_some() {
+                (&mut expect_form, "expect")
+            } else if Self::form_with_head(first, "expect-world").is_some() {
+                (&mut expect_world, "expect-world")
+            } else {
+                break;
+            };
+            if slot.is_some() {
+                let context = ValidationContext::new(source_file.clone(), "discovery");
+                return Err(context.report(
+                    ErrorKind::MalformedConstruct {
+                        construct: format!("test form with more than one ({head} ...)"),
+                    },
+                    to_source_span(first.span),
+                ));
+            }
+            *slot = Some(first.clone());
+            rest = tail;
+        }
 
-        let Some(head) = expect_items.first() else {
-            return Ok(None);
-        };
+        Ok((expect_form, expect_world, rest.to_vec()))
+    }
 
-        let Expr::Symbol(s, _) = &*head.value else {
-            return Ok(None);
+    /// Reads the `(path value)` clauses of an `(expect-world ...)` form. A path is
+    /// written as in `get`: `player.gold`, or a bare name for a top-level key.
+    fn extract_world_expectations(
+        form: &AstNode,
+        source_file: &SourceFile,
+    ) -> Result<Vec<WorldExpectation>, SutraError> {
+        let Expr::List(items, _) = &*form.value else {
+            return Ok(Vec::new());
         };
-
-        if s != "expect" {
-            return Ok(None);
-        }
-
-        Ok(Some(node.clone()))
+        let malformed = |clause: &AstNode| {
+            let context = ValidationContext::new(source_file.clone(), "discovery");
+            context.report(
+                ErrorKind::MalformedConstruct {
+                    construct: "expect-world clause, expected (path value)".to_string(),
+                },
+                to_source_span(clause.span),
+            )
+        };
+        items[1..]
+            .iter()
+            .map(|clause| {
+                let Expr::List(parts, _) = &*clause.value else {
+                    return Err(malformed(clause));
+                };
+                let [path, value] = parts.as_slice() else {
+                    return Err(malformed(clause));
+                };
+                let path = match &*path.value {
+                    Expr::Path(path, _) => path.clone(),
+                    Expr::Symbol(name, _) => WorldPath(name.split('.').map(String::from).collect()),
+                    _ => return Err(malformed(clause)),
+                };
+                Ok(WorldExpectation {
+                    path,
+                    value: value.clone(),
+                    span: clause.span,
+                })
+            })
+            .collect()
     }
 
     // =====================
@@ -0,0 +1,677 @@
+//! Editor Grammars
+//!
+//! `sutra grammar --emit` generates the files editors highlight Sutra with from the
+//! pest grammar (see [`crate::grammar_validation`]) and the names the engine has
+//! registered, so they follow changes to either without being edited by hand: a
+//! TextMate grammar, which VS Code, Sublime Text and most other editors read, and a
+//! Tree-sitter grammar with a highlight query.
+//!
+//! Neither says everything pest can. The TextMate grammar matches tokens one at a
+//! time and approximates the whitespace between the items of a non-atomic rule with
+//! `\s*`. The Tree-sitter grammar is a skeleton: Tree-sitter has no lookaheads, so
+//! each one pest uses is left as a comment, and the conflicts pest settles by trying
+//! alternatives in order must still be declared before `tree-sitter generate` accepts
+//! it.
+
+use std::collections::{HashMap, HashSet};
+
+use crate::grammar_validation::{Expression, Rule};
+
+/// Names the engine registers, which editors highlight apart from other symbols.
+#[derive(Debug, Clone, Default)]
+pub struct Keywords {
+    pub atoms: Vec<String>,
+    pub special_forms: Vec<String>,
+    pub macros: Vec<String>,
+}
+
+/// The TextMate scope of each rule the TextMate grammar highlights, in the order its
+/// patterns are tried. The registered names are tried just before `symbol`.
+const TEXTMATE_SCOPES: &[(&str, &str)] = &[
+    ("line_comment", "comment.line.semicolon.sutra"),
+    ("block_comment", "comment.block.sutra"),
+    ("raw_string", "string.quoted.other.raw.sutra"),
+    ("triple_string", "string.quoted.triple.sutra"),
+    ("quoted_string", "string.quoted.double.sutra"),
+    ("escape_sequence", "constant.character.escape.sutra"),
+    ("number", "constant.numeric.sutra"),
+    ("boolean", "constant.language.boolean.sutra"),
+    ("keyword", "constant.other.keyword.sutra"),
+    ("spread_arg", "variable.parameter.rest.sutra"),
+    ("symbol", "meta.symbol.sutra"),
+];
+
+/// The Tree-sitter capture of each rule the highlight query names.
+const TREE_SITTER_CAPTURES: &[(&str, &str)] = &[
+    ("line_comment", "comment"),
+    ("block_comment", "comment"),
+    ("datum_comment", "comment"),
+    ("string", "string"),
+    ("number", "number"),
+    ("boolean", "boolean"),
+    ("keyword", "string.special.symbol"),
+    ("spread_arg", "variable.parameter"),
+];
+
+/// A regular expression that never matches, standing in for a recursive rule.
+const NEVER: &str = "(?!)";
+
+// =====================
+// TextMate
+// =====================
+
+/// The TextMate grammar for `rules`, as the contents of a `.tmLanguage.json` file.
+///
+/// A rule that begins and ends with a literal, such as a string or block comment,
+/// becomes a `begin`/`end` pair holding the highlighted rules it refers to, so block
+/// comments nest. Any other rule becomes one regular expression, with the rules it
+/// refers to written out in place; a rule that refers back to itself matches nothing
+/// at the point of recursion.
+pub fn textmate(rules: &[Rule], keywords: &Keywords) -> serde_json::Value {
+    let grammar = Grammar::new(rules);
+    let scoped: Vec<(&Rule, &str)> = TEXTMATE_SCOPES
+        .iter()
+        .filter_map(|(name, scope)| Some((*grammar.rules.get(name)?, *scope)))
+        .collect();
+    let scoped_names: HashSet<&str> = scoped.iter().map(|(rule, _)| rule.name.as_str()).collect();
+
+    let mut repository = serde_json::Map::new();
+    let mut nested = HashSet::new();
+    for (rule, scope) in &scoped {
+        let entry = match delimiters(&rule.expression) {
+            Some((open, body, close)) => {
+                let mut inside = Vec::new();
+                grammar.scoped_references(body, &scoped_names, &mut inside);
+                nested.extend(inside.iter().filter(|name| **name != rule.name).cloned());
+                serde_json::json!({
+                    "name": scope,
+                    "begin": regex_literal(open),
+                    "end": regex_literal(close),
+                    "patterns": includes(&inside),
+                })
+            }
+            None => serde_json::json!({
+                "name": scope,
+                "match": grammar.regex_rule(rule),
+            }),
+        };
+        repository.insert(rule.name.clone(), entry);
+    }
+
+    let boundary = grammar
+        .rules
+        .get("symbol_inner")
+        .map(|rule| grammar.regex_rule(rule));
+    let names = [
+        (
+            "special_forms",
+            "keyword.control.sutra",
+            &keywords.special_forms,
+        ),
+        ("macros", "keyword.other.macro.sutra", &keywords.macros),
+        ("atoms", "support.function.sutra", &keywords.atoms),
+    ];
+    let mut top_level = Vec::new();
+    for (rule, _) in &scoped {
+        if rule.name == "symbol" {
+            for (key, scope, list) in names.iter().filter(|(_, _, list)| !list.is_empty()) {
+                let entry = serde_json::json!({
+                    "name": scope,
+                    "match": names_regex(list, boundary.as_deref()),
+                });
+                repository.insert(key.to_string(), entry);
+                top_level.push(key.to_string());
+            }
+        }
+        if !nested.contains(&rule.name) {
+            top_level.push(rule.name.clone());
+        }
+    }
+
+    serde_json::json!({
+        "name": "Sutra",
+        "scopeName": "source.sutra",
+        "fileTypes": ["sutra"],
+        "patterns": includes(&top_level),
+        "repository": repository,
+    })
+}
+
+/// The opening literal, the body and the closing literal of a rule such as
+/// `"#|" ~ body ~ "|#"`.
+fn delimiters(expression: &Expression) -> Option<(&str, &[Expression], &str)> {
+    let Expression::Sequence(items) = expression else {
+        return None;
+    };
+    match items.as_slice() {
+        [Expression::Literal(open), body @ .., Expression::Literal(close)] => {
+            Some((open, body, close))
+        }
+        _ => None,
+    }
+}
+
+fn includes(names: &[String]) -> serde_json::Value {
+    names
+        .iter()
+        .map(|name| serde_json::json!({ "include": format!("#{name}") }))
+        .collect()
+}
+
+/// A pattern matching any of `names` as a whole symbol.
+fn names_regex(names: &[String], boundary: Option<&str>) -> String {
+    let mut names: Vec<&String> = names.iter().collect();
+    // Longer names first, so that `set!` is not matched as `set`
+    names.sort_by_key(|name| std::cmp::Reverse(name.len()));
+    let names: Vec<String> = names.iter().map(|name| regex_literal(name)).collect();
+    let names = names.join("|");
+    match boundary {
+        Some(boundary) => format!("(?<!{boundary})(?:{names})(?!{boundary})"),
+        None => format!("(?:{names})"),
+    }
+}
+
+/// `text` as a regular expression matching it literally.
+fn regex_literal(text: &str) -> String {
+    let mut regex = String::new();
+    for c in text.chars() {
+        match c {
+            '\n' => regex.push_str("\\n"),
+            '\r' => regex.push_str("\\r"),
+            '\t' => regex.push_str("\\t"),
+            c if c.is_control() => regex.push_str(&format!("\\x{{{:X}}}", u32::from(c))),
+            c if "\\.+*?()|[]{}^$".contains(c) => {
+                regex.push('\\');
+                regex.push(c);
+            }
+            c => regex.push(c),
+        }
+    }
+    regex
+}
+
+/// The rules by name, for following references.
+struct Grammar<'a> {
+    rules: HashMap<&'a str, &'a Rule>,
+}
+
+impl<'a> Grammar<'a> {
+    fn new(rules: &'a [Rule]) -> Self {
+        Self {
+            rules: rules
+                .iter()
+                .map(|rule| (rule.name.as_str(), rule))
+                .collect(),
+        }
+    }
+
+    /// Adds to `found` the highlighted rules `items` refer to, looking through the
+    /// rules that are not highlighted.
+    fn scoped_references(
+        &self,
+        items: &[Expression],
+        scoped: &HashSet<&str>,
+        found: &mut Vec<String>,
+    ) {
+        let mut pending = Vec::new();
+        for item in items {
+            item.references(&mut pending);
+        }
+        let mut seen = HashSet::new();
+        while let Some(name) = pending.pop() {
+            if !seen.insert(name.clone()) {
+                continue;
+            }
+            if scoped.contains(name.as_str()) {
+                found.push(name);
+            } else if let Some(rule) = self.rules.get(name.as_str()) {
+                rule.expression.references(&mut pending);
+            }
+        }
+        found.sort();
+    }
+
+    /// The regular expression matching `rule`.
+    fn regex_rule(&self, rule: &Rule) -> String {
+        let mut visiting = vec![rule.name.clone()];
+        self.regex(&rule.expression, rule.is_atomic(), &mut visiting)
+            .0
+    }
+
+    /// The regular expression matching `expression`, and whether it can take a
+    /// quantifier as it is. Whitespace may appear between the items of a sequence
+    /// unless `atomic`.
+    fn regex(
+        &self,
+        expression: &Expression,
+        atomic: bool,
+        visiting: &mut Vec<String>,
+    ) -> (String, bool) {
+        match expression {
+            Expression::Literal(text) => (regex_literal(text), text.chars().count() == 1),
+            Expression::Rule(name) => self.regex_reference(name, atomic, visiting),
+            Expression::Sequence(items) => {
+                let items: Vec<(String, bool)> = items
+                    .iter()
+                    .map(|item| self.regex(item, atomic, visiting))
+                    .filter(|(regex, _)| !regex.is_empty())
+                    .collect();
+                if items.iter().any(|(regex, _)| regex == NEVER) {
+                    return (NEVER.to_string(), true);
+                }
+                let single = items.len() == 1 && items[0].1;
+                let separator = if atomic { "" } else { "\\s*" };
+                let items: Vec<String> = items.into_iter().map(|(regex, _)| regex).collect();
+                (items.join(separator), single)
+            }
+            Expression::Choice(items) => {
+                let items: Vec<String> = items
+                    .iter()
+                    .map(|item| self.regex(item, atomic, visiting).0)
+                    .filter(|regex| regex != NEVER)
+                    .collect();
+                match items.as_slice() {
+                    [] => (NEVER.to_string(), true),
+                    [only] => (only.clone(), false),
+                    _ => (format!("(?:{})", items.join("|")), true),
+                }
+            }
+            Expression::Optional(inner) => self.quantified(inner, "?", atomic, visiting),
+            Expression::Repeat(inner) => self.quantified(inner, "*", atomic, visiting),
+            Expression::RepeatOnce(inner) => self.quantified(inner, "+", atomic, visiting),
+            Expression::RepeatRange(inner, min, Some(max)) => {
+                self.quantified(inner, &format!("{{{min},{max}}}"), atomic, visiting)
+            }
+            Expression::RepeatRange(inner, min, None) => {
+                self.quantified(inner, &format!("{{{min},}}"), atomic, visiting)
+            }
+            Expression::FollowedBy(inner) => {
+                let (regex, _) = self.regex(inner, atomic, visiting);
+                (format!("(?={regex})"), true)
+            }
+            Expression::NotFollowedBy(inner) => match self.regex(inner, atomic, visiting).0 {
+                regex if regex == NEVER => (String::new(), true),
+                regex => (format!("(?!{regex})"), true),
+            },
+        }
+    }
+
+    fn quantified(
+        &self,
+        inner: &Expression,
+        quantifier: &str,
+        atomic: bool,
+        visiting: &mut Vec<String>,
+    ) -> (String, bool) {
+        match self.regex(inner, atomic, visiting) {
+            (regex, _) if regex == NEVER && quantifier != "+" => (String::new(), true),
+            (regex, true) => (format!("{regex}{quantifier}"), false),
+            (regex, false) => (format!("(?:{regex}){quantifier}"), false),
+        }
+    }
+
+    fn regex_reference(
+        &self,
+        name: &str,
+        atomic: bool,
+        visiting: &mut Vec<String>,
+    ) -> (String, bool) {
+        let builtin = match name {
+            "ANY" => "[\\s\\S]",
+            "ASCII_DIGIT" => "[0-9]",
+            "ASCII_HEX_DIGIT" => "[0-9a-fA-F]",
+            "ASCII_ALPHA" => "[a-zA-Z]",
+            "ASCII_ALPHANUMERIC" => "[a-zA-Z0-9]",
+            "SOI" => "\\A",
+            "EOI" => "\\z",
+            _ => "",
+        };
+        if !builtin.is_empty() {
+            return (builtin.to_string(), true);
+        }
+        let Some(rule) = self.rules.get(name) else {
+            return (NEVER.to_string(), true);
+        };
+        if visiting.iter().any(|visited| visited == name) {
+            return (NEVER.to_string(), true);
+        }
+        visiting.push(name.to_string());
+        // Rules called from an atomic rule are atomic too
+        let (regex, single) = self.regex(&rule.expression, atomic || rule.is_atomic(), visiting);
+        visiting.pop();
+        match single {
+            true => (regex, true),
+            false => (format!("(?:{regex})"), true),
+        }
+    }
+}
+
+// =====================
+// Tree-sitter
+// =====================
+
+/// A Tree-sitter `grammar.js` for `rules`.
+///
+/// `program` is the root. The alternatives of `WHITESPACE` become `extras`. Atomic
+/// rules become `token(...)`s with the rules they refer to written out in place, and
+/// silent rules are hidden, their names starting with `_`. Lookaheads are kept as
+/// comments, except that `!x ~ ANY` becomes a negated character class when `x` is a
+/// choice of single characters.
+pub fn tree_sitter(rules: &[Rule]) -> String {
+    let grammar = Grammar::new(rules);
+    let mut ordered: Vec<&Rule> = rules
+        .iter()
+        .filter(|rule| !matches!(rule.name.as_str(), "WHITESPACE" | "COMMENT"))
+        .collect();
+    if let Some(root) = ordered.iter().position(|rule| rule.name == "program") {
+        let root = ordered.remove(root);
+        ordered.insert(0, root);
+    }
+
+    let extras = grammar
+        .rules
+        .get("WHITESPACE")
+        .map(|rule| grammar.extras(&rule.expression))
+        .unwrap_or_default();
+    let mut js = String::from(
+        "// Tree-sitter grammar for Sutra, generated by `sutra grammar --emit treesitter`\n\
+         // from its pest grammar. Tree-sitter has no lookaheads, so each one is left as a\n\
+         // comment, and conflicts pest settles by order must be declared by hand.\n\n",
+    );
+    js.push_str("module.exports = grammar({\n  name: 'sutra',\n\n");
+    js.push_str(&format!(
+        "  extras: $ => [{}],\n\n  rules: {{\n",
+        extras.join(", ")
+    ));
+    for rule in ordered {
+        let body = if rule.is_atomic() {
+            let mut visiting = vec![rule.name.clone()];
+            format!(
+                "token({})",
+                grammar.js(&rule.expression, true, &mut visiting)
+            )
+        } else {
+            grammar.js(&rule.expression, false, &mut Vec::new())
+        };
+        if let Some(doc) = &rule.doc {
+            for line in doc.lines() {
+                js.push_str(&format!("    // {line}\n"));
+            }
+        }
+        js.push_str(&format!("    {}: $ => {body},\n", node_name(rule)));
+    }
+    js.push_str("  },\n});\n");
+    js
+}
+
+/// A Tree-sitter highlight query for the grammar [`tree_sitter`] generates, which
+/// colours the registered names among the symbols.
+pub fn highlights(rules: &[Rule], keywords: &Keywords) -> String {
+    let nodes = visible_nodes(rules);
+    let mut query = String::from(
+        "; Highlight query for Sutra, generated by `sutra grammar --emit treesitter`.\n\n",
+    );
+    for (name, capture) in TREE_SITTER_CAPTURES {
+        if nodes.contains(name) {
+            query.push_str(&format!("({name}) @{capture}\n"));
+        }
+    }
+    let names = [
+        ("keyword", &keywords.special_forms),
+        ("function.macro", &keywords.macros),
+        ("function.builtin", &keywords.atoms),
+    ];
+    for (capture, list) in names.iter().filter(|(_, list)| !list.is_empty()) {
+        let quoted: Vec<String> = list
+            .iter()
+            .map(|name| serde_json::Value::from(name.as_str()).to_string())
+            .collect();
+        query.push_str(&format!(
+            "\n((symbol) @{capture}\n  (#any-of? @{capture} {}))\n",
+            quoted.join(" ")
+        ));
+    }
+    query
+}
+
+/// The rules that are nodes of the Tree-sitter tree: those referred to from a rule
+/// that is not atomic, as atomic rules become tokens with nothing inside.
+fn visible_nodes(rules: &[Rule]) -> HashSet<&str> {
+    rules
+        .iter()
+        .filter(|rule| !rule.is_atomic())
+        .flat_map(|rule| rule.references.iter())
+        .filter_map(|name| rules.iter().find(|rule| &rule.name == name))
+        .filter(|rule| rule.modifier != Some('_'))
+        .map(|rule| rule.name.as_str())
+        .collect()
+}
+
+fn node_name(rule: &Rule) -> String {
+    match rule.modifier {
+        Some('_') => format!("_{}", rule.name),
+        _ => rule.name.clone(),
+    }
+}
+
+impl Grammar<'_> {
+    /// The alternatives of `WHITESPACE`, with silent rules such as `COMMENT` opened.
+    fn extras(&self, expression: &Expression) -> Vec<String> {
+        let alternatives = match expression {
+            Expression::Choice(items) => items.iter().collect(),
+            other => vec![other],
+        };
+        alternatives
+            .into_iter()
+            .flat_map(|alternative| match alternative {
+                Expression::Rule(name) => match self.rules.get(name.as_str()) {
+                    Some(rule) if rule.modifier == Some('_') => self.extras(&rule.expression),
+                    _ => vec![self.js(alternative, false, &mut Vec::new())],
+                },
+                other => vec![self.js(other, false, &mut Vec::new())],
+            })
+            .collect()
+    }
+
+    /// `expression` as a Tree-sitter rule. Inside a token, the rules it refers to
+    /// are written out in place.
+    fn js(&self, expression: &Expression, token: bool, visiting: &mut Vec<String>) -> String {
+        match expression {
+            Expression::Literal(text) => serde_json::Value::from(text.as_str()).to_string(),
+            Expression::Rule(name) => self.js_reference(name, token, visiting),
+            Expression::Sequence(items) => {
+                let items = self.js_sequence(items, token, visiting);
+                js_call("seq", items)
+            }
+            Expression::Choice(items) => {
+                let items = items
+                    .iter()
+                    .map(|item| self.js(item, token, visiting))
+                    .collect();
+                js_call("choice", items)
+            }
+            Expression::Optional(inner) => {
+                format!("optional({})", self.js(inner, token, visiting))
+            }
+            Expression::Repeat(inner) => format!("repeat({})", self.js(inner, token, visiting)),
+            Expression::RepeatOnce(inner) => {
+                format!("repeat1({})", self.js(inner, token, visiting))
+            }
+            Expression::RepeatRange(..) => {
+                let items = self.js_sequence(std::slice::from_ref(expression), token, visiting);
+                js_call("seq", items)
+            }
+            Expression::FollowedBy(_) | Expression::NotFollowedBy(_) => {
+                format!("/* {expression} */")
+            }
+        }
+    }
+
+    fn js_sequence(
+        &self,
+        items: &[Expression],
+        token: bool,
+        visiting: &mut Vec<String>,
+    ) -> Vec<String> {
+        let items: Vec<Expression> = items
+            .iter()
+            .flat_map(|item| Expression::Sequence(vec![item.clone()]).sequence_items())
+            .collect();
+        let mut rendered = Vec::new();
+        let mut i = 0;
+        while i < items.len() {
+            let class = match (&items[i], items.get(i + 1)) {
+                (Expression::NotFollowedBy(excluded), Some(Expression::Rule(any)))
+                    if any == "ANY" =>
+                {
+                    excluded_class(excluded)
+                }
+                _ => None,
+            };
+            match class {
+                Some(class) => {
+                    rendered.push(class);
+                    i += 2;
+                }
+                None => {
+                    rendered.push(self.js(&items[i], token, visiting));
+                    i += 1;
+                }
+            }
+        }
+        rendered
+    }
+
+    fn js_reference(&self, name: &str, token: bool, visiting: &mut Vec<String>) -> String {
+        let builtin = match name {
+            "ANY" => "/[\\s\\S]/",
+            "ASCII_DIGIT" => "/[0-9]/",
+            "ASCII_HEX_DIGIT" => "/[0-9a-fA-F]/",
+            "ASCII_ALPHA" => "/[a-zA-Z]/",
+            "ASCII_ALPHANUMERIC" => "/[a-zA-Z0-9]/",
+            "SOI" | "EOI" => return String::new(),
+            _ => "",
+        };
+        if !builtin.is_empty() {
+            return builtin.to_string();
+        }
+        let Some(rule) = self.rules.get(name) else {
+            return format!("$.{name}");
+        };
+        if !token {
+            return format!("$.{}", node_name(rule));
+        }
+        if visiting.iter().any(|visited| visited == name) {
+            return format!("/* {name} */");
+        }
+        visiting.push(name.to_string());
+        let js = self.js(&rule.expression, true, visiting);
+        visiting.pop();
+        js
+    }
+}
+
+/// `!("a" | "b") ~ ANY` as the character class `/[^ab]/`, when each excluded
+/// literal is one character.
+fn excluded_class(excluded: &Expression) -> Option<String> {
+    let alternatives = match excluded {
+        Expression::Choice(items) => items.iter().collect(),
+        other => vec![other],
+    };
+    let mut class = String::new();
+    for alternative in alternatives {
+        let Expression::Literal(text) = alternative else {
+            return None;
+        };
+        let mut chars = text.chars();
+        let (Some(c), None) = (chars.next(), chars.next()) else {
+            return None;
+        };
+        match c {
+            '\n' => class.push_str("\\n"),
+            '\r' => class.push_str("\\r"),
+            '\t' => class.push_str("\\t"),
+            '\\' | ']' | '[' | '^' | '-' | '/' => {
+                class.push('\\');
+                class.push(c);
+            }
+            c => class.push(c),
+        }
+    }
+    Some(format!("/[^{class}]/"))
+}
+
+/// `name(items...)`. Comments left by lookaheads are attached to the next item, and
+/// a call left with one item is that item.
+fn js_call(name: &str, items: Vec<String>) -> String {
+    let mut args: Vec<String> = Vec::new();
+    let mut comments = String::new();
+    for item in items.into_iter().filter(|item| !item.is_empty()) {
+        if item.starts_with("/*") && item.ends_with("*/") {
+            comments.push_str(&item);
+            comments.push(' ');
+        } else {
+            args.push(format!("{comments}{item}"));
+            comments.clear();
+        }
+    }
+    match args.as_mut_slice() {
+        [] => format!("{comments}blank()"),
+        [only] if comments.is_empty() => only.clone(),
+        [.., last] => {
+            last.push_str(&format!(" {}", comments.trim_end()));
+            format!("{name}({})", args.join(", ").trim_end())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::grammar_validation::{parse_grammar, GRAMMAR};
+
+    #[test]
+    fn textmate_patterns_are_generated_from_the_rules() {
+        let rules = parse_grammar(GRAMMAR).unwrap();
+        let keywords = Keywords {
+            special_forms: vec!["if".into(), "set!".into()],
+            ..Keywords::default()
+        };
+        let grammar = textmate(&rules, &keywords);
+        let repository = &grammar["repository"];
+        assert_eq!(
+            repository["number"]["match"],
+            r"-?[0-9]+(?:\.[0-9]+)?(?!(?:[a-zA-Z0-9]|_|\.|\+|-|\*|/|<|>|=|\?|!))"
+        );
+        assert_eq!(repository["block_comment"]["begin"], r"#\|");
+        assert_eq!(
+            repository["block_comment"]["patterns"],
+            serde_json::json!([{ "include": "#block_comment" }])
+        );
+        assert_eq!(
+            repository["quoted_string"]["patterns"],
+            serde_json::json!([{ "include": "#escape_sequence" }])
+        );
+        let special_forms = repository["special_forms"]["match"].as_str().unwrap();
+        assert!(special_forms.contains("(?:set!|if)"), "{special_forms}");
+        let patterns = grammar["patterns"].to_string();
+        assert!(!patterns.contains("#escape_sequence"), "{patterns}");
+        assert!(patterns.find("#special_forms") < patterns.find("#symbol"));
+    }
+
+    #[test]
+    fn tree_sitter_rules_are_generated_from_the_rules() {
+        let rules = parse_grammar(GRAMMAR).unwrap();
+        let js = tree_sitter(&rules);
+        assert!(js.contains("    program: $ => repeat(choice($.datum_comment, $.expr)),"));
+        assert!(js.contains("extras: $ => [\" \", \"\\t\", \"\\r\", \"\\n\", $.line_comment"));
+        assert!(js.contains("    line_comment: $ => token(seq(\";\", repeat(/[^\\n]/))),"));
+        let keywords = Keywords {
+            atoms: vec!["println".into()],
+            ..Keywords::default()
+        };
+        let query = highlights(&rules, &keywords);
+        assert!(query.contains("(number) @number"));
+        assert!(query.contains("(#any-of? @function.builtin \"println\")"));
+    }
+}
@@ -1,9 +1,15 @@
 //! Sutra Error Handling - Unified Encapsulated API
 //!
 //! All internal implementation is completely hidden to prevent misuse.
+//!
+//! Errors are cheap to create: they carry the kind, a span, a shared handle to the
+//! source, and static phase names. Error codes and labels are only rendered when the
+//! error is displayed, so errors that are caught and discarded (property shrinking,
+//! `expect` tests) never pay for formatting.
 
 use miette::{Diagnostic, SourceSpan};
 use miette::{LabeledSpan, NamedSource};
+use std::borrow::Cow;
 use std::fmt;
 use std::sync::Arc;
 
@@ -11,47 +17,43 @@ use std::sync::Arc;
 // SOURCE CONTEXT - Error reporting infrastructure
 // ============================================================================
 
-/// Represents source context for error reporting with explicit hierarchy
-/// between real sources (preferred) and fallbacks (tolerated when necessary)
+/// The named source text that error spans point into.
+///
+/// Errors with no source text to point into (I/O failures, configuration) are created
+/// with [`SutraError::without_source`] instead of a placeholder context.
+///
+/// The source text is shared, so cloning a context (e.g. per call frame) is cheap.
 #[derive(Debug, Clone)]
 pub struct SourceContext {
-    pub name: String,
-    pub content: String,
+    source: Arc<NamedSource<String>>,
 }
 
 impl SourceContext {
-    /// Create a source context from real file content
-    /// This is the preferred method for error reporting
+    /// Create a source context from real file content, named after the file it came
+    /// from so that diagnostics label the user's path. Other names are only for text
+    /// with no file behind it, such as `--eval` code or a fragment whose errors are
+    /// reported at its caller's span.
     pub fn from_file(name: impl Into<String>, content: impl Into<String>) -> Self {
         Self {
-            name: name.into(),
-            content: content.into(),
+            source: Arc::new(NamedSource::new(name.into(), content.into())),
         }
     }
 
-    /// Create a fallback when real source is unavailable
-    /// Use only when real source cannot be obtained
-    pub fn fallback(context: &str) -> Self {
-        Self {
-            name: "fallback".to_string(),
-            content: format!("// {}", context),
-        }
+    pub fn name(&self) -> &str {
+        self.source.name()
     }
 
-    /// Convert to NamedSource for use with miette error reporting
-    pub fn to_named_source(&self) -> Arc<NamedSource<String>> {
-        Arc::new(NamedSource::new(self.name.clone(), self.content.clone()))
+    pub fn content(&self) -> &str {
+        self.source.inner()
     }
-}
 
-impl Default for SourceContext {
-    fn default() -> Self {
-        Self::fallback("default context")
+    /// Shared handle to the source for use with miette error reporting
+    pub fn to_named_source(&self) -> Arc<NamedSource<String>> {
+        Arc::clone(&self.source)
     }
 }
 
 /// The single error type - no wrapper, no variants, just essential data
-#[derive(Debug)]
 pub struct SutraError {
     /// What went wrong (type-specific data)
     pub kind: ErrorKind,
@@ -83,11 +85,28 @@ pub enum ErrorKind {
         expected: String,
         found: String,
     },
+    /// A bracket, string or block comment that is never closed.
+    UnclosedDelimiter {
+        delimiter: String,
+        line: usize,
+    },
+    /// A closing bracket that does not match the innermost open one.
+    MismatchedDelimiter {
+        open: char,
+        close: char,
+        line: usize,
+        opened_at: SourceSpan,
+    },
 
     // Runtime errors - evaluation failures
     UndefinedSymbol {
         symbol: String,
     },
+    /// A module's unexported definition, used from outside the module.
+    PrivateSymbol {
+        symbol: String,
+        module: String,
+    },
     TypeMismatch {
         expected: String,
         actual: String,
@@ -100,8 +119,77 @@ pub enum ErrorKind {
         operation: String,
         operand_type: String,
     },
-    RecursionLimit,
+    /// Evaluation nested deeper than the configured limit.
+    RecursionLimit {
+        limit: usize,
+    },
     StackOverflow,
+    /// Evaluation ran past its wall-clock time limit.
+    Timeout {
+        limit_ms: u64,
+    },
+    /// A value or the world grew past a configured quota.
+    ResourceLimit {
+        resource: String,
+        limit: usize,
+        actual: usize,
+    },
+    /// A strict-policy write whose parent path does not hold a map.
+    MissingParent {
+        path: String,
+        parent: String,
+    },
+    /// A file passed to `table/load` cannot be read as a table.
+    InvalidTable {
+        path: String,
+        reason: String,
+    },
+    /// An `http/get` request got no complete response.
+    RequestFailed {
+        url: String,
+        reason: String,
+    },
+    /// An engine snapshot cannot be read or resumed.
+    InvalidSnapshot {
+        reason: String,
+    },
+    /// A replay log cannot be read or written.
+    InvalidReplayLog {
+        reason: String,
+    },
+    /// A replayed session did something other than what its log recorded.
+    ReplayDiverged {
+        event: usize,
+        expected: String,
+        actual: String,
+    },
+    /// Text passed to `json/parse` is not valid JSON.
+    InvalidJson {
+        message: String,
+        line: usize,
+        column: usize,
+    },
+    /// Text given to `read` that is not one Sutra form, located by line and column
+    /// within that text.
+    InvalidData {
+        message: String,
+        line: usize,
+        column: usize,
+    },
+    /// An `assert` whose condition does not hold, with the condition's source text.
+    AssertionFailed {
+        expression: String,
+        message: Option<String>,
+    },
+    /// A `for-all` property that does not hold, with its shrunk counterexample and the
+    /// seed that reproduces it.
+    PropertyFalsified {
+        case: usize,
+        counterexample: String,
+        shrinks: usize,
+        seed: u64,
+        reason: String,
+    },
 
     // Validation errors - semantic analysis issues
     InvalidMacro {
@@ -122,6 +210,18 @@ pub enum ErrorKind {
     GeneralValidation {
         message: String,
     },
+    /// A call to an atom that needs a capability the run does not grant.
+    CapabilityDenied {
+        atom: String,
+        capability: String,
+    },
+    /// Macro expansion nested too deeply or produced too many nodes. `chain` names
+    /// the macros being expanded, outermost first.
+    ExpansionLimit {
+        resource: String,
+        limit: usize,
+        chain: Vec<String>,
+    },
 
     // Test errors
     AssertionFailure {
@@ -133,16 +233,32 @@ pub enum ErrorKind {
 /// Context-specific source information
 #[derive(Debug, Clone)]
 pub struct SourceInfo {
-    pub source: Arc<NamedSource<String>>,
-    pub primary_span: SourceSpan,
-    pub phase: String,
+    pub location: ErrorLocation,
+    pub phase: Cow<'static, str>,
 }
 
-/// Diagnostic enhancement data
+/// Where an error points. Every error has a location; there is no placeholder span.
 #[derive(Debug, Clone)]
+pub enum ErrorLocation {
+    /// A span in the source text the error was reported against.
+    Source {
+        source: Arc<NamedSource<String>>,
+        span: SourceSpan,
+    },
+    /// A span reported where the source text was not at hand (macro expansion,
+    /// semantic validation). The caller attaches it with [`SutraError::with_source`].
+    Detached { span: SourceSpan },
+    /// The error concerns a file or resource with no source text to point into,
+    /// such as a script that could not be read.
+    NoSource { path: String },
+}
+
+/// Diagnostic enhancement data
+#[derive(Debug, Clone, Default)]
 pub struct DiagnosticInfo {
-    pub help: Option<String>,
-    pub error_code: String,
+    pub help: Option<Cow<'static, str>>,
+    /// Overrides the default `sutra::<phase>::<kind>` code.
+    pub error_code: Option<Cow<'static, str>>,
 }
 
 /// Context-aware error creation - each context knows how to create appropriate errors
@@ -214,8 +330,9 @@ pub trait ErrorReporting {
             },
             span,
         );
-        error.diagnostic_info.help =
-            Some("This is an internal engine error. Please report this as a bug.".into());
+        error.diagnostic_info.help = Some(Cow::Borrowed(
+            "This is an internal engine error. Please report this as a bug.",
+        ));
         error
     }
 }
@@ -229,28 +346,45 @@ impl ErrorKind {
             | Self::InvalidLiteral { .. }
             | Self::EmptyExpression
             | Self::ParameterOrderViolation { .. }
-            | Self::UnexpectedToken { .. } => ErrorCategory::Parse,
+            | Self::UnexpectedToken { .. }
+            | Self::UnclosedDelimiter { .. }
+            | Self::MismatchedDelimiter { .. } => ErrorCategory::Parse,
 
             Self::UndefinedSymbol { .. }
+            | Self::PrivateSymbol { .. }
             | Self::TypeMismatch { .. }
             | Self::ArityMismatch { .. }
             | Self::InvalidOperation { .. }
-            | Self::RecursionLimit
-            | Self::StackOverflow => ErrorCategory::Runtime,
+            | Self::RecursionLimit { .. }
+            | Self::StackOverflow
+            | Self::Timeout { .. }
+            | Self::ResourceLimit { .. }
+            | Self::MissingParent { .. }
+            | Self::InvalidTable { .. }
+            | Self::RequestFailed { .. }
+            | Self::InvalidSnapshot { .. }
+            | Self::InvalidReplayLog { .. }
+            | Self::ReplayDiverged { .. }
+            | Self::InvalidJson { .. }
+            | Self::InvalidData { .. }
+            | Self::AssertionFailed { .. }
+            | Self::PropertyFalsified { .. } => ErrorCategory::Runtime,
 
             Self::InvalidMacro { .. }
             | Self::InvalidPath { .. }
             | Self::DuplicateDefinition { .. }
             | Self::ScopeViolation { .. }
-            | Self::GeneralValidation { .. } => ErrorCategory::Validation,
+            | Self::GeneralValidation { .. }
+            | Self::CapabilityDenied { .. }
+            | Self::ExpansionLimit { .. } => ErrorCategory::Validation,
 
             Self::AssertionFailure { .. } => ErrorCategory::Test,
         }
     }
 
-    /// Get error code suffix for diagnostic codes
-    /// Uses const evaluation for zero-cost error code generation
-    pub const fn code_suffix(&self) -> &'static str {
+    /// The kind's stable error code, as named by `(expect (error <code>))` and shown
+    /// at the end of diagnostic codes. Every code is listed in [`codes`].
+    pub const fn code(&self) -> &'static str {
         match self {
             Self::MissingElement { .. } => "missing_element",
             Self::MalformedConstruct { .. } => "malformed_construct",
@@ -258,22 +392,230 @@ impl ErrorKind {
             Self::EmptyExpression => "empty_expression",
             Self::ParameterOrderViolation { .. } => "parameter_order_violation",
             Self::UnexpectedToken { .. } => "unexpected_token",
+            Self::UnclosedDelimiter { .. } => "unclosed_delimiter",
+            Self::MismatchedDelimiter { .. } => "mismatched_delimiter",
             Self::UndefinedSymbol { .. } => "undefined_symbol",
+            Self::PrivateSymbol { .. } => "private_symbol",
             Self::TypeMismatch { .. } => "type_mismatch",
             Self::ArityMismatch { .. } => "arity_mismatch",
             Self::InvalidOperation { .. } => "invalid_operation",
-            Self::RecursionLimit => "recursion_limit",
+            Self::RecursionLimit { .. } => "recursion_limit",
             Self::StackOverflow => "stack_overflow",
+            Self::Timeout { .. } => "timeout",
+            Self::ResourceLimit { .. } => "resource_limit",
+            Self::MissingParent { .. } => "missing_parent",
+            Self::InvalidTable { .. } => "invalid_table",
+            Self::RequestFailed { .. } => "request_failed",
+            Self::InvalidSnapshot { .. } => "invalid_snapshot",
+            Self::InvalidReplayLog { .. } => "invalid_replay_log",
+            Self::ReplayDiverged { .. } => "replay_diverged",
+            Self::InvalidJson { .. } => "invalid_json",
+            Self::InvalidData { .. } => "invalid_data",
+            Self::AssertionFailed { .. } => "assertion_failed",
+            Self::PropertyFalsified { .. } => "property_falsified",
             Self::InvalidMacro { .. } => "invalid_macro",
             Self::InvalidPath { .. } => "invalid_path",
             Self::DuplicateDefinition { .. } => "duplicate_definition",
             Self::ScopeViolation { .. } => "scope_violation",
             Self::GeneralValidation { .. } => "general_validation",
+            Self::CapabilityDenied { .. } => "capability_denied",
+            Self::ExpansionLimit { .. } => "expansion_limit",
             Self::AssertionFailure { .. } => "assertion_failure",
         }
     }
 }
 
+/// One entry in the table of error codes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ErrorCode {
+    /// The code, as returned by [`ErrorKind::code`].
+    pub code: &'static str,
+    /// The category every error with this code belongs to.
+    pub category: ErrorCategory,
+    /// A one-line description, for documentation.
+    pub summary: &'static str,
+}
+
+const fn entry(code: &'static str, category: ErrorCategory, summary: &'static str) -> ErrorCode {
+    ErrorCode {
+        code,
+        category,
+        summary,
+    }
+}
+
+/// Every error code, grouped by category.
+///
+/// Codes are part of the language's stable surface: test files name them in
+/// `(expect (error <code>))`, so a code is never renamed or removed, and a new
+/// [`ErrorKind`] variant gets a new code.
+const CODES: &[ErrorCode] = {
+    use ErrorCategory::*;
+    &[
+        entry(
+            "missing_element",
+            Parse,
+            "a required part of a form is missing",
+        ),
+        entry(
+            "malformed_construct",
+            Parse,
+            "a form or expectation has the wrong shape",
+        ),
+        entry(
+            "invalid_literal",
+            Parse,
+            "a number, string or other literal cannot be read",
+        ),
+        entry("empty_expression", Parse, "an expression has nothing in it"),
+        entry(
+            "parameter_order_violation",
+            Parse,
+            "a rest parameter is not the last parameter",
+        ),
+        entry(
+            "unexpected_token",
+            Parse,
+            "the parser found something other than what it expected",
+        ),
+        entry(
+            "unclosed_delimiter",
+            Parse,
+            "a bracket, string or block comment is never closed",
+        ),
+        entry(
+            "mismatched_delimiter",
+            Parse,
+            "a closing bracket does not match the open one",
+        ),
+        entry("undefined_symbol", Runtime, "a symbol has no binding"),
+        entry(
+            "private_symbol",
+            Runtime,
+            "a module's unexported definition is used from outside it",
+        ),
+        entry("type_mismatch", Runtime, "a value has the wrong type"),
+        entry(
+            "arity_mismatch",
+            Runtime,
+            "a call has the wrong number of arguments",
+        ),
+        entry(
+            "invalid_operation",
+            Runtime,
+            "an operation cannot be applied to its operands",
+        ),
+        entry(
+            "recursion_limit",
+            Runtime,
+            "evaluation nested deeper than the limit",
+        ),
+        entry("stack_overflow", Runtime, "evaluation ran out of stack"),
+        entry("timeout", Runtime, "evaluation ran past its time limit"),
+        entry(
+            "resource_limit",
+            Runtime,
+            "a value or the world grew past a quota",
+        ),
+        entry(
+            "missing_parent",
+            Runtime,
+            "a strict write's parent path does not hold a map",
+        ),
+        entry(
+            "invalid_table",
+            Runtime,
+            "a file cannot be loaded as a table",
+        ),
+        entry(
+            "request_failed",
+            Runtime,
+            "an HTTP request got no complete response",
+        ),
+        entry(
+            "invalid_snapshot",
+            Runtime,
+            "a snapshot cannot be read or resumed",
+        ),
+        entry(
+            "invalid_replay_log",
+            Runtime,
+            "a replay log cannot be read or written",
+        ),
+        entry(
+            "replay_diverged",
+            Runtime,
+            "a replayed session departed from its log",
+        ),
+        entry(
+            "invalid_json",
+            Runtime,
+            "text given to json/parse is not valid JSON",
+        ),
+        entry(
+            "invalid_data",
+            Runtime,
+            "text given to read is not one Sutra form",
+        ),
+        entry(
+            "assertion_failed",
+            Runtime,
+            "an assert condition does not hold",
+        ),
+        entry(
+            "property_falsified",
+            Runtime,
+            "a for-all property does not hold",
+        ),
+        entry(
+            "invalid_macro",
+            Validation,
+            "a macro definition or use is invalid",
+        ),
+        entry("invalid_path", Validation, "a world path is invalid"),
+        entry(
+            "duplicate_definition",
+            Validation,
+            "a name is defined twice",
+        ),
+        entry(
+            "scope_violation",
+            Validation,
+            "a name is used outside its scope",
+        ),
+        entry(
+            "general_validation",
+            Validation,
+            "a program fails semantic validation",
+        ),
+        entry(
+            "capability_denied",
+            Validation,
+            "an atom needs a capability the run does not grant",
+        ),
+        entry(
+            "expansion_limit",
+            Validation,
+            "macro expansion nested too deeply or grew too large",
+        ),
+        entry(
+            "assertion_failure",
+            Test,
+            "a test's result does not match its expectation",
+        ),
+    ]
+};
+
+/// Every error code with its category and a summary, in the order of [`ErrorKind`].
+pub fn codes() -> &'static [ErrorCode] {
+    CODES
+}
+
+/// The table entry for `code`, if it names an error kind.
+pub fn lookup_code(code: &str) -> Option<&'static ErrorCode> {
+    CODES.iter().find(|entry| entry.code == code)
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum ErrorCategory {
     Parse,
@@ -284,6 +626,31 @@ pub enum ErrorCategory {
 
 impl std::error::Error for SutraError {}
 
+/// Shows the code, kind and location, but not the source text the error points into.
+impl fmt::Debug for SutraError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut debug = f.debug_struct("SutraError");
+        debug
+            .field("code", &self.code())
+            .field("kind", &self.kind)
+            .field("phase", &self.source_info.phase);
+        match &self.source_info.location {
+            ErrorLocation::Source { source, span } => {
+                let (line, column) = line_and_column(source.inner(), span.offset());
+                debug.field("at", &format_args!("{}:{line}:{column}", source.name()))
+            }
+            ErrorLocation::Detached { span } => {
+                debug.field("at", &format_args!("byte {}", span.offset()))
+            }
+            ErrorLocation::NoSource { path } => debug.field("at", path),
+        };
+        if let Some(help) = &self.diagnostic_info.help {
+            debug.field("help", help);
+        }
+        debug.finish()
+    }
+}
+
 impl fmt::Display for SutraError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match &self.kind {
@@ -308,9 +675,32 @@ impl fmt::Display for SutraError {
             ErrorKind::UnexpectedToken { expected, found } => {
                 write!(f, "Parse error: expected {}, found {}", expected, found)
             }
+            ErrorKind::UnclosedDelimiter { delimiter, line } => {
+                write!(
+                    f,
+                    "Parse error: unclosed '{}' opened at line {}",
+                    delimiter, line
+                )
+            }
+            ErrorKind::MismatchedDelimiter {
+                open, close, line, ..
+            } => {
+                write!(
+                    f,
+                    "Parse error: '{}' does not close the '{}' opened at line {}",
+                    close, open, line
+                )
+            }
             ErrorKind::UndefinedSymbol { symbol } => {
                 write!(f, "Runtime error: undefined symbol '{}'", symbol)
             }
+            ErrorKind::PrivateSymbol { symbol, module } => {
+                write!(
+                    f,
+                    "Runtime error: '{}' is not exported by module '{}'",
+                    symbol, module
+                )
+            }
             ErrorKind::TypeMismatch { expected, actual } => {
                 write!(f, "Type error: expected {}, got {}", expected, actual)
             }
@@ -331,12 +721,107 @@ impl fmt::Display for SutraError {
                     operation, operand_type
                 )
             }
-            ErrorKind::RecursionLimit => {
-                write!(f, "Runtime error: recursion limit exceeded")
+            ErrorKind::RecursionLimit { limit } => {
+                write!(
+                    f,
+                    "Runtime error: evaluation nested deeper than the limit of {}",
+                    limit
+                )
             }
             ErrorKind::StackOverflow => {
                 write!(f, "Runtime error: stack overflow")
             }
+            ErrorKind::Timeout { limit_ms } => {
+                write!(
+                    f,
+                    "Runtime error: evaluation did not finish within {}ms",
+                    limit_ms
+                )
+            }
+            ErrorKind::ResourceLimit {
+                resource,
+                limit,
+                actual,
+            } => {
+                write!(
+                    f,
+                    "Runtime error: {} {} exceeds the limit of {}",
+                    resource, actual, limit
+                )
+            }
+            ErrorKind::MissingParent { path, parent } => {
+                write!(
+                    f,
+                    "Runtime error: cannot set '{}' because '{}' is not a map",
+                    path, parent
+                )
+            }
+            ErrorKind::InvalidTable { path, reason } => {
+                write!(f, "Runtime error: cannot load table '{}': {}", path, reason)
+            }
+            ErrorKind::RequestFailed { url, reason } => {
+                write!(f, "Runtime error: request to '{}' failed: {}", url, reason)
+            }
+            ErrorKind::InvalidSnapshot { reason } => {
+                write!(f, "Runtime error: cannot restore snapshot: {}", reason)
+            }
+            ErrorKind::InvalidReplayLog { reason } => {
+                write!(f, "Runtime error: cannot use replay log: {}", reason)
+            }
+            ErrorKind::ReplayDiverged {
+                event,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "Runtime error: replay diverged at event {}: expected {}, got {}",
+                event, expected, actual
+            ),
+            ErrorKind::InvalidJson {
+                message,
+                line,
+                column,
+            } => {
+                write!(
+                    f,
+                    "Runtime error: invalid JSON at line {}, column {}: {}",
+                    line, column, message
+                )
+            }
+            ErrorKind::InvalidData {
+                message,
+                line,
+                column,
+            } => {
+                write!(
+                    f,
+                    "Runtime error: unreadable data at line {}, column {}: {}",
+                    line, column, message
+                )
+            }
+            ErrorKind::AssertionFailed {
+                expression,
+                message,
+            } => {
+                write!(f, "Runtime error: assertion {} failed", expression)?;
+                match message {
+                    Some(message) => write!(f, ": {}", message),
+                    None => Ok(()),
+                }
+            }
+            ErrorKind::PropertyFalsified {
+                case,
+                counterexample,
+                shrinks,
+                seed,
+                reason,
+            } => {
+                write!(
+                    f,
+                    "Runtime error: property falsified after {} case(s) with {} (shrunk {} time(s); seed {}): {}",
+                    case, counterexample, shrinks, seed, reason
+                )
+            }
             ErrorKind::InvalidMacro { macro_name, reason } => {
                 write!(
                     f,
@@ -357,9 +842,34 @@ impl fmt::Display for SutraError {
                     symbol, scope
                 )
             }
+            ErrorKind::GeneralValidation { message } if self.is_warning() => {
+                write!(f, "Warning: {}", message)
+            }
             ErrorKind::GeneralValidation { message } => {
                 write!(f, "Validation error: {}", message)
             }
+            ErrorKind::CapabilityDenied { atom, capability } => {
+                write!(
+                    f,
+                    "Validation error: '{}' needs the '{}' capability, which this run does not grant",
+                    atom, capability
+                )
+            }
+            ErrorKind::ExpansionLimit {
+                resource,
+                limit,
+                chain,
+            } => {
+                write!(
+                    f,
+                    "Validation error: macro expansion {} exceeds the limit of {}",
+                    resource, limit
+                )?;
+                if !chain.is_empty() {
+                    write!(f, ", expanding {}", describe_chain(chain))?;
+                }
+                Ok(())
+            }
             ErrorKind::AssertionFailure { message, test_name } => {
                 write!(f, "Test assertion failed in '{}': {}", test_name, message)
             }
@@ -367,54 +877,247 @@ impl fmt::Display for SutraError {
     }
 }
 
+/// Renders the default `sutra::<phase>::<kind>` error code on display.
+struct DefaultCode<'a> {
+    phase: &'a str,
+    kind: &'a ErrorKind,
+}
+
+impl fmt::Display for DefaultCode<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "sutra::{}::{}", self.phase, self.kind.code())
+    }
+}
+
 impl Diagnostic for SutraError {
     fn code<'a>(&'a self) -> Option<Box<dyn fmt::Display + 'a>> {
-        Some(Box::new(&self.diagnostic_info.error_code))
+        match &self.diagnostic_info.error_code {
+            Some(code) => Some(Box::new(code)),
+            None => Some(Box::new(DefaultCode {
+                phase: &self.source_info.phase,
+                kind: &self.kind,
+            })),
+        }
+    }
+
+    /// Diagnostics whose code ends in `.warning` are reported as warnings.
+    fn severity(&self) -> Option<miette::Severity> {
+        self.is_warning().then_some(miette::Severity::Warning)
     }
 
     fn help<'a>(&'a self) -> Option<Box<dyn fmt::Display + 'a>> {
-        self.diagnostic_info
-            .help
-            .as_ref()
-            .map(|h| Box::new(h) as Box<dyn fmt::Display>)
+        let help = self.diagnostic_info.help.as_deref();
+        if let Some((line, column)) = self.undrawable_position() {
+            let position = format!("at line {line}, column {column}; the line is too long to show");
+            return Some(Box::new(match help {
+                Some(help) => format!("{help}\n{position}"),
+                None => position,
+            }));
+        }
+        if let Some(help) = help {
+            return Some(Box::new(help));
+        }
+        match &self.source_info.location {
+            ErrorLocation::Source { .. } => None,
+            ErrorLocation::Detached { span } => Some(Box::new(format!(
+                "at byte offset {} (source text unavailable)",
+                span.offset()
+            ))),
+            ErrorLocation::NoSource { path } => Some(Box::new(format!("in {path}"))),
+        }
     }
 
     fn labels(&self) -> Option<Box<dyn Iterator<Item = miette::LabeledSpan> + '_>> {
-        let labels = vec![LabeledSpan::new_with_span(
-            Some(self.primary_label()),
-            self.source_info.primary_span,
-        )];
-        Some(Box::new(labels.into_iter()))
+        let ErrorLocation::Source { span, .. } = &self.source_info.location else {
+            return None;
+        };
+        if self.undrawable_position().is_some() {
+            return None;
+        }
+        let label = LabeledSpan::new_with_span(Some(self.primary_label().to_string()), *span);
+        // A mismatched bracket points at both ends.
+        let opened = match &self.kind {
+            ErrorKind::MismatchedDelimiter {
+                open, opened_at, ..
+            } => Some(LabeledSpan::new_with_span(
+                Some(format!("'{open}' opened here")),
+                *opened_at,
+            )),
+            _ => None,
+        };
+        Some(Box::new(std::iter::once(label).chain(opened)))
     }
 
     fn source_code(&self) -> Option<&dyn miette::SourceCode> {
-        Some(&*self.source_info.source)
+        match &self.source_info.location {
+            ErrorLocation::Source { .. } if self.undrawable_position().is_some() => None,
+            ErrorLocation::Source { source, .. } => Some(&**source),
+            _ => None,
+        }
     }
 }
 
+/// The widest column a label is drawn at. miette fails to draw much past
+/// `u16::MAX`, and an underline that far right is no help anyway.
+const MAX_DRAWN_COLUMN: usize = 10_000;
+
 impl SutraError {
-    fn primary_label(&self) -> String {
+    /// Creates an error pointing at `span` in `source`, with the default code and no help.
+    pub fn new(
+        kind: ErrorKind,
+        source: Arc<NamedSource<String>>,
+        span: SourceSpan,
+        phase: impl Into<Cow<'static, str>>,
+    ) -> Self {
+        Self::at(kind, ErrorLocation::Source { source, span }, phase)
+    }
+
+    /// Creates an error about `path`, for failures that have no source text to point
+    /// into, such as a file that could not be read.
+    pub fn without_source(
+        kind: ErrorKind,
+        path: impl Into<String>,
+        phase: impl Into<Cow<'static, str>>,
+    ) -> Self {
+        Self::at(kind, ErrorLocation::NoSource { path: path.into() }, phase)
+    }
+
+    fn at(kind: ErrorKind, location: ErrorLocation, phase: impl Into<Cow<'static, str>>) -> Self {
+        Self {
+            kind,
+            source_info: SourceInfo {
+                location,
+                phase: phase.into(),
+            },
+            diagnostic_info: DiagnosticInfo::default(),
+        }
+    }
+
+    /// Whether this is a warning rather than an error: its code ends in `.warning`.
+    fn is_warning(&self) -> bool {
+        let code = self.diagnostic_info.error_code.as_deref();
+        code.is_some_and(|code| code.ends_with(".warning"))
+    }
+
+    /// The line and column of the error's span, if a label would end too far right to
+    /// draw. The source is then left out of the report and the position given instead.
+    fn undrawable_position(&self) -> Option<(usize, usize)> {
+        let ErrorLocation::Source { source, span } = &self.source_info.location else {
+            return None;
+        };
+        let opened_at = match &self.kind {
+            ErrorKind::MismatchedDelimiter { opened_at, .. } => Some(opened_at),
+            _ => None,
+        };
+        let text = source.inner();
+        let too_wide = std::iter::once(span)
+            .chain(opened_at)
+            .any(|label| line_and_column(text, label.offset() + label.len()).1 > MAX_DRAWN_COLUMN);
+        too_wide.then(|| line_and_column(text, span.offset()))
+    }
+
+    /// Attaches `source` to an error whose span was reported without it.
+    /// Errors that already have a location are returned unchanged.
+    pub fn with_source(mut self, source: &SourceContext) -> Self {
+        if let ErrorLocation::Detached { span } = self.source_info.location {
+            self.source_info.location = ErrorLocation::Source {
+                source: source.to_named_source(),
+                span,
+            };
+        }
+        self
+    }
+
+    /// Adds `help` to the diagnostic.
+    pub fn with_help(mut self, help: impl Into<Cow<'static, str>>) -> Self {
+        self.diagnostic_info.help = Some(help.into());
+        self
+    }
+
+    /// Adds a "did you mean" hint naming `suggestion`.
+    pub fn with_suggestion(mut self, suggestion: &str) -> Self {
+        self.diagnostic_info.help = Some(format!("did you mean `{suggestion}`?").into());
+        self
+    }
+
+    /// The span the error points at, if it has one.
+    pub fn span(&self) -> Option<SourceSpan> {
+        match &self.source_info.location {
+            ErrorLocation::Source { span, .. } | ErrorLocation::Detached { span } => Some(*span),
+            ErrorLocation::NoSource { .. } => None,
+        }
+    }
+
+    /// The stable code of the error's kind, e.g. `type_mismatch`. See [`codes`].
+    pub fn code(&self) -> &'static str {
+        self.kind.code()
+    }
+
+    /// The diagnostic code, e.g. `sutra::runtime::type_mismatch`.
+    pub fn error_code(&self) -> String {
+        Diagnostic::code(self)
+            .map(|code| code.to_string())
+            .unwrap_or_default()
+    }
+
+    fn primary_label(&self) -> &'static str {
         match &self.kind {
-            ErrorKind::MissingElement { .. } => "missing here".into(),
-            ErrorKind::MalformedConstruct { .. } => "malformed syntax".into(),
-            ErrorKind::InvalidLiteral { .. } => "invalid literal".into(),
-            ErrorKind::EmptyExpression => "empty expression".into(),
-            ErrorKind::ParameterOrderViolation { .. } => "parameter order error".into(),
-            ErrorKind::UnexpectedToken { .. } => "unexpected token".into(),
-            ErrorKind::UndefinedSymbol { .. } => "undefined symbol".into(),
-            ErrorKind::TypeMismatch { .. } => "type mismatch".into(),
-            ErrorKind::ArityMismatch { .. } => "arity mismatch".into(),
-            ErrorKind::InvalidOperation { .. } => "invalid operation".into(),
-            ErrorKind::RecursionLimit => "recursion limit exceeded".into(),
-            ErrorKind::StackOverflow => "stack overflow".into(),
-            ErrorKind::InvalidMacro { .. } => "invalid macro".into(),
-            ErrorKind::InvalidPath { .. } => "invalid path".into(),
-            ErrorKind::DuplicateDefinition { .. } => "duplicate definition".into(),
-            ErrorKind::ScopeViolation { .. } => "scope violation".into(),
-            ErrorKind::GeneralValidation { .. } => "validation issue".into(),
-            ErrorKind::AssertionFailure { .. } => "assertion failed here".into(),
+            ErrorKind::MissingElement { .. } => "missing here",
+            ErrorKind::MalformedConstruct { .. } => "malformed syntax",
+            ErrorKind::InvalidLiteral { .. } => "invalid literal",
+            ErrorKind::EmptyExpression => "empty expression",
+            ErrorKind::ParameterOrderViolation { .. } => "parameter order error",
+            ErrorKind::UnexpectedToken { .. } => "unexpected token",
+            ErrorKind::UnclosedDelimiter { .. } => "never closed",
+            ErrorKind::MismatchedDelimiter { .. } => "mismatched close",
+            ErrorKind::UndefinedSymbol { .. } => "undefined symbol",
+            ErrorKind::PrivateSymbol { .. } => "private symbol",
+            ErrorKind::TypeMismatch { .. } => "type mismatch",
+            ErrorKind::ArityMismatch { .. } => "arity mismatch",
+            ErrorKind::InvalidOperation { .. } => "invalid operation",
+            ErrorKind::RecursionLimit { .. } => "recursion limit exceeded",
+            ErrorKind::StackOverflow => "stack overflow",
+            ErrorKind::Timeout { .. } => "still running here",
+            ErrorKind::ResourceLimit { .. } => "too large",
+            ErrorKind::MissingParent { .. } => "parent path missing",
+            ErrorKind::InvalidTable { .. } => "table not loaded",
+            ErrorKind::RequestFailed { .. } => "request failed",
+            ErrorKind::InvalidSnapshot { .. } => "snapshot not restored",
+            ErrorKind::InvalidReplayLog { .. } => "replay log unusable",
+            ErrorKind::ReplayDiverged { .. } => "replay diverged",
+            ErrorKind::InvalidJson { .. } => "not valid JSON",
+            ErrorKind::InvalidData { .. } => "not readable",
+            ErrorKind::AssertionFailed { .. } => "does not hold",
+            ErrorKind::PropertyFalsified { .. } => "falsified",
+            ErrorKind::InvalidMacro { .. } => "invalid macro",
+            ErrorKind::InvalidPath { .. } => "invalid path",
+            ErrorKind::DuplicateDefinition { .. } => "duplicate definition",
+            ErrorKind::ScopeViolation { .. } => "scope violation",
+            ErrorKind::GeneralValidation { .. } => "validation issue",
+            ErrorKind::CapabilityDenied { .. } => "not allowed here",
+            ErrorKind::ExpansionLimit { .. } => "expanded from here",
+            ErrorKind::AssertionFailure { .. } => "assertion failed here",
+        }
+    }
+}
+
+/// `chain` joined by arrows, with each run of one name given once with its count,
+/// as in `story -> loop (x99)`.
+fn describe_chain(chain: &[String]) -> String {
+    let mut runs: Vec<(&str, usize)> = Vec::new();
+    for name in chain {
+        match runs.last_mut() {
+            Some((last, count)) if last == name => *count += 1,
+            _ => runs.push((name, 1)),
         }
     }
+    runs.iter()
+        .map(|(name, count)| match count {
+            1 => name.to_string(),
+            _ => format!("{name} (x{count})"),
+        })
+        .collect::<Vec<_>>()
+        .join(" -> ")
 }
 
 /// Standalone constructor for grammar validation phase.
@@ -427,31 +1130,19 @@ pub fn grammar_validation_error(
     rule_definition: &str,
     is_warning: bool,
 ) -> SutraError {
-    let code_suffix = if is_warning { "warning" } else { "error" };
-    let source = Arc::new(NamedSource::new(
-        "grammar_validation",
-        rule_definition.to_string(),
-    ));
-
-    SutraError {
-        kind: ErrorKind::GeneralValidation { message },
-        source_info: SourceInfo {
-            source,
-            primary_span: (0..rule_definition.len()).into(),
-            phase: "Grammar Structure".into(),
-        },
-        diagnostic_info: DiagnosticInfo {
-            help: None,
-            error_code: format!("validation.grammar.{}", code_suffix),
-        },
-    }
-}
-
-/// Creates a placeholder span for errors not tied to a specific source code
-/// location, such as I/O errors or internal application state failures.
-/// This makes the intent of using an empty span explicit and searchable.
-pub fn unspanned() -> miette::SourceSpan {
-    miette::SourceSpan::from(0..0)
+    let source = SourceContext::from_file("grammar_validation", rule_definition);
+    let mut error = SutraError::new(
+        ErrorKind::GeneralValidation { message },
+        source.to_named_source(),
+        (0..rule_definition.len()).into(),
+        "Grammar Structure",
+    );
+    error.diagnostic_info.error_code = Some(Cow::Borrowed(if is_warning {
+        "validation.grammar.warning"
+    } else {
+        "validation.grammar.error"
+    }));
+    error
 }
 
 /// Converts a Sutra AST Span to a miette SourceSpan.
@@ -461,35 +1152,87 @@ pub fn to_source_span(span: crate::syntax::Span) -> miette::SourceSpan {
     miette::SourceSpan::from(span.start..span.end)
 }
 
+/// 1-based line and column of byte `offset` in `source`.
+pub fn line_and_column(source: &str, offset: usize) -> (usize, usize) {
+    let before = &source[..offset.min(source.len())];
+    let line = before.matches('\n').count() + 1;
+    let column = before
+        .rfind('\n')
+        .map_or(before.len(), |newline| before.len() - newline - 1)
+        + 1;
+    (line, column)
+}
+
+/// The candidate closest to `name` by edit distance, if one is close enough to be a
+/// likely typo: at most one edit for every three characters of `name`, and at least
+/// one. Ties go to the alphabetically first candidate.
+pub fn closest_name<'a>(
+    name: &str,
+    candidates: impl IntoIterator<Item = &'a str>,
+) -> Option<&'a str> {
+    let limit = (name.chars().count() / 3).max(1);
+    candidates
+        .into_iter()
+        .filter(|candidate| *candidate != name)
+        .map(|candidate| (edit_distance(name, candidate), candidate))
+        .filter(|(distance, _)| *distance <= limit)
+        .min()
+        .map(|(_, candidate)| candidate)
+}
+
+/// Levenshtein distance between `a` and `b`, counted in characters.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, a_char) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, b_char) in b.iter().enumerate() {
+            let substitution = diagonal + usize::from(a_char != *b_char);
+            diagonal = row[j + 1];
+            row[j + 1] = substitution.min(row[j] + 1).min(diagonal + 1);
+        }
+    }
+    row[b.len()]
+}
+
 /// General-purpose error creation context used throughout the codebase
 /// for creating properly contextualized SutraError instances
 pub struct ValidationContext {
     pub source: SourceContext,
-    pub phase: String,
+    pub phase: Cow<'static, str>,
 }
 
 impl ValidationContext {
-    pub fn new(source: SourceContext, phase: String) -> Self {
-        Self { source, phase }
+    pub fn new(source: SourceContext, phase: impl Into<Cow<'static, str>>) -> Self {
+        Self {
+            source,
+            phase: phase.into(),
+        }
     }
 }
 
-impl ErrorReporting for ValidationContext {
+/// Error creation context for phases that see spans but not the source text, such as
+/// macro expansion. Its errors are [`ErrorLocation::Detached`] until the caller
+/// attaches the source with [`SutraError::with_source`].
+pub struct DetachedContext {
+    pub phase: &'static str,
+}
+
+impl ErrorReporting for DetachedContext {
     fn report(&self, kind: ErrorKind, span: SourceSpan) -> SutraError {
-        let error_code = format!("sutra::{}::{}", self.phase, kind.code_suffix());
+        SutraError::at(kind, ErrorLocation::Detached { span }, self.phase)
+    }
+}
 
-        SutraError {
+impl ErrorReporting for ValidationContext {
+    fn report(&self, kind: ErrorKind, span: SourceSpan) -> SutraError {
+        SutraError::new(
             kind,
-            source_info: SourceInfo {
-                source: self.source.to_named_source(),
-                primary_span: span,
-                phase: self.phase.clone(),
-            },
-            diagnostic_info: DiagnosticInfo {
-                help: None,
-                error_code,
-            },
-        }
+            self.source.to_named_source(),
+            span,
+            self.phase.clone(),
+        )
     }
 }
 
@@ -506,3 +1249,206 @@ pub fn print_error(error: SutraError) {
     let report = Report::new(error);
     eprintln!("{report:?}");
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// One error of every kind.
+    fn every_kind() -> Vec<ErrorKind> {
+        let text = || String::from("x");
+        let span = SourceSpan::from(0..1);
+        vec![
+            ErrorKind::MissingElement { element: text() },
+            ErrorKind::MalformedConstruct { construct: text() },
+            ErrorKind::InvalidLiteral {
+                literal_type: text(),
+                value: text(),
+            },
+            ErrorKind::EmptyExpression,
+            ErrorKind::ParameterOrderViolation { rest_span: span },
+            ErrorKind::UnexpectedToken {
+                expected: text(),
+                found: text(),
+            },
+            ErrorKind::UnclosedDelimiter {
+                delimiter: text(),
+                line: 1,
+            },
+            ErrorKind::MismatchedDelimiter {
+                open: '(',
+                close: ']',
+                line: 1,
+                opened_at: span,
+            },
+            ErrorKind::UndefinedSymbol { symbol: text() },
+            ErrorKind::PrivateSymbol {
+                symbol: text(),
+                module: text(),
+            },
+            ErrorKind::TypeMismatch {
+                expected: text(),
+                actual: text(),
+            },
+            ErrorKind::ArityMismatch {
+                expected: text(),
+                actual: 0,
+            },
+            ErrorKind::InvalidOperation {
+                operation: text(),
+                operand_type: text(),
+            },
+            ErrorKind::RecursionLimit { limit: 1 },
+            ErrorKind::StackOverflow,
+            ErrorKind::Timeout { limit_ms: 1 },
+            ErrorKind::ResourceLimit {
+                resource: text(),
+                limit: 1,
+                actual: 2,
+            },
+            ErrorKind::MissingParent {
+                path: text(),
+                parent: text(),
+            },
+            ErrorKind::InvalidTable {
+                path: text(),
+                reason: text(),
+            },
+            ErrorKind::RequestFailed {
+                url: text(),
+                reason: text(),
+            },
+            ErrorKind::InvalidSnapshot { reason: text() },
+            ErrorKind::InvalidReplayLog { reason: text() },
+            ErrorKind::ReplayDiverged {
+                event: 0,
+                expected: text(),
+                actual: text(),
+            },
+            ErrorKind::InvalidJson {
+                message: text(),
+                line: 1,
+                column: 1,
+            },
+            ErrorKind::InvalidData {
+                message: text(),
+                line: 1,
+                column: 1,
+            },
+            ErrorKind::AssertionFailed {
+                expression: text(),
+                message: None,
+            },
+            ErrorKind::PropertyFalsified {
+                case: 1,
+                counterexample: text(),
+                shrinks: 0,
+                seed: 0,
+                reason: text(),
+            },
+            ErrorKind::InvalidMacro {
+                macro_name: text(),
+                reason: text(),
+            },
+            ErrorKind::InvalidPath { path: text() },
+            ErrorKind::DuplicateDefinition {
+                symbol: text(),
+                original_location: span,
+            },
+            ErrorKind::ScopeViolation {
+                symbol: text(),
+                scope: text(),
+            },
+            ErrorKind::GeneralValidation { message: text() },
+            ErrorKind::CapabilityDenied {
+                atom: text(),
+                capability: text(),
+            },
+            ErrorKind::ExpansionLimit {
+                resource: text(),
+                limit: 1,
+                chain: Vec::new(),
+            },
+            ErrorKind::AssertionFailure {
+                message: text(),
+                test_name: text(),
+            },
+        ]
+    }
+
+    #[test]
+    fn every_kind_has_its_own_listed_code_in_its_category() {
+        let kinds = every_kind();
+        let listed: Vec<_> = kinds.iter().map(|kind| kind.code()).collect();
+        let table: Vec<_> = codes().iter().map(|entry| entry.code).collect();
+        assert_eq!(listed, table);
+        for kind in &kinds {
+            assert_eq!(lookup_code(kind.code()).unwrap().category, kind.category());
+        }
+    }
+
+    /// Test files name these codes, so changing this list breaks them. Add to it;
+    /// never rename or remove an entry.
+    #[test]
+    fn codes_are_stable() {
+        let table: Vec<_> = codes().iter().map(|entry| entry.code).collect();
+        assert_eq!(
+            table,
+            [
+                "missing_element",
+                "malformed_construct",
+                "invalid_literal",
+                "empty_expression",
+                "parameter_order_violation",
+                "unexpected_token",
+                "unclosed_delimiter",
+                "mismatched_delimiter",
+                "undefined_symbol",
+                "private_symbol",
+                "type_mismatch",
+                "arity_mismatch",
+                "invalid_operation",
+                "recursion_limit",
+                "stack_overflow",
+                "timeout",
+                "resource_limit",
+                "missing_parent",
+                "invalid_table",
+                "request_failed",
+                "invalid_snapshot",
+                "invalid_replay_log",
+                "replay_diverged",
+                "invalid_json",
+                "invalid_data",
+                "assertion_failed",
+                "property_falsified",
+                "invalid_macro",
+                "invalid_path",
+                "duplicate_definition",
+                "scope_violation",
+                "general_validation",
+                "capability_denied",
+                "expansion_limit",
+                "assertion_failure",
+            ]
+        );
+    }
+
+    #[test]
+    fn debug_shows_code_and_position_but_not_source() {
+        let source = SourceContext::from_file("script", "(+ 1\n  \"a\")");
+        let error = SutraError::new(
+            ErrorKind::TypeMismatch {
+                expected: "Number".into(),
+                actual: "String".into(),
+            },
+            source.to_named_source(),
+            (7..10).into(),
+            "runtime",
+        );
+        let debug = format!("{error:?}");
+        assert!(debug.contains("code: \"type_mismatch\""), "{debug}");
+        assert!(debug.contains("at: script:2:3"), "{debug}");
+        assert!(!debug.contains("(+ 1"), "{debug}");
+    }
+}
@@ -1,16 +1,50 @@
 use crate::errors::SutraError;
-use regex::Regex;
-use std::collections::HashMap;
+use std::{collections::HashMap, fmt, iter::Peekable, str::Chars};
 
 // =====================
 // Core Data Structures
 // =====================
 
+/// The grammar the parser is generated from, as built into `sutra`.
+pub const GRAMMAR: &str = include_str!("grammar/grammar.pest");
+
 #[derive(Debug, Clone)]
 pub struct Rule {
     pub name: String,
     pub definition: String,
     pub references: Vec<String>,
+    /// The pest modifier before the body: `_` silent, `@` atomic, `$` compound
+    /// atomic or `!` non-atomic.
+    pub modifier: Option<char>,
+    /// The body, without comments.
+    pub expression: Expression,
+    /// The `//` comment lines directly above the rule.
+    pub doc: Option<String>,
+}
+
+/// A pest expression, such as the body of a rule.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expression {
+    /// A string literal, with its escapes processed.
+    Literal(String),
+    /// Another rule, or a built-in one such as `ANY`.
+    Rule(String),
+    /// `a ~ b`
+    Sequence(Vec<Expression>),
+    /// `a | b`
+    Choice(Vec<Expression>),
+    /// `a?`
+    Optional(Box<Expression>),
+    /// `a*`
+    Repeat(Box<Expression>),
+    /// `a+`
+    RepeatOnce(Box<Expression>),
+    /// `a{min, max}`, or `a{min, }` with no maximum.
+    RepeatRange(Box<Expression>, u32, Option<u32>),
+    /// `&a`
+    FollowedBy(Box<Expression>),
+    /// `!a`
+    NotFollowedBy(Box<Expression>),
 }
 
 // Built-in rules that don't need to be defined
@@ -21,6 +55,7 @@ const BUILT_IN_RULES: &[&str] = &[
     "COMMENT",
     "ANY",
     "ASCII_DIGIT",
+    "ASCII_HEX_DIGIT",
     "ASCII_ALPHA",
     "ASCII_ALPHANUMERIC",
     "POP",
@@ -28,13 +63,6 @@ const BUILT_IN_RULES: &[&str] = &[
     "PEEK",
     "PEEK_ALL",
     "DROP",
-    "define",
-    "quote",
-    "lambda",
-    "_",
-    "n",
-    "t",
-    "r",
 ];
 
 // Rules that must exist in any grammar
@@ -56,76 +84,609 @@ pub fn validate_grammar_str(content: &str) -> Result<Vec<SutraError>, Box<dyn st
     Ok(check_grammar_rules(&rules))
 }
 
-// =====================
-// Grammar Parsing
-// =====================
-
-fn parse_grammar_rules(content: &str) -> Result<HashMap<String, Rule>, Box<dyn std::error::Error>> {
-    let mut rules = HashMap::new();
-    let mut current_rule: Option<(String, String)> = None;
-    let reference_regex = Regex::new(r"([a-zA-Z_][a-zA-Z0-9_]*)")?;
+/// Parses the rules of a grammar, in the order they are written.
+pub fn parse_grammar(content: &str) -> Result<Vec<Rule>, Box<dyn std::error::Error>> {
+    let mut rules = Vec::new();
+    let mut doc: Vec<&str> = Vec::new();
+    let mut current_rule: Option<(String, String, Option<String>)> = None;
 
     for line in content.lines() {
         let line = line.trim();
 
-        // Skip comments and empty lines
-        if line.starts_with("//") || line.is_empty() {
+        // A blank line ends a doc comment; a comment line extends it
+        if line.is_empty() {
+            doc.clear();
+            continue;
+        }
+        if let Some(comment) = line.strip_prefix("//") {
+            doc.push(comment.trim());
             continue;
         }
 
         // Start of new rule
         if let Some(equals_pos) = line.find(" = ") {
             // Save previous rule if exists
-            if let Some((name, definition)) = current_rule.take() {
-                let references = extract_references(&definition, &reference_regex);
-                rules.insert(
-                    name.clone(),
-                    Rule {
-                        name,
-                        definition,
-                        references,
-                    },
-                );
+            if let Some((name, definition, doc)) = current_rule.take() {
+                rules.push(Rule::parse(name, definition, doc)?);
             }
 
             // Start new rule
             let rule_name = line[..equals_pos].trim().to_string();
-            current_rule = Some((rule_name, line.to_string()));
+            let rule_doc = (!doc.is_empty()).then(|| doc.join("\n"));
+            current_rule = Some((rule_name, line.to_string(), rule_doc));
         }
         // Continue current rule
-        else if let Some((_, ref mut definition)) = current_rule.as_mut() {
+        else if let Some((_, ref mut definition, _)) = current_rule.as_mut() {
             definition.push('\n');
             definition.push_str(line);
         }
+        doc.clear();
     }
 
     // Save final rule
-    if let Some((name, definition)) = current_rule {
-        let references = extract_references(&definition, &reference_regex);
-        rules.insert(
-            name.clone(),
-            Rule {
-                name,
-                definition,
-                references,
-            },
-        );
+    if let Some((name, definition, doc)) = current_rule {
+        rules.push(Rule::parse(name, definition, doc)?);
     }
 
     Ok(rules)
 }
 
-fn extract_references(definition: &str, regex: &Regex) -> Vec<String> {
-    regex
-        .captures_iter(definition)
-        .map(|cap| cap[1].to_string())
-        .filter(|name| {
-            ![
-                "true", "false", "and", "or", "not", "if", "else", "do", "...",
-            ]
-            .contains(&name.as_str())
+// =====================
+// Grammar Parsing
+// =====================
+
+fn parse_grammar_rules(content: &str) -> Result<HashMap<String, Rule>, Box<dyn std::error::Error>> {
+    Ok(parse_grammar(content)?
+        .into_iter()
+        .map(|rule| (rule.name.clone(), rule))
+        .collect())
+}
+
+impl Rule {
+    /// Parses `definition`, the text of the rule from its name to its closing brace.
+    fn parse(
+        name: String,
+        definition: String,
+        doc: Option<String>,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        let body = definition
+            .split_once('=')
+            .map_or("", |(_, body)| body)
+            .trim_start();
+        let modifier = body
+            .chars()
+            .next()
+            .filter(|c| "_@$!".contains(*c) && body[1..].trim_start().starts_with('{'));
+        let body = &body[modifier.map_or(0, char::len_utf8)..];
+        let expression = ExpressionParser::new(body)
+            .and_then(|mut parser| parser.rule_body())
+            .map_err(|e| format!("cannot parse rule '{name}': {e}"))?;
+        let mut references = Vec::new();
+        expression.references(&mut references);
+        Ok(Self {
+            name,
+            definition,
+            references,
+            modifier,
+            expression,
+            doc,
         })
-        .collect()
+    }
+
+    /// The rule's kind, as the JSON export names it.
+    pub fn kind(&self) -> &'static str {
+        match self.modifier {
+            Some('_') => "silent",
+            Some('@') => "atomic",
+            Some('$') => "compound_atomic",
+            Some('!') => "non_atomic",
+            _ => "normal",
+        }
+    }
+
+    /// Whether whitespace is left out between the rule's items.
+    pub fn is_atomic(&self) -> bool {
+        matches!(self.modifier, Some('@' | '$'))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Literal(String),
+    Ident(String),
+    Number(u32),
+    Punct(char),
+}
+
+/// Splits a pest expression into tokens, skipping `//` comments.
+fn tokenize(text: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            c if c.is_whitespace() => {}
+            '/' if chars.peek() == Some(&'/') => while chars.next_if(|&c| c != '\n').is_some() {},
+            '"' => tokens.push(Token::Literal(string_literal(&mut chars)?)),
+            c if c.is_ascii_digit() => {
+                let mut digits = c.to_string();
+                while let Some(digit) = chars.next_if(char::is_ascii_digit) {
+                    digits.push(digit);
+                }
+                let number = digits
+                    .parse()
+                    .map_err(|_| format!("{digits} is too large"))?;
+                tokens.push(Token::Number(number));
+            }
+            c if c.is_alphabetic() || c == '_' => {
+                let mut ident = c.to_string();
+                while let Some(c) = chars.next_if(|&c| c.is_alphanumeric() || c == '_') {
+                    ident.push(c);
+                }
+                tokens.push(Token::Ident(ident));
+            }
+            '~' | '|' | '*' | '+' | '?' | '!' | '&' | '(' | ')' | '{' | '}' | ',' => {
+                tokens.push(Token::Punct(c))
+            }
+            other => return Err(format!("unexpected '{other}'")),
+        }
+    }
+    Ok(tokens)
+}
+
+/// Reads a string literal whose opening quote has been read.
+fn string_literal(chars: &mut Peekable<Chars>) -> Result<String, String> {
+    let mut text = String::new();
+    loop {
+        match chars.next().ok_or("unterminated string")? {
+            '"' => return Ok(text),
+            '\\' => text.push(escape(chars)?),
+            c => text.push(c),
+        }
+    }
+}
+
+fn escape(chars: &mut Peekable<Chars>) -> Result<char, String> {
+    Ok(match chars.next().ok_or("unterminated string")? {
+        'n' => '\n',
+        'r' => '\r',
+        't' => '\t',
+        '0' => '\0',
+        'u' => {
+            let digits: String = chars
+                .by_ref()
+                .skip_while(|&c| c == '{')
+                .take_while(|&c| c != '}')
+                .collect();
+            u32::from_str_radix(&digits, 16)
+                .ok()
+                .and_then(char::from_u32)
+                .ok_or(format!("invalid escape \\u{{{digits}}}"))?
+        }
+        c => c,
+    })
+}
+
+/// A recursive-descent parser for pest expressions. From loosest to tightest, the
+/// operators are `|`, `~`, the prefixes `!` and `&`, and the suffixes.
+struct ExpressionParser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl ExpressionParser {
+    fn new(text: &str) -> Result<Self, String> {
+        Ok(Self {
+            tokens: tokenize(text)?,
+            pos: 0,
+        })
+    }
+
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    /// Consumes the next token if it is `c`.
+    fn eat(&mut self, c: char) -> bool {
+        let found = self.peek() == Some(&Token::Punct(c));
+        self.pos += usize::from(found);
+        found
+    }
+
+    fn expect(&mut self, c: char) -> Result<(), String> {
+        if self.eat(c) {
+            Ok(())
+        } else {
+            Err(format!("expected '{c}', found {:?}", self.peek()))
+        }
+    }
+
+    /// `{ expression }`, and nothing after it.
+    fn rule_body(&mut self) -> Result<Expression, String> {
+        self.expect('{')?;
+        let expression = self.choice()?;
+        self.expect('}')?;
+        match self.peek() {
+            None => Ok(expression),
+            Some(token) => Err(format!("unexpected {token:?} after the rule")),
+        }
+    }
+
+    fn choice(&mut self) -> Result<Expression, String> {
+        let mut items = vec![self.sequence()?];
+        while self.eat('|') {
+            items.push(self.sequence()?);
+        }
+        Ok(flatten(items, Expression::Choice))
+    }
+
+    fn sequence(&mut self) -> Result<Expression, String> {
+        let mut items = vec![self.prefixed()?];
+        while self.eat('~') {
+            items.push(self.prefixed()?);
+        }
+        Ok(flatten(items, Expression::Sequence))
+    }
+
+    fn prefixed(&mut self) -> Result<Expression, String> {
+        if self.eat('!') {
+            return Ok(Expression::NotFollowedBy(Box::new(self.prefixed()?)));
+        }
+        if self.eat('&') {
+            return Ok(Expression::FollowedBy(Box::new(self.prefixed()?)));
+        }
+        self.suffixed()
+    }
+
+    fn suffixed(&mut self) -> Result<Expression, String> {
+        let mut expression = self.primary()?;
+        loop {
+            expression = if self.eat('?') {
+                Expression::Optional(Box::new(expression))
+            } else if self.eat('*') {
+                Expression::Repeat(Box::new(expression))
+            } else if self.eat('+') {
+                Expression::RepeatOnce(Box::new(expression))
+            } else if self.eat('{') {
+                let (min, max) = self.range()?;
+                Expression::RepeatRange(Box::new(expression), min, max)
+            } else {
+                return Ok(expression);
+            };
+        }
+    }
+
+    /// The inside of `{n}`, `{n, }` or `{n, m}`, whose `{` has been read.
+    fn range(&mut self) -> Result<(u32, Option<u32>), String> {
+        let min = self.number()?;
+        let max = if !self.eat(',') {
+            Some(min)
+        } else if matches!(self.peek(), Some(Token::Number(_))) {
+            Some(self.number()?)
+        } else {
+            None
+        };
+        self.expect('}')?;
+        Ok((min, max))
+    }
+
+    fn number(&mut self) -> Result<u32, String> {
+        match self.tokens.get(self.pos) {
+            Some(Token::Number(n)) => {
+                self.pos += 1;
+                Ok(*n)
+            }
+            token => Err(format!("expected a number, found {token:?}")),
+        }
+    }
+
+    fn primary(&mut self) -> Result<Expression, String> {
+        let token = self.peek().cloned();
+        self.pos += 1;
+        match token {
+            Some(Token::Literal(text)) => Ok(Expression::Literal(text)),
+            Some(Token::Ident(name)) => Ok(Expression::Rule(name)),
+            Some(Token::Punct('(')) => {
+                let expression = self.choice()?;
+                self.expect(')')?;
+                Ok(expression)
+            }
+            token => Err(format!("expected an expression, found {token:?}")),
+        }
+    }
+}
+
+/// `items` joined by `join`, or its only item.
+fn flatten(mut items: Vec<Expression>, join: fn(Vec<Expression>) -> Expression) -> Expression {
+    if items.len() == 1 {
+        items.remove(0)
+    } else {
+        join(items)
+    }
+}
+
+impl Expression {
+    /// Adds the rules this expression refers to, each once, to `found`.
+    pub fn references(&self, found: &mut Vec<String>) {
+        match self {
+            Self::Literal(_) => {}
+            Self::Rule(name) if found.contains(name) => {}
+            Self::Rule(name) => found.push(name.clone()),
+            Self::Sequence(items) | Self::Choice(items) => {
+                items.iter().for_each(|item| item.references(found))
+            }
+            Self::Optional(inner)
+            | Self::Repeat(inner)
+            | Self::RepeatOnce(inner)
+            | Self::RepeatRange(inner, ..)
+            | Self::FollowedBy(inner)
+            | Self::NotFollowedBy(inner) => inner.references(found),
+        }
+    }
+
+    /// Adds the string literals in this expression to `found`.
+    pub fn literals(&self, found: &mut Vec<String>) {
+        match self {
+            Self::Literal(text) => found.push(text.clone()),
+            Self::Rule(_) => {}
+            Self::Sequence(items) | Self::Choice(items) => {
+                items.iter().for_each(|item| item.literals(found))
+            }
+            Self::Optional(inner)
+            | Self::Repeat(inner)
+            | Self::RepeatOnce(inner)
+            | Self::RepeatRange(inner, ..)
+            | Self::FollowedBy(inner)
+            | Self::NotFollowedBy(inner) => inner.literals(found),
+        }
+    }
+
+    /// How tightly the expression binds: 0 for a choice, 1 for a sequence, 2 for a
+    /// lookahead and 3 for the rest.
+    fn precedence(&self) -> u8 {
+        match self {
+            Self::Choice(_) => 0,
+            Self::Sequence(_) => 1,
+            Self::FollowedBy(_) | Self::NotFollowedBy(_) => 2,
+            _ => 3,
+        }
+    }
+
+    /// The expression in pest syntax, in parentheses if it binds looser than `min`.
+    fn pest(&self, min: u8) -> String {
+        let text = self.to_string();
+        if self.precedence() < min {
+            format!("({text})")
+        } else {
+            text
+        }
+    }
+
+    /// The expression in W3C EBNF. See [`to_ebnf`].
+    pub fn ebnf(&self) -> String {
+        self.ebnf_with_precedence().0
+    }
+
+    fn ebnf_at(&self, min: u8) -> String {
+        let (text, precedence) = self.ebnf_with_precedence();
+        if precedence < min {
+            format!("({text})")
+        } else {
+            text
+        }
+    }
+
+    /// The expression in EBNF, and how tightly that binds: 0 for a choice, 1 for a
+    /// subtraction, 2 for a sequence and 3 for the rest.
+    fn ebnf_with_precedence(&self) -> (String, u8) {
+        match self {
+            Self::Literal(text) => ebnf_literal(text),
+            Self::Rule(name) => (ebnf_rule(name).to_string(), 3),
+            Self::Sequence(_) | Self::RepeatRange(..) => ebnf_sequence(&self.sequence_items()),
+            Self::Choice(items) => {
+                let items: Vec<String> = items.iter().map(|item| item.ebnf_at(2)).collect();
+                let count = items.len();
+                let items: Vec<String> = items.into_iter().filter(|i| !i.is_empty()).collect();
+                // An alternative that is only `EOI` matches nothing
+                if items.len() < count {
+                    (format!("({})?", items.join(" | ")), 3)
+                } else {
+                    (items.join(" | "), 0)
+                }
+            }
+            Self::Optional(inner) => (format!("{}?", inner.ebnf_at(3)), 3),
+            Self::Repeat(inner) => (format!("{}*", inner.ebnf_at(3)), 3),
+            Self::RepeatOnce(inner) => (format!("{}+", inner.ebnf_at(3)), 3),
+            Self::FollowedBy(inner) => (format!("/* followed by {} */", inner.ebnf()), 3),
+            Self::NotFollowedBy(inner) => (format!("/* not followed by {} */", inner.ebnf()), 3),
+        }
+    }
+
+    /// The items of a sequence, with repetition ranges written out: `a{1, 3}` is
+    /// `a ~ a? ~ a?`.
+    pub(crate) fn sequence_items(&self) -> Vec<Expression> {
+        match self {
+            Self::Sequence(items) => items.iter().flat_map(Self::sequence_items).collect(),
+            Self::RepeatRange(inner, min, max) => {
+                let required = (0..*min).map(|_| (**inner).clone());
+                let rest: Vec<Expression> = match max {
+                    Some(max) => (*min..*max)
+                        .map(|_| Self::Optional(inner.clone()))
+                        .collect(),
+                    None => vec![Self::Repeat(inner.clone())],
+                };
+                required.chain(rest).collect()
+            }
+            other => vec![other.clone()],
+        }
+    }
+}
+
+impl fmt::Display for Expression {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Literal(text) => write!(f, "{text:?}"),
+            Self::Rule(name) => write!(f, "{name}"),
+            Self::Sequence(items) => {
+                let items: Vec<String> = items.iter().map(|item| item.pest(2)).collect();
+                write!(f, "{}", items.join(" ~ "))
+            }
+            Self::Choice(items) => {
+                let items: Vec<String> = items.iter().map(|item| item.pest(1)).collect();
+                write!(f, "{}", items.join(" | "))
+            }
+            Self::Optional(inner) => write!(f, "{}?", inner.pest(3)),
+            Self::Repeat(inner) => write!(f, "{}*", inner.pest(3)),
+            Self::RepeatOnce(inner) => write!(f, "{}+", inner.pest(3)),
+            Self::RepeatRange(inner, min, Some(max)) => {
+                write!(f, "{}{{{min}, {max}}}", inner.pest(3))
+            }
+            Self::RepeatRange(inner, min, None) => write!(f, "{}{{{min}, }}", inner.pest(3)),
+            Self::FollowedBy(inner) => write!(f, "&{}", inner.pest(2)),
+            Self::NotFollowedBy(inner) => write!(f, "!{}", inner.pest(2)),
+        }
+    }
+}
+
+// =====================
+// Grammar Export
+// =====================
+
+const EBNF_HEADER: &str = "\
+/* The Sutra grammar in W3C EBNF notation, converted from its pest grammar.
+ * Any number of WHITESPACE may appear between the items of a rule, except in
+ * rules marked atomic. Lookaheads that EBNF cannot express are kept as comments. */";
+
+/// Renders `rules` as W3C EBNF, one `name ::= expression` line per rule.
+///
+/// A negative lookahead followed by `ANY`, as in `!"\n" ~ ANY`, becomes the
+/// character class minus what is excluded; other lookaheads become comments. The
+/// pest built-ins are written as character classes, and `SOI` and `EOI` are left out.
+pub fn to_ebnf(rules: &[Rule]) -> String {
+    let width = rules.iter().map(|rule| rule.name.len()).max().unwrap_or(0);
+    let mut ebnf = format!("{EBNF_HEADER}\n\n");
+    for rule in rules {
+        let atomic = if rule.is_atomic() {
+            " /* atomic */"
+        } else {
+            ""
+        };
+        let line = format!(
+            "{:width$} ::= {}{atomic}",
+            rule.name,
+            rule.expression.ebnf()
+        );
+        ebnf.push_str(line.trim_end());
+        ebnf.push('\n');
+    }
+    ebnf
+}
+
+/// Describes `rules` as JSON: each rule's name, kind, pest expression, EBNF,
+/// references and doc comment; the names of the atomic rules, which are the tokens a
+/// highlighter colours; and every string literal, sorted.
+pub fn to_json(rules: &[Rule]) -> serde_json::Value {
+    let entries: Vec<serde_json::Value> = rules
+        .iter()
+        .map(|rule| {
+            serde_json::json!({
+                "name": rule.name,
+                "kind": rule.kind(),
+                "expression": rule.expression.to_string(),
+                "ebnf": rule.expression.ebnf(),
+                "references": rule.references,
+                "doc": rule.doc,
+            })
+        })
+        .collect();
+    let tokens: Vec<&str> = rules
+        .iter()
+        .filter(|rule| rule.is_atomic())
+        .map(|rule| rule.name.as_str())
+        .collect();
+    let mut literals = Vec::new();
+    for rule in rules {
+        rule.expression.literals(&mut literals);
+    }
+    literals.sort();
+    literals.dedup();
+    serde_json::json!({
+        "rules": entries,
+        "tokens": tokens,
+        "literals": literals,
+    })
+}
+
+/// A sequence, with `SOI` and `EOI` left out and `!x ~ ANY` written as a
+/// subtraction.
+fn ebnf_sequence(items: &[Expression]) -> (String, u8) {
+    let mut parts: Vec<(String, u8)> = Vec::new();
+    let mut i = 0;
+    while i < items.len() {
+        match (&items[i], items.get(i + 1)) {
+            (Expression::NotFollowedBy(excluded), Some(Expression::Rule(any))) if any == "ANY" => {
+                parts.push((format!("{} - {}", ebnf_rule(any), excluded.ebnf_at(3)), 1));
+                i += 2;
+            }
+            (item, _) => {
+                parts.push(item.ebnf_with_precedence());
+                i += 1;
+            }
+        }
+    }
+    parts.retain(|(text, _)| !text.is_empty());
+    if parts.len() == 1 {
+        return parts.remove(0);
+    }
+    let parts: Vec<String> = parts
+        .into_iter()
+        .map(|(text, precedence)| match precedence {
+            3 => text,
+            _ => format!("({text})"),
+        })
+        .collect();
+    (parts.join(" "), 2)
+}
+
+/// A string literal, with control characters written as `#xN`.
+fn ebnf_literal(text: &str) -> (String, u8) {
+    let mut parts = Vec::new();
+    let mut run = String::new();
+    for c in text.chars() {
+        if c.is_control() {
+            if !run.is_empty() {
+                parts.push(quote(&std::mem::take(&mut run)));
+            }
+            parts.push(format!("#x{:X}", u32::from(c)));
+        } else {
+            run.push(c);
+        }
+    }
+    if !run.is_empty() || parts.is_empty() {
+        parts.push(quote(&run));
+    }
+    let precedence = if parts.len() == 1 { 3 } else { 2 };
+    (parts.join(" "), precedence)
+}
+
+fn quote(text: &str) -> String {
+    if text.contains('\'') {
+        format!("\"{text}\"")
+    } else {
+        format!("'{text}'")
+    }
+}
+
+/// A rule name, or the character class of a pest built-in.
+fn ebnf_rule(name: &str) -> &str {
+    match name {
+        "ANY" => "[#x0-#x10FFFF]",
+        "ASCII_DIGIT" => "[0-9]",
+        "ASCII_HEX_DIGIT" => "[0-9a-fA-F]",
+        "ASCII_ALPHA" => "[a-zA-Z]",
+        "ASCII_ALPHANUMERIC" => "[a-zA-Z0-9]",
+        "SOI" | "EOI" => "",
+        name => name,
+    }
 }
 
 // =====================
@@ -162,3 +723,54 @@ fn check_grammar_rules(rules: &HashMap<String, Rule>) -> Vec<SutraError> {
 
     errors
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rule(source: &str) -> Rule {
+        parse_grammar(source).unwrap().remove(0)
+    }
+
+    #[test]
+    fn the_built_in_grammar_is_valid() {
+        let errors = validate_grammar_str(GRAMMAR).unwrap();
+        assert!(errors.is_empty(), "{errors:?}");
+    }
+
+    #[test]
+    fn rules_keep_their_order_modifier_and_doc_comment() {
+        let rules = parse_grammar(GRAMMAR).unwrap();
+        assert_eq!(rules[0].name, "WHITESPACE");
+        assert_eq!(rules[0].kind(), "silent");
+        assert!(rules[0].doc.as_deref().unwrap().contains("silent rule"));
+        let list = rules.iter().find(|rule| rule.name == "list").unwrap();
+        assert_eq!(list.references, ["datum_comment", "expr"]);
+    }
+
+    #[test]
+    fn expressions_print_back_as_pest() {
+        let number = rule(r#"number = @{ "-"? ~ ASCII_DIGIT+ ~ ("." ~ ASCII_DIGIT+)? }"#);
+        assert!(number.is_atomic());
+        assert_eq!(
+            number.expression.to_string(),
+            r#""-"? ~ ASCII_DIGIT+ ~ ("." ~ ASCII_DIGIT+)?"#
+        );
+    }
+
+    #[test]
+    fn expressions_convert_to_ebnf() {
+        let comment = rule(r#"line_comment = @{ ";" ~ (!"\n" ~ ANY)* } // trailing"#);
+        assert_eq!(comment.expression.ebnf(), "';' ([#x0-#x10FFFF] - #xA)*");
+        let escape = rule(r#"unicode_escape = @{ "u{" ~ ASCII_HEX_DIGIT{1, 3} ~ "}" }"#);
+        assert_eq!(
+            escape.expression.ebnf(),
+            "'u{' [0-9a-fA-F] [0-9a-fA-F]? [0-9a-fA-F]? '}'"
+        );
+        let program = rule("program = { SOI ~ (a | b)* ~ !c ~ EOI }");
+        assert_eq!(
+            program.expression.ebnf(),
+            "(a | b)* /* not followed by c */"
+        );
+    }
+}
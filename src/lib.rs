@@ -1,13 +1,13 @@
 pub use crate::{
     atoms::{
-        build_canonical_macro_env, build_canonical_world, EngineOutputBuffer, EngineStdoutSink,
-        Path, SharedOutput, StateContext, World,
+        build_canonical_macro_env, build_canonical_world, AtomKind, AtomSpec, Capability, Choice,
+        EngineOutputBuffer, EngineStdoutSink, Path, PathPolicy, SharedOutput, StateContext, World,
     },
     errors::print_error,
     macros::{MacroDefinition, MacroSystem},
     runtime::{ConsCell, Lambda, Value},
-    syntax::{AstNode, Expr, ParamList, Span, Spanned},
-    test::{Expectation, TestResult, TestSummary},
+    syntax::{AstList, AstNode, Expr, ParamList, Span, Spanned},
+    test::{Expectation, ExpectedError, TestResult, TestSummary},
 };
 
 // Module aliases for concise imports
@@ -24,29 +24,47 @@ pub mod prelude {
         errors::{ErrorKind, SourceContext, SutraError},
         macros::MacroSystem,
         runtime::{EvaluationContext, NativeFn, Value},
-        syntax::{AstNode, Expr, Span, Spanned},
+        syntax::{AstList, AstNode, Expr, Span, Spanned},
         MacroDefinition,
     };
 
     // New canonical world type for shared, mutable state
+    pub use crate::atoms::WorldHandle;
     pub use std::cell::RefCell;
     pub use std::rc::Rc;
+    #[cfg(not(feature = "sync"))]
     pub type CanonicalWorld = Rc<RefCell<crate::atoms::World>>;
+    #[cfg(feature = "sync")]
+    pub type CanonicalWorld = std::sync::Arc<std::sync::RwLock<crate::atoms::World>>;
 }
 
+pub mod ast_cache;
 pub mod atoms;
+pub mod bench;
 pub mod cli;
 pub mod discovery;
+pub mod editor_grammar;
 pub mod errors;
 pub mod grammar_validation;
+pub mod localization;
+pub mod logging;
 pub mod macros;
+pub mod optimize;
 pub mod parser;
+pub mod project;
 pub mod repl;
+pub mod replay;
 pub mod runtime;
 pub mod semantic_validation;
+pub mod snapshot;
 pub mod syntax;
+pub mod telemetry;
 pub mod test;
 pub mod test_runner;
+pub mod typecheck;
+#[cfg(feature = "watch")]
+pub mod watch;
+pub mod world_diff;
 
 #[cfg(test)]
 mod sutra_harness {
@@ -61,7 +79,7 @@ mod sutra_harness {
         let test_path = Path::new("tests").to_path_buf();
 
         // Use the same test runner that the CLI uses
-        match cli::run_tests(test_path) {
+        match cli::run_tests(test_path, None, &cli::TestOptions::default()) {
             Ok(()) => {
                 // Tests passed - the test runner will have printed results
             }
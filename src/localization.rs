@@ -0,0 +1,262 @@
+//! Sutra Message Catalogs
+//!
+//! Translated text lives in one catalog file per locale, named after the locale
+//! (`en.toml`, `fr.json`). Nested tables flatten to dotted keys, so
+//!
+//! ```toml
+//! [shop]
+//! greeting = "Welcome, {name}!"
+//! ```
+//!
+//! defines `shop.greeting`. `(tr "shop.greeting" :name "Ada")` looks the key up in the
+//! active locale, falls back to the default locale, and fills `{name}` placeholders.
+
+use std::{collections::HashMap, fs, path::Path};
+
+use crate::{
+    errors::{ErrorKind, SutraError},
+    syntax::{fold_nodes, AstNode, Expr, Span},
+};
+
+/// Locale used as the fallback when no other is configured.
+pub const DEFAULT_LOCALE: &str = "en";
+
+/// The messages of one locale, keyed by dotted message key.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Catalog {
+    pub locale: String,
+    pub messages: HashMap<String, String>,
+}
+
+impl Catalog {
+    /// Loads a `.toml` or `.json` catalog; the locale is the file stem.
+    pub fn load(path: &Path) -> Result<Self, SutraError> {
+        let name = path.display().to_string();
+        let locale = path
+            .file_stem()
+            .and_then(|stem| stem.to_str())
+            .ok_or_else(|| catalog_error(&name, format!("{name} has no locale name")))?;
+        let content =
+            fs::read_to_string(path).map_err(|e| catalog_error(&name, format!("{name} ({e})")))?;
+
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some("toml") => Self::from_toml(locale, &content),
+            Some("json") => Self::from_json(locale, &content),
+            _ => Err(catalog_error(
+                &name,
+                format!("{name} is not a .toml or .json catalog"),
+            )),
+        }
+    }
+
+    pub fn from_toml(locale: &str, content: &str) -> Result<Self, SutraError> {
+        let table: toml::Table = toml::from_str(content).map_err(|e| {
+            catalog_error(locale, format!("{locale} catalog is not valid TOML ({e})"))
+        })?;
+        let json = serde_json::to_value(table)
+            .map_err(|e| catalog_error(locale, format!("{locale} catalog ({e})")))?;
+        Self::from_value(locale, &json)
+    }
+
+    pub fn from_json(locale: &str, content: &str) -> Result<Self, SutraError> {
+        let json: serde_json::Value = serde_json::from_str(content).map_err(|e| {
+            catalog_error(locale, format!("{locale} catalog is not valid JSON ({e})"))
+        })?;
+        Self::from_value(locale, &json)
+    }
+
+    fn from_value(locale: &str, value: &serde_json::Value) -> Result<Self, SutraError> {
+        let mut catalog = Self {
+            locale: locale.to_string(),
+            messages: HashMap::new(),
+        };
+        catalog.flatten("", value)?;
+        Ok(catalog)
+    }
+
+    fn flatten(&mut self, prefix: &str, value: &serde_json::Value) -> Result<(), SutraError> {
+        match value {
+            serde_json::Value::Object(table) => {
+                for (key, child) in table {
+                    self.flatten(&join_key(prefix, key), child)?;
+                }
+                Ok(())
+            }
+            serde_json::Value::String(message) => {
+                self.messages.insert(prefix.to_string(), message.clone());
+                Ok(())
+            }
+            _ => Err(catalog_error(
+                &self.locale,
+                format!(
+                    "{} catalog: message '{}' must be a string",
+                    self.locale, prefix
+                ),
+            )),
+        }
+    }
+}
+
+fn join_key(prefix: &str, key: &str) -> String {
+    if prefix.is_empty() {
+        key.to_string()
+    } else {
+        format!("{prefix}.{key}")
+    }
+}
+
+/// The loaded catalogs and the active and fallback locales.
+#[derive(Debug, Clone)]
+pub struct Localization {
+    pub locale: String,
+    pub default_locale: String,
+    catalogs: HashMap<String, Catalog>,
+}
+
+impl Default for Localization {
+    fn default() -> Self {
+        Self {
+            locale: DEFAULT_LOCALE.to_string(),
+            default_locale: DEFAULT_LOCALE.to_string(),
+            catalogs: HashMap::new(),
+        }
+    }
+}
+
+impl Localization {
+    /// Adds a catalog, merging into any catalog already loaded for its locale.
+    pub fn add_catalog(&mut self, catalog: Catalog) {
+        self.catalogs
+            .entry(catalog.locale.clone())
+            .or_insert_with(|| Catalog {
+                locale: catalog.locale.clone(),
+                messages: HashMap::new(),
+            })
+            .messages
+            .extend(catalog.messages);
+    }
+
+    /// Looks `key` up in the active locale, then in the default locale.
+    pub fn lookup(&self, key: &str) -> Option<&str> {
+        [&self.locale, &self.default_locale]
+            .into_iter()
+            .filter_map(|locale| self.catalogs.get(locale))
+            .find_map(|catalog| catalog.messages.get(key))
+            .map(String::as_str)
+    }
+}
+
+/// Fills `{name}` placeholders in `template` from `args`. `{{` and `}}` are literal braces.
+///
+/// Returns the name of the first placeholder that has no argument.
+pub fn format_message(template: &str, args: &HashMap<String, String>) -> Result<String, String> {
+    let mut result = String::with_capacity(template.len());
+    let mut rest = template;
+
+    while let Some(index) = rest.find(['{', '}']) {
+        result.push_str(&rest[..index]);
+        let tail = &rest[index..];
+        if tail.starts_with("{{") || tail.starts_with("}}") {
+            result.push_str(&tail[..1]);
+            rest = &tail[2..];
+            continue;
+        }
+        match (tail.starts_with('{'), tail.find('}')) {
+            (true, Some(end)) => {
+                let name = &tail[1..end];
+                let value = args.get(name).ok_or_else(|| name.to_string())?;
+                result.push_str(value);
+                rest = &tail[end + 1..];
+            }
+            _ => {
+                result.push_str(&tail[..1]);
+                rest = &tail[1..];
+            }
+        }
+    }
+
+    result.push_str(rest);
+    Ok(result)
+}
+
+/// Collects the literal keys of every `(tr "key" ...)` call in `ast`, with the key's span.
+pub fn translation_keys(ast: &AstNode) -> Vec<(String, Span)> {
+    fold_nodes(ast, Vec::new(), |mut keys, node| {
+        if let Expr::List(items, _) = &*node.value {
+            keys.extend(literal_tr_key(items));
+        }
+        keys
+    })
+}
+
+/// The key of a `(tr "key" ...)` call whose key is a string literal.
+fn literal_tr_key(items: &[AstNode]) -> Option<(String, Span)> {
+    let [head, key, ..] = items else {
+        return None;
+    };
+    match (&*head.value, &*key.value) {
+        (Expr::Symbol(name, _), Expr::String(text, ..)) if name == "tr" => {
+            Some((text.clone(), key.span))
+        }
+        _ => None,
+    }
+}
+
+/// `resource` is the catalog file, or the locale when the content did not come from a file.
+fn catalog_error(resource: &str, message: String) -> SutraError {
+    SutraError::without_source(
+        ErrorKind::GeneralValidation { message },
+        resource,
+        "catalog",
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+        pairs
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_toml_tables_flatten_to_dotted_keys() {
+        let catalog =
+            Catalog::from_toml("en", "title = \"Hi\"\n[shop]\ngreeting = \"Welcome\"\n").unwrap();
+        assert_eq!(catalog.messages["title"], "Hi");
+        assert_eq!(catalog.messages["shop.greeting"], "Welcome");
+    }
+
+    #[test]
+    fn test_json_catalog_rejects_non_string_messages() {
+        assert!(Catalog::from_json("en", r#"{"shop": {"price": 3}}"#).is_err());
+    }
+
+    #[test]
+    fn test_lookup_falls_back_to_default_locale() {
+        let mut localization = Localization::default();
+        localization.add_catalog(Catalog::from_json("en", r#"{"a": "A", "b": "B"}"#).unwrap());
+        localization.add_catalog(Catalog::from_json("fr", r#"{"a": "À"}"#).unwrap());
+        localization.locale = "fr".to_string();
+
+        assert_eq!(localization.lookup("a"), Some("À"));
+        assert_eq!(localization.lookup("b"), Some("B"));
+        assert_eq!(localization.lookup("c"), None);
+    }
+
+    #[test]
+    fn test_format_message() {
+        let values = args(&[("name", "Ada"), ("0", "3")]);
+        assert_eq!(
+            format_message("{name} has {0} {{coins}}", &values).unwrap(),
+            "Ada has 3 {coins}"
+        );
+        assert_eq!(
+            format_message("{missing}", &values),
+            Err("missing".to_string())
+        );
+    }
+}
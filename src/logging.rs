@@ -0,0 +1,50 @@
+//! Logging
+//!
+//! The pipeline, macro expander and test runner report what they are doing as
+//! `tracing` events. The CLI installs a subscriber that writes them to stderr at the
+//! level its `-q`/`-v` flags select; an embedder sees none of them unless it installs
+//! a subscriber of its own.
+
+use std::io::IsTerminal;
+
+use tracing::level_filters::LevelFilter;
+
+/// The most detailed level logged for the given `-q` and `-v` flags: only errors
+/// when quiet, warnings by default, and then info, debug and trace for each `-v`.
+pub fn level(quiet: bool, verbose: u8) -> LevelFilter {
+    if quiet {
+        return LevelFilter::ERROR;
+    }
+    match verbose {
+        0 => LevelFilter::WARN,
+        1 => LevelFilter::INFO,
+        2 => LevelFilter::DEBUG,
+        _ => LevelFilter::TRACE,
+    }
+}
+
+/// Writes events up to `level` to stderr. Does nothing if a subscriber is already set.
+pub fn init(level: LevelFilter) {
+    let _ = tracing_subscriber::fmt()
+        .with_max_level(level)
+        .with_writer(std::io::stderr)
+        .with_ansi(std::io::stderr().is_terminal())
+        .with_target(false)
+        .without_time()
+        .try_init();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn flags_select_levels() {
+        assert_eq!(level(true, 0), LevelFilter::ERROR);
+        assert_eq!(level(true, 2), LevelFilter::ERROR);
+        assert_eq!(level(false, 0), LevelFilter::WARN);
+        assert_eq!(level(false, 1), LevelFilter::INFO);
+        assert_eq!(level(false, 2), LevelFilter::DEBUG);
+        assert_eq!(level(false, 5), LevelFilter::TRACE);
+    }
+}
@@ -6,25 +6,56 @@
 //! 1. Template substitution - Replace parameters in macro bodies with arguments
 //! 2. Path canonicalization - Convert various path syntaxes to Expr::Path nodes
 //! 3. Built-in macro expansion - Transform syntax sugar into canonical forms
+//! 4. String interpolation - Desugar `"Hello, {player.name}!"` into `(core/str+ ...)`
+//! 5. Text templates - Desugar `(text "[a|b]")` markup (see [`text`])
+//! 6. Weighted tables - Desugar `(deftable name (weight value)...)` (see [`deftable`])
+//! 7. State machines - Desugar `(defstate name path (state ...)...)` (see [`defstate`])
+//! 8. Modules - Scope the definitions in `(module name (export ...) ...)` (see [`module`])
+//!
+//! Expansions are memoized: a macro call the same as an earlier one reuses its
+//! expansion until the macro is redefined (see [`MacroSystem::expansion_stats`]).
+//! [`MacroSystem::expansion_steps`] expands a program one call at a time, for tools
+//! that show how a program was expanded.
+
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{Arc, Mutex, MutexGuard, PoisonError},
+};
 
-use std::collections::HashMap;
+use sha2::{Digest, Sha256};
+use smallvec::smallvec;
 
 use crate::prelude::*;
 use crate::{
-    errors::{to_source_span, ErrorKind, ErrorReporting, SourceContext, SutraError, ValidationContext},
-    syntax::{parser, ParamList},
+    errors::{
+        to_source_span, DetachedContext, ErrorKind, ErrorReporting, SourceContext, SutraError,
+    },
+    syntax::{parser, rewrite_children, walk_node, ParamList, Rewriter, Visitor},
 };
 
+mod cache;
+pub mod defstate;
+pub mod deftable;
+pub mod module;
+mod text;
+
 // ============================================================================
 // CONSTANTS
 // ============================================================================
 
-/// Maximum recursion depth for macro expansion to prevent infinite loops
-const MAX_RECURSION_DEPTH: usize = 100;
+/// Macro expansions that may nest within one another unless configured otherwise
+pub const DEFAULT_MAX_DEPTH: usize = 100;
+
+/// AST nodes the expansions of one program may produce unless configured otherwise
+pub const DEFAULT_MAX_NODES: usize = 1_000_000;
 
 /// Expected arity for macro definitions (define name params body)
 const MACRO_DEFINITION_ARITY: usize = 3;
 
+/// Most calls [`MacroSystem::expansion_steps`] expands before giving up, as a macro
+/// that expands to itself never finishes
+const MAX_EXPANSION_STEPS: usize = 10_000;
+
 // ============================================================================
 // CORE TYPES
 // ============================================================================
@@ -37,11 +68,25 @@ pub type MacroFunction = fn(&AstNode) -> Result<AstNode, SutraError>;
 pub struct MacroTemplate {
     pub params: ParamList,
     pub body: Box<AstNode>,
+    /// The `;;;` doc comment written above the definition, if any.
+    pub doc: Option<String>,
 }
 
 impl MacroTemplate {
     pub fn new(params: ParamList, body: Box<AstNode>) -> Result<Self, SutraError> {
-        Ok(MacroTemplate { params, body })
+        Ok(MacroTemplate {
+            params,
+            body,
+            doc: None,
+        })
+    }
+
+    /// How to call the macro, e.g. `(greet name ...rest)`.
+    pub fn usage(&self, name: &str) -> String {
+        let mut parts = vec![name.to_string()];
+        parts.extend(self.params.required.iter().cloned());
+        parts.extend(self.params.rest.iter().map(|rest| format!("...{rest}")));
+        format!("({})", parts.join(" "))
     }
 }
 
@@ -54,10 +99,48 @@ pub enum MacroDefinition {
     Template(MacroTemplate),
 }
 
+pub use cache::ExpansionStats;
+
+/// How far macro expansion of one program may go before it fails.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ExpansionLimits {
+    /// Most macro expansions nested within one another, such as a macro whose
+    /// expansion calls itself.
+    pub max_depth: usize,
+    /// Most AST nodes the expansions of one program may produce in all. A template
+    /// that uses an argument twice doubles in size with each level of nesting.
+    pub max_nodes: usize,
+}
+
+impl Default for ExpansionLimits {
+    fn default() -> Self {
+        Self {
+            max_depth: DEFAULT_MAX_DEPTH,
+            max_nodes: DEFAULT_MAX_NODES,
+        }
+    }
+}
+
+/// One call expanded by [`MacroSystem::expansion_steps`].
+#[derive(Debug, Clone)]
+pub struct ExpansionStep {
+    /// The macro expanded, or `module` or `interpolation` for those built-in steps.
+    pub name: String,
+    /// Where the expanded call was.
+    pub span: Span,
+    /// The whole program after this step.
+    pub program: AstNode,
+}
+
 /// The complete macro expansion system
 #[derive(Debug, Clone)]
 pub struct MacroSystem {
     macros: HashMap<String, MacroDefinition>,
+    /// A fresh id for each registration, keying the expansion cache.
+    definition_ids: HashMap<String, u64>,
+    /// Shared with clones; the ids keep one system's expansions from another's.
+    cache: Arc<Mutex<cache::ExpansionCache>>,
+    limits: ExpansionLimits,
 }
 
 // ============================================================================
@@ -69,6 +152,9 @@ impl MacroSystem {
     pub fn new() -> Self {
         let mut system = Self {
             macros: HashMap::new(),
+            definition_ids: HashMap::new(),
+            cache: Arc::default(),
+            limits: ExpansionLimits::default(),
         };
         register_builtins(&mut system);
         system
@@ -76,27 +162,148 @@ impl MacroSystem {
 
     /// Expand all macros in an AST node
     pub fn expand(&self, ast: AstNode) -> Result<AstNode, SutraError> {
-        expand_recursive(self, ast, 0)
+        expand_recursive(&mut Expansion::new(self), module::gather_headers(ast))
+    }
+
+    pub fn limits(&self) -> ExpansionLimits {
+        self.limits
+    }
+
+    /// Sets how far expanding one program may go before it fails.
+    pub fn set_limits(&mut self, limits: ExpansionLimits) {
+        self.limits = limits;
+    }
+
+    /// Expands macros in `ast` one call at a time, in the order [`Self::expand`] does:
+    /// outermost first, then left to right. Returns the program after each step; the
+    /// last is what `expand` returns. A `module` form, or a string's interpolated
+    /// code, is expanded in a single step.
+    ///
+    /// With `only`, just calls of that macro are expanded, and what they expand to is
+    /// left as is.
+    pub fn expansion_steps(
+        &self,
+        ast: AstNode,
+        only: Option<&str>,
+    ) -> Result<Vec<ExpansionStep>, SutraError> {
+        let mut program = module::gather_headers(ast);
+        let mut expansion = Expansion::new(self);
+        let mut finished = HashSet::new();
+        let mut steps = Vec::new();
+        loop {
+            let mut stepper = Stepper {
+                expansion: &mut expansion,
+                only,
+                finished: &mut finished,
+                step: None,
+            };
+            program = stepper.rewrite_node(program)?;
+            let Some((name, span)) = stepper.step else {
+                return Ok(steps);
+            };
+            if steps.len() == MAX_EXPANSION_STEPS {
+                let kind = ErrorKind::ExpansionLimit {
+                    resource: "step count".to_string(),
+                    limit: MAX_EXPANSION_STEPS,
+                    chain: vec![name],
+                };
+                return Err(create_error(kind, span)
+                    .with_help("look for a macro whose expansion calls itself without end"));
+            }
+            steps.push(ExpansionStep {
+                name,
+                span,
+                program: program.clone(),
+            });
+        }
     }
 
-    /// Load and register macros from source code
-    pub fn load_from_source(&mut self, source: &str) -> Result<(), SutraError> {
-        let source_ctx = SourceContext::from_file("macro_source", source);
-        let exprs = parser::parse(source, source_ctx)?;
+    /// Load and register macros from `source`, the content of the file `name`, which
+    /// errors in it are reported against. A template macro keeps the `;;;` doc comment
+    /// written above its definition.
+    pub fn load_from_source(&mut self, name: &str, source: &str) -> Result<(), SutraError> {
+        let source_ctx = SourceContext::from_file(name, source);
+        let exprs = parser::parse(source, source_ctx.clone())?;
+        let comments = parser::comments(source);
+        let span = Span {
+            start: 0,
+            end: source.len(),
+        };
 
-        for expr in exprs {
-            if let Some((name, def)) = parse_macro_definition_internal(&expr)? {
-                self.macros.insert(name, def);
+        let mut definitions = Vec::new();
+        for expr in module::gather_forms(exprs, span) {
+            definitions
+                .extend(macro_definitions_in(&expr).map_err(|e| e.with_source(&source_ctx))?);
+        }
+        for (name, mut definition, at) in definitions {
+            if let MacroDefinition::Template(template) = &mut definition {
+                template.doc = parser::doc_comment(source, &comments, at);
             }
+            self.register(name, definition);
         }
         Ok(())
     }
 
-    /// Register a macro
+    /// Register a macro, replacing any of the same name and its cached expansions
     pub fn register(&mut self, name: String, definition: MacroDefinition) {
+        let id = self.cache().next_definition_id();
+        if let Some(old) = self.definition_ids.insert(name.clone(), id) {
+            self.cache().forget(old);
+        }
         self.macros.insert(name, definition);
     }
 
+    /// How many macro calls have been expanded, and how many of those came from the
+    /// expansion cache, by this system and its clones.
+    pub fn expansion_stats(&self) -> ExpansionStats {
+        self.cache().stats
+    }
+
+    /// A SHA-256 hash, in hex, of the expansion limits and every macro's name and, for
+    /// template macros, their parameters and body, spans included. Loading a different
+    /// prelude changes it.
+    pub fn fingerprint(&self) -> String {
+        let mut names: Vec<&String> = self.macros.keys().collect();
+        names.sort();
+        let mut hasher = Sha256::new();
+        hasher.update(self.limits.max_depth.to_le_bytes());
+        hasher.update(self.limits.max_nodes.to_le_bytes());
+        for name in names {
+            hasher.update(name.as_bytes());
+            if let MacroDefinition::Template(template) = &self.macros[name] {
+                let encoded = bincode::serialize(&(&template.params, &template.body))
+                    .expect("ASTs serialize");
+                hasher.update(encoded);
+            }
+            hasher.update([0]);
+        }
+        hasher
+            .finalize()
+            .iter()
+            .map(|byte| format!("{byte:02x}"))
+            .collect()
+    }
+
+    fn cache(&self) -> MutexGuard<'_, cache::ExpansionCache> {
+        self.cache.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Expands one macro call, reusing the expansion of an identical earlier call.
+    fn expand_call(
+        &self,
+        name: &str,
+        call: &AstNode,
+        definition: &MacroDefinition,
+    ) -> Result<AstNode, SutraError> {
+        let id = self.definition_ids[name];
+        if let Some(expanded) = self.cache().get(id, call) {
+            return Ok(expanded);
+        }
+        let expanded = apply_macro(call, definition)?;
+        self.cache().insert(id, call, &expanded);
+        Ok(expanded)
+    }
+
     /// Get all macro names
     pub fn macro_names(&self) -> Vec<String> {
         self.macros.keys().cloned().collect()
@@ -117,94 +324,246 @@ impl MacroSystem {
 // CORE EXPANSION LOGIC
 // ============================================================================
 
-/// Recursively expand macros with depth checking
-fn expand_recursive(
-    system: &MacroSystem,
-    node: AstNode,
-    depth: usize,
-) -> Result<AstNode, SutraError> {
-    if depth > MAX_RECURSION_DEPTH {
-        return Err(create_error(
-            ErrorKind::RecursionLimit,
-            "macro_expansion",
-            "recursion_limit",
-            node.span,
-        ));
+/// Expanding one program: the expansions under way, checked against the system's
+/// [`ExpansionLimits`].
+struct Expansion<'a> {
+    system: &'a MacroSystem,
+    /// The macros, or modules, whose expansions are being expanded and the spans of
+    /// their calls, outermost first.
+    chain: Vec<(String, Span)>,
+    /// AST nodes produced by expansions so far.
+    nodes: usize,
+}
+
+impl<'a> Expansion<'a> {
+    fn new(system: &'a MacroSystem) -> Self {
+        Self {
+            system,
+            chain: Vec::new(),
+            nodes: 0,
+        }
+    }
+
+    /// Runs `f` one expansion deeper, inside the expansion of `name` at `span`.
+    fn nested<T>(
+        &mut self,
+        name: &str,
+        span: Span,
+        f: impl FnOnce(&mut Self) -> Result<T, SutraError>,
+    ) -> Result<T, SutraError> {
+        let limit = self.system.limits.max_depth;
+        if self.chain.len() >= limit {
+            return Err(self.limit_error("depth", limit, name, span).with_help(
+                "look for a macro whose expansion calls itself without end, \
+                 or raise the limit with `--max-macro-depth`",
+            ));
+        }
+        self.chain.push((name.to_string(), span));
+        let result = f(self);
+        self.chain.pop();
+        result
+    }
+
+    /// Counts the nodes of `expanded`, the expansion of `name` at `span`, against the
+    /// size limit.
+    fn produced(&mut self, expanded: &AstNode, name: &str, span: Span) -> Result<(), SutraError> {
+        let limit = self.system.limits.max_nodes;
+        let mut pending = vec![expanded];
+        while let Some(node) = pending.pop() {
+            self.nodes += 1;
+            if self.nodes > limit {
+                return Err(self.limit_error("node count", limit, name, span).with_help(
+                    "a template that uses an argument more than once doubles in size \
+                     with each level of nesting; raise the limit with `--max-expansion-nodes`",
+                ));
+            }
+            match &*node.value {
+                Expr::List(items, _) => pending.extend(items.iter()),
+                Expr::If {
+                    condition,
+                    then_branch,
+                    else_branch,
+                    ..
+                } => pending.extend([condition, then_branch, else_branch].map(|branch| &**branch)),
+                Expr::Quote(inner, _) | Expr::Spread(inner) => pending.push(inner),
+                _ => {}
+            }
+        }
+        Ok(())
     }
 
+    /// An error naming every expansion under way and then `name`'s, pointing at the
+    /// outermost call: the inner ones may lie in a prelude rather than the program.
+    fn limit_error(&self, resource: &str, limit: usize, name: &str, span: Span) -> SutraError {
+        let mut chain: Vec<String> = self.chain.iter().map(|(name, _)| name.clone()).collect();
+        chain.push(name.to_string());
+        let kind = ErrorKind::ExpansionLimit {
+            resource: resource.to_string(),
+            limit,
+            chain,
+        };
+        let outermost = self.chain.first().map_or(span, |(_, span)| *span);
+        create_error(kind, outermost)
+    }
+}
+
+/// Recursively expand macros within the expansion limits
+fn expand_recursive(expansion: &mut Expansion, node: AstNode) -> Result<AstNode, SutraError> {
+    let system = expansion.system;
+
     // Check if this is a macro call
     let Expr::List(items, _) = &*node.value else {
-        return expand_subforms(system, node, depth);
+        return expand_subforms(expansion, node);
     };
 
     let Some(first) = items.first() else {
-        return expand_subforms(system, node, depth);
+        return expand_subforms(expansion, node);
     };
 
     let Expr::Symbol(name, _) = &*first.value else {
-        return expand_subforms(system, node, depth);
+        return expand_subforms(expansion, node);
     };
 
+    if name == module::MODULE {
+        return expansion.nested(name, node.span, |expansion| {
+            module::expand_module(&node, |form| expand_recursive(expansion, form))
+        });
+    }
+
     if let Some(macro_def) = system.macros.get(name) {
-        let expanded = apply_macro(&node, macro_def)?;
-        return expand_recursive(system, expanded, depth + 1);
+        tracing::trace!(
+            "expanding macro '{name}' at depth {}",
+            expansion.chain.len()
+        );
+        let expanded = system.expand_call(name, &node, macro_def)?;
+        expansion.produced(&expanded, name, node.span)?;
+        return expansion.nested(name, node.span, |expansion| {
+            expand_recursive(expansion, expanded)
+        });
     }
 
-    expand_subforms(system, node, depth)
+    if let Some((module, member)) =
+        module::private_access(name, |private| system.macros.contains_key(private))
+    {
+        return Err(module::private_symbol_error(&module, &member, first.span));
+    }
+
+    expand_subforms(expansion, node)
 }
 
-/// Helper to expand subforms without macro call detection
-fn expand_subforms(
-    system: &MacroSystem,
-    node: AstNode,
-    depth: usize,
-) -> Result<AstNode, SutraError> {
-    match &*node.value {
-        Expr::List(items, span) => {
-            let mut expanded_items = Vec::new();
-            for item in items {
-                expanded_items.push(expand_recursive(system, item.clone(), depth)?);
+/// Expands every subform of a node, quoted ones included, through
+/// [`expand_recursive`].
+struct Expander<'a, 'b> {
+    expansion: &'a mut Expansion<'b>,
+}
+
+impl Rewriter for Expander<'_, '_> {
+    type Error = SutraError;
+
+    fn rewrite_node(&mut self, node: AstNode) -> Result<AstNode, SutraError> {
+        expand_recursive(self.expansion, node)
+    }
+
+    fn rewrite_quote(&mut self, quoted: AstNode) -> Result<AstNode, SutraError> {
+        self.rewrite_node(quoted)
+    }
+}
+
+/// Expands the first call that [`expand_recursive`] would, and nothing else.
+struct Stepper<'a, 'b> {
+    expansion: &'a mut Expansion<'b>,
+    only: Option<&'a str>,
+    /// Expansions not to look into again: fully expanded ones, and with `only`, every
+    /// one made.
+    finished: &'a mut HashSet<*const Expr>,
+    /// The name and span of the call expanded, once there is one.
+    step: Option<(String, Span)>,
+}
+
+impl Stepper<'_, '_> {
+    /// The expansion of `node` if it is the step to take: a macro call or, without
+    /// `only`, a module or a string with interpolated code.
+    fn step_at(&mut self, node: &AstNode) -> Result<Option<AstNode>, SutraError> {
+        let system = self.expansion.system;
+        if let Expr::String(text, span, raw) = &*node.value {
+            if self.only.is_some() || *raw || !text.contains(['{', '}']) {
+                return Ok(None);
             }
-            Ok(Spanned {
-                value: Expr::List(expanded_items, *span).into(),
-                span: node.span,
-            })
+            let expanded = interpolate_string(self.expansion, text, *span)?;
+            return Ok(Some(self.finish(
+                "interpolation",
+                node.span,
+                expanded,
+                true,
+            )));
         }
-        Expr::If {
-            condition,
-            then_branch,
-            else_branch,
-            span,
-        } => {
-            let new_condition = expand_recursive(system, condition.as_ref().clone(), depth)?;
-            let new_then = expand_recursive(system, then_branch.as_ref().clone(), depth)?;
-            let new_else = expand_recursive(system, else_branch.as_ref().clone(), depth)?;
-            Ok(Spanned {
-                value: Expr::If {
-                    condition: Box::new(new_condition),
-                    then_branch: Box::new(new_then),
-                    else_branch: Box::new(new_else),
-                    span: *span,
-                }
-                .into(),
-                span: node.span,
-            })
+        let Expr::List(items, _) = &*node.value else {
+            return Ok(None);
+        };
+        let Some(Expr::Symbol(name, _)) = items.first().map(|head| &*head.value) else {
+            return Ok(None);
+        };
+        if let Some(macro_def) = system.macros.get(name) {
+            if self.only.is_some_and(|only| only != name) {
+                return Ok(None);
+            }
+            let expanded = system.expand_call(name, node, macro_def)?;
+            self.expansion.produced(&expanded, name, node.span)?;
+            let done = self.only.is_some();
+            return Ok(Some(self.finish(name, node.span, expanded, done)));
         }
-        Expr::Quote(inner, span) => {
-            let new_inner = expand_recursive(system, inner.as_ref().clone(), depth)?;
-            Ok(Spanned {
-                value: Expr::Quote(Box::new(new_inner), *span).into(),
-                span: node.span,
-            })
+        if self.only.is_none() && name == module::MODULE {
+            let expanded = self.expansion.nested(name, node.span, |expansion| {
+                module::expand_module(node, |form| expand_recursive(expansion, form))
+            })?;
+            return Ok(Some(self.finish(name, node.span, expanded, true)));
         }
-        Expr::Spread(inner) => {
-            let new_inner = expand_recursive(system, inner.as_ref().clone(), depth)?;
-            Ok(Spanned {
-                value: Expr::Spread(Box::new(new_inner)).into(),
-                span: node.span,
-            })
+        if let Some((module, member)) =
+            module::private_access(name, |private| system.macros.contains_key(private))
+        {
+            return Err(module::private_symbol_error(
+                &module,
+                &member,
+                items[0].span,
+            ));
         }
-        _ => Ok(node),
+        Ok(None)
+    }
+
+    fn finish(&mut self, name: &str, span: Span, expanded: AstNode, done: bool) -> AstNode {
+        if done {
+            self.finished.insert(Arc::as_ptr(&expanded.value));
+        }
+        self.step = Some((name.to_string(), span));
+        expanded
+    }
+}
+
+impl Rewriter for Stepper<'_, '_> {
+    type Error = SutraError;
+
+    fn rewrite_node(&mut self, node: AstNode) -> Result<AstNode, SutraError> {
+        if self.step.is_some() || self.finished.contains(&Arc::as_ptr(&node.value)) {
+            return Ok(node);
+        }
+        match self.step_at(&node)? {
+            Some(expanded) => Ok(expanded),
+            None => rewrite_children(self, node),
+        }
+    }
+
+    fn rewrite_quote(&mut self, quoted: AstNode) -> Result<AstNode, SutraError> {
+        self.rewrite_node(quoted)
+    }
+}
+
+/// Helper to expand subforms without macro call detection
+fn expand_subforms(expansion: &mut Expansion, node: AstNode) -> Result<AstNode, SutraError> {
+    match &*node.value {
+        Expr::String(text, span, false) if text.contains(['{', '}']) => {
+            interpolate_string(expansion, text, *span)
+        }
+        _ => rewrite_children(&mut Expander { expansion }, node),
     }
 }
 
@@ -250,7 +609,7 @@ fn apply_macro(call: &AstNode, definition: &MacroDefinition) -> Result<AstNode,
                 list_items.extend(rest_args);
 
                 let list_node = Spanned {
-                    value: Expr::List(list_items, call.span).into(),
+                    value: Expr::List(list_items.into(), call.span).into(),
                     span: call.span,
                 };
                 bindings.insert(var_name.clone(), list_node);
@@ -277,7 +636,7 @@ fn substitute_template(
         return Ok(node.clone());
     };
 
-    let mut new_items = Vec::new();
+    let mut new_items = AstList::new();
 
     for item in items {
         // Handle spread operator
@@ -308,10 +667,214 @@ fn substitute_template(
     })
 }
 
+// ============================================================================
+// STRING INTERPOLATION
+// ============================================================================
+
+/// One piece of an interpolated string literal.
+pub(crate) enum Segment {
+    Text(String),
+    /// Source text between the braces, and its byte range within the string content.
+    Code(String, usize, usize),
+}
+
+/// Desugars a string literal containing `{...}` into `(core/str+ ...)`.
+///
+/// Each `{expr}` becomes `(str expr)`; a bare path such as `{player.name}`
+/// reads the world path, becoming `(str (get player.name))`. `{{` and `}}` stand
+/// for literal braces. A string with only escaped braces stays a plain string, and
+/// a raw `#"..."#` string is never interpolated. The embedded expressions are
+/// macro-expanded; the literal text is not.
+fn interpolate_string(
+    expansion: &mut Expansion,
+    text: &str,
+    span: Span,
+) -> Result<AstNode, SutraError> {
+    let segments = split_interpolation(text, span)?;
+    if let [Segment::Text(plain)] = segments.as_slice() {
+        return Ok(Spanned {
+            value: Expr::String(plain.clone(), span, true).into(),
+            span,
+        });
+    }
+
+    let mut items: AstList = smallvec![Spanned {
+        value: Expr::Symbol("core/str+".to_string(), span).into(),
+        span,
+    }];
+    for segment in segments {
+        match segment {
+            Segment::Text(text) => items.push(Spanned {
+                value: Expr::String(text, span, true).into(),
+                span,
+            }),
+            Segment::Code(code, start, end) => {
+                let site = interpolation_site(text, span, start, end);
+                let expr = parse_interpolated_expr(&code, site)?;
+                items.push(expand_recursive(expansion, expr)?);
+            }
+        }
+    }
+
+    Ok(Spanned {
+        value: Expr::List(items, span).into(),
+        span,
+    })
+}
+
+/// Splits string content into literal text and `{...}` code segments.
+pub(crate) fn split_interpolation(text: &str, span: Span) -> Result<Vec<Segment>, SutraError> {
+    let mut segments = Vec::new();
+    let mut literal = String::new();
+    let mut chars = text.char_indices().peekable();
+
+    while let Some((index, ch)) = chars.next() {
+        match ch {
+            '{' if chars.next_if(|(_, c)| *c == '{').is_some() => literal.push('{'),
+            '}' if chars.next_if(|(_, c)| *c == '}').is_some() => literal.push('}'),
+            '{' => {
+                let start = index + 1;
+                let Some(end) = find_closing_brace(text, start) else {
+                    return Err(create_error(
+                        ErrorKind::MalformedConstruct {
+                            construct: "string interpolation: unclosed '{'".to_string(),
+                        },
+                        span,
+                    ));
+                };
+                if !literal.is_empty() {
+                    segments.push(Segment::Text(std::mem::take(&mut literal)));
+                }
+                segments.push(Segment::Code(text[start..end].to_string(), start, end));
+                while chars.next_if(|(i, _)| *i <= end).is_some() {}
+            }
+            _ => literal.push(ch),
+        }
+    }
+
+    if !literal.is_empty() || segments.is_empty() {
+        segments.push(Segment::Text(literal));
+    }
+    Ok(segments)
+}
+
+/// Finds the `}` closing an interpolation that starts at `start`, skipping nested
+/// braces (map literals) and string literals inside the expression.
+fn find_closing_brace(text: &str, start: usize) -> Option<usize> {
+    let mut depth = 0usize;
+    let mut in_string = false;
+    let mut escaped = false;
+    for (offset, ch) in text[start..].char_indices() {
+        match ch {
+            _ if escaped => escaped = false,
+            '\\' if in_string => escaped = true,
+            '"' => in_string = !in_string,
+            '{' if !in_string => depth += 1,
+            '}' if !in_string && depth == 0 => return Some(start + offset),
+            '}' if !in_string => depth -= 1,
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Maps an interpolation back to source. When the literal is a plain quoted string
+/// without escapes, the span covers just the braces; otherwise the whole literal.
+fn interpolation_site(text: &str, span: Span, start: usize, end: usize) -> Span {
+    if span.end.saturating_sub(span.start) != text.len() + 2 {
+        return span;
+    }
+    Span {
+        start: span.start + start,
+        end: span.start + end + 2,
+    }
+}
+
+/// Parses the code inside one `{...}` and wraps it for concatenation.
+fn parse_interpolated_expr(code: &str, site: Span) -> Result<AstNode, SutraError> {
+    let malformed = || {
+        create_error(
+            ErrorKind::MalformedConstruct {
+                construct: format!("string interpolation '{{{code}}}'"),
+            },
+            site,
+        )
+    };
+    let parsed = parser::parse(code, SourceContext::from_file("interpolation", code))
+        .map_err(|_| malformed())?;
+    let [expr] = parsed.as_slice() else {
+        return Err(malformed());
+    };
+
+    let expr = relocate(expr, site);
+    let value = match &*expr.value {
+        Expr::Path(..) => call_node("get", vec![expr.clone()], site),
+        _ => expr,
+    };
+    Ok(call_node("str", vec![value], site))
+}
+
+fn call_node(head: &str, args: Vec<AstNode>, span: Span) -> AstNode {
+    let mut items: AstList = smallvec![Spanned {
+        value: Expr::Symbol(head.to_string(), span).into(),
+        span,
+    }];
+    items.extend(args);
+    Spanned {
+        value: Expr::List(items, span).into(),
+        span,
+    }
+}
+
+/// Rewrites every span in a node to `span`, so errors point at the interpolation site.
+fn relocate(node: &AstNode, span: Span) -> AstNode {
+    struct Relocate(Span);
+
+    impl Rewriter for Relocate {
+        type Error = std::convert::Infallible;
+
+        fn rewrite_node(&mut self, node: AstNode) -> Result<AstNode, Self::Error> {
+            let node = rewrite_children(self, node)?;
+            Ok(Spanned {
+                value: node.value.with_span(self.0).into(),
+                span: self.0,
+            })
+        }
+
+        fn rewrite_quote(&mut self, quoted: AstNode) -> Result<AstNode, Self::Error> {
+            self.rewrite_node(quoted)
+        }
+    }
+
+    let Ok(relocated) = Relocate(span).rewrite_node(node.clone());
+    relocated
+}
+
 // ============================================================================
 // PARSING AND UTILITIES
 // ============================================================================
 
+/// The macro definitions in a top-level prelude form: the form itself, or each
+/// definition in a module, registered under its qualified name.
+fn macro_definitions_in(
+    expr: &AstNode,
+) -> Result<Vec<(String, MacroDefinition, usize)>, SutraError> {
+    if !module::is_module(expr) {
+        let definition = parse_macro_definition_internal(expr)?;
+        return Ok(definition
+            .map(|(name, definition)| (name, definition, expr.span.start))
+            .into_iter()
+            .collect());
+    }
+    let mut definitions = Vec::new();
+    for form in module::ModuleDefinition::parse(expr)?.scoped_body()? {
+        let definition = parse_macro_definition_internal(&form)?;
+        definitions
+            .extend(definition.map(|(name, definition)| (name, definition, form.span.start)));
+    }
+    Ok(definitions)
+}
+
 /// Parse a macro definition from AST (internal version)
 fn parse_macro_definition_internal(
     expr: &AstNode,
@@ -340,8 +903,6 @@ fn parse_macro_definition_internal(
             ErrorKind::MissingElement {
                 element: "macro name".to_string(),
             },
-            "macro_definition",
-            "empty_params",
             items[1].span,
         ));
     }
@@ -358,11 +919,40 @@ fn parse_macro_definition_internal(
             span: param_list.span,
         },
         body: Box::new(body),
+        doc: None,
     };
 
     Ok(Some((macro_name, MacroDefinition::Template(template))))
 }
 
+/// Every call of `head` in `ast`, in source order, without looking inside the calls
+/// found.
+fn calls_of<'a>(ast: &'a AstNode, head: &str) -> Vec<&'a AstNode> {
+    struct Calls<'a, 'h> {
+        head: &'h str,
+        found: Vec<&'a AstNode>,
+    }
+
+    impl<'a> Visitor<'a> for Calls<'a, '_> {
+        fn visit_node(&mut self, node: &'a AstNode) {
+            match &*node.value {
+                Expr::List(items, _) if matches!(items.first().map(|first| &*first.value), Some(Expr::Symbol(name, _)) if name == self.head) =>
+                {
+                    self.found.push(node);
+                }
+                _ => walk_node(self, node),
+            }
+        }
+    }
+
+    let mut calls = Calls {
+        head,
+        found: Vec::new(),
+    };
+    calls.visit_node(ast);
+    calls.found
+}
+
 /// Extract arguments from a macro call
 fn extract_args_from_call(call: &AstNode) -> Result<(Vec<AstNode>, Span), SutraError> {
     let Expr::List(items, span) = &*call.value else {
@@ -370,19 +960,12 @@ fn extract_args_from_call(call: &AstNode) -> Result<(Vec<AstNode>, Span), SutraE
             ErrorKind::MalformedConstruct {
                 construct: "macro call".to_string(),
             },
-            "macro_expansion",
-            "invalid_call",
             call.span,
         ));
     };
 
     if items.is_empty() {
-        return Err(create_error(
-            ErrorKind::EmptyExpression,
-            "macro_expansion",
-            "empty_call",
-            *span,
-        ));
+        return Err(create_error(ErrorKind::EmptyExpression, *span));
     }
 
     Ok((items[1..].to_vec(), *span))
@@ -399,31 +982,25 @@ fn extract_args_from_call(call: &AstNode) -> Result<(Vec<AstNode>, Span), SutraE
 // ERROR HELPERS
 // ============================================================================
 
-/// Create a consistent error with validation context
-fn create_error(kind: ErrorKind, module: &str, context: &str, span: Span) -> SutraError {
-    let ctx = ValidationContext::new(
-        SourceContext::from_file(module, context),
-        "macro_expansion".to_string(),
-    );
-    ctx.report(kind, to_source_span(span))
+/// Macro expansion sees spans but not the source text; the pipeline attaches the
+/// source to these errors with `SutraError::with_source`.
+const EXPANSION_ERRORS: DetachedContext = DetachedContext {
+    phase: "macro_expansion",
+};
+
+/// Create a consistent error for macro expansion
+fn create_error(kind: ErrorKind, span: Span) -> SutraError {
+    EXPANSION_ERRORS.report(kind, to_source_span(span))
 }
 
 /// Create an arity mismatch error
 fn create_arity_error(expected: &str, actual: usize, span: Span) -> SutraError {
-    let ctx = ValidationContext::new(
-        SourceContext::from_file("macro_expansion", "arity"),
-        "macro_expansion".to_string(),
-    );
-    ctx.arity_mismatch(expected, actual, to_source_span(span))
+    EXPANSION_ERRORS.arity_mismatch(expected, actual, to_source_span(span))
 }
 
 /// Create a type mismatch error
 fn create_type_error(expected: &str, actual: &str, span: Span) -> SutraError {
-    let ctx = ValidationContext::new(
-        SourceContext::from_file("macro_expansion", "type_error"),
-        "macro_expansion".to_string(),
-    );
-    ctx.type_mismatch(expected, actual, to_source_span(span))
+    EXPANSION_ERRORS.type_mismatch(expected, actual, to_source_span(span))
 }
 
 // ============================================================================
@@ -431,8 +1008,158 @@ fn create_type_error(expected: &str, actual: &str, span: Span) -> SutraError {
 // ============================================================================
 
 /// Register all built-in macros
-fn register_builtins(_system: &mut MacroSystem) {
+fn register_builtins(system: &mut MacroSystem) {
     // Note: Most world state operations (set!, get, del!, exists?, inc!, dec!, add!, sub!, print)
     // are now implemented as direct atoms rather than macros.
     // Only template macros and complex transformations remain here.
+    system.register(
+        "text".to_string(),
+        MacroDefinition::Function(text::expand_text),
+    );
+    system.register(
+        "deftable".to_string(),
+        MacroDefinition::Function(deftable::expand_deftable),
+    );
+    system.register(
+        "defstate".to_string(),
+        MacroDefinition::Function(defstate::expand_defstate),
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn program(source: &str) -> AstNode {
+        parser::wrap_in_do(parser::parse(source, SourceContext::from_file("test", source)).unwrap())
+    }
+
+    fn system() -> MacroSystem {
+        let mut system = MacroSystem::new();
+        system
+            .load_from_source(
+                "test",
+                "(define (twice x) (list x x)) (define (pair x) (twice (twice x)))",
+            )
+            .unwrap();
+        system
+    }
+
+    #[test]
+    fn steps_expand_one_call_at_a_time_outermost_first() {
+        let system = system();
+        let source = "(pair 1) (println \"n: {n}\")";
+        let steps = system.expansion_steps(program(source), None).unwrap();
+        let names: Vec<_> = steps.iter().map(|step| step.name.as_str()).collect();
+        assert_eq!(names, ["pair", "twice", "twice", "twice", "interpolation"]);
+        assert_eq!(
+            steps[0].program.value.pretty(),
+            "(do (twice (twice 1)) (println \"n: {n}\"))"
+        );
+        assert_eq!(
+            steps.last().unwrap().program.value.pretty(),
+            system.expand(program(source)).unwrap().value.pretty()
+        );
+    }
+
+    #[test]
+    fn raw_strings_are_never_interpolated() {
+        let system = MacroSystem::new();
+        let expanded = system
+            .expand(program(r##"(println #"{"a": {x}}"#) (println "{{x}}")"##))
+            .unwrap();
+        let Expr::List(items, _) = &*expanded.value else {
+            panic!("expected a list");
+        };
+        for (call, text) in items[1..].iter().zip([r#"{"a": {x}}"#, "{x}"]) {
+            let Expr::List(args, _) = &*call.value else {
+                panic!("expected a call");
+            };
+            assert!(matches!(&*args[1].value, Expr::String(s, _, true) if s == text));
+        }
+    }
+
+    #[test]
+    fn expanded_literal_braces_print_back_to_the_same_text() {
+        let system = MacroSystem::new();
+        let printed = system
+            .expand(program(r##"(println "{{not code}}" #"{raw}"#)"##))
+            .unwrap()
+            .value
+            .pretty();
+        assert_eq!(printed, r#"(println "{{not code}}" "{{raw}}")"#);
+        let reprinted = system.expand(program(&printed)).unwrap().value.pretty();
+        assert_eq!(reprinted, printed);
+    }
+
+    #[test]
+    fn steps_with_only_leave_expansions_alone() {
+        let steps = system()
+            .expansion_steps(program("(pair 1) (pair 2) (twice 3)"), Some("pair"))
+            .unwrap();
+        assert_eq!(steps.len(), 2);
+        assert_eq!(
+            steps[1].program.value.pretty(),
+            "(do (twice (twice 1)) (twice (twice 2)) (twice 3))"
+        );
+    }
+
+    #[test]
+    fn steps_stop_at_a_macro_that_never_finishes() {
+        let mut system = MacroSystem::new();
+        system
+            .load_from_source("test", "(define (loop x) (loop x))")
+            .unwrap();
+        let error = system
+            .expansion_steps(program("(loop 1)"), None)
+            .unwrap_err();
+        assert!(matches!(
+            error.kind,
+            ErrorKind::ExpansionLimit {
+                limit: MAX_EXPANSION_STEPS,
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn expansion_deeper_than_the_limit_names_the_chain() {
+        let mut system = MacroSystem::new();
+        system
+            .load_from_source(
+                "test",
+                "(define (story x) (loop x)) (define (loop x) (loop x))",
+            )
+            .unwrap();
+        system.set_limits(ExpansionLimits {
+            max_depth: 10,
+            ..ExpansionLimits::default()
+        });
+        let error = system.expand(program("(story 1)")).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Validation error: macro expansion depth exceeds the limit of 10, \
+             expanding story -> loop (x10)"
+        );
+    }
+
+    #[test]
+    fn expansion_larger_than_the_limit_fails() {
+        let source = "(twice (twice (twice (twice 1))))";
+        let mut system = system();
+        system.set_limits(ExpansionLimits {
+            max_nodes: 104,
+            ..ExpansionLimits::default()
+        });
+        system.expand(program(source)).unwrap();
+        system.set_limits(ExpansionLimits {
+            max_nodes: 103,
+            ..ExpansionLimits::default()
+        });
+        let error = system.expand(program(source)).unwrap_err();
+        assert!(matches!(
+            error.kind,
+            ErrorKind::ExpansionLimit { limit: 103, ref chain, .. } if chain.len() == 4
+        ));
+    }
 }
@@ -0,0 +1,328 @@
+//! Memoized macro expansions.
+//!
+//! Expanding a macro call is a pure function of the macro's definition and the call,
+//! so [`ExpansionCache`] keeps each one-step expansion keyed by the definition and a
+//! structural hash of the call, which ignores spans. A later call of the same text,
+//! wherever it was written, reuses the expansion with the spans that lay within the
+//! cached call moved to the new one, so errors still point at the code that caused
+//! them. A call that is the same but laid out differently is a miss.
+//!
+//! Every registration gets a fresh definition id from the cache, so redefining a macro
+//! can never reuse an expansion of the old definition; its entries are also dropped
+//! then. Ids are unique among the systems sharing a cache, which are all that see it,
+//! so independent engines in one process share nothing.
+
+use std::{
+    collections::HashMap,
+    hash::{DefaultHasher, Hash, Hasher},
+};
+
+use crate::{
+    prelude::*,
+    syntax::{rewrite_children, Rewriter},
+};
+
+/// How often expansions were found in the cache.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ExpansionStats {
+    /// Macro calls expanded, cached or not.
+    pub expansions: usize,
+    /// Expansions taken from the cache.
+    pub hits: usize,
+}
+
+impl ExpansionStats {
+    /// The share of expansions taken from the cache, between 0 and 1.
+    pub fn hit_rate(&self) -> f64 {
+        if self.expansions == 0 {
+            return 0.0;
+        }
+        self.hits as f64 / self.expansions as f64
+    }
+}
+
+/// Expansions by definition id and structural hash of the call. Calls with the same
+/// hash are compared structurally, so a hash collision is only a miss.
+#[derive(Debug, Default)]
+pub(super) struct ExpansionCache {
+    entries: HashMap<(u64, u64), Vec<(AstNode, AstNode)>>,
+    /// The id the next definition registered gets.
+    next_id: u64,
+    pub(super) stats: ExpansionStats,
+}
+
+impl ExpansionCache {
+    /// A fresh id for a macro definition.
+    pub(super) fn next_definition_id(&mut self) -> u64 {
+        self.next_id += 1;
+        self.next_id
+    }
+
+    /// The cached expansion of `call` under definition `id`, with its spans moved to
+    /// `call`. Only a cached call of the same text, wherever it was written, matches.
+    pub(super) fn get(&mut self, id: u64, call: &AstNode) -> Option<AstNode> {
+        self.stats.expansions += 1;
+        let (cached_call, expansion, offset) = self
+            .entries
+            .get(&(id, structural_hash(call)))?
+            .iter()
+            .filter(|(cached, _)| structurally_equal(cached, call))
+            .find_map(|(cached, expansion)| {
+                span_offset(cached, call).map(|offset| (cached, expansion, offset))
+            })?;
+        self.stats.hits += 1;
+        if offset == 0 {
+            return Some(expansion.clone());
+        }
+        let mut shift = ShiftSpans {
+            within: cached_call.span,
+            offset,
+        };
+        let Ok(shifted) = shift.rewrite_node(expansion.clone());
+        Some(shifted)
+    }
+
+    pub(super) fn insert(&mut self, id: u64, call: &AstNode, expansion: &AstNode) {
+        self.entries
+            .entry((id, structural_hash(call)))
+            .or_default()
+            .push((call.clone(), expansion.clone()));
+    }
+
+    /// Drops the expansions of definition `id`.
+    pub(super) fn forget(&mut self, id: u64) {
+        self.entries.retain(|(entry_id, _), _| *entry_id != id);
+    }
+}
+
+// ============================================================================
+// STRUCTURAL COMPARISON
+// ============================================================================
+
+/// A hash of `node` that ignores spans.
+fn structural_hash(node: &AstNode) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    hash_node(node, &mut hasher);
+    hasher.finish()
+}
+
+fn hash_node(node: &AstNode, hasher: &mut impl Hasher) {
+    let expr = &*node.value;
+    std::mem::discriminant(expr).hash(hasher);
+    match expr {
+        Expr::List(items, _) => {
+            items.len().hash(hasher);
+            for item in items {
+                hash_node(item, hasher);
+            }
+        }
+        Expr::Symbol(text, _) | Expr::Keyword(text, _) => text.hash(hasher),
+        Expr::String(text, _, raw) => {
+            text.hash(hasher);
+            raw.hash(hasher);
+        }
+        Expr::Path(path, _) => path.0.hash(hasher),
+        Expr::Number(n, _) => n.to_bits().hash(hasher),
+        Expr::Bool(b, _) => b.hash(hasher),
+        Expr::If {
+            condition,
+            then_branch,
+            else_branch,
+            ..
+        } => {
+            for branch in [condition, then_branch, else_branch] {
+                hash_node(branch, hasher);
+            }
+        }
+        Expr::Quote(inner, _) | Expr::Spread(inner) => hash_node(inner, hasher),
+        Expr::ParamList(params) => {
+            params.required.hash(hasher);
+            params.rest.hash(hasher);
+        }
+    }
+}
+
+/// Whether `a` and `b` are the same apart from spans.
+fn structurally_equal(a: &AstNode, b: &AstNode) -> bool {
+    match (&*a.value, &*b.value) {
+        (Expr::List(a, _), Expr::List(b, _)) => {
+            a.len() == b.len() && a.iter().zip(b).all(|(a, b)| structurally_equal(a, b))
+        }
+        (Expr::Symbol(a, _), Expr::Symbol(b, _)) | (Expr::Keyword(a, _), Expr::Keyword(b, _)) => {
+            a == b
+        }
+        (Expr::String(a, _, a_raw), Expr::String(b, _, b_raw)) => a == b && a_raw == b_raw,
+        (Expr::Path(a, _), Expr::Path(b, _)) => a == b,
+        (Expr::Number(a, _), Expr::Number(b, _)) => a.to_bits() == b.to_bits(),
+        (Expr::Bool(a, _), Expr::Bool(b, _)) => a == b,
+        (
+            Expr::If {
+                condition: c1,
+                then_branch: t1,
+                else_branch: e1,
+                ..
+            },
+            Expr::If {
+                condition: c2,
+                then_branch: t2,
+                else_branch: e2,
+                ..
+            },
+        ) => structurally_equal(c1, c2) && structurally_equal(t1, t2) && structurally_equal(e1, e2),
+        (Expr::Quote(a, _), Expr::Quote(b, _)) | (Expr::Spread(a), Expr::Spread(b)) => {
+            structurally_equal(a, b)
+        }
+        (Expr::ParamList(a), Expr::ParamList(b)) => a.required == b.required && a.rest == b.rest,
+        _ => false,
+    }
+}
+
+// ============================================================================
+// SPAN SHIFTING
+// ============================================================================
+
+/// How far `new` is from `old` if every span of one is the matching span of the
+/// other moved by the same amount, as for the same text written elsewhere. The two
+/// must be structurally equal.
+fn span_offset(old: &AstNode, new: &AstNode) -> Option<isize> {
+    let offset = new.span.start as isize - old.span.start as isize;
+    congruent(old, new, offset).then_some(offset)
+}
+
+fn congruent(old: &AstNode, new: &AstNode, offset: isize) -> bool {
+    let shifted = |old: Span, new: Span| shift(old, offset) == new;
+    if !shifted(old.span, new.span) || !shifted(old.value.span(), new.value.span()) {
+        return false;
+    }
+    match (&*old.value, &*new.value) {
+        (Expr::List(old, _), Expr::List(new, _)) => old
+            .iter()
+            .zip(new)
+            .all(|(old, new)| congruent(old, new, offset)),
+        (
+            Expr::If {
+                condition: c1,
+                then_branch: t1,
+                else_branch: e1,
+                ..
+            },
+            Expr::If {
+                condition: c2,
+                then_branch: t2,
+                else_branch: e2,
+                ..
+            },
+        ) => congruent(c1, c2, offset) && congruent(t1, t2, offset) && congruent(e1, e2, offset),
+        (Expr::Quote(old, _), Expr::Quote(new, _)) | (Expr::Spread(old), Expr::Spread(new)) => {
+            congruent(old, new, offset)
+        }
+        _ => true,
+    }
+}
+
+fn shift(span: Span, offset: isize) -> Span {
+    Span {
+        start: span.start.saturating_add_signed(offset),
+        end: span.end.saturating_add_signed(offset),
+    }
+}
+
+/// Moves the spans that lie within the cached call, including those a macro made
+/// for parts of an argument, by the offset to the new call.
+struct ShiftSpans {
+    within: Span,
+    offset: isize,
+}
+
+impl ShiftSpans {
+    fn shifted(&self, span: Span) -> Span {
+        if self.within.start <= span.start && span.end <= self.within.end {
+            shift(span, self.offset)
+        } else {
+            span
+        }
+    }
+}
+
+impl Rewriter for ShiftSpans {
+    type Error = std::convert::Infallible;
+
+    fn rewrite_node(&mut self, node: AstNode) -> Result<AstNode, Self::Error> {
+        let node = rewrite_children(self, node)?;
+        Ok(Spanned {
+            value: node.value.with_span(self.shifted(node.value.span())).into(),
+            span: self.shifted(node.span),
+        })
+    }
+
+    fn rewrite_quote(&mut self, quoted: AstNode) -> Result<AstNode, Self::Error> {
+        self.rewrite_node(quoted)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{errors::SourceContext, parser};
+
+    fn parse(source: &str) -> AstNode {
+        parser::parse(source, SourceContext::from_file("test", source))
+            .unwrap()
+            .remove(0)
+    }
+
+    #[test]
+    fn reuses_expansions_with_the_new_call_spans() {
+        let first = parse("(greet \"Ada\")");
+        let second = parse("  (greet \"Ada\")");
+        let mut cache = ExpansionCache::default();
+        let expansion = parse("(str+ \"Hello, \" \"Ada\")");
+        let Expr::List(items, _) = &*first.value else {
+            unreachable!()
+        };
+        let mut expansion_items = (*expansion.value).clone().into_list().unwrap();
+        expansion_items[2] = items[1].clone();
+        let expansion = Spanned {
+            value: Expr::List(expansion_items.into(), expansion.span).into(),
+            span: expansion.span,
+        };
+        cache.insert(7, &first, &expansion);
+
+        let reused = cache.get(7, &second).unwrap();
+        let Expr::List(reused, _) = &*reused.value else {
+            unreachable!()
+        };
+        assert_eq!(reused[2].span, Span { start: 9, end: 14 });
+        assert!(cache.get(8, &second).is_none());
+        assert!(cache.get(7, &parse("(greet \"Bob\")")).is_none());
+        assert_eq!(
+            cache.stats,
+            ExpansionStats {
+                expansions: 3,
+                hits: 1
+            }
+        );
+    }
+
+    #[test]
+    fn redefining_a_macro_drops_its_expansions() {
+        let mut system = MacroSystem::new();
+        let expand = |system: &MacroSystem| system.expand(parse("(m 2)")).unwrap().value.pretty();
+        system
+            .load_from_source("test", "(define (m x) (+ x 1))")
+            .unwrap();
+        assert_eq!(expand(&system), "(+ 2 1)");
+        system
+            .load_from_source("test", "(define (m x) (- x 1))")
+            .unwrap();
+        assert_eq!(expand(&system), "(- 2 1)");
+        assert_eq!(expand(&system), "(- 2 1)");
+        assert_eq!(
+            system.expansion_stats(),
+            ExpansionStats {
+                expansions: 3,
+                hits: 1
+            }
+        );
+    }
+}
@@ -0,0 +1,308 @@
+//! The `(defstate ...)` state machine macro.
+//!
+//! ```text
+//! (defstate quest quests.main
+//!   (idle
+//!     (start active :when (gte? (get player.level) 2)))
+//!   (active
+//!     :on-enter (println "The quest begins.")
+//!     :on-exit (println "The quest is over.")
+//!     (finish done)
+//!     (abandon idle))
+//!   (done))
+//! ```
+//!
+//! declares a machine whose current state is kept at a world path. Each state clause
+//! names the state, optionally gives `:on-enter` and `:on-exit` hooks, and lists its
+//! transitions as `(event target)` with an optional `:when` guard. The first state is
+//! the initial one. Guards and hooks become lambdas, so they run when `advance!`
+//! fires a transition. The macro expands to a `core/defstate` call taking one map per
+//! state, and checks that every transition targets a declared state.
+
+use super::{call_node, calls_of, create_error, extract_args_from_call};
+use crate::{
+    errors::{ErrorKind, SutraError},
+    prelude::*,
+    syntax::{escape_string, ParamList},
+};
+
+/// A `defstate` form, read without evaluating it.
+#[derive(Debug, Clone)]
+pub struct MachineDefinition {
+    pub name: String,
+    pub path: Path,
+    pub states: Vec<StateDefinition>,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone)]
+pub struct StateDefinition {
+    pub name: String,
+    pub on_enter: Option<AstNode>,
+    pub on_exit: Option<AstNode>,
+    pub transitions: Vec<TransitionDefinition>,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone)]
+pub struct TransitionDefinition {
+    pub event: String,
+    pub target: String,
+    pub guard: Option<AstNode>,
+    pub span: Span,
+}
+
+impl MachineDefinition {
+    /// Reads a `(defstate name path (state ...)...)` call.
+    pub fn parse(call: &AstNode) -> Result<Self, SutraError> {
+        let (args, span) = extract_args_from_call(call)?;
+        let [name, path, clauses @ ..] = args.as_slice() else {
+            return Err(malformed(
+                "defstate: expected a name, a path and states",
+                span,
+            ));
+        };
+        let Some(name) = symbol(name) else {
+            return Err(malformed("defstate: the name must be a symbol", name.span));
+        };
+        let path = match &*path.value {
+            Expr::Path(path, _) => path.clone(),
+            Expr::Symbol(path, _) => Path(path.split('.').map(String::from).collect()),
+            _ => {
+                return Err(malformed(
+                    "defstate: the state must be kept at a path",
+                    path.span,
+                ))
+            }
+        };
+        if clauses.is_empty() {
+            return Err(malformed("defstate: expected at least one state", span));
+        }
+        let mut states: Vec<StateDefinition> = Vec::new();
+        for clause in clauses {
+            let state = parse_state(clause)?;
+            if states.iter().any(|other| other.name == state.name) {
+                return Err(malformed("defstate: a state is declared twice", state.span));
+            }
+            states.push(state);
+        }
+        for transition in states.iter().flat_map(|state| &state.transitions) {
+            if !states.iter().any(|state| state.name == transition.target) {
+                return Err(malformed(
+                    "defstate transition: the target is not a declared state",
+                    transition.span,
+                ));
+            }
+        }
+        Ok(Self {
+            name,
+            path,
+            states,
+            span,
+        })
+    }
+
+    /// The machine as a Graphviz DOT graph: a node per state, an edge per transition
+    /// labelled with its event and any guard, and an arrow into the initial state.
+    pub fn to_dot(&self) -> String {
+        let quote = |text: &str| format!("\"{}\"", escape_string(text));
+        let mut dot = format!("digraph {} {{\n", quote(&self.name));
+        dot.push_str("  rankdir=LR;\n  \"\" [shape=point];\n");
+        dot.push_str(&format!("  \"\" -> {};\n", quote(&self.states[0].name)));
+        for state in &self.states {
+            dot.push_str(&format!("  {};\n", quote(&state.name)));
+        }
+        for state in &self.states {
+            for transition in &state.transitions {
+                let label = match &transition.guard {
+                    Some(guard) => format!("{} when {}", transition.event, guard.value.pretty()),
+                    None => transition.event.clone(),
+                };
+                dot.push_str(&format!(
+                    "  {} -> {} [label={}];\n",
+                    quote(&state.name),
+                    quote(&transition.target),
+                    quote(&label)
+                ));
+            }
+        }
+        dot.push_str("}\n");
+        dot
+    }
+}
+
+/// Reads a `(name [:on-enter expr] [:on-exit expr] (event target [:when guard])...)`
+/// state clause.
+fn parse_state(clause: &AstNode) -> Result<StateDefinition, SutraError> {
+    let Expr::List(items, span) = &*clause.value else {
+        return Err(malformed(
+            "defstate state: expected (name transition...)",
+            clause.span,
+        ));
+    };
+    let Some(name) = items.first().and_then(symbol) else {
+        return Err(malformed(
+            "defstate state: the name must be a symbol",
+            *span,
+        ));
+    };
+    let mut state = StateDefinition {
+        name,
+        on_enter: None,
+        on_exit: None,
+        transitions: Vec::new(),
+        span: *span,
+    };
+    let mut rest = items[1..].iter();
+    while let Some(item) = rest.next() {
+        match &*item.value {
+            Expr::Keyword(hook, _) if hook == "on-enter" || hook == "on-exit" => {
+                let slot = if hook == "on-enter" {
+                    &mut state.on_enter
+                } else {
+                    &mut state.on_exit
+                };
+                let Some(body) = rest.next() else {
+                    return Err(malformed(
+                        "defstate state: a hook needs an expression",
+                        item.span,
+                    ));
+                };
+                if slot.replace(body.clone()).is_some() {
+                    return Err(malformed(
+                        "defstate state: a hook is given twice",
+                        item.span,
+                    ));
+                }
+            }
+            Expr::List(..) => state.transitions.push(parse_transition(item)?),
+            _ => {
+                return Err(malformed(
+                    "defstate state: expected :on-enter, :on-exit or (event target)",
+                    item.span,
+                ))
+            }
+        }
+    }
+    Ok(state)
+}
+
+/// Reads an `(event target [:when guard])` transition.
+fn parse_transition(node: &AstNode) -> Result<TransitionDefinition, SutraError> {
+    let Expr::List(items, span) = &*node.value else {
+        return Err(malformed(
+            "defstate transition: expected (event target)",
+            node.span,
+        ));
+    };
+    let (event, target, guard) = match items.as_slice() {
+        [event, target] => (event, target, None),
+        [event, target, keyword, guard] if is_keyword(keyword, "when") => {
+            (event, target, Some(guard.clone()))
+        }
+        _ => {
+            return Err(malformed(
+                "defstate transition: expected (event target [:when guard])",
+                *span,
+            ))
+        }
+    };
+    let (Some(event), Some(target)) = (symbol(event), symbol(target)) else {
+        return Err(malformed(
+            "defstate transition: the event and target must be symbols",
+            *span,
+        ));
+    };
+    Ok(TransitionDefinition {
+        event,
+        target,
+        guard,
+        span: *span,
+    })
+}
+
+fn symbol(node: &AstNode) -> Option<String> {
+    match &*node.value {
+        Expr::Symbol(name, _) => Some(name.clone()),
+        _ => None,
+    }
+}
+
+fn is_keyword(node: &AstNode, name: &str) -> bool {
+    matches!(&*node.value, Expr::Keyword(keyword, _) if keyword == name)
+}
+
+fn malformed(construct: &str, span: Span) -> SutraError {
+    create_error(
+        ErrorKind::MalformedConstruct {
+            construct: construct.to_string(),
+        },
+        span,
+    )
+}
+
+/// Expands `(defstate ...)` into a `core/defstate` call.
+pub(super) fn expand_defstate(call: &AstNode) -> Result<AstNode, SutraError> {
+    let machine = MachineDefinition::parse(call)?;
+    let node = |expr: Expr, span: Span| Spanned {
+        value: expr.into(),
+        span,
+    };
+    let string = |text: &str, span: Span| node(Expr::String(text.to_string(), span, true), span);
+    let thunk = |body: &AstNode| {
+        let params = ParamList {
+            required: Vec::new(),
+            rest: None,
+            span: body.span,
+        };
+        call_node(
+            "lambda",
+            vec![node(Expr::ParamList(params), body.span), body.clone()],
+            body.span,
+        )
+    };
+
+    let mut args = vec![
+        string(&machine.name, machine.span),
+        node(Expr::Path(machine.path.clone(), machine.span), machine.span),
+    ];
+    for state in &machine.states {
+        let mut fields = vec![string("name", state.span), string(&state.name, state.span)];
+        for (key, hook) in [("on-enter", &state.on_enter), ("on-exit", &state.on_exit)] {
+            if let Some(hook) = hook {
+                fields.extend([string(key, hook.span), thunk(hook)]);
+            }
+        }
+        let transitions = state
+            .transitions
+            .iter()
+            .map(|transition| {
+                let span = transition.span;
+                let mut fields = vec![
+                    string("event", span),
+                    string(&transition.event, span),
+                    string("target", span),
+                    string(&transition.target, span),
+                ];
+                if let Some(guard) = &transition.guard {
+                    fields.extend([string("when", guard.span), thunk(guard)]);
+                }
+                call_node("core/map", fields, span)
+            })
+            .collect();
+        fields.extend([
+            string("transitions", state.span),
+            call_node("list", transitions, state.span),
+        ]);
+        args.push(call_node("core/map", fields, state.span));
+    }
+    Ok(call_node("core/defstate", args, machine.span))
+}
+
+/// Every `defstate` form in `ast`, read without expanding or running it.
+pub fn machine_definitions(ast: &AstNode) -> Vec<Result<MachineDefinition, SutraError>> {
+    calls_of(ast, "defstate")
+        .into_iter()
+        .map(MachineDefinition::parse)
+        .collect()
+}
@@ -0,0 +1,161 @@
+//! The `(deftable ...)` weighted table macro.
+//!
+//! `(deftable loot (6 "gold") (3 "sword") (1 (table potions)))` declares a table with
+//! one `(weight value)` clause per entry, where `(table <name>)` as a value rolls
+//! another table. Weights must be non-negative number literals, so a table's odds can
+//! be checked without running it (see `sutra analyze --audit-tables`). The macro
+//! expands to a `core/deftable` call, with `(weight :table "name")` for nested entries.
+
+use super::{call_node, calls_of, create_error, extract_args_from_call};
+use crate::{
+    atoms::roll_table::NESTED_MARKER,
+    errors::{ErrorKind, SutraError},
+    prelude::*,
+};
+
+/// A `deftable` form, read without evaluating it.
+#[derive(Debug, Clone)]
+pub struct TableDefinition {
+    pub name: String,
+    pub entries: Vec<TableEntry>,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone)]
+pub struct TableEntry {
+    pub weight: f64,
+    pub outcome: EntryOutcome,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone)]
+pub enum EntryOutcome {
+    /// An expression evaluated when the table is declared.
+    Value(AstNode),
+    /// A roll of the named table.
+    Table(String),
+}
+
+impl TableDefinition {
+    pub fn total_weight(&self) -> f64 {
+        self.entries.iter().map(|entry| entry.weight).sum()
+    }
+
+    /// Reads a `(deftable name (weight value)...)` call.
+    pub fn parse(call: &AstNode) -> Result<Self, SutraError> {
+        let (args, span) = extract_args_from_call(call)?;
+        let Some((name, clauses)) = args.split_first() else {
+            return Err(malformed("deftable: expected a name and entries", span));
+        };
+        let Expr::Symbol(name, _) = &*name.value else {
+            return Err(malformed("deftable: the name must be a symbol", name.span));
+        };
+        if clauses.is_empty() {
+            return Err(malformed("deftable: expected at least one entry", span));
+        }
+        let entries = clauses
+            .iter()
+            .map(parse_entry)
+            .collect::<Result<Vec<_>, _>>()?;
+        let definition = Self {
+            name: name.clone(),
+            entries,
+            span,
+        };
+        if definition.total_weight() <= 0.0 {
+            return Err(malformed("deftable: every entry has weight 0", span));
+        }
+        Ok(definition)
+    }
+}
+
+/// Reads a `(weight value)` clause.
+fn parse_entry(clause: &AstNode) -> Result<TableEntry, SutraError> {
+    let Expr::List(items, span) = &*clause.value else {
+        return Err(malformed(
+            "deftable entry: expected (weight value)",
+            clause.span,
+        ));
+    };
+    let [weight, value] = items.as_slice() else {
+        return Err(malformed("deftable entry: expected (weight value)", *span));
+    };
+    let Expr::Number(weight, _) = &*weight.value else {
+        return Err(malformed(
+            "deftable entry: weights must be number literals",
+            weight.span,
+        ));
+    };
+    if !weight.is_finite() || *weight < 0.0 {
+        return Err(malformed(
+            "deftable entry: weights must not be negative",
+            *span,
+        ));
+    }
+    let outcome = match &*value.value {
+        Expr::List(items, _) if is_symbol(items.first(), NESTED_MARKER) => match items.as_slice() {
+            [_, table] if matches!(&*table.value, Expr::Symbol(..)) => {
+                EntryOutcome::Table(table.value.to_string())
+            }
+            _ => {
+                return Err(malformed(
+                    "deftable entry: expected (table name)",
+                    value.span,
+                ))
+            }
+        },
+        _ => EntryOutcome::Value(value.clone()),
+    };
+    Ok(TableEntry {
+        weight: *weight,
+        outcome,
+        span: *span,
+    })
+}
+
+fn is_symbol(node: Option<&AstNode>, name: &str) -> bool {
+    matches!(node.map(|node| &*node.value), Some(Expr::Symbol(symbol, _)) if symbol == name)
+}
+
+fn malformed(construct: &str, span: Span) -> SutraError {
+    create_error(
+        ErrorKind::MalformedConstruct {
+            construct: construct.to_string(),
+        },
+        span,
+    )
+}
+
+/// Expands `(deftable ...)` into a `core/deftable` call.
+pub(super) fn expand_deftable(call: &AstNode) -> Result<AstNode, SutraError> {
+    let definition = TableDefinition::parse(call)?;
+    let node = |expr: Expr| Spanned {
+        value: expr.into(),
+        span: definition.span,
+    };
+    let mut args = vec![node(Expr::String(
+        definition.name.clone(),
+        definition.span,
+        true,
+    ))];
+    for entry in &definition.entries {
+        let mut items = vec![node(Expr::Number(entry.weight, entry.span))];
+        match &entry.outcome {
+            EntryOutcome::Value(value) => items.push(value.clone()),
+            EntryOutcome::Table(table) => {
+                items.push(node(Expr::Keyword(NESTED_MARKER.to_string(), entry.span)));
+                items.push(node(Expr::String(table.clone(), entry.span, true)));
+            }
+        }
+        args.push(call_node("list", items, entry.span));
+    }
+    Ok(call_node("core/deftable", args, definition.span))
+}
+
+/// Every `deftable` form in `ast`, read without expanding or running it.
+pub fn table_definitions(ast: &AstNode) -> Vec<Result<TableDefinition, SutraError>> {
+    calls_of(ast, "deftable")
+        .into_iter()
+        .map(TableDefinition::parse)
+        .collect()
+}
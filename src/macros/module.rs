@@ -0,0 +1,436 @@
+//! The `(module ...)` form, which keeps definitions behind a name.
+//!
+//! ```text
+//! (module shop (export buy!)
+//!   (define price 5)
+//!   (define (buy! item) (charge price) (add! player.items item))
+//!   (define (charge amount) (sub! player.gold amount)))
+//! (shop/buy! "sword")
+//! ```
+//!
+//! Inside a module its definitions are used by their plain names. Outside, exported
+//! ones are reached as `shop/buy!`, and the rest are private: `shop/charge` is an error
+//! naming the module. A declaration with no body, `(module shop (export buy!))`, takes
+//! every form after it in the same file as its body, up to the next such declaration.
+//! In a prelude, a module's `(define (name params) body)` forms register macros under
+//! the same rules.
+//!
+//! The body is macro-expanded before it is scoped, so definitions written by macros
+//! and names used in string interpolation are covered too. A parameter or `let`
+//! binding with a definition's name hides it, as usual. Private definitions are
+//! stored as `shop#charge`, which no symbol can spell.
+
+use std::{collections::HashMap, convert::Infallible};
+
+use super::{call_node, create_error, extract_args_from_call};
+use crate::{
+    errors::{ErrorKind, SutraError},
+    prelude::*,
+    syntax::{rewrite_children, ParamList, Rewriter},
+};
+
+/// The head of a module form.
+pub const MODULE: &str = "module";
+
+/// The head of a module's export list.
+pub const EXPORT: &str = "export";
+
+/// Separates a module's name from a private definition's name.
+const PRIVATE_MARKER: char = '#';
+
+/// A `module` form, read without expanding it.
+#[derive(Debug, Clone)]
+pub struct ModuleDefinition {
+    pub name: String,
+    pub exports: Vec<String>,
+    pub body: Vec<AstNode>,
+    pub span: Span,
+}
+
+impl ModuleDefinition {
+    /// Reads a `(module name [(export name...)] body...)` call.
+    pub fn parse(call: &AstNode) -> Result<Self, SutraError> {
+        let (args, span) = extract_args_from_call(call)?;
+        let Some((name, rest)) = args.split_first() else {
+            return Err(malformed("module: expected a name", span));
+        };
+        let Expr::Symbol(name, _) = &*name.value else {
+            return Err(malformed("module: the name must be a symbol", name.span));
+        };
+        let (exports, body) = match rest.split_first() {
+            Some((first, body)) if is_call(first, EXPORT) => (export_list(first)?, body),
+            _ => (Vec::new(), rest),
+        };
+        Ok(Self {
+            name: name.clone(),
+            exports,
+            body: body.to_vec(),
+            span,
+        })
+    }
+
+    /// The names the body defines at its top level, including inside `do` forms.
+    pub fn defined_names(&self) -> Vec<String> {
+        let mut names = Vec::new();
+        for form in &self.body {
+            collect_definitions(form, &mut names);
+        }
+        names
+    }
+
+    /// The name a definition is stored under: `module/name` if it is exported,
+    /// otherwise a private name.
+    pub fn qualified_name(&self, name: &str) -> String {
+        if self.exports.iter().any(|export| export == name) {
+            format!("{}/{}", self.name, name)
+        } else {
+            private_name(&self.name, name)
+        }
+    }
+
+    /// The body with every definition renamed to its qualified name. Fails if the
+    /// module exports a name it does not define.
+    pub fn scoped_body(&self) -> Result<Vec<AstNode>, SutraError> {
+        let defined = self.defined_names();
+        if let Some(missing) = self.exports.iter().find(|name| !defined.contains(name)) {
+            return Err(malformed(
+                &format!(
+                    "module {}: exports '{missing}', which it does not define",
+                    self.name
+                ),
+                self.span,
+            ));
+        }
+        let mut scope = Scope {
+            renames: defined
+                .iter()
+                .map(|name| (name.clone(), self.qualified_name(name)))
+                .collect(),
+        };
+        Ok(self
+            .body
+            .iter()
+            .map(|form| {
+                let Ok(form) = scope.rewrite_node(form.clone());
+                form
+            })
+            .collect())
+    }
+}
+
+/// The name a module's private definition is stored under.
+pub fn private_name(module: &str, name: &str) -> String {
+    format!("{module}{PRIVATE_MARKER}{name}")
+}
+
+/// If `symbol` is `module/name` for a private definition, the module and the name.
+/// `is_defined` tells whether a private name is defined.
+pub fn private_access(symbol: &str, is_defined: impl Fn(&str) -> bool) -> Option<(String, String)> {
+    let (module, name) = symbol.rsplit_once('/')?;
+    if module.is_empty() || name.is_empty() || !is_defined(&private_name(module, name)) {
+        return None;
+    }
+    Some((module.to_string(), name.to_string()))
+}
+
+/// The error for using a module's private definition from outside it.
+pub(super) fn private_symbol_error(module: &str, name: &str, span: Span) -> SutraError {
+    create_error(
+        ErrorKind::PrivateSymbol {
+            symbol: name.to_string(),
+            module: module.to_string(),
+        },
+        span,
+    )
+}
+
+/// Whether `node` is a module form.
+pub(super) fn is_module(node: &AstNode) -> bool {
+    is_call(node, MODULE)
+}
+
+/// Whether `node` is a module declaration with no body, which takes the rest of its
+/// file as its body.
+fn is_header(node: &AstNode) -> bool {
+    let Expr::List(items, _) = &*node.value else {
+        return false;
+    };
+    match items.as_slice() {
+        [head, _] => is_symbol(head, MODULE),
+        [head, _, exports] => is_symbol(head, MODULE) && is_call(exports, EXPORT),
+        _ => false,
+    }
+}
+
+/// Moves the forms after each bodiless module declaration in a `(do ...)` program
+/// into that declaration.
+pub(crate) fn gather_headers(ast: AstNode) -> AstNode {
+    let Expr::List(items, span) = &*ast.value else {
+        return ast;
+    };
+    if !items.first().is_some_and(|head| is_symbol(head, "do")) || !items.iter().any(is_header) {
+        return ast;
+    }
+    list(gather_forms(items.to_vec(), *span), *span)
+}
+
+/// Moves the forms after each bodiless module declaration into that declaration.
+pub(crate) fn gather_forms(forms: Vec<AstNode>, span: Span) -> Vec<AstNode> {
+    let mut gathered = Vec::new();
+    let mut module: Option<Vec<AstNode>> = None;
+    for form in forms {
+        match &*form.value {
+            Expr::List(header, _) if is_header(&form) => {
+                gathered.extend(
+                    module
+                        .replace(header.to_vec())
+                        .map(|module| list(module, span)),
+                );
+            }
+            _ => match &mut module {
+                Some(module) => module.push(form),
+                None => gathered.push(form),
+            },
+        }
+    }
+    gathered.extend(module.map(|module| list(module, span)));
+    gathered
+}
+
+/// Expands `(module ...)` into a `(do ...)` of its scoped body, expanding the body's
+/// forms with `expand` first.
+pub(super) fn expand_module(
+    call: &AstNode,
+    mut expand: impl FnMut(AstNode) -> Result<AstNode, SutraError>,
+) -> Result<AstNode, SutraError> {
+    let mut module = ModuleDefinition::parse(call)?;
+    module.body = module
+        .body
+        .into_iter()
+        .map(&mut expand)
+        .collect::<Result<_, _>>()?;
+    Ok(call_node("do", module.scoped_body()?, module.span))
+}
+
+/// Every module in `ast`, bodiless declarations taking the forms after them, read
+/// without expanding them.
+pub fn module_definitions(ast: &AstNode) -> Vec<Result<ModuleDefinition, SutraError>> {
+    super::calls_of(&gather_headers(ast.clone()), MODULE)
+        .into_iter()
+        .map(ModuleDefinition::parse)
+        .collect()
+}
+
+// ============================================================================
+// RENAMING
+// ============================================================================
+
+/// Renames a module's definitions, leaving names hidden by bindings alone.
+struct Scope {
+    renames: HashMap<String, String>,
+}
+
+impl Scope {
+    /// This scope without the names in `bound`.
+    fn without(&self, bound: &[String]) -> Scope {
+        let mut renames = self.renames.clone();
+        for name in bound {
+            renames.remove(name);
+        }
+        Scope { renames }
+    }
+
+    fn rename(&self, name: &str) -> String {
+        self.renames
+            .get(name)
+            .cloned()
+            .unwrap_or_else(|| name.to_string())
+    }
+
+    /// Rewrites `forms` with the names in `bound` hidden.
+    fn rewrite_hidden(&self, bound: &[String], forms: &[AstNode]) -> Vec<AstNode> {
+        let mut inner = self.without(bound);
+        forms
+            .iter()
+            .map(|form| {
+                let Ok(form) = inner.rewrite_node(form.clone());
+                form
+            })
+            .collect()
+    }
+
+    /// Rewrites a `(define|lambda params body...)` form.
+    fn rewrite_function(&self, items: &[AstNode], span: Span, named: bool) -> AstNode {
+        let (params, bound) = match &*items[1].value {
+            Expr::ParamList(params) => {
+                let mut required = params.required.clone();
+                let mut bound = required.clone();
+                bound.extend(params.rest.clone());
+                if named && !required.is_empty() {
+                    required[0] = self.rename(&required[0]);
+                    bound.remove(0);
+                }
+                let params = ParamList {
+                    required,
+                    rest: params.rest.clone(),
+                    span: params.span,
+                };
+                (node(Expr::ParamList(params), items[1].span), bound)
+            }
+            Expr::List(signature, list_span) => {
+                let params = if named {
+                    signature.get(1..).unwrap_or_default()
+                } else {
+                    signature
+                };
+                let bound: Vec<String> = params.iter().filter_map(symbol_name).collect();
+                let mut signature = signature.clone();
+                if let (true, Some(Expr::Symbol(name, span))) =
+                    (named, signature.first().map(|name| &*name.value))
+                {
+                    signature[0] = node(Expr::Symbol(self.rename(name), *span), *span);
+                }
+                (
+                    node(Expr::List(signature, *list_span), items[1].span),
+                    bound,
+                )
+            }
+            _ => unreachable!("only called for parameter lists"),
+        };
+        let mut rewritten = vec![items[0].clone(), params];
+        rewritten.extend(self.rewrite_hidden(&bound, &items[2..]));
+        list(rewritten, span)
+    }
+
+    /// Rewrites a `(let ((name value)...) body...)` form.
+    fn rewrite_let(&mut self, items: &[AstNode], span: Span) -> AstNode {
+        let Expr::List(bindings, bindings_span) = &*items[1].value else {
+            unreachable!("only called for binding lists");
+        };
+        let mut bound = Vec::new();
+        let mut rewritten_bindings = Vec::new();
+        for binding in bindings {
+            let Expr::List(pair, pair_span) = &*binding.value else {
+                rewritten_bindings.push(binding.clone());
+                continue;
+            };
+            let mut pair = pair.clone();
+            bound.extend(pair.first().and_then(symbol_name));
+            for value in pair.iter_mut().skip(1) {
+                let Ok(renamed) = self.rewrite_node(value.clone());
+                *value = renamed;
+            }
+            rewritten_bindings.push(list(pair.to_vec(), *pair_span));
+        }
+        let mut rewritten = vec![items[0].clone(), list(rewritten_bindings, *bindings_span)];
+        rewritten.extend(self.rewrite_hidden(&bound, &items[2..]));
+        list(rewritten, span)
+    }
+}
+
+impl Rewriter for Scope {
+    type Error = Infallible;
+
+    fn rewrite_node(&mut self, node: AstNode) -> Result<AstNode, Infallible> {
+        match &*node.value {
+            Expr::Symbol(name, span) if self.renames.contains_key(name) => Ok(Spanned {
+                value: Expr::Symbol(self.rename(name), *span).into(),
+                span: node.span,
+            }),
+            Expr::List(items, span) if items.len() >= 2 => {
+                let is_signature = matches!(&*items[1].value, Expr::ParamList(_) | Expr::List(..));
+                if is_signature && is_symbol(&items[0], "define") {
+                    Ok(self.rewrite_function(items, *span, true))
+                } else if is_signature && is_symbol(&items[0], "lambda") {
+                    Ok(self.rewrite_function(items, *span, false))
+                } else if is_symbol(&items[0], "let") && matches!(&*items[1].value, Expr::List(..))
+                {
+                    Ok(self.rewrite_let(items, *span))
+                } else {
+                    rewrite_children(self, node)
+                }
+            }
+            _ => rewrite_children(self, node),
+        }
+    }
+}
+
+// ============================================================================
+// HELPERS
+// ============================================================================
+
+/// Adds the names `form` defines to `names`, looking inside `do` forms.
+fn collect_definitions(form: &AstNode, names: &mut Vec<String>) {
+    let Expr::List(items, _) = &*form.value else {
+        return;
+    };
+    let Some(head) = items.first() else {
+        return;
+    };
+    if is_symbol(head, "do") {
+        for item in &items[1..] {
+            collect_definitions(item, names);
+        }
+        return;
+    }
+    if !is_symbol(head, "define") {
+        return;
+    }
+    let name = match items.get(1).map(|target| &*target.value) {
+        Some(Expr::Symbol(name, _)) => Some(name.clone()),
+        Some(Expr::ParamList(params)) => params.required.first().cloned(),
+        Some(Expr::List(signature, _)) => signature.first().and_then(symbol_name),
+        _ => None,
+    };
+    if let Some(name) = name.filter(|name| !names.contains(name)) {
+        names.push(name);
+    }
+}
+
+/// Reads an `(export name...)` list.
+fn export_list(node: &AstNode) -> Result<Vec<String>, SutraError> {
+    let Expr::List(items, _) = &*node.value else {
+        unreachable!("only called for export lists");
+    };
+    items[1..]
+        .iter()
+        .map(|item| {
+            symbol_name(item).ok_or_else(|| malformed("module export: expected symbols", item.span))
+        })
+        .collect()
+}
+
+fn symbol_name(node: &AstNode) -> Option<String> {
+    match &*node.value {
+        Expr::Symbol(name, _) => Some(name.clone()),
+        _ => None,
+    }
+}
+
+fn is_symbol(node: &AstNode, name: &str) -> bool {
+    matches!(&*node.value, Expr::Symbol(symbol, _) if symbol == name)
+}
+
+fn is_call(node: &AstNode, head: &str) -> bool {
+    matches!(&*node.value, Expr::List(items, _) if items.first().is_some_and(|first| is_symbol(first, head)))
+}
+
+fn node(expr: Expr, span: Span) -> AstNode {
+    Spanned {
+        value: expr.into(),
+        span,
+    }
+}
+
+fn list(items: Vec<AstNode>, span: Span) -> AstNode {
+    node(Expr::List(items.into(), span), span)
+}
+
+fn malformed(construct: &str, span: Span) -> SutraError {
+    create_error(
+        ErrorKind::MalformedConstruct {
+            construct: construct.to_string(),
+        },
+        span,
+    )
+}
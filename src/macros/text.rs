@@ -0,0 +1,204 @@
+//! The `(text ...)` template macro.
+//!
+//! `(text "You find [a rusty|an old] {item}.")` is desugared at expansion time, so
+//! markup is only recognised in string literal arguments:
+//! - `[a|b|c]` picks one alternative at random from the world's seeded PRNG
+//! - `[if <cond> <then> <else>]` is evaluated like `if`, converted with `str`
+//! - `[[` is a literal `[`; a `]` outside a fragment is literal
+//!
+//! `{...}` interpolation is left in place for ordinary string expansion, so it also
+//! works inside alternatives. Non-string arguments are converted with `str`.
+
+use std::mem;
+
+use super::{
+    call_node, create_error, extract_args_from_call, find_closing_brace, parser, relocate,
+};
+use crate::{
+    errors::{ErrorKind, SourceContext, SutraError},
+    prelude::*,
+};
+
+/// Expands `(text part ...)` into a `(core/str+ ...)` call over the rendered parts.
+pub(super) fn expand_text(call: &AstNode) -> Result<AstNode, SutraError> {
+    let (args, span) = extract_args_from_call(call)?;
+    let mut parts = Vec::new();
+    for arg in &args {
+        match &*arg.value {
+            Expr::String(template, _, false) => parts.extend(parse_template(template, arg.span)?),
+            _ => parts.push(call_node("str", vec![arg.clone()], arg.span)),
+        }
+    }
+    Ok(concat(parts, span))
+}
+
+/// Splits a template into literal text and fragment nodes.
+fn parse_template(text: &str, span: Span) -> Result<Vec<AstNode>, SutraError> {
+    let mut parts = Vec::new();
+    let mut literal = String::new();
+    let mut index = 0;
+
+    while let Some(ch) = text[index..].chars().next() {
+        let rest = &text[index..];
+        if rest.starts_with("[[") {
+            literal.push('[');
+            index += 2;
+        } else if rest.starts_with("{{") {
+            literal.push_str("{{");
+            index += 2;
+        } else if ch == '{' {
+            // Keep interpolations whole so brackets inside them are not markup.
+            let end = find_closing_brace(text, index + 1).map_or(text.len(), |end| end + 1);
+            literal.push_str(&text[index..end]);
+            index = end;
+        } else if ch == '[' {
+            let body_start = index + 1;
+            let Some(end) =
+                find_closing_bracket(text, body_start, is_conditional(&text[body_start..]))
+            else {
+                return Err(malformed("text template: unclosed '['", span));
+            };
+            if !literal.is_empty() {
+                parts.push(string_node(mem::take(&mut literal), span));
+            }
+            parts.push(fragment(&text[body_start..end], span)?);
+            index = end + 1;
+        } else {
+            literal.push(ch);
+            index += ch.len_utf8();
+        }
+    }
+
+    if !literal.is_empty() {
+        parts.push(string_node(literal, span));
+    }
+    Ok(parts)
+}
+
+/// Builds the node for the body of one `[...]` fragment.
+fn fragment(body: &str, span: Span) -> Result<AstNode, SutraError> {
+    if is_conditional(body) {
+        return conditional(body, span);
+    }
+
+    let alternatives = split_alternatives(body)
+        .into_iter()
+        .map(|alternative| parse_template(alternative, span).map(|parts| concat(parts, span)))
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(call_node("text/pick", alternatives, span))
+}
+
+/// Parses `if <cond> <then> <else>` as code and wraps it in `str`. String literal
+/// branches are themselves templates.
+fn conditional(body: &str, span: Span) -> Result<AstNode, SutraError> {
+    let code = format!("({body})");
+    let parsed = parser::parse(&code, SourceContext::from_file("text", &code))
+        .map_err(|_| malformed(&format!("text template fragment '[{body}]'"), span))?;
+    let [expr] = parsed.as_slice() else {
+        return Err(malformed(
+            &format!("text template fragment '[{body}]'"),
+            span,
+        ));
+    };
+    let relocated = relocate(expr, span);
+    let Expr::List(items, _) = &*relocated.value else {
+        return Err(malformed(
+            &format!("text template fragment '[{body}]'"),
+            span,
+        ));
+    };
+
+    let mut form = AstList::with_capacity(items.len());
+    for item in items {
+        match &*item.value {
+            Expr::String(template, _, false) => {
+                form.push(concat(parse_template(template, span)?, span))
+            }
+            _ => form.push(item.clone()),
+        }
+    }
+    let form = Spanned {
+        value: Expr::List(form, span).into(),
+        span,
+    };
+    Ok(call_node("str", vec![form], span))
+}
+
+fn is_conditional(body: &str) -> bool {
+    body.strip_prefix("if")
+        .is_some_and(|rest| rest.starts_with(char::is_whitespace))
+}
+
+/// Finds the `]` closing a fragment whose body starts at `start`, skipping nested
+/// fragments and interpolations. In conditionals, string literals are skipped too.
+fn find_closing_bracket(text: &str, start: usize, code: bool) -> Option<usize> {
+    let bytes = text.as_bytes();
+    let mut depth = 0usize;
+    let mut in_string = false;
+    let mut index = start;
+
+    while index < bytes.len() {
+        match bytes[index] {
+            b'\\' if in_string => index += 1,
+            b'"' if code => in_string = !in_string,
+            _ if in_string => {}
+            b'[' if bytes.get(index + 1) == Some(&b'[') => index += 1,
+            b'[' => depth += 1,
+            b']' if depth == 0 => return Some(index),
+            b']' => depth -= 1,
+            b'{' => index = find_closing_brace(text, index + 1)?,
+            _ => {}
+        }
+        index += 1;
+    }
+    None
+}
+
+/// Splits a fragment body on `|` separators that are not inside a nested group.
+fn split_alternatives(body: &str) -> Vec<&str> {
+    let bytes = body.as_bytes();
+    let mut alternatives = Vec::new();
+    let mut segment_start = 0;
+    let mut index = 0;
+
+    while index < bytes.len() {
+        match bytes[index] {
+            b'[' if bytes.get(index + 1) == Some(&b'[') => index += 1,
+            b'[' => index = find_closing_bracket(body, index + 1, false).unwrap_or(bytes.len()),
+            b'{' => index = find_closing_brace(body, index + 1).unwrap_or(bytes.len()),
+            b'|' => {
+                alternatives.push(&body[segment_start..index]);
+                segment_start = index + 1;
+            }
+            _ => {}
+        }
+        index += 1;
+    }
+
+    alternatives.push(&body[segment_start..]);
+    alternatives
+}
+
+fn concat(mut parts: Vec<AstNode>, span: Span) -> AstNode {
+    match parts.len() {
+        0 => string_node(String::new(), span),
+        1 => parts.remove(0),
+        _ => call_node("core/str+", parts, span),
+    }
+}
+
+fn string_node(text: String, span: Span) -> AstNode {
+    Spanned {
+        value: Expr::String(text, span, false).into(),
+        span,
+    }
+}
+
+fn malformed(construct: &str, span: Span) -> SutraError {
+    create_error(
+        ErrorKind::MalformedConstruct {
+            construct: construct.to_string(),
+        },
+        span,
+    )
+}
@@ -0,0 +1,169 @@
+//! Constant Folding
+//!
+//! An optional pass over macro-expanded ASTs, run before evaluation. A call of a pure
+//! atom whose arguments are all number, string or bool literals is evaluated once and
+//! replaced by its result, and an `(if <literal> then else)` is replaced by the branch
+//! the literal selects. Folding works bottom-up, so `(if (gt? 2 1) a b)` becomes `a`.
+//!
+//! The pass never changes what a program does. A call whose evaluation fails, such as
+//! `(error "boom")` or `(car 1)`, is left in place to fail when it runs, and so is one
+//! whose result is not a literal (a list, a map) or is a string longer than
+//! [`MAX_FOLDED_STRING_BYTES`]. Calls are folded with no room for a collection, so a
+//! large `(range ...)` in a function that never runs is refused rather than built.
+//! Nothing is folded under a name the program or the world binds itself, since
+//! `(define (+ a b) ...)` makes `+` no longer the atom.
+
+use std::collections::BTreeSet;
+
+use crate::{
+    atoms::{AtomKind, EngineOutputBuffer, SharedOutput},
+    errors::SourceContext,
+    prelude::*,
+    runtime::{evaluate_ast_node, ResourceLimits},
+    semantic_validation::bound_names,
+    syntax::map_nodes,
+};
+
+/// Longest string a call may be folded into.
+const MAX_FOLDED_STRING_BYTES: usize = 4096;
+
+/// Folds constant calls of pure atoms in `ast` and prunes `if` branches decided by a
+/// literal condition. Calls are evaluated against `world` under `limits`.
+pub fn fold_constants(ast: AstNode, world: &CanonicalWorld, limits: ResourceLimits) -> AstNode {
+    let mut shadowed = bound_names(&ast);
+    shadowed.extend(world.read().defs.keys().cloned());
+    let mut folder = Folder {
+        world,
+        limits,
+        shadowed,
+    };
+    map_nodes(ast, |node| folder.fold(node))
+}
+
+struct Folder<'w> {
+    world: &'w CanonicalWorld,
+    limits: ResourceLimits,
+    /// Names bound by the program or the world, which may not mean the atom.
+    shadowed: BTreeSet<String>,
+}
+
+impl Folder<'_> {
+    fn fold(&mut self, node: AstNode) -> AstNode {
+        let Expr::List(items, _) = &*node.value else {
+            return node;
+        };
+        let Some(Expr::Symbol(head, _)) = items.first().map(|head| &*head.value) else {
+            return node;
+        };
+        if self.shadowed.contains(head) {
+            return node;
+        }
+        if let [_, condition, then_branch, else_branch] = items.as_slice() {
+            if head == "if" {
+                return match literal_value(condition) {
+                    Some(value) if value.is_truthy() => then_branch.clone(),
+                    Some(_) => else_branch.clone(),
+                    None => node,
+                };
+            }
+        }
+        if !self.is_pure(head) || !items[1..].iter().all(|arg| literal_value(arg).is_some()) {
+            return node;
+        }
+        self.evaluate(&node).unwrap_or(node)
+    }
+
+    fn is_pure(&self, name: &str) -> bool {
+        self.world
+            .read()
+            .atom_specs
+            .get(name)
+            .is_some_and(|spec| spec.kind == AtomKind::Pure)
+    }
+
+    /// The literal `call` evaluates to, if it succeeds and gives one.
+    fn evaluate(&self, call: &AstNode) -> Option<AstNode> {
+        let mut context = EvaluationContext::new(
+            self.world.clone(),
+            SharedOutput::new(EngineOutputBuffer::new()),
+            SourceContext::from_file("constant folding", ""),
+        );
+        // Only a literal result is kept, so there is no room for a list or map and
+        // little for a string; a call that needs more is left to run when it is reached
+        context.limits = ResourceLimits {
+            max_collection_len: Some(0),
+            max_string_bytes: Some(
+                (self.limits.max_string_bytes).map_or(MAX_FOLDED_STRING_BYTES, |limit| {
+                    limit.min(MAX_FOLDED_STRING_BYTES)
+                }),
+            ),
+            ..self.limits
+        };
+        let value = evaluate_ast_node(call, &mut context).ok()?.value;
+        literal_node(value, call.span)
+    }
+}
+
+/// The value of a number, string or bool literal.
+fn literal_value(node: &AstNode) -> Option<Value> {
+    match &*node.value {
+        Expr::Number(n, _) => Some(Value::Number(*n)),
+        Expr::String(s, ..) => Some(Value::String(s.clone())),
+        Expr::Bool(b, _) => Some(Value::Bool(*b)),
+        _ => None,
+    }
+}
+
+/// `value` as a literal node, if it is a number, string or bool.
+fn literal_node(value: Value, span: Span) -> Option<AstNode> {
+    let expr = match value {
+        Value::Number(n) => Expr::Number(n, span),
+        Value::String(s) => Expr::String(s, span, true),
+        Value::Bool(b) => Expr::Bool(b, span),
+        _ => return None,
+    };
+    Some(Spanned {
+        value: expr.into(),
+        span,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{atoms::build_canonical_world, parser};
+
+    fn fold(source: &str) -> String {
+        let ast = parser::wrap_in_do(
+            parser::parse(source, SourceContext::from_file("test", source)).unwrap(),
+        );
+        let world = build_canonical_world();
+        fold_constants(ast, &world, ResourceLimits::default())
+            .value
+            .pretty()
+    }
+
+    #[test]
+    fn folds_pure_calls_and_decided_branches() {
+        assert_eq!(fold("(+ 1 (* 2 3))"), "7");
+        assert_eq!(
+            fold("(if (gt? 2 1) (println \"a\") (println \"b\"))"),
+            "(println \"a\")"
+        );
+        assert_eq!(fold("(str+ \"a\" \"b\")"), "\"ab\"");
+    }
+
+    #[test]
+    fn leaves_failing_stateful_and_shadowed_calls() {
+        assert_eq!(fold("(error \"boom\")"), "(error \"boom\")");
+        assert_eq!(fold("(car 1)"), "(car 1)");
+        assert_eq!(fold("(rand)"), "(rand)");
+        assert_eq!(fold("(list 1 2)"), "(list 1 2)");
+        assert_eq!(
+            fold("(define (f) (len (range 0 1000000000000)))"),
+            "(define (f) (len (range 0 1000000000000)))"
+        );
+        assert_eq!(fold("(str-repeat \"a\" 5000)"), "(str-repeat \"a\" 5000)");
+        assert!(fold("(define (+ a b) a) (+ 1 2)").contains("(+ 1 2)"));
+    }
+}
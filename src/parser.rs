@@ -3,28 +3,47 @@
 //! Converts Sutra source code into Abstract Syntax Tree nodes with source location tracking.
 //! This parser is purely syntactic - no semantic analysis or type checking.
 
-use crate::errors::{to_source_span, ErrorKind, SourceContext, SutraError};
-use crate::{prelude::*, syntax::ParamList};
+use crate::errors::{line_and_column, to_source_span, ErrorKind, SourceContext, SutraError};
+use crate::{prelude::*, syntax::ParamList, telemetry};
 use pest::{error::Error, iterators::Pair, Parser};
 use pest_derive::Parser;
+use smallvec::smallvec;
 
 #[derive(Parser)]
 #[grammar = "grammar/grammar.pest"]
 struct SutraParser;
 
+/// How deeply forms may nest in a script, counting brackets and quotes. The stages
+/// after parsing walk the tree recursively, so a deeper script is refused here
+/// rather than left to exhaust the stack.
+pub const MAX_NESTING: usize = 256;
+
+/// Stack pest is given to parse a script, on a new segment if less is left.
+const PARSE_STACK: usize = 4 << 20;
+
 // ============================================================================
 // PUBLIC API
 // ============================================================================
 
 /// Parse Sutra source code into AST nodes
 pub fn parse(source_text: &str, source_context: SourceContext) -> Result<Vec<AstNode>, SutraError> {
-    let pairs = SutraParser::parse(Rule::program, source_text)
-        .map_err(|e| parse_error(e, &source_context))?;
+    let _stage = telemetry::stage("parse", &source_context);
+    if let Some(span) = too_deep(source_text) {
+        let kind = ErrorKind::MalformedConstruct {
+            construct: format!("expression nested more than {MAX_NESTING} deep"),
+        };
+        return Err(make_error(&source_context, kind, span));
+    }
+    let pairs = stacker::maybe_grow(PARSE_STACK, PARSE_STACK, || {
+        SutraParser::parse(Rule::program, source_text)
+    })
+    .map_err(|e| parse_error(e, source_text, &source_context))?;
 
-    let program = pairs.peek().unwrap(); // pest guarantees program rule exists
+    let Some(program) = pairs.peek() else {
+        return Ok(Vec::new());
+    };
 
-    program
-        .into_inner()
+    children(program)
         .filter(|p| p.as_rule() != Rule::EOI)
         .map(|p| build_node(p, &source_context))
         .collect()
@@ -32,39 +51,203 @@ pub fn parse(source_text: &str, source_context: SourceContext) -> Result<Vec<Ast
 
 /// Wrap multiple AST nodes in a (do ...) form if needed
 pub fn wrap_in_do(nodes: Vec<AstNode>) -> AstNode {
-    match nodes.len() {
-        0 => {
-            let span = Span { start: 0, end: 0 };
-            Spanned {
-                value: Expr::List(vec![], span).into(),
-                span,
-            }
-        }
-        1 => nodes.into_iter().next().unwrap(),
-        _ => {
-            let span = calculate_span(&nodes);
-            let do_symbol = Spanned {
-                value: Expr::Symbol("do".to_string(), span).into(),
-                span,
-            };
-            let mut items = Vec::with_capacity(nodes.len() + 1);
-            items.push(do_symbol);
-            items.extend(nodes);
-            Spanned {
-                value: Expr::List(items, span).into(),
-                span,
+    let nodes = match <[AstNode; 1]>::try_from(nodes) {
+        Ok([node]) => return node,
+        Err(nodes) => nodes,
+    };
+    let (Some(first), Some(last)) = (nodes.first(), nodes.last()) else {
+        let span = Span { start: 0, end: 0 };
+        return Spanned {
+            value: Expr::List(AstList::new(), span).into(),
+            span,
+        };
+    };
+    let span = Span {
+        start: first.span.start,
+        end: last.span.end,
+    };
+    let do_symbol = Spanned {
+        value: Expr::Symbol("do".to_string(), span).into(),
+        span,
+    };
+    let mut items = AstList::with_capacity(nodes.len() + 1);
+    items.push(do_symbol);
+    items.extend(nodes);
+    Spanned {
+        value: Expr::List(items, span).into(),
+        span,
+    }
+}
+
+/// A comment, as written in the source.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Comment {
+    pub text: String,
+    pub span: Span,
+}
+
+/// Every comment in `source_text`, in order: `;` line comments, `#| |#` block
+/// comments, and `#;` datum comments together with the form they skip. The source
+/// is expected to parse.
+pub fn comments(source_text: &str) -> Vec<Comment> {
+    let mut found = Vec::new();
+    let mut pos = 0;
+    while let Some(ch) = source_text[pos..].chars().next() {
+        let rest = &source_text[pos..];
+        let rule = if rest.starts_with(';') {
+            Some(Rule::line_comment)
+        } else if rest.starts_with("#|") {
+            Some(Rule::block_comment)
+        } else if rest.starts_with("#;") {
+            Some(Rule::datum_comment)
+        } else if rest.starts_with('"') || rest.starts_with("#\"") {
+            Some(Rule::string)
+        } else {
+            None
+        };
+        let matched = rule.and_then(|rule| {
+            let pair = SutraParser::parse(rule, rest).ok()?.next()?;
+            Some((rule, pair.as_str()))
+        });
+        match matched {
+            Some((rule, text)) => {
+                if rule != Rule::string {
+                    found.push(Comment {
+                        text: text.trim_end().to_string(),
+                        span: Span {
+                            start: pos,
+                            end: pos + text.len(),
+                        },
+                    });
+                }
+                pos += text.len();
             }
+            None => pos += ch.len_utf8(),
         }
     }
+    found
 }
 
-fn calculate_span(nodes: &[AstNode]) -> Span {
-    if nodes.is_empty() {
-        return Span { start: 0, end: 0 };
+/// The doc comment of the form starting at `offset`: the `;;;` comments on the
+/// lines directly above it, each alone on its line, with the markers and one space
+/// after them removed. `comments` are the [`comments`] of `source_text`.
+pub fn doc_comment(source_text: &str, comments: &[Comment], offset: usize) -> Option<String> {
+    let mut lines = Vec::new();
+    let mut next = offset;
+    for comment in comments.iter().rev().skip_while(|c| c.span.end > offset) {
+        let between = &source_text[comment.span.end..next];
+        let own_line = between.trim().is_empty() && between.matches('\n').count() == 1;
+        let Some(text) = comment.text.strip_prefix(";;;").filter(|_| own_line) else {
+            break;
+        };
+        lines.push(text.strip_prefix(' ').unwrap_or(text));
+        next = comment.span.start;
     }
-    Span {
-        start: nodes.first().unwrap().span.start,
-        end: nodes.last().unwrap().span.end,
+    let line_start = source_text[..next].rfind('\n').map_or(0, |i| i + 1);
+    if !source_text[line_start..next].trim().is_empty() {
+        lines.pop();
+    }
+    lines.reverse();
+    let doc = lines.join("\n").trim().to_string();
+    (!doc.is_empty()).then_some(doc)
+}
+
+/// Formats a script one top-level form per line, keeping its comments.
+pub fn format(source_text: &str, source_context: SourceContext) -> Result<String, SutraError> {
+    let nodes = parse(source_text, source_context)?;
+    let mut layout = Layout {
+        comments: comments(source_text),
+        next: 0,
+    };
+    let mut lines = Vec::new();
+    for node in &nodes {
+        lines.extend(layout.comments_before(node.span.start));
+        lines.push(layout.node(node, 0));
+    }
+    lines.extend(layout.comments_before(source_text.len()));
+    Ok(lines.join("\n"))
+}
+
+/// Lays out forms with the comments found among them, taking comments in source
+/// order.
+struct Layout {
+    comments: Vec<Comment>,
+    next: usize,
+}
+
+impl Layout {
+    /// Takes the comments that start before `offset`.
+    fn comments_before(&mut self, offset: usize) -> Vec<String> {
+        let first = self.next;
+        while self
+            .comments
+            .get(self.next)
+            .is_some_and(|comment| comment.span.start < offset)
+        {
+            self.next += 1;
+        }
+        self.comments[first..self.next]
+            .iter()
+            .map(|comment| comment.text.clone())
+            .collect()
+    }
+
+    /// Lays out `node` starting at column `indent`. A form that holds comments is
+    /// split over lines, one per element, so a line comment cannot swallow what
+    /// follows it.
+    fn node(&mut self, node: &AstNode, indent: usize) -> String {
+        let holds_comment = self
+            .comments
+            .get(self.next)
+            .is_some_and(|comment| comment.span.start < node.span.end);
+        if !holds_comment {
+            return node.value.pretty();
+        }
+        match &*node.value {
+            Expr::Quote(quoted, _) => format!("'{}", self.node(quoted, indent + 1)),
+            Expr::List(items, span) => self.list(items, *span, indent),
+            // Nothing else can hold a comment in place, so its comments go first.
+            _ => {
+                let mut lines = self.comments_before(node.span.end);
+                lines.push(node.value.pretty());
+                lines.join(&format!("\n{}", " ".repeat(indent)))
+            }
+        }
+    }
+
+    fn list(&mut self, items: &[AstNode], span: Span, indent: usize) -> String {
+        let mut pieces: Vec<String> = Vec::new();
+        for item in items {
+            // `lambda` and `define` heads are made up and span the whole form.
+            if item.span == span {
+                pieces.push(item.value.pretty());
+                continue;
+            }
+            pieces.extend(self.comments_before(item.span.start));
+            let column = if pieces.is_empty() {
+                indent + 1
+            } else {
+                indent + 2
+            };
+            pieces.push(self.node(item, column));
+        }
+        pieces.extend(self.comments_before(span.end));
+
+        let mut text = String::from("(");
+        for (i, piece) in pieces.iter().enumerate() {
+            if i > 0 {
+                text.push('\n');
+                text.push_str(&" ".repeat(indent + 2));
+            }
+            text.push_str(piece);
+        }
+        // A closing paren after a line comment would be commented out.
+        if pieces.last().is_some_and(|piece| piece.starts_with(';')) {
+            text.push('\n');
+            text.push_str(&" ".repeat(indent));
+        }
+        text.push(')');
+        text
     }
 }
 
@@ -73,12 +256,18 @@ fn calculate_span(nodes: &[AstNode]) -> Span {
 // ============================================================================
 
 fn build_node(pair: Pair<Rule>, source: &SourceContext) -> Result<AstNode, SutraError> {
+    crate::runtime::grow_stack(|| build_any_node(pair, source))
+}
+
+fn build_any_node(pair: Pair<Rule>, source: &SourceContext) -> Result<AstNode, SutraError> {
     let span = extract_span(&pair);
 
     let expr = match pair.as_rule() {
         // Skip wrapper rules - grammar should handle this directly
         Rule::expr | Rule::atom => {
-            let inner = pair.into_inner().next().unwrap();
+            let inner = children(pair)
+                .next()
+                .ok_or_else(|| missing_element_error(source, "expression", span))?;
             return build_node(inner, source);
         }
 
@@ -100,10 +289,12 @@ fn build_node(pair: Pair<Rule>, source: &SourceContext) -> Result<AstNode, Sutra
         }
 
         Rule::string => {
-            let content = unescape_string(pair.as_str())?;
-            Expr::String(content, span)
+            let (content, raw) = decode_string_literal(pair.as_str(), source, span)?;
+            Expr::String(content, span, raw)
         }
 
+        Rule::keyword => Expr::Keyword(pair.as_str()[1..].to_string(), span),
+
         Rule::symbol => {
             let text = pair.as_str();
             if text.contains('.') {
@@ -118,14 +309,13 @@ fn build_node(pair: Pair<Rule>, source: &SourceContext) -> Result<AstNode, Sutra
         }
 
         Rule::list | Rule::block => {
-            let children: Result<Vec<_>, _> =
-                pair.into_inner().map(|p| build_node(p, source)).collect();
+            let children: Result<AstList, _> =
+                children(pair).map(|p| build_node(p, source)).collect();
             Expr::List(children?, span)
         }
 
         Rule::quote => {
-            let inner = pair
-                .into_inner()
+            let inner = children(pair)
                 .next()
                 .ok_or_else(|| missing_element_error(source, "expression after quote", span))?;
             let quoted = build_node(inner, source)?;
@@ -145,8 +335,7 @@ fn build_node(pair: Pair<Rule>, source: &SourceContext) -> Result<AstNode, Sutra
         }
 
         Rule::spread_arg => {
-            let symbol_pair = pair
-                .into_inner()
+            let symbol_pair = children(pair)
                 .next()
                 .ok_or_else(|| missing_element_error(source, "symbol after spread", span))?;
             let symbol = build_node(symbol_pair, source)?;
@@ -172,12 +361,10 @@ fn build_node(pair: Pair<Rule>, source: &SourceContext) -> Result<AstNode, Sutra
 
 fn build_param_list(pair: Pair<Rule>, source: &SourceContext) -> Result<AstNode, SutraError> {
     let span = extract_span(&pair);
-    let param_items: Vec<_> = pair
-        .into_inner()
+    let items = children(pair)
         .next()
-        .unwrap() // grammar guarantees param_items exists
-        .into_inner()
-        .collect();
+        .ok_or_else(|| missing_element_error(source, "parameter list", span))?;
+    let param_items: Vec<_> = children(items).collect();
 
     let mut required = Vec::new();
     let mut rest = None;
@@ -188,7 +375,10 @@ fn build_param_list(pair: Pair<Rule>, source: &SourceContext) -> Result<AstNode,
                 required.push(item.as_str().to_string());
             }
             Rule::spread_arg if rest.is_none() => {
-                let symbol = item.into_inner().next().unwrap();
+                let item_span = extract_span(&item);
+                let symbol = children(item).next().ok_or_else(|| {
+                    missing_element_error(source, "symbol after '...'", item_span)
+                })?;
                 rest = Some(symbol.as_str().to_string());
             }
             Rule::symbol => {
@@ -229,7 +419,7 @@ fn build_special_form(
     form_name: &str,
 ) -> Result<AstNode, SutraError> {
     let span = extract_span(&pair);
-    let mut inner = pair.into_inner();
+    let mut inner = children(pair);
 
     let param_list = build_param_list(
         inner
@@ -253,7 +443,7 @@ fn build_special_form(
     };
 
     Ok(Spanned {
-        value: Expr::List(vec![form_symbol, param_list, body], span).into(),
+        value: Expr::List(smallvec![form_symbol, param_list, body], span).into(),
         span,
     })
 }
@@ -270,6 +460,13 @@ fn build_define_form(pair: Pair<Rule>, source: &SourceContext) -> Result<AstNode
 // UTILITIES
 // ============================================================================
 
+/// The inner pairs of `pair`, leaving out `#;` datum comments. The grammar skips
+/// them like other comments, but the form a datum comment holds still yields pairs.
+fn children(pair: Pair<Rule>) -> impl Iterator<Item = Pair<Rule>> {
+    pair.into_inner()
+        .filter(|p| p.as_rule() != Rule::datum_comment)
+}
+
 fn extract_span(pair: &Pair<Rule>) -> Span {
     Span {
         start: pair.as_span().start(),
@@ -303,9 +500,37 @@ fn missing_element_error(source: &SourceContext, element: &str, span: Span) -> S
     )
 }
 
-fn unescape_string(text: &str) -> Result<String, SutraError> {
-    // Remove surrounding quotes
-    let inner = &text[1..text.len() - 1];
+/// Decodes any of the three string literal forms into its content, and whether it
+/// was raw.
+///
+/// - `#"..."#` is raw: taken verbatim, braces included.
+/// - `"""..."""` may span lines; one newline directly after the opening quotes is dropped.
+/// - `"..."` is the ordinary form.
+///
+/// Escapes in the latter two are processed by [`unescape_string`].
+fn decode_string_literal(
+    text: &str,
+    source: &SourceContext,
+    span: Span,
+) -> Result<(String, bool), SutraError> {
+    if let Some(raw) = text.strip_prefix("#\"").and_then(|t| t.strip_suffix("\"#")) {
+        return Ok((raw.to_string(), true));
+    }
+    if text.len() >= 6 && text.starts_with("\"\"\"") {
+        let inner = &text[3..text.len() - 3];
+        let inner = inner
+            .strip_prefix("\r\n")
+            .or_else(|| inner.strip_prefix('\n'))
+            .unwrap_or(inner);
+        return Ok((unescape_string(inner, source, span)?, false));
+    }
+    Ok((
+        unescape_string(&text[1..text.len() - 1], source, span)?,
+        false,
+    ))
+}
+
+fn unescape_string(inner: &str, source: &SourceContext, span: Span) -> Result<String, SutraError> {
     let mut result = String::with_capacity(inner.len());
     let mut chars = inner.chars();
 
@@ -317,8 +542,10 @@ fn unescape_string(text: &str) -> Result<String, SutraError> {
         match chars.next() {
             Some('n') => result.push('\n'),
             Some('t') => result.push('\t'),
+            Some('r') => result.push('\r'),
             Some('\\') => result.push('\\'),
             Some('"') => result.push('"'),
+            Some('u') => result.push(unescape_unicode(&mut chars, source, span)?),
             Some(other) => {
                 result.push('\\');
                 result.push(other);
@@ -330,54 +557,347 @@ fn unescape_string(text: &str) -> Result<String, SutraError> {
     Ok(result)
 }
 
+/// Reads the `{XXXX}` part of a `\u{XXXX}` escape. The grammar guarantees 1-6 hex digits.
+fn unescape_unicode(
+    chars: &mut std::str::Chars,
+    source: &SourceContext,
+    span: Span,
+) -> Result<char, SutraError> {
+    let digits: String = chars.by_ref().skip(1).take_while(|c| *c != '}').collect();
+    u32::from_str_radix(&digits, 16)
+        .ok()
+        .and_then(char::from_u32)
+        .ok_or_else(|| {
+            invalid_literal_error(source, "unicode escape", &format!("\\u{{{digits}}}"), span)
+        })
+}
+
 // ============================================================================
 // ERROR HANDLING
 // ============================================================================
 
 fn make_error(source: &SourceContext, kind: ErrorKind, span: Span) -> SutraError {
-    SutraError {
+    let mut error = SutraError::new(
         kind,
-        source_info: crate::errors::SourceInfo {
-            source: source.to_named_source(),
-            primary_span: to_source_span(span),
-            phase: "parsing".to_string(),
-        },
-        diagnostic_info: crate::errors::DiagnosticInfo {
-            help: None,
-            error_code: "sutra::parse".to_string(),
-        },
-    }
+        source.to_named_source(),
+        to_source_span(span),
+        "parsing",
+    );
+    error.diagnostic_info.error_code = Some("sutra::parse".into());
+    error
 }
 
-fn parse_error(error: Error<Rule>, source: &SourceContext) -> SutraError {
-    let span = match error.location {
-        pest::error::InputLocation::Pos(pos) => Span {
-            start: pos,
-            end: pos,
-        },
-        pest::error::InputLocation::Span((start, end)) => Span { start, end },
+/// Turns a pest failure into an error in the script's terms. Delimiters are checked
+/// first: an unclosed or mismatched bracket, string or block comment usually makes
+/// pest fail far from the cause, so it is reported instead when it comes no later
+/// than where pest gave up.
+fn parse_error(error: Error<Rule>, source_text: &str, source: &SourceContext) -> SutraError {
+    let pos = match error.location {
+        pest::error::InputLocation::Pos(pos) => pos,
+        pest::error::InputLocation::Span((start, _)) => start,
     };
+    if let Some((at, kind, span)) = delimiter_problem(source_text) {
+        if at <= pos {
+            return make_error(source, kind, span);
+        }
+    }
 
-    // Simple error message improvement
-    let message = if error.to_string().contains("expected ')'") {
-        "Missing closing parenthesis"
-    } else if error.to_string().contains("expected '}'") {
-        "Missing closing brace"
-    } else if error.to_string().contains("expected '\"'") {
-        "Missing closing quote"
+    let span = Span {
+        start: pos,
+        end: pos,
+    };
+    let before = source_text[..pos].trim_end();
+    let after = &source_text[pos..];
+    let missing = if before.ends_with('\'') {
+        Some("expression after quote")
+    } else if before.ends_with("...") {
+        Some("symbol after '...'")
+    } else if before.ends_with("#;") || after.starts_with("#;") {
+        Some("expression after '#;'")
     } else {
-        "Syntax error"
+        None
     };
+    if let Some(element) = missing {
+        return make_error(
+            source,
+            ErrorKind::MissingElement {
+                element: element.to_string(),
+            },
+            span,
+        );
+    }
 
+    let mut expected: Vec<&str> = Vec::new();
+    if let pest::error::ErrorVariant::ParsingError { positives, .. } = &error.variant {
+        for rule in positives {
+            let description = describe_rule(*rule);
+            if !expected.contains(&description) {
+                expected.push(description);
+            }
+        }
+    }
+    if expected.is_empty() {
+        expected.push("valid syntax");
+    }
+    let found = match after.chars().next() {
+        Some(c) => format!("'{c}'"),
+        None => "end of input".to_string(),
+    };
     make_error(
         source,
-        ErrorKind::MalformedConstruct {
-            construct: message.to_string(),
+        ErrorKind::UnexpectedToken {
+            expected: expected.join(" or "),
+            found,
         },
         span,
     )
 }
 
+/// What pest expecting `rule` means to someone writing a script.
+fn describe_rule(rule: Rule) -> &'static str {
+    match rule {
+        Rule::EOI => "end of input",
+        Rule::param_list | Rule::param_items => "a parameter list",
+        Rule::escape_sequence | Rule::unicode_escape => "an escape sequence",
+        _ => "an expression",
+    }
+}
+
+/// The first unclosed or mismatched delimiter in `text`, if any: the offset to weigh
+/// against pest's failure, the error, and the span it points at. Strings and
+/// comments are skipped so brackets inside them do not count.
+fn delimiter_problem(text: &str) -> Option<(usize, ErrorKind, Span)> {
+    let line = |offset: usize| line_and_column(text, offset).0;
+    let mut open: Vec<(char, usize)> = Vec::new();
+    let mut i = 0;
+    while let Some(c) = text[i..].chars().next() {
+        if let Some(skipped) = skipped_length(&text[i..], || line(i)) {
+            match skipped {
+                Ok(len) => i += len,
+                Err((kind, span)) => return Some((i, kind, offset_span(span, i))),
+            }
+            continue;
+        }
+
+        let span = Span {
+            start: i,
+            end: i + 1,
+        };
+        match (c, open.last()) {
+            ('(' | '{', _) => open.push((c, i)),
+            (')', Some(('(', _))) | ('}', Some(('{', _))) => {
+                open.pop();
+            }
+            (')' | '}', Some(&(opener, at))) => {
+                let kind = ErrorKind::MismatchedDelimiter {
+                    open: opener,
+                    close: c,
+                    line: line(at),
+                    opened_at: to_source_span(Span {
+                        start: at,
+                        end: at + 1,
+                    }),
+                };
+                return Some((i, kind, span));
+            }
+            (')' | '}', None) => {
+                let kind = ErrorKind::UnexpectedToken {
+                    expected: "an expression or end of input".to_string(),
+                    found: format!("'{c}', which closes nothing"),
+                };
+                return Some((i, kind, span));
+            }
+            _ => {}
+        }
+        i += c.len_utf8();
+    }
+    open.pop().map(|(opener, at)| {
+        let kind = ErrorKind::UnclosedDelimiter {
+            delimiter: opener.to_string(),
+            line: line(at),
+        };
+        let span = Span {
+            start: at,
+            end: at + 1,
+        };
+        (text.len(), kind, span)
+    })
+}
+
+/// Where `text` nests more than [`MAX_NESTING`] deep, if it does: the bracket, quote
+/// or `#;` that goes too deep. A quote or `#;` nests the form after it one level
+/// deeper than the brackets around it. Strings and comments are skipped, and the scan
+/// stops at one that is never closed, which pest fails at.
+fn too_deep(text: &str) -> Option<Span> {
+    let mut depth: usize = 0;
+    let mut prefixes = 0;
+    let mut i = 0;
+    while let Some(c) = text[i..].chars().next() {
+        let rest = &text[i..];
+        if let Some(skipped) = skipped_length(rest, || 0) {
+            i += skipped.ok()?;
+            continue;
+        }
+        let width = match c {
+            '#' if rest.starts_with("#;") => {
+                prefixes += 1;
+                2
+            }
+            '\'' => {
+                prefixes += 1;
+                1
+            }
+            '(' | '{' => {
+                depth += 1;
+                prefixes = 0;
+                1
+            }
+            ')' | '}' => {
+                depth = depth.saturating_sub(1);
+                1
+            }
+            c if c.is_whitespace() => c.len_utf8(),
+            c => {
+                prefixes = 0;
+                c.len_utf8()
+            }
+        };
+        if depth + prefixes > MAX_NESTING {
+            return Some(Span {
+                start: i,
+                end: i + width,
+            });
+        }
+        i += width;
+    }
+    None
+}
+
+/// The length of the line comment, block comment or string literal `rest` starts
+/// with, if it starts with one. Fails as [`literal_length`] does, given the line it
+/// starts on.
+fn skipped_length(
+    rest: &str,
+    line: impl FnOnce() -> usize,
+) -> Option<Result<usize, (ErrorKind, Span)>> {
+    if rest.starts_with(';') {
+        return Some(Ok(rest.find('\n').unwrap_or(rest.len())));
+    }
+    let delimiter = ["#|", "#\"", "\"\"\"", "\""]
+        .into_iter()
+        .find(|delimiter| rest.starts_with(delimiter))?;
+    Some(literal_length(rest, delimiter, line()))
+}
+
+fn offset_span(span: Span, by: usize) -> Span {
+    Span {
+        start: span.start + by,
+        end: span.end + by,
+    }
+}
+
+/// The length of the string literal or block comment that `text` starts with;
+/// `delimiter` is how it opens and `line` the line it opens on. Fails if it is never
+/// closed or holds an escape the grammar does not accept, with a span relative to
+/// `text`.
+fn literal_length(text: &str, delimiter: &str, line: usize) -> Result<usize, (ErrorKind, Span)> {
+    let length = match delimiter {
+        "#|" => block_comment_length(text),
+        "#\"" => text[2..].find("\"#").map(|end| end + 4),
+        _ => string_length(text, delimiter),
+    };
+    let Some(length) = length else {
+        let kind = ErrorKind::UnclosedDelimiter {
+            delimiter: delimiter.to_string(),
+            line,
+        };
+        let span = Span {
+            start: 0,
+            end: delimiter.len(),
+        };
+        return Err((kind, span));
+    };
+    let escapes_checked = delimiter.starts_with('"');
+    match invalid_escape(&text[..length]).filter(|_| escapes_checked) {
+        Some(span) => Err((
+            ErrorKind::InvalidLiteral {
+                literal_type: "escape sequence".to_string(),
+                value: text[span.start..span.end].to_string(),
+            },
+            span,
+        )),
+        None => Ok(length),
+    }
+}
+
+fn block_comment_length(text: &str) -> Option<usize> {
+    let mut depth = 0;
+    let mut i = 0;
+    while i < text.len() {
+        if text[i..].starts_with("#|") {
+            depth += 1;
+            i += 2;
+        } else if text[i..].starts_with("|#") {
+            depth -= 1;
+            i += 2;
+        } else {
+            i += text[i..].chars().next()?.len_utf8();
+        }
+        if depth == 0 {
+            return Some(i);
+        }
+    }
+    None
+}
+
+/// The length of the string that `text` starts with. Quoted strings end at the line;
+/// triple-quoted ones may span lines.
+fn string_length(text: &str, quotes: &str) -> Option<usize> {
+    let body = &text[quotes.len()..];
+    let mut chars = body.char_indices();
+    while let Some((i, c)) = chars.next() {
+        if c == '\\' {
+            chars.next();
+        } else if body[i..].starts_with(quotes) {
+            return Some(quotes.len() + i + quotes.len());
+        } else if c == '\n' && quotes == "\"" {
+            return None;
+        }
+    }
+    None
+}
+
+/// The first escape in `literal` that the grammar does not accept.
+fn invalid_escape(literal: &str) -> Option<Span> {
+    let mut chars = literal.char_indices();
+    while let Some((start, c)) = chars.next() {
+        if c != '\\' {
+            continue;
+        }
+        let (_, escaped) = chars.next()?;
+        let end = start + 1 + escaped.len_utf8();
+        let digits = match escaped {
+            '"' | '\\' | 'n' | 't' | 'r' => continue,
+            'u' => literal[end..]
+                .strip_prefix('{')
+                .and_then(|rest| rest.split_once('}'))
+                .map(|(digits, _)| digits)
+                .filter(|digits| {
+                    (1..=6).contains(&digits.len()) && digits.chars().all(|d| d.is_ascii_hexdigit())
+                }),
+            _ => None,
+        };
+        match digits {
+            // Skip the braces and digits.
+            Some(digits) => {
+                chars.nth(digits.len() + 1);
+            }
+            None => return Some(Span { start, end }),
+        }
+    }
+    None
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -402,4 +922,358 @@ mod tests {
         let result = parse("(a b", SourceContext::from_file("test", "(a b"));
         assert!(result.is_err());
     }
+
+    #[test]
+    fn test_spans_after_comments() {
+        let source = "#| a\n|# (x #;(y\n z) w) #; v u";
+        let nodes = parse(source, SourceContext::from_file("test", source)).unwrap();
+        assert_eq!(nodes.len(), 2);
+        assert_eq!(
+            &source[nodes[0].span.start..nodes[0].span.end],
+            "(x #;(y\n z) w)"
+        );
+        let Expr::List(items, _) = &*nodes[0].value else {
+            panic!("expected list");
+        };
+        assert_eq!(items.len(), 2);
+        assert_eq!(&source[items[1].span.start..items[1].span.end], "w");
+        assert_eq!(&source[nodes[1].span.start..nodes[1].span.end], "u");
+    }
+
+    #[test]
+    fn test_comments_skip_strings() {
+        let source = "(f \"; no\" #| yes |#) ; tail";
+        let texts: Vec<_> = comments(source).into_iter().map(|c| c.text).collect();
+        assert_eq!(texts, vec!["#| yes |#", "; tail"]);
+    }
+
+    #[test]
+    fn test_doc_comment_is_the_run_of_triple_semicolons_above_a_form() {
+        let doc = |source: &str| {
+            let at = source.rfind("(define").unwrap();
+            doc_comment(source, &comments(source), at)
+        };
+        let documented = "; file\n\n;;; Greets someone.\n;;;\n;;;   Indented.\n(define (f) 1)";
+        assert_eq!(
+            doc(documented).as_deref(),
+            Some("Greets someone.\n\n  Indented.")
+        );
+        assert_eq!(
+            doc("  ;;; Indented too.\n  (define (f) 1)").as_deref(),
+            Some("Indented too.")
+        );
+        assert_eq!(doc(";;; Cut off.\n\n(define (f) 1)"), None);
+        assert_eq!(doc(";;; Cut off.\n; plain\n(define (f) 1)"), None);
+        assert_eq!(doc("(g) ;;; Trails g.\n(define (f) 1)"), None);
+        assert_eq!(doc("(define (f) 1)"), None);
+    }
+
+    #[test]
+    fn test_format_keeps_comments() {
+        let source = "; head\n(f 1 ; one\n 2 #;(g))\n#| tail |#\n";
+        let formatted = format(source, SourceContext::from_file("test", source)).unwrap();
+        assert_eq!(
+            formatted,
+            "; head\n(f\n  1\n  ; one\n  2\n  #;(g))\n#| tail |#"
+        );
+        let again = format(&formatted, SourceContext::from_file("test", &formatted)).unwrap();
+        assert_eq!(again, formatted);
+    }
+
+    #[test]
+    fn test_pretty_parses_back_to_the_same_program() {
+        let source = "(get player.hp) (define (f a ...rest) rest) (lambda (...xs) xs) '(a \"q\\\"\") (f ...args)";
+        let nodes = parse(source, SourceContext::from_file("test", source)).unwrap();
+        let printed: Vec<_> = nodes.iter().map(|node| node.value.pretty()).collect();
+        assert_eq!(
+            printed,
+            vec![
+                "(get player.hp)",
+                "(define (f a ...rest) rest)",
+                "(lambda (...xs) xs)",
+                "'(a \"q\\\"\")",
+                "(f ...args)"
+            ]
+        );
+        let text = printed.join(" ");
+        let again = parse(&text, SourceContext::from_file("test", &text)).unwrap();
+        for (node, reparsed) in nodes.iter().zip(&again) {
+            assert_eq!(reparsed.value.pretty(), node.value.pretty());
+            assert_eq!(reparsed.value.type_name(), node.value.type_name());
+        }
+    }
+
+    fn parse_failure(source: &str) -> SutraError {
+        parse(source, SourceContext::from_file("test", source)).unwrap_err()
+    }
+
+    #[test]
+    fn test_unclosed_bracket_names_its_line() {
+        let error = parse_failure("(define (f x)\n  (+ x 1)\n(f 2)");
+        assert_eq!(
+            error.to_string(),
+            "Parse error: unclosed '(' opened at line 1"
+        );
+        assert_eq!(error.span().unwrap().offset(), 0);
+    }
+
+    #[test]
+    fn test_mismatched_bracket_points_at_both_ends() {
+        let error = parse_failure("(a\n (b c})");
+        assert_eq!(
+            error.to_string(),
+            "Parse error: '}' does not close the '(' opened at line 2"
+        );
+        assert_eq!(error.span().unwrap().offset(), 8);
+        let labels: Vec<_> = miette::Diagnostic::labels(&error)
+            .unwrap()
+            .map(|l| l.offset())
+            .collect();
+        assert_eq!(labels, vec![8, 4]);
+    }
+
+    #[test]
+    fn test_delimiters_in_strings_and_comments_do_not_count() {
+        let error = parse_failure("(a \"(}\" ; )\n #| ) |# b");
+        assert_eq!(
+            error.to_string(),
+            "Parse error: unclosed '(' opened at line 1"
+        );
+    }
+
+    #[test]
+    fn test_unclosed_string_and_block_comment() {
+        assert_eq!(
+            parse_failure("(a)\n(println \"hi)").to_string(),
+            "Parse error: unclosed '\"' opened at line 2"
+        );
+        assert_eq!(
+            parse_failure("(+ 1 #| 2 #| 3 |# 4)").to_string(),
+            "Parse error: unclosed '#|' opened at line 1"
+        );
+    }
+
+    #[test]
+    fn test_expectation_hints() {
+        assert_eq!(
+            parse_failure("(list 1 ')").to_string(),
+            "Parse error: missing expression after quote"
+        );
+        assert_eq!(
+            parse_failure("(+ 1 2))").to_string(),
+            "Parse error: expected an expression or end of input, found ')', which closes nothing"
+        );
+        assert_eq!(
+            parse_failure("(f @)").to_string(),
+            "Parse error: expected an expression, found '@'"
+        );
+        assert_eq!(
+            parse_failure("\"a\\qb\"").to_string(),
+            "Parse error: invalid escape sequence '\\q'"
+        );
+    }
+
+    fn parse_string(source: &str) -> String {
+        let nodes = parse(source, SourceContext::from_file("test", source)).unwrap();
+        match &*nodes[0].value {
+            Expr::String(s, ..) => s.clone(),
+            other => panic!("expected string, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_string_escapes() {
+        assert_eq!(parse_string(r#""a\nb\tc\rd\\e\"f""#), "a\nb\tc\rd\\e\"f");
+        assert_eq!(parse_string(r#""\u{48}\u{1F600}""#), "H\u{1F600}");
+    }
+
+    #[test]
+    fn test_invalid_unicode_escape() {
+        let source = r#""\u{D800}""#;
+        assert!(parse(source, SourceContext::from_file("test", source)).is_err());
+    }
+
+    #[test]
+    fn test_triple_quoted_string() {
+        assert_eq!(
+            parse_string("\"\"\"\nline \"one\"\nline\\ttwo\"\"\""),
+            "line \"one\"\nline\ttwo"
+        );
+    }
+
+    #[test]
+    fn test_raw_string() {
+        assert_eq!(
+            parse_string(r##"#"C:\path\n "quoted""#"##),
+            r#"C:\path\n "quoted""#
+        );
+    }
+
+    #[test]
+    fn test_string_pretty_round_trip() {
+        use rand::{Rng, SeedableRng};
+        use rand_xoshiro::Xoshiro256PlusPlus;
+
+        const ALPHABET: &[char] = &[
+            'a',
+            'Z',
+            '0',
+            ' ',
+            '"',
+            '\\',
+            '\n',
+            '\t',
+            '\r',
+            '{',
+            '}',
+            '#',
+            ';',
+            '\u{0}',
+            '\u{1b}',
+            '\u{7f}',
+            'é',
+            '\u{1F600}',
+        ];
+        let mut rng = Xoshiro256PlusPlus::seed_from_u64(323);
+        for _ in 0..500 {
+            let len = rng.gen_range(0..16);
+            let original: String = (0..len)
+                .map(|_| ALPHABET[rng.gen_range(0..ALPHABET.len())])
+                .collect();
+            let printed = Expr::String(original.clone(), Span::default(), false).pretty();
+            let reparsed = parse_string(&printed);
+            assert_eq!(reparsed, original, "printed as {printed}");
+            let reprinted = Expr::String(reparsed, Span::default(), false).pretty();
+            assert_eq!(reprinted, printed);
+        }
+    }
+
+    /// `node` with every span, its own and its children's, set to the default.
+    fn without_spans(node: &AstNode) -> AstNode {
+        let span = Span::default();
+        let expr = match &*node.value {
+            Expr::List(items, _) => Expr::List(items.iter().map(without_spans).collect(), span),
+            Expr::Quote(inner, _) => Expr::Quote(Box::new(without_spans(inner)), span),
+            Expr::Spread(inner) => Expr::Spread(Box::new(without_spans(inner))),
+            Expr::If {
+                condition,
+                then_branch,
+                else_branch,
+                ..
+            } => Expr::If {
+                condition: Box::new(without_spans(condition)),
+                then_branch: Box::new(without_spans(then_branch)),
+                else_branch: Box::new(without_spans(else_branch)),
+                span,
+            },
+            Expr::ParamList(params) => Expr::ParamList(ParamList {
+                span,
+                ..params.clone()
+            }),
+            other => other.with_span(span),
+        };
+        Spanned {
+            value: expr.into(),
+            span,
+        }
+    }
+
+    mod round_trip {
+        use proptest::{collection::vec, option, prelude::*};
+
+        use super::*;
+
+        fn node(expr: Expr) -> AstNode {
+            Spanned {
+                value: expr.into(),
+                span: Span::default(),
+            }
+        }
+
+        /// Names that read as symbols, rather than as numbers or booleans. `define`
+        /// and `lambda` are left out: a list they head may read as one of their forms.
+        fn name() -> impl Strategy<Value = String> {
+            "[a-zA-Z_+*/<>=?!-][a-zA-Z0-9_+*/<>=?!-]{0,5}".prop_filter("reads as a symbol", |s| {
+                let number =
+                    s.len() > 1 && s.starts_with('-') && s[1..].bytes().all(|b| b.is_ascii_digit());
+                !number && !["true", "false", "define", "lambda"].contains(&s.as_str())
+            })
+        }
+
+        /// A name, or the lone `:` of type annotations.
+        fn symbol() -> impl Strategy<Value = String> {
+            prop_oneof![Just(":".to_string()), name()]
+        }
+
+        fn path() -> impl Strategy<Value = Path> {
+            (name(), vec("[a-zA-Z0-9_+*/<>=?!-]{1,4}", 1..3))
+                .prop_map(|(first, rest)| Path(std::iter::once(first).chain(rest).collect()))
+        }
+
+        fn number() -> impl Strategy<Value = f64> {
+            prop_oneof![
+                any::<i32>().prop_map(f64::from),
+                any::<f64>().prop_filter("literals are finite", |n| n.is_finite()),
+            ]
+        }
+
+        fn param_list() -> impl Strategy<Value = AstNode> {
+            (vec(symbol(), 0..4), option::of(symbol())).prop_map(|(required, rest)| {
+                node(Expr::ParamList(ParamList {
+                    required,
+                    rest,
+                    span: Span::default(),
+                }))
+            })
+        }
+
+        /// Trees of the shapes the parser builds: no `if` nodes, spreads only of
+        /// symbols and paths, and parameter lists only in `define` and `lambda` forms.
+        fn tree() -> impl Strategy<Value = AstNode> {
+            let span = Span::default();
+            let leaf = prop_oneof![
+                symbol().prop_map(move |s| node(Expr::Symbol(s, span))),
+                "[a-zA-Z0-9_.+*/<>=?!-]{1,6}".prop_map(move |k| node(Expr::Keyword(k, span))),
+                path().prop_map(move |p| node(Expr::Path(p, span))),
+                any::<String>().prop_map(move |s| node(Expr::String(s, span, false))),
+                number().prop_map(move |n| node(Expr::Number(n, span))),
+                any::<bool>().prop_map(move |b| node(Expr::Bool(b, span))),
+                symbol().prop_map(move |s| {
+                    node(Expr::Spread(Box::new(node(Expr::Symbol(s, span)))))
+                }),
+                path().prop_map(move |p| node(Expr::Spread(Box::new(node(Expr::Path(p, span)))))),
+            ];
+            leaf.prop_recursive(4, 48, 6, move |inner| {
+                prop_oneof![
+                    vec(inner.clone(), 0..6)
+                        .prop_map(move |items| node(Expr::List(items.into(), span))),
+                    inner
+                        .clone()
+                        .prop_map(move |quoted| node(Expr::Quote(Box::new(quoted), span))),
+                    (symbol(), inner.clone()).prop_map(move |(name, value)| {
+                        let head = node(Expr::Symbol("define".into(), span));
+                        let name = node(Expr::Symbol(name, span));
+                        node(Expr::List(smallvec![head, name, value], span))
+                    }),
+                    (prop_oneof!["define", "lambda"], param_list(), inner).prop_map(
+                        move |(form, params, body)| {
+                            let head = node(Expr::Symbol(form, span));
+                            node(Expr::List(smallvec![head, params, body], span))
+                        }
+                    ),
+                ]
+            })
+        }
+
+        proptest! {
+            #[test]
+            fn pretty_output_parses_back_to_the_same_tree(tree in tree()) {
+                let printed = tree.value.pretty();
+                let parsed = parse(&printed, SourceContext::from_file("test", &printed));
+                let parsed = parsed.map_err(|e| TestCaseError::fail(format!("{printed}: {e}")))?;
+                prop_assert_eq!(parsed.len(), 1, "{}", printed);
+                prop_assert_eq!(without_spans(&parsed[0]), tree, "{}", printed);
+            }
+        }
+    }
 }
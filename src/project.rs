@@ -0,0 +1,186 @@
+//! Sutra Projects
+//!
+//! A project is a directory with a `sutra.toml` manifest at its root:
+//!
+//! ```toml
+//! entry = "main.sutra"
+//! include = ["lib"]
+//! preludes = ["macros/story.sutra"]
+//! catalogs = ["locales/en.toml"]
+//! seed = 42
+//! max_depth = 500
+//! max_macro_depth = 200
+//! max_expansion_nodes = 5000000
+//! time_limit_ms = 2000
+//! tests = "tests"
+//! ```
+//!
+//! Paths are relative to the project root. `sutra run .` loads the `include` paths in
+//! the order listed (a directory contributes its `.sutra` files sorted by path), then
+//! the entry point, against one shared world.
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use serde::Deserialize;
+
+use crate::{
+    cli::{args::Settings, ExecutionPipeline, ExecutionPipelineBuilder},
+    discovery::TestDiscoverer,
+    errors::{ErrorKind, SutraError},
+};
+
+/// File name of the manifest at a project's root.
+pub const MANIFEST_FILE: &str = "sutra.toml";
+
+/// The contents of `sutra.toml`. Every field is optional.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Manifest {
+    /// Script run last, after the included files.
+    pub entry: PathBuf,
+    /// Files and directories loaded before the entry point, in order.
+    pub include: Vec<PathBuf>,
+    /// Macro files loaded before any script.
+    pub preludes: Vec<PathBuf>,
+    /// Message catalogs for `tr`.
+    pub catalogs: Vec<PathBuf>,
+    pub locale: Option<String>,
+    pub seed: Option<u64>,
+    pub max_depth: Option<usize>,
+    pub max_macro_depth: Option<usize>,
+    pub max_expansion_nodes: Option<usize>,
+    pub time_limit_ms: Option<u64>,
+    /// Directory `sutra test` searches.
+    pub tests: PathBuf,
+}
+
+impl Default for Manifest {
+    fn default() -> Self {
+        Self {
+            entry: PathBuf::from("main.sutra"),
+            include: Vec::new(),
+            preludes: Vec::new(),
+            catalogs: Vec::new(),
+            locale: None,
+            seed: None,
+            max_depth: None,
+            max_macro_depth: None,
+            max_expansion_nodes: None,
+            time_limit_ms: None,
+            tests: PathBuf::from("tests"),
+        }
+    }
+}
+
+impl Manifest {
+    pub fn from_toml(content: &str, name: &str) -> Result<Self, SutraError> {
+        toml::from_str(content)
+            .map_err(|e| project_error(name, format!("{name} is not a valid manifest ({e})")))
+    }
+}
+
+/// A project root and its manifest.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Project {
+    pub root: PathBuf,
+    pub manifest: Manifest,
+}
+
+impl Project {
+    /// The project rooted at `path`, if `path` is a directory with a manifest.
+    pub fn at(path: &Path) -> Result<Option<Self>, SutraError> {
+        if !path.join(MANIFEST_FILE).is_file() {
+            return Ok(None);
+        }
+        Self::load(path).map(Some)
+    }
+
+    /// Reads the manifest of the project rooted at `root`.
+    pub fn load(root: &Path) -> Result<Self, SutraError> {
+        let path = root.join(MANIFEST_FILE);
+        let name = path.display().to_string();
+        let content =
+            fs::read_to_string(&path).map_err(|e| project_error(&name, format!("{name} ({e})")))?;
+        Ok(Self {
+            root: root.to_path_buf(),
+            manifest: Manifest::from_toml(&content, &name)?,
+        })
+    }
+
+    /// `path` relative to the project root.
+    pub fn resolve(&self, path: &Path) -> PathBuf {
+        self.root.join(path)
+    }
+
+    /// The scripts to run, in load order: included files, then the entry point.
+    /// A file reached twice is loaded only the first time.
+    pub fn source_files(&self) -> Result<Vec<PathBuf>, SutraError> {
+        let mut files: Vec<PathBuf> = Vec::new();
+        for include in &self.manifest.include {
+            let path = self.resolve(include);
+            let found = if path.is_dir() {
+                TestDiscoverer::discover_test_files(&path)?
+            } else {
+                vec![path]
+            };
+            for file in found {
+                push_unique(&mut files, file);
+            }
+        }
+        push_unique(&mut files, self.resolve(&self.manifest.entry));
+        Ok(files)
+    }
+
+    /// The directory `sutra test` searches.
+    pub fn test_dir(&self) -> PathBuf {
+        self.resolve(&self.manifest.tests)
+    }
+
+    /// A pipeline builder with the manifest's preludes, catalogs and run settings.
+    pub fn builder(&self) -> ExecutionPipelineBuilder {
+        self.configure(ExecutionPipeline::builder())
+    }
+
+    /// Applies the manifest's preludes, catalogs and run settings on top of `builder`,
+    /// e.g. one set up from the user config.
+    pub fn configure(&self, builder: ExecutionPipelineBuilder) -> ExecutionPipelineBuilder {
+        Settings::from_project(self).configure(builder)
+    }
+}
+
+fn push_unique(files: &mut Vec<PathBuf>, file: PathBuf) {
+    if !files.contains(&file) {
+        files.push(file);
+    }
+}
+
+fn project_error(resource: &str, message: String) -> SutraError {
+    SutraError::without_source(
+        ErrorKind::GeneralValidation { message },
+        resource,
+        "project",
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_manifest_fields_default_when_absent() {
+        let manifest =
+            Manifest::from_toml("seed = 7\ninclude = [\"lib\"]\n", "sutra.toml").unwrap();
+        assert_eq!(manifest.seed, Some(7));
+        assert_eq!(manifest.include, vec![PathBuf::from("lib")]);
+        assert_eq!(manifest.entry, PathBuf::from("main.sutra"));
+        assert_eq!(manifest.tests, PathBuf::from("tests"));
+    }
+
+    #[test]
+    fn test_manifest_rejects_unknown_fields() {
+        assert!(Manifest::from_toml("entrypoint = \"a.sutra\"\n", "sutra.toml").is_err());
+    }
+}
@@ -37,13 +37,21 @@ impl ReplState {
         let output = SharedOutput::new(EngineStdoutSink);
 
         // Use the same execution logic as SutraEngine
-        use crate::{errors::SourceContext, evaluate, parser};
+        use crate::{errors::SourceContext, evaluate, parser, semantic};
 
         let source_context = SourceContext::from_file(&source_name, input);
         let execution_result = parser::parse(input, source_context.clone())
             .and_then(|ast_nodes| {
+                // Redefinitions of earlier inputs' definitions show up here
+                let warnings =
+                    semantic::check_definitions(&ast_nodes, &self.world.read(), &self.macro_env);
+                for warning in warnings {
+                    print_error(warning.with_source(&source_context));
+                }
                 let program = parser::wrap_in_do(ast_nodes);
-                self.macro_env.expand(program)
+                self.macro_env
+                    .expand(program)
+                    .map_err(|e| e.with_source(&source_context))
             })
             .and_then(|expanded| {
                 evaluate(
@@ -57,7 +65,7 @@ impl ReplState {
         match execution_result {
             Ok(result) => {
                 if !result.is_nil() {
-                    output.emit(&result.to_string(), None);
+                    output.emit(&format!("{result}\n"), None);
                 }
                 self.line_number += 1;
                 Ok(())
@@ -87,7 +95,7 @@ pub fn run_repl() {
         } else {
             print!("    -> ");
         }
-        io::stdout().flush().unwrap();
+        io::stdout().flush().ok();
 
         // Read input
         let mut line = String::new();
@@ -159,7 +167,7 @@ fn handle_repl_command(command: &str, state: &mut ReplState) -> ReplCommand {
         ":clear" | ":c" => {
             // Clear screen using ANSI escape codes
             print!("\x1B[2J\x1B[1;1H");
-            io::stdout().flush().unwrap();
+            io::stdout().flush().ok();
 
             // Reset state to clear all user-defined variables and functions
             state.world = build_canonical_world();
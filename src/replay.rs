@@ -0,0 +1,416 @@
+//! Replay Logs
+//!
+//! `sutra run --record session.log` writes every input that could make a run turn out
+//! differently: the PRNG seed and each number drawn from it, the answer to each
+//! `choice`, each line `read-line` reads, and the script arguments. It also records a
+//! checksum of the world state before each input and after each script, so `sutra
+//! replay session.log` can rerun the session without a player and name the first
+//! point where the rerun differs from the recording.
+//!
+//! A log is JSON lines: a [`Header`] holding the seed, the arguments and the source
+//! of every script run, then one [`Event`] per line in the order they happened.
+//! Events are written as they happen, so a session that crashes still leaves a log
+//! up to the crash.
+
+use std::{
+    fmt,
+    io::{self, Write},
+    sync::{Arc, Mutex, MutexGuard, PoisonError},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    atoms::{Path, World},
+    errors::{ErrorKind, SutraError},
+    prelude::{CanonicalWorld, WorldHandle},
+    syntax::escape_string,
+};
+
+/// The log format this engine writes and reads.
+pub const REPLAY_VERSION: u64 = 1;
+
+/// The first line of a replay log.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Header {
+    pub version: u64,
+    pub seed: u64,
+    pub args: Vec<String>,
+    pub files: Vec<LoggedFile>,
+    /// The function `run --entry` named, if it named one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub entry: Option<String>,
+}
+
+/// A script run in a recorded session, with its source as it was then.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LoggedFile {
+    pub name: String,
+    pub source: String,
+}
+
+/// Something a recorded session drew, was told or computed, in the order it did.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Event {
+    /// A number drawn from the world's PRNG.
+    Draw(u32),
+    /// The index of the option picked at a `choice`, or none if it went unanswered.
+    Choice(Option<usize>),
+    /// A line read by `read-line`, or none at the end of input.
+    Line(Option<String>),
+    /// The world state's [`checksum`] at a point named by `at`.
+    Checksum { at: String, hash: String },
+}
+
+impl fmt::Display for Event {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Event::Draw(value) => write!(f, "random draw {value}"),
+            Event::Choice(Some(index)) => write!(f, "choice of option {}", index + 1),
+            Event::Choice(None) => write!(f, "unanswered choice"),
+            Event::Line(Some(line)) => write!(f, "input line {}", escape_string(line)),
+            Event::Line(None) => write!(f, "end of input"),
+            Event::Checksum { at, hash } => write!(f, "world checksum {hash} {at}"),
+        }
+    }
+}
+
+/// A short checksum of the world's state: the start of its [`World::hash`].
+pub fn checksum(world: &World) -> String {
+    let mut hash = world.hash(&Path(Vec::new()));
+    hash.truncate(16);
+    hash
+}
+
+/// Where a replay first did something other than what the log recorded.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Divergence {
+    /// 1-based position of the event among the log's events.
+    pub event: usize,
+    /// The recorded event, or `None` if the log had ended.
+    pub expected: Option<Event>,
+    /// What the replay did instead.
+    pub actual: String,
+}
+
+/// The recording or replaying a world's inputs go through.
+pub enum Session {
+    Recording {
+        out: Box<dyn Write + Send>,
+        /// The first failure to write the log.
+        error: Option<io::Error>,
+    },
+    Replaying {
+        events: Vec<Event>,
+        next: usize,
+        divergence: Option<Divergence>,
+    },
+}
+
+impl fmt::Debug for Session {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Session::Recording { .. } => write!(f, "Session::Recording"),
+            Session::Replaying { next, .. } => write!(f, "Session::Replaying(at {next})"),
+        }
+    }
+}
+
+impl Session {
+    /// Starts a log on `out` by writing `header`.
+    pub fn record(mut out: Box<dyn Write + Send>, header: &Header) -> io::Result<Self> {
+        serde_json::to_writer(&mut out, header)?;
+        writeln!(out)?;
+        Ok(Session::Recording { out, error: None })
+    }
+
+    /// Reads a log, returning its header and a session that replays its events.
+    pub fn replay(log: &str) -> Result<(Header, Self), String> {
+        let mut lines = log
+            .lines()
+            .enumerate()
+            .filter(|(_, line)| !line.trim().is_empty());
+        let (_, first) = lines.next().ok_or("it is empty")?;
+        let header: Header =
+            serde_json::from_str(first).map_err(|e| format!("its header is invalid: {e}"))?;
+        if header.version != REPLAY_VERSION {
+            return Err(format!(
+                "it was written in format {}, but this engine reads only format {REPLAY_VERSION}",
+                header.version
+            ));
+        }
+        let events = lines
+            .map(|(n, line)| {
+                serde_json::from_str(line).map_err(|e| format!("line {} is invalid: {e}", n + 1))
+            })
+            .collect::<Result<_, _>>()?;
+        let session = Session::Replaying {
+            events,
+            next: 0,
+            divergence: None,
+        };
+        Ok((header, session))
+    }
+
+    /// Notes an event: writes it when recording, or checks it against the log when
+    /// replaying.
+    pub fn observe(&mut self, event: Event) {
+        match self {
+            Session::Recording { out, error } => {
+                if error.is_none() {
+                    let written = serde_json::to_writer(&mut *out, &event)
+                        .map_err(io::Error::from)
+                        .and_then(|()| writeln!(out));
+                    *error = written.err();
+                }
+            }
+            Session::Replaying { .. } => {
+                let expected = self.next_event();
+                if expected.as_ref() != Some(&event) {
+                    self.diverge(expected, event.to_string());
+                }
+            }
+        }
+    }
+
+    /// An input: the logged one when replaying, or else the one `live` gets, which
+    /// is logged when recording. `kind` describes the input, for divergences.
+    fn input(&mut self, kind: &str, live: impl FnOnce() -> Event) -> Option<Event> {
+        if let Session::Recording { .. } = self {
+            let event = live();
+            self.observe(event.clone());
+            return Some(event);
+        }
+        let expected = self.next_event();
+        let same_kind = matches!(
+            (&expected, kind),
+            (Some(Event::Choice(_)), "choice") | (Some(Event::Line(_)), "input line")
+        );
+        if same_kind {
+            return expected;
+        }
+        self.diverge(expected, format!("a request for a {kind}"));
+        None
+    }
+
+    fn next_event(&mut self) -> Option<Event> {
+        let Session::Replaying { events, next, .. } = self else {
+            return None;
+        };
+        *next += 1;
+        events.get(*next - 1).cloned()
+    }
+
+    /// Notes the current event as the first divergence, unless there already is one.
+    fn diverge(&mut self, expected: Option<Event>, actual: String) {
+        if let Session::Replaying {
+            next, divergence, ..
+        } = self
+        {
+            divergence.get_or_insert(Divergence {
+                event: *next,
+                expected,
+                actual,
+            });
+        }
+    }
+
+    /// Ends the session. A replay fails with its first divergence, including events
+    /// the log holds that the replay never reached; a recording fails if the log
+    /// could not be written. Otherwise returns the number of events replayed.
+    pub fn finish(&mut self) -> Result<usize, SutraError> {
+        match self {
+            Session::Recording { out, error } => {
+                let error = match error.take() {
+                    Some(error) => Some(error),
+                    None => out.flush().err(),
+                };
+                match error {
+                    Some(error) => Err(log_error(error.to_string())),
+                    None => Ok(0),
+                }
+            }
+            Session::Replaying {
+                events,
+                next,
+                divergence,
+            } => {
+                if divergence.is_none() && *next < events.len() {
+                    *divergence = Some(Divergence {
+                        event: *next + 1,
+                        expected: events.get(*next).cloned(),
+                        actual: "the end of the session".to_string(),
+                    });
+                }
+                match divergence.clone() {
+                    Some(divergence) => Err(SutraError::without_source(
+                        ErrorKind::ReplayDiverged {
+                            event: divergence.event,
+                            expected: divergence
+                                .expected
+                                .map_or("the end of the log".to_string(), |e| e.to_string()),
+                            actual: divergence.actual,
+                        },
+                        "replay",
+                        "runtime",
+                    )),
+                    None => Ok(events.len()),
+                }
+            }
+        }
+    }
+}
+
+/// The error for a replay log that cannot be read or written.
+pub fn log_error(reason: String) -> SutraError {
+    SutraError::without_source(ErrorKind::InvalidReplayLog { reason }, "replay", "runtime")
+}
+
+/// A session shared by a world and the pipeline that started it.
+#[derive(Debug, Clone)]
+pub struct SharedSession(Arc<Mutex<Session>>);
+
+impl SharedSession {
+    pub fn new(session: Session) -> Self {
+        Self(Arc::new(Mutex::new(session)))
+    }
+
+    pub fn lock(&self) -> MutexGuard<'_, Session> {
+        self.0.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+fn session(world: &CanonicalWorld) -> Option<SharedSession> {
+    world.read().session.clone()
+}
+
+/// Records the world's checksum at `at`, if the world has a session.
+pub fn checkpoint(world: &CanonicalWorld, at: &str) {
+    if let Some(session) = session(world) {
+        let hash = checksum(&world.read());
+        session.lock().observe(Event::Checksum {
+            at: at.to_string(),
+            hash,
+        });
+    }
+}
+
+/// The answer to a choice: the logged one when replaying, or else what `ask` returns.
+pub fn choose(world: &CanonicalWorld, ask: impl FnOnce() -> Option<usize>) -> Option<usize> {
+    let Some(session) = session(world) else {
+        return ask();
+    };
+    checkpoint(world, "before a choice");
+    let answer = session.lock().input("choice", || Event::Choice(ask()));
+    match answer {
+        Some(Event::Choice(answer)) => answer,
+        _ => None,
+    }
+}
+
+/// A line of input: the logged one when replaying, or else what `read` returns.
+pub fn read_line(
+    world: &CanonicalWorld,
+    read: impl FnOnce() -> io::Result<Option<String>>,
+) -> io::Result<Option<String>> {
+    let Some(session) = session(world) else {
+        return read();
+    };
+    checkpoint(world, "before reading a line");
+    let mut failed = None;
+    let event = session.lock().input("input line", || match read() {
+        Ok(line) => Event::Line(line),
+        Err(error) => {
+            failed = Some(error);
+            Event::Line(None)
+        }
+    });
+    if let Some(error) = failed {
+        return Err(error);
+    }
+    match event {
+        Some(Event::Line(line)) => Ok(line),
+        _ => Ok(None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn replaying(events: Vec<Event>) -> Session {
+        Session::Replaying {
+            events,
+            next: 0,
+            divergence: None,
+        }
+    }
+
+    #[test]
+    fn replays_logged_inputs() {
+        let mut session = replaying(vec![Event::Draw(7), Event::Choice(Some(1))]);
+        session.observe(Event::Draw(7));
+        assert_eq!(
+            session.input("choice", || unreachable!()),
+            Some(Event::Choice(Some(1)))
+        );
+        assert_eq!(session.finish().unwrap(), 2);
+    }
+
+    #[test]
+    fn reports_the_first_divergence() {
+        let mut session = replaying(vec![Event::Draw(7), Event::Draw(8), Event::Draw(9)]);
+        session.observe(Event::Draw(7));
+        session.observe(Event::Draw(5));
+        session.observe(Event::Draw(6));
+        let error = session.finish().unwrap_err().to_string();
+        assert!(
+            error.contains("event 2: expected random draw 8, got random draw 5"),
+            "{error}"
+        );
+    }
+
+    #[test]
+    fn unreached_events_diverge() {
+        let mut session = replaying(vec![Event::Draw(7), Event::Line(None)]);
+        session.observe(Event::Draw(7));
+        let error = session.finish().unwrap_err().to_string();
+        assert!(
+            error.contains("event 2: expected end of input, got the end of the session"),
+            "{error}"
+        );
+    }
+
+    #[test]
+    fn records_events_as_json_lines() {
+        let header = Header {
+            version: REPLAY_VERSION,
+            seed: 1,
+            args: Vec::new(),
+            files: Vec::new(),
+            entry: None,
+        };
+        let log = Arc::new(Mutex::new(Vec::new()));
+        struct Shared(Arc<Mutex<Vec<u8>>>);
+        impl Write for Shared {
+            fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+                self.0.lock().unwrap().write(buf)
+            }
+            fn flush(&mut self) -> io::Result<()> {
+                Ok(())
+            }
+        }
+        let mut session = Session::record(Box::new(Shared(log.clone())), &header).unwrap();
+        session.observe(Event::Draw(3));
+        session.observe(Event::Line(Some("hi".to_string())));
+        session.finish().unwrap();
+
+        let log = String::from_utf8(log.lock().unwrap().clone()).unwrap();
+        let (read, mut replay) = Session::replay(&log).unwrap();
+        assert_eq!(read.seed, 1);
+        replay.observe(Event::Draw(3));
+        replay.observe(Event::Line(Some("hi".to_string())));
+        assert_eq!(replay.finish().unwrap(), 2);
+    }
+}
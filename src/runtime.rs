@@ -4,12 +4,17 @@
 //! All computation, macro expansion, and evaluation produces or manipulates these types.
 //! Values are deeply compositional: lists and maps can contain any other value.
 
-use std::{collections::HashMap, fmt, rc::Rc};
+use std::{
+    collections::{BTreeMap, HashMap, VecDeque},
+    fmt,
+    sync::Arc,
+};
 
-use serde::{Deserialize, Serialize};
+use serde::{de::Error as _, Deserialize, Deserializer, Serialize, Serializer};
 
+use crate::atoms::{WorldHandle, WorldState};
 use crate::errors::SutraError;
-use crate::{AstNode, ParamList, Path, Span};
+use crate::{AstList, AstNode, ParamList, Path, Span, Spanned};
 
 /// Unified native function signature.
 ///
@@ -34,17 +39,40 @@ pub enum Value {
     Bool(bool),
     /// A Lisp-style cons cell, forming the head of a list.
     Cons(ConsRepr),
-    /// Map from string keys to values (deeply compositional).
-    Map(HashMap<String, Value>),
+    /// Map from string keys to values (deeply compositional), iterated in key order.
+    Map(BTreeMap<String, Value>),
     /// Reference to a path in the world state (not auto-resolved).
     Path(Path),
     /// User-defined lambda function (captures parameter list and body).
-    Lambda(Rc<Lambda>),
-    /// Native (Rust) function.
-    #[serde(skip)]
-    NativeFn(NativeFn),
+    Lambda(Arc<Lambda>),
     Symbol(String),
+    /// Self-evaluating `:name` keyword; the stored name excludes the colon.
+    Keyword(String),
     Quote(Box<Value>),
+    /// Instance of a type declared with `defrecord`.
+    Record(Arc<Record>),
+    /// Suspendable function call created by `coroutine`. Only an engine snapshot can
+    /// save one; see [`crate::snapshot`].
+    Coroutine(
+        #[serde(
+            serialize_with = "crate::snapshot::save_coroutine",
+            deserialize_with = "crate::snapshot::load_coroutine"
+        )]
+        Arc<crate::atoms::coroutines::Coroutine>,
+    ),
+    /// Text that grows in place, created by `str-builder`. Copies share it.
+    StringBuilder(
+        #[serde(
+            serialize_with = "crate::atoms::string_builder::save_builder",
+            deserialize_with = "crate::atoms::string_builder::load_builder"
+        )]
+        Arc<crate::atoms::string_builder::StringBuilder>,
+    ),
+    /// Native (Rust) function. It has no saved form; it stays the last variant because
+    /// serde numbers the variants after a skipped one differently when reading than when
+    /// writing.
+    #[serde(skip)]
+    NativeFn(NativeFn),
 }
 
 /// Represents a user-defined lambda function (for closures and function values).
@@ -55,6 +83,23 @@ pub struct Lambda {
     pub captured_env: HashMap<String, Value>,
 }
 
+/// A record instance: the record type's name and its fields in declaration order.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Record {
+    pub type_name: String,
+    pub fields: Vec<(String, Value)>,
+}
+
+impl Record {
+    /// Returns the value of `field`, if the record has it.
+    pub fn get(&self, field: &str) -> Option<&Value> {
+        self.fields
+            .iter()
+            .find(|(name, _)| name == field)
+            .map(|(_, value)| value)
+    }
+}
+
 /// Represents a Lisp-style cons cell (a pair of values).
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct ConsCell {
@@ -62,13 +107,27 @@ pub struct ConsCell {
     pub cdr: Value,
 }
 
+impl Drop for ConsCell {
+    /// Unlinks the tail one cell at a time, so dropping a long list doesn't recurse once
+    /// per element. A tail another list still shares is left to it.
+    fn drop(&mut self) {
+        let mut tail = std::mem::take(&mut self.cdr);
+        while let Value::Cons(ConsRepr::Chain(cell)) = tail {
+            match Arc::try_unwrap(cell) {
+                Ok(mut cell) => tail = std::mem::take(&mut cell.cdr),
+                Err(_) => break,
+            }
+        }
+    }
+}
+
 /// Internal optimized representation for cons cells
-#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[derive(Debug, Clone)]
 pub enum ConsRepr {
     /// Single element list (most common case)
     Single(Box<Value>),
     /// General case for longer lists
-    Chain(Rc<ConsCell>),
+    Chain(Arc<ConsCell>),
 }
 
 impl ConsRepr {
@@ -94,12 +153,82 @@ impl ConsRepr {
             Value::Nil => ConsRepr::Single(Box::new(car)),
             Value::Cons(ConsRepr::Single(existing)) => {
                 // Create a proper two-element list where cdr points to single-element list
-                ConsRepr::Chain(Rc::new(ConsCell {
+                ConsRepr::Chain(Arc::new(ConsCell {
                     car,
                     cdr: Value::Cons(ConsRepr::Single(existing)),
                 }))
             }
-            _ => ConsRepr::Chain(Rc::new(ConsCell { car, cdr })),
+            _ => ConsRepr::Chain(Arc::new(ConsCell { car, cdr })),
+        }
+    }
+}
+
+impl PartialEq for ConsRepr {
+    /// Compares the lists a pair at a time rather than recursing down their tails.
+    fn eq(&self, other: &Self) -> bool {
+        let (mut a, mut b) = (self, other);
+        loop {
+            match (a, b) {
+                (ConsRepr::Single(x), ConsRepr::Single(y)) => return x == y,
+                (ConsRepr::Chain(x), ConsRepr::Chain(y)) => {
+                    if x.car != y.car {
+                        return false;
+                    }
+                    match (&x.cdr, &y.cdr) {
+                        (Value::Cons(x), Value::Cons(y)) => (a, b) = (x, y),
+                        (x, y) => return x == y,
+                    }
+                }
+                _ => return false,
+            }
+        }
+    }
+}
+
+/// How a list written by [`ConsRepr`]'s `Serialize` ends, after its elements.
+#[derive(Serialize, Deserialize)]
+enum ListEnd<V> {
+    /// A last element with nothing after it.
+    Single(V),
+    /// What the last pair's tail holds, for a list that doesn't end in nil.
+    Tail(V),
+}
+
+impl Serialize for ConsRepr {
+    /// Writes the list flat, its elements and then how it ends, so a long list doesn't
+    /// nest once per element.
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut elements = Vec::new();
+        let mut link = self;
+        let end = loop {
+            match link {
+                ConsRepr::Single(value) => break ListEnd::Single(&**value),
+                ConsRepr::Chain(cell) => {
+                    elements.push(&cell.car);
+                    match &cell.cdr {
+                        Value::Cons(next) => link = next,
+                        tail => break ListEnd::Tail(tail),
+                    }
+                }
+            }
+        };
+        (elements, end).serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for ConsRepr {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let (elements, end) = <(Vec<Value>, ListEnd<Value>)>::deserialize(deserializer)?;
+        let mut list = match end {
+            ListEnd::Single(value) => Value::Cons(ConsRepr::Single(Box::new(value))),
+            ListEnd::Tail(tail) => tail,
+        };
+        for car in elements.into_iter().rev() {
+            list = Value::Cons(ConsRepr::Chain(Arc::new(ConsCell { car, cdr: list })));
+        }
+        match list {
+            Value::Cons(repr) => Ok(repr),
+            _ => Err(D::Error::custom("a list with no elements")),
         }
     }
 }
@@ -117,7 +246,11 @@ impl PartialEq for Value {
             (Value::Lambda(a), Value::Lambda(b)) => a == b,
             (Value::NativeFn(a), Value::NativeFn(b)) => *a as usize == *b as usize,
             (Value::Symbol(a), Value::Symbol(b)) => a == b,
+            (Value::Keyword(a), Value::Keyword(b)) => a == b,
             (Value::Quote(a), Value::Quote(b)) => a == b,
+            (Value::Record(a), Value::Record(b)) => a == b,
+            (Value::Coroutine(a), Value::Coroutine(b)) => Arc::ptr_eq(a, b),
+            (Value::StringBuilder(a), Value::StringBuilder(b)) => Arc::ptr_eq(a, b),
             _ => false,
         }
     }
@@ -137,7 +270,11 @@ impl Value {
             Value::Lambda(_) => "Lambda",
             Value::NativeFn(_) => "NativeFn",
             Value::Symbol(_) => "Symbol",
+            Value::Keyword(_) => "Keyword",
             Value::Quote(_) => "Quote",
+            Value::Record(_) => "Record",
+            Value::Coroutine(_) => "Coroutine",
+            Value::StringBuilder(_) => "StringBuilder",
         }
     }
 
@@ -178,7 +315,7 @@ impl Value {
     }
 
     /// Returns a reference to the contained map if this is a Map value, else None.
-    pub fn as_map(&self) -> Option<&HashMap<String, Value>> {
+    pub fn as_map(&self) -> Option<&BTreeMap<String, Value>> {
         match self {
             Value::Map(m) => Some(m),
             _ => None,
@@ -242,7 +379,7 @@ impl Value {
         write!(f, ")")
     }
 
-    fn fmt_map(f: &mut fmt::Formatter<'_>, map: &HashMap<String, Value>) -> fmt::Result {
+    fn fmt_map(f: &mut fmt::Formatter<'_>, map: &BTreeMap<String, Value>) -> fmt::Result {
         write!(f, "{{")?;
         let mut first = true;
         for (k, v) in map.iter() {
@@ -254,13 +391,53 @@ impl Value {
         }
         write!(f, "}}")
     }
+
+    fn fmt_record(f: &mut fmt::Formatter<'_>, record: &Record) -> fmt::Result {
+        write!(f, "{}{{", record.type_name)?;
+        for (i, (name, value)) in record.fields.iter().enumerate() {
+            if i > 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "{name}: {value}")?;
+        }
+        write!(f, "}}")
+    }
+}
+
+/// Significant digits a number displays with: the most an `f64` always holds exactly,
+/// so a literal written with no more prints as written, while the artifacts of
+/// arithmetic such as `0.30000000000000004` print as `0.3`.
+const DISPLAY_DIGITS: usize = 15;
+
+/// Formats a number for display: the shortest text that reads back as the number
+/// rounded to [`DISPLAY_DIGITS`] significant digits. Magnitudes from `1e21` up and
+/// below `1e-6` use exponent notation, and negative zero prints as `0`.
+pub fn format_number(n: f64) -> String {
+    if !n.is_finite() {
+        return n.to_string();
+    }
+    if n == 0.0 {
+        return "0".to_string();
+    }
+    let scientific = format!("{:.*e}", DISPLAY_DIGITS - 1, n);
+    let rounded: f64 = scientific.parse().unwrap_or(n);
+    let exponent = scientific
+        .rsplit('e')
+        .next()
+        .and_then(|exponent| exponent.parse::<i32>().ok())
+        .unwrap_or(0);
+    if (-6..21).contains(&exponent) {
+        rounded.to_string()
+    } else {
+        format!("{rounded:e}")
+    }
 }
 
 impl fmt::Display for Value {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             Value::Nil => write!(f, "nil"),
-            Value::Number(n) => write!(f, "{n}"),
+            Value::Number(n) => write!(f, "{}", format_number(*n)),
             Value::String(s) => write!(f, "{s}"),
             Value::Bool(b) => write!(f, "{b}"),
             Value::Cons(_) => Value::fmt_list_like(f, self),
@@ -269,7 +446,11 @@ impl fmt::Display for Value {
             Value::Lambda(_) => write!(f, "<lambda>"),
             Value::NativeFn(_) => write!(f, "<native_fn>"),
             Value::Symbol(s) => write!(f, "{s}"),
+            Value::Keyword(k) => write!(f, ":{k}"),
             Value::Quote(v) => write!(f, "'{v}"),
+            Value::Record(record) => Value::fmt_record(f, record),
+            Value::Coroutine(_) => write!(f, "<coroutine>"),
+            Value::StringBuilder(_) => write!(f, "<string-builder>"),
         }
     }
 }
@@ -412,10 +593,179 @@ impl Iterator for ValueIterator {
     }
 }
 
+// ============================================================================
+// UNDO HISTORY - World snapshots taken at checkpoints
+// ============================================================================
+
+/// Number of checkpoints kept by default; older ones are dropped first.
+pub const DEFAULT_HISTORY_DEPTH: usize = 100;
+
+/// Undo/redo stacks of world state snapshots.
+///
+/// `(checkpoint!)` records the current state; `(undo!)` returns to the most recent
+/// checkpoint and `(redo!)` reverses the undo. Taking a new checkpoint clears redo.
+#[derive(Debug, Clone)]
+pub struct History {
+    undo: VecDeque<WorldState>,
+    redo: Vec<WorldState>,
+    max_depth: usize,
+}
+
+impl Default for History {
+    fn default() -> Self {
+        Self::new(DEFAULT_HISTORY_DEPTH)
+    }
+}
+
+impl History {
+    /// Creates an empty history that keeps at most `max_depth` checkpoints.
+    pub fn new(max_depth: usize) -> Self {
+        Self {
+            undo: VecDeque::new(),
+            redo: Vec::new(),
+            max_depth,
+        }
+    }
+
+    pub fn max_depth(&self) -> usize {
+        self.max_depth
+    }
+
+    /// Changes the depth limit, dropping the oldest checkpoints if over it.
+    pub fn set_max_depth(&mut self, max_depth: usize) {
+        self.max_depth = max_depth;
+        while self.undo.len() > max_depth {
+            self.undo.pop_front();
+        }
+    }
+
+    pub fn checkpoint(&mut self, state: &WorldState) {
+        if self.max_depth == 0 {
+            return;
+        }
+        if self.undo.len() == self.max_depth {
+            self.undo.pop_front();
+        }
+        self.undo.push_back(state.clone());
+        self.redo.clear();
+    }
+
+    /// Returns the state to restore, saving `current` for redo, or `None` if there
+    /// is no checkpoint.
+    pub fn undo(&mut self, current: &WorldState) -> Option<WorldState> {
+        let previous = self.undo.pop_back()?;
+        self.redo.push(current.clone());
+        Some(previous)
+    }
+
+    /// Returns the state an undo left, saving `current` for undo, or `None` if there
+    /// is nothing to redo.
+    pub fn redo(&mut self, current: &WorldState) -> Option<WorldState> {
+        let next = self.redo.pop()?;
+        self.undo.push_back(current.clone());
+        Some(next)
+    }
+
+    pub fn can_undo(&self) -> bool {
+        !self.undo.is_empty()
+    }
+
+    pub fn can_redo(&self) -> bool {
+        !self.redo.is_empty()
+    }
+}
+
 // ============================================================================
 // EVALUATION CONTEXT - Simplified evaluation state
 // ============================================================================
 
+/// A wall-clock limit on evaluation, checked before each node is evaluated.
+#[derive(Debug, Clone, Copy)]
+pub struct Deadline {
+    pub at: std::time::Instant,
+    pub limit: std::time::Duration,
+}
+
+impl Deadline {
+    /// A deadline `limit` from now.
+    pub fn after(limit: std::time::Duration) -> Self {
+        Self {
+            at: std::time::Instant::now() + limit,
+            limit,
+        }
+    }
+
+    pub fn has_passed(&self) -> bool {
+        std::time::Instant::now() >= self.at
+    }
+}
+
+/// Ceilings on what an evaluation may allocate; `None` leaves a resource unbounded.
+/// Each call's result is checked, and the world after each stateful atom call.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ResourceLimits {
+    /// Most elements a list or map may hold.
+    pub max_collection_len: Option<usize>,
+    /// Most bytes a string may hold.
+    pub max_string_bytes: Option<usize>,
+    /// Most entries the world state may hold, as counted by [`WorldState::size`].
+    ///
+    /// [`WorldState::size`]: crate::atoms::WorldState::size
+    pub max_world_size: Option<usize>,
+}
+
+impl ResourceLimits {
+    /// The resource `value` exceeds, with its limit and actual size.
+    fn exceeded_by(&self, value: &Value) -> Option<(&'static str, usize, usize)> {
+        match value {
+            Value::String(s) => {
+                let limit = self.max_string_bytes?;
+                (s.len() > limit).then_some(("string length", limit, s.len()))
+            }
+            Value::StringBuilder(builder) => {
+                let limit = self.max_string_bytes?;
+                let len = builder.len();
+                (len > limit).then_some(("string length", limit, len))
+            }
+            Value::Cons(_) => {
+                let limit = self.max_collection_len?;
+                // Stop one past the limit so huge lists are not walked in full.
+                let mut len = 0;
+                let mut current = value;
+                while let Value::Cons(cons) = current {
+                    len += 1;
+                    match cons {
+                        ConsRepr::Chain(cell) if len <= limit => current = &cell.cdr,
+                        _ => break,
+                    }
+                }
+                (len > limit).then_some(("list length", limit, len))
+            }
+            Value::Map(map) => {
+                let limit = self.max_collection_len?;
+                (map.len() > limit).then_some(("map size", limit, map.len()))
+            }
+            _ => None,
+        }
+    }
+}
+
+/// How deeply evaluation may nest unless configured otherwise.
+pub const DEFAULT_MAX_DEPTH: usize = 1000;
+
+/// Stack left below which [`grow_stack`] moves to a new stack segment, and the size
+/// of each segment.
+const STACK_RED_ZONE: usize = 256 << 10;
+const STACK_SEGMENT: usize = 4 << 20;
+
+/// Runs `f`, on a new stack segment if little of the current one is left. Passes
+/// that recurse once per nested form call this at each level, so a deep but legal
+/// program cannot overflow a small thread stack before `max_depth` or
+/// [`crate::parser::MAX_NESTING`] stops it.
+pub(crate) fn grow_stack<R>(f: impl FnOnce() -> R) -> R {
+    stacker::maybe_grow(STACK_RED_ZONE, STACK_SEGMENT, f)
+}
+
 /// Simplified evaluation context with essential state only
 pub struct EvaluationContext {
     pub world: crate::prelude::CanonicalWorld,
@@ -423,7 +773,13 @@ pub struct EvaluationContext {
     pub source: crate::errors::SourceContext,
     pub depth: usize,
     pub max_depth: usize,
+    /// Evaluation fails with a timeout once this passes, if set.
+    pub deadline: Option<Deadline>,
+    /// Allocation quotas, checked after each call.
+    pub limits: ResourceLimits,
     pub env: Vec<std::collections::HashMap<String, Value>>, // Stack of lexical scopes
+    /// The coroutine this evaluation runs in, if any, for `yield`.
+    pub(crate) yielder: Option<crate::atoms::coroutines::YieldHandle>,
 }
 
 impl EvaluationContext {
@@ -441,8 +797,11 @@ impl EvaluationContext {
             output,
             source,
             depth: 0,
-            max_depth: 1000,
+            max_depth: DEFAULT_MAX_DEPTH,
+            deadline: None,
+            limits: ResourceLimits::default(),
             env: vec![global_env],
+            yielder: None,
         }
     }
 
@@ -465,6 +824,17 @@ impl EvaluationContext {
         }
     }
 
+    /// Bind a `define`d name. Inside a function or `let` the binding is lexical; at top
+    /// level it is stored in the world so later forms, files, and REPL inputs can use it.
+    /// Redefinitions are reported before evaluation, among the pipeline's warnings.
+    pub fn define_var(&mut self, name: &str, value: Value) {
+        if self.env.len() > 1 {
+            self.set_var(name, value);
+            return;
+        }
+        self.world.write().defs.insert(name.to_string(), value);
+    }
+
     /// Get a variable from the lexical scope chain (search from innermost to outermost)
     pub fn get_var(&self, name: &str) -> Option<&Value> {
         // Search from innermost scope to outermost
@@ -482,12 +852,15 @@ impl EvaluationContext {
         new_env.push(std::collections::HashMap::new()); // Add new lexical scope
 
         Self {
-            world: std::rc::Rc::clone(&self.world),
+            world: self.world.clone(),
             output: self.output.clone(),
             source: self.source.clone(),
             depth: self.depth,
             max_depth: self.max_depth,
+            deadline: self.deadline,
+            limits: self.limits,
             env: new_env,
+            yielder: self.yielder,
         }
     }
 
@@ -503,20 +876,7 @@ impl crate::errors::ErrorReporting for EvaluationContext {
         kind: crate::errors::ErrorKind,
         span: miette::SourceSpan,
     ) -> crate::errors::SutraError {
-        use crate::errors::{DiagnosticInfo, SourceInfo, SutraError};
-
-        SutraError {
-            kind: kind.clone(),
-            source_info: SourceInfo {
-                source: self.source.to_named_source(),
-                primary_span: span,
-                phase: "runtime".into(),
-            },
-            diagnostic_info: DiagnosticInfo {
-                help: None,
-                error_code: format!("sutra::runtime::{}", kind.code_suffix()),
-            },
-        }
+        crate::errors::SutraError::new(kind, self.source.to_named_source(), span, "runtime")
     }
 }
 
@@ -544,23 +904,17 @@ impl<'a> ErrorContext<'a> {
         actual: &str,
         span: miette::SourceSpan,
     ) -> crate::errors::SutraError {
-        use crate::errors::{DiagnosticInfo, ErrorKind, SourceInfo, SutraError};
+        use crate::errors::{ErrorKind, SutraError};
 
-        SutraError {
-            kind: ErrorKind::TypeMismatch {
+        SutraError::new(
+            ErrorKind::TypeMismatch {
                 expected: expected.to_string(),
                 actual: actual.to_string(),
             },
-            source_info: SourceInfo {
-                source: self.source.to_named_source(),
-                primary_span: span,
-                phase: self.phase.into(),
-            },
-            diagnostic_info: DiagnosticInfo {
-                help: None,
-                error_code: format!("sutra::{}::type_mismatch", self.phase),
-            },
-        }
+            self.source.to_named_source(),
+            span,
+            self.phase,
+        )
     }
 
     /// Create an arity mismatch error.
@@ -570,23 +924,17 @@ impl<'a> ErrorContext<'a> {
         actual: usize,
         span: miette::SourceSpan,
     ) -> crate::errors::SutraError {
-        use crate::errors::{DiagnosticInfo, ErrorKind, SourceInfo, SutraError};
+        use crate::errors::{ErrorKind, SutraError};
 
-        SutraError {
-            kind: ErrorKind::ArityMismatch {
+        SutraError::new(
+            ErrorKind::ArityMismatch {
                 expected: expected.to_string(),
                 actual,
             },
-            source_info: SourceInfo {
-                source: self.source.to_named_source(),
-                primary_span: span,
-                phase: self.phase.into(),
-            },
-            diagnostic_info: DiagnosticInfo {
-                help: None,
-                error_code: format!("sutra::{}::arity_mismatch", self.phase),
-            },
-        }
+            self.source.to_named_source(),
+            span,
+            self.phase,
+        )
     }
 }
 
@@ -596,25 +944,52 @@ impl<'a> ErrorContext<'a> {
 
 /// Core recursive evaluator
 pub fn evaluate_ast_node(expr: &AstNode, context: &mut EvaluationContext) -> SpannedResult {
+    grow_stack(|| evaluate_node(expr, context))
+}
+
+fn evaluate_node(expr: &AstNode, context: &mut EvaluationContext) -> SpannedResult {
     use crate::{errors::ErrorReporting, Expr};
 
     // Check recursion limit
     if context.depth > context.max_depth {
+        let error = context.report(
+            crate::errors::ErrorKind::RecursionLimit {
+                limit: context.max_depth,
+            },
+            context.span_for_node(expr),
+        );
+        return Err(error.with_help(
+            "look for a function that calls itself without end, or raise the limit with `--max-depth`",
+        ));
+    }
+
+    // Check wall-clock limit
+    if let Some(deadline) = context.deadline.filter(Deadline::has_passed) {
         return Err(context.report(
-            crate::errors::ErrorKind::RecursionLimit,
+            crate::errors::ErrorKind::Timeout {
+                limit_ms: deadline.limit.as_millis() as u64,
+            },
             context.span_for_node(expr),
         ));
     }
 
     // Evaluate based on expression type
     match &*expr.value {
-        Expr::List(items, _) => evaluate_call(items, context),
+        Expr::List(items, _) => {
+            let result = evaluate_call(items, context)?;
+            check_resource_limits(expr, items, &result.value, context)?;
+            Ok(result)
+        }
         Expr::Quote(inner, _) => Ok(SpannedValue {
             value: Value::Quote(Box::new(ast_to_value(inner))),
             span: expr.span,
         }),
         Expr::Symbol(name, _) => resolve_symbol(name, expr, context),
-        Expr::String(s, _) => Ok(SpannedValue {
+        Expr::Keyword(k, _) => Ok(SpannedValue {
+            value: Value::Keyword(k.clone()),
+            span: expr.span,
+        }),
+        Expr::String(s, ..) => Ok(SpannedValue {
             value: Value::String(s.clone()),
             span: expr.span,
         }),
@@ -667,7 +1042,8 @@ fn evaluate_call(items: &[AstNode], context: &mut EvaluationContext) -> SpannedR
     let head = &items[0];
     let tail = &items[1..];
 
-    // Resolve callable
+    // Resolve callable. Special forms are not named here: they live in the
+    // environment like any other atom, so embedders can add their own.
     let callable = if let Expr::Symbol(name, _) = &*head.value {
         resolve_symbol(name, head, context)?.value
     } else {
@@ -682,6 +1058,14 @@ fn evaluate_call(items: &[AstNode], context: &mut EvaluationContext) -> SpannedR
             crate::atoms::special_forms::call_lambda(&lambda, &args, context, &head.span)
         }
         Value::NativeFn(func) => {
+            // Atoms and special forms alike decide how to evaluate their arguments
+            let _call = match &*head.value {
+                Expr::Symbol(name, _) => {
+                    check_atom_arity(name, tail.len(), head, context)?;
+                    Some(crate::telemetry::atom_call(name, &head.span, context.depth))
+                }
+                _ => None,
+            };
             // Pass unevaluated arguments to native function
             func(tail, context, &head.span)
         }
@@ -695,6 +1079,67 @@ fn evaluate_call(items: &[AstNode], context: &mut EvaluationContext) -> SpannedR
     }
 }
 
+/// Fails if a call's result, or the world after a stateful atom call, is over a quota.
+/// The error points at the call.
+fn check_resource_limits(
+    call: &AstNode,
+    items: &[AstNode],
+    value: &Value,
+    context: &EvaluationContext,
+) -> Result<(), SutraError> {
+    use crate::{atoms::AtomKind, errors::ErrorReporting, Expr};
+
+    let limits = context.limits;
+    let mut exceeded = limits.exceeded_by(value);
+    if let (None, Some(limit), Some(head)) = (exceeded, limits.max_world_size, items.first()) {
+        let world = context.world.read();
+        let stateful = match &*head.value {
+            Expr::Symbol(name, _) => world
+                .atom_specs
+                .get(name)
+                .is_some_and(|spec| spec.kind == AtomKind::Stateful),
+            _ => false,
+        };
+        let size = if stateful { world.state.size() } else { 0 };
+        exceeded = (size > limit).then_some(("world size", limit, size));
+    }
+
+    let Some((resource, limit, actual)) = exceeded else {
+        return Ok(());
+    };
+    Err(context.report(
+        crate::errors::ErrorKind::ResourceLimit {
+            resource: resource.to_string(),
+            limit,
+            actual,
+        },
+        context.span_for_node(call),
+    ))
+}
+
+/// Checks a call to a registered atom against its [`AtomSpec`](crate::atoms::AtomSpec)
+/// before the atom runs, unless a local binding shadows the atom's name.
+fn check_atom_arity(
+    name: &str,
+    count: usize,
+    head: &AstNode,
+    context: &EvaluationContext,
+) -> Result<(), SutraError> {
+    use crate::errors::ErrorReporting;
+
+    let world = context.world.read();
+    let Some(spec) = world.atom_specs.get(name) else {
+        return Ok(());
+    };
+    if spec.accepts(count) || context.get_var(name).is_some() || world.defs.contains_key(name) {
+        return Ok(());
+    }
+
+    let mut error = context.arity_mismatch(&spec.expected(), count, context.span_for_node(head));
+    error.diagnostic_info.help = Some(format!("usage: {}", spec.usage()).into());
+    Err(error)
+}
+
 /// Evaluate arguments for function calls
 fn evaluate_args(
     args: &[AstNode],
@@ -720,33 +1165,76 @@ fn resolve_symbol(name: &str, node: &AstNode, context: &mut EvaluationContext) -
         });
     }
 
+    // Check top-level definitions
+    if let Some(value) = context.world.read().defs.get(name) {
+        return Ok(SpannedValue {
+            value: value.clone(),
+            span: node.span,
+        });
+    }
+
     // Check global world state
     let world_path = crate::atoms::Path(vec![name.to_string()]);
-    if let Some(value) = context.world.borrow().state.get(&world_path) {
+    if let Some(value) = context.world.read().state.get(&world_path) {
         return Ok(SpannedValue {
             value: value.clone(),
             span: node.span,
         });
     }
 
+    // A module's private definition, used from outside it
+    let private = crate::macros::module::private_access(name, |private| {
+        context.world.read().defs.contains_key(private)
+    });
+    if let Some((module, symbol)) = private {
+        return Err(context.report(
+            crate::errors::ErrorKind::PrivateSymbol { symbol, module },
+            context.span_for_node(node),
+        ));
+    }
+
     // Undefined
-    Err(context.report(
+    let error = context.report(
         crate::errors::ErrorKind::UndefinedSymbol {
             symbol: name.to_string(),
         },
         context.span_for_node(node),
-    ))
+    );
+    match suggest_symbol(name, context) {
+        Some(suggestion) => Err(error.with_suggestion(&suggestion)),
+        None => Err(error),
+    }
+}
+
+/// The name in scope closest to the undefined `name`: lexical bindings, top-level
+/// definitions, atoms and other globals, and macros.
+fn suggest_symbol(name: &str, context: &EvaluationContext) -> Option<String> {
+    let world = context.world.read();
+    let macros = world.macros.macro_names();
+    let globals = match world.state.get(&crate::atoms::Path(vec![])) {
+        Some(Value::Map(map)) => map.keys().map(String::as_str).collect(),
+        _ => Vec::new(),
+    };
+    let candidates = context
+        .env
+        .iter()
+        .flat_map(|scope| scope.keys().map(String::as_str))
+        .chain(world.defs.keys().map(String::as_str))
+        .chain(globals)
+        .chain(macros.iter().map(String::as_str));
+    crate::errors::closest_name(name, candidates).map(str::to_string)
 }
 
 /// Convert AST to quoted value
-fn ast_to_value(node: &AstNode) -> Value {
+pub(crate) fn ast_to_value(node: &AstNode) -> Value {
     use crate::Expr;
 
     match &*node.value {
         Expr::Symbol(s, _) => Value::Symbol(s.clone()),
+        Expr::Keyword(k, _) => Value::Keyword(k.clone()),
         Expr::Number(n, _) => Value::Number(*n),
         Expr::Bool(b, _) => Value::Bool(*b),
-        Expr::String(s, _) => Value::String(s.clone()),
+        Expr::String(s, ..) => Value::String(s.clone()),
         Expr::List(items, _) => {
             let mut result = Value::Nil;
             for item in items.iter().rev() {
@@ -757,8 +1245,131 @@ fn ast_to_value(node: &AstNode) -> Value {
         }
         Expr::Quote(inner, _) => Value::Quote(Box::new(ast_to_value(inner))),
         Expr::Path(p, _) => Value::Path(p.clone()),
-        _ => Value::Nil,
+        Expr::If {
+            condition,
+            then_branch,
+            else_branch,
+            ..
+        } => Value::from_list(vec![
+            Value::Symbol("if".to_string()),
+            ast_to_value(condition),
+            ast_to_value(then_branch),
+            ast_to_value(else_branch),
+        ]),
+        Expr::ParamList(params) => {
+            let rest = params
+                .rest
+                .iter()
+                .map(|rest| format!("{REST_PREFIX}{rest}"));
+            Value::from_list(
+                params
+                    .required
+                    .iter()
+                    .cloned()
+                    .chain(rest)
+                    .map(Value::Symbol)
+                    .collect(),
+            )
+        }
+        Expr::Spread(inner) => match &*inner.value {
+            Expr::Symbol(name, _) => Value::Symbol(format!("{REST_PREFIX}{name}")),
+            _ => Value::Nil,
+        },
+    }
+}
+
+/// Marks a rest parameter or spread argument in quoted code, as in `...rest`.
+const REST_PREFIX: &str = "...";
+
+/// Converts quoted code back to an AST, the inverse of evaluating `quote`; `eval`
+/// uses it. Every node gets `span`, normally that of the call asking for the
+/// conversion, so errors in the code point there. A list right after `lambda` or
+/// `define` becomes a parameter list, with `...rest` as its rest parameter, and
+/// `...name` anywhere else a spread.
+///
+/// Fails with the part of `value` that is not code: a map, function, record or
+/// coroutine, or a list not ending in nil.
+pub fn value_to_ast(value: &Value, span: Span) -> Result<AstNode, Value> {
+    use crate::Expr;
+
+    let expr = match value {
+        Value::Nil => Expr::List(AstList::new(), span),
+        Value::Number(n) => Expr::Number(*n, span),
+        Value::String(s) => Expr::String(s.clone(), span, true),
+        Value::Bool(b) => Expr::Bool(*b, span),
+        Value::Keyword(k) => Expr::Keyword(k.clone(), span),
+        Value::Path(p) => Expr::Path(p.clone(), span),
+        Value::Symbol(s) => match s.strip_prefix(REST_PREFIX) {
+            Some(name) if !name.is_empty() => {
+                let name = Value::Symbol(name.to_string());
+                Expr::Spread(Box::new(value_to_ast(&name, span)?))
+            }
+            _ => Expr::Symbol(s.clone(), span),
+        },
+        Value::Quote(inner) => Expr::Quote(Box::new(value_to_ast(inner, span)?), span),
+        Value::Cons(_) => {
+            let values = list_values(value)?;
+            let binds_params = matches!(
+                values.first(),
+                Some(Value::Symbol(head)) if head == "lambda" || head == "define"
+            );
+            let mut items = AstList::with_capacity(values.len());
+            for (i, value) in values.iter().enumerate() {
+                items.push(match value {
+                    Value::Cons(_) | Value::Nil if i == 1 && binds_params => {
+                        param_list_to_ast(value, span)?
+                    }
+                    _ => value_to_ast(value, span)?,
+                });
+            }
+            Expr::List(items, span)
+        }
+        _ => return Err(value.clone()),
+    };
+    Ok(Spanned {
+        value: expr.into(),
+        span,
+    })
+}
+
+/// The elements of a list that ends in nil.
+fn list_values(list: &Value) -> Result<Vec<Value>, Value> {
+    let mut values = Vec::new();
+    let mut rest = list.clone();
+    loop {
+        match rest {
+            Value::Nil => return Ok(values),
+            Value::Cons(cell) => {
+                values.push(cell.car().clone());
+                rest = cell.cdr();
+            }
+            _ => return Err(list.clone()),
+        }
+    }
+}
+
+fn param_list_to_ast(list: &Value, span: Span) -> Result<AstNode, Value> {
+    let mut params = ParamList {
+        required: Vec::new(),
+        rest: None,
+        span,
+    };
+    for value in list_values(list)? {
+        let Value::Symbol(name) = &value else {
+            return Err(value);
+        };
+        match name.strip_prefix(REST_PREFIX) {
+            Some(rest) if params.rest.is_none() && !rest.is_empty() => {
+                params.rest = Some(rest.to_string())
+            }
+            _ if params.rest.is_some() => return Err(value),
+            _ => params.required.push(name.clone()),
+        }
     }
+    Ok(Spanned {
+        value: crate::Expr::ParamList(params).into(),
+        span,
+    })
 }
 
 /// Evaluate condition as boolean
@@ -784,7 +1395,135 @@ pub fn evaluate(
     output: crate::atoms::SharedOutput,
     source: crate::errors::SourceContext,
 ) -> Result<Value, crate::errors::SutraError> {
+    let _stage = crate::telemetry::stage("eval", &source);
     let mut context = EvaluationContext::new(world, output, source);
     let result = evaluate_ast_node(expr, &mut context)?;
     Ok(result.value)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn state_with(turn: f64) -> WorldState {
+        let mut state = WorldState::new();
+        state.set(&Path(vec!["turn".to_string()]), Value::Number(turn));
+        state
+    }
+
+    fn turn(state: &WorldState) -> Option<&Value> {
+        state.get(&Path(vec!["turn".to_string()]))
+    }
+
+    #[test]
+    fn test_format_number_rounds_away_float_artifacts() {
+        assert_eq!(format_number(0.1 + 0.2), "0.3");
+        assert_eq!(format_number(1.0 / 3.0), "0.333333333333333");
+        assert_eq!(format_number(0.1), "0.1");
+        assert_eq!(format_number(-0.0), "0");
+        assert_eq!(format_number(100.0), "100");
+        assert_eq!(format_number(1e21), "1e21");
+        assert_eq!(format_number(1.5e-7), "1.5e-7");
+        assert_eq!(format_number(0.000001), "0.000001");
+    }
+
+    #[test]
+    fn test_value_to_ast_inverts_quote() {
+        let source =
+            "(do (define (f a ...rest) (g ...rest)) (lambda () 'x) (h :k a.b \"s\" 1 true))";
+        let context = crate::errors::SourceContext::from_file("test", source);
+        let code = crate::parser::parse(source, context).unwrap().remove(0);
+        let span = Span { start: 3, end: 4 };
+        let ast = value_to_ast(&ast_to_value(&code), span).unwrap();
+        assert_eq!(ast.value.pretty(), code.value.pretty());
+        assert_eq!(ast.span, span);
+
+        let lambda = Value::Lambda(Arc::new(Lambda {
+            params: ParamList {
+                required: Vec::new(),
+                rest: None,
+                span,
+            },
+            body: Box::new(ast),
+            captured_env: HashMap::new(),
+        }));
+        let list = Value::from_list(vec![Value::Symbol("f".to_string()), lambda.clone()]);
+        assert_eq!(value_to_ast(&list, span), Err(lambda));
+    }
+
+    #[test]
+    fn test_history_drops_oldest_checkpoint_past_depth() {
+        let mut history = History::new(2);
+        for n in 1..=3 {
+            history.checkpoint(&state_with(n as f64));
+        }
+
+        let current = state_with(4.0);
+        let previous = history.undo(&current).unwrap();
+        assert_eq!(turn(&previous), Some(&Value::Number(3.0)));
+        let previous = history.undo(&previous).unwrap();
+        assert_eq!(turn(&previous), Some(&Value::Number(2.0)));
+        assert!(history.undo(&previous).is_none());
+    }
+
+    #[test]
+    fn test_history_depth_zero_disables_checkpoints() {
+        let mut history = History::new(0);
+        history.checkpoint(&state_with(1.0));
+        assert!(!history.can_undo());
+    }
+
+    /// `(unless <condition> <body>)`: a special form defined outside the crate's atoms.
+    const ATOM_UNLESS: NativeFn = |args, context, call_span| {
+        if evaluate_condition_as_bool(&args[0], context)? {
+            return Ok(SpannedValue {
+                value: Value::Nil,
+                span: *call_span,
+            });
+        }
+        evaluate_ast_node(&args[1], context)
+    };
+
+    fn eval_with_unless(code: &str) -> Result<Value, SutraError> {
+        use crate::atoms::{register_all_atoms, AtomKind, AtomSpec, NullSink, SharedOutput, World};
+
+        let mut world = World::new();
+        register_all_atoms(&mut world);
+        let spec = AtomSpec::new("unless", AtomKind::SpecialForm, 2, &["Bool"], "Inverse if.");
+        world.register_atom(spec, ATOM_UNLESS);
+
+        let source = crate::errors::SourceContext::from_file("test", code);
+        let program = crate::parser::wrap_in_do(crate::parser::parse(code, source.clone())?);
+        let world = crate::prelude::CanonicalWorld::from_world(world);
+        evaluate(&program, world, SharedOutput::new(NullSink), source)
+    }
+
+    #[test]
+    fn test_registered_special_form_controls_evaluation() {
+        assert_eq!(
+            eval_with_unless("(unless false 7)").unwrap(),
+            Value::Number(7.0)
+        );
+        // The body would fail if it were evaluated.
+        assert_eq!(
+            eval_with_unless("(unless true (error \"evaluated\"))").unwrap(),
+            Value::Nil
+        );
+        assert!(eval_with_unless("(unless true)").is_err());
+    }
+
+    #[cfg(feature = "sync")]
+    #[test]
+    fn test_sync_worlds_are_shared_across_threads() {
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<crate::prelude::CanonicalWorld>();
+
+        let world = crate::atoms::build_canonical_world();
+        let hp = Path(vec!["hp".to_string()]);
+        let (writer, path) = (world.clone(), hp.clone());
+        std::thread::spawn(move || writer.write().set(&path, Value::Number(3.0)))
+            .join()
+            .unwrap();
+        assert_eq!(world.read().get(&hp), Some(&Value::Number(3.0)));
+    }
+}
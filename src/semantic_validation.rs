@@ -1,50 +1,148 @@
+use std::collections::{BTreeMap, BTreeSet};
+
 use crate::{
-    errors::{to_source_span, ErrorKind, SourceContext, SutraError},
-    macros::MacroSystem,
+    atoms::{AtomKind, Capability},
+    errors::{
+        closest_name, to_source_span, DetachedContext, ErrorKind, ErrorReporting, SourceContext,
+        SutraError,
+    },
+    macros::{
+        module::{self, module_definitions},
+        MacroSystem,
+    },
     prelude::*,
+    syntax::{fold_nodes, walk_node, Visitor},
     MacroDefinition,
 };
 
-/// Validates an AST for semantic correctness: undefined symbols and macro arity.
-/// Returns all validation errors found.
+/// Validates an AST for semantic correctness: undefined symbols, macro and atom
+/// arity, and uses of a module's unexported definitions. Returns all validation errors
+/// found; an undefined symbol close to a known name suggests it.
 pub fn validate_ast_semantics(
     ast: &AstNode,
     macros: &MacroSystem,
     world: &World,
     _source: &SourceContext,
 ) -> Vec<SutraError> {
-    let mut errors = Vec::new();
-    validate_node(ast, macros, world, &mut errors);
+    let private = private_names(ast);
+    let errors = fold_nodes(ast, Vec::new(), |mut errors, node| {
+        match &*node.value {
+            Expr::Symbol(symbol, _) => {
+                if let Some((module, name)) = private.get(symbol) {
+                    errors.push(create_semantic_error(
+                        ErrorKind::PrivateSymbol {
+                            symbol: name.clone(),
+                            module: module.clone(),
+                        },
+                        node,
+                    ));
+                }
+            }
+            _ => {
+                let call = call_of_symbol(node).filter(|(name, _)| !private.contains_key(*name));
+                if let Some((name, nodes)) = call {
+                    validate_call(name, nodes, macros, world, &mut errors);
+                }
+            }
+        }
+        errors
+    });
+    if !errors
+        .iter()
+        .any(|error| matches!(error.kind, ErrorKind::UndefinedSymbol { .. }))
+    {
+        return errors;
+    }
+
+    let mut names = bound_names(ast);
+    names.extend(macros.macro_names());
+    names.extend(world.atom_specs.keys().cloned());
+    names.extend(world.defs.keys().cloned());
+    if let Some(Value::Map(globals)) = world.get(&Path(vec![])) {
+        names.extend(globals.keys().cloned());
+    }
     errors
+        .into_iter()
+        .map(|error| {
+            let suggestion = match &error.kind {
+                ErrorKind::UndefinedSymbol { symbol } => {
+                    closest_name(symbol, names.iter().map(String::as_str)).map(str::to_string)
+                }
+                _ => None,
+            };
+            match suggestion {
+                Some(suggestion) => error.with_suggestion(&suggestion),
+                None => error,
+            }
+        })
+        .collect()
 }
 
-fn validate_node(
-    node: &AstNode,
-    macros: &MacroSystem,
-    world: &World,
-    errors: &mut Vec<SutraError>,
-) {
-    match &*node.value {
-        Expr::List(nodes, _) if !nodes.is_empty() => {
-            if let Expr::Symbol(name, _) = &*nodes[0].value {
-                validate_call(name, nodes, macros, world, errors);
-            }
-            // Validate all children
-            for child in nodes {
-                validate_node(child, macros, world, errors);
+/// The qualified names of the unexported definitions of the modules in `ast`, each
+/// with its module and plain name.
+fn private_names(ast: &AstNode) -> BTreeMap<String, (String, String)> {
+    let mut names = BTreeMap::new();
+    for module in module_definitions(ast).into_iter().flatten() {
+        for name in module.defined_names() {
+            if !module.exports.contains(&name) {
+                let qualified = format!("{}/{}", module.name, name);
+                names.insert(qualified, (module.name.clone(), name));
             }
         }
-        Expr::If {
-            condition,
-            then_branch,
-            else_branch,
-            ..
-        } => {
-            validate_node(condition, macros, world, errors);
-            validate_node(then_branch, macros, world, errors);
-            validate_node(else_branch, macros, world, errors);
+    }
+    names
+}
+
+/// Collects the names `ast` binds: parameters, `define`d names and `let` bindings.
+pub(crate) fn bound_names(ast: &AstNode) -> BTreeSet<String> {
+    fold_nodes(ast, BTreeSet::new(), |mut names, node| {
+        match &*node.value {
+            Expr::ParamList(params) => {
+                names.extend(params.required.iter().cloned());
+                names.extend(params.rest.iter().cloned());
+            }
+            Expr::List(items, _) => match items.as_slice() {
+                [head, name, ..] if is_symbol(head, "define") => {
+                    if let Expr::Symbol(name, _) = &*name.value {
+                        names.insert(name.clone());
+                    }
+                }
+                [head, bindings, ..] if is_symbol(head, "let") => {
+                    if let Expr::List(bindings, _) = &*bindings.value {
+                        names.extend(bindings.iter().filter_map(binding_name));
+                    }
+                }
+                _ => {}
+            },
+            _ => {}
         }
-        _ => {} // Atoms don't need validation
+        names
+    })
+}
+
+/// The head symbol and items of a non-empty list whose head is a symbol.
+fn call_of_symbol(node: &AstNode) -> Option<(&str, &[AstNode])> {
+    let Expr::List(items, _) = &*node.value else {
+        return None;
+    };
+    match items.first().map(|head| &*head.value) {
+        Some(Expr::Symbol(name, _)) => Some((name, items)),
+        _ => None,
+    }
+}
+
+fn is_symbol(node: &AstNode, name: &str) -> bool {
+    matches!(&*node.value, Expr::Symbol(symbol, _) if symbol == name)
+}
+
+/// The name of a `(name value)` let binding.
+fn binding_name(binding: &AstNode) -> Option<String> {
+    let Expr::List(pair, _) = &*binding.value else {
+        return None;
+    };
+    match pair.first().map(|name| &*name.value) {
+        Some(Expr::Symbol(name, _)) => Some(name.clone()),
+        _ => None,
     }
 }
 
@@ -55,11 +153,8 @@ fn validate_call(
     world: &World,
     errors: &mut Vec<SutraError>,
 ) {
-    // Skip special forms
-    if matches!(
-        name,
-        "define" | "if" | "lambda" | "let" | "do" | "error" | "apply"
-    ) {
+    // Module forms are scoped at expansion
+    if name == module::MODULE || name == module::EXPORT {
         return;
     }
 
@@ -95,6 +190,26 @@ fn validate_call(
         return;
     }
 
+    // Check atom arity against its spec; a spread argument's length is unknown
+    if let Some(spec) = world.atom_specs.get(name) {
+        let actual = nodes.len() - 1;
+        let has_spread = nodes[1..]
+            .iter()
+            .any(|node| matches!(&*node.value, Expr::Spread(_)));
+        if !has_spread && !spec.accepts(actual) {
+            let mut error = create_semantic_error(
+                ErrorKind::ArityMismatch {
+                    expected: spec.expected(),
+                    actual,
+                },
+                &nodes[0],
+            );
+            error.diagnostic_info.help = Some(format!("usage: {}", spec.usage()).into());
+            errors.push(error);
+        }
+        return;
+    }
+
     // Check if atom exists
     if world.get(&Path(vec![name.to_string()])).is_some() {
         return;
@@ -109,23 +224,585 @@ fn validate_call(
     ));
 }
 
+/// Finds uses of atoms that need a capability outside `granted`, whether called or
+/// referred to, as in `(define p print)`. Quoted data is not a use. Returns one error
+/// per denied use, in source order.
+pub fn check_capabilities(ast: &AstNode, world: &World, granted: &[Capability]) -> Vec<SutraError> {
+    struct Capabilities<'w> {
+        world: &'w World,
+        granted: &'w [Capability],
+        errors: Vec<SutraError>,
+    }
+
+    impl Visitor<'_> for Capabilities<'_> {
+        fn visit_node(&mut self, node: &AstNode) {
+            match &*node.value {
+                Expr::Symbol(name, _) => {
+                    let missing = (self.world.atom_specs.get(name))
+                        .and_then(|spec| spec.missing_capability(self.granted));
+                    if let Some(capability) = missing {
+                        self.errors.push(create_semantic_error(
+                            ErrorKind::CapabilityDenied {
+                                atom: name.to_string(),
+                                capability: capability.to_string(),
+                            },
+                            node,
+                        ));
+                    }
+                }
+                _ if call_of_symbol(node).is_some_and(|(head, _)| head == "quote") => {}
+                _ => walk_node(self, node),
+            }
+        }
+    }
+
+    let mut check = Capabilities {
+        world,
+        granted,
+        errors: Vec::new(),
+    };
+    check.visit_node(ast);
+    check.errors
+}
+
+/// Finds `set!` calls on a literal nested path whose parent neither holds a map in
+/// `world` nor is written by an earlier `set!` or `ensure-path!`, so the call would
+/// create it. Returns one warning per call, in source order.
+pub fn check_path_writes(ast: &AstNode, world: &World) -> Vec<SutraError> {
+    let mut check = PathWrites {
+        world,
+        written: Vec::new(),
+        warnings: Vec::new(),
+    };
+    check.visit_node(ast);
+    check.warnings
+}
+
+/// Walks calls innermost first, as they run, remembering the paths written so far.
+struct PathWrites<'w> {
+    world: &'w World,
+    written: Vec<Path>,
+    warnings: Vec<SutraError>,
+}
+
+impl Visitor<'_> for PathWrites<'_> {
+    fn visit_node(&mut self, node: &AstNode) {
+        walk_node(self, node);
+        let Some((name, nodes)) = call_of_symbol(node) else {
+            return;
+        };
+        if name != "set!" && name != "ensure-path!" {
+            return;
+        }
+        let Some(path) = nodes.get(1).and_then(literal_path) else {
+            return;
+        };
+        if name == "set!" && !parent_exists(&path, self.world, &self.written) {
+            let parent = Path(path.0[..path.0.len() - 1].to_vec());
+            let mut warning = create_semantic_warning(
+                format!("set! of '{}' creates the missing path '{}'", path, parent),
+                &nodes[1],
+            );
+            warning.diagnostic_info.help = Some(
+                format!(
+                    "initialize it first with (ensure-path! {} (core/map))",
+                    parent
+                )
+                .into(),
+            );
+            self.warnings.push(warning);
+        }
+        self.written.push(path);
+    }
+}
+
+/// Finds definitions among the top-level forms `nodes` of a script or prelude that
+/// hide something already defined: an atom, a macro, an earlier definition in the
+/// same file or a value `world` already defines. Also finds `define` and `lambda`
+/// parameters the body never names; a parameter whose name starts with `_` is meant
+/// to go unused. Forms inside a module are only checked for unused parameters, since
+/// their names are qualified by the module. Returns one warning per issue, in source
+/// order.
+pub fn check_definitions(
+    nodes: &[AstNode],
+    world: &World,
+    macros: &MacroSystem,
+) -> Vec<SutraError> {
+    let mut warnings = Vec::new();
+    let mut defined = BTreeSet::new();
+    let span = Span {
+        start: 0,
+        end: nodes.last().map_or(0, |node| node.span.end),
+    };
+    for node in module::gather_forms(nodes.to_vec(), span) {
+        if let Some((name, name_node)) = defined_name(&node) {
+            let again = !defined.insert(name.to_string()) || world.defs.contains_key(name);
+            let hidden = if world.atom_specs.contains_key(name) {
+                Some("the atom")
+            } else if macros.get_macro(name).is_some() {
+                Some("the macro")
+            } else if again {
+                Some("an earlier definition")
+            } else {
+                None
+            };
+            if let Some(hidden) = hidden {
+                let mut warning = create_semantic_warning(
+                    format!("'{name}' hides {hidden} of the same name"),
+                    name_node,
+                );
+                warning.diagnostic_info.help = Some("rename one of the two".into());
+                warnings.push(warning);
+            }
+        }
+        let mut unused = UnusedParams(Vec::new());
+        unused.visit_node(&node);
+        warnings.extend(unused.0);
+    }
+    warnings
+}
+
+/// The forms a script with an entry point may have at top level, all of which only
+/// define things.
+const DEFINING_FORMS: &[&str] = &[
+    "define",
+    "defrecord",
+    "defproto",
+    "deftable",
+    "defstate",
+    "module",
+];
+
+/// Whether `nodes` define the function `entry`, as in `(define (main) ...)`, at top
+/// level. An entry point is called with no arguments, so one that takes parameters
+/// is an error.
+pub fn defines_entry_point(nodes: &[AstNode], entry: &str) -> Result<bool, SutraError> {
+    for node in nodes {
+        let Some(("define", items)) = call_of_symbol(node) else {
+            continue;
+        };
+        // A body of several forms leaves the signature a plain list
+        let (name, takes_params) = match items.get(1).map(|signature| &*signature.value) {
+            Some(Expr::ParamList(params)) => (
+                params.required.first(),
+                params.required.len() > 1 || params.rest.is_some(),
+            ),
+            Some(Expr::List(signature, _)) => (
+                signature.first().and_then(|name| match &*name.value {
+                    Expr::Symbol(name, _) => Some(name),
+                    _ => None,
+                }),
+                signature.len() > 1,
+            ),
+            _ => continue,
+        };
+        if name.map(String::as_str) != Some(entry) {
+            continue;
+        }
+        if takes_params {
+            let message = format!("the entry point '{entry}' must take no parameters");
+            let mut error =
+                create_semantic_error(ErrorKind::GeneralValidation { message }, &items[1]);
+            error.diagnostic_info.help =
+                Some("read the script's arguments with (args) instead".into());
+            return Err(error);
+        }
+        return Ok(true);
+    }
+    Ok(false)
+}
+
+/// Checks the top level of a script run with the entry point `entry`: each form
+/// must define something, since the entry point is called once every script has
+/// loaded and anything else would run before it. Fails at the first form that does
+/// not.
+pub fn check_entry_script(nodes: &[AstNode], entry: &str) -> Result<(), SutraError> {
+    let defines = |node: &AstNode| {
+        call_of_symbol(node).is_some_and(|(head, _)| DEFINING_FORMS.contains(&head))
+    };
+    let Some(node) = nodes.iter().find(|node| !defines(node)) else {
+        return Ok(());
+    };
+    let message = format!(
+        "only definitions may be at the top level of a script with the entry point '{entry}'"
+    );
+    let mut error = create_semantic_error(ErrorKind::GeneralValidation { message }, node);
+    error.diagnostic_info.help = Some(format!("move this into '{entry}'").into());
+    Err(error)
+}
+
+/// The top-level forms a load-time snapshot can stand in for, whose only effect is to
+/// add to a world's definitions, prototypes or weighted tables.
+const LOAD_TIME_FORMS: &[&str] = &["define", "defrecord", "defproto", "core/deftable", "do"];
+
+/// How many of a script's prepared top-level `forms`, from the first, do the same
+/// thing whenever they run after `world`'s definitions: each only defines something,
+/// computing its values with pure atoms alone, never the world state, output, PRNG
+/// or clock. A name the script or `world` binds is not taken for the atom it hides,
+/// and a form that mentions a function is not pure, since an atom such as `map` may
+/// call it.
+pub fn load_time_prefix(forms: &[AstNode], world: &World) -> usize {
+    let mut bound: BTreeSet<String> = world.defs.keys().cloned().collect();
+    for form in forms {
+        bound.extend(bound_names(form));
+    }
+    let functions = world
+        .defs
+        .iter()
+        .filter(|(_, value)| matches!(value, Value::Lambda(_) | Value::NativeFn(_)))
+        .map(|(name, _)| name.clone())
+        .collect();
+    let mut check = LoadTimeCheck {
+        world,
+        bound,
+        functions,
+    };
+    forms
+        .iter()
+        .take_while(|form| {
+            let pure = check.defines_purely(form);
+            check.note_functions(form);
+            pure
+        })
+        .count()
+}
+
+struct LoadTimeCheck<'w> {
+    world: &'w World,
+    /// Names bound by the script or the world, which may not mean the atom.
+    bound: BTreeSet<String>,
+    /// Names bound to functions so far.
+    functions: BTreeSet<String>,
+}
+
+impl LoadTimeCheck<'_> {
+    fn defines_purely(&self, form: &AstNode) -> bool {
+        let Some((head, items)) = call_of_symbol(form) else {
+            return false;
+        };
+        if !LOAD_TIME_FORMS.contains(&head) || self.bound.contains(head) {
+            return false;
+        }
+        match (head, &items[1..]) {
+            // A function's body runs only when it is called, however it is defined
+            ("define", [name, value]) if matches!(&*name.value, Expr::Symbol(..)) => {
+                call_of_symbol(value)
+                    .is_some_and(|(head, _)| head == "lambda" && !self.bound.contains(head))
+                    || self.computes_purely(value)
+            }
+            ("define", [signature, ..]) => {
+                matches!(&*signature.value, Expr::ParamList(_) | Expr::List(..))
+            }
+            ("defrecord", _) => true,
+            ("defproto", args) => args.iter().all(|arg| match &*arg.value {
+                Expr::List(clause, _) => clause.iter().skip(1).all(|v| self.computes_purely(v)),
+                _ => true,
+            }),
+            ("do", forms) => forms.iter().all(|form| self.defines_purely(form)),
+            (_, args) => args.iter().all(|arg| self.computes_purely(arg)),
+        }
+    }
+
+    fn computes_purely(&self, expr: &AstNode) -> bool {
+        fold_nodes(expr, true, |pure, node| {
+            pure && match &*node.value {
+                Expr::Symbol(name, _) => !self.functions.contains(name),
+                Expr::List(items, _) => match items.first().map(|head| &*head.value) {
+                    Some(Expr::Symbol(head, _)) => self.is_pure_atom(head),
+                    Some(_) => false,
+                    None => true,
+                },
+                _ => true,
+            }
+        })
+    }
+
+    fn is_pure_atom(&self, name: &str) -> bool {
+        !self.bound.contains(name)
+            && self.world.atom_specs.get(name).is_some_and(|spec| {
+                spec.kind != AtomKind::Stateful && spec.missing_capability(&[]).is_none()
+            })
+    }
+
+    /// Notes the functions `form` defines, such as `(define (f) ...)`.
+    fn note_functions(&mut self, form: &AstNode) {
+        let Some((head, items)) = call_of_symbol(form) else {
+            return;
+        };
+        let name = match (head, &items[1..]) {
+            ("define", [target, rest @ ..]) => match (&*target.value, rest) {
+                (Expr::Symbol(name, _), [value]) => call_of_symbol(value)
+                    .is_some_and(|(head, _)| head == "lambda")
+                    .then_some(name),
+                (Expr::ParamList(params), _) => params.required.first(),
+                (Expr::List(signature, _), _) => {
+                    signature.first().and_then(|name| match &*name.value {
+                        Expr::Symbol(name, _) => Some(name),
+                        _ => None,
+                    })
+                }
+                _ => None,
+            },
+            ("do", forms) => {
+                forms.iter().for_each(|form| self.note_functions(form));
+                None
+            }
+            _ => None,
+        };
+        self.functions.extend(name.cloned());
+    }
+}
+
+/// Turns a warning into an error, for `--deny warnings`.
+pub fn deny(mut warning: SutraError) -> SutraError {
+    warning.diagnostic_info.error_code = Some("validation.semantic.error".into());
+    warning
+}
+
+/// The name a top-level `(define name value)` or `(define (name ...) body)` defines,
+/// and the node that names it.
+fn defined_name(node: &AstNode) -> Option<(&str, &AstNode)> {
+    let (head, items) = call_of_symbol(node)?;
+    if head != "define" {
+        return None;
+    }
+    let name_node = items.get(1)?;
+    match &*name_node.value {
+        Expr::Symbol(name, _) => Some((name, name_node)),
+        Expr::ParamList(params) => Some((params.required.first()?, name_node)),
+        _ => None,
+    }
+}
+
+/// Collects warnings for the parameters of `define` and `lambda` forms that their
+/// body never names.
+struct UnusedParams(Vec<SutraError>);
+
+impl Visitor<'_> for UnusedParams {
+    fn visit_node(&mut self, node: &AstNode) {
+        walk_node(self, node);
+        let Some((head, [_, params_node, body @ ..])) = call_of_symbol(node) else {
+            return;
+        };
+        let Expr::ParamList(params) = &*params_node.value else {
+            return;
+        };
+        let (owner, skip) = match head {
+            "define" => (params.required.first().map(|name| format!("'{name}'")), 1),
+            "lambda" => (Some("the lambda".to_string()), 0),
+            _ => return,
+        };
+        let (Some(owner), false) = (owner, body.is_empty()) else {
+            return;
+        };
+        let names = params.required.iter().skip(skip).chain(&params.rest);
+        for name in names.filter(|name| !name.starts_with('_')) {
+            if body.iter().any(|node| mentions(node, name)) {
+                continue;
+            }
+            let mut warning = create_semantic_warning(
+                format!("parameter '{name}' of {owner} is never used"),
+                params_node,
+            );
+            warning.diagnostic_info.help =
+                Some(format!("remove it, or rename it to '_{name}' if it must stay").into());
+            self.0.push(warning);
+        }
+    }
+}
+
+/// Whether `node` names `name` as a symbol, or in a string that may interpolate it.
+/// Quoted code counts too, as a macro template substitutes into it.
+fn mentions(node: &AstNode, name: &str) -> bool {
+    struct Mentions<'n> {
+        name: &'n str,
+        found: bool,
+    }
+
+    impl Visitor<'_> for Mentions<'_> {
+        fn visit_node(&mut self, node: &AstNode) {
+            match &*node.value {
+                Expr::Symbol(symbol, _) => self.found |= symbol == self.name,
+                Expr::String(text, _, false) => {
+                    self.found |= text.contains('{') && text.contains(self.name)
+                }
+                _ => walk_node(self, node),
+            }
+        }
+
+        fn visit_quote(&mut self, quoted: &AstNode) {
+            self.visit_node(quoted);
+        }
+    }
+
+    let mut mentions = Mentions { name, found: false };
+    mentions.visit_node(node);
+    mentions.found
+}
+
+/// The path a `set!` argument names when it is written out in the source.
+fn literal_path(node: &AstNode) -> Option<Path> {
+    match &*node.value {
+        Expr::Path(path, _) => Some(path.clone()),
+        Expr::Symbol(s, _) if s.contains('.') => {
+            Some(Path(s.split('.').map(String::from).collect()))
+        }
+        _ => None,
+    }
+}
+
+/// True if `path` is top-level, or its parent holds a map in `world` or lies on an
+/// already written path.
+fn parent_exists(path: &Path, world: &World, written: &[Path]) -> bool {
+    let Some((_, parent)) = path.0.split_last() else {
+        return true;
+    };
+    parent.is_empty()
+        || matches!(world.get(&Path(parent.to_vec())), Some(Value::Map(_)))
+        || written
+            .iter()
+            .any(|path| path.0.len() >= parent.len() && path.0.starts_with(parent))
+}
+
+/// Validation works on the AST alone; callers attach the source with `with_source`.
 fn create_semantic_error(kind: ErrorKind, node: &AstNode) -> SutraError {
-    use crate::errors::{DiagnosticInfo, SourceInfo};
-    use miette::NamedSource;
-    use std::sync::Arc;
-
-    let span = to_source_span(node.span);
-
-    SutraError {
-        kind,
-        source_info: SourceInfo {
-            source: Arc::new(NamedSource::new("semantic_validation", "".to_string())),
-            primary_span: span,
-            phase: "Semantic".into(),
-        },
-        diagnostic_info: DiagnosticInfo {
-            help: None,
-            error_code: "validation.semantic.error".to_string(),
-        },
+    let context = DetachedContext { phase: "Semantic" };
+    let mut error = context.report(kind, to_source_span(node.span));
+    error.diagnostic_info.error_code = Some("validation.semantic.error".into());
+    error
+}
+
+/// A [`create_semantic_error`] reported as a warning.
+fn create_semantic_warning(message: String, node: &AstNode) -> SutraError {
+    let mut warning = create_semantic_error(ErrorKind::GeneralValidation { message }, node);
+    warning.diagnostic_info.error_code = Some("validation.semantic.warning".into());
+    warning
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser;
+
+    #[test]
+    fn undefined_symbols_suggest_known_names() {
+        let source = "(do (define (tick counter) (countr)) (lenn (list 1)) (zzzz))";
+        let ast = parser::parse(source, SourceContext::from_file("test", source))
+            .unwrap()
+            .remove(0);
+        let mut world = World::new();
+        crate::atoms::register_all_atoms(&mut world);
+        let macros = MacroSystem::new();
+        let errors = validate_ast_semantics(
+            &ast,
+            &macros,
+            &world,
+            &SourceContext::from_file("test", source),
+        );
+        let help: Vec<_> = errors
+            .iter()
+            .map(|error| error.diagnostic_info.help.as_deref())
+            .collect();
+        assert_eq!(
+            help,
+            vec![
+                Some("did you mean `counter`?"),
+                Some("did you mean `len`?"),
+                None
+            ]
+        );
+    }
+
+    #[test]
+    fn private_module_definitions_are_reported() {
+        let source =
+            "(module shop (export buy!) (define (buy! x) (charge x)) (define (charge x) x))\n\
+                      (shop/charge 1)";
+        let ast = parser::wrap_in_do(
+            parser::parse(source, SourceContext::from_file("test", source)).unwrap(),
+        );
+        let mut world = World::new();
+        crate::atoms::register_all_atoms(&mut world);
+        let errors = validate_ast_semantics(
+            &ast,
+            &MacroSystem::new(),
+            &world,
+            &SourceContext::from_file("test", source),
+        );
+        let private: Vec<_> = errors
+            .iter()
+            .filter(|error| matches!(error.kind, ErrorKind::PrivateSymbol { .. }))
+            .map(|error| error.to_string())
+            .collect();
+        assert_eq!(
+            private,
+            vec!["Runtime error: 'charge' is not exported by module 'shop'"]
+        );
+    }
+
+    #[test]
+    fn definitions_that_hide_names_and_unused_parameters_warn() {
+        let source = "(define (len xs) xs)
+                      (define (when c) c)
+                      (define (f x _y) \"{x}\")
+                      (define (f) (lambda (a ...rest) a))
+                      (define (m q) '(q))
+                      (module shop (export len) (define (len z) 1))";
+        let nodes = parser::parse(source, SourceContext::from_file("test", source)).unwrap();
+        let mut world = World::new();
+        crate::atoms::register_all_atoms(&mut world);
+        let mut macros = MacroSystem::new();
+        macros
+            .load_from_source("test", "(define (when c) c)")
+            .unwrap();
+        let warnings: Vec<_> = check_definitions(&nodes, &world, &macros)
+            .iter()
+            .map(|warning| warning.to_string())
+            .collect();
+        assert_eq!(
+            warnings,
+            vec![
+                "Warning: 'len' hides the atom of the same name",
+                "Warning: 'when' hides the macro of the same name",
+                "Warning: 'f' hides an earlier definition of the same name",
+                "Warning: parameter 'rest' of the lambda is never used",
+                "Warning: parameter 'z' of 'len' is never used",
+            ]
+        );
+    }
+
+    #[test]
+    fn scripts_with_an_entry_point_only_define_things() {
+        let parse =
+            |source: &str| parser::parse(source, SourceContext::from_file("test", source)).unwrap();
+        let script = parse("(define (main) (greet) 2) (define (greet) 1) (defrecord point x y)");
+        assert!(defines_entry_point(&script, "main").unwrap());
+        assert!(!defines_entry_point(&script, "start").unwrap());
+        assert!(check_entry_script(&script, "main").is_ok());
+
+        let error = check_entry_script(&parse("(define (main) 1) (println 2)"), "main");
+        assert!(error.unwrap_err().to_string().contains("only definitions"));
+        let error = defines_entry_point(&parse("(define (main who) who who)"), "main");
+        assert!(error.unwrap_err().to_string().contains("no parameters"));
+    }
+
+    #[test]
+    fn load_time_prefixes_end_at_the_first_form_that_does_more_than_define() {
+        let world = crate::atoms::build_canonical_world();
+        let prefix = |source: &str| {
+            let forms = parser::parse(source, SourceContext::from_file("test", source)).unwrap();
+            load_time_prefix(&forms, &world.read())
+        };
+        assert_eq!(
+            prefix("(define (f x) (set! x 1)) (define n (+ 1 2)) (defproto goblin (hp (* 2 3))) (defrecord point (x y)) (print n) (define m 1)"),
+            4
+        );
+        assert_eq!(prefix("(define a 1) (define r (rand)) (define b 2)"), 1);
+        assert_eq!(prefix("(define (f x) x) (define xs (map f (list 1)))"), 1);
+        assert_eq!(prefix("(define (+ a b) 0) (define n (+ 1 2))"), 1);
+        assert_eq!(
+            prefix("(define f (lambda () (println \"hi\"))) (define (g) (println \"hi\")) (f)"),
+            2
+        );
     }
 }
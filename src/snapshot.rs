@@ -0,0 +1,416 @@
+//! Engine Snapshots
+//!
+//! An [`EngineState`] is what a host saves mid-scene to resume later: the world's
+//! state, tags, definitions, prototypes, weighted tables and state machines, its
+//! PRNG, the current locale, and every coroutine those refer to, suspended where it
+//! was. It serializes to JSON.
+//!
+//! Registered atoms, macros, message catalogs and undo history are not saved; they
+//! come from the pipeline a snapshot is restored into. A suspended coroutine's stack
+//! cannot be saved, so restoring runs it again from its record (see
+//! [`crate::atoms::coroutines`]). That rebuilds the same coroutine when what it does
+//! depends only on the values it was resumed with and the world changes it made
+//! itself; when it yields anything else, restoring fails rather than resume a
+//! different story. A coroutine that resumes another coroutine, asks a `choice` or
+//! reads input cannot be rebuilt.
+//!
+//! Every snapshot records its format version, and one written in another format
+//! fails to restore with an error naming both.
+
+use std::{
+    cell::RefCell,
+    collections::{BTreeMap, BTreeSet},
+    sync::Arc,
+};
+
+use serde::{
+    de::DeserializeOwned, ser::Error as _, Deserialize, Deserializer, Serialize, Serializer,
+};
+
+use crate::{
+    atoms::{
+        coroutines::{Coroutine, CoroutineRecord},
+        prototypes::Prototype,
+        roll_table::RollTable,
+        state_machine::StateMachine,
+        Path, SmallRng, World, WorldHandle,
+    },
+    cli::ExecutionPipeline,
+    errors::{ErrorKind, SourceContext, SutraError},
+    runtime::{EvaluationContext, Value},
+};
+
+/// The snapshot format this engine writes and reads.
+pub const SNAPSHOT_VERSION: u64 = 2;
+
+thread_local! {
+    /// The coroutines of the snapshot being written or read; values refer to them by
+    /// their index here.
+    static COROUTINES: RefCell<Option<Vec<Arc<Coroutine>>>> = const { RefCell::new(None) };
+}
+
+/// Holds [`COROUTINES`] for one snapshot, clearing it when dropped.
+struct CoroutineTable;
+
+impl CoroutineTable {
+    fn open(coroutines: Vec<Arc<Coroutine>>) -> Self {
+        COROUTINES.with(|table| *table.borrow_mut() = Some(coroutines));
+        Self
+    }
+
+    fn get(&self, index: usize) -> Option<Arc<Coroutine>> {
+        COROUTINES.with(|table| table.borrow().as_ref()?.get(index).cloned())
+    }
+
+    fn take(self) -> Vec<Arc<Coroutine>> {
+        COROUTINES.with(|table| table.borrow_mut().take().unwrap_or_default())
+    }
+}
+
+impl Drop for CoroutineTable {
+    fn drop(&mut self) {
+        COROUTINES.with(|table| *table.borrow_mut() = None);
+    }
+}
+
+/// Writes a coroutine as its index in the snapshot being written.
+pub(crate) fn save_coroutine<S: Serializer>(
+    coroutine: &Arc<Coroutine>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    let index = COROUTINES.with(|table| {
+        let mut table = table.borrow_mut();
+        let table = table.as_mut()?;
+        let index = match table.iter().position(|c| Arc::ptr_eq(c, coroutine)) {
+            Some(index) => index,
+            None => {
+                table.push(Arc::clone(coroutine));
+                table.len() - 1
+            }
+        };
+        Some(index)
+    });
+    match index {
+        Some(index) => serializer.serialize_u64(index as u64),
+        None => Err(S::Error::custom(
+            "a coroutine can only be saved in an engine snapshot",
+        )),
+    }
+}
+
+/// Reads a coroutine index of the snapshot being read.
+pub(crate) fn load_coroutine<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Arc<Coroutine>, D::Error> {
+    use serde::de::Error;
+    let index = usize::deserialize(deserializer)?;
+    COROUTINES
+        .with(|table| table.borrow().as_ref()?.get(index).cloned())
+        .ok_or_else(|| D::Error::custom(format!("no saved coroutine {index}")))
+}
+
+fn invalid(reason: impl ToString) -> SutraError {
+    SutraError::without_source(
+        ErrorKind::InvalidSnapshot {
+            reason: reason.to_string(),
+        },
+        "snapshot",
+        "runtime",
+    )
+}
+
+fn to_json(value: &impl Serialize) -> Result<serde_json::Value, SutraError> {
+    serde_json::to_value(value).map_err(invalid)
+}
+
+/// Removes and reads the field `name` of a snapshot.
+fn take_field<T: DeserializeOwned>(
+    json: &mut serde_json::Value,
+    name: &str,
+) -> Result<T, SutraError> {
+    let field = json.get_mut(name).map(serde_json::Value::take);
+    let field = field.ok_or_else(|| invalid(format!("it has no '{name}'")))?;
+    serde_json::from_value(field).map_err(|e| invalid(format!("its '{name}' is invalid: {e}")))
+}
+
+/// Everything needed to resume a world later; see the [module docs](self).
+pub struct EngineState {
+    state: BTreeMap<String, Value>,
+    tags: BTreeMap<String, BTreeSet<Path>>,
+    defs: BTreeMap<String, Value>,
+    prototypes: BTreeMap<String, Prototype>,
+    roll_tables: BTreeMap<String, RollTable>,
+    state_machines: BTreeMap<String, StateMachine>,
+    prng: SmallRng,
+    locale: String,
+    /// Coroutines read by [`deserialize`](Self::deserialize), still to be rebuilt.
+    coroutines: Vec<(Arc<Coroutine>, CoroutineRecord)>,
+}
+
+impl EngineState {
+    /// The parts of `world` a snapshot keeps.
+    pub fn capture(world: &World) -> Self {
+        Self {
+            state: world.state.entries(),
+            tags: world.state.tags().clone(),
+            defs: world.defs.clone(),
+            prototypes: world.prototypes.clone(),
+            roll_tables: world.roll_tables.clone(),
+            state_machines: world.state_machines.clone(),
+            prng: world.prng.clone(),
+            locale: world.localization.locale.clone(),
+            coroutines: Vec::new(),
+        }
+    }
+
+    /// Writes the snapshot as JSON. Fails if it holds a value with no saved form,
+    /// such as a native function stored in the world.
+    pub fn serialize(&self) -> Result<String, SutraError> {
+        let table =
+            CoroutineTable::open(self.coroutines.iter().map(|(c, _)| Arc::clone(c)).collect());
+        let mut json = serde_json::json!({
+            "version": SNAPSHOT_VERSION,
+            "engine": env!("CARGO_PKG_VERSION"),
+            "state": to_json(&self.state)?,
+            "tags": to_json(&self.tags)?,
+            "defs": to_json(&self.defs)?,
+            "prototypes": to_json(&self.prototypes)?,
+            "roll_tables": to_json(&self.roll_tables)?,
+            "state_machines": to_json(&self.state_machines)?,
+            "prng": to_json(&self.prng)?,
+            "locale": self.locale,
+        });
+        // Records can refer to coroutines not met yet, which join the table.
+        let mut records = Vec::new();
+        while let Some(coroutine) = table.get(records.len()) {
+            let record = match self.coroutines.get(records.len()) {
+                Some((_, record)) => to_json(record)?,
+                None => to_json(&*coroutine.record())?,
+            };
+            records.push(record);
+        }
+        json["coroutines"] = serde_json::Value::Array(records);
+        serde_json::to_string(&json).map_err(invalid)
+    }
+
+    /// Reads a snapshot written by [`serialize`](Self::serialize). Fails if it is not
+    /// one, or was written in another snapshot format.
+    pub fn deserialize(text: &str) -> Result<Self, SutraError> {
+        let mut json: serde_json::Value = serde_json::from_str(text)
+            .map_err(|e| invalid(format!("it is not valid JSON: {e}")))?;
+        match json.get("version").and_then(serde_json::Value::as_u64) {
+            Some(SNAPSHOT_VERSION) => {}
+            Some(version) => {
+                let engine = json.get("engine").and_then(serde_json::Value::as_str);
+                return Err(invalid(format!(
+                    "it was saved in format {version} by sutra {}, but this engine reads only format {SNAPSHOT_VERSION}",
+                    engine.unwrap_or("(unknown version)")
+                )));
+            }
+            None => return Err(invalid("it has no format version")),
+        }
+
+        let records: Vec<serde_json::Value> = take_field(&mut json, "coroutines")?;
+        let table = CoroutineTable::open(
+            records
+                .iter()
+                .map(|_| Arc::new(Coroutine::pending()))
+                .collect(),
+        );
+        let state = take_field(&mut json, "state")?;
+        let tags = take_field(&mut json, "tags")?;
+        let defs = take_field(&mut json, "defs")?;
+        let prototypes = take_field(&mut json, "prototypes")?;
+        let roll_tables = take_field(&mut json, "roll_tables")?;
+        let state_machines = take_field(&mut json, "state_machines")?;
+        let prng = take_field(&mut json, "prng")?;
+        let locale = take_field(&mut json, "locale")?;
+        let records = records
+            .into_iter()
+            .enumerate()
+            .map(|(n, record)| {
+                serde_json::from_value(record)
+                    .map_err(|e| invalid(format!("its coroutine {} is invalid: {e}", n + 1)))
+            })
+            .collect::<Result<Vec<CoroutineRecord>, _>>()?;
+
+        Ok(Self {
+            state,
+            tags,
+            defs,
+            prototypes,
+            roll_tables,
+            state_machines,
+            prng,
+            locale,
+            coroutines: table.take().into_iter().zip(records).collect(),
+        })
+    }
+
+    /// Replaces the state, tags, definitions, prototypes, weighted tables, state
+    /// machines, PRNG and locale of `pipeline`'s world with the snapshot's, then
+    /// rebuilds the snapshot's coroutines. Rebuilt coroutines write to the pipeline's output sink.
+    pub fn restore(self, pipeline: &ExecutionPipeline) -> Result<(), SutraError> {
+        {
+            let mut world = pipeline.world.write();
+            world.state.replace_entries(self.state);
+            world.state.replace_tags(self.tags);
+            world.defs = self.defs;
+            world.prototypes = self.prototypes;
+            world.roll_tables = self.roll_tables;
+            world.state_machines = self.state_machines;
+            world.prng = self.prng;
+            world.localization.locale = self.locale;
+        }
+
+        let mut context = EvaluationContext::with_settings(
+            pipeline.world.clone(),
+            pipeline.output.clone(),
+            SourceContext::from_file("snapshot", ""),
+            pipeline.max_depth,
+        );
+        context.limits = pipeline.limits;
+        for (n, (coroutine, record)) in self.coroutines.into_iter().enumerate() {
+            coroutine.replay(record, &context).map_err(|reason| {
+                invalid(format!(
+                    "coroutine {} could not be rebuilt: {reason}",
+                    n + 1
+                ))
+            })?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::RefCell, rc::Rc};
+
+    use super::*;
+    use crate::atoms::{EngineOutputBuffer, SharedOutput};
+
+    const STORY: &str = r#"
+        (define story
+          (coroutine (lambda ()
+            (do
+              (print "You wake.")
+              (set! gold 10)
+              (define pick (yield "door or window?"))
+              (set! gold (+ (get gold) (rand)))
+              (yield (str+ "you chose " pick))
+              (get gold)))))
+    "#;
+
+    fn pipeline() -> (ExecutionPipeline, Rc<RefCell<EngineOutputBuffer>>) {
+        let buffer = Rc::new(RefCell::new(EngineOutputBuffer::new()));
+        let pipeline = ExecutionPipeline::builder()
+            .with_seed(7)
+            .with_output(SharedOutput(buffer.clone()))
+            .build()
+            .unwrap();
+        (pipeline, buffer)
+    }
+
+    fn save(pipeline: &ExecutionPipeline) -> String {
+        EngineState::capture(&pipeline.world.read())
+            .serialize()
+            .unwrap()
+    }
+
+    fn run(pipeline: &ExecutionPipeline, source: &str) -> Value {
+        pipeline.execute_for_value(source, "test").unwrap()
+    }
+
+    #[test]
+    fn restores_world_and_prng() {
+        let (saved, _) = pipeline();
+        run(
+            &saved,
+            "(do (set! player.hp 12) (define name \"Ada\") (defproto goblin (hp 3)) (tag! player 'hero) (defstate quest quests.main (idle (go done :when (get player.hp))) (done)) (rand))",
+        );
+        let snapshot = save(&saved);
+
+        let (restored, _) = pipeline();
+        run(&restored, "(set! stale true)");
+        EngineState::deserialize(&snapshot)
+            .unwrap()
+            .restore(&restored)
+            .unwrap();
+        let probe = "(list (get player.hp) name (exists? stale) (spawn! npcs.g1 goblin) (with-tag 'hero) (advance! quest go) (rand))";
+        assert_eq!(run(&restored, probe), run(&saved, probe));
+    }
+
+    #[test]
+    fn restores_suspended_coroutines() {
+        let (saved, _) = pipeline();
+        run(&saved, STORY);
+        run(&saved, "(resume story)");
+        let snapshot = save(&saved);
+
+        let (restored, output) = pipeline();
+        EngineState::deserialize(&snapshot)
+            .unwrap()
+            .restore(&restored)
+            .unwrap();
+        assert_eq!(output.borrow().buffer, "", "replays are silent");
+        assert_eq!(run(&restored, "(get gold)"), Value::Number(10.0));
+
+        let rest = "(list (resume story \"door\") (resume story))";
+        assert_eq!(run(&restored, rest), run(&saved, rest));
+        assert_eq!(run(&restored, "(coroutine/done? story)"), Value::Bool(true));
+    }
+
+    #[test]
+    fn restores_coroutines_waiting_at_a_choice() {
+        let (saved, _) = pipeline();
+        run(
+            &saved,
+            "(define scene (coroutine (lambda () (choice ((\"Go north\" (set! room \"forest\")) (\"Rest\" (set! room \"camp\")))))))",
+        );
+        run(&saved, "(resume scene)");
+        let snapshot = save(&saved);
+
+        let (restored, _) = pipeline();
+        EngineState::deserialize(&snapshot)
+            .unwrap()
+            .restore(&restored)
+            .unwrap();
+        run(&restored, "(resume scene 1)");
+        assert_eq!(run(&restored, "(get room)"), Value::String("camp".into()));
+    }
+
+    #[test]
+    fn rejects_coroutines_that_replay_differently() {
+        let (saved, _) = pipeline();
+        run(
+            &saved,
+            "(define co (coroutine (lambda () (do (yield (get x)) (yield (get x))))))",
+        );
+        run(&saved, "(do (resume co) (set! x 2) (resume co))");
+        let snapshot = save(&saved);
+
+        let (restored, _) = pipeline();
+        let error = EngineState::deserialize(&snapshot)
+            .unwrap()
+            .restore(&restored)
+            .unwrap_err();
+        assert!(error
+            .to_string()
+            .contains("resume 2 yielded nil where 2 was recorded"));
+    }
+
+    #[test]
+    fn rejects_other_formats() {
+        let (saved, _) = pipeline();
+        let mut snapshot: serde_json::Value = serde_json::from_str(&save(&saved)).unwrap();
+        snapshot["version"] = serde_json::json!(SNAPSHOT_VERSION + 1);
+        snapshot["engine"] = serde_json::json!("9.0.0");
+        let error = EngineState::deserialize(&snapshot.to_string())
+            .err()
+            .unwrap();
+        assert!(error.to_string().contains(&format!(
+            "it was saved in format {} by sutra 9.0.0, but this engine reads only format {SNAPSHOT_VERSION}",
+            SNAPSHOT_VERSION + 1
+        )));
+    }
+}
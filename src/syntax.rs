@@ -5,13 +5,17 @@
 use std::sync::Arc;
 
 use serde::{Deserialize, Serialize};
+use smallvec::{smallvec, SmallVec};
 
-use crate::Path;
+use crate::{
+    errors::{to_source_span, ErrorKind, ErrorReporting, SutraError},
+    Path,
+};
 
 pub use crate::runtime::{ConsCell, Lambda, Value};
 
 /// Represents a span in the source code.
-#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize, Default)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Serialize, Deserialize, Default)]
 pub struct Span {
     pub start: usize,
     pub end: usize,
@@ -27,13 +31,21 @@ pub struct Spanned<T> {
 /// Canonical AST node type with shared ownership for efficient macro expansion.
 pub type AstNode = Spanned<Arc<Expr>>;
 
+/// The items of a list. Most lists are short calls like `(get player.hp)`, so up to
+/// four items are kept inline in the node rather than in a separate allocation.
+pub type AstList = SmallVec<[AstNode; 4]>;
+
 /// The core AST node for Sutra expressions.
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum Expr {
-    List(Vec<AstNode>, Span),
+    List(AstList, Span),
     Symbol(String, Span),
+    /// `:name` keyword literal; the stored name excludes the colon.
+    Keyword(String, Span),
     Path(Path, Span),
-    String(String, Span),
+    /// String literal. When the flag is set the text is literal, as in a raw `#"..."#`
+    /// string or text already interpolated, and its braces are never interpolated.
+    String(String, Span, bool),
     Number(f64, Span),
     Bool(bool, Span),
     If {
@@ -62,8 +74,9 @@ impl Expr {
         match self {
             List(_, span)
             | Symbol(_, span)
+            | Keyword(_, span)
             | Path(_, span)
-            | String(_, span)
+            | String(_, span, _)
             | Number(_, span)
             | Bool(_, span)
             | If { span, .. }
@@ -73,22 +86,46 @@ impl Expr {
         }
     }
 
+    /// This expression with its own span set to `span`; its children keep theirs.
+    pub fn with_span(&self, span: Span) -> Expr {
+        use Expr::*;
+        let mut expr = self.clone();
+        match &mut expr {
+            List(_, own)
+            | Symbol(_, own)
+            | Keyword(_, own)
+            | Path(_, own)
+            | String(_, own, _)
+            | Number(_, own)
+            | Bool(_, own)
+            | If { span: own, .. }
+            | Quote(_, own) => *own = span,
+            ParamList(param_list) => param_list.span = span,
+            Spread(_) => {}
+        }
+        expr
+    }
+
     /// Converts this expression into a list of expressions if it is a list.
     pub fn into_list(self) -> Option<Vec<AstNode>> {
         if let Expr::List(items, _) = self {
-            return Some(items);
+            return Some(items.into_vec());
         }
         None
     }
 
-    /// Pretty-prints the expression as a string.
+    /// Pretty-prints the expression as source text, which parses back to the same
+    /// expression apart from spans. A literal string with braces is printed with them
+    /// doubled, so it parses back to a string that expands to the same text.
     pub fn pretty(&self) -> String {
         use Expr::*;
         match self {
             List(exprs, _) => Self::pretty_list(exprs),
             Symbol(s, _) => s.clone(),
-            Path(p, _) => format!("(path {})", p.0.join(" ")),
-            String(s, _) => format!("\"{}\"", s),
+            Keyword(k, _) => format!(":{}", k),
+            Path(p, _) => p.0.join("."),
+            String(s, _, false) => format!("\"{}\"", escape_string(s)),
+            String(s, _, true) => format!("\"{}\"", escape_literal_string(s)),
             Number(n, _) => n.to_string(),
             Bool(b, _) => b.to_string(),
             If {
@@ -131,8 +168,9 @@ impl Expr {
         }
         if let Some(rest) = &param_list.rest {
             if !param_list.required.is_empty() {
-                s.push_str(" . ");
+                s.push(' ');
             }
+            s.push_str("...");
             s.push_str(rest);
         }
         s.push(')');
@@ -144,8 +182,9 @@ impl Expr {
         match self {
             Expr::List(_, _) => "List",
             Expr::Symbol(_, _) => "Symbol",
+            Expr::Keyword(_, _) => "Keyword",
             Expr::Path(_, _) => "Path",
-            Expr::String(_, _) => "String",
+            Expr::String(..) => "String",
             Expr::Number(_, _) => "Number",
             Expr::Bool(_, _) => "Bool",
             Expr::If { .. } => "If",
@@ -169,6 +208,208 @@ fn spanned(expr: Expr, span: Span) -> Spanned<Arc<Expr>> {
     }
 }
 
+// ============================================================================
+// AST TRAVERSAL
+// ============================================================================
+
+/// A read-only walk over an AST, for analyses and lints.
+///
+/// Override [`Visitor::visit_node`] to act on each node, calling [`walk_node`] from it
+/// to go on into the node's children; returning without calling it skips them. The
+/// default visits every node. Quoted data is not code, so the walk does not enter a
+/// quote unless [`Visitor::visit_quote`] is overridden.
+///
+/// ```
+/// use sutra::{errors::SourceContext, parser};
+/// use sutra::syntax::{walk_node, AstNode, Expr, Visitor};
+///
+/// /// Counts `print` calls.
+/// struct Prints(usize);
+///
+/// impl<'ast> Visitor<'ast> for Prints {
+///     fn visit_node(&mut self, node: &'ast AstNode) {
+///         if let Expr::List(items, _) = &*node.value {
+///             if items.first().is_some_and(|head| head.value.to_string() == "print") {
+///                 self.0 += 1;
+///             }
+///         }
+///         walk_node(self, node);
+///     }
+/// }
+///
+/// let source = "(do (print 1) (if true (print 2) '(print 3)))";
+/// let ast = parser::parse(source, SourceContext::from_file("doc", source)).unwrap();
+/// let mut prints = Prints(0);
+/// prints.visit_node(&ast[0]);
+/// assert_eq!(prints.0, 2);
+/// ```
+pub trait Visitor<'ast> {
+    fn visit_node(&mut self, node: &'ast AstNode) {
+        walk_node(self, node);
+    }
+
+    /// Called with the expression under a quote. Does nothing by default.
+    fn visit_quote(&mut self, _quoted: &'ast AstNode) {}
+}
+
+/// Visits the direct children of `node`: list items, `if` branches and the symbol of
+/// a spread, each with [`Visitor::visit_node`], and a quoted expression with
+/// [`Visitor::visit_quote`].
+pub fn walk_node<'ast, V: Visitor<'ast> + ?Sized>(visitor: &mut V, node: &'ast AstNode) {
+    match &*node.value {
+        Expr::List(items, _) => {
+            for item in items {
+                visitor.visit_node(item);
+            }
+        }
+        Expr::If {
+            condition,
+            then_branch,
+            else_branch,
+            ..
+        } => {
+            visitor.visit_node(condition);
+            visitor.visit_node(then_branch);
+            visitor.visit_node(else_branch);
+        }
+        Expr::Quote(quoted, _) => visitor.visit_quote(quoted),
+        Expr::Spread(inner) => visitor.visit_node(inner),
+        _ => {}
+    }
+}
+
+/// A transform that rebuilds an AST, such as macro expansion.
+///
+/// Override [`Rewriter::rewrite_node`] to replace nodes, calling [`rewrite_children`]
+/// to rebuild a node from its rewritten children. Rebuilt nodes keep their spans, so
+/// errors in the result still point at the source. As with [`Visitor`], quoted data
+/// is left alone unless [`Rewriter::rewrite_quote`] is overridden. Use
+/// [`std::convert::Infallible`] as the error type of a rewrite that cannot fail.
+pub trait Rewriter {
+    type Error;
+
+    fn rewrite_node(&mut self, node: AstNode) -> Result<AstNode, Self::Error> {
+        rewrite_children(self, node)
+    }
+
+    /// Rewrites the expression under a quote. Returns it unchanged by default.
+    fn rewrite_quote(&mut self, quoted: AstNode) -> Result<AstNode, Self::Error> {
+        Ok(quoted)
+    }
+}
+
+/// Rebuilds `node` from its children, each rewritten with [`Rewriter::rewrite_node`]
+/// or, under a quote, [`Rewriter::rewrite_quote`]. The node keeps its spans; a node
+/// without children, or whose children all come back as they were, is returned as it
+/// is, so a rewrite only allocates along the paths it changes.
+pub fn rewrite_children<R: Rewriter + ?Sized>(
+    rewriter: &mut R,
+    node: AstNode,
+) -> Result<AstNode, R::Error> {
+    let expr = match &*node.value {
+        Expr::List(items, span) => {
+            let rewritten = items
+                .iter()
+                .map(|item| rewriter.rewrite_node(item.clone()))
+                .collect::<Result<AstList, _>>()?;
+            if items
+                .iter()
+                .zip(&rewritten)
+                .all(|(old, new)| unchanged(old, new))
+            {
+                return Ok(node);
+            }
+            Expr::List(rewritten, *span)
+        }
+        Expr::If {
+            condition,
+            then_branch,
+            else_branch,
+            span,
+        } => {
+            let branches = [
+                rewriter.rewrite_node((**condition).clone())?,
+                rewriter.rewrite_node((**then_branch).clone())?,
+                rewriter.rewrite_node((**else_branch).clone())?,
+            ];
+            let old = [condition, then_branch, else_branch];
+            if old
+                .iter()
+                .zip(&branches)
+                .all(|(old, new)| unchanged(old, new))
+            {
+                return Ok(node);
+            }
+            let [condition, then_branch, else_branch] = branches.map(Box::new);
+            Expr::If {
+                condition,
+                then_branch,
+                else_branch,
+                span: *span,
+            }
+        }
+        Expr::Spread(inner) => {
+            let rewritten = rewriter.rewrite_node((**inner).clone())?;
+            if unchanged(inner, &rewritten) {
+                return Ok(node);
+            }
+            Expr::Spread(Box::new(rewritten))
+        }
+        Expr::Quote(quoted, span) => {
+            let rewritten = rewriter.rewrite_quote((**quoted).clone())?;
+            if unchanged(quoted, &rewritten) {
+                return Ok(node);
+            }
+            Expr::Quote(Box::new(rewritten), *span)
+        }
+        _ => return Ok(node),
+    };
+    Ok(spanned(expr, node.span))
+}
+
+/// Whether a rewrite gave back `old` itself rather than a new node.
+fn unchanged(old: &AstNode, new: &AstNode) -> bool {
+    Arc::ptr_eq(&old.value, &new.value) && old.span == new.span
+}
+
+/// Folds `f` over every node of `ast` outside quoted data, parents before children
+/// and in source order.
+pub fn fold_nodes<'ast, T>(ast: &'ast AstNode, init: T, f: impl FnMut(T, &'ast AstNode) -> T) -> T {
+    struct Fold<T, F> {
+        acc: Option<T>,
+        f: F,
+    }
+    impl<'ast, T, F: FnMut(T, &'ast AstNode) -> T> Visitor<'ast> for Fold<T, F> {
+        fn visit_node(&mut self, node: &'ast AstNode) {
+            let acc = self
+                .acc
+                .take()
+                .expect("the fold holds its accumulator between nodes");
+            self.acc = Some((self.f)(acc, node));
+            walk_node(self, node);
+        }
+    }
+    let mut fold = Fold { acc: Some(init), f };
+    fold.visit_node(ast);
+    fold.acc
+        .expect("the fold holds its accumulator between nodes")
+}
+
+/// Applies `f` to every node of `ast` outside quoted data, children before parents,
+/// keeping spans.
+pub fn map_nodes(ast: AstNode, f: impl FnMut(AstNode) -> AstNode) -> AstNode {
+    struct Map<F>(F);
+    impl<F: FnMut(AstNode) -> AstNode> Rewriter for Map<F> {
+        type Error = std::convert::Infallible;
+        fn rewrite_node(&mut self, node: AstNode) -> Result<AstNode, Self::Error> {
+            let node = rewrite_children(self, node)?;
+            Ok((self.0)(node))
+        }
+    }
+    let Ok(mapped) = Map(f).rewrite_node(ast);
+    mapped
+}
+
 /// Helper to check if a span is valid for a given source string.
 pub fn assert_valid_span(span: Span, source: &str) {
     debug_assert!(
@@ -180,20 +421,144 @@ pub fn assert_valid_span(span: Span, source: &str) {
     );
 }
 
+/// Escapes a string's content so that `"<escaped>"` parses back to the same string.
+pub fn escape_string(s: &str) -> String {
+    escape(s, false)
+}
+
+/// Like [`escape_string`], also doubling braces, so that `"<escaped>"` is not taken
+/// for string interpolation and expands back to the same literal text.
+pub fn escape_literal_string(s: &str) -> String {
+    escape(s, true)
+}
+
+fn escape(s: &str, braces: bool) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for ch in s.chars() {
+        match ch {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\t' => escaped.push_str("\\t"),
+            '\r' => escaped.push_str("\\r"),
+            '{' | '}' if braces => {
+                escaped.push(ch);
+                escaped.push(ch);
+            }
+            c if c.is_control() => escaped.push_str(&format!("\\u{{{:x}}}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// A `:name value` pair from a call's argument list.
+#[derive(Debug, Clone, Copy)]
+pub struct KeywordArg<'a> {
+    /// Keyword name without the colon.
+    pub name: &'a str,
+    pub keyword: &'a AstNode,
+    pub value: &'a AstNode,
+}
+
+/// Splits leading `:name value` pairs off `args`, returning them and the remaining arguments.
+///
+/// Used by forms that take options before their main arguments, e.g. `(for-all :runs 10 ...)`.
+pub fn leading_keyword_args<'a>(
+    mut args: &'a [AstNode],
+    reporter: &impl ErrorReporting,
+) -> Result<(Vec<KeywordArg<'a>>, &'a [AstNode]), SutraError> {
+    let mut keywords: Vec<KeywordArg<'a>> = Vec::new();
+    while let Some(keyword) = args.first() {
+        let Expr::Keyword(name, _) = &*keyword.value else {
+            break;
+        };
+        let Some(value) = args.get(1) else {
+            return Err(reporter.missing_element(
+                &format!("value for keyword ':{}'", name),
+                to_source_span(keyword.span),
+            ));
+        };
+        push_keyword_arg(&mut keywords, name, keyword, value, reporter)?;
+        args = &args[2..];
+    }
+    Ok((keywords, args))
+}
+
+/// Splits `args` into positional arguments and the trailing `:name value` pairs.
+///
+/// Once the first keyword appears, every remaining argument must be part of a pair,
+/// e.g. `(spawn-npc "guard" :name "Ada" :hp 10)`.
+pub fn trailing_keyword_args<'a>(
+    args: &'a [AstNode],
+    reporter: &impl ErrorReporting,
+) -> Result<(&'a [AstNode], Vec<KeywordArg<'a>>), SutraError> {
+    let split = args
+        .iter()
+        .position(|arg| matches!(&*arg.value, Expr::Keyword(..)))
+        .unwrap_or(args.len());
+    let (positional, rest) = args.split_at(split);
+
+    let mut keywords: Vec<KeywordArg<'a>> = Vec::new();
+    for pair in rest.chunks(2) {
+        let keyword = &pair[0];
+        let Expr::Keyword(name, _) = &*keyword.value else {
+            return Err(reporter.type_mismatch(
+                "keyword",
+                keyword.value.type_name(),
+                to_source_span(keyword.span),
+            ));
+        };
+        let Some(value) = pair.get(1) else {
+            return Err(reporter.missing_element(
+                &format!("value for keyword ':{}'", name),
+                to_source_span(keyword.span),
+            ));
+        };
+        push_keyword_arg(&mut keywords, name, keyword, value, reporter)?;
+    }
+    Ok((positional, keywords))
+}
+
+fn push_keyword_arg<'a>(
+    keywords: &mut Vec<KeywordArg<'a>>,
+    name: &'a str,
+    keyword: &'a AstNode,
+    value: &'a AstNode,
+    reporter: &impl ErrorReporting,
+) -> Result<(), SutraError> {
+    if let Some(previous) = keywords.iter().find(|arg| arg.name == name) {
+        return Err(reporter.report(
+            ErrorKind::DuplicateDefinition {
+                symbol: format!(":{}", name),
+                original_location: to_source_span(previous.keyword.span),
+            },
+            to_source_span(keyword.span),
+        ));
+    }
+    keywords.push(KeywordArg {
+        name,
+        keyword,
+        value,
+    });
+    Ok(())
+}
+
 /// Utility to construct an Expr from a Value, requiring a span.
 pub fn expr_from_value_with_span(val: Value, span: Span) -> Result<Expr, String> {
     match val {
-        Value::Nil => Ok(Expr::List(vec![], span)),
+        Value::Nil => Ok(Expr::List(AstList::new(), span)),
         Value::Number(n) => Ok(Expr::Number(n, span)),
-        Value::String(s) => Ok(Expr::String(s, span)),
+        Value::String(s) => Ok(Expr::String(s, span, true)),
         Value::Bool(b) => Ok(Expr::Bool(b, span)),
         Value::Symbol(s) => Ok(Expr::Symbol(s, span)),
+        Value::Keyword(k) => Ok(Expr::Keyword(k, span)),
         Value::Quote(v) => {
             let quoted = expr_from_value_with_span(*v, span)?;
             Ok(Expr::Quote(Box::new(spanned(quoted, span)), span))
         }
         Value::Cons(cell) => {
-            let mut items = Vec::new();
+            let mut items = AstList::new();
             let mut current = Value::Cons(cell);
 
             loop {
@@ -214,32 +579,114 @@ pub fn expr_from_value_with_span(val: Value, span: Span) -> Result<Expr, String>
             Ok(Expr::List(items, span))
         }
         Value::Map(map) => {
-            let mut items = Vec::new();
+            let mut items = AstList::new();
             for (k, v) in map {
-                let key_expr = Expr::String(k, span);
+                let key_expr = Expr::String(k, span, true);
                 let val_expr = expr_from_value_with_span(v, span)?;
-                let pair = Expr::List(vec![spanned(key_expr, span), spanned(val_expr, span)], span);
+                let pair = Expr::List(
+                    smallvec![spanned(key_expr, span), spanned(val_expr, span)],
+                    span,
+                );
                 items.push(spanned(pair, span));
             }
             Ok(Expr::List(items, span))
         }
         Value::Path(p) => Ok(Expr::Path(p, span)),
+        Value::Record(_) => {
+            Err("Cannot convert record value to AST expression. This is a logic error.".to_string())
+        }
         Value::Lambda(_) | Value::NativeFn(_) => Err(
             "Cannot convert function value to AST expression. This is a logic error.".to_string(),
         ),
+        Value::Coroutine(_) => Err(
+            "Cannot convert coroutine value to AST expression. This is a logic error.".to_string(),
+        ),
+        Value::StringBuilder(_) => Err(
+            "Cannot convert string builder value to AST expression. This is a logic error."
+                .to_string(),
+        ),
     }
 }
 
-// WARNING: Do not use From<Value> for Expr in production code; use expr_from_value_with_span instead.
-impl From<Value> for Expr {
-    fn from(val: Value) -> Self {
-        // This is only safe for tests or when span is irrelevant.
-        match expr_from_value_with_span(val, Span::default()) {
-            Ok(expr) => expr,
-            Err(msg) => panic!("{}", msg),
+// Re-export parser for backward compatibility
+pub use crate::parser;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::errors::SourceContext;
+
+    fn parse_one(source: &str) -> AstNode {
+        parser::parse(source, SourceContext::from_file("test", source))
+            .unwrap()
+            .remove(0)
+    }
+
+    #[test]
+    fn fold_visits_code_in_order_and_skips_quotes() {
+        let ast = parse_one("(a (b ...c) '(d))");
+        let symbols = fold_nodes(&ast, Vec::new(), |mut symbols, node| {
+            if let Expr::Symbol(name, _) = &*node.value {
+                symbols.push(name.clone());
+            }
+            symbols
+        });
+        assert_eq!(symbols, vec!["a", "b", "c"]);
+    }
+
+    #[test]
+    fn map_rewrites_bottom_up_and_keeps_spans() {
+        let source = "(+ 1 (* 2 3))";
+        let ast = parse_one(source);
+        let doubled = map_nodes(ast.clone(), |node| match &*node.value {
+            Expr::Number(n, span) => Spanned {
+                value: Expr::Number(n * 2.0, *span).into(),
+                span: node.span,
+            },
+            _ => node,
+        });
+        assert_eq!(doubled.value.pretty(), "(+ 2 (* 4 6))");
+        let Expr::List(items, span) = &*doubled.value else {
+            panic!("expected a list");
+        };
+        assert_eq!((*span, doubled.span), (ast.span, ast.span));
+        assert_eq!(&source[items[2].span.start..items[2].span.end], "(* 2 3)");
+    }
+
+    #[test]
+    fn rewriter_errors_stop_the_rewrite() {
+        struct RejectSymbol;
+        impl Rewriter for RejectSymbol {
+            type Error = String;
+            fn rewrite_node(&mut self, node: AstNode) -> Result<AstNode, String> {
+                match &*node.value {
+                    Expr::Symbol(name, _) if name == "bad" => Err(name.clone()),
+                    _ => rewrite_children(self, node),
+                }
+            }
         }
+        assert_eq!(
+            RejectSymbol.rewrite_node(parse_one("(a (b bad))")),
+            Err("bad".to_string())
+        );
+        assert!(RejectSymbol.rewrite_node(parse_one("(a 'bad)")).is_ok());
     }
-}
 
-// Re-export parser for backward compatibility
-pub use crate::parser;
+    #[test]
+    fn rewrites_share_the_nodes_they_leave_alone() {
+        let ast = parse_one("(a (b 1) (c 2))");
+        let same = map_nodes(ast.clone(), |node| node);
+        assert!(Arc::ptr_eq(&ast.value, &same.value));
+
+        let changed = map_nodes(ast.clone(), |node| match &*node.value {
+            Expr::Number(n, span) if *n == 2.0 => spanned(Expr::Number(3.0, *span), node.span),
+            _ => node,
+        });
+        assert_eq!(changed.value.pretty(), "(a (b 1) (c 3))");
+        let (Expr::List(old, _), Expr::List(new, _)) = (&*ast.value, &*changed.value) else {
+            panic!("expected lists");
+        };
+        assert!(Arc::ptr_eq(&old[1].value, &new[1].value));
+        assert!(!Arc::ptr_eq(&old[2].value, &new[2].value));
+    }
+}
@@ -0,0 +1,108 @@
+//! Telemetry
+//!
+//! With the `telemetry` feature, the engine opens `tracing` spans around its pipeline
+//! stages and, at trace level, around each atom call, so a host that already collects
+//! `tracing` data sees where a script spends its time:
+//!
+//! - `sutra.stage`, at info level, with `stage` (`parse`, `expand`, `validate` or
+//!   `eval`) and `source`, the script's name
+//! - `sutra.atom`, at trace level, with `symbol`, `span` (a byte range in the script)
+//!   and `depth`, the evaluation depth of the call
+//!
+//! Without the feature these are empty guards that compile away, so default builds
+//! pay nothing for them.
+
+use crate::{errors::SourceContext, syntax::Span};
+
+/// A span that stays open until dropped.
+#[must_use]
+pub(crate) struct Guard {
+    #[cfg(feature = "telemetry")]
+    _entered: tracing::span::EnteredSpan,
+}
+
+/// Opens the span of a pipeline stage for `source`.
+#[cfg(feature = "telemetry")]
+pub(crate) fn stage(stage: &'static str, source: &SourceContext) -> Guard {
+    Guard {
+        _entered: tracing::info_span!("sutra.stage", stage, source = source.name()).entered(),
+    }
+}
+
+#[cfg(not(feature = "telemetry"))]
+pub(crate) fn stage(_stage: &'static str, _source: &SourceContext) -> Guard {
+    Guard {}
+}
+
+/// Opens the span of a call to the atom `symbol` at `span`.
+#[cfg(feature = "telemetry")]
+pub(crate) fn atom_call(symbol: &str, span: &Span, depth: usize) -> Guard {
+    Guard {
+        _entered: tracing::trace_span!(
+            "sutra.atom",
+            symbol,
+            span = ?(span.start..span.end),
+            depth
+        )
+        .entered(),
+    }
+}
+
+#[cfg(not(feature = "telemetry"))]
+pub(crate) fn atom_call(_symbol: &str, _span: &Span, _depth: usize) -> Guard {
+    Guard {}
+}
+
+#[cfg(all(test, feature = "telemetry"))]
+mod tests {
+    use std::{
+        io,
+        sync::{Arc, Mutex},
+    };
+
+    use tracing_subscriber::fmt::format::FmtSpan;
+
+    use crate::cli::ExecutionPipeline;
+
+    #[derive(Clone, Default)]
+    struct Captured(Arc<Mutex<Vec<u8>>>);
+
+    impl io::Write for Captured {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn pipeline_stages_and_atom_calls_open_spans() {
+        let captured = Captured::default();
+        let writer = captured.clone();
+        let subscriber = tracing_subscriber::fmt()
+            .with_max_level(tracing::Level::TRACE)
+            .with_span_events(FmtSpan::NEW)
+            .with_writer(move || writer.clone())
+            .with_ansi(false)
+            .without_time()
+            .finish();
+        tracing::subscriber::with_default(subscriber, || {
+            ExecutionPipeline::builder()
+                .build()
+                .unwrap()
+                .execute_for_value("(set! x 1)", "spans")
+                .unwrap();
+        });
+        let log = String::from_utf8(captured.0.lock().unwrap().clone()).unwrap();
+        for stage in ["parse", "expand", "validate", "eval"] {
+            assert!(
+                log.contains(&format!("stage=\"{stage}\" source=\"spans\"")),
+                "{stage} missing from:\n{log}"
+            );
+        }
+        assert!(log.contains("symbol=\"set!\" span=1..5 depth="), "{log}");
+    }
+}
@@ -1,71 +1,447 @@
+use std::{collections::BTreeSet, fmt, fs, path::Path, str::FromStr};
+
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+
 use crate::{
-    errors::{ErrorKind, SutraError},
+    discovery::HookKind,
+    errors::{closest_name, codes, lookup_code, ErrorCategory, ErrorKind, SutraError},
     Value,
 };
 
-/// Type-safe expectation enum for test assertions.
-/// This replaces fragile string-based expectation handling.
+/// What a test's `(expect ...)` clause asks of its body. `sutra test` and the
+/// `cargo test` harness both check tests against this one type.
 #[derive(Debug, Clone, PartialEq)]
 pub enum Expectation {
-    /// Expect a specific value result
+    /// The body evaluates to this value.
     Value(Value),
-    /// Expect a specific error type
-    Error(ErrorKind),
-    /// Expect specific output to be produced
+    /// The body fails with an error of this category or kind.
+    Error(ExpectedError),
+    /// The body prints exactly this text. Checked against the captured output, not
+    /// the result.
     Output(String),
+    /// Any value, for a test with only an `(expect-world ...)` form.
+    Any,
 }
 
 impl Expectation {
-    /// Creates a Value expectation
-    pub fn value(val: Value) -> Self {
-        Self::Value(val)
+    /// Whether `result` meets a value, error or any-value expectation. Output
+    /// expectations are not decided by the result, so they never match here.
+    pub fn matches(&self, result: &Result<Value, SutraError>) -> bool {
+        match (self, result) {
+            (Self::Value(expected), Ok(actual)) => expected == actual,
+            (Self::Error(expected), Err(error)) => expected.matches(&error.kind),
+            (Self::Any, Ok(_)) => true,
+            _ => false,
+        }
     }
+}
+
+/// The error named by an `(error ...)` clause: a whole category or one error kind.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExpectedError {
+    Category(ErrorCategory),
+    /// An error kind code such as `type_mismatch`, as listed by [`codes`].
+    Kind(&'static str),
+}
 
-    /// Creates an Error expectation
-    pub fn error(error_type: ErrorKind) -> Self {
-        Self::Error(error_type)
+impl ExpectedError {
+    pub fn matches(&self, kind: &ErrorKind) -> bool {
+        match self {
+            Self::Category(category) => kind.category() == *category,
+            Self::Kind(code) => kind.code() == *code,
+        }
     }
+}
 
-    /// Checks if this expectation matches the given result
-    pub fn matches(&self, result: &Result<Value, SutraError>) -> bool {
-        match (self, result) {
-            (Expectation::Value(expected), Ok(actual)) => expected == actual,
-            (Expectation::Error(expected_type), Err(error)) => &error.kind == expected_type,
-            _ => false,
+impl fmt::Display for ExpectedError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Category(category) => write!(f, "category {:?}", category),
+            Self::Kind(code) => write!(f, "kind {}", code),
+        }
+    }
+}
+
+/// Capitalized names are categories; lowercase names are error codes, which must be
+/// listed in [`codes`].
+impl FromStr for ExpectedError {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "Parse" => Ok(Self::Category(ErrorCategory::Parse)),
+            "Validation" => Ok(Self::Category(ErrorCategory::Validation)),
+            "Runtime" => Ok(Self::Category(ErrorCategory::Runtime)),
+            "Test" => Ok(Self::Category(ErrorCategory::Test)),
+            code if code.starts_with(|c: char| c.is_ascii_lowercase()) => {
+                if let Some(entry) = lookup_code(code) {
+                    return Ok(Self::Kind(entry.code));
+                }
+                let hint = closest_name(code, codes().iter().map(|entry| entry.code))
+                    .map(|name| format!(" (did you mean {name}?)"))
+                    .unwrap_or_default();
+                Err(format!("unknown error code: {code}{hint}"))
+            }
+            _ => Err(format!("unknown error category: {s}")),
         }
     }
 }
 
 /// Test result summary for CLI reporting
-#[derive(Debug, Default)]
+#[derive(Debug, Default, Serialize)]
 pub struct TestSummary {
     pub passed: usize,
+    /// Tests whose result did not match their `(expect ...)` clause.
     pub failed: usize,
+    /// Tests that could not be checked: malformed tests, failing fixtures, or errors
+    /// the test did not expect.
+    pub errored: usize,
+    /// Tests stopped for running past their time limit.
+    pub timed_out: usize,
+    pub skipped: usize,
+    /// Tests that did not pass at first but passed when run again.
+    #[serde(skip_serializing_if = "is_zero")]
+    pub flaky: usize,
+    /// Quarantined tests that did not pass, reported as warnings.
+    #[serde(skip_serializing_if = "is_zero")]
+    pub quarantined: usize,
+    /// Setup and teardown hooks that failed, around a test or a whole file.
+    #[serde(skip_serializing_if = "is_zero")]
+    pub hook_failed: usize,
+    /// Tests left to the other jobs of a sharded run.
+    #[serde(skip_serializing_if = "is_zero")]
+    pub other_shards: usize,
+}
+
+fn is_zero(count: &usize) -> bool {
+    *count == 0
 }
 
 impl TestSummary {
+    pub fn record(&mut self, result: &TestResult) {
+        match result {
+            TestResult::Passed => self.passed += 1,
+            TestResult::Failed(_) => self.failed += 1,
+            TestResult::Errored(_) => self.errored += 1,
+            TestResult::TimedOut(_) => self.timed_out += 1,
+            TestResult::Skipped(_) => self.skipped += 1,
+            TestResult::Flaky(_) => self.flaky += 1,
+            TestResult::Quarantined(_) => self.quarantined += 1,
+            TestResult::HookFailed(..) => self.hook_failed += 1,
+        }
+    }
+
+    pub fn merge(&mut self, other: &TestSummary) {
+        self.passed += other.passed;
+        self.failed += other.failed;
+        self.errored += other.errored;
+        self.timed_out += other.timed_out;
+        self.skipped += other.skipped;
+        self.flaky += other.flaky;
+        self.quarantined += other.quarantined;
+        self.hook_failed += other.hook_failed;
+        self.other_shards += other.other_shards;
+    }
+
     pub fn has_failures(&self) -> bool {
-        self.failed > 0
+        self.failed > 0 || self.errored > 0 || self.timed_out > 0 || self.hook_failed > 0
     }
 
     pub fn total_tests(&self) -> usize {
-        self.passed + self.failed
+        self.passed
+            + self.failed
+            + self.errored
+            + self.timed_out
+            + self.skipped
+            + self.flaky
+            + self.quarantined
+            + self.hook_failed
     }
 
+    /// Tests that passed in the end, including flaky ones.
+    pub fn passing(&self) -> usize {
+        self.passed + self.flaky
+    }
+
+    /// Percentage of the tests that ran (everything but skipped tests) which passed.
     pub fn success_rate(&self) -> f64 {
-        if self.total_tests() == 0 {
+        let ran = self.total_tests() - self.skipped;
+        if ran == 0 {
             return 0.0;
         }
-        (self.passed as f64 / self.total_tests() as f64) * 100.0
+        (self.passing() as f64 / ran as f64) * 100.0
+    }
+}
+
+/// One of `count` parts of a test suite, for splitting it across CI jobs. Each test
+/// belongs to exactly one shard, chosen by hashing its file and name, so the jobs
+/// running shards `1/N` to `N/N` run every test once between them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Shard {
+    /// Which shard, from 1 to `count`.
+    pub index: usize,
+    pub count: usize,
+}
+
+impl Shard {
+    /// Whether the test `name` in `file`, a path relative to the test directory,
+    /// belongs to this shard. The path's separators do not matter, so every
+    /// platform agrees.
+    pub fn includes(&self, file: &Path, name: &str) -> bool {
+        let file: Vec<_> = file
+            .components()
+            .map(|part| part.as_os_str().to_string_lossy())
+            .collect();
+        let mut hasher = Sha256::new();
+        hasher.update(file.join("/").as_bytes());
+        hasher.update([0]);
+        hasher.update(name.as_bytes());
+        let digest = hasher.finalize();
+        let hash = u64::from_le_bytes(digest[..8].try_into().expect("8 bytes"));
+        (hash % self.count as u64) as usize == self.index - 1
+    }
+}
+
+impl fmt::Display for Shard {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.index, self.count)
+    }
+}
+
+impl FromStr for Shard {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("invalid shard '{s}' (expected K/N with 1 <= K <= N, e.g. 2/5)");
+        let (index, count) = s.split_once('/').ok_or_else(invalid)?;
+        let index: usize = index.trim().parse().map_err(|_| invalid())?;
+        let count: usize = count.trim().parse().map_err(|_| invalid())?;
+        if index == 0 || index > count {
+            return Err(invalid());
+        }
+        Ok(Self { index, count })
+    }
+}
+
+/// Tests known to be flaky, whose failures are only warnings. A quarantine file names
+/// one test per line; blank lines and lines starting with `#` are ignored.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Quarantine {
+    names: BTreeSet<String>,
+}
+
+impl Quarantine {
+    pub fn load(path: &Path) -> Result<Self, SutraError> {
+        let text = fs::read_to_string(path).map_err(|e| {
+            SutraError::without_source(
+                ErrorKind::InvalidPath {
+                    path: format!("{} ({})", path.display(), e),
+                },
+                path.display().to_string(),
+                "testing",
+            )
+        })?;
+        let Ok(quarantine) = text.parse();
+        Ok(quarantine)
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.names.contains(name)
+    }
+}
+
+impl FromStr for Quarantine {
+    type Err = std::convert::Infallible;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let names = s
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .map(str::to_string)
+            .collect();
+        Ok(Self { names })
     }
 }
 
 /// Test result for individual test execution
-#[derive(Debug, Clone)]
+#[derive(Debug)]
 pub enum TestResult {
     Passed,
-    Failed,
+    /// The test ran but its result did not match its expectation.
+    Failed(SutraError),
+    /// The test could not be checked against its expectation.
+    Errored(SutraError),
+    /// The test ran past its time limit and was stopped.
+    TimedOut(SutraError),
+    /// The test was marked `:skip`, with the given reason.
+    Skipped(String),
+    /// The test did not pass at first but passed on a retry. Holds the first error.
+    Flaky(SutraError),
+    /// The test is quarantined and did not pass, which is only a warning.
+    Quarantined(SutraError),
+    /// A setup or teardown hook failed, so the test could not be judged.
+    HookFailed(HookKind, SutraError),
+}
+
+impl TestResult {
+    /// The outcome's name in `sutra test --json` reports.
+    pub fn outcome(&self) -> &'static str {
+        match self {
+            Self::Passed => "passed",
+            Self::Failed(_) => "failed",
+            Self::Errored(_) => "errored",
+            Self::TimedOut(_) => "timed_out",
+            Self::Skipped(_) => "skipped",
+            Self::Flaky(_) => "flaky",
+            Self::Quarantined(_) => "quarantined",
+            Self::HookFailed(..) => "hook_failed",
+        }
+    }
+
+    /// The error message, or the reason a skipped test was skipped.
+    pub fn message(&self) -> Option<String> {
+        match self {
+            Self::Passed => None,
+            Self::Failed(e)
+            | Self::Errored(e)
+            | Self::TimedOut(e)
+            | Self::Flaky(e)
+            | Self::Quarantined(e) => Some(e.to_string()),
+            Self::HookFailed(hook, e) => Some(format!("{hook} hook: {e}")),
+            Self::Skipped(reason) => Some(reason.clone()),
+        }
+    }
+
+    /// Whether the test failed, errored or timed out.
+    pub fn is_failure(&self) -> bool {
+        matches!(self, Self::Failed(_) | Self::Errored(_) | Self::TimedOut(_))
+    }
+
+    /// This result for a quarantined test: a failure becomes a warning.
+    pub fn quarantine(self) -> Self {
+        match self {
+            Self::Failed(e) | Self::Errored(e) | Self::TimedOut(e) => Self::Quarantined(e),
+            result => result,
+        }
+    }
+}
+
+/// One run of a retried test: the seed its random number generator was given, and
+/// how the run went.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct Attempt {
+    pub seed: u64,
+    pub outcome: &'static str,
+}
+
+/// One test's outcome in a JSON report.
+#[derive(Debug, Serialize)]
+pub struct TestRecord {
+    pub file: String,
+    pub name: String,
+    pub outcome: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+    /// Every run of a test that was retried, in order.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub attempts: Vec<Attempt>,
+}
+
+impl TestRecord {
+    pub fn new(file: &Path, name: &str, result: &TestResult, attempts: Vec<Attempt>) -> Self {
+        Self {
+            file: file.display().to_string(),
+            name: name.to_string(),
+            outcome: result.outcome(),
+            message: result.message(),
+            attempts,
+        }
+    }
+}
+
+/// Results for a whole test run, as written by `sutra test --json`.
+#[derive(Debug, Default, Serialize)]
+pub struct TestReport {
+    pub summary: TestSummary,
+    /// The seed the run was shuffled with, if it was.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub shuffle_seed: Option<u64>,
+    pub tests: Vec<TestRecord>,
+}
+
+impl TestReport {
+    /// Writes the report as pretty-printed JSON.
+    pub fn save(&self, path: &Path) -> Result<(), SutraError> {
+        let io_error = |e: String| {
+            SutraError::without_source(
+                ErrorKind::InvalidPath {
+                    path: format!("{} ({})", path.display(), e),
+                },
+                path.display().to_string(),
+                "testing",
+            )
+        };
+        let json = serde_json::to_string_pretty(self).map_err(|e| io_error(e.to_string()))?;
+        fs::write(path, json).map_err(|e| io_error(e.to_string()))
+    }
 }
 
 // Re-export test runner for backward compatibility
 pub use crate::test_runner;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shards_split_a_suite_exactly_once() {
+        let tests: Vec<String> = (0..200).map(|i| format!("test {i}")).collect();
+        let shards: Vec<Shard> = (1..=5).map(|i| format!("{i}/5").parse().unwrap()).collect();
+        for name in &tests {
+            let owners = shards
+                .iter()
+                .filter(|shard| shard.includes(Path::new("world/tags.sutra"), name))
+                .count();
+            assert_eq!(owners, 1, "{name}");
+        }
+        let first = tests
+            .iter()
+            .filter(|name| shards[0].includes(Path::new("a.sutra"), name))
+            .count();
+        assert!((20..=60).contains(&first), "{first} of 200 in shard 1/5");
+    }
+
+    #[test]
+    fn quarantine_lists_skip_comments_and_blank_lines() {
+        let quarantine: Quarantine = "# known flaky\n\n  dice roll  \nshuffle\n".parse().unwrap();
+        assert!(quarantine.contains("dice roll"));
+        assert!(quarantine.contains("shuffle"));
+        assert!(!quarantine.contains("# known flaky"));
+    }
+
+    #[test]
+    fn expected_errors_name_a_category_or_a_listed_code() {
+        let mismatch = ErrorKind::TypeMismatch {
+            expected: "Number".into(),
+            actual: "String".into(),
+        };
+        let runtime: ExpectedError = "Runtime".parse().unwrap();
+        let kind: ExpectedError = "type_mismatch".parse().unwrap();
+        let other: ExpectedError = "arity_mismatch".parse().unwrap();
+        assert!(runtime.matches(&mismatch) && kind.matches(&mismatch));
+        assert!(!other.matches(&mismatch));
+        let typo = "type_mismach".parse::<ExpectedError>().unwrap_err();
+        assert!(typo.contains("did you mean type_mismatch?"), "{typo}");
+        assert!("Runtim".parse::<ExpectedError>().is_err());
+    }
+
+    #[test]
+    fn shards_parse_as_k_of_n() {
+        assert_eq!("2/5".parse::<Shard>(), Ok(Shard { index: 2, count: 5 }));
+        for invalid in ["0/5", "6/5", "2", "a/b", "1/0"] {
+            assert!(invalid.parse::<Shard>().is_err(), "{invalid}");
+        }
+    }
+}
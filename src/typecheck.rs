@@ -0,0 +1,633 @@
+//! Sutra Gradual Type Checker
+//!
+//! An opt-in static pass over macro-expanded ASTs. Names can be annotated with
+//! `(: <type> <name>)`, for example `(: (-> Number Number Bool) can-afford?)`;
+//! annotations have no effect at runtime.
+//!
+//! The checker infers coarse types (numbers, strings, bools, lists, maps, paths,
+//! keywords, lambdas) for literals, built-in calls, and annotated definitions, and reports
+//! a mismatch only when both sides are known. Anything it cannot infer is `Any`
+//! and is accepted everywhere, so unannotated code never produces errors.
+
+use std::{collections::HashMap, fmt};
+
+use crate::{
+    errors::{
+        to_source_span, ErrorKind, ErrorReporting, SourceContext, SutraError, ValidationContext,
+    },
+    syntax::{walk_node, AstNode, Expr, ParamList, Span, Visitor},
+};
+
+/// A statically inferred type.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Type {
+    /// Unknown; compatible with every other type.
+    Any,
+    Nil,
+    Number,
+    String,
+    Bool,
+    List,
+    Map,
+    Path,
+    Keyword,
+    /// A function, with its signature when known.
+    Fn(Option<Box<Signature>>),
+}
+
+/// Parameter and return types of a function.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Signature {
+    pub params: Vec<Type>,
+    /// Type of each extra argument, for variadic functions.
+    pub rest: Option<Type>,
+    pub ret: Type,
+}
+
+impl Type {
+    /// Returns true if a value of type `actual` may be used where `self` is expected.
+    pub fn accepts(&self, actual: &Type) -> bool {
+        match (self, actual) {
+            (Type::Any, _) | (_, Type::Any) => true,
+            // The empty list is nil.
+            (Type::List, Type::Nil) => true,
+            (Type::Fn(_), Type::Fn(_)) => true,
+            _ => self == actual,
+        }
+    }
+
+    /// The type of an expression that may produce either `self` or `other`.
+    fn join(self, other: Type) -> Type {
+        match (self, other) {
+            (a, b) if a == b => a,
+            (Type::List, Type::Nil) | (Type::Nil, Type::List) => Type::List,
+            _ => Type::Any,
+        }
+    }
+
+    fn is_known(&self) -> bool {
+        !matches!(self, Type::Any)
+    }
+}
+
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Type::Any => write!(f, "Any"),
+            Type::Nil => write!(f, "Nil"),
+            Type::Number => write!(f, "Number"),
+            Type::String => write!(f, "String"),
+            Type::Bool => write!(f, "Bool"),
+            Type::List => write!(f, "List"),
+            Type::Map => write!(f, "Map"),
+            Type::Path => write!(f, "Path"),
+            Type::Keyword => write!(f, "Keyword"),
+            Type::Fn(None) => write!(f, "Lambda"),
+            Type::Fn(Some(sig)) => {
+                write!(f, "(->")?;
+                for param in &sig.params {
+                    write!(f, " {param}")?;
+                }
+                if let Some(rest) = &sig.rest {
+                    write!(f, " ...{rest}")?;
+                }
+                write!(f, " {})", sig.ret)
+            }
+        }
+    }
+}
+
+impl Signature {
+    fn fixed(params: &[Type], ret: Type) -> Self {
+        Self {
+            params: params.to_vec(),
+            rest: None,
+            ret,
+        }
+    }
+
+    fn variadic(rest: Type, ret: Type) -> Self {
+        Self {
+            params: Vec::new(),
+            rest: Some(rest),
+            ret,
+        }
+    }
+}
+
+/// Signatures of the built-in atoms the checker knows about.
+fn builtin_signature(name: &str) -> Option<Signature> {
+    use Type::*;
+    let sig = match name {
+        "+" | "-" | "*" | "/" | "min" | "max" => Signature::variadic(Number, Number),
+        "mod" => Signature::fixed(&[Number, Number], Number),
+        "abs" => Signature::fixed(&[Number], Number),
+        "gt?" | ">" | "over?" | "lt?" | "<" | "under?" | "gte?" | ">=" | "at-least?" | "lte?"
+        | "<=" | "at-most?" => Signature::variadic(Number, Bool),
+        "eq?" | "=" | "is?" | "equal?" => Signature::variadic(Any, Bool),
+        "not" | "null?" | "bool?" | "number?" | "string?" | "list?" | "path?" => {
+            Signature::fixed(&[Any], Bool)
+        }
+        "num->str" => Signature::fixed(&[Number], String),
+        "len" => Signature::fixed(&[Any], Number),
+        "list" => Signature::variadic(Any, List),
+        "car" => Signature::fixed(&[List], Any),
+        "cdr" => Signature::fixed(&[List], List),
+        "cons" => Signature::fixed(&[Any, List], List),
+        "append" => Signature::variadic(List, List),
+        "str" => Signature::fixed(&[Any], String),
+        "str+" | "core/str+" => Signature::variadic(Any, String),
+        "print" | "println" | "log/debug" | "log/info" | "log/warn" => {
+            Signature::variadic(Any, Nil)
+        }
+        "with-output-to-string" => Signature::variadic(Any, String),
+        "rand" => Signature::fixed(&[], Number),
+        _ => return None,
+    };
+    Some(sig)
+}
+
+/// Type-checks a macro-expanded program and returns every mismatch found.
+pub fn typecheck_ast(ast: &AstNode, source: &SourceContext) -> Vec<SutraError> {
+    let mut checker = TypeChecker {
+        context: ValidationContext::new(source.clone(), "typecheck"),
+        annotations: HashMap::new(),
+        scopes: vec![HashMap::new()],
+        errors: Vec::new(),
+    };
+    checker.visit_node(ast);
+    checker.infer(ast);
+    checker.errors
+}
+
+struct TypeChecker {
+    context: ValidationContext,
+    /// Declared types from `(: <type> <name>)` forms anywhere in the program.
+    annotations: HashMap<String, Type>,
+    /// Inferred types of bound names, innermost scope last.
+    scopes: Vec<HashMap<String, Type>>,
+    errors: Vec<SutraError>,
+}
+
+/// Gathers annotations up front so they apply regardless of where they appear.
+impl Visitor<'_> for TypeChecker {
+    fn visit_node(&mut self, node: &AstNode) {
+        match &*node.value {
+            Expr::List(items, _) if head_symbol(items) == Some(":") => {
+                self.record_annotation(node, items)
+            }
+            _ => walk_node(self, node),
+        }
+    }
+}
+
+impl TypeChecker {
+    // ------------------------------------------------------------------------
+    // Annotations
+    // ------------------------------------------------------------------------
+
+    fn record_annotation(&mut self, node: &AstNode, items: &[AstNode]) {
+        let [_, type_node, name_node] = items else {
+            self.malformed(node.span);
+            return;
+        };
+        let Expr::Symbol(name, _) = &*name_node.value else {
+            self.malformed(name_node.span);
+            return;
+        };
+        if let Some(ty) = self.parse_type(type_node) {
+            self.annotations.insert(name.clone(), ty);
+        }
+    }
+
+    fn parse_type(&mut self, node: &AstNode) -> Option<Type> {
+        let ty = match &*node.value {
+            Expr::Symbol(name, _) => match name.as_str() {
+                "Any" => Type::Any,
+                "Nil" => Type::Nil,
+                "Number" => Type::Number,
+                "String" => Type::String,
+                "Bool" => Type::Bool,
+                "List" => Type::List,
+                "Map" => Type::Map,
+                "Path" => Type::Path,
+                "Keyword" => Type::Keyword,
+                "Lambda" => Type::Fn(None),
+                _ => {
+                    self.malformed(node.span);
+                    return None;
+                }
+            },
+            Expr::List(items, _) if head_symbol(items) == Some("->") && items.len() >= 2 => {
+                let mut types = Vec::with_capacity(items.len() - 1);
+                for item in &items[1..] {
+                    types.push(self.parse_type(item)?);
+                }
+                let ret = types.pop()?;
+                Type::Fn(Some(Box::new(Signature::fixed(&types, ret))))
+            }
+            _ => {
+                self.malformed(node.span);
+                return None;
+            }
+        };
+        Some(ty)
+    }
+
+    fn malformed(&mut self, span: Span) {
+        self.errors.push(self.context.report(
+            ErrorKind::MalformedConstruct {
+                construct: "type annotation".to_string(),
+            },
+            to_source_span(span),
+        ));
+    }
+
+    // ------------------------------------------------------------------------
+    // Inference
+    // ------------------------------------------------------------------------
+
+    fn infer(&mut self, node: &AstNode) -> Type {
+        match &*node.value {
+            Expr::Number(..) => Type::Number,
+            Expr::String(..) => Type::String,
+            Expr::Bool(..) => Type::Bool,
+            Expr::Path(..) => Type::Path,
+            Expr::Keyword(..) => Type::Keyword,
+            Expr::Quote(inner, _) => match &*inner.value {
+                Expr::List(items, _) if items.is_empty() => Type::Nil,
+                Expr::List(..) => Type::List,
+                _ => Type::Any,
+            },
+            Expr::Symbol(name, _) => self.lookup(name),
+            Expr::If {
+                condition,
+                then_branch,
+                else_branch,
+                ..
+            } => {
+                self.infer(condition);
+                let then_type = self.infer(then_branch);
+                then_type.join(self.infer(else_branch))
+            }
+            Expr::List(items, _) => self.infer_list(items, node.span),
+            Expr::ParamList(_) | Expr::Spread(_) => Type::Any,
+        }
+    }
+
+    fn infer_list(&mut self, items: &[AstNode], span: Span) -> Type {
+        let Some(first) = items.first() else {
+            return Type::Nil;
+        };
+        let args = &items[1..];
+        match head_symbol(items) {
+            Some(":") => Type::Nil,
+            Some("quote") => Type::Any,
+            Some("do") => self.infer_body(args),
+            Some("if") => self.infer_if(args),
+            Some("define") => self.infer_define(args, span),
+            Some("lambda") => self.infer_lambda(args),
+            Some("let") => self.infer_let(args),
+            Some(name) => {
+                let signature = self.signature_of(name);
+                self.infer_call(name, signature, args, span)
+            }
+            None => {
+                self.infer(first);
+                self.infer_all(args);
+                Type::Any
+            }
+        }
+    }
+
+    fn infer_all(&mut self, nodes: &[AstNode]) {
+        for node in nodes {
+            self.infer(node);
+        }
+    }
+
+    fn infer_body(&mut self, body: &[AstNode]) -> Type {
+        body.iter()
+            .map(|node| self.infer(node))
+            .last()
+            .unwrap_or(Type::Nil)
+    }
+
+    fn infer_if(&mut self, args: &[AstNode]) -> Type {
+        let Some((condition, branches)) = args.split_first() else {
+            return Type::Any;
+        };
+        self.infer(condition);
+        match branches {
+            [then_branch] => self.infer(then_branch).join(Type::Nil),
+            [then_branch, else_branch] => {
+                let then_type = self.infer(then_branch);
+                then_type.join(self.infer(else_branch))
+            }
+            _ => {
+                self.infer_all(branches);
+                Type::Any
+            }
+        }
+    }
+
+    fn infer_call(
+        &mut self,
+        name: &str,
+        signature: Option<Signature>,
+        args: &[AstNode],
+        span: Span,
+    ) -> Type {
+        let arg_types: Vec<Type> = args.iter().map(|arg| self.infer(arg)).collect();
+        let Some(signature) = signature else {
+            return Type::Any;
+        };
+
+        let arity_ok = match signature.rest {
+            Some(_) => args.len() >= signature.params.len(),
+            None => args.len() == signature.params.len(),
+        };
+        if !arity_ok {
+            let expected = match signature.rest {
+                Some(_) => format!("at least {}", signature.params.len()),
+                None => signature.params.len().to_string(),
+            };
+            self.errors.push(self.context.arity_mismatch(
+                &format!("{expected} for '{name}'"),
+                args.len(),
+                to_source_span(span),
+            ));
+            return signature.ret;
+        }
+
+        let expected_types = signature.params.iter().chain(std::iter::repeat(
+            signature.rest.as_ref().unwrap_or(&Type::Any),
+        ));
+        for ((arg, actual), expected) in args.iter().zip(&arg_types).zip(expected_types) {
+            self.expect(expected, actual, arg.span);
+        }
+        signature.ret
+    }
+
+    /// Checks `(define name value)` and `(define (name params...) body...)`.
+    fn infer_define(&mut self, args: &[AstNode], span: Span) -> Type {
+        let Some((target, body)) = args.split_first() else {
+            return Type::Any;
+        };
+        let (name, params) = match &*target.value {
+            Expr::Symbol(name, _) => {
+                let value_type = self.infer_body(body);
+                let declared = self.annotations.get(name).cloned();
+                let bound = match declared {
+                    Some(declared) => {
+                        self.expect(
+                            &declared,
+                            &value_type,
+                            body.first().map_or(span, |b| b.span),
+                        );
+                        declared
+                    }
+                    None => value_type,
+                };
+                self.bind(name, bound.clone());
+                return bound;
+            }
+            Expr::ParamList(ParamList { required, rest, .. }) if !required.is_empty() => {
+                (required[0].clone(), (required[1..].to_vec(), rest.clone()))
+            }
+            Expr::List(items, _) => match symbol_names(items).split_first() {
+                Some((name, params)) => (name.clone(), (params.to_vec(), None)),
+                None => return Type::Any,
+            },
+            _ => return Type::Any,
+        };
+
+        let signature = match self.annotations.get(&name).cloned() {
+            Some(Type::Fn(signature)) => signature,
+            Some(declared) => {
+                self.expect(&declared, &Type::Fn(None), target.span);
+                None
+            }
+            None => None,
+        };
+        let fn_type = Type::Fn(signature.clone());
+        self.bind(&name, fn_type.clone());
+
+        let (required, rest) = params;
+        if let Some(signature) = &signature {
+            if signature.params.len() != required.len() {
+                self.errors.push(self.context.arity_mismatch(
+                    &format!(
+                        "{} parameter(s) per the annotation of '{}'",
+                        signature.params.len(),
+                        name
+                    ),
+                    required.len(),
+                    to_source_span(target.span),
+                ));
+            }
+        }
+
+        let mut scope: HashMap<String, Type> = required
+            .iter()
+            .enumerate()
+            .map(|(i, param)| {
+                let ty = signature
+                    .as_ref()
+                    .and_then(|sig| sig.params.get(i).cloned())
+                    .unwrap_or(Type::Any);
+                (param.clone(), ty)
+            })
+            .collect();
+        if let Some(rest) = rest {
+            scope.insert(rest, Type::List);
+        }
+
+        self.scopes.push(scope);
+        let body_type = self.infer_body(body);
+        self.scopes.pop();
+
+        if let Some(signature) = &signature {
+            let body_span = body.last().map_or(span, |b| b.span);
+            self.expect(&signature.ret, &body_type, body_span);
+        }
+        fn_type
+    }
+
+    fn infer_lambda(&mut self, args: &[AstNode]) -> Type {
+        let Some((params, body)) = args.split_first() else {
+            return Type::Fn(None);
+        };
+        let mut scope = HashMap::new();
+        match &*params.value {
+            Expr::ParamList(ParamList { required, rest, .. }) => {
+                scope.extend(required.iter().map(|p| (p.clone(), Type::Any)));
+                if let Some(rest) = rest {
+                    scope.insert(rest.clone(), Type::List);
+                }
+            }
+            Expr::List(items, _) => {
+                scope.extend(symbol_names(items).into_iter().map(|p| (p, Type::Any)));
+            }
+            _ => {}
+        }
+        self.scopes.push(scope);
+        self.infer_body(body);
+        self.scopes.pop();
+        Type::Fn(None)
+    }
+
+    fn infer_let(&mut self, args: &[AstNode]) -> Type {
+        let Some((bindings, body)) = args.split_first() else {
+            return Type::Any;
+        };
+        self.scopes.push(HashMap::new());
+        if let Expr::List(pairs, _) = &*bindings.value {
+            pairs.iter().for_each(|pair| self.bind_let_pair(pair));
+        }
+        let result = self.infer_body(body);
+        self.scopes.pop();
+        result
+    }
+
+    fn bind_let_pair(&mut self, pair: &AstNode) {
+        let Expr::List(items, _) = &*pair.value else {
+            return;
+        };
+        let [name_node, value_node] = items.as_slice() else {
+            return;
+        };
+        let value_type = self.infer(value_node);
+        if let Expr::Symbol(name, _) = &*name_node.value {
+            self.bind(name, value_type);
+        }
+    }
+
+    // ------------------------------------------------------------------------
+    // Environment
+    // ------------------------------------------------------------------------
+
+    fn bind(&mut self, name: &str, ty: Type) {
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.insert(name.to_string(), ty);
+        }
+    }
+
+    fn is_bound_locally(&self, name: &str) -> bool {
+        self.scopes.iter().any(|scope| scope.contains_key(name))
+    }
+
+    fn lookup(&self, name: &str) -> Type {
+        if name == "nil" {
+            return Type::Nil;
+        }
+        self.scopes
+            .iter()
+            .rev()
+            .find_map(|scope| scope.get(name).cloned())
+            .or_else(|| self.annotations.get(name).cloned())
+            .unwrap_or(Type::Any)
+    }
+
+    /// The signature to check calls to `name` against: annotated, then local, then built-in.
+    fn signature_of(&self, name: &str) -> Option<Signature> {
+        match self.lookup(name) {
+            Type::Fn(Some(signature)) => Some(*signature),
+            Type::Any if !self.is_bound_locally(name) => builtin_signature(name),
+            _ => None,
+        }
+    }
+
+    fn expect(&mut self, expected: &Type, actual: &Type, span: Span) {
+        if expected.is_known() && !expected.accepts(actual) {
+            self.errors.push(self.context.type_mismatch(
+                &expected.to_string(),
+                &actual.to_string(),
+                to_source_span(span),
+            ));
+        }
+    }
+}
+
+fn head_symbol(items: &[AstNode]) -> Option<&str> {
+    match items.first().map(|node| &*node.value) {
+        Some(Expr::Symbol(name, _)) => Some(name.as_str()),
+        _ => None,
+    }
+}
+
+fn symbol_names(items: &[AstNode]) -> Vec<String> {
+    items
+        .iter()
+        .filter_map(|node| match &*node.value {
+            Expr::Symbol(name, _) => Some(name.clone()),
+            _ => None,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser;
+
+    fn check(source: &str) -> Vec<SutraError> {
+        let context = SourceContext::from_file("test", source);
+        let nodes = parser::parse(source, context.clone()).expect("source should parse");
+        typecheck_ast(&parser::wrap_in_do(nodes), &context)
+    }
+
+    #[test]
+    fn test_unannotated_code_has_no_errors() {
+        assert!(check("(define (f x) (+ x 1)) (f \"a\")").is_empty());
+    }
+
+    #[test]
+    fn test_builtin_argument_mismatch() {
+        assert_eq!(check("(+ 1 \"two\")").len(), 1);
+    }
+
+    #[test]
+    fn test_annotated_call_mismatch() {
+        let errors = check(
+            "(: (-> Number Number Bool) can-afford?)
+             (define (can-afford? gold cost) (>= gold cost))
+             (can-afford? \"lots\" 3)",
+        );
+        assert_eq!(errors.len(), 1);
+    }
+
+    #[test]
+    fn test_annotated_return_mismatch() {
+        let errors = check(
+            "(: (-> Number String) label)
+             (define (label n) (+ n 1))",
+        );
+        assert_eq!(errors.len(), 1);
+    }
+
+    #[test]
+    fn test_annotated_arity_mismatch() {
+        let errors = check(
+            "(: (-> Number Number) double)
+             (define (double n) (* n 2))
+             (double 1 2)",
+        );
+        assert_eq!(errors.len(), 1);
+    }
+
+    #[test]
+    fn test_annotated_variable_mismatch() {
+        assert_eq!(check("(: Number gold) (define gold \"many\")").len(), 1);
+    }
+
+    #[test]
+    fn test_inferred_variable_flows_into_calls() {
+        assert_eq!(check("(define name \"Ada\") (* name 2)").len(), 1);
+    }
+
+    #[test]
+    fn test_malformed_annotation() {
+        assert_eq!(check("(: Integer gold)").len(), 1);
+    }
+}
@@ -0,0 +1,99 @@
+//! Watch Mode
+//!
+//! `sutra run --watch` and `sutra test --watch` run once, then again each time a watched
+//! file changes. Editors often write a file several times per save, so changes are
+//! collected until none arrive for [`DEBOUNCE`] before the next run starts.
+//!
+//! Every run parses all of its files again.
+
+use std::{
+    path::{Path, PathBuf},
+    sync::mpsc::{self, Receiver, RecvTimeoutError},
+    time::Duration,
+};
+
+use notify::{Event, EventKind, RecursiveMode, Watcher};
+
+use crate::errors::{ErrorKind, SutraError};
+
+/// How long the files must stay unchanged before a change triggers a run.
+pub const DEBOUNCE: Duration = Duration::from_millis(200);
+
+/// Calls `run` now and after every change under `paths`, clearing the screen first.
+/// Returns only if the watcher fails.
+pub fn watch(paths: &[PathBuf], mut run: impl FnMut()) -> Result<(), SutraError> {
+    let watched = paths
+        .iter()
+        .map(|path| path.display().to_string())
+        .collect::<Vec<_>>()
+        .join(", ");
+    let (tx, rx) = mpsc::channel();
+    let mut watcher = notify::recommended_watcher(tx).map_err(|e| watch_error(&watched, e))?;
+    for path in paths {
+        // Editors may save by replacing a file, which ends a watch on the file itself.
+        let (path, mode) = match path.parent() {
+            Some(parent) if path.is_file() => (parent, RecursiveMode::NonRecursive),
+            _ => (path.as_path(), RecursiveMode::Recursive),
+        };
+        let path = if path.as_os_str().is_empty() {
+            Path::new(".")
+        } else {
+            path
+        };
+        watcher
+            .watch(path, mode)
+            .map_err(|e| watch_error(&watched, e))?;
+    }
+
+    loop {
+        clear_screen();
+        run();
+        eprintln!("\nWatching {watched} for changes...");
+        wait_for_change(&rx).map_err(|e| watch_error(&watched, e))?;
+    }
+}
+
+/// Blocks until a file changes, then until changes stop for [`DEBOUNCE`].
+fn wait_for_change(rx: &Receiver<notify::Result<Event>>) -> notify::Result<()> {
+    loop {
+        let event = rx.recv().map_err(|_| disconnected())??;
+        if is_change(&event) {
+            break;
+        }
+    }
+    loop {
+        match rx.recv_timeout(DEBOUNCE) {
+            Ok(event) => {
+                event?;
+            }
+            Err(RecvTimeoutError::Timeout) => return Ok(()),
+            Err(RecvTimeoutError::Disconnected) => return Err(disconnected()),
+        }
+    }
+}
+
+/// Reads and other metadata-only events do not trigger a run.
+fn is_change(event: &Event) -> bool {
+    matches!(
+        event.kind,
+        EventKind::Create(_) | EventKind::Modify(_) | EventKind::Remove(_)
+    )
+}
+
+fn clear_screen() {
+    print!("\x1b[2J\x1b[H");
+}
+
+fn disconnected() -> notify::Error {
+    notify::Error::generic("the watcher stopped")
+}
+
+fn watch_error(watched: &str, error: notify::Error) -> SutraError {
+    SutraError::without_source(
+        ErrorKind::GeneralValidation {
+            message: format!("cannot watch {watched} ({error})"),
+        },
+        watched,
+        "watch",
+    )
+}
@@ -0,0 +1,292 @@
+//! World Diffing
+//!
+//! Compares two world states path by path. Maps are walked key by key, so adding
+//! `player` with two fields reports `player.hp` and `player.name`. Any other value
+//! is reported whole at its path: a changed list is one change, not one per element.
+//! Changes come out in path order because maps are sorted.
+//!
+//! A diff can be replayed onto another state with [`WorldDiff::apply_to`]. Each change
+//! records the value it expects to find, so a patch applied to a world that has moved
+//! on since the diff was taken fails with a [`ConflictError`] instead of clobbering it.
+
+use std::fmt;
+
+use crate::{
+    atoms::{Path, WorldState},
+    runtime::Value,
+    syntax::escape_string,
+};
+
+/// A single difference between two world states.
+#[derive(Debug, Clone, PartialEq)]
+pub enum WorldChange {
+    Added { path: Path, value: Value },
+    Removed { path: Path, value: Value },
+    Changed { path: Path, old: Value, new: Value },
+}
+
+impl WorldChange {
+    pub fn path(&self) -> &Path {
+        match self {
+            Self::Added { path, .. } | Self::Removed { path, .. } | Self::Changed { path, .. } => {
+                path
+            }
+        }
+    }
+}
+
+impl WorldChange {
+    /// The value this change expects to find at its path before it is applied.
+    fn base(&self) -> Option<&Value> {
+        match self {
+            Self::Added { .. } => None,
+            Self::Removed { value, .. } => Some(value),
+            Self::Changed { old, .. } => Some(old),
+        }
+    }
+
+    fn conflict(&self, state: &WorldState) -> Option<Conflict> {
+        let path = self.path();
+        let found = state.get(path);
+        (found != self.base()).then(|| Conflict {
+            path: path.clone(),
+            expected: self.base().cloned(),
+            found: found.cloned(),
+        })
+    }
+}
+
+impl fmt::Display for WorldChange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Added { path, value } => write!(f, "+ {path}: {}", render(value)),
+            Self::Removed { path, value } => write!(f, "- {path}: {}", render(value)),
+            Self::Changed { path, old, new } => {
+                write!(f, "~ {path}: {} -> {}", render(old), render(new))
+            }
+        }
+    }
+}
+
+/// The changes that turn one world state into another, in path order.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct WorldDiff {
+    pub changes: Vec<WorldChange>,
+}
+
+impl WorldDiff {
+    /// Computes the changes from `before` to `after`.
+    pub fn between(before: &WorldState, after: &WorldState) -> Self {
+        let root = Path(Vec::new());
+        Self::between_values(
+            &root,
+            before.get(&root).unwrap_or(&Value::Nil),
+            after.get(&root).unwrap_or(&Value::Nil),
+        )
+    }
+
+    /// Computes the changes from `old` to `new`, two values held at `path`.
+    pub fn between_values(path: &Path, old: &Value, new: &Value) -> Self {
+        let mut diff = Self::default();
+        diff_values(&mut path.0.clone(), old, new, &mut diff.changes);
+        diff
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+
+    /// Applies the changes to a copy of `state`. Nothing is applied if any change
+    /// conflicts: an added path must be absent, and removed or changed paths must still
+    /// hold their old value.
+    pub fn apply_to(&self, state: &WorldState) -> Result<WorldState, ConflictError> {
+        let conflicts: Vec<Conflict> = self
+            .changes
+            .iter()
+            .filter_map(|change| change.conflict(state))
+            .collect();
+        if !conflicts.is_empty() {
+            return Err(ConflictError { conflicts });
+        }
+
+        let mut patched = state.clone();
+        for change in &self.changes {
+            match change {
+                WorldChange::Added { path, value } => patched.set(path, value.clone()),
+                WorldChange::Changed { path, new, .. } => patched.set(path, new.clone()),
+                WorldChange::Removed { path, .. } => patched.del(path),
+            }
+        }
+        Ok(patched)
+    }
+}
+
+/// One change per line, e.g. `~ player.hp: 10 -> 7`.
+impl fmt::Display for WorldDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for change in &self.changes {
+            writeln!(f, "{change}")?;
+        }
+        Ok(())
+    }
+}
+
+/// A path whose current value is not the one a patch was computed against.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Conflict {
+    pub path: Path,
+    /// The value the patch expected; `None` if it expected the path to be absent.
+    pub expected: Option<Value>,
+    /// The value actually present; `None` if the path is absent.
+    pub found: Option<Value>,
+}
+
+/// Returned when a diff no longer applies cleanly, listing every conflicting path.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConflictError {
+    pub conflicts: Vec<Conflict>,
+}
+
+impl fmt::Display for ConflictError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "world diff conflicts with the current state at")?;
+        for conflict in &self.conflicts {
+            write!(
+                f,
+                "\n  {}: expected {}, found {}",
+                conflict.path,
+                render_slot(conflict.expected.as_ref()),
+                render_slot(conflict.found.as_ref())
+            )?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for ConflictError {}
+
+fn diff_values(path: &mut Vec<String>, old: &Value, new: &Value, changes: &mut Vec<WorldChange>) {
+    let (Value::Map(old_map), Value::Map(new_map)) = (old, new) else {
+        if old != new {
+            changes.push(WorldChange::Changed {
+                path: Path(path.clone()),
+                old: old.clone(),
+                new: new.clone(),
+            });
+        }
+        return;
+    };
+
+    let mut keys: Vec<&String> = old_map.keys().chain(new_map.keys()).collect();
+    keys.sort();
+    keys.dedup();
+    for key in keys {
+        path.push(key.clone());
+        diff_entry(path, old_map.get(key), new_map.get(key), changes);
+        path.pop();
+    }
+}
+
+fn diff_entry(
+    path: &mut Vec<String>,
+    old: Option<&Value>,
+    new: Option<&Value>,
+    changes: &mut Vec<WorldChange>,
+) {
+    match (old, new) {
+        (Some(old), Some(new)) => diff_values(path, old, new, changes),
+        (Some(old), None) => push_leaves(path, old, changes, |path, value| WorldChange::Removed {
+            path,
+            value,
+        }),
+        (None, Some(new)) => push_leaves(path, new, changes, |path, value| WorldChange::Added {
+            path,
+            value,
+        }),
+        (None, None) => {}
+    }
+}
+
+/// Reports every leaf of an added or removed subtree; empty maps count as leaves.
+fn push_leaves(
+    path: &mut Vec<String>,
+    value: &Value,
+    changes: &mut Vec<WorldChange>,
+    change: fn(Path, Value) -> WorldChange,
+) {
+    match value {
+        Value::Map(map) if !map.is_empty() => {
+            for (key, child) in map {
+                path.push(key.clone());
+                push_leaves(path, child, changes, change);
+                path.pop();
+            }
+        }
+        _ => changes.push(change(Path(path.clone()), value.clone())),
+    }
+}
+
+fn render_slot(value: Option<&Value>) -> String {
+    value.map_or_else(|| "nothing".to_string(), render)
+}
+
+fn render(value: &Value) -> String {
+    match value {
+        Value::String(s) => format!("\"{}\"", escape_string(s)),
+        other => other.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn state(entries: &[(&str, Value)]) -> WorldState {
+        let mut state = WorldState::new();
+        for (path, value) in entries {
+            state.set(
+                &Path(path.split('.').map(String::from).collect()),
+                value.clone(),
+            );
+        }
+        state
+    }
+
+    #[test]
+    fn test_diff_reports_leaf_paths_in_order() {
+        let before = state(&[("gold", Value::Number(3.0)), ("hp", Value::Number(10.0))]);
+        let after = state(&[
+            ("hp", Value::Number(7.0)),
+            ("player.name", Value::String("Ada".into())),
+        ]);
+        assert_eq!(
+            WorldDiff::between(&before, &after).to_string(),
+            "- gold: 3\n~ hp: 10 -> 7\n+ player.name: \"Ada\"\n"
+        );
+    }
+
+    #[test]
+    fn test_apply_replays_diff() {
+        let before = state(&[("gold", Value::Number(3.0)), ("a.b", Value::Bool(true))]);
+        let after = state(&[("gold", Value::Number(5.0)), ("c", Value::Nil)]);
+        let diff = WorldDiff::between(&before, &after);
+
+        let patched = diff.apply_to(&before).unwrap();
+        assert!(WorldDiff::between(&patched, &after).is_empty());
+    }
+
+    #[test]
+    fn test_apply_lists_every_conflict_and_changes_nothing() {
+        let before = state(&[("gold", Value::Number(3.0)), ("hp", Value::Number(10.0))]);
+        let after = state(&[("hp", Value::Number(7.0)), ("xp", Value::Number(1.0))]);
+        let diff = WorldDiff::between(&before, &after);
+
+        let moved_on = state(&[("hp", Value::Number(8.0)), ("xp", Value::Number(1.0))]);
+        let error = diff.apply_to(&moved_on).unwrap_err();
+        let paths: Vec<String> = error.conflicts.iter().map(|c| c.path.to_string()).collect();
+        assert_eq!(paths, ["gold", "hp", "xp"]);
+        assert_eq!(error.conflicts[0].found, None);
+        assert_eq!(error.conflicts[1].found, Some(Value::Number(8.0)));
+        assert_eq!(error.conflicts[2].expected, None);
+    }
+}
@@ -23,3 +23,1719 @@ fn cli_reports_miette_diagnostics_on_error() {
     // Clean up
     let _ = fs::remove_file(bad_file);
 }
+
+#[test]
+fn cli_suggests_close_names_for_undefined_symbols() {
+    let script = "tests/typo_fixture.sutra";
+    fs::write(script, "(define xs (list 1 2))\n(println (lenn xs))\n").unwrap();
+
+    Command::cargo_bin("sutra")
+        .unwrap()
+        .args(["run", script])
+        .assert()
+        .failure()
+        .stderr(contains("undefined symbol 'lenn'").and(contains("did you mean `len`?")));
+
+    let _ = fs::remove_file(script);
+}
+
+#[test]
+fn cli_translates_with_catalogs_and_reports_missing_keys() {
+    let dir = "tests/tr_fixture";
+    fs::create_dir_all(dir).unwrap();
+    fs::write(
+        format!("{dir}/en.toml"),
+        "[shop]\ngreeting = \"Welcome, {name}!\"\nfarewell = \"Bye\"\n",
+    )
+    .unwrap();
+    fs::write(
+        format!("{dir}/fr.json"),
+        r#"{"shop": {"greeting": "Bienvenue, {name} !"}}"#,
+    )
+    .unwrap();
+    let script = format!("{dir}/shop.sutra.txt");
+    fs::write(
+        &script,
+        "(println (tr \"shop.greeting\" :name \"Ada\"))\n(println (tr \"shop.farewell\"))\n",
+    )
+    .unwrap();
+
+    Command::cargo_bin("sutra")
+        .unwrap()
+        .args(["run", &script, "--locale", "fr"])
+        .args(["--catalog", &format!("{dir}/en.toml")])
+        .args(["--catalog", &format!("{dir}/fr.json")])
+        .assert()
+        .success()
+        .stdout(contains("Bienvenue, Ada !").and(contains("Bye")));
+
+    Command::cargo_bin("sutra")
+        .unwrap()
+        .args(["analyze", &script, "--missing-translations"])
+        .args(["--catalog", &format!("{dir}/en.toml")])
+        .args(["--catalog", &format!("{dir}/fr.json")])
+        .assert()
+        .failure()
+        .stdout(contains("fr: missing 'shop.farewell'").and(contains("en: missing").not()));
+
+    let _ = fs::remove_dir_all(dir);
+}
+
+#[test]
+fn cli_audits_weighted_tables() {
+    let script = "tests/audit_tables_fixture.sutra.txt";
+    fs::write(
+        script,
+        "(deftable loot (3 \"gold\") (0 \"cursed\") (1 (table gems)))\n(println (roll-table loot))\n",
+    )
+    .unwrap();
+
+    Command::cargo_bin("sutra")
+        .unwrap()
+        .args(["analyze", script, "--audit-tables"])
+        .assert()
+        .failure()
+        .stdout(
+            contains("loot (tests/audit_tables_fixture.sutra.txt:1:1): total weight 4")
+                .and(contains("75.0%  \"gold\""))
+                .and(contains("\"cursed\"  (unreachable: weight 0)"))
+                .and(contains(
+                    "table gems  (unreachable: no table 'gems' is declared)",
+                )),
+        )
+        .stderr(contains("2 unreachable table entries"));
+
+    let _ = fs::remove_file(script);
+}
+
+#[test]
+fn cli_greps_symbols_and_paths_before_or_after_expansion() {
+    let dir = std::env::temp_dir().join("sutra_grep");
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(
+        dir.join("sutra.toml"),
+        "entry = \"main.sutra\"\npreludes = [\"macros.sutra\"]\n",
+    )
+    .unwrap();
+    fs::write(
+        dir.join("macros.sutra"),
+        "(define (pay! n) (add! player.gold n))\n",
+    )
+    .unwrap();
+    fs::write(
+        dir.join("main.sutra"),
+        "; player.gold\n(pay! 5)\n(println (get (path \"player\" \"gold\")))\n",
+    )
+    .unwrap();
+    let grep = |args: &[&str]| {
+        let mut command = Command::cargo_bin("sutra").unwrap();
+        command.arg("grep").args(args).arg(&dir).arg("--no-cache");
+        command.assert()
+    };
+
+    grep(&["player.gold"]).success().stdout(
+        contains("main.sutra:3:15: (get (path \"player\" \"gold\"))")
+            .and(contains("(pay! 5)").not()),
+    );
+    grep(&["player", "--expanded"]).success().stdout(
+        contains("main.sutra:2:1: (add! player.gold 5)").and(contains(
+            "main.sutra:3:15: (get (path \"player\" \"gold\"))",
+        )),
+    );
+    grep(&["pay!"])
+        .success()
+        .stdout(contains("main.sutra:2:2: (pay! 5)"));
+    grep(&["pay!", "--expanded"]).failure().stdout("");
+
+    let _ = fs::remove_dir_all(dir);
+}
+
+#[test]
+fn cli_renames_definitions_across_a_project() {
+    let dir = std::env::temp_dir().join("sutra_rename");
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(dir.join("tests")).unwrap();
+    fs::write(dir.join("sutra.toml"), "entry = \"main.sutra\"\n").unwrap();
+    let main = "; heal restores hp\n(define (heal  n) (add! player.hp n))\n(define (twice n) (heal (heal n)))\n(let ((heal 1)) heal)\n";
+    fs::write(dir.join("main.sutra"), main).unwrap();
+    fs::write(
+        dir.join("tests/heal.sutra"),
+        "(test \"heals\" (expect (value 2)) (heal 2))\n",
+    )
+    .unwrap();
+    let rename = |args: &[&str]| {
+        let mut command = Command::cargo_bin("sutra").unwrap();
+        command.arg("rename").args(args).arg(&dir);
+        command.assert()
+    };
+
+    rename(&["heal", "mend", "--dry-run"]).success().stdout(
+        contains("-(define (heal  n) (add! player.hp n))")
+            .and(contains("+(define (mend  n) (add! player.hp n))"))
+            .and(contains("+(test \"heals\" (expect (value 2)) (mend 2))"))
+            .and(contains(
+                "Would rename 'heal' to 'mend': 4 use(s) in 2 file(s)",
+            )),
+    );
+    assert_eq!(fs::read_to_string(dir.join("main.sutra")).unwrap(), main);
+
+    rename(&["heal", "n"]).failure().stderr(contains(
+        "renaming 'heal' here would make it mean the local 'n'",
+    ));
+    assert_eq!(fs::read_to_string(dir.join("main.sutra")).unwrap(), main);
+    rename(&["heal", "mend"]).success();
+    assert_eq!(
+        fs::read_to_string(dir.join("main.sutra")).unwrap(),
+        "; heal restores hp\n(define (mend  n) (add! player.hp n))\n(define (twice n) (mend (mend n)))\n(let ((heal 1)) heal)\n"
+    );
+    rename(&["heal", "cure"])
+        .failure()
+        .stderr(contains("No uses of 'heal' found"));
+
+    let _ = fs::remove_dir_all(dir);
+}
+
+#[test]
+fn cli_renders_state_machines_as_dot() {
+    let script = "tests/dot_machines_fixture.sutra.txt";
+    fs::write(
+        script,
+        "(defstate door doors.front\n  (closed (open opened :when (get has-key)))\n  (opened (close closed)))\n",
+    )
+    .unwrap();
+
+    Command::cargo_bin("sutra")
+        .unwrap()
+        .args(["analyze", script, "--dot-machines"])
+        .assert()
+        .success()
+        .stdout(
+            contains("digraph \"door\" {")
+                .and(contains("\"\" -> \"closed\";"))
+                .and(contains(
+                    "\"closed\" -> \"opened\" [label=\"open when (get has-key)\"];",
+                ))
+                .and(contains("\"opened\" -> \"closed\" [label=\"close\"];")),
+        );
+
+    let _ = fs::remove_file(script);
+}
+
+#[test]
+fn cli_dumps_world_diff_after_run() {
+    let script = "tests/world_diff_fixture.sutra.txt";
+    fs::write(
+        script,
+        "(set! gold 3)\n(set! player.hp 7)\n(set! player.name \"Ada\")\n(del! gold)\n",
+    )
+    .unwrap();
+
+    Command::cargo_bin("sutra")
+        .unwrap()
+        .args(["run", script, "--dump-world-diff"])
+        .assert()
+        .success()
+        .stdout("+ player.hp: 7\n+ player.name: \"Ada\"\n");
+
+    let _ = fs::remove_file(script);
+}
+
+#[test]
+fn cli_errors_point_at_the_script_or_name_the_file() {
+    let script = "tests/macro_error_fixture.sutra.txt";
+    fs::write(script, "(println \"ok\")\n(text \"[unclosed\")\n").unwrap();
+
+    Command::cargo_bin("sutra")
+        .unwrap()
+        .args(["run", script])
+        .assert()
+        .failure()
+        .stderr(contains(script).and(contains("(text \"[unclosed\")")));
+
+    Command::cargo_bin("sutra")
+        .unwrap()
+        .args(["run", "tests/no_such_script.sutra"])
+        .assert()
+        .failure()
+        .stderr(contains("in tests/no_such_script.sutra"));
+
+    let _ = fs::remove_file(script);
+}
+
+#[test]
+fn cli_diagnostics_label_the_file_that_was_read() {
+    let dir = std::env::temp_dir().join("sutra_source_names");
+    fs::create_dir_all(&dir).unwrap();
+    let script = dir.join("unclosed.sutra");
+    let prelude = dir.join("prelude.sutra");
+    fs::write(&script, "(define x (+ 1 2)\n").unwrap();
+    fs::write(&prelude, "(define (m x) (+ x)\n").unwrap();
+    let label = |path: &std::path::Path| format!("[{}:1:1]", path.display());
+
+    for command in ["ast", "format", "macroexpand", "macrotrace"] {
+        Command::cargo_bin("sutra")
+            .unwrap()
+            .arg(command)
+            .arg(&script)
+            .assert()
+            .failure()
+            .stderr(contains(label(&script)));
+    }
+
+    Command::cargo_bin("sutra")
+        .unwrap()
+        .arg("run")
+        .arg("--prelude")
+        .arg(&prelude)
+        .arg(&script)
+        .assert()
+        .failure()
+        .stderr(contains(label(&prelude)));
+
+    let _ = fs::remove_dir_all(dir);
+}
+
+#[test]
+fn cli_test_separates_failures_errors_timeouts_and_skips() {
+    let dir = std::env::temp_dir().join("sutra_test_outcomes");
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(
+        dir.join("outcomes.sutra"),
+        r#"(test "passes" (expect (value 2)) (+ 1 1))
+(test "fails" (expect (value 3)) (+ 1 1))
+(test "wrong error" (expect (error "arity_mismatch")) (+ 1 "two"))
+(test "errors" (expect (value 1)) (undefined-thing))
+(test "malformed" (expect (error 42)) (+ 1 1))
+(test "skipped" :skip "not yet" (expect (value 1)) (+ 1 1))
+(test "slow" :timeout 50 (expect (error Runtime))
+  (define (fib n) (if (< n 2) n (+ (fib (- n 1)) (fib (- n 2)))))
+  (fib 40))
+"#,
+    )
+    .unwrap();
+
+    let report = dir.join("report.json");
+    Command::cargo_bin("sutra")
+        .unwrap()
+        .arg("test")
+        .arg(dir.join("outcomes.sutra"))
+        .arg("--json")
+        .arg(&report)
+        .assert()
+        .failure()
+        .stdout(
+            contains("Tests passed:    1")
+                .and(contains("Tests failed:    2"))
+                .and(contains("Tests errored:   2"))
+                .and(contains("Tests timed out: 1"))
+                .and(contains("Tests skipped:   1"))
+                .and(contains("skipped (skipped: not yet)"))
+                .and(contains(
+                    "1/6 passed (16.7%), 2 errored, 1 timed out, 1 skipped",
+                )),
+        );
+
+    let json = fs::read_to_string(&report).unwrap();
+    assert!(json.contains(r#""timed_out": 1"#));
+    assert!(json.contains(r#""outcome": "timed_out""#));
+
+    let _ = fs::remove_dir_all(dir);
+}
+
+#[test]
+fn cli_test_reports_a_timeout_inside_for_all_as_timed_out() {
+    let dir = std::env::temp_dir().join("sutra_property_timeout");
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(
+        dir.join("slow_property.sutra"),
+        r#"(test "slow property" :timeout 50 (expect (value true))
+  (define (fib n) (if (< n 2) n (+ (fib (- n 1)) (fib (- n 2)))))
+  (for-all ((x (gen/int 30 35))) (gt? (fib x) 0)))
+"#,
+    )
+    .unwrap();
+
+    Command::cargo_bin("sutra")
+        .unwrap()
+        .arg("test")
+        .arg(dir.join("slow_property.sutra"))
+        .assert()
+        .failure()
+        .stdout(
+            contains("Tests timed out: 1")
+                .and(contains("Tests errored:   0"))
+                .and(contains("slow property (timed out)")),
+        );
+
+    let _ = fs::remove_dir_all(dir);
+}
+
+#[test]
+fn cli_test_rejects_unknown_error_codes() {
+    let dir = std::env::temp_dir().join("sutra_error_codes");
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(
+        dir.join("codes.sutra"),
+        r#"(test "known" (expect (error type_mismatch)) (+ 1 "a"))
+(test "typo" (expect (error type_mismach)) (+ 1 "a"))
+"#,
+    )
+    .unwrap();
+
+    Command::cargo_bin("sutra")
+        .unwrap()
+        .arg("test")
+        .arg(dir.join("codes.sutra"))
+        .arg("--no-cache")
+        .assert()
+        .failure()
+        .stdout(contains("1/2 passed (50.0%), 1 errored"))
+        .stderr(contains("unknown error code").and(contains("did you mean type_mismatch?")));
+
+    let _ = fs::remove_dir_all(dir);
+}
+
+#[test]
+fn cli_test_reports_each_path_expect_world_finds_different() {
+    let dir = std::env::temp_dir().join("sutra_expect_world");
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(
+        dir.join("shop.sutra"),
+        r#"(test "buys a sword"
+  (expect-world (player.gold 95) (inventory (list "sword")))
+  (set! player.gold 90)
+  (set! inventory (list "sword")))
+"#,
+    )
+    .unwrap();
+
+    Command::cargo_bin("sutra")
+        .unwrap()
+        .arg("test")
+        .arg(&dir)
+        .assert()
+        .failure()
+        .stdout(contains("Tests failed:    1"))
+        .stderr(contains("~ player.gold: 95 -> 90").and(contains(": (sword)").not()));
+
+    let _ = fs::remove_dir_all(dir);
+}
+
+#[test]
+fn cli_test_reports_failing_hooks_apart_from_failing_tests() {
+    let dir = std::env::temp_dir().join("sutra_test_hooks");
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(
+        dir.join("door.sutra"),
+        r#"(before-each (set! door "shut"))
+(after-each (if (eq? (get door) "shut") nil (error "door left open")))
+(test "knocks" (expect (value "shut")) (get door))
+(test "walks through" (expect (value "open")) (set! door "open") (get door))
+"#,
+    )
+    .unwrap();
+    fs::write(
+        dir.join("vault.sutra"),
+        r#"(before-all (error "vault is sealed"))
+(test "opens" (expect (value 1)) 1)
+"#,
+    )
+    .unwrap();
+    let report = dir.join("report.json");
+
+    Command::cargo_bin("sutra")
+        .unwrap()
+        .arg("test")
+        .arg(&dir)
+        .arg("--json")
+        .arg(&report)
+        .assert()
+        .failure()
+        .stdout(
+            contains("walks through (after-each hook failed)")
+                .and(contains("before-all (before-all hook failed)"))
+                .and(contains("opens (skipped: before-all hook failed)"))
+                .and(contains("Tests failed:    0"))
+                .and(contains("1/3 passed (33.3%), 1 skipped, 2 hook failed")),
+        )
+        .stderr(contains("door left open").and(contains("vault is sealed")));
+
+    let json = fs::read_to_string(&report).unwrap();
+    assert!(json.contains(r#""hook_failed": 2"#));
+    assert!(json.contains(r#""message": "after-each hook: "#));
+
+    let _ = fs::remove_dir_all(dir);
+}
+
+#[test]
+fn cli_test_orders_tests_by_name_unless_shuffled_with_a_seed() {
+    let dir = std::env::temp_dir().join("sutra_test_order");
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    let tests: String = ["delta", "alpha", "echo", "charlie", "bravo", "foxtrot"]
+        .iter()
+        .map(|name| format!("(test \"{name}\" (expect (value 1)) 1)\n"))
+        .collect();
+    fs::write(dir.join("order.sutra"), tests).unwrap();
+    let order = |args: &[&str]| {
+        let output = Command::cargo_bin("sutra")
+            .unwrap()
+            .arg("test")
+            .arg(&dir)
+            .args(args)
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+        let stdout = String::from_utf8(output.stdout).unwrap();
+        let names: Vec<String> = stdout
+            .lines()
+            .filter(|line| line.contains('✓'))
+            .filter_map(|line| line.split(' ').next_back().map(String::from))
+            .collect();
+        (names, stdout)
+    };
+
+    let (sorted, _) = order(&[]);
+    assert_eq!(
+        sorted,
+        ["alpha", "bravo", "charlie", "delta", "echo", "foxtrot"]
+    );
+
+    let (shuffled, stdout) = order(&["--shuffle", "--seed", "42"]);
+    assert!(stdout.contains("Shuffling tests with seed 42."));
+    assert!(stdout.contains("repeat it with --shuffle --seed 42"));
+    assert_eq!(order(&["--shuffle", "--seed", "42"]).0, shuffled);
+    let mut names = shuffled.clone();
+    names.sort();
+    assert_eq!(names, sorted);
+
+    let (_, stdout) = order(&["--shuffle"]);
+    assert!(stdout.contains("Shuffling tests with seed "));
+
+    Command::cargo_bin("sutra")
+        .unwrap()
+        .arg("test")
+        .arg(&dir)
+        .args(["--seed", "42"])
+        .assert()
+        .failure()
+        .stderr(contains("--shuffle"));
+
+    let _ = fs::remove_dir_all(dir);
+}
+
+#[test]
+fn cli_test_shards_run_every_test_exactly_once() {
+    let dir = std::env::temp_dir().join("sutra_test_shards");
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    let tests: String = (0..12)
+        .map(|i| format!("(test \"sum {i}\" (expect (value {i})) (+ {i} 0))\n"))
+        .collect();
+    fs::write(dir.join("sums.sutra"), tests).unwrap();
+
+    let mut ran = 0;
+    for shard in ["1/3", "2/3", "3/3"] {
+        let output = Command::cargo_bin("sutra")
+            .unwrap()
+            .arg("test")
+            .arg(&dir)
+            .args(["--shard", shard])
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+        let stdout = String::from_utf8(output.stdout).unwrap();
+        let note = stdout
+            .lines()
+            .find(|line| line.starts_with(&format!("Shard {shard} ran ")))
+            .unwrap();
+        let counts: Vec<usize> = note
+            .split(' ')
+            .filter_map(|word| word.trim_end_matches(';').parse().ok())
+            .collect();
+        assert_eq!(counts[0] + counts[1], 12, "{note}");
+        ran += counts[0];
+    }
+    assert_eq!(ran, 12);
+
+    Command::cargo_bin("sutra")
+        .unwrap()
+        .arg("test")
+        .arg(&dir)
+        .args(["--shard", "4/3"])
+        .assert()
+        .failure()
+        .stderr(contains("invalid shard '4/3'"));
+
+    let _ = fs::remove_dir_all(dir);
+}
+
+#[test]
+fn cli_test_retries_flaky_tests_and_quarantines_known_failures() {
+    let dir = std::env::temp_dir().join("sutra_test_retries");
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    // Each coin passes half the time, so with 20 of them one is all but sure to need
+    // a retry, and none will fail 40 times running.
+    let mut tests: String = (0..20)
+        .map(|i| format!("(test \"coin {i}\" (expect (value true)) (lt? (rand) 0.5))\n"))
+        .collect();
+    tests.push_str("(test \"never\" (expect (value 1)) 2)\n");
+    fs::write(dir.join("coins.sutra"), tests).unwrap();
+    fs::write(dir.join("quarantine.txt"), "# known flaky\nnever\n").unwrap();
+    let report = dir.join("report.json");
+
+    Command::cargo_bin("sutra")
+        .unwrap()
+        .arg("test")
+        .arg(dir.join("coins.sutra"))
+        .args(["--retries", "40"])
+        .assert()
+        .failure()
+        .stdout(contains("never (41 attempts, seeds "));
+
+    Command::cargo_bin("sutra")
+        .unwrap()
+        .arg("test")
+        .arg(dir.join("coins.sutra"))
+        .args(["--retries", "40", "--quarantine"])
+        .arg(dir.join("quarantine.txt"))
+        .arg("--json")
+        .arg(&report)
+        .assert()
+        .success()
+        .stdout(
+            contains("(flaky; ")
+                .and(contains("never (quarantined; 41 attempts, seeds "))
+                .and(contains("20/21 passed"))
+                .and(contains("flaky, 1 quarantined")),
+        );
+
+    let json = fs::read_to_string(&report).unwrap();
+    assert!(json.contains(r#""outcome": "flaky""#));
+    assert!(json.contains(r#""quarantined": 1"#));
+    assert!(json.contains(r#""seed": "#));
+
+    let _ = fs::remove_dir_all(dir);
+}
+
+#[test]
+fn cli_lists_atoms_and_macros_as_json() {
+    let output = Command::cargo_bin("sutra")
+        .unwrap()
+        .args(["list-atoms", "--json"])
+        .assert()
+        .success()
+        .get_output()
+        .stdout
+        .clone();
+    let atoms: serde_json::Value = serde_json::from_slice(&output).unwrap();
+    let atom = |name: &str| {
+        atoms
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|atom| atom["name"] == name)
+            .cloned()
+            .unwrap()
+    };
+    assert_eq!(atom("+")["kind"], "pure");
+    assert_eq!(atom("set!")["kind"], "stateful");
+    assert_eq!(atom("if")["kind"], "special_form");
+    assert!(atom("if")["namespace"].is_null());
+    assert_eq!(atom("text/pick")["namespace"], "text");
+    assert_eq!(atom("plural")["min_args"], 2);
+    assert_eq!(atom("plural")["max_args"], 3);
+    assert!(atom("+")["max_args"].is_null());
+    assert_eq!(atom(">")["arg_types"], serde_json::json!(["Number"]));
+    assert_eq!(atom("print")["capabilities"], serde_json::json!(["io"]));
+
+    Command::cargo_bin("sutra")
+        .unwrap()
+        .args(["list-macros", "--json"])
+        .assert()
+        .success()
+        .stdout(contains(r#""name": "text""#).and(contains(r#""kind": "native""#)));
+}
+
+#[test]
+fn cli_lists_commands_for_launchers_and_prints_completions() {
+    let output = Command::cargo_bin("sutra")
+        .unwrap()
+        .args(["--list-commands", "--json"])
+        .assert()
+        .success()
+        .get_output()
+        .stdout
+        .clone();
+    let commands: serde_json::Value = serde_json::from_slice(&output).unwrap();
+    let run = commands
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|command| command["name"] == "run")
+        .unwrap();
+    let arg = |name: &str| {
+        run["args"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|arg| arg["name"] == name)
+            .cloned()
+            .unwrap()
+    };
+    assert_eq!(arg("files")["positional"], true);
+    assert_eq!(arg("files")["repeatable"], true);
+    assert_eq!(arg("paths")["long"], "paths");
+    assert_eq!(arg("paths")["takes_value"], true);
+    assert_eq!(arg("sandbox")["takes_value"], false);
+
+    Command::cargo_bin("sutra")
+        .unwrap()
+        .arg("--list-commands")
+        .assert()
+        .success()
+        .stdout(contains("completions\tPrint a completion script"));
+
+    Command::cargo_bin("sutra")
+        .unwrap()
+        .args(["completions", "bash"])
+        .assert()
+        .success()
+        .stdout(contains("_sutra()").and(contains("list-atoms")));
+}
+
+#[test]
+fn cli_doc_shows_atom_usage_and_arity_errors_use_it() {
+    Command::cargo_bin("sutra")
+        .unwrap()
+        .args(["doc", "plural"])
+        .assert()
+        .success()
+        .stdout(contains("(plural <Number> <String> [<String>])"));
+
+    Command::cargo_bin("sutra")
+        .unwrap()
+        .args(["doc", "no-such-atom"])
+        .assert()
+        .failure();
+
+    Command::cargo_bin("sutra")
+        .unwrap()
+        .args(["eval", "(mod 7)"])
+        .assert()
+        .failure()
+        .stderr(contains("expected 2, got 1").and(contains("usage: (mod <Number> <Number>)")));
+}
+
+#[test]
+fn cli_shows_doc_comments_of_prelude_macros() {
+    let dir = std::env::temp_dir().join("sutra_macro_docs");
+    fs::create_dir_all(&dir).unwrap();
+    let prelude = dir.join("prelude.sutra");
+    let source = ";; Not a doc.\n\n;;; Greets someone.\n;;; More may follow.\n(define (greet name ...more) name)\n";
+    fs::write(&prelude, source).unwrap();
+    let doc = "(greet name ...more)\n    Greets someone.\n    More may follow.\n";
+
+    Command::cargo_bin("sutra")
+        .unwrap()
+        .args(["list-macros", "--verbose", "--prelude"])
+        .arg(&prelude)
+        .assert()
+        .success()
+        .stdout(contains(doc));
+
+    Command::cargo_bin("sutra")
+        .unwrap()
+        .args(["doc", "greet", "--prelude"])
+        .arg(&prelude)
+        .assert()
+        .success()
+        .stdout(doc);
+
+    Command::cargo_bin("sutra")
+        .unwrap()
+        .args(["list-macros", "--json", "--prelude"])
+        .arg(&prelude)
+        .assert()
+        .success()
+        .stdout(contains(r#""doc": "Greets someone.\nMore may follow.""#));
+}
+
+#[test]
+fn cli_sandbox_rejects_atoms_needing_ungranted_capabilities() {
+    Command::cargo_bin("sutra")
+        .unwrap()
+        .args(["eval", "--sandbox", "(do (set! x 1) (print \"hi\"))"])
+        .assert()
+        .failure()
+        .stdout(contains("hi").not())
+        .stderr(contains("'print' needs the 'io' capability"));
+
+    Command::cargo_bin("sutra")
+        .unwrap()
+        .args(["eval", "--sandbox", "(do (define p print) (p \"hi\"))"])
+        .assert()
+        .failure()
+        .stdout(contains("hi").not())
+        .stderr(contains("'print' needs the 'io' capability"));
+
+    Command::cargo_bin("sutra")
+        .unwrap()
+        .args(["eval", "--sandbox", "(list 'print (quote print))"])
+        .assert()
+        .success();
+
+    Command::cargo_bin("sutra")
+        .unwrap()
+        .args(["eval", "--sandbox", "(table/load \"items.csv\")"])
+        .assert()
+        .failure()
+        .stderr(contains("'table/load' needs the 'io' capability"));
+
+    Command::cargo_bin("sutra")
+        .unwrap()
+        .args(["eval", "--sandbox", "(do (set! x 2) (get x))"])
+        .assert()
+        .success()
+        .stdout(contains("2"));
+
+    Command::cargo_bin("sutra")
+        .unwrap()
+        .args(["eval", "--sandbox", "--allow", "io", "(print \"hi\")"])
+        .assert()
+        .success()
+        .stdout(contains("hi"));
+
+    Command::cargo_bin("sutra")
+        .unwrap()
+        .args(["eval", "--sandbox", "(eval '(+ 1 2))"])
+        .assert()
+        .failure()
+        .stderr(contains("'eval' needs the 'eval' capability"));
+
+    Command::cargo_bin("sutra")
+        .unwrap()
+        .args(["eval", "--sandbox", "--allow", "eval", "(eval '(+ 1 2))"])
+        .assert()
+        .success()
+        .stdout("3\n");
+
+    Command::cargo_bin("sutra")
+        .unwrap()
+        .args(["eval", "--sandbox", "--allow", "net", "(print \"hi\")"])
+        .assert()
+        .failure()
+        .stderr(contains(
+            "the net capability cannot be granted to sandboxed scripts",
+        ));
+}
+
+#[test]
+fn cli_resource_limits_point_at_the_oversized_call() {
+    Command::cargo_bin("sutra")
+        .unwrap()
+        .args(["eval", "--max-collection-len", "3", "(len (list 1 2 3 4))"])
+        .assert()
+        .failure()
+        .stderr(contains("list length 4 exceeds the limit of 3").and(contains("too large")));
+
+    Command::cargo_bin("sutra")
+        .unwrap()
+        .args(["eval", "--max-string-bytes", "4", "(str+ \"ab\" \"cde\")"])
+        .assert()
+        .failure()
+        .stderr(contains("string length 5 exceeds the limit of 4"));
+
+    Command::cargo_bin("sutra")
+        .unwrap()
+        .args([
+            "eval",
+            "--max-world-size",
+            "2",
+            "(do (set! a 1) (set! b 2) (set! c 3))",
+        ])
+        .assert()
+        .failure()
+        .stderr(contains("world size 3 exceeds the limit of 2"));
+
+    Command::cargo_bin("sutra")
+        .unwrap()
+        .args([
+            "eval",
+            "--max-collection-len",
+            "3",
+            "(generate! rooms 4 (lambda (i) i))",
+        ])
+        .assert()
+        .failure()
+        .stderr(contains("generated entries 4 exceeds the limit of 3"));
+
+    Command::cargo_bin("sutra")
+        .unwrap()
+        .args([
+            "eval",
+            "--max-collection-len",
+            "3",
+            "(range 0 1000000000000 0.5)",
+        ])
+        .assert()
+        .failure()
+        .stderr(contains(
+            "range length 2000000000000 exceeds the limit of 3",
+        ));
+
+    Command::cargo_bin("sutra")
+        .unwrap()
+        .args(["eval", "--max-collection-len", "3", "(list 1 2 3)"])
+        .assert()
+        .success();
+}
+
+#[test]
+fn cli_path_policy_controls_writes_under_missing_parents() {
+    Command::cargo_bin("sutra")
+        .unwrap()
+        .args(["eval", "--paths", "strict", "(set! a.b.c 1)"])
+        .assert()
+        .failure()
+        .stderr(contains("cannot set 'a.b.c' because 'a' is not a map"));
+
+    Command::cargo_bin("sutra")
+        .unwrap()
+        .args([
+            "eval",
+            "--paths",
+            "strict",
+            "(do (ensure-path! a.b (core/map)) (set! a.b.c 1) (get a.b.c))",
+        ])
+        .assert()
+        .success()
+        .stdout(contains("1"));
+
+    Command::cargo_bin("sutra")
+        .unwrap()
+        .args([
+            "eval",
+            "--paths",
+            "warn",
+            "(do (set! a.b.c 1) (set! a.b.d 2))",
+        ])
+        .assert()
+        .success()
+        .stderr(contains("set! of 'a.b.c' creates the missing path 'a.b'"))
+        .stderr(contains("'a.b.d'").not());
+}
+
+#[test]
+fn cli_prints_exactly_what_print_and_println_write() {
+    let script = "(do (print \"a\" 1) (print \"b\") (println \"c\" true) (println) (+ 1 2))";
+    Command::cargo_bin("sutra")
+        .unwrap()
+        .args(["eval", script])
+        .assert()
+        .success()
+        .stdout("a1bctrue\n\n3\n");
+
+    Command::cargo_bin("sutra")
+        .unwrap()
+        .args(["eval", "--print-separator", ", ", script])
+        .assert()
+        .success()
+        .stdout("a, 1bc, true\n\n3\n");
+}
+
+#[test]
+fn cli_reports_failed_assertions_unless_they_are_off() {
+    let script =
+        "(do (set! hp -3) (assert (gte? (get hp) 0) \"hp went negative\") (println \"ran\"))";
+    Command::cargo_bin("sutra")
+        .unwrap()
+        .args(["eval", script])
+        .assert()
+        .failure()
+        .stderr(contains(
+            "assertion (gte? (get hp) 0) failed: hp went negative",
+        ));
+
+    Command::cargo_bin("sutra")
+        .unwrap()
+        .args(["eval", "--no-assert", script])
+        .assert()
+        .success()
+        .stdout("ran\n");
+}
+
+#[test]
+fn cli_locates_read_errors_within_the_text() {
+    Command::cargo_bin("sutra")
+        .unwrap()
+        .args(["eval", "(read \"(a\\n  (b ]\")"])
+        .assert()
+        .failure()
+        .stderr(contains("unreadable data at line 2, column 6"));
+}
+
+#[test]
+fn cli_filters_log_messages_by_level() {
+    let script = "(do (log/debug \"d\") (log/info \"i\") (log/warn \"w\"))";
+    Command::cargo_bin("sutra")
+        .unwrap()
+        .args(["eval", script])
+        .assert()
+        .success()
+        .stdout("[info] i\n[warn] w\n");
+
+    Command::cargo_bin("sutra")
+        .unwrap()
+        .args(["eval", "--log-level", "debug", script])
+        .assert()
+        .success()
+        .stdout("[debug] d\n[info] i\n[warn] w\n");
+
+    Command::cargo_bin("sutra")
+        .unwrap()
+        .args(["eval", "--log-level", "loud", script])
+        .assert()
+        .failure()
+        .stderr(contains("unknown log level 'loud'"));
+}
+
+#[test]
+fn cli_prints_the_ast_as_sexpr_or_json() {
+    let path = std::env::temp_dir().join("sutra_ast_fixture.sutra");
+    fs::write(&path, "(get player.hp)\n(text \"hi {name}\")\n").unwrap();
+    let ast = |args: &[&str]| {
+        let output = Command::cargo_bin("sutra")
+            .unwrap()
+            .arg("ast")
+            .arg(&path)
+            .args(args)
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+        String::from_utf8(output.stdout).unwrap()
+    };
+
+    assert_eq!(
+        ast(&[]),
+        "; 1:1\n(get player.hp)\n; 2:1\n(text \"hi {name}\")\n"
+    );
+    assert_eq!(
+        ast(&["--expanded", "--no-spans"]),
+        "(do (get player.hp) (core/str+ \"hi \" (str name)))\n"
+    );
+
+    let json: serde_json::Value = serde_json::from_str(&ast(&["--format", "json"])).unwrap();
+    assert_eq!(json[0]["span"], serde_json::json!({"start": 0, "end": 15}));
+    let json: serde_json::Value =
+        serde_json::from_str(&ast(&["--format", "json", "--no-spans"])).unwrap();
+    assert_eq!(
+        json[0],
+        serde_json::json!({"List": [{"Symbol": "get"}, {"Path": ["player", "hp"]}]})
+    );
+}
+
+#[test]
+fn cli_shows_macro_expansion_steps_as_diffs() {
+    let path = std::env::temp_dir().join("sutra_macroexpand_fixture.sutra");
+    fs::write(
+        &path,
+        "(deftable loot (3 \"gold\"))\n(println (text \"[a|b]\"))\n",
+    )
+    .unwrap();
+    let macroexpand = |args: &[&str]| {
+        let output = Command::cargo_bin("sutra")
+            .unwrap()
+            .arg("macroexpand")
+            .arg(&path)
+            .args(args)
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+        String::from_utf8(output.stdout).unwrap()
+    };
+
+    assert_eq!(
+        macroexpand(&["--step"]),
+        concat!(
+            ";; step 1: deftable at 1:1\n",
+            "-(do (deftable loot (3 \"gold\")) (println (text \"[a|b]\")))\n",
+            "+(do (core/deftable \"loot\" (list 3 \"gold\")) (println (text \"[a|b]\")))\n",
+            ";; step 2: text at 2:10\n",
+            "-(do (core/deftable \"loot\" (list 3 \"gold\")) (println (text \"[a|b]\")))\n",
+            "+(do (core/deftable \"loot\" (list 3 \"gold\")) (println (text/pick \"a\" \"b\")))\n",
+        )
+    );
+    assert_eq!(
+        macroexpand(&["--only", "text"]),
+        "(do (deftable loot (3 \"gold\")) (println (text/pick \"a\" \"b\")))\n"
+    );
+    assert!(macroexpand(&["--diff"]).ends_with("(println (text/pick \"a\" \"b\")))\n"));
+}
+
+#[test]
+fn cli_runs_tests_and_checks_a_project_from_its_manifest() {
+    let dir = std::env::temp_dir().join("sutra_project_fixture");
+    let _ = fs::remove_dir_all(&dir);
+    for sub in ["lib", "macros", "tests"] {
+        fs::create_dir_all(dir.join(sub)).unwrap();
+    }
+    fs::write(
+        dir.join("sutra.toml"),
+        "include = [\"lib\"]\npreludes = [\"macros/story.sutra\"]\n",
+    )
+    .unwrap();
+    fs::write(
+        dir.join("macros/story.sutra"),
+        "(define (greet name) (str+ \"Hello, \" name))\n",
+    )
+    .unwrap();
+    fs::write(dir.join("lib/a.sutra"), "(set! hp 10)\n").unwrap();
+    fs::write(dir.join("lib/b.sutra"), "(set! hp (+ (get hp) 1))\n").unwrap();
+    fs::write(
+        dir.join("main.sutra"),
+        "(println (greet \"Ada\") \" \" (get hp))\n",
+    )
+    .unwrap();
+    fs::write(
+        dir.join("tests/math.sutra"),
+        "(test \"adds\" (expect (value 2)) (+ 1 1))\n",
+    )
+    .unwrap();
+
+    Command::cargo_bin("sutra")
+        .unwrap()
+        .arg("run")
+        .arg(&dir)
+        .assert()
+        .success()
+        .stdout(contains("Hello, Ada 11"));
+
+    Command::cargo_bin("sutra")
+        .unwrap()
+        .arg("test")
+        .arg(&dir)
+        .assert()
+        .success()
+        .stdout(contains("1/1 passed"));
+
+    Command::cargo_bin("sutra")
+        .unwrap()
+        .arg("typecheck")
+        .arg(&dir)
+        .assert()
+        .success()
+        .stdout(contains("No type errors found"));
+
+    fs::write(dir.join("sutra.toml"), "entrypoint = \"main.sutra\"\n").unwrap();
+    Command::cargo_bin("sutra")
+        .unwrap()
+        .arg("run")
+        .arg(&dir)
+        .assert()
+        .failure()
+        .stderr(contains("unknown field `entrypoint`"));
+
+    let _ = fs::remove_dir_all(dir);
+}
+
+#[test]
+fn cli_layers_the_user_config_under_the_project_and_flags() {
+    let dir = std::env::temp_dir().join("sutra_config_fixture");
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(dir.join("game")).unwrap();
+    let config = dir.join("config.toml");
+    fs::write(
+        &config,
+        "seed = 1\nprint_separator = \"-\"\npreludes = [\"house.sutra\"]\n",
+    )
+    .unwrap();
+    fs::write(dir.join("house.sutra"), "(define (twice x) (* 2 x))\n").unwrap();
+    fs::write(dir.join("game/sutra.toml"), "seed = 5\n").unwrap();
+    let sutra = |args: &[&str]| {
+        let mut command = Command::cargo_bin("sutra").unwrap();
+        command.env("SUTRA_CONFIG", &config).args(args);
+        command
+    };
+
+    sutra(&["eval", "(print (twice 2) 3)"])
+        .assert()
+        .success()
+        .stdout("4-3");
+
+    let game = dir.join("game");
+    sutra(&[
+        "config",
+        "show",
+        "--project",
+        game.to_str().unwrap(),
+        "--max-depth",
+        "7",
+    ])
+    .assert()
+    .success()
+    .stdout(
+        contains("seed = 5  # project")
+            .and(contains("max_depth = 7  # flag"))
+            .and(contains("print_separator = \"-\"  # user config"))
+            .and(contains("max_macro_depth = 100  # default"))
+            .and(contains("locale = (not set)")),
+    );
+
+    fs::write(&config, "max_steps = 10\n").unwrap();
+    sutra(&["eval", "1"])
+        .assert()
+        .failure()
+        .stderr(contains("unknown field `max_steps`"));
+
+    let _ = fs::remove_dir_all(dir);
+}
+
+#[test]
+fn cli_reports_macro_expansion_cache_hits() {
+    let prelude = "tests/stats_prelude_fixture.sutra";
+    let script = "tests/stats_fixture.sutra";
+    fs::write(prelude, "(define (twice x) (do x x))\n").unwrap();
+    fs::write(
+        script,
+        "(twice (println \"hi\"))\n(twice (println \"hi\"))\n",
+    )
+    .unwrap();
+
+    Command::cargo_bin("sutra")
+        .unwrap()
+        .args(["run", "--stats", "--prelude", prelude, script])
+        .assert()
+        .success()
+        .stderr(contains(
+            "Macro expansions: 2 (1 from cache, 50.0% hit rate)",
+        ));
+
+    let _ = fs::remove_file(prelude);
+    let _ = fs::remove_file(script);
+}
+
+#[test]
+fn cli_reports_the_macros_behind_an_expansion_limit() {
+    let prelude = "tests/limits_prelude_fixture.sutra";
+    fs::write(
+        prelude,
+        "(define (story x) (loop x))\n(define (loop x) (loop x))\n(define (twice x) (list x x))\n",
+    )
+    .unwrap();
+    let eval = |args: &[&str]| {
+        let mut command = Command::cargo_bin("sutra").unwrap();
+        command.args(["eval", "--prelude", prelude]).args(args);
+        command.assert()
+    };
+
+    eval(&["--max-macro-depth", "5", "(story 1)"])
+        .failure()
+        .stderr(contains("story -> loop (x5)"))
+        .stderr(contains("--max-macro-depth"));
+    eval(&["--max-expansion-nodes", "10", "(twice (twice (twice 1)))"])
+        .failure()
+        .stderr(contains("node count exceeds the limit of 10"));
+    eval(&[
+        "--max-expansion-nodes",
+        "100",
+        "(len (twice (twice (twice 1))))",
+    ])
+    .success()
+    .stdout("2\n");
+
+    let _ = fs::remove_file(prelude);
+}
+
+#[test]
+fn cli_scopes_module_definitions_across_project_files() {
+    let dir = std::env::temp_dir().join("sutra_module_fixture");
+    let _ = fs::remove_dir_all(&dir);
+    for sub in ["lib", "macros"] {
+        fs::create_dir_all(dir.join(sub)).unwrap();
+    }
+    fs::write(
+        dir.join("sutra.toml"),
+        "include = [\"lib\"]\npreludes = [\"macros/story.sutra\"]\n",
+    )
+    .unwrap();
+    fs::write(
+        dir.join("macros/story.sutra"),
+        "(module story (export scene))\n\
+         (define (scene title) (say title))\n\
+         (define (say text) (println text))\n",
+    )
+    .unwrap();
+    fs::write(
+        dir.join("lib/shop.sutra"),
+        "(module shop (export buy!))\n\
+         (define price 5)\n\
+         (define (buy! item) \"{item} for {price}\")\n",
+    )
+    .unwrap();
+    fs::write(
+        dir.join("main.sutra"),
+        "(story/scene (shop/buy! \"sword\"))\n",
+    )
+    .unwrap();
+
+    Command::cargo_bin("sutra")
+        .unwrap()
+        .arg("run")
+        .arg(&dir)
+        .assert()
+        .success()
+        .stdout(contains("sword for 5"));
+
+    fs::write(dir.join("main.sutra"), "(println shop/price)\n").unwrap();
+    Command::cargo_bin("sutra")
+        .unwrap()
+        .arg("run")
+        .arg(&dir)
+        .assert()
+        .failure()
+        .stderr(contains("'price' is not exported by module 'shop'"));
+
+    fs::write(dir.join("main.sutra"), "(story/say \"hi\")\n").unwrap();
+    Command::cargo_bin("sutra")
+        .unwrap()
+        .arg("run")
+        .arg(&dir)
+        .assert()
+        .failure()
+        .stderr(contains("'say' is not exported by module 'story'"));
+
+    let _ = fs::remove_dir_all(dir);
+}
+
+#[cfg(not(feature = "watch"))]
+#[test]
+fn cli_watch_needs_the_watch_feature() {
+    Command::cargo_bin("sutra")
+        .unwrap()
+        .args(["test", "tests", "--watch"])
+        .assert()
+        .failure()
+        .stderr(contains(
+            "--watch needs sutra built with the `watch` feature",
+        ));
+}
+
+#[test]
+fn cli_verbosity_flags_control_logging() {
+    Command::cargo_bin("sutra")
+        .unwrap()
+        .args(["eval", "-vv", "(+ 1 2)"])
+        .assert()
+        .success()
+        .stdout(contains("3"))
+        .stderr(contains("evaluating <eval>"));
+
+    Command::cargo_bin("sutra")
+        .unwrap()
+        .args(["eval", "(+ 1 2)"])
+        .assert()
+        .success()
+        .stderr(contains("evaluating").not());
+
+    Command::cargo_bin("sutra")
+        .unwrap()
+        .args(["-q", "eval", "(car 1)"])
+        .assert()
+        .code(1)
+        .stderr(contains("car"));
+}
+
+#[test]
+fn cli_passes_arguments_and_stdin_to_scripts() {
+    Command::cargo_bin("sutra")
+        .unwrap()
+        .args([
+            "eval",
+            "(list->str (args) \",\")",
+            "--",
+            "north",
+            "two words",
+        ])
+        .assert()
+        .success()
+        .stdout(contains("north,two words"));
+
+    Command::cargo_bin("sutra")
+        .unwrap()
+        .args(["eval", "(list (read-line) (read-line) (read-line))"])
+        .write_stdin("Ada\r\nBo\n")
+        .assert()
+        .success()
+        .stdout(contains("(Ada Bo nil)"));
+
+    Command::cargo_bin("sutra")
+        .unwrap()
+        .args(["eval", "--sandbox", "(read-line)"])
+        .assert()
+        .failure()
+        .stderr(contains("'read-line' needs the 'io' capability"));
+}
+
+#[test]
+fn cli_prompts_for_choices_on_stdin() {
+    Command::cargo_bin("sutra")
+        .unwrap()
+        .args([
+            "eval",
+            "(choice \"The road forks.\" ((\"Go north\" \"north\") (\"Rest\" \"rested\")))",
+        ])
+        .write_stdin("3\n2\n")
+        .assert()
+        .success()
+        .stdout(
+            contains("The road forks.\n1. Go north\n2. Rest\n")
+                .and(contains("Enter a number from 1 to 2."))
+                .and(contains("rested")),
+        );
+}
+
+#[test]
+fn replays_recorded_sessions() {
+    let dir = std::env::temp_dir().join("sutra_replay_fixture");
+    fs::create_dir_all(&dir).unwrap();
+    let script = dir.join("story.sutra");
+    fs::write(
+        &script,
+        "(set! luck (rand))\n\
+         (choice \"Where?\" ((\"North\" (set! room \"forest\")) (\"South\" (set! room \"beach\"))))\n\
+         (println \"In the \" (get room) \", \" (read-line) \".\")\n",
+    )
+    .unwrap();
+    let log = dir.join("session.log");
+
+    Command::cargo_bin("sutra")
+        .unwrap()
+        .args(["run", "--record"])
+        .args([&log, &script])
+        .write_stdin("2\nresting\n")
+        .assert()
+        .success()
+        .stdout(contains("In the beach, resting."));
+
+    Command::cargo_bin("sutra")
+        .unwrap()
+        .arg("replay")
+        .arg(&log)
+        .assert()
+        .success()
+        .stdout(
+            contains("In the beach, resting.")
+                .and(contains("Replay matched all 6 recorded events.")),
+        );
+
+    let edited = fs::read_to_string(&log)
+        .unwrap()
+        .replace("room \\\"beach\\\"", "room \\\"cave\\\"");
+    fs::write(&log, edited).unwrap();
+    Command::cargo_bin("sutra")
+        .unwrap()
+        .arg("replay")
+        .arg(&log)
+        .assert()
+        .failure()
+        .stderr(contains("replay diverged at event 4"));
+}
+
+#[test]
+fn cli_caches_parsed_tests_until_cleaned() {
+    let dir = std::env::temp_dir().join("sutra_cache_fixture");
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(dir.join("tests")).unwrap();
+    fs::write(
+        dir.join("tests/sums.sutra"),
+        r#"(test "sums" (expect (value 3)) (+ 1 2))"#,
+    )
+    .unwrap();
+    let sutra = |args: &[&str]| {
+        let mut command = Command::cargo_bin("sutra").unwrap();
+        command.current_dir(&dir).args(args);
+        command
+    };
+
+    sutra(&["test", "--no-cache"])
+        .assert()
+        .success()
+        .stdout(contains("1/1 passed"));
+    assert!(!dir.join(".sutra-cache").exists());
+
+    for _ in 0..2 {
+        sutra(&["test"])
+            .assert()
+            .success()
+            .stdout(contains("1/1 passed"));
+    }
+    let entries = fs::read_dir(dir.join(".sutra-cache")).unwrap().count();
+    assert_eq!(entries, 2, "one parse and one expansion");
+
+    sutra(&["clean"])
+        .assert()
+        .success()
+        .stdout(contains("Removed .sutra-cache"));
+    assert!(!dir.join(".sutra-cache").exists());
+    sutra(&["clean"])
+        .assert()
+        .success()
+        .stdout(contains("No cache to remove"));
+
+    let _ = fs::remove_dir_all(dir);
+}
+
+#[test]
+fn cli_run_prints_warnings_at_the_end_and_can_deny_them() {
+    let dir = std::env::temp_dir().join("sutra_warnings");
+    fs::create_dir_all(&dir).unwrap();
+    let script = dir.join("shadow.sutra");
+    fs::write(
+        &script,
+        "(define (len xs) 0)\n(define hp 1)\n(define hp 2)\n(println \"ran\")\n",
+    )
+    .unwrap();
+
+    Command::cargo_bin("sutra")
+        .unwrap()
+        .arg("run")
+        .arg(&script)
+        .assert()
+        .success()
+        .stdout(contains("ran"))
+        .stderr(
+            contains("Warning: 'len' hides the atom of the same name")
+                .and(contains("parameter 'xs' of 'len' is never used"))
+                .and(contains("'hp' hides an earlier definition").count(1))
+                .and(contains("Validation error").not())
+                .and(contains("3 warning(s)")),
+        );
+
+    Command::cargo_bin("sutra")
+        .unwrap()
+        .args(["run", "--deny", "warnings"])
+        .arg(&script)
+        .assert()
+        .failure()
+        .stdout(contains("ran").not())
+        .stderr(contains("validation.semantic.error"));
+}
+
+#[test]
+fn cli_lists_shows_and_runs_every_built_in_example() {
+    let expected = [
+        ("state", "Ada has 15 gold."),
+        ("macros", "Story beats: 2"),
+        ("testing", "4/4 passed"),
+        ("dialogue", "They wave you across."),
+    ];
+    for (name, output) in expected {
+        Command::cargo_bin("sutra")
+            .unwrap()
+            .arg("examples")
+            .assert()
+            .success()
+            .stdout(contains(format!("  {name} ")));
+        Command::cargo_bin("sutra")
+            .unwrap()
+            .args(["examples", "show", name])
+            .assert()
+            .success()
+            .stdout(contains(";;"));
+        Command::cargo_bin("sutra")
+            .unwrap()
+            .args(["examples", "run", name])
+            .write_stdin("1\n")
+            .assert()
+            .success()
+            .stdout(contains(output));
+    }
+
+    Command::cargo_bin("sutra")
+        .unwrap()
+        .args(["examples", "run", "dialog"])
+        .assert()
+        .failure()
+        .stderr(contains("did you mean `dialogue`?"));
+}
+
+#[test]
+fn cli_exports_the_grammar_as_ebnf_and_json() {
+    Command::cargo_bin("sutra")
+        .unwrap()
+        .arg("grammar")
+        .assert()
+        .success()
+        .stdout(contains(
+            "line_comment    ::= ';' ([#x0-#x10FFFF] - #xA)* /* atomic */",
+        ))
+        .stdout(contains(
+            "list            ::= '(' (datum_comment | expr)* ')'",
+        ));
+
+    let dir = std::env::temp_dir().join("sutra_grammar");
+    fs::create_dir_all(&dir).unwrap();
+    let prelude = dir.join("beats.sutra");
+    fs::write(&prelude, "(define (beat x) (print x))").unwrap();
+    let output = Command::cargo_bin("sutra")
+        .unwrap()
+        .args(["grammar", "--format", "json", "--prelude"])
+        .arg(&prelude)
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let json: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    let names = |key: &str| -> Vec<String> { serde_json::from_value(json[key].clone()).unwrap() };
+    assert!(names("atoms").contains(&"println".to_string()));
+    assert!(names("special_forms").contains(&"lambda".to_string()));
+    assert!(names("macros").contains(&"beat".to_string()));
+    assert!(names("tokens").contains(&"symbol".to_string()));
+    assert!(names("literals").contains(&"#|".to_string()));
+    let number = &json["rules"].as_array().unwrap()[11];
+    assert_eq!(number["name"], "number");
+    assert_eq!(number["kind"], "atomic");
+    assert_eq!(
+        number["references"],
+        serde_json::json!(["ASCII_DIGIT", "symbol_inner"])
+    );
+
+    let _ = fs::remove_dir_all(dir);
+}
+
+#[test]
+fn cli_emits_textmate_and_tree_sitter_grammars() {
+    let dir = std::env::temp_dir().join("sutra_editor_grammars");
+    let _ = fs::remove_dir_all(&dir);
+    Command::cargo_bin("sutra")
+        .unwrap()
+        .args(["grammar", "--emit", "textmate", "--out"])
+        .arg(&dir)
+        .assert()
+        .success()
+        .stdout(contains("sutra.tmLanguage.json"));
+    let textmate = fs::read_to_string(dir.join("sutra.tmLanguage.json")).unwrap();
+    let textmate: serde_json::Value = serde_json::from_str(&textmate).unwrap();
+    assert_eq!(textmate["scopeName"], "source.sutra");
+    let atoms = textmate["repository"]["atoms"]["match"].as_str().unwrap();
+    assert!(atoms.contains("|println|"), "{atoms}");
+
+    Command::cargo_bin("sutra")
+        .unwrap()
+        .args(["grammar", "--emit", "treesitter", "--out"])
+        .arg(&dir)
+        .assert()
+        .success()
+        .stdout(contains("grammar.js").and(contains("highlights.scm")));
+    let grammar = fs::read_to_string(dir.join("grammar.js")).unwrap();
+    assert!(grammar
+        .contains("    list: $ => seq(\"(\", repeat(choice($.datum_comment, $.expr)), \")\"),"));
+    let query = fs::read_to_string(dir.join("queries/highlights.scm")).unwrap();
+    assert!(query.contains("(#any-of? @keyword"), "{query}");
+
+    Command::cargo_bin("sutra")
+        .unwrap()
+        .args(["grammar", "--format", "json", "--emit", "textmate"])
+        .assert()
+        .failure()
+        .stderr(contains("cannot be used with"));
+
+    let _ = fs::remove_dir_all(dir);
+}
+
+#[test]
+fn cli_run_calls_the_entry_point_after_loading_the_scripts() {
+    let dir = std::env::temp_dir().join("sutra_entry_point");
+    fs::create_dir_all(&dir).unwrap();
+    let script = dir.join("story.sutra");
+    fs::write(
+        &script,
+        "(define (main) (println \"start\") (greet))\n\
+         (define (greet) (println \"hello\"))\n\
+         (define (epilogue) (println \"the end\"))\n\
+         (define chapters 3)\n",
+    )
+    .unwrap();
+    Command::cargo_bin("sutra")
+        .unwrap()
+        .arg("run")
+        .arg(&script)
+        .assert()
+        .success()
+        .stdout("start\nhello\n");
+    Command::cargo_bin("sutra")
+        .unwrap()
+        .arg("run")
+        .arg(&script)
+        .args(["--entry", "epilogue"])
+        .assert()
+        .success()
+        .stdout("the end\n");
+    Command::cargo_bin("sutra")
+        .unwrap()
+        .arg("run")
+        .arg(&script)
+        .args(["--entry", "prologue"])
+        .assert()
+        .failure()
+        .stderr(contains("entry point 'prologue' is not defined"));
+
+    let loose = dir.join("loose.sutra");
+    fs::write(&loose, "(println \"too early\")\n(define (main) 1)\n").unwrap();
+    Command::cargo_bin("sutra")
+        .unwrap()
+        .arg("run")
+        .arg(&loose)
+        .assert()
+        .failure()
+        .stdout("")
+        .stderr(
+            contains("only definitions may be at the top level")
+                .and(contains("move this into 'main'")),
+        );
+
+    let _ = fs::remove_dir_all(dir);
+}
+
+#[test]
+fn cli_run_restores_the_definitions_scripts_open_with_from_the_cache() {
+    let dir = std::env::temp_dir().join("sutra_load_cache");
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    let data = "(define (double x) (* x 2))\n\
+                (define base (+ 40 2))\n\
+                (defproto goblin (hp 6))\n";
+    fs::write(dir.join("data.sutra"), data).unwrap();
+    fs::write(
+        dir.join("main.sutra"),
+        "(spawn! npcs.g1 goblin)\n(println (double base) (get npcs.g1.hp))\n",
+    )
+    .unwrap();
+    let run = |extra: &[&str]| {
+        Command::cargo_bin("sutra")
+            .unwrap()
+            .current_dir(&dir)
+            .args(["run", "data.sutra", "main.sutra"])
+            .args(extra)
+            .assert()
+            .success()
+    };
+    let entries = || fs::read_dir(dir.join(".sutra-cache")).map_or(0, |e| e.count());
+
+    run(&["--no-cache"]).stdout("goblin\n846\n");
+    assert_eq!(entries(), 0);
+    run(&[]).stdout("goblin\n846\n");
+    assert_eq!(entries(), 1);
+    run(&[]).stdout("goblin\n846\n");
+    assert_eq!(entries(), 1);
+
+    fs::write(dir.join("data.sutra"), data.replace("40", "10")).unwrap();
+    run(&[]).stdout("goblin\n246\n");
+    assert_eq!(entries(), 2);
+
+    let _ = fs::remove_dir_all(dir);
+}
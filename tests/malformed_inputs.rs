@@ -0,0 +1,212 @@
+// Malformed input never panics: every script below, however broken, must come back
+// from the pipeline as a value or a diagnostic. The corpus is hand-written cases,
+// including generator maps built by hand rather than by the `gen/...` atoms, every
+// atom called with ill-typed arguments, and truncated or damaged copies of the
+// `.sutra` test files.
+
+use std::{fs, panic, path::Path, time::Duration};
+
+use sutra::{
+    build_canonical_world,
+    cli::{ExecutionPipeline, SANDBOX_LIMITS},
+    prelude::*,
+    print_error, Capability, EngineOutputBuffer,
+};
+
+const CORPUS: &[&str] = &[
+    "(",
+    ")",
+    "((((",
+    "\"unterminated",
+    "\"\\q\"",
+    "#| never closed",
+    "#;",
+    "'",
+    "...",
+    "(...)",
+    "{",
+    "\"{\"",
+    "\"{(}\"",
+    "\"}{\"",
+    "(quote)",
+    "(define)",
+    "(define (f))",
+    "(define (f x x) x)",
+    "(define 1 2)",
+    "(lambda)",
+    "(lambda (1) 1)",
+    "((lambda (x) x))",
+    "((lambda (...xs) xs) 1 2)",
+    "(let)",
+    "(let ((x)) x)",
+    "(let (x) x)",
+    "(if)",
+    "(do)",
+    "(define (f) (f)) (f)",
+    "(define (f n) (+ 1 (f n))) (f 0)",
+    "(eval '(eval '(eval 1)))",
+    "(define-macro (m x) (m x)) (m 1)",
+    "(module)",
+    "(module m (export))",
+    "(text \"[if]\")",
+    "(text \"[a|\")",
+    "(range 0 1e308 1e-308)",
+    "(generate! x 1e9 (lambda (i) i))",
+    "(str-repeat \"a\" 1e12)",
+    "(for-all ((x (core/map))) true)",
+    "(for-all ((x (core/map \"gen\" 1))) true)",
+    "(for-all ((x (core/map \"gen\" \"int\"))) true)",
+    "(for-all ((x (core/map \"gen\" \"int\" \"min\" 5 \"max\" 1))) true)",
+    "(for-all ((x (core/map \"gen\" \"int\" \"min\" 1.2 \"max\" 1.8))) true)",
+    "(for-all ((x (core/map \"gen\" \"number\" \"min\" 1 \"max\" -1))) true)",
+    "(for-all ((x (core/map \"gen\" \"number\" \"min\" (- 0 (/ 1e308 1e-308)) \"max\" 1))) true)",
+    "(for-all ((x (core/map \"gen\" \"string\" \"max-len\" -1))) true)",
+    "(for-all ((x (core/map \"gen\" \"list-of\" \"max-len\" 2))) true)",
+    "(for-all ((x (core/map \"gen\" \"list-of\" \"max-len\" 2 \"elem\" (core/map \"gen\" \"path\" \"max-depth\" 0)))) true)",
+    "(for-all ((x (core/map \"gen\" \"one-of\" \"choices\" (list)))) true)",
+    "(for-all ((x (core/map \"gen\" \"one-of\" \"choices\" 5))) true)",
+    "(for-all ((x (core/map \"gen\" \"path\" \"max-depth\" 0))) true)",
+    "(for-all ((x (core/map \"gen\" \"path\" \"max-depth\" -3))) true)",
+];
+
+/// Arguments of every type, to call each atom with.
+const ARGS: &[&str] = &[
+    "1",
+    "-1",
+    "1e20",
+    "0.5",
+    "\"\"",
+    "\"x\"",
+    "(list)",
+    "(list 1 2)",
+    "nil",
+    "true",
+    "'s",
+    "a.b",
+    "(lambda (x) x)",
+    ":k",
+];
+
+/// Runs `source`, returning whether it finished without panicking.
+fn survives(source: &str) -> bool {
+    panic::catch_unwind(|| {
+        let built = ExecutionPipeline::builder()
+            .with_capabilities(&[
+                Capability::State,
+                Capability::Random,
+                Capability::Time,
+                Capability::Eval,
+            ])
+            .with_resource_limits(SANDBOX_LIMITS)
+            .with_time_limit(Duration::from_millis(200))
+            .with_output(SharedOutput(Rc::new(RefCell::new(
+                EngineOutputBuffer::new(),
+            ))))
+            .build();
+        if let Ok(pipeline) = built {
+            // Errors are the expected outcome; only a panic fails.
+            let _ = pipeline.execute_for_value(source, "malformed");
+        }
+    })
+    .is_ok()
+}
+
+fn panics(sources: impl IntoIterator<Item = String>) -> Vec<String> {
+    let hook = panic::take_hook();
+    panic::set_hook(Box::new(|_| {}));
+    let failed = sources
+        .into_iter()
+        .filter(|source| !survives(source))
+        .collect();
+    panic::set_hook(hook);
+    failed
+}
+
+fn sutra_files(dir: &Path, found: &mut Vec<String>) {
+    for entry in fs::read_dir(dir).unwrap() {
+        let path = entry.unwrap().path();
+        if path.is_dir() {
+            sutra_files(&path, found);
+        } else if path.extension().is_some_and(|ext| ext == "sutra") {
+            found.push(fs::read_to_string(path).unwrap());
+        }
+    }
+}
+
+#[test]
+fn hand_written_malformed_scripts_do_not_panic() {
+    let failed = panics(CORPUS.iter().map(|source| source.to_string()));
+    assert!(failed.is_empty(), "panicked on: {failed:#?}");
+}
+
+#[test]
+fn atoms_given_wrong_arguments_do_not_panic() {
+    let world = build_canonical_world();
+    let atoms: Vec<String> = world.read().atom_specs.keys().cloned().collect();
+    let calls = atoms.iter().flat_map(|atom| {
+        (0..ARGS.len()).flat_map(move |i| {
+            let one = ARGS[i];
+            let two = ARGS[(i * 5 + 3) % ARGS.len()];
+            let three = ARGS[(i * 7 + 1) % ARGS.len()];
+            [
+                format!("({atom})"),
+                format!("({atom} {one})"),
+                format!("({atom} {one} {two})"),
+                format!("({atom} {one} {two} {three})"),
+            ]
+        })
+    });
+    let failed = panics(calls);
+    assert!(failed.is_empty(), "panicked on: {failed:#?}");
+}
+
+#[test]
+fn damaged_test_scripts_do_not_panic() {
+    let mut scripts = Vec::new();
+    sutra_files(Path::new("tests"), &mut scripts);
+    let damaged = scripts.iter().flat_map(|script| {
+        let cuts: Vec<usize> = script.char_indices().map(|(i, _)| i).collect();
+        let step = (cuts.len() / 12).max(1);
+        cuts.into_iter().step_by(step).flat_map(move |cut| {
+            let next = script[cut..].chars().next().map_or(0, char::len_utf8);
+            [
+                script[..cut].to_string(),
+                format!("{}{}", &script[..cut], &script[cut + next..]),
+            ]
+        })
+    });
+    let failed = panics(damaged);
+    assert!(failed.is_empty(), "panicked on {} scripts", failed.len());
+}
+
+#[test]
+fn deep_nesting_is_refused_instead_of_overflowing() {
+    for open in ["(list ", "'", "{", "#; "] {
+        let source = format!("{}1{}", open.repeat(100_000), ")".repeat(100_000));
+        let error = sutra::parser::parse(&source, SourceContext::from_file("deep", &source))
+            .expect_err("nesting past the limit is refused");
+        assert!(error.to_string().contains("nested more than"), "{error}");
+    }
+}
+
+#[test]
+fn errors_on_very_long_lines_are_reported() {
+    let source = format!("(list {}nope)", "1 ".repeat(40_000));
+    let error = ExecutionPipeline::builder()
+        .build()
+        .unwrap()
+        .execute_for_value(&source, "long")
+        .unwrap_err();
+    assert!(panic::catch_unwind(|| print_error(error)).is_ok());
+}
+
+#[test]
+fn long_lists_are_dropped_without_overflowing() {
+    let value = ExecutionPipeline::builder()
+        .with_optimization(false)
+        .build()
+        .unwrap()
+        .execute_for_value("(len (list 1 (range 0 100000)))", "long")
+        .unwrap();
+    assert_eq!(value, Value::Number(2.0));
+}